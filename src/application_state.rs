@@ -1,30 +1,53 @@
 use rppal::gpio::Gpio;
 use rppal::spi::Bus;
 use rppal::spi::SlaveSelect;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
-use std::time::SystemTime;
-use tokio::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use tokio::sync::{Mutex, Notify, broadcast};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 use crate::AppConfig;
+use crate::config::{self, TemperatureConfig};
 use crate::motor::AsyncStepperMotor;
 use crate::motor::stepper_28byj48::Stepper28BYJ48;
 use crate::motor::stepper_mock::StepperMock;
 use crate::motor::stepper_nema14::StepperNema14;
+use crate::motor::stepper_unavailable::StepperUnavailable;
 use crate::sensors::PowerReading;
 use crate::sensors::PowerSensor;
 use crate::sensors::WeightReading;
 use crate::sensors::WeightSensor;
 use crate::sensors::WeightSensorCalibration;
+use crate::sensors::WeightSensorSettings;
+use crate::sensors::sensor_fused::{FusedCell, SensorFused};
+use crate::sensors::temperature::{TemperatureReading, TemperatureSensor};
+use crate::services::backup_scheduler::BackupStatus;
+use crate::services::config_reload::StagedConfig;
+use crate::services::bowl::{self, BowlStats};
+use crate::services::trickle::TrickleJob;
+use crate::services::training::TrainingState;
+use crate::services::digital_inputs::{self, DigitalInputState};
+use crate::services::digital_outputs::{self, DigitalOutput};
+use crate::services::events::EventBus;
+use crate::services::fan::Fan;
+use crate::services::humidity::HumidityHistory;
+use crate::services::persisted_files::QuarantinedFile;
+use crate::services::power_monitor::{OvercurrentOverride, PowerTrace};
+use crate::services::scheduler::NextScheduledDispense;
+use crate::services::stats::{self, DispenseStats};
+use crate::services::triggers::TriggerLog;
+use crate::services::webhooks::DeliveryLog;
 use crate::services::weight_monitor;
+use crate::utils::datetime;
 
 pub type AppStateMutex = Arc<Mutex<ApplicationState>>;
 
-#[derive(Serialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
 pub enum DispenserStatus {
     Dispensing,
     Operational,
@@ -37,6 +60,9 @@ pub enum DispenserStatus {
     Cancelled,
     Calibrating,
     CalibrationFailed,
+    Stirring,
+    /// Running the motor unloaded to measure its current, see `current_calibration`
+    MeasuringCurrent,
 }
 
 impl fmt::Display for DispenserStatus {
@@ -45,11 +71,69 @@ impl fmt::Display for DispenserStatus {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Available,
+    Unavailable,
+}
+
+/// Capacity of the status transition channel, slow subscribers lag after this many.
+const STATUS_TRANSITIONS_CAPACITY: usize = 64;
+
+/// A change of the dispenser status, sent to `status_transitions_tx` subscribers.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StatusTransition {
+    pub from: DispenserStatus,
+    pub to: DispenserStatus,
+    pub timestamp: String,
+}
+
+/// Whether a piece of hardware initialized, with the error if it didn't.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct ComponentStatus {
+    pub state: ComponentState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentStatus {
+    fn from_result<T>(result: &Result<T, String>) -> Self {
+        match result {
+            Ok(_) => ComponentStatus {
+                state: ComponentState::Available,
+                error: None,
+            },
+            Err(e) => ComponentStatus {
+                state: ComponentState::Unavailable,
+                error: Some(e.clone()),
+            },
+        }
+    }
+}
+
+/// Startup state of each hardware component, reported in `/status`.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct HardwareStatus {
+    pub gpio: ComponentStatus,
+    pub motor: ComponentStatus,
+    pub power_sensor: ComponentStatus,
+    pub weight_sensor: ComponentStatus,
+    /// Only reported if a temperature sensor is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature_sensor: Option<ComponentStatus>,
+    /// Only reported if a bowl load cell is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bowl_sensor: Option<ComponentStatus>,
+}
+
 pub struct ApplicationState {
     pub gpio: Option<Gpio>,
     pub status: DispenserStatus,
     pub startup_time: SystemTime,
     pub last_dispense_time: Option<String>,
+    /// End of the cooldown after the last dispense, see `motor.cooldown_ms`
+    pub cooldown_until: Option<Instant>,
     pub last_error_msg: Option<String>,
     pub last_error_time: Option<String>,
     pub last_step_index: Option<u32>,
@@ -60,36 +144,126 @@ pub struct ApplicationState {
     pub power_readings_tx: tokio::sync::watch::Sender<PowerReading>,
     pub power_readings_rx: tokio::sync::watch::Receiver<PowerReading>,
     pub motor_cancel_token: Option<CancellationToken>,
+    /// Why the motor cancel token was cancelled, recorded in the dispense history
+    pub motor_cancel_reason: Option<String>,
+    /// Set along with the cancel reason when the power monitor stopped the motor for a
+    /// jam, the dispense then ends as `Jammed` instead of `Cancelled`
+    pub motor_jam_reason: Option<String>,
+    /// Span of the dispense job in progress, lets other tasks attach their events to it.
+    pub dispense_span: Option<tracing::Span>,
     pub weight_sensor_mutex: Option<Arc<Mutex<Box<dyn WeightSensor>>>>,
     pub weight_readings_tx: tokio::sync::watch::Sender<WeightReading>,
     pub weight_readings_rx: tokio::sync::watch::Receiver<WeightReading>,
+    pub temperature_sensor_mutex: Option<Arc<Mutex<Box<dyn TemperatureSensor>>>>,
+    /// Load cell under the food bowl, see `bowl`
+    pub bowl_sensor_mutex: Option<Arc<Mutex<Box<dyn WeightSensor>>>>,
+    /// Latest temperature reading, unset until the sensor has been read
+    pub temperature_readings_tx: tokio::sync::watch::Sender<Option<TemperatureReading>>,
+    pub temperature_readings_rx: tokio::sync::watch::Receiver<Option<TemperatureReading>>,
     pub calibration_in_progress: Arc<AtomicBool>,
     pub calibration_tx: tokio::sync::watch::Sender<WeightSensorCalibration>,
     pub calibration_rx: tokio::sync::watch::Receiver<WeightSensorCalibration>,
+    /// Settings for the weight sensor, the weight monitor applies changes between readings
+    pub weight_sensor_settings_tx: tokio::sync::watch::Sender<WeightSensorSettings>,
+    pub weight_sensor_settings_rx: tokio::sync::watch::Receiver<WeightSensorSettings>,
+    pub event_bus: Arc<EventBus>,
+    /// Recent outbound webhook deliveries, see `webhooks`
+    pub webhook_deliveries: Arc<DeliveryLog>,
+    pub last_backup: Option<BackupStatus>,
+    /// Set by the feeding scheduler while schedules are configured
+    pub next_scheduled_dispense: Option<NextScheduledDispense>,
+    /// Set while a staged config reload waits for `POST /config/confirm`
+    pub staged_config: Option<StagedConfig>,
+    /// Notified whenever the dispenser status, last dispense or last error changes.
+    pub status_tx: tokio::sync::watch::Sender<DispenserStatus>,
+    /// Only notified on status transitions. Subscribe once to follow the status without
+    /// locking the application state.
+    pub dispenser_status_tx: tokio::sync::watch::Sender<DispenserStatus>,
+    /// Every status transition in order, for consumers that must not miss short-lived
+    /// states which the watch channels coalesce.
+    pub status_transitions_tx: broadcast::Sender<StatusTransition>,
+    /// Accepted dispenses per trigger, for the `triggers` limits and hook intervals.
+    pub trigger_log: TriggerLog,
+    /// Sensor reading channels the watchdog found stale, e.g. `power_readings`.
+    pub stale_channels: Vec<String>,
+    pub dispense_stats: DispenseStats,
+    pub bowl_stats: BowlStats,
+    /// Set while a trickle dispense runs, see `trickle`
+    pub trickle_job: Option<TrickleJob>,
+    /// Restart requests of the running supervised background tasks, by task name
+    pub supervised_tasks: BTreeMap<&'static str, Arc<Notify>>,
+    /// Training session and totals, see `training`
+    pub training: TrainingState,
+    /// Hardware that failed to initialize at startup
+    pub init_errors: Vec<String>,
+    /// Persisted files that failed the startup check
+    pub quarantined_files: Vec<QuarantinedFile>,
+    pub hardware: HardwareStatus,
+    /// Configured digital inputs by label
+    pub digital_inputs: BTreeMap<String, DigitalInputState>,
+    /// Configured digital outputs by label
+    pub digital_outputs: BTreeMap<String, DigitalOutput>,
+    pub fan: Option<Fan>,
+    pub humidity: HumidityHistory,
+    pub power_trace: PowerTrace,
+    /// Set while overcurrent cancellation is disabled through the API
+    pub overcurrent_override: Option<OvercurrentOverride>,
+}
+
+/// Drivers that take the place of the ones selected in the config, for embedding the
+/// dispenser with other hardware, see `DispenserBuilder`.
+#[derive(Default)]
+pub struct Drivers {
+    pub motor: Option<Box<dyn AsyncStepperMotor + Send + Sync>>,
+    pub power_sensor: Option<Box<dyn PowerSensor>>,
+    pub weight_sensor: Option<Box<dyn WeightSensor>>,
+    pub temperature_sensor: Option<Box<dyn TemperatureSensor>>,
 }
 
 impl ApplicationState {
     pub fn new(app_config: AppConfig) -> Self {
+        Self::with_drivers(app_config, Drivers::default())
+    }
+
+    pub fn with_drivers(app_config: AppConfig, drivers: Drivers) -> Self {
         let version = env!("CARGO_PKG_VERSION").to_string();
-        let status: DispenserStatus;
 
         info!("Starting treat-dispenser-api, version: {}", version);
 
-        let motor = match init_motor(&app_config) {
+        // hardware that fails to initialize is reported in /status instead of stopping
+        // the service, so the API stays reachable to diagnose it
+        let mut init_errors = Vec::new();
+
+        let motor_result = match drivers.motor {
+            Some(motor) => Ok(motor),
+            None => init_motor(&app_config),
+        };
+        let motor_status = ComponentStatus::from_result(&motor_result);
+        let motor: Arc<Box<dyn AsyncStepperMotor + Send + Sync>> = match motor_result {
             Ok(motor) => {
                 info!("Motor initialized: {}", motor.get_name());
                 Arc::new(motor)
             }
             Err(e) => {
                 error!("Failed to select motor: {}", e);
-                std::process::exit(1);
+                init_errors.push(format!("Failed to initialize motor: {}", e));
+                Arc::new(Box::new(StepperUnavailable::new(
+                    &app_config.motor.motor_type,
+                    &e,
+                )))
             }
         };
 
-        let power_sensor_mutex = match init_power_sensor(&app_config) {
+        let power_sensor_result = match drivers.power_sensor {
+            Some(sensor) => Ok(sensor),
+            None => init_power_sensor(&app_config),
+        };
+        let power_sensor_status = ComponentStatus::from_result(&power_sensor_result);
+        let power_sensor_mutex = match power_sensor_result {
             Ok(sensor) => Some(Arc::new(Mutex::new(sensor))),
             Err(e) => {
                 error!("Failed to initialize power sensor: {}", e);
+                init_errors.push(format!("Failed to initialize power sensor: {}", e));
                 None
             }
         };
@@ -97,7 +271,9 @@ impl ApplicationState {
         let (power_readings_tx, power_readings_rx) =
             tokio::sync::watch::channel(PowerReading::default());
 
-        let gpio = match Gpio::new() {
+        let gpio_result = Gpio::new().map_err(|e| e.to_string());
+        let gpio_status = ComponentStatus::from_result(&gpio_result);
+        let gpio = match gpio_result {
             Ok(gpio) => {
                 info!("GPIO initialized successfully");
                 Some(gpio)
@@ -108,26 +284,75 @@ impl ApplicationState {
             }
         };
 
-        if motor.requires_gpio() && gpio.is_none() {
+        let status = if motor.as_any().is::<StepperUnavailable>() {
+            DispenserStatus::MotorControlError
+        } else if motor.requires_gpio() && gpio.is_none() {
             error!("Motor requires GPIO but GPIO initialization failed");
-            status = DispenserStatus::NoGpio;
+            DispenserStatus::NoGpio
         } else {
-            status = DispenserStatus::Operational;
-        }
+            DispenserStatus::Operational
+        };
 
-        let weight_sensor_result = init_weight_sensor(&app_config);
-        let weight_sensor = match weight_sensor_result {
-            Ok(sensor) => sensor,
+        let weight_sensor_result = match drivers.weight_sensor {
+            Some(sensor) => Ok(sensor),
+            None => init_weight_sensor(&app_config),
+        };
+        let weight_sensor_status = ComponentStatus::from_result(&weight_sensor_result);
+        let weight_sensor_mutex = match weight_sensor_result {
+            Ok(sensor) => Some(Arc::new(Mutex::new(sensor))),
             Err(e) => {
                 error!("Failed to initialize weight sensor: {}", e);
-                std::process::exit(1)
+                init_errors.push(format!("Failed to initialize weight sensor: {}", e));
+                None
             }
         };
-
-        let weight_sensor_mutex = Some(Arc::new(Mutex::new(weight_sensor)));
         let (weight_readings_tx, weight_readings_rx) =
             tokio::sync::watch::channel(WeightReading::default());
 
+        let mut temperature_sensor_status = None;
+        let temperature_sensor_result = match drivers.temperature_sensor {
+            Some(sensor) => Some(Ok(sensor)),
+            None => init_temperature_sensor(&app_config),
+        };
+        let temperature_sensor_mutex = match temperature_sensor_result {
+            None => None,
+            Some(result) => {
+                temperature_sensor_status = Some(ComponentStatus::from_result(&result));
+                match result {
+                    Ok(sensor) => Some(Arc::new(Mutex::new(sensor))),
+                    Err(e) => {
+                        error!("Failed to initialize temperature sensor: {}", e);
+                        init_errors.push(format!("Failed to initialize temperature sensor: {}", e));
+                        None
+                    }
+                }
+            }
+        };
+        let (temperature_readings_tx, temperature_readings_rx) = tokio::sync::watch::channel(None);
+
+        let mut bowl_sensor_status = None;
+        let bowl_sensor_mutex = match app_config.bowl.as_ref() {
+            None => None,
+            Some(bowl_config) => {
+                let result = init_load_cell(
+                    "bowl",
+                    &bowl_config.sensor,
+                    bowl_config
+                        .slave_select
+                        .unwrap_or(config::BOWL_SLAVE_SELECT_DEFAULT),
+                );
+                bowl_sensor_status = Some(ComponentStatus::from_result(&result));
+                match result {
+                    Ok(sensor) => Some(Arc::new(Mutex::new(sensor))),
+                    Err(e) => {
+                        error!("Failed to initialize bowl sensor: {}", e);
+                        init_errors.push(format!("Failed to initialize bowl sensor: {}", e));
+                        None
+                    }
+                }
+            }
+        };
+
         let weight_sensor_calibration = weight_monitor::load_calibration_from_file()
             .unwrap_or_else(|e| {
                 warn!("Failed to load weight sensor calibration from file, will use default values instead. Error: {}", e);
@@ -136,14 +361,30 @@ impl ApplicationState {
 
         let (calibration_tx, calibration_rx) =
             tokio::sync::watch::channel(weight_sensor_calibration);
+        let (weight_sensor_settings_tx, weight_sensor_settings_rx) = tokio::sync::watch::channel(
+            WeightSensorSettings::from_config(&app_config.weight_monitor),
+        );
+
+        let last_error_time = init_errors
+            .last()
+            .map(|_| datetime::get_formatted_current_timestamp());
+
+        let digital_inputs = digital_inputs::initial_states(app_config.digital_inputs.as_ref());
+        let digital_outputs =
+            digital_outputs::init_outputs(gpio.as_ref(), app_config.digital_outputs.as_ref());
+        let fan = app_config
+            .fan
+            .as_ref()
+            .map(|fan_config| Fan::new(gpio.as_ref(), fan_config));
 
         Self {
             gpio,
-            status,
+            status: status.clone(),
             startup_time: SystemTime::now(),
             last_dispense_time: None,
-            last_error_msg: None,
-            last_error_time: None,
+            cooldown_until: None,
+            last_error_msg: init_errors.last().cloned(),
+            last_error_time,
             last_step_index: None,
             motor,
             app_config,
@@ -154,37 +395,203 @@ impl ApplicationState {
             weight_sensor_mutex,
             weight_readings_tx,
             weight_readings_rx,
+            temperature_sensor_mutex,
+            temperature_readings_tx,
+            temperature_readings_rx,
+            bowl_sensor_mutex,
             motor_cancel_token: None,
+            motor_cancel_reason: None,
+            motor_jam_reason: None,
+            dispense_span: None,
             calibration_in_progress: Arc::new(AtomicBool::new(false)),
             calibration_tx,
             calibration_rx,
+            weight_sensor_settings_tx,
+            weight_sensor_settings_rx,
+            event_bus: Arc::new(EventBus::new()),
+            webhook_deliveries: Arc::new(DeliveryLog::new()),
+            last_backup: None,
+            next_scheduled_dispense: None,
+            staged_config: None,
+            status_tx: tokio::sync::watch::Sender::new(status.clone()),
+            dispenser_status_tx: tokio::sync::watch::Sender::new(status.clone()),
+            status_transitions_tx: broadcast::channel(STATUS_TRANSITIONS_CAPACITY).0,
+            trigger_log: TriggerLog::default(),
+            stale_channels: Vec::new(),
+            dispense_stats: stats::load_stats_from_file(),
+            bowl_stats: bowl::load_stats_from_file(),
+            trickle_job: None,
+            supervised_tasks: BTreeMap::new(),
+            training: TrainingState::default(),
+            init_errors,
+            quarantined_files: Vec::new(),
+            hardware: HardwareStatus {
+                gpio: gpio_status,
+                motor: motor_status,
+                power_sensor: power_sensor_status,
+                weight_sensor: weight_sensor_status,
+                temperature_sensor: temperature_sensor_status,
+                bowl_sensor: bowl_sensor_status,
+            },
+            digital_inputs,
+            digital_outputs,
+            fan,
+            humidity: HumidityHistory::default(),
+            power_trace: PowerTrace::default(),
+            overcurrent_override: None,
+        }
+    }
+}
+
+impl ApplicationState {
+    /// Sets the dispenser status and notifies status watchers, transition subscribers and
+    /// the event log if it changed.
+    pub fn set_status(&mut self, status: DispenserStatus) {
+        let previous = std::mem::replace(&mut self.status, status.clone());
+        let changed = self.status_tx.send_if_modified(|current| {
+            if *current == status {
+                false
+            } else {
+                *current = status.clone();
+                true
+            }
+        });
+        if changed {
+            self.dispenser_status_tx.send_replace(status.clone());
+            // no subscribers is not an error
+            let _ = self.status_transitions_tx.send(StatusTransition {
+                from: previous,
+                to: status.clone(),
+                timestamp: datetime::get_formatted_current_timestamp(),
+            });
+            self.event_bus.publish_status_change(&status);
         }
     }
+
+    /// Wakes status watchers for changes that aren't a status transition,
+    /// e.g. a new last error.
+    pub fn notify_status_changed(&self) {
+        self.status_tx.send_modify(|_| {});
+    }
 }
 
 fn init_weight_sensor(
     app_config: &AppConfig,
 ) -> Result<Box<dyn WeightSensor>, String> {
     match app_config.weight_monitor.sensor.as_str() {
+        "SensorHX711" => Ok(Box::new(crate::sensors::sensor_hx711::SensorHx711::new(
+            Bus::Spi0,
+            SlaveSelect::Ss0,
+            app_config
+                .weight_monitor
+                .geometry_factor
+                .unwrap_or(config::WEIGHT_GEOMETRY_FACTOR_DEFAULT),
+        )?)),
+        "SensorFused" => {
+            let fusion_config = match app_config.weight_monitor.fusion.as_ref() {
+                Some(config) => config,
+                None => return Err("weight_monitor.fusion configuration is missing".to_string()),
+            };
+            let settings = WeightSensorSettings::from_config(&app_config.weight_monitor);
+            let cells = fusion_config
+                .cells
+                .iter()
+                .zip(&settings.cells)
+                .map(|(cell_config, cell_settings)| {
+                    Ok(FusedCell {
+                        name: cell_config.name.clone(),
+                        sensor: init_load_cell(
+                            &cell_config.name,
+                            &cell_config.sensor,
+                            cell_config.slave_select.unwrap_or(0),
+                        )?,
+                        gain: cell_settings.gain,
+                        expected_share: cell_settings.expected_share,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(Box::new(SensorFused::new(
+                cells,
+                settings.geometry_factor,
+                settings.mismatch_tolerance,
+                settings.mismatch_min_grams,
+            )?))
+        }
+        "SensorMock" => Ok(Box::new(crate::sensors::sensor_mock::SensorMock::new())),
+        _ => Err(format!("Unsupported weight sensor type '{}'", app_config.weight_monitor.sensor)),
+    }
+}
+
+/// A single load cell, e.g. one of a `SensorFused` or the bowl. Without geometry
+/// correction, for a fused sensor it applies to the sum.
+fn init_load_cell(
+    name: &str,
+    sensor: &str,
+    slave_select: u8,
+) -> Result<Box<dyn WeightSensor>, String> {
+    match sensor {
         "SensorHX711" => {
-            return Ok(Box::new(crate::sensors::sensor_hx711::SensorHx711::new(
+            let slave_select = match slave_select {
+                0 => SlaveSelect::Ss0,
+                1 => SlaveSelect::Ss1,
+                2 => SlaveSelect::Ss2,
+                other => {
+                    return Err(format!(
+                        "Invalid slave_select {} of load cell '{}', must be 0 to 2",
+                        other, name
+                    ));
+                }
+            };
+            Ok(Box::new(crate::sensors::sensor_hx711::SensorHx711::new(
                 Bus::Spi0,
-                SlaveSelect::Ss0,
-            )?));
+                slave_select,
+                config::WEIGHT_GEOMETRY_FACTOR_DEFAULT,
+            )?))
         }
-        "SensorMock" => return Ok(Box::new(crate::sensors::sensor_mock::SensorMock::new())),
-        _ => return Err(format!("Unsupported weight sensor type '{}'", app_config.weight_monitor.sensor)),
-    };
+        "SensorMock" => Ok(Box::new(crate::sensors::sensor_mock::SensorMock::new())),
+        _ => Err(format!(
+            "Unsupported sensor type '{}' of load cell '{}'",
+            sensor, name
+        )),
+    }
+}
+
+/// Returns `None` if no temperature sensor is configured.
+fn init_temperature_sensor(
+    app_config: &AppConfig,
+) -> Option<Result<Box<dyn TemperatureSensor>, String>> {
+    let config = app_config.temperature.as_ref()?;
+    Some(select_temperature_sensor(config))
+}
+
+fn select_temperature_sensor(
+    config: &TemperatureConfig,
+) -> Result<Box<dyn TemperatureSensor>, String> {
+    match config.sensor.as_str() {
+        "SensorDS18B20" => Ok(Box::new(crate::sensors::sensor_ds18b20::SensorDs18b20::new(
+            config.ds18b20.as_ref(),
+        )?)),
+        "SensorDHT22" => Ok(Box::new(crate::sensors::sensor_dht22::SensorDht22::new(
+            config.dht22.as_ref(),
+        )?)),
+        "SensorBME280" => Ok(Box::new(crate::sensors::sensor_bme280::SensorBme280::new(
+            config.bme280.as_ref(),
+        )?)),
+        "SensorMock" => Ok(Box::new(crate::sensors::sensor_mock::SensorMock::new())),
+        _ => Err(format!("Unsupported temperature sensor type '{}'", config.sensor)),
+    }
 }
 
 fn init_power_sensor(
     app_config: &AppConfig,
 ) -> Result<Box<dyn PowerSensor>, String> {
     match app_config.power_monitor.sensor.as_str() {
-        "SensorINA219" => return Ok(Box::new(crate::sensors::sensor_ina219::SensorIna219::new())),
-        "SensorMock" => return Ok(Box::new(crate::sensors::sensor_mock::SensorMock::new())),
-        _ => return Err(format!("Unsupported power sensor type '{}'", app_config.power_monitor.sensor)),
-    };
+        "SensorINA219" => Ok(Box::new(crate::sensors::sensor_ina219::SensorIna219::new(
+            app_config.power_monitor.ina219.as_ref(),
+        )?)),
+        "SensorMock" => Ok(Box::new(crate::sensors::sensor_mock::SensorMock::new())),
+        _ => Err(format!("Unsupported power sensor type '{}'", app_config.power_monitor.sensor)),
+    }
 }
 
 fn init_motor(
@@ -197,7 +604,10 @@ fn init_motor(
                 Some(config) => config,
                 None => return Err("Nema14 configuration is missing".to_string()),
             };
-            Ok(Box::new(StepperNema14::new(nema14_config)))
+            Ok(Box::new(StepperNema14::new(
+                nema14_config,
+                config.motor.realtime_stepping.clone(),
+            )))
         }
         "StepperMock" => Ok(Box::new(StepperMock::new())),
         _ => Err(format!("Unsupported motor type '{}'", config.motor.motor_type)),
@@ -0,0 +1,129 @@
+use axum::Router;
+
+use crate::application_state::{AppStateMutex, Drivers};
+use crate::config::AppConfig;
+use crate::motor::AsyncStepperMotor;
+use crate::sensors::temperature::TemperatureSensor;
+use crate::sensors::{PowerSensor, WeightSensor};
+use crate::services::{
+    auth, backup_scheduler, bowl, digital_inputs, dispense_recovery, fan, fleet, hopper_level,
+    humidity, migrations, mqtt, persisted_files, power_monitor, push_notifications,
+    schedule_export, scheduler, stir, temperature_monitor, training, watchdog, webhooks,
+    weight_history, weight_monitor,
+};
+
+/// Sets up the dispenser service inside another program, e.g. a home automation hub that
+/// serves the API next to its own routes instead of running a separate process.
+///
+/// The config selects the hardware, a driver passed here takes the place of the one the
+/// config names. The config is used as given, check configs that come from users with
+/// `services::config_validation::validate` first.
+///
+/// ```no_run
+/// # async fn embed(app_config: treat_dispenser_api::config::AppConfig) {
+/// use treat_dispenser_api::DispenserBuilder;
+/// use treat_dispenser_api::motor::stepper_mock::StepperMock;
+///
+/// let dispenser = DispenserBuilder::new(app_config)
+///     .motor(StepperMock::new())
+///     .build()
+///     .await;
+/// let app = axum::Router::new().nest("/dispenser", dispenser.router);
+/// # }
+/// ```
+pub struct DispenserBuilder {
+    app_config: AppConfig,
+    drivers: Drivers,
+}
+
+impl DispenserBuilder {
+    pub fn new(app_config: AppConfig) -> Self {
+        DispenserBuilder {
+            app_config,
+            drivers: Drivers::default(),
+        }
+    }
+
+    pub fn motor(mut self, motor: impl AsyncStepperMotor + 'static) -> Self {
+        self.drivers.motor = Some(Box::new(motor));
+        self
+    }
+
+    pub fn power_sensor(mut self, sensor: impl PowerSensor + 'static) -> Self {
+        self.drivers.power_sensor = Some(Box::new(sensor));
+        self
+    }
+
+    pub fn weight_sensor(mut self, sensor: impl WeightSensor + 'static) -> Self {
+        self.drivers.weight_sensor = Some(Box::new(sensor));
+        self
+    }
+
+    /// Read even without a `temperature` section, which is then only needed for the
+    /// dispense limits and the humidity warning.
+    pub fn temperature_sensor(mut self, sensor: impl TemperatureSensor + 'static) -> Self {
+        self.drivers.temperature_sensor = Some(Box::new(sensor));
+        self
+    }
+
+    /// Migrates and checks the data directory, initializes the hardware and starts the
+    /// background services, as the standalone service does at startup. Logging is left to
+    /// the embedding program.
+    pub async fn build(mut self) -> Dispenser {
+        auth::check_admin_password(&self.app_config.api);
+
+        // before build_app, which loads the persisted files
+        let migration_failures = migrations::run_migrations();
+        let quarantined_files = persisted_files::check_persisted_files();
+        schedule_export::apply_saved_schedules(&mut self.app_config);
+        let (app_state, router) =
+            crate::build_app_with_drivers(self.app_config.clone(), self.drivers);
+        migrations::report_failures(&app_state, migration_failures).await;
+        persisted_files::report_quarantined_files(&app_state, quarantined_files).await;
+
+        start_services(&app_state).await;
+        Dispenser {
+            app_state,
+            router,
+            app_config: self.app_config,
+        }
+    }
+}
+
+/// A running dispenser. Serve `router` with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, the rate limits and logs need the
+/// client address.
+pub struct Dispenser {
+    pub app_state: AppStateMutex,
+    pub router: Router,
+    app_config: AppConfig,
+}
+
+impl Dispenser {
+    /// Serves the API on `api.listen_address` until Ctrl+C, like the standalone service.
+    pub async fn serve(self) {
+        crate::start_server(self.router, self.app_config).await;
+    }
+}
+
+async fn start_services(app_state: &AppStateMutex) {
+    power_monitor::start_power_monitoring_thread(app_state).await;
+    weight_monitor::start_weight_monitoring_thread(app_state).await;
+    weight_history::start_weight_history_recorder(app_state).await;
+    bowl::start_bowl_monitor(app_state).await;
+    temperature_monitor::start_temperature_monitoring_thread(app_state).await;
+    fan::start_fan_controller(app_state).await;
+    humidity::start_humidity_tracker(app_state).await;
+    hopper_level::start_hopper_level_monitor(app_state).await;
+    backup_scheduler::start_backup_scheduler(app_state).await;
+    push_notifications::start_push_notifier(app_state).await;
+    fleet::start_fleet_heartbeat(app_state).await;
+    webhooks::start_webhook_sender(app_state).await;
+    mqtt::start_mqtt_publisher(app_state).await;
+    watchdog::start_watchdog(app_state).await;
+    stir::start_stir_scheduler(app_state).await;
+    scheduler::start_scheduler(app_state).await;
+    digital_inputs::start_digital_inputs_monitor(app_state).await;
+    training::start_training_monitor(app_state).await;
+    dispense_recovery::recover_interrupted_dispense(app_state).await;
+}
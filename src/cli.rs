@@ -0,0 +1,167 @@
+use std::net::SocketAddr;
+
+use clap::{ArgGroup, Parser};
+use tracing_subscriber::EnvFilter;
+
+/// Command line options of the standalone service. The commands run once and exit,
+/// without any the service serves the API.
+#[derive(Parser, Debug, Default, PartialEq)]
+#[command(
+    version,
+    about = "REST API for a Raspberry Pi treat dispenser",
+    group(ArgGroup::new("command").multiple(false))
+)]
+pub struct CliArgs {
+    /// Config file to load [default: $DISPENSER_DATA_DIR/config.yaml]
+    #[arg(long, value_name = "PATH", value_parser = non_empty)]
+    pub config: Option<String>,
+    /// Address to serve the API on, overrides api.listen_address
+    #[arg(long, value_name = "ADDRESS", value_parser = socket_address)]
+    pub listen: Option<String>,
+    /// Log filter in RUST_LOG syntax, e.g. debug [default: $RUST_LOG]
+    #[arg(long, value_name = "FILTER", value_parser = log_filter)]
+    pub log_level: Option<String>,
+
+    /// Load and validate the config file
+    #[arg(long, group = "command", help_heading = "Commands")]
+    pub check_config: bool,
+    /// Play the schedules of the next days against the dispense limits
+    #[arg(long, group = "command", help_heading = "Commands")]
+    pub check_schedules: bool,
+    /// Print the JSON Schema of the config file
+    #[arg(long, group = "command", help_heading = "Commands")]
+    pub config_schema: bool,
+    /// Hash a password read from stdin for api.admin_password
+    #[arg(long, group = "command", help_heading = "Commands")]
+    pub hash_password: bool,
+    /// Import feeding history exported from another feeder
+    #[arg(
+        long,
+        value_name = "CSV",
+        group = "command",
+        help_heading = "Commands",
+        value_parser = non_empty
+    )]
+    pub import_history: Option<String>,
+    /// Read the imported dates as day/month instead of month/day
+    #[arg(long, requires = "import_history", help_heading = "Commands")]
+    pub day_first: bool,
+}
+
+/// What the service does after parsing the options. Everything but `Serve` exits when done.
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    Serve,
+    CheckConfig,
+    CheckSchedules,
+    ConfigSchema,
+    HashPassword,
+    ImportHistory {
+        path: String,
+        day_first: bool,
+    },
+}
+
+impl CliArgs {
+    pub fn command(&self) -> Command {
+        if self.check_config {
+            Command::CheckConfig
+        } else if self.check_schedules {
+            Command::CheckSchedules
+        } else if self.config_schema {
+            Command::ConfigSchema
+        } else if self.hash_password {
+            Command::HashPassword
+        } else if let Some(path) = &self.import_history {
+            Command::ImportHistory {
+                path: path.clone(),
+                day_first: self.day_first,
+            }
+        } else {
+            Command::Serve
+        }
+    }
+}
+
+fn non_empty(value: &str) -> Result<String, String> {
+    if value.is_empty() {
+        return Err("needs a value".to_string());
+    }
+    Ok(value.to_string())
+}
+
+fn socket_address(value: &str) -> Result<String, String> {
+    value
+        .parse::<SocketAddr>()
+        .map(|_| value.to_string())
+        .map_err(|e| format!("invalid address: {}", e))
+}
+
+fn log_filter(value: &str) -> Result<String, String> {
+    EnvFilter::try_new(value)
+        .map(|_| value.to_string())
+        .map_err(|e| format!("invalid filter: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, clap::Error> {
+        CliArgs::try_parse_from(std::iter::once("treat-dispenser-api").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let cli_args = parse(&[]).unwrap();
+        assert_eq!(cli_args, CliArgs::default());
+        assert_eq!(cli_args.command(), Command::Serve);
+
+        let cli_args = parse(&[
+            "--config",
+            "/tmp/config.yaml",
+            "--listen=0.0.0.0:3500",
+            "--log-level",
+            "info,treat_dispenser_api=debug",
+            "--check-config",
+        ])
+        .unwrap();
+        assert_eq!(cli_args.config.as_deref(), Some("/tmp/config.yaml"));
+        assert_eq!(cli_args.listen.as_deref(), Some("0.0.0.0:3500"));
+        assert_eq!(
+            cli_args.log_level.as_deref(),
+            Some("info,treat_dispenser_api=debug")
+        );
+        assert_eq!(cli_args.command(), Command::CheckConfig);
+
+        // --day-first may come before the path
+        assert_eq!(
+            parse(&["--day-first", "--import-history", "export.csv"])
+                .unwrap()
+                .command(),
+            Command::ImportHistory {
+                path: "export.csv".to_string(),
+                day_first: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases: &[&[&str]] = &[
+            &["--verbose"],
+            &["--config"],
+            &["--config="],
+            &["--config", "--check-config"],
+            &["--listen", "localhost"],
+            &["--log-level", "info,treat_dispenser_api=loud"],
+            &["--check-config", "--check-schedules"],
+            &["--day-first"],
+            &["serve"],
+        ];
+        for args in cases {
+            assert!(parse(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+}
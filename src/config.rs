@@ -1,63 +1,1247 @@
+use crate::error::FieldError;
 use crate::utils;
 use crate::motor::stepper_nema14::Nema14Config;
+use crate::sensors::sensor_bme280::Bme280Config;
+use crate::sensors::sensor_dht22::Dht22Config;
+use crate::sensors::sensor_ds18b20::Ds18b20Config;
+use crate::sensors::sensor_ina219::Ina219Config;
+use crate::services::config_validation;
+use crate::services::dispenser::TriggerSource;
+use crate::utils::units::WeightUnit;
 
-use tracing ::{debug};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+use tracing ::{debug, info};
 
+pub const ENV_OVERRIDE_PREFIX: &str = "TD_";
+
+/// Address set with `--listen`, takes precedence over the file and the `TD_` variables.
+static LISTEN_ADDRESS_OVERRIDE: OnceLock<String> = OnceLock::new();
 pub const MOTOR_COOLDOWN_MS_DEFAULT: u64 = 5000;
 pub const MOTOR_CURRENT_LIMIT_AMPS_DEFAULT: f32 = 0.7;
+pub const MOTOR_COOLDOWN_MS_MAX: u64 = 3_600_000;
+pub const GPIO_PIN_MAX: u8 = 27;
+pub const DISPENSE_DEGREES_DEFAULT: f32 = 2160.0;
+pub const MAX_DISPENSE_DEGREES_DEFAULT: f32 = 7200.0;
+pub const WEIGHT_GEOMETRY_FACTOR_DEFAULT: f32 = 1.0;
+pub const LOAD_CELL_GAIN_DEFAULT: f32 = 1.0;
+pub const LOAD_CELL_MISMATCH_TOLERANCE_DEFAULT: f32 = 0.25;
+pub const LOAD_CELL_MISMATCH_MIN_GRAMS_DEFAULT: f32 = 50.0;
+pub const BOWL_SLAVE_SELECT_DEFAULT: u8 = 1;
+pub const BOWL_MIN_DROP_GRAMS_DEFAULT: f32 = 5.0;
+pub const BOWL_DROP_WINDOW_SECS_DEFAULT: u64 = 60;
+pub const BOWL_SETTLE_SECS_DEFAULT: u64 = 20;
+pub const BOWL_IMMEDIATE_MINUTES_DEFAULT: u64 = 5;
+pub const BOWL_IGNORED_MINUTES_DEFAULT: u64 = 60;
+pub const CALIBRATION_KNOWN_MASS_GRAMS_MAX: f32 = 5000.0;
+pub const ACCESS_LOG_FILE_PREFIX_DEFAULT: &str = "access.log";
+pub const ACCESS_LOG_ROTATION_DEFAULT: &str = "daily";
+pub const ACCESS_LOG_MAX_FILES_DEFAULT: usize = 14;
+pub const SYSLOG_PROTOCOL_DEFAULT: &str = "udp";
+pub const SYSLOG_FACILITY_DEFAULT: u8 = 16; // local0
+pub const SYSLOG_LEVEL_DEFAULT: &str = "info";
+pub const SYSLOG_QUEUE_SIZE: usize = 1024;
+pub const RECENT_LOG_LINES: usize = 500;
+pub const POWER_TRACE_SAMPLES: usize = 600;
+pub const CURRENT_JAM_SPIKE_FRACTION_DEFAULT: f32 = 0.8;
+pub const CURRENT_JAM_WINDOW_MS_DEFAULT: u64 = 1000;
+pub const CURRENT_JAM_MIN_SPIKE_SHARE_DEFAULT: f32 = 0.8;
+pub const TRAINING_RATIO_DEFAULT: u32 = 5;
+pub const TRAINING_RATIO_MAX: u32 = 100;
+pub const ERROR_REPORTING_ENVIRONMENT_DEFAULT: &str = "production";
+pub const ERROR_REPORTING_DEDUP_WINDOW_SECS: u64 = 300;
+pub const ERROR_REPORTING_PANIC_TIMEOUT_SECS: u64 = 3;
+pub const BACKUP_INTERVAL_HOURS_DEFAULT: u64 = 24;
+pub const BACKUP_LOCAL_KEEP_DEFAULT: usize = 7;
+pub const BACKUP_S3_REGION_DEFAULT: &str = "us-east-1";
+pub const BACKUP_SFTP_PORT_DEFAULT: u16 = 22;
+pub const STATUS_WAIT_TIMEOUT_SECS_DEFAULT: u64 = 30;
+pub const STATUS_WAIT_TIMEOUT_SECS_MAX: u64 = 120;
+pub const ASSISTANT_DEVICE_NAME_DEFAULT: &str = "Treat Dispenser";
+pub const HOOK_MIN_INTERVAL_SECS_DEFAULT: u64 = 60;
+pub const CORS_MAX_AGE_SECS: u64 = 3600;
+pub const TLS_HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+pub const TLS_CERT_RELOAD_CHECK_SECS: u64 = 60;
+pub const CONFIG_CONFIRM_WITHIN_SECS_MAX: u64 = 3600;
+pub const RATE_LIMIT_LOGIN_REQUESTS_DEFAULT: u32 = 5;
+pub const RATE_LIMIT_DISPENSE_REQUESTS_DEFAULT: u32 = 10;
+pub const RATE_LIMIT_PER_SECONDS_DEFAULT: u64 = 60;
+pub const SHARE_LABEL_MAX_CHARS: usize = 100;
+pub const SHARE_EXPIRES_IN_HOURS_MAX: u64 = 24 * 365;
+pub const WATCHDOG_STALE_AFTER_SECS_DEFAULT: u64 = 10;
+pub const RAW_DEBUG_DURATION_SECS_DEFAULT: u64 = 5;
+pub const I2C_BUS_DEFAULT: u8 = 1;
+pub const DIGITAL_INPUT_DEBOUNCE_MS_DEFAULT: u64 = 50;
+pub const TEMPERATURE_INTERVAL_SECS_DEFAULT: u64 = 10;
+pub const HUMIDITY_SUSTAIN_MINUTES_DEFAULT: u64 = 60;
+pub const FAN_HYSTERESIS_CELSIUS_DEFAULT: f32 = 2.0;
+pub const FAN_PWM_FREQUENCY_HZ: f64 = 100.0;
+pub const RAW_DEBUG_DURATION_SECS_MAX: u64 = 60;
+pub const RAW_DEBUG_INTERVAL_MS_DEFAULT: u64 = 10;
+pub const RAW_DEBUG_INTERVAL_MS_MIN: u64 = 1;
+pub const RAW_DEBUG_INTERVAL_MS_MAX: u64 = 1000;
+pub const DISPENSE_PIECES_MAX: u32 = 50;
+pub const PIECE_CHUNK_DEGREES_DEFAULT: f32 = 180.0;
+pub const DISPENSE_GRAMS_MAX: f32 = 500.0;
+pub const DISPENSE_NOTE_MAX_CHARS: usize = 200;
+pub const GRAMS_TOLERANCE_DEFAULT: f32 = 1.0;
+pub const WEIGHT_DISPENSE_TIMEOUT_SECS_DEFAULT: u64 = 120;
+pub const TRICKLE_BURST_GRAMS_DEFAULT: f32 = 2.0;
+pub const TRICKLE_MAX_DURATION_MINUTES_DEFAULT: u64 = 120;
+pub const JAM_CHECK_AT_FRACTION_DEFAULT: f32 = 0.5;
+pub const JAM_MIN_DROP_GRAMS_DEFAULT: f32 = 1.0;
+pub const REFILL_MIN_INCREASE_GRAMS_DEFAULT: f32 = 20.0;
+pub const REFILL_SUSTAIN_SECS_DEFAULT: u64 = 5;
+pub const VIBRATION_DOWN_WEIGHT_FACTOR_DEFAULT: f32 = 0.2;
+pub const VIBRATION_SETTLE_MS_DEFAULT: u64 = 500;
+pub const VIBRATION_STABLE_TOLERANCE_GRAMS_DEFAULT: f32 = 0.5;
+pub const VIBRATION_SETTLE_WAIT_MAX_MS: u64 = 5000;
+pub const STIR_INTERVAL_MINUTES_DEFAULT: u64 = 240;
+pub const STIR_DEGREES_DEFAULT: f32 = 90.0;
+pub const STIR_CYCLES_DEFAULT: u32 = 3;
+pub const STIR_MAX_MOTOR_SECS_PER_HOUR_DEFAULT: u64 = 60;
+pub const FLEET_INTERVAL_SECS_DEFAULT: u64 = 300;
+pub const FLEET_BACKOFF_MAX_SECS: u64 = 3600;
+pub const WEBHOOK_MAX_ATTEMPTS_DEFAULT: u32 = 5;
+pub const WEBHOOK_MAX_ATTEMPTS_MAX: u32 = 20;
+pub const WEBHOOK_RETRY_DELAY_MS_DEFAULT: u64 = 1000;
+pub const WEBHOOK_BACKOFF_MAX_MS: u64 = 300_000;
+pub const CAMERA_SNAPSHOT_DELAY_MS_DEFAULT: u64 = 2000;
+pub const CAMERA_SNAPSHOT_TIMEOUT_SECS: u64 = 10;
+pub const CAMERA_SNAPSHOT_MAX_BYTES: usize = 2 * 1024 * 1024;
+pub const ADMIN_BATCH_OPERATIONS_MAX: usize = 100;
+pub const MQTT_PORT_DEFAULT: u16 = 1883;
+pub const MQTT_TELEMETRY_INTERVAL_SECS_DEFAULT: u64 = 5;
+pub const SCHEDULE_BUSY_WAIT_SECS: u64 = 300;
+pub const SCHEDULE_JITTER_MINUTES_MAX: u64 = 720;
+pub const SCHEDULE_WARNING_MINUTES_MAX: u64 = 720;
+pub const SCHEDULE_DRY_RUN_DAYS: i64 = 7;
+pub const OVERCURRENT_DISABLE_SECS_DEFAULT: u64 = 300;
+pub const OVERCURRENT_DISABLE_SECS_MAX: u64 = 3600;
+pub const HISTORY_PER_PAGE_DEFAULT: u32 = 20;
+pub const HISTORY_PER_PAGE_MAX: u32 = 100;
+pub const WEIGHT_HISTORY_RETENTION_DAYS_DEFAULT: u32 = 90;
+pub const WEIGHT_HISTORY_RANGE_HOURS_DEFAULT: i64 = 24;
+pub const WEIGHT_HISTORY_INTERVAL_MINUTES_MAX: u32 = 1440;
+pub const WEIGHT_HISTORY_POINTS_MAX: i64 = 20_000;
+pub const CURRENT_CALIBRATION_DEGREES_DEFAULT: f32 = 720.0;
+pub const CURRENT_CALIBRATION_MARGIN_PERCENT_DEFAULT: u32 = 50;
+pub const CURRENT_CALIBRATION_MARGIN_PERCENT_MAX: u32 = 200;
+pub const WEIGHT_STREAM_INTERVAL_MS_DEFAULT: u64 = 500;
+pub const WEIGHT_STREAM_INTERVAL_MS_MIN: u64 = 100;
+pub const WEIGHT_STREAM_INTERVAL_MS_MAX: u64 = 10_000;
+
+/// Identifies this dispenser among several, e.g. `kitchen-feeder` and `barn-feeder`.
+#[derive(
+    serde::Deserialize,
+    serde::Serialize,
+    schemars::JsonSchema,
+    Debug,
+    Clone,
+    PartialEq,
+    utoipa::ToSchema,
+)]
+pub struct DeviceConfig {
+    pub name: String,
+    /// Free text, e.g. "Barn, left stall"
+    pub location: Option<String>,
+    /// Groups dispensers that are managed together
+    pub fleet_id: Option<String>,
+}
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
 pub struct ApiConfig {
     pub listen_address: String,
     pub admin_user: String,
     pub admin_password: String,
+    /// Origins allowed to call the API from a browser, all origins when unset
+    pub cors_allowed_origins: Option<Vec<String>>,
+    pub session_cookies: Option<SessionCookieConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub status_redaction: Option<StatusRedactionConfig>,
+    pub tls: Option<TlsConfig>,
+    /// Texts of error codes and event kinds by language, added to or replacing the
+    /// built-in Finnish ones
+    pub messages: Option<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+/// Serves the API over HTTPS instead of plain HTTP.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate, followed by any intermediate certificates
+    pub cert_path: String,
+    /// PEM file with the certificate's private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: String,
+}
+
+/// Leaves fields out of `/status` and `/status/wait` for requests without an admin token.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct StatusRedactionConfig {
+    /// Field groups (`errors`, `motor`, `power`) or top-level field names of `/status`
+    pub fields: Vec<String>,
+}
+
+/// Limits how often each client IP may call `/login` and the dispense endpoints.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct RateLimitConfig {
+    /// Default 5 requests per 60 seconds
+    pub login: Option<RateLimitRule>,
+    /// Shared by `/dispense`, `/dispense/grams` and `/dispense/trickle`, default 10
+    /// requests per 60 seconds
+    pub dispense: Option<RateLimitRule>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Copy)]
+pub struct RateLimitRule {
+    /// Requests allowed in a burst
+    pub requests: u32,
+    /// Time until a used up burst is fully allowed again
+    pub per_seconds: u64,
+}
+
+/// Lets browser clients log in with an HttpOnly session cookie instead of keeping the
+/// JWT in script-readable storage.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct SessionCookieConfig {
+    pub enabled: Option<bool>,
+    /// Only send the cookies over HTTPS (default true), disable for plain HTTP on the LAN
+    pub secure: Option<bool>,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
 pub struct PowerMonitorConfig {
     pub sensor: String,
     pub motor_current_limit_amps: Option<f32>,
+    pub ina219: Option<Ina219Config>,
+    pub jam_detection: Option<CurrentJamConfig>,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+/// Stops a dispense as jammed when the motor current stays high, as with a blocked auger.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct CurrentJamConfig {
+    pub enabled: Option<bool>,
+    /// Current that counts as a spike, defaults to 80% of `motor_current_limit_amps`
+    pub spike_amps: Option<f32>,
+    /// Readings are judged over this window, longer than the spike when the motor starts
+    /// (default 1000)
+    pub window_ms: Option<u64>,
+    /// Share of the readings in the window that must be spikes (default 0.8)
+    pub min_spike_share: Option<f32>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
 pub struct WeightMonitorConfig {
     pub sensor: String,
+    /// Unit weights are displayed in next to the canonical grams, defaults to grams
+    pub display_unit: Option<WeightUnit>,
+    /// Average weight of one treat, enables piece counts in `/stats`
+    pub piece_weight_grams: Option<f32>,
+    /// Accepted shortfall when dispensing by pieces, defaults to half a piece
+    pub piece_tolerance_grams: Option<f32>,
+    /// Accepted shortfall when dispensing by grams (default 1.0)
+    pub grams_tolerance_grams: Option<f32>,
+    /// Multiplies the calibrated weight for a load cell that isn't directly under the
+    /// hopper center, e.g. 1.25 if it carries 80% of the weight (default 1.0)
+    pub geometry_factor: Option<f32>,
+    /// Load cells summed into one reading, required by the `SensorFused` sensor
+    pub fusion: Option<WeightFusionConfig>,
+    pub jam_detection: Option<JamDetectionConfig>,
+    pub hopper_level: Option<HopperLevelConfig>,
+    pub motor_vibration: Option<MotorVibrationConfig>,
+}
+
+/// Several load cells under one platform, read as a single weight sensor.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct WeightFusionConfig {
+    pub cells: Vec<LoadCellConfig>,
+    /// Largest accepted difference between a cell's share of the weight and its expected
+    /// share before the cell is flagged as failing (default 0.25)
+    pub mismatch_tolerance: Option<f32>,
+    /// Shares are only checked above this total weight (default 50)
+    pub mismatch_min_grams: Option<f32>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct LoadCellConfig {
+    /// Shown in `/status` and events, e.g. `left`
+    pub name: String,
+    /// SensorHX711 | SensorMock
+    pub sensor: String,
+    /// SPI0 slave select of an HX711, 0 to 2 (default 0)
+    pub slave_select: Option<u8>,
+    /// Multiplies the raw readings, matches cells of different sensitivity (default 1.0)
+    pub gain: Option<f32>,
+    /// Share of the weight this cell carries, between 0 and 1 (default equal shares)
+    pub expected_share: Option<f32>,
+}
+
+/// Handling of weight samples taken while the motor shakes the load cell.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct MotorVibrationConfig {
+    /// suppress (default) drops samples while the motor steps, down_weight blends them in
+    pub mode: Option<VibrationMode>,
+    /// Share of a new average that counts while the motor steps in down_weight mode (default 0.2)
+    pub down_weight_factor: Option<f32>,
+    /// Minimum time after the motor stops before readings can be settled (default 500)
+    pub settle_ms: Option<u64>,
+    /// Readings are settled once consecutive averages differ by at most this (default 0.5)
+    pub stable_tolerance_grams: Option<f32>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VibrationMode {
+    #[default]
+    Suppress,
+    DownWeight,
+}
+
+/// Empty detection and automatic recovery after a refill.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct HopperLevelConfig {
+    /// The dispenser is `Empty` while the hopper weighs less than this
+    pub empty_threshold_grams: f32,
+    /// Publishes a `hopper_low` event once the hopper weighs less than this, above
+    /// `empty_threshold_grams`
+    pub low_threshold_grams: Option<f32>,
+    /// Weight increase over the lowest empty reading that counts as a refill (default 20)
+    pub refill_min_increase_grams: Option<f32>,
+    /// How long the increase must hold before the dispenser is operational again (default 5)
+    pub refill_sustain_secs: Option<u64>,
+}
+
+/// Aborts a dispense as jammed if the hopper weight doesn't drop during the run.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct JamDetectionConfig {
+    pub enabled: Option<bool>,
+    /// Fraction of the motor run after which the weight is checked (default 0.5)
+    pub check_at_fraction: Option<f32>,
+    /// Minimum weight drop by then (default 1.0)
+    pub min_drop_grams: Option<f32>,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
 pub struct MotorConfig {
     pub motor_type: String,
     pub nema14: Option<Nema14Config>,
     pub cooldown_ms: Option<u64>,
+    /// Motor rotation per dispense when the request doesn't specify one
+    pub dispense_degrees: Option<f32>,
+    /// Upper bound for `degrees` in dispense requests
+    pub max_dispense_degrees: Option<f32>,
+    /// Rotation between weighings when dispensing by pieces or grams
+    pub piece_chunk_degrees: Option<f32>,
+    /// A dispense by pieces or grams that hasn't reached its target after this long is
+    /// aborted (default 120)
+    pub weight_dispense_timeout_secs: Option<u64>,
+    pub realtime_stepping: Option<RealtimeSteppingConfig>,
+}
+
+/// Generate step pulses on a dedicated realtime thread instead of the async runtime.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct RealtimeSteppingConfig {
+    pub enabled: Option<bool>,
+    /// SCHED_FIFO priority of the step thread, 1-99 (default 50)
+    pub priority: Option<u8>,
+}
+
+/// Settings for the request access log, written independently of tracing output.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct AccessLogConfig {
+    /// Directory the access log files are written to.
+    pub directory: String,
+    /// File name prefix, rotated files get a date suffix appended.
+    pub file_prefix: Option<String>,
+    /// One of: minutely | hourly | daily | never
+    pub rotation: Option<String>,
+    /// Number of rotated files to keep before the oldest is deleted.
+    pub max_files: Option<usize>,
+}
+
+/// Settings for forwarding logs to a remote syslog server (RFC 5424).
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct SyslogConfig {
+    /// host:port of the syslog server
+    pub address: String,
+    /// One of: udp | tcp | tls
+    pub protocol: Option<String>,
+    /// Syslog facility number (0-23), defaults to local0
+    pub facility: Option<u8>,
+    /// Most verbose level forwarded: error | warn | info | debug | trace
+    pub level: Option<String>,
+    /// Overrides the hostname reported in each message
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    /// PEM file of CA certificates trusted for `tls` besides the bundled roots
+    pub ca_cert: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct LoggingConfig {
+    pub access_log: Option<AccessLogConfig>,
+    pub syslog: Option<SyslogConfig>,
+}
+
+/// Settings for reporting panics and internal errors to Sentry and/or a generic webhook.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct ErrorReportingConfig {
+    pub enabled: Option<bool>,
+    /// Sentry DSN, e.g. https://<key>@o0.ingest.sentry.io/<project_id>
+    pub sentry_dsn: Option<String>,
+    /// URL receiving a JSON POST for every reported error
+    pub webhook_url: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// Settings for automatic backups of the data directory.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct BackupConfig {
+    pub enabled: Option<bool>,
+    pub interval_hours: Option<u64>,
+    /// One of: local | s3 | sftp, the matching section below must be present
+    pub destination: String,
+    pub local: Option<LocalBackupConfig>,
+    pub s3: Option<S3BackupConfig>,
+    pub sftp: Option<SftpBackupConfig>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct LocalBackupConfig {
+    pub directory: String,
+    /// Number of backups to keep, older ones are deleted.
+    pub keep: Option<usize>,
+}
+
+/// Any S3-compatible object storage (AWS, MinIO, Backblaze B2, ...), addressed path-style.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct S3BackupConfig {
+    /// e.g. https://s3.eu-west-1.amazonaws.com or http://nas.lan:9000
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: Option<String>,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Key prefix, e.g. `kitchen-dispenser/`
+    pub prefix: Option<String>,
+}
+
+/// Uploads with the system `sftp` client using key authentication.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct SftpBackupConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: String,
+    /// Private key used for authentication, defaults to the ssh client's own configuration
+    pub identity_file: Option<String>,
+    pub remote_directory: String,
+}
+
+/// Google Assistant / Alexa smart home fulfillment, the dispenser is exposed as a scene.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct AssistantConfig {
+    /// Access tokens issued to linked accounts, every fulfillment request must carry one
+    pub access_tokens: Vec<String>,
+    /// Name the assistant uses for the device, e.g. "give the rabbit a treat"
+    pub device_name: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct IntegrationsConfig {
+    pub assistant: Option<AssistantConfig>,
+}
+
+/// A trigger URL for webhook services (IFTTT, Zapier, ...) that can't log in.
+/// The token only grants access to `/hooks/dispense`.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct HookConfig {
+    pub name: String,
+    pub token: String,
+    /// Minimum time between two dispenses triggered by this hook
+    pub min_interval_secs: Option<u64>,
+}
+
+/// A feeding at set times of day or cron times. Set `at`, `cron` or both.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct FeedingScheduleConfig {
+    pub name: String,
+    /// Local times of day, `HH:MM`
+    pub at: Option<Vec<String>>,
+    /// Cron expression with five fields, e.g. `30 7 * * 1-5` for 7:30 on weekdays
+    pub cron: Option<String>,
+    /// Number of treats, measured by weight, requires `weight_monitor.piece_weight_grams`
+    pub pieces: Option<u32>,
+    /// Motor rotation, defaults to `motor.dispense_degrees`
+    pub degrees: Option<f32>,
+    /// Recorded with every feeding in the history, e.g. why the portion was changed
+    pub note: Option<String>,
+    pub enabled: Option<bool>,
+    pub randomize: Option<ScheduleRandomizationConfig>,
+    /// Publishes a `feeding_upcoming` event this many minutes before every feeding, e.g. so
+    /// a camera starts recording in advance
+    pub warn_minutes_before: Option<u64>,
+}
+
+/// Varies the portion and time of every feeding of a schedule, so feedings are less
+/// predictable. The values drawn are logged.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct ScheduleRandomizationConfig {
+    /// Smallest portion, dispensed by weight instead of `pieces` or `degrees`
+    pub min_grams: Option<f32>,
+    pub max_grams: Option<f32>,
+    /// Feedings happen up to this many minutes before or after their time
+    pub jitter_minutes: Option<u64>,
+}
+
+/// Limits applied to every dispense trigger alike: the API, hooks and voice assistants.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct TriggersConfig {
+    /// Times of day no trigger may dispense, e.g. overnight
+    pub quiet_hours: Option<Vec<ConserveWindow>>,
+    /// Maximum dispenses per day, counted since local midnight
+    pub daily_limit: Option<u32>,
+    /// Minimum time between two dispenses, whichever trigger started them
+    pub min_interval_secs: Option<u64>,
+    pub arbitration: Option<ArbitrationConfig>,
+}
+
+/// How competing triggers are resolved, e.g. a hook firing during a button press.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Default)]
+pub struct ArbitrationConfig {
+    /// Triggers arriving within this time of the first one are merged into one dispense.
+    /// Delays every dispense by the window, unset dispenses right away
+    pub coalesce_window_ms: Option<u64>,
+    /// Trigger sources by priority, highest first. The merged dispense is started for the
+    /// highest one with its amount, unlisted sources come last
+    pub priority: Option<Vec<TriggerSource>>,
+    /// Trigger sources that may not dispense
+    pub disabled: Option<Vec<TriggerSource>>,
+}
+
+/// Firebase Cloud Messaging (HTTP v1 API) for the companion app.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct FcmConfig {
+    /// Service account key JSON downloaded from the Firebase console
+    pub service_account_file: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct NotificationsConfig {
+    pub fcm: Option<FcmConfig>,
+}
+
+/// Heartbeat reporting status and version to a central fleet dashboard.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct FleetConfig {
+    pub enabled: Option<bool>,
+    /// Receives every heartbeat as a JSON POST
+    pub url: String,
+    /// Time between heartbeats (default 300)
+    pub interval_secs: Option<u64>,
+    /// Shared secret the heartbeat body is signed with (HMAC-SHA256)
+    pub secret: Option<String>,
+}
+
+/// Events a webhook can be sent for.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    DispenseCompleted,
+    /// Jammed, stalled or otherwise failed motor runs
+    DispenseFailed,
+    DispenseCancelled,
+    /// The hopper level fell below `weight_monitor.hopper_level.low_threshold_grams`
+    HopperLow,
+    /// The hopper level fell below `weight_monitor.hopper_level.empty_threshold_grams`
+    HopperEmpty,
+}
+
+/// An endpoint that receives dispenser events as JSON POSTs.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Events to send, defaults to all of them
+    pub events: Option<Vec<WebhookEvent>>,
+    /// Shared secret the body is signed with (HMAC-SHA256)
+    pub secret: Option<String>,
+    /// Attempts per event before giving up (default 5)
+    pub max_attempts: Option<u32>,
+    /// Delay before the first retry, doubled with every further one (default 1000)
+    pub retry_delay_ms: Option<u64>,
+    /// Attach a `camera` snapshot to `dispense_completed` (default false)
+    pub attach_snapshot: Option<bool>,
+}
+
+/// Camera that takes a picture of the bowl after a dispense, for the webhooks.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct CameraConfig {
+    /// URL that answers a GET with a JPEG, e.g. the snapshot URL of an IP camera
+    pub snapshot_url: String,
+    /// Wait after the dispense, so the treats have landed (default 2000)
+    pub delay_ms: Option<u64>,
+}
+
+/// Telemetry published to an MQTT broker, e.g. for a home automation system.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct MqttConfig {
+    pub enabled: Option<bool>,
+    pub host: String,
+    /// Defaults to 1883, TLS is not supported
+    pub port: Option<u16>,
+    /// Defaults to `treat-dispenser-<device name>`
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Prefix of the default topics, defaults to `treat-dispenser/<device name>`
+    pub topic_prefix: Option<String>,
+    /// Dispenser status changes, retained (default `<prefix>/status`)
+    pub status_topic: Option<String>,
+    /// Weight readings (default `<prefix>/weight`)
+    pub weight_topic: Option<String>,
+    /// Power readings (default `<prefix>/power`)
+    pub power_topic: Option<String>,
+    /// Minimum time between weight and power messages (default 5)
+    pub telemetry_interval_secs: Option<u64>,
+}
+
+/// Minute averages of the hopper weight kept for `GET /weight/history`.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct WeightHistoryConfig {
+    /// Recording is on unless disabled here
+    pub enabled: Option<bool>,
+    /// Older averages are deleted (default 90)
+    pub retention_days: Option<u32>,
+}
+
+/// Alarms when sensor readings stop being published.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct WatchdogConfig {
+    pub stale_after_secs: Option<u64>,
+}
+
+/// Periodic back-and-forth motor runs that keep treats from settling in the hopper.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct StirConfig {
+    pub enabled: Option<bool>,
+    pub interval_minutes: Option<u64>,
+    /// Rotation in each direction per cycle
+    pub degrees: Option<f32>,
+    pub cycles: Option<u32>,
+    /// Motor runtime stirring may use within any hour, runs are skipped once it is used up
+    pub max_motor_secs_per_hour: Option<u64>,
+}
+
+/// Dispenses spread over time in small bursts, see `POST /dispense/trickle`.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct TrickleConfig {
+    /// Weight dispensed per burst (default 2.0)
+    pub burst_grams: Option<f32>,
+    /// Upper bound for `duration_minutes` in trickle requests (default 120)
+    pub max_duration_minutes: Option<u64>,
+}
+
+/// Load cell under the food bowl, detects the pet eating.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct BowlConfig {
+    /// SensorHX711 | SensorMock
+    pub sensor: String,
+    /// SPI0 slave select of the HX711 (default 1, next to the hopper cell on 0)
+    pub slave_select: Option<u8>,
+    /// Raw reading change per gram, only weight changes are measured so no tare is needed
+    /// (default 1.0)
+    pub counts_per_gram: Option<f32>,
+    /// Weight decrease that counts as eating (default 5)
+    pub min_drop_grams: Option<f32>,
+    /// The decrease must happen within this time, slower ones are drift (default 60)
+    pub drop_window_secs: Option<u64>,
+    /// Eating is over once the weight hasn't decreased further for this long (default 20)
+    pub settle_secs: Option<u64>,
+    /// Eating within this time after a dispense counts as immediate (default 5)
+    pub immediate_minutes: Option<u64>,
+    /// A dispense not followed by eating within this time was ignored (default 60)
+    pub ignored_minutes: Option<u64>,
+}
+
+/// Enclosure temperature (and humidity) sensor, optionally limiting when treats may be dispensed.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct TemperatureConfig {
+    /// SensorDS18B20, SensorDHT22, SensorBME280 or SensorMock
+    pub sensor: String,
+    /// Time between readings (default 10)
+    pub interval_secs: Option<u64>,
+    /// Dispense requests are refused above this temperature
+    pub max_dispense_celsius: Option<f32>,
+    /// Dispense requests are refused below this temperature
+    pub min_dispense_celsius: Option<f32>,
+    pub ds18b20: Option<Ds18b20Config>,
+    pub dht22: Option<Dht22Config>,
+    pub bme280: Option<Bme280Config>,
+    pub humidity_warning: Option<HumidityWarningConfig>,
+}
+
+/// Warns when the hopper stays humid, soggy treats jam the auger.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct HumidityWarningConfig {
+    /// Relative humidity above which treats are at risk
+    pub max_percent: f32,
+    /// How long the humidity must stay above `max_percent` before warning (default 60)
+    pub sustain_minutes: Option<u64>,
+}
+
+/// Enclosure fan switched by the temperature sensor, through a transistor or MOSFET on a GPIO pin.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct FanConfig {
+    /// BCM GPIO number
+    pub pin: u8,
+    /// The fan starts above this temperature
+    pub target_celsius: f32,
+    /// The fan stops once the temperature is this far below the target (default 2)
+    pub hysteresis_celsius: Option<f32>,
+    /// Below 100 the fan runs on 100 Hz software PWM (default 100)
+    pub speed_percent: Option<u8>,
+}
+
+/// Energy saving for battery or solar powered installs. While conserving, background
+/// tasks like stirring and scheduled backups are deferred.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct EnergyConfig {
+    /// Conserve while the supply voltage measured by the power monitor is below this
+    pub low_battery_volts: Option<f32>,
+    /// Times of day to always conserve, e.g. overnight
+    pub conserve_windows: Option<Vec<ConserveWindow>>,
+}
+
+/// Local time range, `HH:MM`. A window may span midnight, e.g. `20:00` to `07:00`.
+/// Also used for `triggers.quiet_hours`.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct ConserveWindow {
+    pub start: String,
+    pub end: String,
+}
+
+/// What happens at startup when the previous run stopped in the middle of a dispense,
+/// e.g. because of a power cut.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct DispenseRecoveryConfig {
+    /// Backwards rotation to free treats left in the auger, no motor run when unset
+    pub reverse_degrees: Option<f32>,
+    /// Status the dispenser starts in: unknown (default) | jammed
+    pub status: Option<RecoveryStatus>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecoveryStatus {
+    #[default]
+    Unknown,
+    Jammed,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+/// A switch or sensor on a GPIO pin, e.g. a lid switch. Level changes are published as events.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct DigitalInputConfig {
+    /// Name used in events and `/status`, must be unique
+    pub label: String,
+    /// BCM GPIO number
+    pub pin: u8,
+    pub pull: Option<InputPull>,
+    /// How long a new level must hold before it counts as a change (default 50)
+    pub debounce_ms: Option<u64>,
+}
+
+/// Training mode, presses on a button or paw pad are rewarded on a reinforcement schedule.
+/// See `POST /training/start`.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct TrainingConfig {
+    /// Label of the `digital_inputs` entry the pet presses
+    pub input: String,
+    /// Input level while pressed (default false, a button to ground with the pull-up)
+    pub pressed_high: Option<bool>,
+    /// fixed (default) | variable
+    pub schedule: Option<ReinforcementSchedule>,
+    /// Presses per reward, on average for a variable schedule (default 5)
+    pub ratio: Option<u32>,
+    /// Rotation per reward, defaults to `motor.dispense_degrees`
+    pub reward_degrees: Option<f32>,
+}
+
+/// When a press is rewarded: after every `ratio` presses (fixed), or after a random
+/// number of presses averaging `ratio` (variable).
+#[derive(
+    serde::Deserialize,
+    serde::Serialize,
+    schemars::JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ReinforcementSchedule {
+    #[default]
+    Fixed,
+    Variable,
+}
+
+/// Auxiliary hardware on a GPIO pin, e.g. a chute light, switched with `POST /outputs/{label}`.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
+pub struct DigitalOutputConfig {
+    /// Name used in the endpoint path, events and `/status`, must be unique
+    pub label: String,
+    /// BCM GPIO number
+    pub pin: u8,
+}
+
+/// Internal pull resistor enabled on a digital input.
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputPull {
+    #[default]
+    Up,
+    Down,
+    None,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, schemars::JsonSchema, Debug, Clone)]
 pub struct AppConfig {
+    pub device: Option<DeviceConfig>,
     pub api: ApiConfig,
     pub motor: MotorConfig,
     pub power_monitor: PowerMonitorConfig,
     pub weight_monitor: WeightMonitorConfig,
+    pub bowl: Option<BowlConfig>,
+    pub temperature: Option<TemperatureConfig>,
+    pub fan: Option<FanConfig>,
+    pub logging: Option<LoggingConfig>,
+    pub error_reporting: Option<ErrorReportingConfig>,
+    pub backup: Option<BackupConfig>,
+    pub integrations: Option<IntegrationsConfig>,
+    pub hooks: Option<Vec<HookConfig>>,
+    pub triggers: Option<TriggersConfig>,
+    pub schedules: Option<Vec<FeedingScheduleConfig>>,
+    pub notifications: Option<NotificationsConfig>,
+    pub fleet: Option<FleetConfig>,
+    pub webhooks: Option<Vec<WebhookConfig>>,
+    pub camera: Option<CameraConfig>,
+    pub mqtt: Option<MqttConfig>,
+    pub weight_history: Option<WeightHistoryConfig>,
+    pub watchdog: Option<WatchdogConfig>,
+    pub stir: Option<StirConfig>,
+    pub trickle: Option<TrickleConfig>,
+    pub energy: Option<EnergyConfig>,
+    pub dispense_recovery: Option<DispenseRecoveryConfig>,
+    pub digital_inputs: Option<Vec<DigitalInputConfig>>,
+    pub digital_outputs: Option<Vec<DigitalOutputConfig>>,
+    pub training: Option<TrainingConfig>,
+}
+
+/// JSON Schema of the config file, for validating configs before deploying them.
+pub fn app_config_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(AppConfig)).unwrap_or_default()
+}
+
+/// Serves the API on `listen_address` whatever the config file says, also after a reload.
+/// Only the first call counts.
+pub fn override_listen_address(listen_address: String) {
+    let _ = LISTEN_ADDRESS_OVERRIDE.set(listen_address);
+}
+
+/// Parses the config file and applies the `TD_` environment variables on top of it, see
+/// `apply_env_overrides`, then the `--listen` address.
+pub fn parse_app_config(config_str: &str) -> Result<AppConfig, String> {
+    let mut app_config = parse_with_env_overrides(config_str)?;
+    if let Some(listen_address) = LISTEN_ADDRESS_OVERRIDE.get() {
+        app_config.api.listen_address = listen_address.clone();
+    }
+    Ok(app_config)
+}
+
+fn parse_with_env_overrides(config_str: &str) -> Result<AppConfig, String> {
+    let overrides: Vec<(String, String)> = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .filter(|(name, _)| name.starts_with(ENV_OVERRIDE_PREFIX))
+        .collect();
+    if overrides.is_empty() {
+        // straight from the text, so errors keep their line numbers
+        return serde_yaml::from_str(config_str).map_err(|e| e.to_string());
+    }
+
+    let mut config: serde_yaml::Value =
+        serde_yaml::from_str(config_str).map_err(|e| e.to_string())?;
+    let overridden = apply_env_overrides(&mut config, overrides)?;
+    info!(
+        "Config overridden by environment variables: {}",
+        overridden.join(", ")
+    );
+    serde_yaml::from_value(config).map_err(|e| format!("{} (after applying the TD_ overrides)", e))
+}
+
+/// Sets config values from `TD_` environment variables. `__` separates the levels, e.g.
+/// `TD_MOTOR__COOLDOWN_MS=2000` sets `motor.cooldown_ms`, and a number selects a list
+/// entry, e.g. `TD_SCHEDULES__0__TIME`. Values are read as YAML, so numbers and booleans
+/// keep their type and an empty value unsets an optional setting. Returns the dotted
+/// paths that were set.
+fn apply_env_overrides(
+    config: &mut serde_yaml::Value,
+    vars: Vec<(String, String)>,
+) -> Result<Vec<String>, String> {
+    let mut vars = vars;
+    // parents before their children
+    vars.sort();
+    let mut overridden = Vec::new();
+    for (name, raw_value) in vars {
+        let Some(path) = name.strip_prefix(ENV_OVERRIDE_PREFIX) else {
+            continue;
+        };
+        let keys: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+        if keys.iter().any(String::is_empty) {
+            return Err(format!("{}: empty key in the variable name", name));
+        }
+        let value = serde_yaml::from_str(&raw_value)
+            .unwrap_or_else(|_| serde_yaml::Value::String(raw_value.clone()));
+        set_override(config, &keys, value).map_err(|e| format!("{}: {}", name, e))?;
+        overridden.push(keys.join("."));
+    }
+    Ok(overridden)
+}
+
+fn set_override(
+    node: &mut serde_yaml::Value,
+    keys: &[String],
+    value: serde_yaml::Value,
+) -> Result<(), String> {
+    let Some((key, rest)) = keys.split_first() else {
+        *node = value;
+        return Ok(());
+    };
+    let index = key.parse::<usize>().ok();
+    if node.is_null() {
+        *node = match index {
+            Some(_) => serde_yaml::Value::Sequence(Vec::new()),
+            None => serde_yaml::Value::Mapping(serde_yaml::Mapping::new()),
+        };
+    }
+    let child = match (node, index) {
+        (serde_yaml::Value::Mapping(mapping), _) => mapping
+            .entry(serde_yaml::Value::String(key.clone()))
+            .or_insert(serde_yaml::Value::Null),
+        (serde_yaml::Value::Sequence(entries), Some(index)) => {
+            // the next entry can be added, so lists can be built from variables alone
+            if index == entries.len() {
+                entries.push(serde_yaml::Value::Null);
+            }
+            let count = entries.len();
+            entries.get_mut(index).ok_or_else(|| {
+                format!(
+                    "index {} is out of range, the list has {} entries",
+                    index, count
+                )
+            })?
+        }
+        (serde_yaml::Value::Sequence(_), None) => {
+            return Err(format!("'{}' is a list, expected an index", key));
+        }
+        _ => return Err(format!("'{}' is below a value that isn't a section", key)),
+    };
+    set_override(child, rest, value)
 }
 
 pub fn load_app_config_from_str(config_str: &str) -> AppConfig {
-    serde_yaml::from_str(config_str).expect("Failed to parse app config")
+    parse_app_config(config_str).expect("Failed to parse app config")
 }
 
-pub fn load_app_config() -> AppConfig {
+/// Why the config file couldn't be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String),
+    Parse(String),
+    Invalid(Vec<FieldError>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(msg) => write!(f, "{}", msg),
+            ConfigError::Parse(msg) => write!(f, "{}", msg),
+            ConfigError::Invalid(errors) => {
+                write!(f, "The config file has {} problem(s):", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  {}: {}", error.field, error.message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads, parses and validates the config file, see `config_validation::validate`.
+pub fn load_app_config() -> Result<AppConfig, ConfigError> {
     let app_config_path = utils::filesystem::get_config_path();
-    let config_str = std::fs::read_to_string(&app_config_path).expect(&format!(
-        "Failed to read app config file at {}",
-        app_config_path
-    ));
+    let config_str = std::fs::read_to_string(&app_config_path).map_err(|e| {
+        ConfigError::Read(format!(
+            "Failed to read app config file at {}: {}",
+            app_config_path, e
+        ))
+    })?;
 
-    let app_config: AppConfig = load_app_config_from_str(&config_str);
+    let app_config = parse_app_config(&config_str).map_err(|e| {
+        ConfigError::Parse(format!(
+            "Failed to parse app config file at {}: {}",
+            app_config_path, e
+        ))
+    })?;
+    let errors = config_validation::validate(&app_config);
+    if !errors.is_empty() {
+        return Err(ConfigError::Invalid(errors));
+    }
 
     // Log the config struct as json
     debug!(
         "Parsed app config: {}",
         serde_json::to_string(&app_config).unwrap_or_default()
     );
-    app_config
+    Ok(app_config)
+}
+
+/// Builds an `AppConfig` in code, for tests and programs embedding the dispenser. Starts
+/// with the mock motor and sensors and no optional sections, listening on
+/// `127.0.0.1:3500`.
+///
+/// ```
+/// use treat_dispenser_api::config::{AppConfigBuilder, TriggersConfig};
+///
+/// let app_config = AppConfigBuilder::new("admin", "password")
+///     .listen_address("127.0.0.1:0")
+///     .cooldown_ms(1000)
+///     .triggers(TriggersConfig {
+///         daily_limit: Some(4),
+///         ..Default::default()
+///     })
+///     .build();
+/// assert_eq!(app_config.motor.motor_type, "StepperMock");
+/// ```
+pub struct AppConfigBuilder {
+    app_config: AppConfig,
+}
+
+impl AppConfigBuilder {
+    pub fn new(admin_user: &str, admin_password: &str) -> Self {
+        AppConfigBuilder {
+            app_config: AppConfig {
+                device: None,
+                api: ApiConfig {
+                    listen_address: "127.0.0.1:3500".to_string(),
+                    admin_user: admin_user.to_string(),
+                    admin_password: admin_password.to_string(),
+                    cors_allowed_origins: None,
+                    session_cookies: None,
+                    rate_limit: None,
+                    status_redaction: None,
+                    tls: None,
+                    messages: None,
+                },
+                motor: MotorConfig {
+                    motor_type: "StepperMock".to_string(),
+                    nema14: None,
+                    cooldown_ms: None,
+                    dispense_degrees: None,
+                    max_dispense_degrees: None,
+                    piece_chunk_degrees: None,
+                    weight_dispense_timeout_secs: None,
+                    realtime_stepping: None,
+                },
+                power_monitor: PowerMonitorConfig {
+                    sensor: "SensorMock".to_string(),
+                    motor_current_limit_amps: None,
+                    ina219: None,
+                    jam_detection: None,
+                },
+                weight_monitor: WeightMonitorConfig {
+                    sensor: "SensorMock".to_string(),
+                    display_unit: None,
+                    piece_weight_grams: None,
+                    piece_tolerance_grams: None,
+                    grams_tolerance_grams: None,
+                    geometry_factor: None,
+                    fusion: None,
+                    jam_detection: None,
+                    hopper_level: None,
+                    motor_vibration: None,
+                },
+                bowl: None,
+                temperature: None,
+                fan: None,
+                logging: None,
+                error_reporting: None,
+                backup: None,
+                integrations: None,
+                hooks: None,
+                triggers: None,
+                schedules: None,
+                notifications: None,
+                fleet: None,
+                webhooks: None,
+                camera: None,
+                mqtt: None,
+                weight_history: None,
+                watchdog: None,
+                stir: None,
+                trickle: None,
+                energy: None,
+                dispense_recovery: None,
+                digital_inputs: None,
+                digital_outputs: None,
+                training: None,
+            },
+        }
+    }
+
+    pub fn device(mut self, device: DeviceConfig) -> Self {
+        self.app_config.device = Some(device);
+        self
+    }
+
+    pub fn listen_address(mut self, listen_address: &str) -> Self {
+        self.app_config.api.listen_address = listen_address.to_string();
+        self
+    }
+
+    pub fn session_cookies(mut self, session_cookies: SessionCookieConfig) -> Self {
+        self.app_config.api.session_cookies = Some(session_cookies);
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.app_config.api.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Field groups or field names of `/status` hidden from requests without an admin token
+    pub fn status_redaction(mut self, fields: &[&str]) -> Self {
+        self.app_config.api.status_redaction = Some(StatusRedactionConfig {
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Stepper28BYJ48 | StepperNema14 | StepperMock
+    pub fn motor_type(mut self, motor_type: &str) -> Self {
+        self.app_config.motor.motor_type = motor_type.to_string();
+        self
+    }
+
+    pub fn nema14(mut self, nema14: Nema14Config) -> Self {
+        self.app_config.motor.nema14 = Some(nema14);
+        self
+    }
+
+    pub fn cooldown_ms(mut self, cooldown_ms: u64) -> Self {
+        self.app_config.motor.cooldown_ms = Some(cooldown_ms);
+        self
+    }
+
+    pub fn dispense_degrees(mut self, dispense_degrees: f32) -> Self {
+        self.app_config.motor.dispense_degrees = Some(dispense_degrees);
+        self
+    }
+
+    /// SensorINA219 | SensorMock
+    pub fn power_sensor(mut self, sensor: &str) -> Self {
+        self.app_config.power_monitor.sensor = sensor.to_string();
+        self
+    }
+
+    pub fn motor_current_limit_amps(mut self, amps: f32) -> Self {
+        self.app_config.power_monitor.motor_current_limit_amps = Some(amps);
+        self
+    }
+
+    pub fn current_jam_detection(mut self, jam_detection: CurrentJamConfig) -> Self {
+        self.app_config.power_monitor.jam_detection = Some(jam_detection);
+        self
+    }
+
+    /// SensorHX711 | SensorFused | SensorMock
+    pub fn weight_sensor(mut self, sensor: &str) -> Self {
+        self.app_config.weight_monitor.sensor = sensor.to_string();
+        self
+    }
+
+    pub fn piece_weight_grams(mut self, grams: f32) -> Self {
+        self.app_config.weight_monitor.piece_weight_grams = Some(grams);
+        self
+    }
+
+    pub fn weight_fusion(mut self, fusion: WeightFusionConfig) -> Self {
+        self.app_config.weight_monitor.fusion = Some(fusion);
+        self
+    }
+
+    pub fn weight_jam_detection(mut self, jam_detection: JamDetectionConfig) -> Self {
+        self.app_config.weight_monitor.jam_detection = Some(jam_detection);
+        self
+    }
+
+    pub fn hopper_level(mut self, hopper_level: HopperLevelConfig) -> Self {
+        self.app_config.weight_monitor.hopper_level = Some(hopper_level);
+        self
+    }
+
+    pub fn bowl(mut self, bowl: BowlConfig) -> Self {
+        self.app_config.bowl = Some(bowl);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: TemperatureConfig) -> Self {
+        self.app_config.temperature = Some(temperature);
+        self
+    }
+
+    pub fn fan(mut self, fan: FanConfig) -> Self {
+        self.app_config.fan = Some(fan);
+        self
+    }
+
+    pub fn triggers(mut self, triggers: TriggersConfig) -> Self {
+        self.app_config.triggers = Some(triggers);
+        self
+    }
+
+    pub fn schedule(mut self, schedule: FeedingScheduleConfig) -> Self {
+        self.app_config
+            .schedules
+            .get_or_insert_with(Vec::new)
+            .push(schedule);
+        self
+    }
+
+    pub fn digital_input(mut self, input: DigitalInputConfig) -> Self {
+        self.app_config
+            .digital_inputs
+            .get_or_insert_with(Vec::new)
+            .push(input);
+        self
+    }
+
+    pub fn digital_output(mut self, output: DigitalOutputConfig) -> Self {
+        self.app_config
+            .digital_outputs
+            .get_or_insert_with(Vec::new)
+            .push(output);
+        self
+    }
+
+    /// Changes settings without a setter of their own.
+    pub fn with(mut self, update: impl FnOnce(&mut AppConfig)) -> Self {
+        update(&mut self.app_config);
+        self
+    }
+
+    pub fn build(self) -> AppConfig {
+        self.app_config
+    }
 }
 
 #[cfg(test)]
@@ -88,6 +1272,11 @@ mod tests {
 
         weight_monitor:
             sensor: "SensorHX711"
+
+        logging:
+            access_log:
+              directory: "/var/log/treat-dispenser-api"
+              rotation: "hourly"
         "# ;  
 
         let config = load_app_config_from_str(config_str);
@@ -111,5 +1300,107 @@ mod tests {
         assert_eq!(nema14_config.reset_pin, 6);
         assert_eq!(nema14_config.enable_pin, 17);
 
+        let access_log_config = config.logging.unwrap().access_log.unwrap();
+        assert_eq!(access_log_config.directory, "/var/log/treat-dispenser-api");
+        assert_eq!(access_log_config.rotation, Some("hourly".to_string()));
+        assert_eq!(access_log_config.max_files, None);
+
+    }
+
+    #[test]
+    fn test_app_config_builder_matches_yaml() {
+        let built = AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .cooldown_ms(0)
+            .triggers(TriggersConfig {
+                daily_limit: Some(3),
+                ..Default::default()
+            })
+            .with(|config| config.motor.weight_dispense_timeout_secs = Some(1))
+            .build();
+        let parsed = load_app_config_from_str(
+            r#"
+            api:
+              listen_address: "127.0.0.1:0"
+              admin_user: "admin"
+              admin_password: "password"
+            power_monitor:
+              sensor: "SensorMock"
+            weight_monitor:
+              sensor: "SensorMock"
+            motor:
+              motor_type: "StepperMock"
+              cooldown_ms: 0
+              weight_dispense_timeout_secs: 1
+            triggers:
+              daily_limit: 3
+            "#,
+        );
+        assert_eq!(
+            serde_json::to_value(&built).unwrap(),
+            serde_json::to_value(&parsed).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_apply_env_overrides() {
+        let mut config: serde_yaml::Value = serde_yaml::from_str(
+            r#"
+            motor:
+              motor_type: "StepperMock"
+              cooldown_ms: 5000
+            schedules:
+              - name: "breakfast"
+                cron: "0 0 7 * * *"
+            "#,
+        )
+        .unwrap();
+        let vars = [
+            ("TD_MOTOR__COOLDOWN_MS", "2000"),
+            ("TD_SCHEDULES__0__NAME", "early breakfast"),
+            ("TD_MOTOR__REALTIME_STEPPING__ENABLED", "true"),
+            ("TD_API__LISTEN_ADDRESS", "0.0.0.0:3500"),
+            ("TD_MOTOR__MOTOR_TYPE", ""),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let overridden = apply_env_overrides(&mut config, vars).unwrap();
+        assert_eq!(
+            overridden,
+            vec![
+                "api.listen_address",
+                "motor.cooldown_ms",
+                "motor.motor_type",
+                "motor.realtime_stepping.enabled",
+                "schedules.0.name",
+            ]
+        );
+        assert_eq!(
+            config["motor"]["cooldown_ms"],
+            serde_yaml::Value::from(2000)
+        );
+        assert!(config["motor"]["motor_type"].is_null());
+        assert_eq!(
+            config["motor"]["realtime_stepping"]["enabled"],
+            serde_yaml::Value::from(true)
+        );
+        assert_eq!(config["api"]["listen_address"], "0.0.0.0:3500");
+        assert_eq!(config["schedules"][0]["name"], "early breakfast");
+        assert_eq!(config["schedules"][0]["cron"], "0 0 7 * * *");
+
+        let error = apply_env_overrides(
+            &mut config,
+            vec![("TD_SCHEDULES__3__NAME".to_string(), "lunch".to_string())],
+        )
+        .unwrap_err();
+        assert!(error.starts_with("TD_SCHEDULES__3__NAME: index 3 is out of range"));
+        assert!(
+            apply_env_overrides(
+                &mut config,
+                vec![("TD_MOTOR__COOLDOWN_MS__X".to_string(), "1".to_string())],
+            )
+            .is_err()
+        );
     }
 }
\ No newline at end of file
@@ -1,16 +1,48 @@
 use axum::{
+    Extension,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use tracing::warn;
 
-#[derive(Debug)]
+use crate::services::error_reporting::{self, ErrorKind};
+
+/// A single invalid field in a request body.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Body of a 422 response.
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub struct ValidationErrorBody {
+    /// Always `Validation failed`
+    pub error: String,
+    pub fields: Vec<FieldError>,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum ApiError {
     Unauthorized,
+    Forbidden(String),
     Busy(String),
     Hardware(String),
     BadRequest(String),
+    NotFound(String),
+    RateLimited(String),
+    Validation(Vec<FieldError>),
     Internal(String),
 }
 
@@ -19,27 +51,99 @@ impl fmt::Display for ApiError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ApiError::Unauthorized => write!(f, "Unauthorized request"),
+            ApiError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             ApiError::Busy(msg) => write!(f, "Dispenser is busy: {}", msg),
             ApiError::Hardware(msg) => write!(f, "Hardware error: {}", msg),
             ApiError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            ApiError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            ApiError::RateLimited(msg) => write!(f, "Too many requests: {}", msg),
+            ApiError::Validation(errors) => {
+                let fields: Vec<String> = errors
+                    .iter()
+                    .map(|e| format!("{} {}", e.field, e.message))
+                    .collect();
+                write!(f, "Validation failed: {}", fields.join(", "))
+            }
             ApiError::Internal(msg) => write!(f, "Internal server error: {}", msg),
         }
     }
 }
 
+/// Code and details of an error response, kept in its extensions so the localization
+/// middleware can translate it.
+#[derive(Debug, Clone)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    /// The message after the error's title, left in English
+    pub detail: Option<String>,
+}
+
+impl ApiError {
+    /// Stable code of the error, e.g. `not_found`, the key of its message in the catalogue.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Busy(_) => "busy",
+            ApiError::Hardware(_) => "hardware",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            ApiError::Unauthorized | ApiError::Validation(_) => None,
+            ApiError::Forbidden(msg)
+            | ApiError::Busy(msg)
+            | ApiError::Hardware(msg)
+            | ApiError::BadRequest(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::RateLimited(msg)
+            | ApiError::Internal(msg) => Some(msg.clone()),
+        }
+    }
+}
+
 // tells axum how to convert ApiError into an HTTP response
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         warn!("{}", self);
+        let error_code = ErrorCode {
+            code: self.code(),
+            detail: self.detail(),
+        };
         let (status, body) = match self {
             ApiError::Unauthorized => {
                 (StatusCode::UNAUTHORIZED, "Unauthorized request".to_string())
             }
+            ApiError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
             ApiError::Hardware(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ApiError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            ApiError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            ApiError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            ApiError::Internal(_) => {
+                error_reporting::report(ErrorKind::Internal, self.to_string());
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
             ApiError::Busy(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            ApiError::Validation(errors) => {
+                // field level details are returned as JSON so clients can highlight the inputs
+                let body = ValidationErrorBody {
+                    error: "Validation failed".to_string(),
+                    fields: errors,
+                };
+                return (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Extension(error_code),
+                    axum::Json(body),
+                )
+                    .into_response();
+            }
         };
-        (status, body).into_response()
+        (status, Extension(error_code), body).into_response()
     }
 }
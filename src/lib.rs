@@ -1,5 +1,8 @@
 pub mod application_state;
+pub mod builder;
+pub mod cli;
 pub mod error;
+pub mod logging;
 pub mod middleware;
 pub mod motor;
 pub mod routes;
@@ -9,73 +12,208 @@ pub mod utils;
 pub mod config;
 
 use axum::extract::ConnectInfo;
-use axum::http::{Method, Request, StatusCode};
-use axum::{Router, routing::get, routing::post};
+use axum::serve::ListenerExt;
+use axum::http::{HeaderValue, Method, Request, StatusCode, header};
+use axum::{Extension, Router};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tower_http::trace::{DefaultOnFailure, TraceLayer};
 use tracing::{Level, error, info, trace, warn};
-use tracing_subscriber::EnvFilter;
-
-use crate::application_state::ApplicationState;
-use crate::config::AppConfig;
-
-pub fn configure_logging() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_env("RUST_LOG").unwrap_or_else(|_| {
-                info!("RUST_LOG not set, using default log level 'info'");
-                EnvFilter::new("info") // Default log level if not set
-            }),
-        )
-        .with_thread_ids(true)
-        .with_thread_names(false)
-        .with_writer(std::io::stdout) // log to stdout for compat with containerized environments
-        .init();
-}
+
+use crate::application_state::{ApplicationState, Drivers};
+use crate::config::{ApiConfig, AppConfig};
+use crate::routes::route_table::{RouteAuth, RouteTable};
+
+pub use builder::{Dispenser, DispenserBuilder};
+pub use logging::{
+    configure_logging, configure_logging_with_config, configure_logging_with_filter,
+};
 
 /// Builds the Axum application with routes and shared state.
 /// A TraceLayer is added for logging client request details.
 pub fn build_app(app_config: AppConfig) -> (Arc<Mutex<ApplicationState>>, axum::Router) {
-    let app_state = Arc::new(Mutex::new(ApplicationState::new(
-        app_config,
+    build_app_with_drivers(app_config, Drivers::default())
+}
+
+/// Like `build_app`, with drivers that take the place of the ones selected in the config.
+pub fn build_app_with_drivers(
+    app_config: AppConfig,
+    drivers: Drivers,
+) -> (Arc<Mutex<ApplicationState>>, axum::Router) {
+    services::error_reporting::init(&app_config);
+
+    let access_log_config = app_config
+        .logging
+        .as_ref()
+        .and_then(|logging| logging.access_log.clone());
+
+    let cors = build_cors_layer(&app_config.api);
+    let rate_limit_config = app_config.api.rate_limit.clone();
+    let status_redaction_config = app_config.api.status_redaction.clone();
+    let message_catalogue =
+        services::localization::MessageCatalogue::new(app_config.api.messages.as_ref());
+
+    let app_state = Arc::new(Mutex::new(ApplicationState::with_drivers(
+        app_config, drivers,
     )));
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any) // Allow all origins for simplicity, adjust as needed
-        .allow_methods(vec![
+    let (public_routes, mut route_list) = RouteTable::new(RouteAuth::None)
+        .route(Method::GET, "/", routes::root)
+        .route(
             Method::GET,
+            "/favicon.ico",
+            || async { axum::http::StatusCode::NO_CONTENT },
+        ) // avoids 401 and 404 errors for browser requests to the API, which sometimes request favicon.ico
+        .route(Method::POST, "/login", routes::auth::login)
+        .route(Method::POST, "/logout", routes::auth::logout)
+        .route(Method::GET, "/status", routes::status::detailed_health)
+        .route(Method::GET, "/status/wait", routes::status::wait_for_status)
+        .route(Method::GET, "/summary", routes::status::summary)
+        .route(Method::GET, "/config/schema", routes::config::get_config_schema)
+        .route(Method::GET, "/routes", routes::route_table::list_routes)
+        .route(Method::GET, "/openapi.json", routes::openapi::get_openapi)
+        .merge("/docs", routes::openapi::swagger_ui().into())
+        .route_with_auth(
             Method::POST,
-            Method::PUT,
-            Method::DELETE,
-            Method::OPTIONS,
-        ])
-        .allow_headers(Any);
+            "/integrations/assistant",
+            routes::integrations::assistant_fulfillment,
+            RouteAuth::AssistantToken,
+        )
+        .route_with_auth(
+            Method::POST,
+            "/hooks/dispense",
+            routes::hooks::dispense_hook,
+            RouteAuth::HookToken,
+        )
+        .route_with_auth(
+            Method::GET,
+            "/shared/{token}",
+            routes::share::shared_status,
+            RouteAuth::ShareToken,
+        )
+        .into_parts();
 
-    let public_routes = Router::new()
-        .route("/", get(routes::root))
+    let (protected_routes, protected_route_list) = RouteTable::new(RouteAuth::BearerToken)
+        .route(Method::POST, "/dispense", routes::dispense::dispense_treat)
+        .route(Method::POST, "/dispense/grams", routes::dispense::dispense_grams)
+        .route(Method::POST, "/dispense/trickle", routes::dispense::dispense_trickle)
+        .route(Method::GET, "/dispense/trickle", routes::dispense::get_trickle)
+        .route(Method::POST, "/dispense/trickle/pause", routes::dispense::pause_trickle)
+        .route(Method::POST, "/dispense/trickle/resume", routes::dispense::resume_trickle)
+        .route(Method::POST, "/cancel", routes::dispense::cancel_dispense)
+        .route(Method::GET, "/training", routes::training::get_training)
+        .route(Method::POST, "/training/start", routes::training::start_training)
+        .route(Method::POST, "/training/stop", routes::training::stop_training)
+        .route(Method::POST, "/tare", routes::sensors::tare_weight_sensor)
+        .route(Method::POST, "/calibrate", routes::sensors::calibrate_weight_sensor)
+        .route(Method::GET, "/events", routes::events::get_events)
+        .route(Method::GET, "/events/stream", routes::events::stream_events)
+        .route(Method::GET, "/webhooks/deliveries", routes::webhooks::get_deliveries)
+        .route(Method::GET, "/stats", routes::stats::get_stats)
+        .route(Method::GET, "/history", routes::history::get_history)
+        .route(Method::POST, "/history/import", routes::history::import_history)
+        .route(Method::PATCH, "/history/{id}", routes::history::update_history_entry)
+        .route(Method::GET, "/weight/history", routes::history::get_weight_history)
+        .route(Method::GET, "/schedules/export", routes::schedules::export_schedules)
+        .route(Method::PUT, "/schedules/export", routes::schedules::import_schedules)
+        .route(Method::POST, "/share", routes::share::create_share)
+        .route(Method::GET, "/share", routes::share::list_shares)
+        .route(Method::DELETE, "/share/{id}", routes::share::revoke_share)
+        .route(Method::POST, "/config/reload", routes::config::reload_config)
+        .route(Method::POST, "/config/confirm", routes::config::confirm_config)
+        .route(Method::GET, "/admin/log-level", routes::admin::get_log_level)
+        .route(Method::PUT, "/admin/log-level", routes::admin::set_log_level)
         .route(
-            "/favicon.ico",
-            get(|| async { axum::http::StatusCode::NO_CONTENT }),
-        ) // avoids 401 and 404 errors for browser requests to the API, which sometimes request favicon.ico
-        .route("/login", post(routes::auth::login))
-        .route("/status", get(routes::status::detailed_health));
-
-    let protected_routes = Router::new()
-        .route("/dispense", post(routes::dispense::dispense_treat))
-        .route("/cancel", post(routes::dispense::cancel_dispense))
-        .route("/tare", post(routes::sensors::tare_weight_sensor))
-        .route("/calibrate", post(routes::sensors::calibrate_weight_sensor))
-        .layer(axum::middleware::from_fn(
-            middleware::auth::token_auth_middleware,
+            Method::POST,
+            "/notifications/devices",
+            routes::notifications::register_device,
+        )
+        .route(
+            Method::DELETE,
+            "/notifications/devices/{token}",
+            routes::notifications::unregister_device,
+        )
+        .route(Method::GET, "/admin/backup", routes::admin::download_backup)
+        .route(Method::POST, "/admin/restore", routes::admin::restore_backup)
+        .route(Method::GET, "/admin/diagnostics", routes::admin::download_diagnostics)
+        .route(Method::POST, "/admin/batch", routes::admin::batch)
+        .route(Method::POST, "/fan", routes::fan::set_fan_mode)
+        .route(Method::POST, "/outputs/{label}", routes::outputs::set_output)
+        .route(
+            Method::POST,
+            "/power/overcurrent/disable",
+            routes::power::disable_overcurrent_protection,
+        )
+        .route(
+            Method::POST,
+            "/power/overcurrent/enable",
+            routes::power::enable_overcurrent_protection,
+        )
+        .route(
+            Method::POST,
+            "/power/current-calibration",
+            routes::power::calibrate_current_limit,
+        )
+        .route(Method::GET, "/system/i2c-scan", routes::system::i2c_scan)
+        .route(Method::GET, "/ws/weight", routes::ws::stream_weight)
+        .route(Method::GET, "/debug/weight/raw", routes::debug::stream_raw_weight)
+        .route(Method::GET, "/debug/power/raw", routes::debug::stream_raw_power)
+        .route(
+            Method::GET,
+            "/debug/temperature/raw",
+            routes::debug::stream_raw_temperature,
+        )
+        .into_parts();
+    let protected_routes = protected_routes.layer(axum::middleware::from_fn(
+        middleware::auth::token_auth_middleware,
+    ));
+    route_list.extend(protected_route_list);
+
+    let mut merged_routes = public_routes
+        .merge(protected_routes)
+        .layer(Extension(Arc::new(route_list)));
+
+    if let Some(status_redaction_config) = status_redaction_config {
+        merged_routes = merged_routes.layer(axum::middleware::from_fn_with_state(
+            Arc::new(middleware::redaction::StatusRedaction::from_config(
+                &status_redaction_config,
+            )),
+            middleware::redaction::status_redaction_middleware,
+        ));
+    }
+
+    // inside the access log, so rate limited requests are logged
+    if let Some(rate_limit_config) = rate_limit_config {
+        merged_routes = merged_routes.layer(axum::middleware::from_fn_with_state(
+            Arc::new(middleware::rate_limit::RateLimiter::from_config(
+                &rate_limit_config,
+            )),
+            middleware::rate_limit::rate_limit_middleware,
         ));
+    }
+
+    // outside the rate limiter and the auth check, so their errors are translated too
+    merged_routes = merged_routes.layer(axum::middleware::from_fn_with_state(
+        Arc::new(message_catalogue),
+        middleware::localization::localization_middleware,
+    ));
 
-    let merged_routes = public_routes.merge(protected_routes);
+    if let Some(access_log_config) = access_log_config {
+        match middleware::access_log::AccessLog::from_config(&access_log_config) {
+            Ok(access_log) => {
+                merged_routes = merged_routes.layer(axum::middleware::from_fn_with_state(
+                    Arc::new(access_log),
+                    middleware::access_log::access_log_middleware,
+                ));
+            }
+            Err(e) => error!("Access log disabled: {}", e),
+        }
+    }
 
-    return (
+    (
         app_state.clone(),
         merged_routes.with_state(app_state).layer(cors).layer(
             TraceLayer::new_for_http()
@@ -99,12 +237,42 @@ pub fn build_app(app_config: AppConfig) -> (Arc<Mutex<ApplicationState>>, axum::
                 .on_request(log_http_request)
                 .on_response(log_http_response_code),
         ),
-    );
+    )
+}
+
+/// CORS for browser clients. Preflight requests are answered here, before routing and
+/// authentication, so they never need a token.
+fn build_cors_layer(api_config: &ApiConfig) -> CorsLayer {
+    let allow_origin = match &api_config.cors_allowed_origins {
+        Some(origins) => AllowOrigin::list(origins.iter().filter_map(|origin| {
+            origin
+                .parse::<HeaderValue>()
+                .inspect_err(|_| warn!("Ignoring invalid CORS origin '{}'", origin))
+                .ok()
+        })),
+        None => AllowOrigin::any(),
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(vec![
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers(Any)
+        .expose_headers([header::ETAG])
+        .max_age(Duration::from_secs(config::CORS_MAX_AGE_SECS))
 }
 
 /// Starts the Axum server with the provided router and configuration.
+/// With `api.tls` configured the API is served over HTTPS only.
 pub async fn start_server(app: Router, config: AppConfig) {
-    let bind_address: SocketAddr = format!("{}", config.api.listen_address).parse().unwrap();
+    let bind_address: SocketAddr = config.api.listen_address.parse().unwrap();
     let listener = tokio::net::TcpListener::bind(bind_address)
         .await
         .expect("Failed to bind to address");
@@ -116,15 +284,36 @@ pub async fn start_server(app: Router, config: AppConfig) {
         info!("Received shutdown signal, shutting down gracefully...");
     };
 
-    info!("Starting server, API listening on {}", bind_address);
+    match &config.api.tls {
+        Some(tls_config) => {
+            let server_config = utils::tls::load_server_config(tls_config)
+                .unwrap_or_else(|e| panic!("Failed to set up TLS: {}", e));
+            let listener = utils::tls::TlsListener::new(listener, server_config)
+                .expect("Failed to set up TLS listener");
 
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .with_graceful_shutdown(shutdown_handler)
-    .await
-    .expect("Failed to start server");
+            info!("Starting server, API listening on https://{}", bind_address);
+
+            // tap_io makes the client address available as ConnectInfo<SocketAddr>
+            axum::serve(
+                listener.tap_io(|_| {}),
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_handler)
+            .await
+            .expect("Failed to start server");
+        }
+        None => {
+            info!("Starting server, API listening on {}", bind_address);
+
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_handler)
+            .await
+            .expect("Failed to start server");
+        }
+    }
 }
 
 // This function is called by the TraceLayer to log the response status code
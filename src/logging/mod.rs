@@ -0,0 +1,127 @@
+pub mod recent;
+pub mod syslog;
+
+use std::sync::OnceLock;
+use tracing::{error, info};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// Handle to the active EnvFilter, used to change log directives at runtime.
+/// Only set when logging was configured through `configure_logging`.
+static LOG_FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+use crate::config::LoggingConfig;
+
+/// Configures logging to stdout only, see `configure_logging_with_config` for additional sinks.
+pub fn configure_logging() {
+    configure_logging_with_config(None);
+}
+
+/// Configures logging to stdout plus any sinks enabled in the logging section of the config.
+pub fn configure_logging_with_config(logging_config: Option<&LoggingConfig>) {
+    init_logging(logging_config, env_filter());
+}
+
+/// Like `configure_logging_with_config`, with `directives` (RUST_LOG syntax) taking the
+/// place of RUST_LOG when given, e.g. from `--log-level`. Invalid directives are returned
+/// as an error and logging is left unconfigured.
+pub fn configure_logging_with_filter(
+    logging_config: Option<&LoggingConfig>,
+    directives: Option<&str>,
+) -> Result<(), String> {
+    let env_filter = match directives {
+        Some(directives) => EnvFilter::try_new(directives)
+            .map_err(|e| format!("Invalid log filter '{}': {}", directives, e))?,
+        None => env_filter(),
+    };
+    init_logging(logging_config, env_filter);
+    Ok(())
+}
+
+/// The filter from RUST_LOG, `info` if it isn't set. An invalid RUST_LOG is reported on
+/// stderr, as logging isn't set up yet.
+fn env_filter() -> EnvFilter {
+    let Ok(directives) = std::env::var("RUST_LOG") else {
+        return EnvFilter::new("info"); // Default log level if not set
+    };
+    EnvFilter::try_new(&directives).unwrap_or_else(|e| {
+        eprintln!("Ignoring invalid RUST_LOG '{}': {}", directives, e);
+        EnvFilter::new("info")
+    })
+}
+
+fn init_logging(logging_config: Option<&LoggingConfig>, env_filter: EnvFilter) {
+    let (filter_layer, filter_handle) = reload::Layer::new(env_filter);
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_thread_ids(true)
+        .with_thread_names(false)
+        .with_writer(std::io::stdout); // log to stdout for compat with containerized environments
+
+    let mut syslog_error = None;
+    let syslog_layer = logging_config
+        .and_then(|logging| logging.syslog.as_ref())
+        .and_then(|syslog_config| match syslog::SyslogLayer::from_config(syslog_config) {
+            Ok(layer) => Some(layer),
+            Err(e) => {
+                syslog_error = Some(e);
+                None
+            }
+        });
+    let syslog_enabled = syslog_layer.is_some();
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(fmt_layer)
+        .with(recent::RecentLogsLayer)
+        .with(syslog_layer)
+        .init();
+
+    if let Some(e) = syslog_error {
+        error!("Syslog forwarding disabled: {}", e);
+    } else if syslog_enabled {
+        info!("Forwarding logs to remote syslog server");
+    }
+
+    let _ = LOG_FILTER_HANDLE.set(filter_handle);
+    info!("Logging configured with filter '{}'", get_log_filter().unwrap_or_default());
+}
+
+/// Returns the currently active filter directives, e.g. `info,treat_dispenser_api::services=debug`.
+pub fn get_log_filter() -> Option<String> {
+    LOG_FILTER_HANDLE
+        .get()
+        .and_then(|handle| handle.with_current(|filter| filter.to_string()).ok())
+}
+
+/// Replaces the active filter with the supplied directives (same syntax as RUST_LOG,
+/// per-target directives supported). Returns the filter now in effect.
+pub fn set_log_filter(directives: &str) -> Result<String, String> {
+    let handle = LOG_FILTER_HANDLE
+        .get()
+        .ok_or_else(|| "Runtime log level control is not available".to_string())?;
+
+    let new_filter = EnvFilter::try_new(directives)
+        .map_err(|e| format!("Invalid log filter '{}': {}", directives, e))?;
+
+    handle
+        .reload(new_filter)
+        .map_err(|e| format!("Failed to apply log filter: {}", e))?;
+
+    let active_filter = get_log_filter().unwrap_or_default();
+    info!("Log filter changed to '{}'", active_filter);
+    Ok(active_filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_filter_is_rejected() {
+        let result = configure_logging_with_filter(None, Some("info,treat_dispenser_api=loud"));
+        assert!(result.unwrap_err().starts_with("Invalid log filter"));
+        assert!(get_log_filter().is_none());
+    }
+}
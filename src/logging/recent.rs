@@ -0,0 +1,50 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+use super::syslog::FieldVisitor;
+use crate::config;
+
+static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Tracing layer keeping the last `RECENT_LOG_LINES` log lines in memory, so the
+/// diagnostics bundle can include them without access to journald.
+pub struct RecentLogsLayer;
+
+impl<S: Subscriber> Layer<S> for RecentLogsLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let mut line = format!(
+            "{} {:>5} {}: {}",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            metadata.level(),
+            metadata.target(),
+            visitor.message
+        );
+        for (name, value) in &visitor.fields {
+            let _ = write!(line, " {}={}", name, value);
+        }
+
+        let Ok(mut lines) = RECENT_LOGS.lock() else {
+            return;
+        };
+        if lines.len() == config::RECENT_LOG_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+/// The most recent log lines, oldest first.
+pub fn recent_logs() -> Vec<String> {
+    RECENT_LOGS
+        .lock()
+        .map(|lines| lines.iter().cloned().collect())
+        .unwrap_or_default()
+}
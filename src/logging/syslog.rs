@@ -0,0 +1,435 @@
+use axum::http::uri::Authority;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+use crate::config::{self, SyslogConfig};
+
+/// Structured data ID used for event fields, `32473` is the IANA example enterprise number.
+const STRUCTURED_DATA_ID: &str = "fields@32473";
+
+#[derive(Clone, Debug)]
+enum SyslogProtocol {
+    Udp,
+    Tcp,
+    Tls(Arc<rustls::ClientConfig>),
+}
+
+/// Tracing layer that forwards events to a remote syslog server as RFC 5424 messages.
+/// Formatting happens on the logging thread, delivery on a dedicated background thread
+/// so a slow or unreachable server never blocks the dispenser. Messages are dropped
+/// (not queued indefinitely) when the server cannot keep up.
+pub struct SyslogLayer {
+    sender: SyncSender<String>,
+    facility: u8,
+    max_level: Level,
+    hostname: String,
+    app_name: String,
+}
+
+impl SyslogLayer {
+    pub fn from_config(syslog_config: &SyslogConfig) -> Result<Self, String> {
+        let protocol = match syslog_config
+            .protocol
+            .as_deref()
+            .unwrap_or(config::SYSLOG_PROTOCOL_DEFAULT)
+        {
+            "udp" => SyslogProtocol::Udp,
+            "tcp" => SyslogProtocol::Tcp,
+            "tls" => SyslogProtocol::Tls(tls_client_config(syslog_config.ca_cert.as_deref())?),
+            other => return Err(format!("Unsupported syslog protocol '{}'", other)),
+        };
+
+        let facility = syslog_config
+            .facility
+            .unwrap_or(config::SYSLOG_FACILITY_DEFAULT);
+        if facility > 23 {
+            return Err(format!("Invalid syslog facility {}, must be 0-23", facility));
+        }
+
+        let max_level = syslog_config
+            .level
+            .as_deref()
+            .unwrap_or(config::SYSLOG_LEVEL_DEFAULT)
+            .parse::<Level>()
+            .map_err(|e| format!("Invalid syslog level: {}", e))?;
+
+        let hostname = syslog_config
+            .hostname
+            .clone()
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| "-".to_string());
+
+        let app_name = syslog_config
+            .app_name
+            .clone()
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+
+        let (sender, receiver) = sync_channel(config::SYSLOG_QUEUE_SIZE);
+        let address = syslog_config.address.clone();
+
+        std::thread::Builder::new()
+            .name("syslog-forwarder".to_string())
+            .spawn(move || run_forwarder(receiver, address, protocol))
+            .map_err(|e| format!("Failed to start syslog forwarder thread: {}", e))?;
+
+        Ok(SyslogLayer {
+            sender,
+            facility,
+            max_level,
+            hostname,
+            app_name,
+        })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SyslogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > self.max_level {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let message = format_rfc5424(
+            self.facility,
+            metadata.level(),
+            &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            &self.hostname,
+            &self.app_name,
+            metadata.target(),
+            &visitor.fields,
+            &visitor.message,
+        );
+
+        match self.sender.try_send(message) {
+            Ok(_) | Err(TrySendError::Full(_)) => {} // drop rather than block the caller
+            Err(TrySendError::Disconnected(_)) => {
+                eprintln!("syslog forwarder thread has stopped, message dropped");
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct FieldVisitor {
+    pub(super) message: String,
+    pub(super) fields: Vec<(String, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+}
+
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// Escapes a structured data parameter value as required by RFC 5424 section 6.3.3.
+fn escape_param_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Builds an RFC 5424 message: `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG`.
+/// The tracing target and all event fields are carried as structured data so the
+/// receiving side can index them.
+#[allow(clippy::too_many_arguments)]
+fn format_rfc5424(
+    facility: u8,
+    level: &Level,
+    timestamp: &str,
+    hostname: &str,
+    app_name: &str,
+    target: &str,
+    fields: &[(String, String)],
+    message: &str,
+) -> String {
+    let priority = facility as u16 * 8 + severity(level) as u16;
+
+    let mut structured_data = format!(
+        "[{} target=\"{}\"",
+        STRUCTURED_DATA_ID,
+        escape_param_value(target)
+    );
+    for (name, value) in fields {
+        let _ = write!(structured_data, " {}=\"{}\"", name, escape_param_value(value));
+    }
+    structured_data.push(']');
+
+    format!(
+        "<{}>1 {} {} {} {} - {} {}",
+        priority,
+        timestamp,
+        hostname,
+        app_name,
+        std::process::id(),
+        structured_data,
+        message
+    )
+}
+
+/// The name the TLS certificate is checked against, the host of `host:port`. IPv6
+/// addresses are written in brackets, `[::1]:6514`.
+fn tls_server_name(address: &str) -> Result<ServerName<'static>, String> {
+    let authority: Authority = address
+        .parse()
+        .map_err(|e| format!("Invalid syslog server address '{}': {}", address, e))?;
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    ServerName::try_from(host.to_string()).map_err(|e| format!("Invalid syslog server name: {}", e))
+}
+
+/// Trusts the bundled Mozilla root certificates, plus the certificates in the PEM file
+/// `ca_cert`, e.g. of a private CA that signed the syslog server's certificate.
+fn tls_client_config(ca_cert: Option<&str>) -> Result<Arc<rustls::ClientConfig>, String> {
+    let mut root_store =
+        rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(ca_cert) = ca_cert {
+        let certs = CertificateDer::pem_file_iter(ca_cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to read syslog CA certificate {}: {}", ca_cert, e))?;
+        let (added, _) = root_store.add_parsable_certificates(certs);
+        if added == 0 {
+            return Err(format!("No CA certificate found in {}", ca_cert));
+        }
+    }
+    let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| e.to_string())?
+    .with_root_certificates(root_store)
+    .with_no_client_auth();
+    Ok(Arc::new(tls_config))
+}
+
+enum SyslogConnection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+impl SyslogConnection {
+    fn connect(address: &str, protocol: &SyslogProtocol) -> Result<Self, String> {
+        match protocol {
+            SyslogProtocol::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+                socket.connect(address).map_err(|e| e.to_string())?;
+                Ok(SyslogConnection::Udp(socket))
+            }
+            SyslogProtocol::Tcp => {
+                let stream = TcpStream::connect(address).map_err(|e| e.to_string())?;
+                Ok(SyslogConnection::Tcp(stream))
+            }
+            SyslogProtocol::Tls(tls_config) => {
+                let server_name = tls_server_name(address)?;
+                let connection = rustls::ClientConnection::new(tls_config.clone(), server_name)
+                    .map_err(|e| e.to_string())?;
+                let stream = TcpStream::connect(address).map_err(|e| e.to_string())?;
+                Ok(SyslogConnection::Tls(Box::new(rustls::StreamOwned::new(
+                    connection, stream,
+                ))))
+            }
+        }
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), String> {
+        match self {
+            SyslogConnection::Udp(socket) => socket
+                .send(message.as_bytes())
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            // stream transports use octet-counting framing (RFC 6587 / RFC 5425)
+            SyslogConnection::Tcp(stream) => {
+                write!(stream, "{} {}", message.len(), message).map_err(|e| e.to_string())
+            }
+            SyslogConnection::Tls(stream) => {
+                write!(stream, "{} {}", message.len(), message)
+                    .and_then(|_| stream.flush())
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Delivers queued messages, reconnecting with a capped backoff when the server goes away.
+/// Errors are written to stderr because logging them through tracing would loop back here.
+fn run_forwarder(receiver: Receiver<String>, address: String, protocol: SyslogProtocol) {
+    let mut connection: Option<SyslogConnection> = None;
+    let mut backoff = Duration::from_secs(1);
+
+    while let Ok(message) = receiver.recv() {
+        if connection.is_none() {
+            match SyslogConnection::connect(&address, &protocol) {
+                Ok(conn) => {
+                    connection = Some(conn);
+                    backoff = Duration::from_secs(1);
+                }
+                Err(e) => {
+                    eprintln!("Failed to connect to syslog server {}: {}", address, e);
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(Duration::from_secs(60));
+                    continue;
+                }
+            }
+        }
+
+        if let Some(conn) = connection.as_mut()
+            && let Err(e) = conn.send(&message)
+        {
+            eprintln!("Failed to forward log message to syslog server: {}", e);
+            connection = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_rfc5424() {
+        let message = format_rfc5424(
+            16,
+            &Level::WARN,
+            "2025-01-01T12:00:00.000Z",
+            "kitchen-pi",
+            "treat-dispenser-api",
+            "treat_dispenser_api::services::power_monitor",
+            &[("current".to_string(), "0.9 \"high\"".to_string())],
+            "High average current detected",
+        );
+
+        let expected_prefix = "<132>1 2025-01-01T12:00:00.000Z kitchen-pi treat-dispenser-api ";
+        assert!(message.starts_with(expected_prefix), "{}", message);
+        assert!(message.ends_with(
+            " - [fields@32473 target=\"treat_dispenser_api::services::power_monitor\" current=\"0.9 \\\"high\\\"\"] High average current detected"
+        ), "{}", message);
+    }
+
+    #[test]
+    fn test_severity_mapping() {
+        assert_eq!(severity(&Level::ERROR), 3);
+        assert_eq!(severity(&Level::INFO), 6);
+        assert_eq!(severity(&Level::TRACE), 7);
+    }
+
+    #[test]
+    fn test_tls_server_name() {
+        let dns = ServerName::try_from("logs.example.com").unwrap();
+        assert_eq!(tls_server_name("logs.example.com:6514").unwrap(), dns);
+        assert_eq!(tls_server_name("logs.example.com").unwrap(), dns);
+
+        let ipv4 =
+            ServerName::IpAddress("192.168.1.20".parse::<std::net::IpAddr>().unwrap().into());
+        assert_eq!(tls_server_name("192.168.1.20:6514").unwrap(), ipv4);
+
+        let ipv6 = ServerName::IpAddress("::1".parse::<std::net::IpAddr>().unwrap().into());
+        assert_eq!(tls_server_name("[::1]:514").unwrap(), ipv6);
+        assert_eq!(tls_server_name("[::1]").unwrap(), ipv6);
+
+        assert!(tls_server_name("logs example:6514").is_err());
+    }
+
+    #[test]
+    fn test_tls_ca_cert() {
+        let error = tls_client_config(Some("/nonexistent/ca.pem")).unwrap_err();
+        assert!(error.contains("/nonexistent/ca.pem"), "{}", error);
+        let error =
+            tls_client_config(Some("tests/fixtures/tls/syslog_server_key.pem")).unwrap_err();
+        assert!(error.starts_with("No CA certificate found"), "{}", error);
+
+        // a server with a certificate of a private CA, reading one message per connection
+        let cert_chain = CertificateDer::pem_file_iter("tests/fixtures/tls/syslog_server_cert.pem")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = rustls::pki_types::PrivateKeyDer::from_pem_file(
+            "tests/fixtures/tls/syslog_server_key.pem",
+        )
+        .unwrap();
+        let server_config = Arc::new(
+            rustls::ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)
+            .unwrap(),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            for stream in listener.incoming().take(2) {
+                let stream = stream.unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                let connection = rustls::ServerConnection::new(server_config.clone()).unwrap();
+                let mut tls = rustls::StreamOwned::new(connection, stream);
+                let mut message = [0u8; 7];
+                let message = std::io::Read::read_exact(&mut tls, &mut message)
+                    .map(|_| String::from_utf8_lossy(&message).to_string());
+                received.push(message);
+            }
+            received
+        });
+
+        // only the bundled roots, the certificate isn't trusted
+        let protocol = SyslogProtocol::Tls(tls_client_config(None).unwrap());
+        let mut untrusted = SyslogConnection::connect(&address, &protocol).unwrap();
+        assert!(untrusted.send("untrusted").is_err());
+
+        let ca_cert = Some("tests/fixtures/tls/syslog_ca.pem");
+        let protocol = SyslogProtocol::Tls(tls_client_config(ca_cert).unwrap());
+        let mut trusted = SyslogConnection::connect(&address, &protocol).unwrap();
+        trusted.send("hello").unwrap();
+
+        // kept open until the server has read the message
+        let received = server.join().unwrap();
+        assert!(received[0].is_err());
+        assert_eq!(received[1].as_deref().unwrap(), "5 hello");
+    }
+}
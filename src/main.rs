@@ -1,18 +1,120 @@
-use treat_dispenser_api::config::load_app_config;
+use clap::Parser;
+use treat_dispenser_api::cli::{CliArgs, Command};
+use treat_dispenser_api::config::{self, AppConfig, app_config_schema, load_app_config};
+use treat_dispenser_api::utils::filesystem;
 use treat_dispenser_api::{
-    build_app, configure_logging, services::power_monitor, services::weight_monitor, start_server,
+    DispenserBuilder, configure_logging_with_filter, services::auth, services::history_import,
+    services::migrations, services::schedule_export, services::scheduler,
 };
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
 
-    configure_logging();
+    let cli_args = CliArgs::parse();
+    let command = cli_args.command();
+    if let Some(path) = cli_args.config {
+        filesystem::set_config_path(path);
+    }
+    if let Some(listen_address) = cli_args.listen {
+        config::override_listen_address(listen_address);
+    }
 
-    let config = load_app_config();
-    let (app_state, router) = build_app(config.clone());
+    if command != Command::Serve {
+        run_command(command).await;
+        return;
+    }
 
-    power_monitor::start_power_monitoring_thread(&app_state).await;
-    weight_monitor::start_weight_monitoring_thread(&app_state).await;
-    start_server(router, config).await;
+    let config = load_app_config_or_exit();
+    if let Err(e) =
+        configure_logging_with_filter(config.logging.as_ref(), cli_args.log_level.as_deref())
+    {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    DispenserBuilder::new(config).build().await.serve().await;
+}
+
+/// Runs one of the commands that exit when done instead of serving the API.
+async fn run_command(command: Command) {
+    match command {
+        Command::Serve => {}
+
+        // load and validate the config file like at startup, nothing is started
+        Command::CheckConfig => {
+            load_app_config_or_exit();
+            println!("{} is valid", filesystem::get_config_path());
+        }
+
+        // print the config schema, used to validate config files before deploying them
+        Command::ConfigSchema => println!(
+            "{}",
+            serde_json::to_string_pretty(&app_config_schema()).unwrap_or_default()
+        ),
+
+        // hash a password read from stdin for api.admin_password
+        Command::HashPassword => {
+            let mut password = String::new();
+            if let Err(e) = std::io::stdin().read_line(&mut password) {
+                eprintln!("Failed to read password: {}", e);
+                std::process::exit(1);
+            }
+            match auth::hash_password(password.trim_end_matches(['\r', '\n'])) {
+                Ok(hash) => println!("{}", hash),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        // import feeding history exported from another feeder into the data directory
+        Command::ImportHistory { path, day_first } => {
+            for failure in migrations::run_migrations() {
+                eprintln!("{}", failure);
+            }
+            let result = match std::fs::read_to_string(&path) {
+                Ok(csv) => history_import::import(csv, day_first)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(format!("Failed to read {}: {}", path, e)),
+            };
+            match result {
+                Ok(summary) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&summary).unwrap_or_default()
+                ),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        // play the schedules of the next days against the dispense limits, fails on conflicts
+        Command::CheckSchedules => {
+            let mut app_config = load_app_config_or_exit();
+            schedule_export::apply_saved_schedules(&mut app_config);
+            let report = scheduler::dry_run(
+                &app_config,
+                chrono::Local::now(),
+                config::SCHEDULE_DRY_RUN_DAYS,
+            );
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            );
+            if !report.conflicts.is_empty() || !report.ignored.is_empty() {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Prints every problem of the config file and exits, logging isn't set up yet.
+fn load_app_config_or_exit() -> AppConfig {
+    load_app_config().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
 }
@@ -0,0 +1,131 @@
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{error, info};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+use crate::config::{self, AccessLogConfig};
+use crate::middleware::auth::AuthenticatedUser;
+
+/// Writes one JSON line per handled request to a rotating file. This is deliberately
+/// separate from tracing so the access history survives log level changes and journald
+/// retention limits. The lines are written to the file on a background thread, so a slow
+/// SD card doesn't hold up the request handlers.
+pub struct AccessLog {
+    writer: NonBlocking,
+    /// Flushes the lines not written yet when the access log is dropped
+    _guard: WorkerGuard,
+}
+
+#[derive(Serialize)]
+struct AccessLogEntry<'a> {
+    time: String,
+    client_ip: String,
+    user: &'a str,
+    method: &'a str,
+    route: &'a str,
+    status: u16,
+    latency_ms: u128,
+}
+
+impl AccessLog {
+    pub fn from_config(access_log_config: &AccessLogConfig) -> Result<Self, String> {
+        let rotation = match access_log_config
+            .rotation
+            .as_deref()
+            .unwrap_or(config::ACCESS_LOG_ROTATION_DEFAULT)
+        {
+            "minutely" => Rotation::MINUTELY,
+            "hourly" => Rotation::HOURLY,
+            "daily" => Rotation::DAILY,
+            "never" => Rotation::NEVER,
+            other => return Err(format!("Unsupported access log rotation '{}'", other)),
+        };
+
+        let file_prefix = access_log_config
+            .file_prefix
+            .clone()
+            .unwrap_or_else(|| config::ACCESS_LOG_FILE_PREFIX_DEFAULT.to_string());
+
+        let appender = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(file_prefix)
+            .max_log_files(
+                access_log_config
+                    .max_files
+                    .unwrap_or(config::ACCESS_LOG_MAX_FILES_DEFAULT),
+            )
+            .build(&access_log_config.directory)
+            .map_err(|e| format!("Failed to create access log appender: {}", e))?;
+
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        info!("Access log enabled, writing to {}", access_log_config.directory);
+        Ok(AccessLog {
+            writer,
+            _guard: guard,
+        })
+    }
+
+    fn write_entry(&self, entry: &AccessLogEntry) {
+        let mut line = match serde_json::to_vec(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize access log entry: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // one write per line, so lines of concurrent requests don't interleave
+        if let Err(e) = self.writer.clone().write_all(&line) {
+            error!("Failed to queue access log entry: {}", e);
+        }
+    }
+}
+
+/// Records time, client IP, authenticated user, route, status and latency of every request.
+pub async fn access_log_middleware(
+    State(access_log): State<Arc<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let time = chrono::Local::now().to_rfc3339();
+    let method = request.method().to_string();
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let response = next.run(request).await;
+
+    let user = response
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .map(|AuthenticatedUser(user)| user.as_str())
+        .unwrap_or("-");
+
+    access_log.write_entry(&AccessLogEntry {
+        time,
+        client_ip,
+        user,
+        method: &method,
+        route: &route,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_millis(),
+    });
+
+    response
+}
@@ -3,21 +3,41 @@ use axum::{extract::Request, http, middleware::Next, response::Response};
 use jsonwebtoken::{DecodingKey, Validation, decode};
 use tracing::{debug, warn};
 
-use crate::services::auth::Claims;
+use crate::services::auth::{CSRF_HEADER, Claims, SESSION_COOKIE};
 
-pub async fn token_auth_middleware(request: Request, next: Next) -> Result<Response, ApiError> {
+/// Name of the user a request was authenticated as, taken from the JWT `sub` claim.
+/// Inserted into both the request and response extensions so handlers and outer
+/// layers (e.g. the access log) can attribute a request to a user.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser(pub String);
+
+pub async fn token_auth_middleware(mut request: Request, next: Next) -> Result<Response, ApiError> {
+    // OPTIONS never reaches a handler, let the router answer with the allowed methods
+    if request.method() == http::Method::OPTIONS {
+        return Ok(next.run(request).await);
+    }
+
+    let user = authenticate(&request)?;
+    request.extensions_mut().insert(user.clone());
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(user);
+    Ok(response)
+}
+
+/// Checks the bearer token or session cookie of a request, like the middleware of the
+/// protected routes does.
+pub fn authenticate(request: &Request) -> Result<AuthenticatedUser, ApiError> {
     // Extract token from Authorization header
     let auth_header: Option<String> = request
         .headers()
         .get(http::header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
-        .and_then(|auth_value| {
-            if auth_value.starts_with("Bearer ") {
-                Some(auth_value[7..].to_string())
-            } else {
-                None
-            }
-        });
+        .and_then(|auth_value| auth_value.strip_prefix("Bearer ").map(|t| t.to_string()));
+    // browsers of cookie logins send the session cookie instead
+    let session_cookie = match auth_header {
+        Some(_) => None,
+        None => cookie_value(request.headers(), SESSION_COOKIE),
+    };
 
     let jwt_secret_result = std::env::var("DISPENSER_JWT_SECRET");
 
@@ -33,14 +53,19 @@ pub async fn token_auth_middleware(request: Request, next: Next) -> Result<Respo
         }
     };
 
-    if let Some(token) = auth_header {
+    if let Some(token) = auth_header.as_ref().or(session_cookie.as_ref()) {
         // Validate token
         match decode::<Claims>(
-            &token,
+            token,
             &DecodingKey::from_secret(jwt_secret.as_ref()),
             &Validation::default(),
         ) {
-            Ok(_) => Ok(next.run(request).await),
+            Ok(token_data) => {
+                if session_cookie.is_some() {
+                    check_csrf(request, token_data.claims.csrf.as_deref())?;
+                }
+                Ok(AuthenticatedUser(token_data.claims.sub))
+            }
             Err(_) => Err(ApiError::Unauthorized),
         }
     } else {
@@ -48,3 +73,61 @@ pub async fn token_auth_middleware(request: Request, next: Next) -> Result<Respo
         Err(ApiError::Unauthorized)
     }
 }
+
+/// Browsers attach cookies on their own, so a request authenticated by the session cookie
+/// must prove it comes from a page that could read the CSRF cookie, unless it only reads.
+/// Tokens from a regular login carry no CSRF token and are never accepted as a cookie.
+fn check_csrf(request: &Request, expected: Option<&str>) -> Result<(), ApiError> {
+    let Some(expected) = expected else {
+        warn!("Session cookie holds a token that wasn't issued by a cookie login");
+        return Err(ApiError::Unauthorized);
+    };
+    if matches!(*request.method(), http::Method::GET | http::Method::HEAD) {
+        return Ok(());
+    }
+    let sent = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|h| h.to_str().ok());
+    if sent == Some(expected) {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(format!(
+            "Missing or invalid {} header",
+            CSRF_HEADER
+        )))
+    }
+}
+
+/// Value of the cookie `name` in the request's `Cookie` headers.
+pub(crate) fn cookie_value(headers: &http::HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(http::header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_value() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::COOKIE,
+            "theme=dark; dispenser_session=abc.def.ghi; dispenser_csrf=123"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            cookie_value(&headers, SESSION_COOKIE).as_deref(),
+            Some("abc.def.ghi")
+        );
+        assert_eq!(cookie_value(&headers, "missing"), None);
+    }
+}
@@ -0,0 +1,115 @@
+use axum::body::{Body, to_bytes};
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::error::ErrorCode;
+use crate::services::localization::MessageCatalogue;
+
+/// Largest response body that is translated, the event log is well below it.
+const BODY_LIMIT_BYTES: usize = 1024 * 1024;
+
+/// Translates error responses, and adds the event texts to `GET /events`, into the language
+/// asked for with `Accept-Language`. Responses in English, or to requests that ask for a
+/// language the catalogue doesn't have, pass through unchanged.
+pub async fn localization_middleware(
+    State(catalogue): State<Arc<MessageCatalogue>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let accept_language = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    let Some(language) = catalogue
+        .select_language(accept_language)
+        .map(str::to_string)
+    else {
+        return next.run(request).await;
+    };
+    let events_route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|route| route.as_str() == "/events");
+
+    let response = next.run(request).await;
+    if let Some(error_code) = response.extensions().get::<ErrorCode>().cloned() {
+        let Some(title) = catalogue.text(&language, error_code.code) else {
+            return response;
+        };
+        let validation = response.status() == StatusCode::UNPROCESSABLE_ENTITY;
+        translate_body(response, &language, |body| {
+            translate_error(body, title, &error_code, validation)
+        })
+        .await
+    } else if events_route && response.status() == StatusCode::OK {
+        translate_body(response, &language, |body| {
+            add_event_texts(body, &catalogue, &language)
+        })
+        .await
+    } else {
+        response
+    }
+}
+
+/// Replaces the body with its translation. A body that can't be translated is kept.
+async fn translate_body(
+    response: Response,
+    language: &str,
+    translate: impl FnOnce(&[u8]) -> Option<Vec<u8>>,
+) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, BODY_LIMIT_BYTES).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to read the response to translate: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Some(translated) = translate(&body) else {
+        return Response::from_parts(parts, Body::from(body));
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    if let Ok(language) = HeaderValue::from_str(language) {
+        parts.headers.insert(header::CONTENT_LANGUAGE, language);
+    }
+    Response::from_parts(parts, Body::from(translated))
+}
+
+/// The title of the error in the language, followed by the details in English. For a
+/// validation error only the `error` field is translated.
+fn translate_error(
+    body: &[u8],
+    title: &str,
+    error_code: &ErrorCode,
+    validation: bool,
+) -> Option<Vec<u8>> {
+    if validation {
+        let mut body: Value = serde_json::from_slice(body).ok()?;
+        body["error"] = Value::String(title.to_string());
+        return serde_json::to_vec(&body).ok();
+    }
+    let text = match &error_code.detail {
+        Some(detail) => format!("{}: {}", title, detail),
+        None => title.to_string(),
+    };
+    Some(text.into_bytes())
+}
+
+/// Adds the text of each event's kind as `text`, the English `message` is kept.
+fn add_event_texts(body: &[u8], catalogue: &MessageCatalogue, language: &str) -> Option<Vec<u8>> {
+    let mut events: Vec<Value> = serde_json::from_slice(body).ok()?;
+    for event in &mut events {
+        let text = event["kind"]
+            .as_str()
+            .and_then(|kind| catalogue.text(language, kind));
+        if let Some(text) = text {
+            event["text"] = Value::String(text.to_string());
+        }
+    }
+    serde_json::to_vec(&events).ok()
+}
@@ -1 +1,5 @@
+pub mod access_log;
 pub mod auth;
+pub mod localization;
+pub mod rate_limit;
+pub mod redaction;
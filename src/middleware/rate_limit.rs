@@ -0,0 +1,231 @@
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::{HeaderValue, Method, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::{self, RateLimitConfig, RateLimitRule};
+use crate::error::ApiError;
+
+/// Clients tracked before buckets that have refilled are dropped.
+const TRACKED_CLIENTS_MAX: usize = 1024;
+
+/// Routes sharing the `dispense` limit.
+const DISPENSE_ROUTES: [&str; 3] = ["/dispense", "/dispense/grams", "/dispense/trickle"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Limit {
+    Login,
+    Dispense,
+}
+
+/// Token bucket of one client and limit. Starts full with `requests` tokens and refills
+/// evenly over `per_seconds`.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(rule: &RateLimitRule, now: Instant) -> Self {
+        Bucket {
+            tokens: capacity(rule),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rule: &RateLimitRule, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_rate(rule)).min(capacity(rule));
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long until the next one.
+    fn take(&mut self, rule: &RateLimitRule, now: Instant) -> Result<(), Duration> {
+        self.refill(rule, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / refill_rate(rule),
+        ))
+    }
+
+    fn is_full(&self, rule: &RateLimitRule, now: Instant) -> bool {
+        let mut bucket = *self;
+        bucket.refill(rule, now);
+        bucket.tokens >= capacity(rule)
+    }
+}
+
+fn capacity(rule: &RateLimitRule) -> f64 {
+    rule.requests.max(1) as f64
+}
+
+/// Tokens per second.
+fn refill_rate(rule: &RateLimitRule) -> f64 {
+    capacity(rule) / rule.per_seconds.max(1) as f64
+}
+
+/// Per client IP limits of the login and dispense endpoints. Clients behind a reverse proxy
+/// share the proxy's IP.
+pub struct RateLimiter {
+    login: RateLimitRule,
+    dispense: RateLimitRule,
+    buckets: Mutex<HashMap<(IpAddr, Limit), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn from_config(rate_limit_config: &RateLimitConfig) -> Self {
+        let login = rate_limit_config.login.unwrap_or(RateLimitRule {
+            requests: config::RATE_LIMIT_LOGIN_REQUESTS_DEFAULT,
+            per_seconds: config::RATE_LIMIT_PER_SECONDS_DEFAULT,
+        });
+        let dispense = rate_limit_config.dispense.unwrap_or(RateLimitRule {
+            requests: config::RATE_LIMIT_DISPENSE_REQUESTS_DEFAULT,
+            per_seconds: config::RATE_LIMIT_PER_SECONDS_DEFAULT,
+        });
+        info!(
+            "Rate limiting enabled, login {} per {}s, dispense {} per {}s",
+            login.requests, login.per_seconds, dispense.requests, dispense.per_seconds
+        );
+        RateLimiter {
+            login,
+            dispense,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn rule(&self, limit: Limit) -> &RateLimitRule {
+        match limit {
+            Limit::Login => &self.login,
+            Limit::Dispense => &self.dispense,
+        }
+    }
+
+    fn check(&self, ip: IpAddr, limit: Limit, now: Instant) -> Result<(), Duration> {
+        let rule = self.rule(limit);
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if buckets.len() >= TRACKED_CLIENTS_MAX {
+            buckets.retain(|(_, limit), bucket| !bucket.is_full(self.rule(*limit), now));
+        }
+        buckets
+            .entry((ip, limit))
+            .or_insert_with(|| Bucket::full(rule, now))
+            .take(rule, now)
+    }
+}
+
+fn limit_for(method: &Method, route: &str) -> Option<Limit> {
+    if method != Method::POST {
+        return None;
+    }
+    if route == "/login" {
+        Some(Limit::Login)
+    } else if DISPENSE_ROUTES.contains(&route) {
+        Some(Limit::Dispense)
+    } else {
+        None
+    }
+}
+
+/// Answers `429` with a `Retry-After` header once a client has used up its requests to
+/// `/login` or the dispense endpoints. Runs before authentication, so failed logins and
+/// unauthenticated dispense requests count too.
+pub async fn rate_limit_middleware(
+    State(rate_limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limit = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|route| limit_for(request.method(), route.as_str()));
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let (Some(limit), Some(client_ip)) = (limit, client_ip) else {
+        return next.run(request).await;
+    };
+
+    match rate_limiter.check(client_ip, limit, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            warn!(
+                "Rate limited {} on {}, retry in {}s",
+                client_ip,
+                request.uri().path(),
+                retry_after_secs
+            );
+            let mut response =
+                ApiError::RateLimited(format!("retry in {} seconds", retry_after_secs))
+                    .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let rule = RateLimitRule {
+            requests: 3,
+            per_seconds: 30,
+        };
+        let start = Instant::now();
+        let mut bucket = Bucket::full(&rule, start);
+        for _ in 0..3 {
+            assert!(bucket.take(&rule, start).is_ok());
+        }
+        // a token comes back every 10 seconds
+        assert_eq!(bucket.take(&rule, start), Err(Duration::from_secs(10)));
+        let later = start + Duration::from_secs(15);
+        assert!(bucket.take(&rule, later).is_ok());
+        assert_eq!(bucket.take(&rule, later), Err(Duration::from_secs(5)));
+        assert!(bucket.is_full(&rule, later + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_limits_per_client_and_route() {
+        let rate_limiter = RateLimiter::from_config(&RateLimitConfig {
+            login: Some(RateLimitRule {
+                requests: 1,
+                per_seconds: 60,
+            }),
+            dispense: None,
+        });
+        let now = Instant::now();
+        let client: IpAddr = "192.168.1.20".parse().unwrap();
+        let other_client: IpAddr = "192.168.1.21".parse().unwrap();
+
+        assert!(rate_limiter.check(client, Limit::Login, now).is_ok());
+        assert!(rate_limiter.check(client, Limit::Login, now).is_err());
+        assert!(rate_limiter.check(other_client, Limit::Login, now).is_ok());
+        assert!(rate_limiter.check(client, Limit::Dispense, now).is_ok());
+
+        assert_eq!(limit_for(&Method::POST, "/login"), Some(Limit::Login));
+        assert_eq!(
+            limit_for(&Method::POST, "/dispense/grams"),
+            Some(Limit::Dispense)
+        );
+        assert_eq!(limit_for(&Method::GET, "/dispense/trickle"), None);
+        assert_eq!(limit_for(&Method::POST, "/cancel"), None);
+    }
+}
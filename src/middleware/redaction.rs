@@ -0,0 +1,152 @@
+use axum::body::{Body, to_bytes};
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::config::StatusRedactionConfig;
+use crate::middleware::auth::{self, cookie_value};
+use crate::services::auth::SESSION_COOKIE;
+
+/// Public routes whose responses are redacted. The event and weight streams need the admin
+/// token anyway, and status links only show a fixed set of fields without errors, motor or
+/// power details.
+const REDACTED_ROUTES: [&str; 3] = ["/status", "/status/wait", "/summary"];
+
+/// Largest response body that is redacted, `/status` is a few KB.
+const BODY_LIMIT_BYTES: usize = 1024 * 1024;
+
+/// Fields left out for each group name of `status_redaction.fields`.
+const FIELD_GROUPS: [(&str, &[&str]); 3] = [
+    (
+        "errors",
+        &[
+            "last_error_msg",
+            "last_error_time",
+            "init_errors",
+            "quarantined_files",
+            "stale_channels",
+            "hardware",
+        ],
+    ),
+    (
+        "motor",
+        &["motor", "motor_operational", "overcurrent_protection"],
+    ),
+    (
+        "power",
+        &[
+            "motor_power_sensor",
+            "motor_voltage_volts",
+            "motor_current_amps",
+            "motor_power_watts",
+        ],
+    ),
+];
+
+/// The status fields hidden from requests without an admin token.
+pub struct StatusRedaction {
+    fields: BTreeSet<String>,
+}
+
+impl StatusRedaction {
+    pub fn from_config(redaction_config: &StatusRedactionConfig) -> Self {
+        let fields: BTreeSet<String> = redaction_config
+            .fields
+            .iter()
+            .flat_map(
+                |name| match FIELD_GROUPS.iter().find(|(group, _)| group == name) {
+                    Some((_, fields)) => fields.iter().map(|field| field.to_string()).collect(),
+                    None => vec![name.clone()],
+                },
+            )
+            .collect();
+        info!(
+            "Status fields redacted without an admin token: {}",
+            fields.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+        StatusRedaction { fields }
+    }
+
+    fn redact(&self, body: &[u8]) -> Result<Vec<u8>, serde_json::Error> {
+        let mut status: serde_json::Value = serde_json::from_slice(body)?;
+        if let Some(status) = status.as_object_mut() {
+            status.retain(|field, _| !self.fields.contains(field));
+        }
+        serde_json::to_vec(&status)
+    }
+}
+
+/// True if the request carries a valid bearer token or session cookie. Requests without
+/// one are checked without logging, most `/status` callers never send one.
+fn is_admin(request: &Request) -> bool {
+    let has_credentials = request.headers().contains_key(header::AUTHORIZATION)
+        || cookie_value(request.headers(), SESSION_COOKIE).is_some();
+    has_credentials && auth::authenticate(request).is_ok()
+}
+
+/// Shapes `/status`, `/status/wait` and `/summary` responses for requests that aren't
+/// authenticated as the admin, leaving out the configured fields. Other routes pass
+/// through unchanged.
+pub async fn status_redaction_middleware(
+    State(redaction): State<Arc<StatusRedaction>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let redacted_route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|route| REDACTED_ROUTES.contains(&route.as_str()));
+    if !redacted_route || is_admin(&request) {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let redacted = match to_bytes(body, BODY_LIMIT_BYTES).await {
+        Ok(body) => redaction.redact(&body).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    match redacted {
+        Ok(body) => Response::from_parts(parts, Body::from(body)),
+        Err(e) => {
+            // never fall back to the unredacted body
+            error!("Failed to redact the status response: {}", e);
+            parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+            Response::from_parts(parts, Body::empty())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_groups_and_fields() {
+        let redaction = StatusRedaction::from_config(&StatusRedactionConfig {
+            fields: vec!["power".to_string(), "last_backup".to_string()],
+        });
+        let status = serde_json::json!({
+            "dispenser_status": "Operational",
+            "motor": "StepperMock",
+            "motor_current_amps": 0.4,
+            "motor_power_watts": 2.1,
+            "last_backup": null
+        });
+        let redacted = redaction
+            .redact(&serde_json::to_vec(&status).unwrap())
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&redacted).unwrap(),
+            serde_json::json!({ "dispenser_status": "Operational", "motor": "StepperMock" })
+        );
+    }
+}
@@ -0,0 +1,529 @@
+//! Checks a motor driver against the contract of `StepperMotor` and `AsyncStepperMotor`
+//! without hardware, so new drivers can show they behave like the existing ones:
+//!
+//! - `get_name` isn't empty and `as_any` returns the driver itself.
+//! - Every supported step mode has steps per rotation, and finer modes don't have fewer.
+//! - Runs return the number of steps taken, `degrees / 360` of a rotation rounded down.
+//!   Zero or negative degrees take no steps.
+//! - Unsupported step modes fail instead of running.
+//! - Async runs stop with an error soon after their token is cancelled, also when it was
+//!   cancelled before the run started. The error isn't a stall, which would be reported
+//!   as a jam.
+//!
+//! Drivers that drive GPIO pins directly can only be checked on the Raspberry Pi.
+//!
+//! ```no_run
+//! # async fn check() {
+//! use treat_dispenser_api::motor::StepMode;
+//! use treat_dispenser_api::motor::conformance::MotorConformance;
+//! use treat_dispenser_api::motor::stepper_mock::StepperMock;
+//!
+//! MotorConformance::new()
+//!     .supported_step_modes(&[StepMode::Full, StepMode::Half])
+//!     .assert_conforms(StepperMock::new())
+//!     .await;
+//! # }
+//! ```
+
+use crate::application_state::{AppStateMutex, ApplicationState};
+use crate::config::AppConfigBuilder;
+use crate::motor::{AsyncStepperMotor, Direction, STALL_ERROR_PREFIX, StepMode};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+const ALL_STEP_MODES: [StepMode; 5] = [
+    StepMode::Full,
+    StepMode::Half,
+    StepMode::Quarter,
+    StepMode::Eighth,
+    StepMode::Sixteenth,
+];
+/// Degrees of the runs that check the step counts, a quarter turn keeps slow drivers quick.
+const CHECK_RUN_DEGREES: f32 = 90.0;
+/// Degrees of the run that is cancelled, long enough to still be running when cancelled.
+const CANCEL_RUN_DEGREES: f32 = 3600.0;
+/// Time the cancelled run is given to start before its token is cancelled.
+const CANCEL_AFTER: Duration = Duration::from_millis(100);
+
+/// A way the driver breaks the contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceFailure {
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.check, self.message)
+    }
+}
+
+/// Runs the conformance checks, see the module docs for the contract.
+pub struct MotorConformance {
+    supported_step_modes: Vec<StepMode>,
+    run_timeout: Duration,
+    cancel_timeout: Duration,
+}
+
+impl MotorConformance {
+    /// Checks full steps only, with 30 s per run and 1 s to stop after a cancel.
+    pub fn new() -> Self {
+        MotorConformance {
+            supported_step_modes: vec![StepMode::Full],
+            run_timeout: Duration::from_secs(30),
+            cancel_timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Step modes the driver runs in, the others must fail.
+    pub fn supported_step_modes(mut self, step_modes: &[StepMode]) -> Self {
+        self.supported_step_modes = step_modes.to_vec();
+        self
+    }
+
+    /// Longest a run of a quarter turn may take.
+    pub fn run_timeout(mut self, run_timeout: Duration) -> Self {
+        self.run_timeout = run_timeout;
+        self
+    }
+
+    /// Longest a run may take to stop after its token was cancelled.
+    pub fn cancel_timeout(mut self, cancel_timeout: Duration) -> Self {
+        self.cancel_timeout = cancel_timeout;
+        self
+    }
+
+    /// Runs every check and returns the failures, empty if the driver conforms. The runs
+    /// are sequential, like the dispenser's.
+    pub async fn check<M: AsyncStepperMotor + 'static>(&self, motor: M) -> Vec<ConformanceFailure> {
+        let motor = Arc::new(motor);
+        let app_state: AppStateMutex = Arc::new(Mutex::new(ApplicationState::new(
+            AppConfigBuilder::new("admin", "conformance").build(),
+        )));
+        let mut failures = Vec::new();
+        let mut fail = |check: &'static str, message: String| {
+            failures.push(ConformanceFailure { check, message })
+        };
+
+        if motor.get_name().trim().is_empty() {
+            fail("identity", "get_name is empty".to_string());
+        }
+        if !motor.as_any().is::<M>() {
+            fail("identity", "as_any doesn't return the driver".to_string());
+        }
+
+        let mut previous: Option<(StepMode, u32)> = None;
+        for step_mode in ALL_STEP_MODES {
+            if !self.supported_step_modes.contains(&step_mode) {
+                continue;
+            }
+            let steps_per_rotation = motor.get_step_count_for_full_rotation(&step_mode);
+            if steps_per_rotation == 0 {
+                fail(
+                    "steps_per_rotation",
+                    format!("{} step mode has no steps per rotation", step_mode),
+                );
+            }
+            if let Some((previous_mode, previous_steps)) = previous
+                && steps_per_rotation < previous_steps
+            {
+                fail(
+                    "steps_per_rotation",
+                    format!(
+                        "{} step mode has fewer steps per rotation ({}) than {} ({})",
+                        step_mode, steps_per_rotation, previous_mode, previous_steps
+                    ),
+                );
+            }
+            previous = Some((step_mode, steps_per_rotation));
+        }
+
+        // the sync runs, the other direction and the edge cases in the first mode only, so
+        // slow drivers get through the checks in reasonable time
+        for (index, &step_mode) in self.supported_step_modes.iter().enumerate() {
+            let steps_per_rotation = motor.get_step_count_for_full_rotation(&step_mode);
+            let expected_steps = (CHECK_RUN_DEGREES / 360.0 * steps_per_rotation as f32) as u32;
+
+            let mut async_runs = vec![(CHECK_RUN_DEGREES, Direction::Clockwise, expected_steps)];
+            if index == 0 {
+                let steps = expected_steps;
+                let result = self
+                    .run_blocking(&motor, &app_state, move |motor, app_state| {
+                        motor.run_motor(steps, &Direction::Clockwise, &step_mode, app_state)
+                    })
+                    .await;
+                if let Some(message) = check_steps(result, expected_steps, 0) {
+                    fail(
+                        "run_motor",
+                        format!("{} steps, {}: {}", steps, step_mode, message),
+                    );
+                }
+
+                let result = self
+                    .run_blocking(&motor, &app_state, move |motor, app_state| {
+                        motor.run_motor_degrees(
+                            CHECK_RUN_DEGREES,
+                            &Direction::CounterClockwise,
+                            &step_mode,
+                            app_state,
+                        )
+                    })
+                    .await;
+                // the drivers compute the steps in different ways, allow for rounding
+                if let Some(message) = check_steps(result, expected_steps, 1) {
+                    fail(
+                        "run_motor_degrees",
+                        format!("{}°, {}: {}", CHECK_RUN_DEGREES, step_mode, message),
+                    );
+                }
+
+                async_runs.extend([
+                    (
+                        CHECK_RUN_DEGREES,
+                        Direction::CounterClockwise,
+                        expected_steps,
+                    ),
+                    (0.0, Direction::Clockwise, 0),
+                    (-CHECK_RUN_DEGREES, Direction::Clockwise, 0),
+                ]);
+            }
+
+            for (degrees, direction, expected_steps) in async_runs {
+                let cancel_token = CancellationToken::new();
+                let result = self
+                    .run_async(
+                        &motor,
+                        &app_state,
+                        degrees,
+                        direction,
+                        step_mode,
+                        &cancel_token,
+                    )
+                    .await;
+                if let Some(message) = check_steps(result, expected_steps, 1) {
+                    fail(
+                        "run_motor_degrees_async",
+                        format!("{}° {:?}, {}: {}", degrees, direction, step_mode, message),
+                    );
+                }
+            }
+        }
+
+        for step_mode in ALL_STEP_MODES {
+            if self.supported_step_modes.contains(&step_mode) {
+                continue;
+            }
+            let result = self
+                .run_blocking(&motor, &app_state, move |motor, app_state| {
+                    motor.run_motor(1, &Direction::Clockwise, &step_mode, app_state)
+                })
+                .await;
+            if let Some(Ok(steps)) = result {
+                fail(
+                    "unsupported_step_mode",
+                    format!(
+                        "run_motor in {} step mode took {} step(s)",
+                        step_mode, steps
+                    ),
+                );
+            }
+            let cancel_token = CancellationToken::new();
+            let result = self
+                .run_async(
+                    &motor,
+                    &app_state,
+                    CHECK_RUN_DEGREES,
+                    Direction::Clockwise,
+                    step_mode,
+                    &cancel_token,
+                )
+                .await;
+            if let Some(Ok(steps)) = result {
+                fail(
+                    "unsupported_step_mode",
+                    format!(
+                        "run_motor_degrees_async in {} step mode took {} step(s)",
+                        step_mode, steps
+                    ),
+                );
+            }
+        }
+
+        if let Some(&step_mode) = self.supported_step_modes.first() {
+            for failure in self.check_cancellation(&motor, &app_state, step_mode).await {
+                fail(failure.check, failure.message);
+            }
+        }
+        failures
+    }
+
+    /// Cancels a run before it starts and another one while it runs.
+    async fn check_cancellation<M: AsyncStepperMotor + 'static>(
+        &self,
+        motor: &Arc<M>,
+        app_state: &AppStateMutex,
+        step_mode: StepMode,
+    ) -> Vec<ConformanceFailure> {
+        let mut failures = Vec::new();
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        let result = timeout(
+            self.cancel_timeout,
+            motor.run_motor_degrees_async(
+                CANCEL_RUN_DEGREES,
+                &Direction::Clockwise,
+                &step_mode,
+                app_state,
+                &cancel_token,
+            ),
+        )
+        .await;
+        if let Some(message) = check_cancelled(result, self.cancel_timeout) {
+            failures.push(ConformanceFailure {
+                check: "cancel_before_run",
+                message,
+            });
+        }
+
+        let cancel_token = CancellationToken::new();
+        let run = {
+            let motor = Arc::clone(motor);
+            let app_state = Arc::clone(app_state);
+            let cancel_token = cancel_token.clone();
+            tokio::spawn(async move {
+                motor
+                    .run_motor_degrees_async(
+                        CANCEL_RUN_DEGREES,
+                        &Direction::Clockwise,
+                        &step_mode,
+                        &app_state,
+                        &cancel_token,
+                    )
+                    .await
+            })
+        };
+        tokio::time::sleep(CANCEL_AFTER).await;
+        // a run that is already done can't show anything
+        if !run.is_finished() {
+            cancel_token.cancel();
+            let result = timeout(self.cancel_timeout, async {
+                run.await
+                    .unwrap_or_else(|e| Err(format!("panicked: {}", e)))
+            })
+            .await;
+            if let Some(message) = check_cancelled(result, self.cancel_timeout) {
+                failures.push(ConformanceFailure {
+                    check: "cancel_during_run",
+                    message,
+                });
+            }
+        }
+
+        failures
+    }
+
+    /// Runs the checks and panics with every failure, for use in tests.
+    pub async fn assert_conforms<M: AsyncStepperMotor + 'static>(&self, motor: M) {
+        let name = motor.get_name();
+        let failures = self.check(motor).await;
+        if !failures.is_empty() {
+            let failures: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+            panic!(
+                "{} breaks the motor contract in {} way(s):\n  {}",
+                name,
+                failures.len(),
+                failures.join("\n  ")
+            );
+        }
+    }
+
+    /// Runs a sync method on a blocking thread, `None` if it timed out.
+    async fn run_blocking<M, F>(
+        &self,
+        motor: &Arc<M>,
+        app_state: &AppStateMutex,
+        run: F,
+    ) -> Option<Result<u32, String>>
+    where
+        M: AsyncStepperMotor + 'static,
+        F: FnOnce(&M, &AppStateMutex) -> Result<u32, String> + Send + 'static,
+    {
+        let motor = Arc::clone(motor);
+        let app_state = Arc::clone(app_state);
+        let handle = tokio::task::spawn_blocking(move || run(&motor, &app_state));
+        timeout(self.run_timeout, async {
+            handle
+                .await
+                .unwrap_or_else(|e| Err(format!("panicked: {}", e)))
+        })
+        .await
+    }
+
+    async fn run_async<M: AsyncStepperMotor + 'static>(
+        &self,
+        motor: &Arc<M>,
+        app_state: &AppStateMutex,
+        degrees: f32,
+        direction: Direction,
+        step_mode: StepMode,
+        cancel_token: &CancellationToken,
+    ) -> Option<Result<u32, String>> {
+        timeout(
+            self.run_timeout,
+            motor.run_motor_degrees_async(degrees, &direction, &step_mode, app_state, cancel_token),
+        )
+        .await
+    }
+}
+
+impl Default for MotorConformance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn timeout<F: Future<Output = Result<u32, String>>>(
+    duration: Duration,
+    run: F,
+) -> Option<Result<u32, String>> {
+    tokio::time::timeout(duration, run).await.ok()
+}
+
+/// Describes what's wrong with the result of a run that should take `expected` steps.
+fn check_steps(
+    result: Option<Result<u32, String>>,
+    expected: u32,
+    tolerance: u32,
+) -> Option<String> {
+    match result {
+        None => Some("timed out".to_string()),
+        Some(Err(e)) => Some(format!("failed: {}", e)),
+        Some(Ok(steps)) if steps.abs_diff(expected) > tolerance => {
+            Some(format!("took {} step(s), expected {}", steps, expected))
+        }
+        Some(Ok(_)) => None,
+    }
+}
+
+/// Describes what's wrong with the result of a cancelled run.
+fn check_cancelled(
+    result: Option<Result<u32, String>>,
+    cancel_timeout: Duration,
+) -> Option<String> {
+    match result {
+        None => Some(format!(
+            "still running {:?} after the cancel",
+            cancel_timeout
+        )),
+        Some(Ok(steps)) => Some(format!(
+            "finished with {} step(s) instead of failing",
+            steps
+        )),
+        Some(Err(e)) if e.starts_with(STALL_ERROR_PREFIX) => {
+            Some(format!("reported as a stall: {}", e))
+        }
+        Some(Err(_)) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motor::StepperMotor;
+    use crate::motor::stepper_mock::StepperMock;
+    use crate::motor::stepper_unavailable::StepperUnavailable;
+
+    #[tokio::test]
+    async fn test_mock_conforms() {
+        MotorConformance::new()
+            .supported_step_modes(&ALL_STEP_MODES)
+            .assert_conforms(StepperMock::with_run_steps(200))
+            .await;
+    }
+
+    /// Counts its steps right but never looks at the cancel token.
+    struct UncancellableMotor;
+
+    #[async_trait::async_trait]
+    impl AsyncStepperMotor for UncancellableMotor {
+        async fn run_motor_degrees_async(
+            &self,
+            degrees: f32,
+            _direction: &Direction,
+            step_mode: &StepMode,
+            _app_state: &AppStateMutex,
+            _cancel_token: &CancellationToken,
+        ) -> Result<u32, String> {
+            let steps =
+                (degrees / 360.0 * self.get_step_count_for_full_rotation(step_mode) as f32) as u32;
+            tokio::time::sleep(Duration::from_millis(steps as u64)).await;
+            Ok(steps)
+        }
+    }
+
+    impl StepperMotor for UncancellableMotor {
+        fn run_motor(
+            &self,
+            steps: u32,
+            _direction: &Direction,
+            _step_mode: &StepMode,
+            _app_state: &AppStateMutex,
+        ) -> Result<u32, String> {
+            Ok(steps)
+        }
+
+        fn get_step_count_for_full_rotation(&self, _step_mode: &StepMode) -> u32 {
+            200
+        }
+
+        fn get_name(&self) -> String {
+            "UncancellableMotor".to_string()
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_reported() {
+        let failures = MotorConformance::new()
+            .supported_step_modes(&[StepMode::Full, StepMode::Half])
+            .cancel_timeout(Duration::from_millis(200))
+            .check(UncancellableMotor)
+            .await;
+        let checks: Vec<&str> = failures.iter().map(|f| f.check).collect();
+        assert_eq!(
+            checks,
+            vec![
+                "unsupported_step_mode",
+                "unsupported_step_mode",
+                "unsupported_step_mode",
+                "unsupported_step_mode",
+                "unsupported_step_mode",
+                "unsupported_step_mode",
+                "cancel_before_run",
+                "cancel_during_run",
+            ],
+            "{:?}",
+            failures
+        );
+
+        let failures = MotorConformance::new()
+            .check(StepperUnavailable::new("StepperNema14", "no GPIO"))
+            .await;
+        assert!(failures.contains(&ConformanceFailure {
+            check: "steps_per_rotation",
+            message: "Full step mode has no steps per rotation".to_string(),
+        }));
+        assert!(
+            failures
+                .iter()
+                .any(|f| f.check == "run_motor_degrees_async")
+        );
+    }
+}
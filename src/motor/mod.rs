@@ -5,10 +5,18 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
+pub mod conformance;
+pub mod position;
+pub mod progress;
 pub mod stepper_28byj48;
 pub mod stepper_mock;
 pub mod stepper_nema14;
+pub mod stepper_unavailable;
 
+/// Motor errors starting with this are stalls, the dispenser reports them as a jam.
+pub const STALL_ERROR_PREFIX: &str = "Motor stall";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StepMode {
     Full,
     Half,
@@ -29,6 +37,7 @@ impl fmt::Display for StepMode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Direction {
     Clockwise,
     CounterClockwise,
@@ -38,7 +47,7 @@ pub enum Direction {
 pub trait AsyncStepperMotor: Send + Sync + StepperMotor {
     /// Runs the motor for a specified number of degrees in a given direction and step mode.
     /// The number of steps is calculated based on the step mode and the degrees.
-    /// Returns the last step index reached after running the motor.
+    /// Returns the number of steps taken, see `conformance` for the whole contract.
     async fn run_motor_degrees_async(
         &self,
         degrees: f32,
@@ -50,6 +59,7 @@ pub trait AsyncStepperMotor: Send + Sync + StepperMotor {
 }
 
 pub trait StepperMotor: std::any::Any {
+    /// Runs the motor for `steps` steps and returns the number of steps taken.
     fn run_motor(
         &self,
         steps: u32,
@@ -60,7 +70,7 @@ pub trait StepperMotor: std::any::Any {
 
     /// Runs the motor for a specified number of degrees in a given direction and step mode.
     /// The number of steps is calculated based on the step mode and the degrees.
-    /// Returns the last step index reached after running the motor.
+    /// Returns the number of steps taken, see `conformance` for the whole contract.
     fn run_motor_degrees(
         &self,
         degrees: f32,
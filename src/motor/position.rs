@@ -0,0 +1,115 @@
+/// Verifies the mechanical position of the motor against the commanded steps using an
+/// index sensor that triggers once per `steps_per_index` steps at a fixed position.
+/// The first index pulse of a run sets the reference, every later pulse in the same
+/// direction should arrive at the same commanded phase. Any difference is steps that were
+/// lost (or gained). Pulses in the other direction are ignored, the sensor triggers at a
+/// slightly different position depending on the side it's approached from.
+pub struct PositionTracker {
+    steps_per_index: i64,
+    position: i64,
+    /// Phase and direction of the first index pulse
+    reference: Option<(i64, bool)>,
+    index_count: u32,
+    max_deviation: i64,
+}
+
+/// Result of position verification for one motor run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionCheck {
+    /// Index pulses seen during the run
+    pub index_count: u32,
+    /// Largest deviation from the expected position, in steps
+    pub max_deviation_steps: i64,
+}
+
+impl PositionTracker {
+    pub fn new(steps_per_index: u32) -> Self {
+        PositionTracker {
+            steps_per_index: steps_per_index.max(1) as i64,
+            position: 0,
+            reference: None,
+            index_count: 0,
+            max_deviation: 0,
+        }
+    }
+
+    /// Records one commanded step.
+    pub fn step(&mut self, forward: bool) {
+        self.position += if forward { 1 } else { -1 };
+    }
+
+    /// Records an index pulse at the current commanded position and returns the
+    /// deviation in steps. Returns `None` for the first pulse, which only sets the
+    /// reference, and for pulses while moving in the other direction.
+    pub fn on_index(&mut self, forward: bool) -> Option<i64> {
+        let phase = self.position.rem_euclid(self.steps_per_index);
+        let Some((reference, reference_forward)) = self.reference else {
+            self.index_count += 1;
+            self.reference = Some((phase, forward));
+            return None;
+        };
+        if forward != reference_forward {
+            return None;
+        }
+        self.index_count += 1;
+
+        // shortest signed distance around the circle
+        let mut deviation = (phase - reference).rem_euclid(self.steps_per_index);
+        if deviation > self.steps_per_index / 2 {
+            deviation -= self.steps_per_index;
+        }
+        self.max_deviation = self.max_deviation.max(deviation.abs());
+        Some(deviation)
+    }
+
+    pub fn result(&self) -> PositionCheck {
+        PositionCheck {
+            index_count: self.index_count,
+            max_deviation_steps: self.max_deviation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_tracker() {
+        let mut tracker = PositionTracker::new(200);
+        for _ in 0..50 {
+            tracker.step(true);
+        }
+        assert_eq!(tracker.on_index(true), None);
+
+        // one full revolution later the index should trigger at the same phase
+        for _ in 0..200 {
+            tracker.step(true);
+        }
+        assert_eq!(tracker.on_index(true), Some(0));
+
+        // 3 steps were lost, the index arrives 3 commanded steps late
+        for _ in 0..203 {
+            tracker.step(true);
+        }
+        assert_eq!(tracker.on_index(true), Some(3));
+
+        // approached from the other side while reversing, ignored
+        for _ in 0..5 {
+            tracker.step(false);
+        }
+        assert_eq!(tracker.on_index(false), None);
+        for _ in 0..5 {
+            tracker.step(true);
+        }
+        assert_eq!(tracker.on_index(true), Some(3));
+
+        assert_eq!(
+            tracker.result(),
+            PositionCheck {
+                index_count: 4,
+                max_deviation_steps: 3
+            }
+        );
+    }
+}
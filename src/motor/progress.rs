@@ -0,0 +1,85 @@
+use crate::services::events::{DispenseProgress, EventBus};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Progress events are published in steps of this many percent, so a dispense adds at
+/// most 11 events to the event log.
+const PROGRESS_PERCENT_STEP: u8 = 10;
+
+/// Publishes `DispenseProgress` events while a motor run advances. Plain struct without
+/// locking so it can be driven from the realtime step thread.
+pub struct ProgressReporter {
+    /// `None` for runs that aren't a dispense, e.g. stirring the hopper
+    event_bus: Option<Arc<EventBus>>,
+    total_steps: u32,
+    started: Instant,
+    next_percent: u8,
+}
+
+impl ProgressReporter {
+    pub fn new(event_bus: Option<Arc<EventBus>>, total_steps: u32) -> Self {
+        ProgressReporter {
+            event_bus,
+            total_steps,
+            started: Instant::now(),
+            next_percent: 0,
+        }
+    }
+
+    /// Records that `steps_done` steps of the run are complete, publishing an event each
+    /// time another `PROGRESS_PERCENT_STEP` percent is reached.
+    pub fn update(&mut self, steps_done: u32) {
+        let Some(event_bus) = &self.event_bus else {
+            return;
+        };
+        let Some(percent) = self.percent(steps_done) else {
+            return;
+        };
+        self.next_percent = (percent / PROGRESS_PERCENT_STEP + 1) * PROGRESS_PERCENT_STEP;
+        event_bus.publish_progress(DispenseProgress {
+            percent,
+            steps_done,
+            total_steps: self.total_steps,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+        });
+    }
+
+    /// Percent complete after `steps_done` steps, if it is due to be published.
+    fn percent(&self, steps_done: u32) -> Option<u8> {
+        if self.next_percent > 100 {
+            return None;
+        }
+        let percent = if self.total_steps == 0 {
+            100
+        } else {
+            (steps_done.min(self.total_steps) as u64 * 100 / self.total_steps as u64) as u8
+        };
+        (percent >= self.next_percent).then_some(percent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::events::EventKind;
+
+    #[test]
+    fn test_progress_is_published_in_steps() {
+        let event_bus = Arc::new(EventBus::new());
+        let mut progress = ProgressReporter::new(Some(Arc::clone(&event_bus)), 200);
+        for steps_done in 0..=200 {
+            progress.update(steps_done);
+        }
+        // a late final update doesn't repeat 100%
+        progress.update(200);
+
+        let events = event_bus.recent();
+        assert!(events.iter().all(|e| e.kind == EventKind::DispenseProgress));
+        let percents: Vec<u8> = events
+            .iter()
+            .map(|e| e.progress.as_ref().unwrap().percent)
+            .collect();
+        assert_eq!(percents, vec![0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+        assert_eq!(events[5].progress.as_ref().unwrap().steps_done, 100);
+    }
+}
@@ -70,11 +70,9 @@ impl StepperMotor for Stepper28BYJ48 {
 
                 let [mut pin1, mut pin2, mut pin3, mut pin4] = pins
                     .try_into()
-                    .map_err(|_| format!("Failed to initialize stepper pins."))?;
+                    .map_err(|_| "Failed to initialize stepper pins.".to_string())?;
                 info!("Starting motor with {} steps", step_count);
 
-                let mut last_step_index: u32 = 0;
-
                 match direction {
                     Direction::Clockwise => {
                         info!("Running motor in clockwise direction");
@@ -87,7 +85,6 @@ impl StepperMotor for Stepper28BYJ48 {
 
                 for step in 0..step_count {
                     let index = step % step_sequence.len() as u32;
-                    last_step_index = index;
 
                     let sequence = &step_sequence[index as usize];
                     pin1.write(sequence[0].into());
@@ -103,7 +100,7 @@ impl StepperMotor for Stepper28BYJ48 {
                 pin4.write(Low);
                 info!("Motor operation completed");
 
-                Ok(last_step_index)
+                Ok(step_count)
             }
             Err(e) => Err(format!("Failed to create local Gpio instance: {}", e)),
         }
@@ -130,7 +127,14 @@ impl Stepper28BYJ48 {
     }
 }
 
-const STEPPER_PINS: [u8; 4] = [26, 19, 13, 6];
+impl Default for Stepper28BYJ48 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// BCM GPIO numbers of the ULN2003 driver inputs IN1 to IN4.
+pub const STEPPER_PINS: [u8; 4] = [26, 19, 13, 6];
 
 fn init_stepper_pins(gpio: &Gpio) -> rppal::gpio::Result<Vec<OutputPin>> {
     STEPPER_PINS
@@ -1,14 +1,31 @@
 use crate::application_state::ApplicationState;
+use crate::motor::progress::ProgressReporter;
 use crate::motor::{AsyncStepperMotor, Direction, StepMode, StepperMotor};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
-pub struct StepperMock {}
+/// Number of simulated 1 ms steps per async run.
+const MOCK_RUN_STEPS: u32 = 5000;
+
+pub struct StepperMock {
+    run_steps: u32,
+}
 
 impl StepperMock {
     pub fn new() -> Self {
-        StepperMock {}
+        Self::with_run_steps(MOCK_RUN_STEPS)
+    }
+
+    /// Simulates `run_steps` steps per async run instead of 5000, for quicker tests.
+    pub fn with_run_steps(run_steps: u32) -> Self {
+        StepperMock { run_steps }
+    }
+}
+
+impl Default for StepperMock {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -16,33 +33,52 @@ impl StepperMock {
 impl AsyncStepperMotor for StepperMock {
     async fn run_motor_degrees_async(
         &self,
-        _degrees: f32,
+        degrees: f32,
         _direction: &Direction,
-        _step_mode: &StepMode,
-        _app_state: &Arc<Mutex<ApplicationState>>,
+        step_mode: &StepMode,
+        app_state: &Arc<Mutex<ApplicationState>>,
         cancel_token: &CancellationToken,
     ) -> Result<u32, String> {
+        let steps =
+            (degrees / 360.0 * self.get_step_count_for_full_rotation(step_mode) as f32) as u32;
+        if steps == 0 {
+            return Ok(0);
+        }
+        let event_bus = {
+            let state_guard = app_state.lock().await;
+            // only dispense runs report progress
+            state_guard
+                .dispense_span
+                .is_some()
+                .then(|| state_guard.event_bus.clone())
+        };
+        let mut progress = ProgressReporter::new(event_bus, self.run_steps);
+
         // Simulate motor operation
-        for _ in 0..5000 {
+        for step in 0..self.run_steps {
             if cancel_token.is_cancelled() {
                 return Err("Motor operation cancelled".to_string());
             }
             tokio::time::sleep(Duration::from_millis(1)).await;
+            progress.update(step + 1);
         }
-        Ok(0) // Mock implementation
+        // the simulated run takes the same time for every angle
+        Ok(steps)
     }
 }
 
 impl StepperMotor for StepperMock {
     fn run_motor(
         &self,
-        _steps: u32,
+        steps: u32,
         _direction: &Direction,
         _step_mode: &StepMode,
         _app_state: &Arc<Mutex<ApplicationState>>,
     ) -> Result<u32, String> {
-        std::thread::sleep(Duration::from_millis(3000)); // Simulate motor operation
-        Ok(0) // Mock implementation
+        if steps > 0 {
+            std::thread::sleep(Duration::from_millis(3000)); // Simulate motor operation
+        }
+        Ok(steps)
     }
 
     fn get_step_count_for_full_rotation(&self, _step_mode: &StepMode) -> u32 {
@@ -1,19 +1,41 @@
 use crate::motor::{AsyncStepperMotor, Direction, StepMode, StepperMotor};
 
 use crate::application_state::ApplicationState;
+use crate::config::{self, RealtimeSteppingConfig};
+use crate::motor::position::PositionTracker;
+use crate::motor::progress::ProgressReporter;
+use crate::sensors::PowerReading;
+use crate::services::events::EventKind;
+use crate::utils::state_helpers;
 use rand::Rng;
 use rand::SeedableRng;
 use rand::rngs::StdRng;
-use rppal::gpio::{Gpio, OutputPin};
+use rppal::gpio::{Gpio, InputPin, Level, OutputPin};
+use rppal::pwm::{Channel, Pwm};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, watch};
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+const ANTI_JAM_MIN_INTERVAL_STEPS_DEFAULT: u32 = 110;
+const ANTI_JAM_MAX_INTERVAL_STEPS_DEFAULT: u32 = 200;
+const INDEX_STEPS_PER_INDEX_DEFAULT: u32 = 200;
+const INDEX_TOLERANCE_STEPS_DEFAULT: u32 = 2;
+const REALTIME_PRIORITY_DEFAULT: u8 = 50;
+/// The realtime step thread sleeps until this close to a pulse edge, then busy-waits.
+const REALTIME_SPIN_THRESHOLD: Duration = Duration::from_micros(200);
+/// Steps per PWM segment while ramping up, the rate is updated between segments.
+const PWM_RAMP_SEGMENT_STEPS: u32 = 10;
+const SOFT_START_STEP_SPEED_US_DEFAULT: u64 = 3000;
+const SOFT_START_RAMP_STEPS_DEFAULT: u32 = 100;
+/// Consecutive power readings above the stall current before the run is aborted.
+const SOFT_START_STALL_READINGS: u32 = 2;
 
 pub struct StepperNema14 {
     config: Nema14Config,
+    realtime: Option<RealtimeSteppingConfig>,
 }
 
 impl StepperMotor for StepperNema14 {
@@ -61,32 +83,23 @@ impl StepperMotor for StepperNema14 {
                     Direction::CounterClockwise => dir_pin.write(rppal::gpio::Level::Low),
                 }
 
-                let step_speed_us = self.config.step_speed_us.or(Some(1000)).unwrap();
+                let step_speed_us = self.config.step_speed_us.unwrap_or(1000);
 
-                let mut i = 0;
                 let mut is_dir_high = match direction {
                     Direction::Clockwise => true,
                     Direction::CounterClockwise => false,
                 };
+                let mut anti_jam = AntiJam::new(self.config.anti_jam.as_ref(), StdRng::from_os_rng());
 
-                // randomize number of steps before toggling direction
-                // we want to toggle direction pin every 110-200 steps (200 is full rotation), helps prevent treats from jamming
-                let mut rng = rand::rng();
-                let mut random_steps = rng.random_range(110..=200);
-
-                for _step in 0..steps {
-                    i += 1;
-                    if i % random_steps == 0 {
-                        if is_dir_high {
-                            dir_pin.write(rppal::gpio::Level::Low);
-                            is_dir_high = false;
+                for step in 0..steps {
+                    if anti_jam.next_step() {
+                        is_dir_high = !is_dir_high;
+                        dir_pin.write(if is_dir_high {
+                            rppal::gpio::Level::High
                         } else {
-                            dir_pin.write(rppal::gpio::Level::High);
-                            is_dir_high = true;
-                        }
-                        debug!("Direction pin toggled at step {}", i);
-                        i = 0; // Reset the counter after toggling
-                        random_steps = rng.random_range(110..=200);
+                            rppal::gpio::Level::Low
+                        });
+                        debug!("Direction pin toggled at step {}", step);
                     }
 
                     // pulse the step pin to move motor shaft
@@ -100,9 +113,7 @@ impl StepperMotor for StepperNema14 {
                 enable_pin.write(rppal::gpio::Level::High);
                 Ok(steps)
             }
-            Err(e) => {
-                return Err(format!("Failed to initialize GPIO: {}", e));
-            }
+            Err(e) => Err(format!("Failed to initialize GPIO: {}", e)),
         }
     }
 
@@ -128,12 +139,31 @@ impl AsyncStepperMotor for StepperNema14 {
         degrees: f32,
         direction: &Direction,
         step_mode: &StepMode,
-        _app_state: &Arc<Mutex<ApplicationState>>,
+        app_state: &Arc<Mutex<ApplicationState>>,
         cancel_token: &CancellationToken,
     ) -> Result<u32, String> {
         let steps = (degrees / 1.80) as u32;
         info!("Starting NEMA14 motor with {} steps [ASYNC]", steps);
 
+        let (mut power_readings_rx, motor_current_limit, event_bus) = {
+            let state_guard = app_state.lock().await;
+            (
+                state_guard.power_readings_rx.clone(),
+                state_guard
+                    .app_config
+                    .power_monitor
+                    .motor_current_limit_amps
+                    .unwrap_or(config::MOTOR_CURRENT_LIMIT_AMPS_DEFAULT),
+                // only dispense runs report progress
+                state_guard
+                    .dispense_span
+                    .is_some()
+                    .then(|| state_guard.event_bus.clone()),
+            )
+        };
+        // only readings taken after the motor was enabled count towards stall detection
+        power_readings_rx.mark_unchanged();
+
         match step_mode {
             StepMode::Full => {
                 // NEMA14 typically supports full and half step modes
@@ -146,7 +176,6 @@ impl AsyncStepperMotor for StepperNema14 {
 
         match Gpio::new() {
             Ok(_gpio) => {
-                let mut step_pin = self.get_output_pin(self.config.step_pin)?;
                 let mut dir_pin = self.get_output_pin(self.config.dir_pin)?;
                 let mut sleep_pin = self.get_output_pin(self.config.sleep_pin)?;
                 let mut reset_pin = self.get_output_pin(self.config.reset_pin)?;
@@ -161,60 +190,469 @@ impl AsyncStepperMotor for StepperNema14 {
                     Direction::CounterClockwise => dir_pin.write(rppal::gpio::Level::Low),
                 }
 
-                let step_speed_us = self.config.step_speed_us.or(Some(1000)).unwrap();
-
-                let mut i = 0;
-                let mut is_dir_high = match direction {
+                let soft_start = self.config.soft_start.clone().unwrap_or_default();
+                let step_speed_us = self.config.step_speed_us.unwrap_or(1000);
+                let is_dir_high = match direction {
                     Direction::Clockwise => true,
                     Direction::CounterClockwise => false,
                 };
+                let step_generation = self.config.step_generation.clone().unwrap_or_default();
+                let index_sensor = match &self.config.index_sensor {
+                    Some(_) if step_generation == StepGeneration::Pwm => {
+                        warn!("Index sensor checks are not available with PWM step generation");
+                        None
+                    }
+                    Some(index_config) => Some(IndexSensor::new(index_config)?),
+                    None => None,
+                };
+
+                let mut run = StepRun {
+                    dir_pin,
+                    is_dir_high,
+                    commanded_dir_high: is_dir_high,
+                    anti_jam: AntiJam::new(self.config.anti_jam.as_ref(), StdRng::from_os_rng()),
+                    index_sensor,
+                    power_readings_rx,
+                    stall_current: soft_start.stall_current_amps.unwrap_or(motor_current_limit),
+                    stall_readings: 0,
+                    ramp_steps: soft_start.ramp_steps.unwrap_or(SOFT_START_RAMP_STEPS_DEFAULT),
+                    start_speed_us: soft_start
+                        .start_step_speed_us
+                        .unwrap_or(SOFT_START_STEP_SPEED_US_DEFAULT)
+                        .max(step_speed_us),
+                    step_speed_us,
+                    cancel_token: cancel_token.clone(),
+                    progress: ProgressReporter::new(event_bus, steps),
+                };
+
+                let realtime = self.realtime.clone().unwrap_or_default();
+                let result = if step_generation == StepGeneration::Pwm {
+                    let pwm = Pwm::new(pwm_channel(self.config.step_pin)?)
+                        .map_err(|e| format!("Failed to initialize PWM: {}", e))?;
+                    run_pwm(&mut run, &pwm, steps).await
+                } else if realtime.enabled.unwrap_or(false) {
+                    let step_pin = self.get_output_pin(self.config.step_pin)?;
+                    let (returned_run, result) =
+                        run_realtime(run, step_pin, steps, realtime).await?;
+                    run = returned_run;
+                    result
+                } else {
+                    let mut step_pin = self.get_output_pin(self.config.step_pin)?;
+                    run_async(&mut run, &mut step_pin, steps).await
+                };
 
-                // randomize number of steps before toggling direction
-                // we want to toggle direction pin every 110-200 steps (200 is full rotation), helps prevent treats from jamming
-                let mut rng = StdRng::from_os_rng();
-                let mut random_steps = rng.random_range(110..=200);
+                // Disables the motor after operation
+                enable_pin.write(rppal::gpio::Level::High);
 
-                for _step in 0..steps {
+                if let Err(e) = &result {
                     if cancel_token.is_cancelled() {
                         info!("Received cancellation request, stopping motor operation.");
-                        return Err("Motor run cancelled".to_string());
                     }
+                    return Err(e.clone());
+                }
+                if let Some(index_sensor) = run.index_sensor {
+                    index_sensor.report(app_state).await;
+                }
+                Ok(steps)
+            }
+            Err(e) => Err(format!("Failed to initialize GPIO: {}", e)),
+        }
+    }
+}
 
-                    i += 1;
-                    if i % random_steps == 0 {
-                        if is_dir_high {
-                            dir_pin.write(rppal::gpio::Level::Low);
-                            is_dir_high = false;
-                        } else {
-                            dir_pin.write(rppal::gpio::Level::High);
-                            is_dir_high = true;
-                        }
-                        debug!("Direction pin toggled at step {}", i);
-                        i = 0; // Reset the counter after toggling
-                        random_steps = rng.random_range(110..=200);
-                    }
+/// Pins and per-run state of a motor run. The pulse timing is left to the caller so
+/// the same logic drives the async loop and the realtime thread.
+struct StepRun {
+    dir_pin: OutputPin,
+    is_dir_high: bool,
+    commanded_dir_high: bool,
+    anti_jam: AntiJam,
+    index_sensor: Option<IndexSensor>,
+    power_readings_rx: watch::Receiver<PowerReading>,
+    stall_current: f32,
+    stall_readings: u32,
+    ramp_steps: u32,
+    start_speed_us: u64,
+    step_speed_us: u64,
+    cancel_token: CancellationToken,
+    progress: ProgressReporter,
+}
 
-                    // pulse the step pin to move motor shaft
-                    step_pin.write(rppal::gpio::Level::High);
-                    tokio::time::sleep(Duration::from_micros(step_speed_us)).await;
-                    step_pin.write(rppal::gpio::Level::Low);
-                    tokio::time::sleep(Duration::from_micros(step_speed_us)).await;
-                }
+impl StepRun {
+    /// Checks for cancellation and stalls, toggles the direction if due and returns the
+    /// delay to use for both halves of the step pulse.
+    fn before_step(&mut self, step: u32) -> Result<u64, String> {
+        if self.cancel_token.is_cancelled() {
+            return Err("Motor run cancelled".to_string());
+        }
 
-                // Disables the motor after operation
-                enable_pin.write(rppal::gpio::Level::High);
-                Ok(steps)
+        // a stalled motor draws its full coil current, catch it while still ramping
+        if step < self.ramp_steps && self.power_readings_rx.has_changed().unwrap_or(false) {
+            let current = self.power_readings_rx.borrow_and_update().current_amps;
+            if current > self.stall_current {
+                self.stall_readings += 1;
+                debug!("Soft start current {} A at step {}", current, step);
+            } else {
+                self.stall_readings = 0;
             }
-            Err(e) => {
-                return Err(format!("Failed to initialize GPIO: {}", e));
+            if self.stall_readings >= SOFT_START_STALL_READINGS {
+                return Err(format!(
+                    "{} during soft start at step {} ({} A)",
+                    super::STALL_ERROR_PREFIX,
+                    step,
+                    current
+                ));
             }
         }
+
+        if self.anti_jam.next_step() {
+            self.is_dir_high = !self.is_dir_high;
+            self.dir_pin.write(if self.is_dir_high {
+                rppal::gpio::Level::High
+            } else {
+                rppal::gpio::Level::Low
+            });
+            debug!("Direction pin toggled at step {}", step);
+        }
+
+        Ok(soft_start_delay_us(
+            step,
+            self.start_speed_us,
+            self.step_speed_us,
+            self.ramp_steps,
+        ))
+    }
+
+    fn after_step(&mut self, step: u32) {
+        let forward = self.is_dir_high == self.commanded_dir_high;
+        if let Some(index_sensor) = self.index_sensor.as_mut() {
+            index_sensor.after_step(forward);
+        }
+        self.progress.update(step + 1);
     }
 }
 
+async fn run_async(
+    run: &mut StepRun,
+    step_pin: &mut OutputPin,
+    steps: u32,
+) -> Result<(), String> {
+    for step in 0..steps {
+        let step_delay_us = run.before_step(step)?;
+
+        // pulse the step pin to move motor shaft
+        step_pin.write(rppal::gpio::Level::High);
+        tokio::time::sleep(Duration::from_micros(step_delay_us)).await;
+        step_pin.write(rppal::gpio::Level::Low);
+        tokio::time::sleep(Duration::from_micros(step_delay_us)).await;
+
+        run.after_step(step);
+    }
+    Ok(())
+}
+
+/// Generates the step pulses on a dedicated thread with realtime priority and
+/// busy-wait timing. Tokio and `thread::sleep` timers jitter by hundreds of µs on a
+/// Pi, which is a large fraction of a 1000 µs step and makes the motor run rough.
+async fn run_realtime(
+    mut run: StepRun,
+    mut step_pin: OutputPin,
+    steps: u32,
+    realtime: RealtimeSteppingConfig,
+) -> Result<(StepRun, Result<(), String>), String> {
+    let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+    let priority = realtime
+        .priority
+        .unwrap_or(REALTIME_PRIORITY_DEFAULT)
+        .clamp(1, 99);
+
+    std::thread::Builder::new()
+        .name("nema14-steps".to_string())
+        .spawn(move || {
+            set_realtime_priority(priority);
+
+            let mut deadline = Instant::now();
+            let mut result = Ok(());
+            for step in 0..steps {
+                let step_delay = match run.before_step(step) {
+                    Ok(step_delay_us) => Duration::from_micros(step_delay_us),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                };
+
+                // deadlines are absolute so an occasional late wakeup doesn't accumulate drift
+                deadline = deadline.max(Instant::now());
+                step_pin.write(rppal::gpio::Level::High);
+                deadline += step_delay;
+                precise_wait_until(deadline);
+                step_pin.write(rppal::gpio::Level::Low);
+                deadline += step_delay;
+                precise_wait_until(deadline);
+
+                run.after_step(step);
+            }
+            let _ = result_tx.send((run, result));
+        })
+        .map_err(|e| format!("Failed to start step thread: {}", e))?;
+
+    result_rx
+        .await
+        .map_err(|_| "Step thread exited without a result".to_string())
+}
+
+/// Drives the STEP pin from a hardware PWM channel so the pulses need no CPU time. The
+/// PWM has no pulse counter, so steps are counted by running each segment for exactly
+/// `steps * period`. Segments end at anti-jam direction toggles, and during the soft
+/// start ramp every `PWM_RAMP_SEGMENT_STEPS` steps to update the rate.
+async fn run_pwm(run: &mut StepRun, pwm: &Pwm, steps: u32) -> Result<(), String> {
+    let mut step = 0;
+    while step < steps {
+        let step_delay_us = run.before_step(step)?;
+
+        let mut segment_steps = steps - step;
+        if step < run.ramp_steps {
+            segment_steps = segment_steps.min(PWM_RAMP_SEGMENT_STEPS);
+        }
+        if let Some(steps_until_toggle) = run.anti_jam.steps_until_toggle() {
+            segment_steps = segment_steps.min(steps_until_toggle);
+        }
+        // before_step already counted the first step of the segment
+        run.anti_jam.skip_steps(segment_steps - 1);
+
+        let period = Duration::from_micros(step_delay_us * 2);
+        pwm.set_period(period)
+            .and_then(|_| pwm.set_pulse_width(period / 2))
+            .and_then(|_| pwm.enable())
+            .map_err(|e| format!("Failed to start PWM: {}", e))?;
+        let cancelled = tokio::select! {
+            _ = run.cancel_token.cancelled() => true,
+            _ = tokio::time::sleep(period * segment_steps) => false,
+        };
+        pwm.disable()
+            .map_err(|e| format!("Failed to stop PWM: {}", e))?;
+        if cancelled {
+            return Err("Motor run cancelled".to_string());
+        }
+
+        step += segment_steps;
+        run.progress.update(step);
+    }
+    Ok(())
+}
+
+/// Hardware PWM channel that can drive the given STEP pin.
+pub(crate) fn pwm_channel(step_pin: u8) -> Result<Channel, String> {
+    match step_pin {
+        12 | 18 => Ok(Channel::Pwm0),
+        13 | 19 => Ok(Channel::Pwm1),
+        _ => Err(format!(
+            "Pin {} has no hardware PWM, use GPIO 12, 13, 18 or 19 as step_pin",
+            step_pin
+        )),
+    }
+}
+
+/// Sleeps until shortly before `deadline`, then spins for the remainder.
+fn precise_wait_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        let remaining = deadline - now;
+        if remaining > REALTIME_SPIN_THRESHOLD {
+            std::thread::sleep(remaining - REALTIME_SPIN_THRESHOLD);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Switches the calling thread to SCHED_FIFO. Needs root or CAP_SYS_NICE, otherwise the
+/// thread keeps its normal priority and only the busy-wait timing applies.
+fn set_realtime_priority(priority: u8) {
+    let param = libc::sched_param {
+        sched_priority: priority as i32,
+    };
+    // SAFETY: pthread_self() is always a valid handle for the calling thread and
+    // param outlives the call
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if result == 0 {
+        debug!("Step thread running with SCHED_FIFO priority {}", priority);
+    } else {
+        warn!(
+            "Could not set realtime priority for the step thread (error {}), continuing with normal priority",
+            result
+        );
+    }
+}
+
+/// Home/index sensor (hall or optical) that triggers once per `steps_per_index` steps,
+/// used to verify that no steps were lost.
+struct IndexSensor {
+    pin: InputPin,
+    active_level: Level,
+    last_level: Level,
+    tolerance_steps: u32,
+    tracker: PositionTracker,
+}
+
+impl IndexSensor {
+    fn new(config: &IndexSensorConfig) -> Result<Self, String> {
+        let pin = Gpio::new()
+            .and_then(|gpio| gpio.get(config.pin))
+            .map(|pin| pin.into_input_pullup())
+            .map_err(|_| format!("Failed to get index sensor pin {}", config.pin))?;
+        let active_level = if config.active_low.unwrap_or(true) {
+            Level::Low
+        } else {
+            Level::High
+        };
+        let last_level = pin.read();
+        Ok(IndexSensor {
+            pin,
+            active_level,
+            last_level,
+            tolerance_steps: config
+                .tolerance_steps
+                .unwrap_or(INDEX_TOLERANCE_STEPS_DEFAULT),
+            tracker: PositionTracker::new(
+                config.steps_per_index.unwrap_or(INDEX_STEPS_PER_INDEX_DEFAULT),
+            ),
+        })
+    }
+
+    fn after_step(&mut self, forward: bool) {
+        self.tracker.step(forward);
+        let level = self.pin.read();
+        if level != self.last_level
+            && level == self.active_level
+            && let Some(deviation) = self.tracker.on_index(forward)
+        {
+            debug!("Index pulse, position deviation {} steps", deviation);
+        }
+        self.last_level = level;
+    }
+
+    /// Records a step loss event if the position drifted beyond the tolerance.
+    async fn report(self, app_state: &Arc<Mutex<ApplicationState>>) {
+        let check = self.tracker.result();
+        if check.index_count < 2 {
+            debug!(
+                "Position not verified, {} index pulse(s) during the run",
+                check.index_count
+            );
+            return;
+        }
+        if check.max_deviation_steps <= self.tolerance_steps as i64 {
+            debug!(
+                "Position verified, max deviation {} steps",
+                check.max_deviation_steps
+            );
+            return;
+        }
+
+        let message = format!(
+            "Step loss detected: position off by up to {} steps over {} index pulses",
+            check.max_deviation_steps, check.index_count
+        );
+        warn!("{}", message);
+        state_helpers::record_error(app_state, &message).await;
+        let event_bus = app_state.lock().await.event_bus.clone();
+        event_bus.publish(EventKind::StepLoss, message);
+    }
+}
+
+/// Decides when to flip the direction pin to shake loose jammed treats. By default the
+/// direction toggles every 110-200 steps (a full rotation is 200), with `reverse_steps`
+/// set the motor only sweeps back that far before resuming the original direction.
+struct AntiJam {
+    enabled: bool,
+    min_interval_steps: u32,
+    max_interval_steps: u32,
+    reverse_steps: Option<u32>,
+    rng: StdRng,
+    steps_since_toggle: u32,
+    next_toggle: u32,
+    reversing: bool,
+}
+
+impl AntiJam {
+    fn new(config: Option<&AntiJamConfig>, rng: StdRng) -> Self {
+        let config = config.cloned().unwrap_or_default();
+        let min_interval_steps = config
+            .min_interval_steps
+            .unwrap_or(ANTI_JAM_MIN_INTERVAL_STEPS_DEFAULT)
+            .max(1);
+        let mut anti_jam = AntiJam {
+            enabled: config.enabled.unwrap_or(true),
+            min_interval_steps,
+            max_interval_steps: config
+                .max_interval_steps
+                .unwrap_or(ANTI_JAM_MAX_INTERVAL_STEPS_DEFAULT)
+                .max(min_interval_steps),
+            reverse_steps: config.reverse_steps.filter(|steps| *steps > 0),
+            rng,
+            steps_since_toggle: 0,
+            next_toggle: 0,
+            reversing: false,
+        };
+        anti_jam.next_toggle = anti_jam.random_interval();
+        anti_jam
+    }
+
+    fn random_interval(&mut self) -> u32 {
+        self.rng
+            .random_range(self.min_interval_steps..=self.max_interval_steps)
+    }
+
+    /// Called before every step, returns true if the direction should be toggled first.
+    fn next_step(&mut self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.steps_since_toggle += 1;
+        if self.steps_since_toggle < self.next_toggle {
+            return false;
+        }
+
+        self.steps_since_toggle = 0;
+        self.next_toggle = match self.reverse_steps {
+            Some(reverse_steps) if !self.reversing => reverse_steps,
+            _ => self.random_interval(),
+        };
+        self.reversing = self.reverse_steps.is_some() && !self.reversing;
+        true
+    }
+
+    /// Number of `next_step` calls until the next toggle, `None` if disabled.
+    fn steps_until_toggle(&self) -> Option<u32> {
+        self.enabled
+            .then(|| self.next_toggle.saturating_sub(self.steps_since_toggle).max(1))
+    }
+
+    /// Advances by `steps` steps that are known not to reach the next toggle.
+    fn skip_steps(&mut self, steps: u32) {
+        self.steps_since_toggle += steps;
+    }
+}
+
+/// Step delay for `step` while soft starting: ramps linearly from `start_us` to
+/// `target_us` over the first `ramp_steps` steps.
+fn soft_start_delay_us(step: u32, start_us: u64, target_us: u64, ramp_steps: u32) -> u64 {
+    if step >= ramp_steps || start_us <= target_us {
+        return target_us;
+    }
+    let remaining = (ramp_steps - step) as u64;
+    target_us + (start_us - target_us) * remaining / ramp_steps as u64
+}
+
 impl StepperNema14 {
-    pub fn new(config: Nema14Config) -> Self {
-        StepperNema14 { config }
+    pub fn new(config: Nema14Config, realtime: Option<RealtimeSteppingConfig>) -> Self {
+        StepperNema14 { config, realtime }
     }
 
     fn get_output_pin(&self, pin_num: u8) -> Result<OutputPin, String> {
@@ -225,7 +663,7 @@ impl StepperNema14 {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct Nema14Config {
     pub dir_pin: u8,
     pub step_pin: u8,
@@ -233,4 +671,142 @@ pub struct Nema14Config {
     pub reset_pin: u8,
     pub enable_pin: u8,
     pub step_speed_us: Option<u64>, // Speed in microseconds per step
+    pub soft_start: Option<SoftStartConfig>,
+    pub anti_jam: Option<AntiJamConfig>,
+    pub index_sensor: Option<IndexSensorConfig>,
+    pub step_generation: Option<StepGeneration>,
+}
+
+/// How the STEP pin pulses are generated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StepGeneration {
+    /// Toggle the pin from software, timed by the async runtime or the realtime thread
+    #[default]
+    Software,
+    /// Hardware PWM, `step_pin` must be one of the PWM capable GPIOs 12, 13, 18 or 19
+    Pwm,
+}
+
+/// Home/index sensor used to detect lost steps.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct IndexSensorConfig {
+    /// GPIO pin of the sensor output, read with the internal pull-up enabled
+    pub pin: u8,
+    /// Steps between two index pulses (default 200, one per revolution)
+    pub steps_per_index: Option<u32>,
+    /// Deviation in steps that is still accepted (default 2)
+    pub tolerance_steps: Option<u32>,
+    /// Sensor pulls the pin low when triggered (default true)
+    pub active_low: Option<bool>,
+}
+
+/// Periodic direction reversals that help prevent treats from jamming.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct AntiJamConfig {
+    /// Set to false to always turn in the commanded direction (default true)
+    pub enabled: Option<bool>,
+    /// Fewest steps between direction changes (default 110)
+    pub min_interval_steps: Option<u32>,
+    /// Most steps between direction changes (default 200)
+    pub max_interval_steps: Option<u32>,
+    /// Steps to sweep back before resuming the commanded direction. When unset the
+    /// direction simply alternates at every interval.
+    pub reverse_steps: Option<u32>,
+}
+
+/// Ramp from a slow step rate up to `step_speed_us` at the start of every run, watching
+/// the motor current for a stall while ramping.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct SoftStartConfig {
+    /// Step delay at the first step, in microseconds (default 3000)
+    pub start_step_speed_us: Option<u64>,
+    /// Number of steps to reach `step_speed_us`, 0 disables the ramp (default 100)
+    pub ramp_steps: Option<u32>,
+    /// Current that indicates a stall while ramping (default `motor_current_limit_amps`)
+    pub stall_current_amps: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toggle_steps(config: AntiJamConfig, steps: u32) -> Vec<u32> {
+        let mut anti_jam = AntiJam::new(Some(&config), StdRng::seed_from_u64(7));
+        (0..steps).filter(|_| anti_jam.next_step()).collect()
+    }
+
+    #[test]
+    fn test_anti_jam_toggles() {
+        let fixed = AntiJamConfig {
+            min_interval_steps: Some(10),
+            max_interval_steps: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(toggle_steps(fixed.clone(), 35), vec![9, 19, 29]);
+
+        let sweep = AntiJamConfig {
+            reverse_steps: Some(3),
+            ..fixed.clone()
+        };
+        // back 3 steps, then forward again for the full interval
+        assert_eq!(toggle_steps(sweep, 35), vec![9, 12, 22, 25]);
+
+        let disabled = AntiJamConfig {
+            enabled: Some(false),
+            ..fixed
+        };
+        assert!(toggle_steps(disabled, 500).is_empty());
+    }
+
+    #[test]
+    fn test_soft_start_delay() {
+        assert_eq!(soft_start_delay_us(0, 3000, 1000, 100), 3000);
+        assert_eq!(soft_start_delay_us(50, 3000, 1000, 100), 2000);
+        assert_eq!(soft_start_delay_us(100, 3000, 1000, 100), 1000);
+        assert_eq!(soft_start_delay_us(500, 3000, 1000, 100), 1000);
+        // ramp disabled
+        assert_eq!(soft_start_delay_us(0, 3000, 1000, 0), 1000);
+    }
+
+    #[test]
+    fn test_anti_jam_steps_until_toggle() {
+        let config = AntiJamConfig {
+            min_interval_steps: Some(10),
+            max_interval_steps: Some(10),
+            ..Default::default()
+        };
+        let mut anti_jam = AntiJam::new(Some(&config), StdRng::seed_from_u64(7));
+        assert_eq!(anti_jam.steps_until_toggle(), Some(10));
+        assert!(!anti_jam.next_step());
+        anti_jam.skip_steps(7);
+        assert_eq!(anti_jam.steps_until_toggle(), Some(2));
+        assert!(!anti_jam.next_step());
+        assert!(anti_jam.next_step());
+
+        let disabled = AntiJamConfig {
+            enabled: Some(false),
+            ..config
+        };
+        let anti_jam = AntiJam::new(Some(&disabled), StdRng::seed_from_u64(7));
+        assert_eq!(anti_jam.steps_until_toggle(), None);
+    }
+
+    #[test]
+    fn test_pwm_channel() {
+        assert!(matches!(pwm_channel(18), Ok(Channel::Pwm0)));
+        assert!(matches!(pwm_channel(19), Ok(Channel::Pwm1)));
+        assert!(pwm_channel(26).is_err());
+    }
+
+    #[test]
+    fn test_precise_wait_until() {
+        let start = Instant::now();
+        let deadline = start + Duration::from_micros(1500);
+        precise_wait_until(deadline);
+        assert!(Instant::now() >= deadline);
+
+        // deadlines in the past return immediately
+        precise_wait_until(start);
+    }
 }
@@ -0,0 +1,67 @@
+use crate::application_state::ApplicationState;
+use crate::motor::{AsyncStepperMotor, Direction, StepMode, StepperMotor};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Stands in for a motor that failed to initialize, so the API can keep running in a
+/// degraded state. Every run fails with the initialization error.
+pub struct StepperUnavailable {
+    motor_type: String,
+    error: String,
+}
+
+impl StepperUnavailable {
+    pub fn new(motor_type: &str, error: &str) -> Self {
+        StepperUnavailable {
+            motor_type: motor_type.to_string(),
+            error: error.to_string(),
+        }
+    }
+
+    fn unavailable_error(&self) -> String {
+        format!("Motor '{}' is unavailable: {}", self.motor_type, self.error)
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncStepperMotor for StepperUnavailable {
+    async fn run_motor_degrees_async(
+        &self,
+        _degrees: f32,
+        _direction: &Direction,
+        _step_mode: &StepMode,
+        _app_state: &Arc<Mutex<ApplicationState>>,
+        _cancel_token: &CancellationToken,
+    ) -> Result<u32, String> {
+        Err(self.unavailable_error())
+    }
+}
+
+impl StepperMotor for StepperUnavailable {
+    fn run_motor(
+        &self,
+        _steps: u32,
+        _direction: &Direction,
+        _step_mode: &StepMode,
+        _app_state: &Arc<Mutex<ApplicationState>>,
+    ) -> Result<u32, String> {
+        Err(self.unavailable_error())
+    }
+
+    fn get_step_count_for_full_rotation(&self, _step_mode: &StepMode) -> u32 {
+        0
+    }
+
+    fn get_name(&self) -> String {
+        format!("{} (unavailable)", self.motor_type)
+    }
+
+    fn requires_gpio(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
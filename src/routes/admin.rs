@@ -0,0 +1,191 @@
+use crate::application_state::{AppStateMutex, DispenserStatus};
+use crate::error::ApiError;
+use crate::logging;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::services::admin_batch::{self, BatchRequest, BatchResponse};
+use crate::services::backup::{self, RestoreResponse};
+use crate::services::diagnostics;
+use crate::services::weight_monitor;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::{Extension, Json};
+use axum::response::IntoResponse;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct LogLevelBody {
+    /// Filter directives in RUST_LOG syntax, e.g. `info,treat_dispenser_api::services::weight_monitor=trace`
+    pub filter: String,
+}
+
+/// Returns the active log filter.
+#[utoipa::path(
+    get,
+    path = "/admin/log-level",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Active log filter", body = LogLevelBody),
+    )
+)]
+pub async fn get_log_level() -> Result<Json<LogLevelBody>, ApiError> {
+    match logging::get_log_filter() {
+        Some(filter) => Ok(Json(LogLevelBody { filter })),
+        None => Err(ApiError::Internal(
+            "Runtime log level control is not available".to_string(),
+        )),
+    }
+}
+
+/// Replaces the log filter until the next restart.
+#[utoipa::path(
+    put,
+    path = "/admin/log-level",
+    tag = "admin",
+    request_body = LogLevelBody,
+    responses(
+        (status = 200, description = "Log filter now in effect", body = LogLevelBody),
+        (status = 400, description = "Invalid filter", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn set_log_level(Json(body): Json<LogLevelBody>) -> Result<Json<LogLevelBody>, ApiError> {
+    if logging::get_log_filter().is_none() {
+        return Err(ApiError::Internal(
+            "Runtime log level control is not available".to_string(),
+        ));
+    }
+
+    logging::set_log_filter(&body.filter)
+        .map(|filter| Json(LogLevelBody { filter }))
+        .map_err(ApiError::BadRequest)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/backup",
+    tag = "admin",
+    responses(
+        (status = 200, description = "tar.gz archive of the config and the persisted data", body = Vec<u8>, content_type = "application/gzip"),
+    )
+)]
+/// Downloads a tar.gz backup of the config and all persisted dispenser data.
+pub async fn download_backup() -> Result<impl IntoResponse, ApiError> {
+    let archive = backup::create_backup().map_err(ApiError::Internal)?;
+    let file_name = format!("treat-dispenser-backup-{}.tar.gz", backup::backup_timestamp());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        archive,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/diagnostics",
+    tag = "admin",
+    responses(
+        (status = 200, description = "tar.gz diagnostics bundle", body = Vec<u8>, content_type = "application/gzip"),
+    )
+)]
+/// Downloads a tar.gz diagnostics bundle to attach to bug reports, secrets in the
+/// config are redacted.
+pub async fn download_diagnostics(
+    State(app_state): State<AppStateMutex>,
+) -> Result<impl IntoResponse, ApiError> {
+    let archive = diagnostics::create_diagnostics(&app_state)
+        .await
+        .map_err(ApiError::Internal)?;
+    let file_name = format!(
+        "treat-dispenser-diagnostics-{}.tar.gz",
+        backup::backup_timestamp()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        archive,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/restore",
+    tag = "admin",
+    request_body(content = Vec<u8>, description = "Archive from `GET /admin/backup`", content_type = "application/gzip"),
+    responses(
+        (status = 200, description = "Backup restored", body = RestoreResponse),
+        (status = 400, description = "Invalid backup archive", body = String, content_type = "text/plain"),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+    )
+)]
+/// Restores a backup produced by `GET /admin/backup`. The archive is validated before
+/// anything is written and the current data is snapshotted first. The weight sensor
+/// calibration is applied immediately, config changes take effect after a restart.
+pub async fn restore_backup(
+    State(app_state): State<AppStateMutex>,
+    body: Bytes,
+) -> Result<Json<RestoreResponse>, ApiError> {
+    let status = app_state.lock().await.status.clone();
+    if status == DispenserStatus::Dispensing || status == DispenserStatus::Calibrating {
+        return Err(ApiError::Busy(format!(
+            "Cannot restore a backup while the dispenser is {}",
+            status
+        )));
+    }
+
+    let files = backup::validate_backup(&body).map_err(ApiError::BadRequest)?;
+    let (restored_files, snapshot) = backup::apply_backup(&files).map_err(|e| {
+        error!("Backup restore failed: {}", e);
+        ApiError::Internal(e)
+    })?;
+
+    if let Ok(calibration) = weight_monitor::load_calibration_from_file() {
+        let calibration_tx = app_state.lock().await.calibration_tx.clone();
+        let _ = calibration_tx.send(calibration);
+    }
+
+    info!("Restored {} files from backup", restored_files.len());
+    Ok(Json(RestoreResponse {
+        message: "Backup restored, restart the service to apply config changes".to_string(),
+        restored_files,
+        snapshot,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/batch",
+    tag = "admin",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "All operations applied or already in effect", body = BatchResponse),
+        (status = 400, description = "No operations or too many", body = String, content_type = "text/plain"),
+        (status = 422, description = "An operation failed or the config would be invalid, nothing was applied", body = BatchResponse),
+    )
+)]
+/// Runs a list of admin operations in order, all of them or none, and reports the result
+/// of each. Saves round trips for provisioning scripts.
+pub async fn batch(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Json(body): Json<BatchRequest>,
+) -> Result<(StatusCode, Json<BatchResponse>), ApiError> {
+    let response = admin_batch::run_batch(&app_state, &body.operations, &user).await?;
+    let status = match response.applied {
+        true => StatusCode::OK,
+        false => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    Ok((status, Json(response)))
+}
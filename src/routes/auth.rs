@@ -1,19 +1,96 @@
 use crate::application_state;
-use crate::error::ApiError;
-use crate::services::auth::{LoginRequest, LoginResponse, handle_login};
+use crate::config::SessionCookieConfig;
+use crate::error::{ApiError, FieldError};
+use crate::services::auth::{self, LoginRequest, SessionResponse, handle_login};
 use axum::extract::{Json, State};
+use axum::http::{StatusCode, header};
+use axum::response::{AppendHeaders, IntoResponse, Response};
 use tracing::info;
 
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "JWT for the `Authorization: Bearer` header, or with `session_cookie` the CSRF token of the session cookie", body = crate::services::auth::LoginResponse),
+        (status = 401, description = "Wrong username or password"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 429, description = "Too many failed logins"),
+    )
+)]
+/// Returns the JWT in the body, or with `session_cookie` set in an HttpOnly cookie
+/// together with a CSRF token.
 pub async fn login(
     State(app_state): State<application_state::AppStateMutex>,
     Json(payload): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, ApiError> {
-    let result = handle_login(app_state, payload.clone()).await;
-    match result {
-        Ok(response) => {
-            info!("Login successful for user: {}", &payload.username);
-            Ok(Json(response))
-        }
-        Err(e) => Err(e),
+) -> Result<Response, ApiError> {
+    if !payload.session_cookie {
+        let response = handle_login(app_state, payload.clone(), None).await?;
+        info!("Login successful for user: {}", &payload.username);
+        return Ok(Json(response).into_response());
     }
+
+    let cookie_config = session_cookie_config(&app_state).await.ok_or_else(|| {
+        ApiError::Validation(vec![FieldError::new(
+            "session_cookie",
+            "requires api.session_cookies to be enabled",
+        )])
+    })?;
+    let csrf_token = auth::new_csrf_token();
+    let response = handle_login(app_state, payload.clone(), Some(csrf_token.clone())).await?;
+    info!("Cookie login successful for user: {}", &payload.username);
+
+    let [session_cookie, csrf_cookie] =
+        auth::session_cookies(&response.token, &csrf_token, &cookie_config);
+    Ok((
+        AppendHeaders([
+            (header::SET_COOKIE, session_cookie),
+            (header::SET_COOKIE, csrf_cookie),
+        ]),
+        Json(SessionResponse {
+            csrf_token,
+            expires_at: response.expires_at,
+        }),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    responses(
+        (status = 204, description = "Session cookies cleared"),
+        (status = 404, description = "Session cookies are not enabled", body = String, content_type = "text/plain"),
+    )
+)]
+/// Clears the session cookies of a cookie login.
+pub async fn logout(
+    State(app_state): State<application_state::AppStateMutex>,
+) -> Result<impl IntoResponse, ApiError> {
+    let cookie_config = session_cookie_config(&app_state)
+        .await
+        .ok_or_else(|| ApiError::NotFound("Session cookies are not enabled".to_string()))?;
+    let [session_cookie, csrf_cookie] = auth::expired_session_cookies(&cookie_config);
+    Ok((
+        StatusCode::NO_CONTENT,
+        AppendHeaders([
+            (header::SET_COOKIE, session_cookie),
+            (header::SET_COOKIE, csrf_cookie),
+        ]),
+    ))
+}
+
+async fn session_cookie_config(
+    app_state: &application_state::AppStateMutex,
+) -> Option<SessionCookieConfig> {
+    app_state
+        .lock()
+        .await
+        .app_config
+        .api
+        .session_cookies
+        .clone()
+        .filter(|c| c.enabled.unwrap_or(true))
 }
@@ -0,0 +1,74 @@
+use crate::application_state::AppStateMutex;
+use crate::config;
+use crate::error::ApiError;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::services::config_reload::{self, ConfigReloadResponse, ReloadMode};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use serde::Deserialize;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReloadQuery {
+    /// Only check the file and report what would change
+    pub dry_run: Option<bool>,
+    /// Restore the previous config unless `POST /config/confirm` is called in time
+    pub confirm_within_secs: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/schema",
+    tag = "config",
+    responses(
+        (status = 200, description = "JSON Schema of config.yaml", body = Object),
+    )
+)]
+/// Serves the JSON Schema of `config.yaml`.
+pub async fn get_config_schema() -> Json<serde_json::Value> {
+    Json(config::app_config_schema())
+}
+
+#[utoipa::path(
+    post,
+    path = "/config/reload",
+    tag = "config",
+    params(ReloadQuery),
+    responses(
+        (status = 200, description = "Applied and restart-only changes", body = ConfigReloadResponse),
+        (status = 400, description = "The file doesn't parse or a staged config waits for confirmation", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 500, description = "The config file can't be read or parsed", body = String, content_type = "text/plain"),
+    )
+)]
+/// Re-reads `config.yaml` and applies the settings that don't need a restart.
+pub async fn reload_config(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    Query(query): Query<ReloadQuery>,
+) -> Result<Json<ConfigReloadResponse>, ApiError> {
+    let mode = ReloadMode {
+        dry_run: query.dry_run.unwrap_or(false),
+        confirm_within_secs: query.confirm_within_secs,
+    };
+    Ok(Json(config_reload::reload(&app_state, &user, mode).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/config/confirm",
+    tag = "config",
+    responses(
+        (status = 204, description = "Staged config kept"),
+        (status = 404, description = "No staged config is waiting for confirmation", body = String, content_type = "text/plain"),
+    )
+)]
+/// Keeps the config of a staged reload, see `confirm_within_secs` of `POST /config/reload`.
+pub async fn confirm_config(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+) -> Result<StatusCode, ApiError> {
+    config_reload::confirm(&app_state, &user).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,116 @@
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::sensor_debug::{self, CaptureSettings};
+use crate::services::temperature_monitor;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RawCaptureQuery {
+    pub duration_secs: Option<u64>,
+    pub interval_ms: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/debug/weight/raw",
+    tag = "debug",
+    params(RawCaptureQuery),
+    responses(
+        (status = 200, description = "One JSON sample per line", body = String, content_type = "application/x-ndjson"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 500, description = "No weight sensor available", body = String, content_type = "text/plain"),
+    )
+)]
+/// Streams raw HX711 ADC values as NDJSON, bypassing tare, scale and filtering.
+pub async fn stream_raw_weight(
+    State(app_state): State<AppStateMutex>,
+    Query(query): Query<RawCaptureQuery>,
+) -> Result<Response, ApiError> {
+    let settings = CaptureSettings::from_query(query.duration_secs, query.interval_ms)?;
+    let sensor_mutex = app_state
+        .lock()
+        .await
+        .weight_sensor_mutex
+        .clone()
+        .ok_or_else(|| ApiError::Hardware("No weight sensor available".to_string()))?;
+
+    let stream = sensor_debug::stream_raw_samples(settings, move || {
+        let sensor_mutex = Arc::clone(&sensor_mutex);
+        async move { sensor_mutex.lock().await.get_raw() }
+    });
+    Ok(ndjson_response(Body::from_stream(stream)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/debug/power/raw",
+    tag = "debug",
+    params(RawCaptureQuery),
+    responses(
+        (status = 200, description = "One JSON sample per line", body = String, content_type = "application/x-ndjson"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 500, description = "No power sensor available", body = String, content_type = "text/plain"),
+    )
+)]
+/// Streams raw INA219 register values as NDJSON.
+pub async fn stream_raw_power(
+    State(app_state): State<AppStateMutex>,
+    Query(query): Query<RawCaptureQuery>,
+) -> Result<Response, ApiError> {
+    let settings = CaptureSettings::from_query(query.duration_secs, query.interval_ms)?;
+    let sensor_mutex = app_state
+        .lock()
+        .await
+        .power_sensor_mutex
+        .clone()
+        .ok_or_else(|| ApiError::Hardware("No power sensor available".to_string()))?;
+
+    let stream = sensor_debug::stream_raw_samples(settings, move || {
+        let sensor_mutex = Arc::clone(&sensor_mutex);
+        async move { sensor_mutex.lock().await.get_raw() }
+    });
+    Ok(ndjson_response(Body::from_stream(stream)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/debug/temperature/raw",
+    tag = "debug",
+    params(RawCaptureQuery),
+    responses(
+        (status = 200, description = "One JSON sample per line", body = String, content_type = "application/x-ndjson"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 500, description = "No temperature sensor available", body = String, content_type = "text/plain"),
+    )
+)]
+/// Streams temperature sensor readings as NDJSON. A DS18B20 takes up to 750 ms per
+/// read, which bounds the sample rate.
+pub async fn stream_raw_temperature(
+    State(app_state): State<AppStateMutex>,
+    Query(query): Query<RawCaptureQuery>,
+) -> Result<Response, ApiError> {
+    let settings = CaptureSettings::from_query(query.duration_secs, query.interval_ms)?;
+    let sensor_mutex = app_state
+        .lock()
+        .await
+        .temperature_sensor_mutex
+        .clone()
+        .ok_or_else(|| ApiError::Hardware("No temperature sensor available".to_string()))?;
+
+    let stream = sensor_debug::stream_raw_samples(settings, move || {
+        let sensor_mutex = Arc::clone(&sensor_mutex);
+        async move { temperature_monitor::read_blocking(&sensor_mutex).await }
+    });
+    Ok(ndjson_response(Body::from_stream(stream)))
+}
+
+fn ndjson_response(body: Body) -> Response {
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
@@ -1,25 +1,143 @@
 use crate::application_state;
 use crate::error::ApiError;
-use crate::services::dispenser;
-use crate::utils::state_helpers;
+use crate::services::dispenser::{self, DispenseGramsRequest, DispenseRequest};
+use crate::services::trickle::{self, TrickleProgress, TrickleRequest};
+use crate::services::triggers::{self, ApiTrigger};
+use axum::Json;
 use axum::extract::State;
-use std::sync::Arc;
 
+/// Starts a dispense, by default of `motor.dispense_degrees`.
+#[utoipa::path(
+    post,
+    path = "/dispense",
+    tag = "dispense",
+    request_body(content = Option<DispenseRequest>, description = "Optional, without it `motor.dispense_degrees` are dispensed"),
+    responses(
+        (status = 200, description = "Dispensing started", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn dispense_treat(
     State(hw_state): State<application_state::AppStateMutex>,
+    request: Option<Json<DispenseRequest>>,
 ) -> Result<&'static str, ApiError> {
-    let hw_state_clone = Arc::clone(&hw_state);
-
-    match dispenser::dispense(hw_state_clone).await {
-        Ok(_) => (),
-        Err(e) => {
-            state_helpers::record_error(&hw_state, &e).await;
-            return Err(e);
-        }
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    {
+        let state_guard = hw_state.lock().await;
+        request.validate(&state_guard.app_config)?;
+    }
+    let trigger = ApiTrigger {
+        amount: request.amount(),
+        note: request.note(),
     };
+    triggers::request_dispense(&hw_state, &trigger).await?;
     Ok("Dispensing started, please wait...")
 }
 
+/// Starts a dispense that runs until the hopper lost the requested weight.
+#[utoipa::path(
+    post,
+    path = "/dispense/grams",
+    tag = "dispense",
+    request_body = DispenseGramsRequest,
+    responses(
+        (status = 200, description = "Dispensing started", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn dispense_grams(
+    State(hw_state): State<application_state::AppStateMutex>,
+    Json(request): Json<DispenseGramsRequest>,
+) -> Result<&'static str, ApiError> {
+    request.validate()?;
+    triggers::request_dispense(&hw_state, &ApiTrigger::new(request.amount())).await?;
+    Ok("Dispensing started, please wait...")
+}
+
+/// Starts dispensing a weight in small bursts spread over a duration.
+#[utoipa::path(
+    post,
+    path = "/dispense/trickle",
+    tag = "dispense",
+    request_body = TrickleRequest,
+    responses(
+        (status = 200, description = "Trickle dispensing started", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn dispense_trickle(
+    State(hw_state): State<application_state::AppStateMutex>,
+    Json(request): Json<TrickleRequest>,
+) -> Result<&'static str, ApiError> {
+    {
+        let state_guard = hw_state.lock().await;
+        request.validate(&state_guard.app_config)?;
+    }
+    triggers::request_dispense(&hw_state, &ApiTrigger::new(request.amount())).await?;
+    Ok("Trickle dispensing started")
+}
+
+/// Progress of the running trickle dispense.
+#[utoipa::path(
+    get,
+    path = "/dispense/trickle",
+    tag = "dispense",
+    responses(
+        (status = 200, description = "Progress of the running trickle dispense", body = TrickleProgress),
+        (status = 404, description = "No trickle dispense is running", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_trickle(
+    State(hw_state): State<application_state::AppStateMutex>,
+) -> Result<Json<TrickleProgress>, ApiError> {
+    trickle::progress(&hw_state).await.map(Json)
+}
+
+/// Pauses the running trickle dispense after the current burst.
+#[utoipa::path(
+    post,
+    path = "/dispense/trickle/pause",
+    tag = "dispense",
+    responses(
+        (status = 200, description = "Trickle dispense paused", body = TrickleProgress),
+        (status = 404, description = "No trickle dispense is running", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn pause_trickle(
+    State(hw_state): State<application_state::AppStateMutex>,
+) -> Result<Json<TrickleProgress>, ApiError> {
+    trickle::set_paused(&hw_state, true).await.map(Json)
+}
+
+/// Resumes a paused trickle dispense.
+#[utoipa::path(
+    post,
+    path = "/dispense/trickle/resume",
+    tag = "dispense",
+    responses(
+        (status = 200, description = "Trickle dispense resumed", body = TrickleProgress),
+        (status = 404, description = "No trickle dispense is running", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn resume_trickle(
+    State(hw_state): State<application_state::AppStateMutex>,
+) -> Result<Json<TrickleProgress>, ApiError> {
+    trickle::set_paused(&hw_state, false).await.map(Json)
+}
+
+/// Cancels the running dispense.
+#[utoipa::path(
+    post,
+    path = "/cancel",
+    tag = "dispense",
+    responses(
+        (status = 200, description = "Dispensing cancelled", body = String, content_type = "text/plain"),
+        (status = 500, description = "No dispense is running", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn cancel_dispense(
     State(hw_state): State<application_state::AppStateMutex>,
 ) -> Result<&'static str, ApiError> {
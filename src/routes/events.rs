@@ -0,0 +1,56 @@
+use crate::application_state::ApplicationState;
+use crate::services::events::DispenserEvent;
+use axum::Json;
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "status",
+    responses(
+        (status = 200, description = "Recent events, oldest first", body = Vec<DispenserEvent>),
+    )
+)]
+/// Returns the most recent dispenser events, oldest first.
+pub async fn get_events(
+    State(state): State<Arc<Mutex<ApplicationState>>>,
+) -> Json<Vec<DispenserEvent>> {
+    let event_bus = state.lock().await.event_bus.clone();
+    Json(event_bus.recent())
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/stream",
+    tag = "status",
+    responses(
+        (status = 200, description = "Server-Sent Events, a `DispenserEvent` as JSON per message", body = String, content_type = "text/event-stream"),
+    )
+)]
+/// Streams dispenser events as Server-Sent Events as they are published. A client that
+/// falls too far behind gets a `lagged` event with the number of events it missed, which it
+/// can catch up on with `GET /events`.
+pub async fn stream_events(
+    State(state): State<Arc<Mutex<ApplicationState>>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events_rx = state.lock().await.event_bus.subscribe();
+    let stream = futures::stream::unfold(events_rx, |mut events_rx| async move {
+        let event = match events_rx.recv().await {
+            Ok(event) => Event::default()
+                .json_data(&event)
+                .unwrap_or_else(|e| Event::default().comment(e.to_string())),
+            Err(RecvError::Lagged(missed)) => {
+                Event::default().event("lagged").data(missed.to_string())
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), events_rx))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
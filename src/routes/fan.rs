@@ -0,0 +1,35 @@
+use axum::Json;
+use axum::extract::State;
+use serde::Deserialize;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::fan::{self, FanMode, FanStatus};
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct SetFanModeRequest {
+    pub mode: FanMode,
+}
+
+#[utoipa::path(
+    post,
+    path = "/fan",
+    tag = "hardware",
+    request_body = SetFanModeRequest,
+    responses(
+        (status = 200, description = "Fan state after the change", body = FanStatus),
+        (status = 404, description = "No fan configured", body = String, content_type = "text/plain"),
+        (status = 500, description = "The fan couldn't be switched", body = String, content_type = "text/plain"),
+    )
+)]
+/// Overrides the fan (`on`, `off`) or hands it back to the temperature controller (`auto`).
+pub async fn set_fan_mode(
+    State(app_state): State<AppStateMutex>,
+    Json(request): Json<SetFanModeRequest>,
+) -> Result<Json<FanStatus>, ApiError> {
+    match fan::set_fan_mode(&app_state, request.mode).await {
+        Ok(Some(status)) => Ok(Json(status)),
+        Ok(None) => Err(ApiError::NotFound("No fan configured".to_string())),
+        Err(e) => Err(ApiError::Hardware(e)),
+    }
+}
@@ -0,0 +1,120 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use chrono::Local;
+use serde::Deserialize;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::history::{self, HistoryEntry, HistoryPage, HistoryUpdate, Pagination};
+use crate::services::history_import::{self, ImportSummary};
+use crate::services::stats;
+use crate::services::weight_history::{self, TimelineRange, WeightTimeline};
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+    pub include_archived: Option<bool>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/history",
+    tag = "history",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "One page of dispense attempts", body = HistoryPage),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+    )
+)]
+/// Lists recorded dispense attempts, newest first.
+pub async fn get_history(Query(query): Query<HistoryQuery>) -> Result<Json<HistoryPage>, ApiError> {
+    let pagination = Pagination::from_query(query.page, query.per_page)?;
+    let include_archived = query.include_archived.unwrap_or(false);
+    Ok(Json(history::list(pagination, include_archived).await?))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/history/{id}",
+    tag = "history",
+    request_body = HistoryUpdate,
+    params(("id" = i64, Path, description = "Id of the history entry")),
+    responses(
+        (status = 200, description = "The updated entry", body = HistoryEntry),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 404, description = "No entry with this id", body = String, content_type = "text/plain"),
+    )
+)]
+/// Annotates, archives or restores a dispense attempt. Archiving takes a completed
+/// dispense out of the stats and restoring puts it back.
+pub async fn update_history_entry(
+    State(app_state): State<AppStateMutex>,
+    Path(id): Path<i64>,
+    Json(update): Json<HistoryUpdate>,
+) -> Result<Json<HistoryEntry>, ApiError> {
+    update.validate()?;
+    let (before, after) = history::update(id, update).await?;
+    if before.archived != after.archived {
+        stats::set_excluded(&app_state, &after).await;
+    }
+    Ok(Json(after))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    pub day_first: Option<bool>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/history/import",
+    tag = "history",
+    request_body(content = String, description = "CSV export of another feeder", content_type = "text/csv"),
+    params(ImportQuery),
+    responses(
+        (status = 200, description = "Imported and skipped rows", body = ImportSummary),
+        (status = 400, description = "The CSV can't be read", body = String, content_type = "text/plain"),
+    )
+)]
+/// Imports feeding history exported as CSV from another feeder, the file is the body.
+pub async fn import_history(
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<ImportSummary>, ApiError> {
+    let day_first = query.day_first.unwrap_or(false);
+    Ok(Json(history_import::import(body, day_first).await?))
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WeightHistoryQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub interval_minutes: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/weight/history",
+    tag = "history",
+    params(WeightHistoryQuery),
+    responses(
+        (status = 200, description = "Averaged hopper weights", body = WeightTimeline),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+    )
+)]
+/// Recorded hopper weight averages over a time range, oldest first.
+pub async fn get_weight_history(
+    Query(query): Query<WeightHistoryQuery>,
+) -> Result<Json<WeightTimeline>, ApiError> {
+    let range = TimelineRange::from_query(
+        query.from.as_deref(),
+        query.to.as_deref(),
+        query.interval_minutes,
+        Local::now(),
+    )?;
+    Ok(Json(weight_history::timeline(range).await?))
+}
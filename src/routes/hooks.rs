@@ -0,0 +1,59 @@
+use crate::application_state::AppStateMutex;
+use crate::config::HookConfig;
+use crate::error::ApiError;
+use crate::services::triggers;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use serde::Deserialize;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HookQuery {
+    pub token: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/hooks/dispense",
+    tag = "integrations",
+    params(HookQuery, ("X-Hook-Token" = Option<String>, Header, description = "Hook token, instead of `?token=`")),
+    responses(
+        (status = 200, description = "Dispensing started", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or unknown hook token"),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+    )
+)]
+/// Dispenses a treat for a webhook service. Authenticates with a per-hook token passed
+/// as `?token=` or in the `X-Hook-Token` header, and enforces the hook's minimum interval.
+pub async fn dispense_hook(
+    State(app_state): State<AppStateMutex>,
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+) -> Result<String, ApiError> {
+    let token = headers
+        .get("x-hook-token")
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_string)
+        .or(query.token)
+        .ok_or(ApiError::Unauthorized)?;
+
+    let hook: HookConfig = {
+        let state_guard = app_state.lock().await;
+        state_guard
+            .app_config
+            .hooks
+            .iter()
+            .flatten()
+            .find(|hook| constant_time_eq(hook.token.as_bytes(), token.as_bytes()))
+            .cloned()
+            .ok_or(ApiError::Unauthorized)?
+    };
+
+    triggers::request_dispense(&app_state, &hook).await?;
+    Ok(format!("Dispensing started by hook '{}'", hook.name))
+}
+
+/// Compares secrets without leaking the position of the first difference through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,60 @@
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::assistant;
+use axum::Json;
+use axum::extract::State;
+use axum::http::{HeaderMap, header};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[utoipa::path(
+    post,
+    path = "/integrations/assistant",
+    tag = "integrations",
+    request_body(content = Object, description = "Google smart home intent or Alexa directive"),
+    responses(
+        (status = 200, description = "Smart home response", body = Object),
+        (status = 401, description = "Invalid linked-account token"),
+        (status = 404, description = "Assistant integration is not configured", body = String, content_type = "text/plain"),
+    )
+)]
+/// Smart home fulfillment webhook for Google Assistant and Alexa. Authenticates with the
+/// linked-account tokens from `integrations.assistant`, not with the API's JWT.
+pub async fn assistant_fulfillment(
+    State(app_state): State<AppStateMutex>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let assistant_config = app_state
+        .lock()
+        .await
+        .app_config
+        .integrations
+        .as_ref()
+        .and_then(|i| i.assistant.clone())
+        .ok_or_else(|| ApiError::NotFound("Assistant integration is not configured".to_string()))?;
+
+    // Alexa directives carry the token in the body, Google sends it as a bearer token
+    if body.get("directive").is_some() {
+        let request = serde_json::from_value(body)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid Alexa directive: {}", e)))?;
+        let response =
+            assistant::handle_alexa(Arc::clone(&app_state), &assistant_config, request).await;
+        return Ok(Json(response));
+    }
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !assistant::is_valid_token(&assistant_config, token) {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let request = serde_json::from_value(body)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid smart home request: {}", e)))?;
+    assistant::handle_google(Arc::clone(&app_state), &assistant_config, request)
+        .await
+        .map(Json)
+}
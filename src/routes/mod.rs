@@ -1,10 +1,39 @@
+pub mod admin;
 pub mod auth;
+pub mod config;
+pub mod debug;
 pub mod dispense;
+pub mod events;
+pub mod fan;
+pub mod history;
+pub mod hooks;
+pub mod integrations;
+pub mod notifications;
+pub mod openapi;
+pub mod outputs;
+pub mod power;
+pub mod route_table;
+pub mod schedules;
 pub mod sensors;
+pub mod share;
+pub mod stats;
 pub mod status;
+pub mod system;
+pub mod training;
+pub mod webhooks;
+pub mod ws;
 
 use axum::response::IntoResponse;
 
+/// Checks that the API is online.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "status",
+    responses(
+        (status = 200, description = "Greeting", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn root() -> impl IntoResponse {
     "Treat dispenser is online! Binky time!"
 }
@@ -0,0 +1,56 @@
+use crate::error::ApiError;
+use crate::services::push_notifications::{self, DeviceRegistration};
+use axum::Json;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use serde::Deserialize;
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct RegisterDeviceRequest {
+    pub token: String,
+    pub platform: Option<String>,
+}
+
+/// Registers a device for push notifications.
+#[utoipa::path(
+    post,
+    path = "/notifications/devices",
+    tag = "notifications",
+    request_body = RegisterDeviceRequest,
+    responses(
+        (status = 200, description = "Device registered", body = DeviceRegistration),
+        (status = 400, description = "Empty token", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn register_device(
+    Json(request): Json<RegisterDeviceRequest>,
+) -> Result<Json<DeviceRegistration>, ApiError> {
+    push_notifications::register_device(&request.token, request.platform)
+        .map(Json)
+        .map_err(|e| {
+            if request.token.trim().is_empty() {
+                ApiError::BadRequest(e)
+            } else {
+                ApiError::Internal(e)
+            }
+        })
+}
+
+/// Stops push notifications to a device.
+#[utoipa::path(
+    delete,
+    path = "/notifications/devices/{token}",
+    tag = "notifications",
+    params(("token" = String, Path, description = "Push token of the device")),
+    responses(
+        (status = 204, description = "Device unregistered"),
+        (status = 404, description = "Device is not registered", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn unregister_device(Path(token): Path<String>) -> Result<StatusCode, ApiError> {
+    match push_notifications::unregister_device(&token) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::NotFound("Device is not registered".to_string())),
+        Err(e) => Err(ApiError::Internal(e)),
+    }
+}
@@ -0,0 +1,143 @@
+use axum::{Extension, Json};
+use std::sync::Arc;
+use utoipa::OpenApi;
+use utoipa::openapi::path::{Operation, PathItem};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::routes::route_table::{RouteAuth, RouteInfo};
+use crate::routes::{
+    admin, auth, config, debug, dispense, events, fan, history, hooks, integrations, notifications,
+    outputs, power, route_table, schedules, sensors, share, stats, status, system, training,
+    webhooks, ws,
+};
+
+/// Name of the security scheme of the routes that need the JWT from `POST /login`.
+const BEARER_SCHEME: &str = "bearer_token";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Treat Dispenser API",
+        license(name = "MIT"),
+        description = "Controls a pet treat dispenser. Log in with `POST /login` and send \
+            the token as `Authorization: Bearer <token>`. Errors are plain text, except \
+            422 responses, which list the invalid fields."
+    ),
+    paths(
+        crate::routes::root,
+        auth::login,
+        auth::logout,
+        status::detailed_health,
+        status::wait_for_status,
+        status::summary,
+        config::get_config_schema,
+        route_table::list_routes,
+        integrations::assistant_fulfillment,
+        hooks::dispense_hook,
+        share::shared_status,
+        dispense::dispense_treat,
+        dispense::dispense_grams,
+        dispense::dispense_trickle,
+        dispense::get_trickle,
+        dispense::pause_trickle,
+        dispense::resume_trickle,
+        dispense::cancel_dispense,
+        training::get_training,
+        training::start_training,
+        training::stop_training,
+        sensors::tare_weight_sensor,
+        sensors::calibrate_weight_sensor,
+        events::get_events,
+        events::stream_events,
+        stats::get_stats,
+        history::get_history,
+        history::import_history,
+        history::update_history_entry,
+        history::get_weight_history,
+        schedules::export_schedules,
+        schedules::import_schedules,
+        share::create_share,
+        share::list_shares,
+        share::revoke_share,
+        config::reload_config,
+        config::confirm_config,
+        admin::get_log_level,
+        admin::set_log_level,
+        notifications::register_device,
+        notifications::unregister_device,
+        webhooks::get_deliveries,
+        admin::download_backup,
+        admin::restore_backup,
+        admin::download_diagnostics,
+        admin::batch,
+        fan::set_fan_mode,
+        outputs::set_output,
+        power::disable_overcurrent_protection,
+        power::enable_overcurrent_protection,
+        power::calibrate_current_limit,
+        system::i2c_scan,
+        ws::stream_weight,
+        debug::stream_raw_weight,
+        debug::stream_raw_power,
+        debug::stream_raw_temperature,
+    )
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document with the security requirements taken from the route table, so
+/// they match what the auth middleware enforces.
+pub fn openapi_document(routes: &[RouteInfo]) -> utoipa::openapi::OpenApi {
+    let mut document = ApiDoc::openapi();
+    document
+        .components
+        .get_or_insert_with(Default::default)
+        .add_security_scheme(
+            BEARER_SCHEME,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+
+    for route in routes.iter().filter(|r| r.auth == RouteAuth::BearerToken) {
+        let Some(path_item) = document.paths.paths.get_mut(&route.path) else {
+            continue;
+        };
+        for method in &route.methods {
+            if let Some(operation) = operation_mut(path_item, method) {
+                operation.security = Some(vec![SecurityRequirement::new(
+                    BEARER_SCHEME,
+                    Vec::<String>::new(),
+                )]);
+            }
+        }
+    }
+    document
+}
+
+fn operation_mut<'a>(path_item: &'a mut PathItem, method: &str) -> Option<&'a mut Operation> {
+    match method {
+        "GET" => path_item.get.as_mut(),
+        "POST" => path_item.post.as_mut(),
+        "PUT" => path_item.put.as_mut(),
+        "PATCH" => path_item.patch.as_mut(),
+        "DELETE" => path_item.delete.as_mut(),
+        _ => None,
+    }
+}
+
+/// Serves the OpenAPI document, a machine-readable contract of the API for client developers.
+pub async fn get_openapi(
+    Extension(routes): Extension<Arc<Vec<RouteInfo>>>,
+) -> Json<utoipa::openapi::OpenApi> {
+    Json(openapi_document(&routes))
+}
+
+/// Swagger UI at `/docs`, reading the document from `/openapi.json`. The URL is relative, so
+/// it still works when the API is nested under a prefix.
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/docs").config(Config::from("../openapi.json"))
+}
@@ -0,0 +1,40 @@
+use axum::Json;
+use axum::extract::{Path, State};
+use serde::Deserialize;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::digital_outputs::{self, DigitalOutputState};
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct SetOutputRequest {
+    pub on: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/outputs/{label}",
+    tag = "hardware",
+    request_body = SetOutputRequest,
+    params(("label" = String, Path, description = "Label of the output in `digital_outputs`")),
+    responses(
+        (status = 200, description = "Output state after the change", body = DigitalOutputState),
+        (status = 404, description = "No output with this label", body = String, content_type = "text/plain"),
+        (status = 500, description = "The output couldn't be switched", body = String, content_type = "text/plain"),
+    )
+)]
+/// Switches one of the outputs configured under `digital_outputs`.
+pub async fn set_output(
+    State(app_state): State<AppStateMutex>,
+    Path(label): Path<String>,
+    Json(request): Json<SetOutputRequest>,
+) -> Result<Json<DigitalOutputState>, ApiError> {
+    match digital_outputs::set_output(&app_state, &label, request.on).await {
+        Ok(Some(state)) => Ok(Json(state)),
+        Ok(None) => Err(ApiError::NotFound(format!(
+            "No output configured with label '{}'",
+            label
+        ))),
+        Err(e) => Err(ApiError::Hardware(e)),
+    }
+}
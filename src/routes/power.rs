@@ -0,0 +1,99 @@
+use axum::extract::State;
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::application_state::AppStateMutex;
+use crate::config;
+use crate::error::{ApiError, FieldError};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::services::current_calibration::{
+    self, CurrentCalibrationRequest, CurrentCalibrationResponse,
+};
+use crate::services::power_monitor::{self, OvercurrentProtectionStatus};
+
+#[derive(Deserialize, Default, utoipa::ToSchema)]
+pub struct DisableOvercurrentRequest {
+    /// Defaults to `OVERCURRENT_DISABLE_SECS_DEFAULT`
+    pub duration_secs: Option<u64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/power/overcurrent/disable",
+    tag = "power",
+    request_body(content = Option<DisableOvercurrentRequest>, description = "Optional"),
+    responses(
+        (status = 200, description = "Protection disabled", body = OvercurrentProtectionStatus),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+    )
+)]
+/// Stops high current from cancelling motor runs for a while, for maintenance under load.
+pub async fn disable_overcurrent_protection(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    request: Option<Json<DisableOvercurrentRequest>>,
+) -> Result<Json<OvercurrentProtectionStatus>, ApiError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let duration_secs = request
+        .duration_secs
+        .unwrap_or(config::OVERCURRENT_DISABLE_SECS_DEFAULT);
+    if !(1..=config::OVERCURRENT_DISABLE_SECS_MAX).contains(&duration_secs) {
+        return Err(ApiError::Validation(vec![FieldError::new(
+            "duration_secs",
+            format!(
+                "must be between 1 and {}",
+                config::OVERCURRENT_DISABLE_SECS_MAX
+            ),
+        )]));
+    }
+    Ok(Json(
+        power_monitor::disable_overcurrent_protection(
+            &app_state,
+            Duration::from_secs(duration_secs),
+            &user,
+        )
+        .await,
+    ))
+}
+
+/// Turns overcurrent protection back on before the disable period ends.
+#[utoipa::path(
+    post,
+    path = "/power/overcurrent/enable",
+    tag = "power",
+    responses(
+        (status = 200, description = "Protection enabled", body = OvercurrentProtectionStatus),
+    )
+)]
+pub async fn enable_overcurrent_protection(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+) -> Json<OvercurrentProtectionStatus> {
+    Json(power_monitor::enable_overcurrent_protection(&app_state, &user).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/power/current-calibration",
+    tag = "power",
+    request_body(content = Option<CurrentCalibrationRequest>, description = "Optional"),
+    responses(
+        (status = 200, description = "Measured current and recommended limit", body = CurrentCalibrationResponse),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+        (status = 500, description = "No power sensor or the motor failed", body = String, content_type = "text/plain"),
+    )
+)]
+/// Measures the unloaded motor current and recommends, optionally applies, a current limit.
+pub async fn calibrate_current_limit(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    request: Option<Json<CurrentCalibrationRequest>>,
+) -> Result<Json<CurrentCalibrationResponse>, ApiError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    request.validate(&app_state.lock().await.app_config)?;
+    Ok(Json(
+        current_calibration::calibrate_current_limit(&app_state, &request, &user).await?,
+    ))
+}
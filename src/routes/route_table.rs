@@ -0,0 +1,116 @@
+use axum::handler::Handler;
+use axum::http::Method;
+use axum::routing::{MethodFilter, MethodRouter};
+use axum::{Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// How a route is authenticated.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteAuth {
+    None,
+    /// JWT from `POST /login` as a bearer token
+    BearerToken,
+    /// Linked-account tokens from `integrations.assistant`
+    AssistantToken,
+    /// Per-hook tokens from `hooks`
+    HookToken,
+    /// Read-only status link tokens from `POST /share`, part of the path
+    ShareToken,
+}
+
+/// A registered route, listed by `GET /routes`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct RouteInfo {
+    pub path: String,
+    pub methods: Vec<String>,
+    pub auth: RouteAuth,
+}
+
+/// Router builder that records every route it registers, so `GET /routes` can't drift
+/// from what is actually served.
+pub struct RouteTable<S> {
+    router: Router<S>,
+    routes: Vec<RouteInfo>,
+    auth: RouteAuth,
+}
+
+impl<S: Clone + Send + Sync + 'static> RouteTable<S> {
+    /// Routes added to this table use `auth` unless registered with `route_with_auth`.
+    pub fn new(auth: RouteAuth) -> Self {
+        RouteTable {
+            router: Router::new(),
+            routes: Vec::new(),
+            auth,
+        }
+    }
+
+    pub fn route<H, T>(self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        let auth = self.auth;
+        self.route_with_auth(method, path, handler, auth)
+    }
+
+    /// Registers a route that authenticates requests itself instead of using the table's auth.
+    pub fn route_with_auth<H, T>(
+        mut self,
+        method: Method,
+        path: &str,
+        handler: H,
+        auth: RouteAuth,
+    ) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(method.clone())
+            .unwrap_or_else(|e| panic!("Unsupported method for {}: {}", path, e));
+        self.router = self
+            .router
+            .route(path, MethodRouter::new().on(filter, handler));
+
+        match self.routes.iter_mut().find(|r| r.path == path) {
+            Some(route) => route.methods.push(method.to_string()),
+            None => self.routes.push(RouteInfo {
+                path: path.to_string(),
+                methods: vec![method.to_string()],
+                auth,
+            }),
+        }
+        self
+    }
+
+    /// Merges a router that serves GET requests below `path` itself, e.g. the Swagger UI.
+    pub fn merge(mut self, path: &str, router: Router<S>) -> Self {
+        self.router = self.router.merge(router);
+        self.routes.push(RouteInfo {
+            path: path.to_string(),
+            methods: vec![Method::GET.to_string()],
+            auth: self.auth,
+        });
+        self
+    }
+
+    pub fn into_parts(self) -> (Router<S>, Vec<RouteInfo>) {
+        (self.router, self.routes)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/routes",
+    tag = "status",
+    responses(
+        (status = 200, description = "Every route with its methods and authentication", body = Vec<RouteInfo>),
+    )
+)]
+/// Lists all registered routes with their methods and authentication.
+pub async fn list_routes(
+    Extension(routes): Extension<Arc<Vec<RouteInfo>>>,
+) -> Json<Vec<RouteInfo>> {
+    Json(routes.as_ref().clone())
+}
@@ -0,0 +1,81 @@
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::services::schedule_export::{self, ScheduleFormat, ScheduleImportSummary};
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScheduleExportQuery {
+    /// `json` or `yaml`, takes precedence over the `Accept` header
+    pub format: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules/export",
+    tag = "schedules",
+    params(ScheduleExportQuery),
+    responses(
+        (status = 200, description = "All feeding schedules, as JSON or YAML", body = Object),
+    )
+)]
+/// Serves every feeding schedule as one document, YAML if asked for with `Accept` or
+/// `?format=yaml`. `PUT` takes the same document back.
+pub async fn export_schedules(
+    State(app_state): State<AppStateMutex>,
+    Query(query): Query<ScheduleExportQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let format = ScheduleFormat::from_media_type(query.format.as_deref().or_else(|| {
+        headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+    }));
+    let set = schedule_export::export(&app_state).await;
+    let document = format.serialize(&set).map_err(ApiError::Internal)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::VARY, header::ACCEPT.as_str()),
+        ],
+        document,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/schedules/export",
+    tag = "schedules",
+    request_body(content = Object, description = "All feeding schedules, as JSON or YAML depending on `Content-Type`"),
+    responses(
+        (status = 200, description = "Schedules saved and the scheduler restarted", body = ScheduleImportSummary),
+        (status = 400, description = "The document can't be read", body = String, content_type = "text/plain"),
+        (status = 422, description = "Invalid schedules, nothing was saved", body = crate::error::ValidationErrorBody),
+    )
+)]
+/// Replaces every feeding schedule with the ones in the body, e.g. from a plan kept in git.
+/// They take the place of the `schedules` section of `config.yaml` from then on.
+pub async fn import_schedules(
+    State(app_state): State<AppStateMutex>,
+    Extension(AuthenticatedUser(user)): Extension<AuthenticatedUser>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ScheduleImportSummary>, ApiError> {
+    let format = ScheduleFormat::from_media_type(
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok()),
+    );
+    let set = format
+        .deserialize(&body)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid schedules document: {}", e)))?;
+    Ok(Json(schedule_export::import(&app_state, set, &user).await?))
+}
@@ -7,6 +7,17 @@ use crate::utils::state_helpers;
 use axum::Json;
 use axum::extract::State;
 
+/// Sets the current weight on the load cell as zero.
+#[utoipa::path(
+    post,
+    path = "/tare",
+    tag = "sensors",
+    responses(
+        (status = 200, description = "Weight sensor tared", body = CalibrationResponse),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+        (status = 500, description = "No weight sensor or it failed", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn tare_weight_sensor(
     State(app_state): State<application_state::AppStateMutex>,
 ) -> Result<Json<CalibrationResponse>, ApiError> {
@@ -18,15 +29,29 @@ pub async fn tare_weight_sensor(
         Ok(response) => Ok(Json(response)),
         Err(e) => {
             state_helpers::record_error(&app_state, &e).await;
-            Err(ApiError::Hardware(e))
+            Err(e)
         }
     }
 }
 
+/// Calibrates the scale with a known mass placed on the tared load cell.
+#[utoipa::path(
+    post,
+    path = "/calibrate",
+    tag = "sensors",
+    request_body = weight_monitor::CalibrationRequest,
+    responses(
+        (status = 200, description = "Weight sensor calibrated", body = CalibrationResponse),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 503, description = "The dispenser is busy or cooling down", body = String, content_type = "text/plain"),
+        (status = 500, description = "No weight sensor or it failed", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn calibrate_weight_sensor(
     State(app_state): State<application_state::AppStateMutex>,
     Json(request): Json<weight_monitor::CalibrationRequest>,
 ) -> Result<Json<CalibrationResponse>, ApiError> {
+    request.validate()?;
     let app_state = Arc::clone(&app_state);
 
     let calibration_result =
@@ -37,7 +62,7 @@ pub async fn calibrate_weight_sensor(
         Ok(response) => Ok(Json(response)),
         Err(e) => {
             state_helpers::record_error(&app_state, &e).await;
-            Err(ApiError::Hardware(e))
+            Err(e)
         }
     }
 }
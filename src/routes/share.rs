@@ -0,0 +1,106 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use serde::Deserialize;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::share::{self, CreateShareRequest, CreatedShare, ShareInfo};
+
+#[utoipa::path(
+    post,
+    path = "/share",
+    tag = "share",
+    request_body = CreateShareRequest,
+    responses(
+        (status = 200, description = "The link and its token, shown only once", body = CreatedShare),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+    )
+)]
+/// Mints a read-only status link, e.g. for a pet-sitter.
+pub async fn create_share(
+    Json(request): Json<CreateShareRequest>,
+) -> Result<Json<CreatedShare>, ApiError> {
+    request.validate()?;
+    Ok(Json(share::create(request)?))
+}
+
+/// Lists the active status links, without their tokens.
+#[utoipa::path(
+    get,
+    path = "/share",
+    tag = "share",
+    responses(
+        (status = 200, description = "Active status links", body = Vec<ShareInfo>),
+    )
+)]
+pub async fn list_shares() -> Json<Vec<ShareInfo>> {
+    Json(share::list())
+}
+
+/// Revokes a status link.
+#[utoipa::path(
+    delete,
+    path = "/share/{id}",
+    tag = "share",
+    params(("id" = String, Path, description = "Id of the status link")),
+    responses(
+        (status = 204, description = "Status link revoked"),
+        (status = 404, description = "No status link with this id", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn revoke_share(Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    if share::revoke(&id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!("No status link {}", id)))
+    }
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SharedStatusQuery {
+    /// `json` or `html`, otherwise chosen by the `Accept` header
+    pub format: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/shared/{token}",
+    tag = "share",
+    params(("token" = String, Path, description = "Token of the status link"), SharedStatusQuery),
+    responses(
+        (status = 200, description = "Status page as HTML or JSON", body = share::SharedStatus),
+        (status = 404, description = "Status link not found or expired", body = String, content_type = "text/plain"),
+    )
+)]
+/// Status page of a status link, authenticated by the token in the path. HTML for
+/// browsers, JSON for `?format=json` or an `Accept: application/json` header.
+pub async fn shared_status(
+    State(app_state): State<AppStateMutex>,
+    Path(token): Path<String>,
+    Query(query): Query<SharedStatusQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if share::find(&token).is_none() {
+        return Err(ApiError::NotFound(
+            "Status link not found or expired".to_string(),
+        ));
+    }
+
+    let shared = share::get_shared_status(&app_state).await;
+    let wants_json = match query.format.as_deref() {
+        Some(format) => format == "json",
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/json")),
+    };
+    let no_store = [(header::CACHE_CONTROL, "no-store")];
+    if wants_json {
+        Ok((no_store, Json(shared)).into_response())
+    } else {
+        Ok((no_store, Html(share::render_html(&shared))).into_response())
+    }
+}
@@ -0,0 +1,45 @@
+use crate::application_state::ApplicationState;
+use crate::services::bowl::BowlStats;
+use crate::services::humidity::HumidityStats;
+use crate::services::stats::DispenseStats;
+use axum::Json;
+use axum::extract::State;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub dispense: DispenseStats,
+    /// Hopper humidity and its trend, with a humidity-capable temperature sensor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<HumidityStats>,
+    /// Meals eaten from the bowl, with a bowl load cell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bowl: Option<BowlStats>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "history",
+    responses(
+        (status = 200, description = "Dispense, humidity and bowl statistics", body = StatsResponse),
+    )
+)]
+/// Returns dispense totals, including estimated piece counts, the humidity trend and bowl meals.
+pub async fn get_stats(
+    State(state): State<Arc<Mutex<ApplicationState>>>,
+) -> Json<StatsResponse> {
+    let state_guard = state.lock().await;
+    Json(StatsResponse {
+        dispense: state_guard.dispense_stats.clone(),
+        humidity: state_guard.humidity.stats(),
+        bowl: state_guard
+            .app_config
+            .bowl
+            .as_ref()
+            .map(|_| state_guard.bowl_stats.clone()),
+    })
+}
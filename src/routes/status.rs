@@ -1,13 +1,142 @@
+use crate::config;
 use crate::services::status;
 use crate::application_state::ApplicationState;
-use axum::extract::State;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Response;
 use axum::{Json, response::IntoResponse};
+use serde::Deserialize;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+#[utoipa::path(
+    get,
+    path = "/summary",
+    tag = "status",
+    responses(
+        (status = 200, description = "Compact status", body = status::SummaryResponse),
+    )
+)]
+/// Compact status for displays and widgets.
+pub async fn summary(
+    State(hw_state): State<Arc<Mutex<ApplicationState>>>,
+) -> Json<status::SummaryResponse> {
+    Json(status::get_summary(&hw_state).await)
+}
+
+/// The version of the status response asked for in the `Accept` header.
+fn requested_version(headers: &HeaderMap) -> Result<status::StatusVersion, (StatusCode, String)> {
+    let accept = headers.get(header::ACCEPT).and_then(|a| a.to_str().ok());
+    status::StatusVersion::from_accept(accept).map_err(|e| (StatusCode::NOT_ACCEPTABLE, e))
+}
+
+/// The status in the requested version. A versioned media type in `Accept` is echoed as the
+/// content type, other requests get `application/json`.
+fn status_response(
+    headers: &HeaderMap,
+    version: status::StatusVersion,
+    status_response: status::StatusResponse,
+    etag: String,
+) -> Response {
+    let versioned = headers
+        .get(header::ACCEPT)
+        .and_then(|a| a.to_str().ok())
+        .is_some_and(|accept| accept.contains(version.media_type()));
+    let content_type = if versioned {
+        version.media_type()
+    } else {
+        "application/json"
+    };
+    (
+        [
+            (header::ETAG, etag),
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::VARY, header::ACCEPT.to_string()),
+        ],
+        Json(status::VersionedStatusResponse::new(status_response, version)),
+    )
+        .into_response()
+}
+
+/// Full status of the dispenser and its hardware.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "status",
+    responses(
+        (status = 200, description = "Full status, with an `ETag` header for `/status/wait`. Version 1 unless another is asked for in `Accept`", content(
+            (status::StatusResponseV1 = "application/json"),
+            (status::StatusResponseV1 = "application/vnd.treat-dispenser.status.v1+json"),
+            (status::StatusResponseV2 = "application/vnd.treat-dispenser.status.v2+json"),
+            (status::StatusResponseV3 = "application/vnd.treat-dispenser.status.v3+json"),
+        )),
+        (status = 406, description = "Unsupported status version", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn detailed_health(
     State(hw_state): State<Arc<Mutex<ApplicationState>>>,
-) -> impl IntoResponse {
-    let status_response = status::get_status(&hw_state).await;
-    Json(status_response)
+    headers: HeaderMap,
+) -> Response {
+    let version = match requested_version(&headers) {
+        Ok(version) => version,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let status = status::get_status(&hw_state).await;
+    let etag = status::status_etag(&status);
+    status_response(&headers, version, status, etag)
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WaitQuery {
+    /// ETag from a previous `/status` or `/status/wait` response
+    pub since: Option<String>,
+    pub timeout_secs: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/status/wait",
+    tag = "status",
+    params(WaitQuery),
+    responses(
+        (status = 200, description = "The status changed, in the version asked for in `Accept` like `/status`", content(
+            (status::StatusResponseV1 = "application/json"),
+            (status::StatusResponseV1 = "application/vnd.treat-dispenser.status.v1+json"),
+            (status::StatusResponseV2 = "application/vnd.treat-dispenser.status.v2+json"),
+            (status::StatusResponseV3 = "application/vnd.treat-dispenser.status.v3+json"),
+        )),
+        (status = 304, description = "No change before the timeout"),
+        (status = 406, description = "Unsupported status version", body = String, content_type = "text/plain"),
+    )
+)]
+/// Long-polls the status: responds as soon as the ETag differs from `since`, or with
+/// `304 Not Modified` once the timeout elapses without a change.
+pub async fn wait_for_status(
+    State(hw_state): State<Arc<Mutex<ApplicationState>>>,
+    Query(query): Query<WaitQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let version = match requested_version(&headers) {
+        Ok(version) => version,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let timeout = Duration::from_secs(
+        query
+            .timeout_secs
+            .unwrap_or(config::STATUS_WAIT_TIMEOUT_SECS_DEFAULT)
+            .min(config::STATUS_WAIT_TIMEOUT_SECS_MAX),
+    );
+
+    let Some(since) = query.since else {
+        let status = status::get_status(&hw_state).await;
+        let etag = status::status_etag(&status);
+        return status_response(&headers, version, status, etag);
+    };
+
+    match status::wait_for_status_change(&hw_state, &since, timeout).await {
+        (status, etag, true) => status_response(&headers, version, status, etag),
+        (_, etag, false) => (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response(),
+    }
 }
@@ -0,0 +1,37 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::application_state::AppStateMutex;
+use crate::config;
+use crate::error::ApiError;
+use crate::services::i2c_scan::{self, I2cScanResponse};
+
+#[utoipa::path(
+    get,
+    path = "/system/i2c-scan",
+    tag = "hardware",
+    responses(
+        (status = 200, description = "Responding addresses", body = I2cScanResponse),
+        (status = 500, description = "The bus can't be opened", body = String, content_type = "text/plain"),
+    )
+)]
+/// Scans the I2C bus the power sensor is configured for and lists the responding addresses.
+pub async fn i2c_scan(
+    State(app_state): State<AppStateMutex>,
+) -> Result<Json<I2cScanResponse>, ApiError> {
+    let bus = app_state
+        .lock()
+        .await
+        .app_config
+        .power_monitor
+        .ina219
+        .as_ref()
+        .and_then(|c| c.i2c_bus)
+        .unwrap_or(config::I2C_BUS_DEFAULT);
+
+    match tokio::task::spawn_blocking(move || i2c_scan::scan_bus(bus)).await {
+        Ok(Ok(response)) => Ok(Json(response)),
+        Ok(Err(e)) => Err(ApiError::Hardware(e)),
+        Err(e) => Err(ApiError::Internal(format!("I2C scan task failed: {}", e))),
+    }
+}
@@ -0,0 +1,56 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::application_state::AppStateMutex;
+use crate::error::ApiError;
+use crate::services::training::{self, StartTrainingRequest, TrainingState};
+
+/// State of the training session.
+#[utoipa::path(
+    get,
+    path = "/training",
+    tag = "training",
+    responses(
+        (status = 200, description = "Training session state", body = TrainingState),
+    )
+)]
+pub async fn get_training(State(app_state): State<AppStateMutex>) -> Json<TrainingState> {
+    Json(training::get_state(&app_state).await)
+}
+
+/// Starts a training session.
+#[utoipa::path(
+    post,
+    path = "/training/start",
+    tag = "training",
+    request_body(content = Option<StartTrainingRequest>, description = "Optional"),
+    responses(
+        (status = 200, description = "Training started", body = TrainingState),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+        (status = 404, description = "No training input configured", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn start_training(
+    State(app_state): State<AppStateMutex>,
+    request: Option<Json<StartTrainingRequest>>,
+) -> Result<Json<TrainingState>, ApiError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    request.validate()?;
+    training::start(&app_state, request).await.map(Json)
+}
+
+/// Stops the training session.
+#[utoipa::path(
+    post,
+    path = "/training/stop",
+    tag = "training",
+    responses(
+        (status = 200, description = "Training stopped", body = TrainingState),
+        (status = 404, description = "No training session is running", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn stop_training(
+    State(app_state): State<AppStateMutex>,
+) -> Result<Json<TrainingState>, ApiError> {
+    training::stop(&app_state).await.map(Json)
+}
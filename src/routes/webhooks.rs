@@ -0,0 +1,23 @@
+use crate::application_state::ApplicationState;
+use crate::services::webhooks::WebhookDelivery;
+use axum::Json;
+use axum::extract::State;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/deliveries",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Recent webhook deliveries, oldest first", body = Vec<WebhookDelivery>),
+    )
+)]
+/// Returns the most recent webhook deliveries with the outcome of their latest attempt,
+/// oldest first.
+pub async fn get_deliveries(
+    State(state): State<Arc<Mutex<ApplicationState>>>,
+) -> Json<Vec<WebhookDelivery>> {
+    let delivery_log = state.lock().await.webhook_deliveries.clone();
+    Json(delivery_log.recent())
+}
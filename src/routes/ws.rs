@@ -0,0 +1,92 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::Response;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::debug;
+
+use crate::application_state::AppStateMutex;
+use crate::config;
+use crate::error::{ApiError, FieldError};
+use crate::sensors::WeightReading;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WeightStreamQuery {
+    pub interval_ms: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws/weight",
+    tag = "sensors",
+    params(WeightStreamQuery),
+    responses(
+        (status = 101, description = "WebSocket sending a `WeightReading` as JSON per message"),
+        (status = 422, description = "Invalid fields", body = crate::error::ValidationErrorBody),
+    )
+)]
+/// Upgrades to a WebSocket that sends the latest `WeightReading` as JSON whenever the
+/// weight monitor published a new one, at most once per `interval_ms`.
+pub async fn stream_weight(
+    State(app_state): State<AppStateMutex>,
+    Query(query): Query<WeightStreamQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let interval_ms = query
+        .interval_ms
+        .unwrap_or(config::WEIGHT_STREAM_INTERVAL_MS_DEFAULT);
+    if !(config::WEIGHT_STREAM_INTERVAL_MS_MIN..=config::WEIGHT_STREAM_INTERVAL_MS_MAX)
+        .contains(&interval_ms)
+    {
+        return Err(ApiError::Validation(vec![FieldError::new(
+            "interval_ms",
+            format!(
+                "must be between {} and {}",
+                config::WEIGHT_STREAM_INTERVAL_MS_MIN,
+                config::WEIGHT_STREAM_INTERVAL_MS_MAX
+            ),
+        )]));
+    }
+    let weight_readings_rx = app_state.lock().await.weight_readings_rx.clone();
+    Ok(ws.on_upgrade(move |socket| {
+        send_weight_readings(
+            socket,
+            weight_readings_rx,
+            Duration::from_millis(interval_ms),
+        )
+    }))
+}
+
+async fn send_weight_readings(
+    mut socket: WebSocket,
+    mut weight_readings_rx: watch::Receiver<WeightReading>,
+    interval: Duration,
+) {
+    // the current reading right away, so the client doesn't wait for the next one
+    weight_readings_rx.mark_changed();
+    loop {
+        tokio::select! {
+            changed = weight_readings_rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let reading = weight_readings_rx.borrow_and_update().clone();
+                let Ok(json) = serde_json::to_string(&reading) else {
+                    break;
+                };
+                if socket.send(Message::Text(json.into())).await.is_err() {
+                    break;
+                }
+                tokio::time::sleep(interval).await;
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // pings are answered by axum, anything else the client sends is ignored
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("Weight stream closed");
+}
@@ -0,0 +1,489 @@
+//! Checks weight and power sensors against the contract of `WeightSensor` and `PowerSensor`.
+//! The sensor under test is built around a `SensorReplay` that feeds it scripted raw
+//! samples: the replay alone checks the conversion shared with the HX711 and INA219
+//! drivers, and a fused sensor takes it as one of its load cells.
+//!
+//! - `get_name` isn't empty.
+//! - `get_raw` returns the raw samples unchanged and `get_raw_with_cells` agrees with it.
+//! - Weights are `((raw - tare_raw) - offset) / scale * geometry_factor`, also with a
+//!   negative scale, and are settled. Whether the bowl is settled is up to the weight monitor.
+//! - Weights closer to zero than `WEIGHT_DEADBAND_GRAMS` read as zero.
+//! - A read error fails the reading with the sensor's message, and the next read recovers.
+//! - `apply_settings` takes over the geometry factor, and rejects invalid settings without
+//!   changing anything.
+//! - Power readings have 4 mV per bus voltage LSB and 1 mA per current LSB, currents are
+//!   clamped to `0..=CURRENT_MAX_AMPS`, and the power is voltage times current.
+//!
+//! ```no_run
+//! use treat_dispenser_api::sensors::WeightSensor;
+//! use treat_dispenser_api::sensors::conformance::WeightSensorConformance;
+//!
+//! WeightSensorConformance::new()
+//!     .assert_conforms(|replay| Ok(Box::new(replay) as Box<dyn WeightSensor>));
+//! ```
+
+use crate::config;
+use crate::motor::conformance::ConformanceFailure;
+use crate::sensors::sensor_ina219::CURRENT_MAX_AMPS;
+use crate::sensors::sensor_replay::{RecordedSample, SensorReplay};
+use crate::sensors::{
+    PowerRawReading, PowerSensor, WEIGHT_DEADBAND_GRAMS, WeightSensor, WeightSensorCalibration,
+    WeightSensorSettings,
+};
+
+/// The ends of the HX711's 24 bit range and readings in between.
+const RAW_SAMPLES: [i32; 5] = [-8_388_608, -52_000, 0, 84_310, 8_388_607];
+const READ_ERROR: &str = "bus timeout";
+
+/// Runs the weight sensor checks, see the module docs for the contract.
+pub struct WeightSensorConformance {
+    settings: WeightSensorSettings,
+}
+
+impl WeightSensorConformance {
+    /// Checks a single load cell.
+    pub fn new() -> Self {
+        WeightSensorConformance {
+            settings: WeightSensorSettings {
+                geometry_factor: 1.0,
+                cells: Vec::new(),
+                mismatch_tolerance: config::LOAD_CELL_MISMATCH_TOLERANCE_DEFAULT,
+                mismatch_min_grams: config::LOAD_CELL_MISMATCH_MIN_GRAMS_DEFAULT,
+            },
+        }
+    }
+
+    /// Settings the sensor accepts, e.g. with the load cells of a fused sensor. The checks
+    /// only change their geometry factor, which must be 1 for the sensor as built.
+    pub fn settings(mut self, settings: WeightSensorSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Runs every check and returns the failures, empty if the sensor conforms. `build`
+    /// is called for each check with a replay of that check's samples.
+    pub fn check<F>(&self, build: F) -> Vec<ConformanceFailure>
+    where
+        F: Fn(SensorReplay) -> Result<Box<dyn WeightSensor>, String>,
+    {
+        let mut failures = Vec::new();
+        let mut fail = |check: &'static str, message: String| {
+            failures.push(ConformanceFailure { check, message })
+        };
+        let build_with = |samples: Vec<RecordedSample<i32>>| {
+            build(SensorReplay::new().with_weight_samples(samples))
+                .map_err(|e| format!("sensor could not be built: {}", e))
+        };
+        let raw_samples = || RAW_SAMPLES.iter().map(|raw| Ok(*raw)).collect::<Vec<_>>();
+
+        match build_with(raw_samples()) {
+            Ok(mut sensor) => {
+                if sensor.get_name().trim().is_empty() {
+                    fail("identity", "get_name is empty".to_string());
+                }
+                for raw in RAW_SAMPLES {
+                    match sensor.get_raw() {
+                        Ok(actual) if actual == raw => {}
+                        result => fail("raw", format!("get_raw of {}: {:?}", raw, result)),
+                    }
+                }
+                for raw in RAW_SAMPLES {
+                    match sensor.get_raw_with_cells() {
+                        Ok((actual, _)) if actual == raw => {}
+                        result => fail(
+                            "raw",
+                            format!("get_raw_with_cells of {}: {:?}", raw, result),
+                        ),
+                    }
+                }
+            }
+            Err(e) => fail("identity", e),
+        }
+
+        let calibrations = [
+            WeightSensorCalibration::default(),
+            calibration(420.5, 12.0, 84_310),
+            // a load cell mounted upside down
+            calibration(-215.0, 0.0, -52_000),
+        ];
+        for calibration in &calibrations {
+            let mut sensor = match build_with(raw_samples()) {
+                Ok(sensor) => sensor,
+                Err(e) => {
+                    fail("calibration", e);
+                    continue;
+                }
+            };
+            for raw in RAW_SAMPLES {
+                let expected = expected_grams(raw, calibration, 1.0);
+                if let Some(message) = check_grams(sensor.as_mut(), calibration, expected) {
+                    fail(
+                        "calibration",
+                        format!("raw {}, {:?}: {}", raw, calibration, message),
+                    );
+                }
+            }
+        }
+
+        // 1 g per 10 counts, so the readings land on both sides of the deadband
+        let deadband_calibration = calibration(10.0, 0.0, 1000);
+        let deadband_raws = [1000, 1005, 995, 1009, 991, 1010, 990];
+        match build_with(deadband_raws.iter().map(|raw| Ok(*raw)).collect()) {
+            Ok(mut sensor) => {
+                for raw in deadband_raws {
+                    let expected = expected_grams(raw, &deadband_calibration, 1.0);
+                    if let Some(message) =
+                        check_grams(sensor.as_mut(), &deadband_calibration, expected)
+                    {
+                        fail("deadband", format!("raw {}: {}", raw, message));
+                    }
+                }
+            }
+            Err(e) => fail("deadband", e),
+        }
+
+        let error_calibration = WeightSensorCalibration::default();
+        match build_with(vec![Ok(1000), Err(READ_ERROR.to_string()), Ok(2000)]) {
+            Ok(mut sensor) => {
+                if let Some(message) = check_grams(sensor.as_mut(), &error_calibration, 1000.0) {
+                    fail("read_error", format!("before the error: {}", message));
+                }
+                match sensor.get_weight_reading(&error_calibration) {
+                    Err(e) if e.contains(READ_ERROR) => {}
+                    Err(e) => fail("read_error", format!("message without the cause: {}", e)),
+                    Ok(reading) => {
+                        fail("read_error", format!("read error gave {} g", reading.grams))
+                    }
+                }
+                if let Some(message) = check_grams(sensor.as_mut(), &error_calibration, 2000.0) {
+                    fail("read_error", format!("after the error: {}", message));
+                }
+            }
+            Err(e) => fail("read_error", e),
+        }
+        match build_with(vec![Err(READ_ERROR.to_string())]) {
+            Ok(mut sensor) => {
+                if !matches!(sensor.get_raw(), Err(e) if e.contains(READ_ERROR)) {
+                    fail("read_error", "get_raw hides the read error".to_string());
+                }
+            }
+            Err(e) => fail("read_error", e),
+        }
+
+        // 80 g on the load cell, which carries 80% of the weight
+        let settings_calibration = calibration(2.0, 0.0, 1000);
+        match build_with(vec![Ok(1160)]) {
+            Ok(mut sensor) => {
+                let mut settings = self.settings.clone();
+                settings.geometry_factor = 1.25;
+                if let Err(e) = sensor.apply_settings(&settings) {
+                    fail("settings", format!("valid settings rejected: {}", e));
+                }
+                if let Some(message) = check_grams(sensor.as_mut(), &settings_calibration, 100.0) {
+                    fail("settings", format!("geometry factor 1.25: {}", message));
+                }
+                settings.geometry_factor = 0.0;
+                if sensor.apply_settings(&settings).is_ok() {
+                    fail("settings", "geometry factor 0 accepted".to_string());
+                }
+                if let Some(message) = check_grams(sensor.as_mut(), &settings_calibration, 100.0) {
+                    fail("settings", format!("after invalid settings: {}", message));
+                }
+            }
+            Err(e) => fail("settings", e),
+        }
+
+        failures
+    }
+
+    /// Runs the checks and panics with every failure, for use in tests.
+    pub fn assert_conforms<F>(&self, build: F)
+    where
+        F: Fn(SensorReplay) -> Result<Box<dyn WeightSensor>, String>,
+    {
+        let name = build(SensorReplay::new())
+            .map(|sensor| sensor.get_name())
+            .unwrap_or_default();
+        panic_on_failures(&name, "weight sensor", self.check(build));
+    }
+}
+
+impl Default for WeightSensorConformance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the power sensor checks, see the module docs for the contract.
+pub struct PowerSensorConformance;
+
+impl PowerSensorConformance {
+    pub fn new() -> Self {
+        PowerSensorConformance
+    }
+
+    /// Runs every check and returns the failures, empty if the sensor conforms. `build`
+    /// is called for each check with a replay of that check's samples.
+    pub fn check<F>(&self, build: F) -> Vec<ConformanceFailure>
+    where
+        F: Fn(SensorReplay) -> Result<Box<dyn PowerSensor>, String>,
+    {
+        let mut failures = Vec::new();
+        let mut fail = |check: &'static str, message: String| {
+            failures.push(ConformanceFailure { check, message })
+        };
+        let build_with = |samples: Vec<RecordedSample<PowerRawReading>>| {
+            build(SensorReplay::new().with_power_samples(samples))
+                .map_err(|e| format!("sensor could not be built: {}", e))
+        };
+        let raw_samples = [
+            // idle at 12 V, running, noise around zero, a spike and a dead supply
+            power_raw(450, 3000, 45),
+            power_raw(6000, 2990, 600),
+            power_raw(-20, 3001, 0xFFFE),
+            power_raw(32000, 2980, 2500),
+            power_raw(0, 0, 0),
+        ];
+
+        match build_with(raw_samples.iter().cloned().map(Ok).collect()) {
+            Ok(mut sensor) => {
+                if sensor.get_name().trim().is_empty() {
+                    fail("identity", "get_name is empty".to_string());
+                }
+                for raw in &raw_samples {
+                    match sensor.get_raw() {
+                        Ok(actual) if actual == *raw => {}
+                        result => fail("raw", format!("get_raw of {:?}: {:?}", raw, result)),
+                    }
+                }
+                for raw in &raw_samples {
+                    if let Some(message) = check_power(sensor.as_mut(), raw) {
+                        fail("conversion", format!("{:?}: {}", raw, message));
+                    }
+                }
+            }
+            Err(e) => fail("identity", e),
+        }
+
+        match build_with(vec![
+            Ok(raw_samples[0].clone()),
+            Err(READ_ERROR.to_string()),
+            Ok(raw_samples[1].clone()),
+        ]) {
+            Ok(mut sensor) => {
+                if let Some(message) = check_power(sensor.as_mut(), &raw_samples[0]) {
+                    fail("read_error", format!("before the error: {}", message));
+                }
+                match sensor.get_power_reading() {
+                    Err(e) if e.contains(READ_ERROR) => {}
+                    Err(e) => fail("read_error", format!("message without the cause: {}", e)),
+                    Ok(reading) => fail("read_error", format!("read error gave {:?}", reading)),
+                }
+                if let Some(message) = check_power(sensor.as_mut(), &raw_samples[1]) {
+                    fail("read_error", format!("after the error: {}", message));
+                }
+            }
+            Err(e) => fail("read_error", e),
+        }
+
+        failures
+    }
+
+    /// Runs the checks and panics with every failure, for use in tests.
+    pub fn assert_conforms<F>(&self, build: F)
+    where
+        F: Fn(SensorReplay) -> Result<Box<dyn PowerSensor>, String>,
+    {
+        let name = build(SensorReplay::new())
+            .map(|sensor| sensor.get_name())
+            .unwrap_or_default();
+        panic_on_failures(&name, "power sensor", self.check(build));
+    }
+}
+
+impl Default for PowerSensorConformance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn calibration(scale: f32, offset: f32, tare_raw: i32) -> WeightSensorCalibration {
+    WeightSensorCalibration {
+        scale,
+        offset,
+        tare_raw,
+        cell_tare_raw: Vec::new(),
+    }
+}
+
+fn power_raw(
+    shunt_voltage_10uv: i16,
+    bus_voltage_4mv: u16,
+    current_register: u16,
+) -> PowerRawReading {
+    PowerRawReading {
+        shunt_voltage_10uv,
+        bus_voltage_4mv,
+        current_register,
+    }
+}
+
+/// The weight the contract asks for, computed independently of the drivers.
+fn expected_grams(raw: i32, calibration: &WeightSensorCalibration, geometry_factor: f32) -> f32 {
+    let grams = (raw as f64 - calibration.tare_raw as f64 - calibration.offset as f64)
+        / calibration.scale as f64
+        * geometry_factor as f64;
+    if grams.abs() < WEIGHT_DEADBAND_GRAMS as f64 {
+        0.0
+    } else {
+        grams as f32
+    }
+}
+
+/// Reads the next weight and describes what's wrong with it.
+fn check_grams(
+    sensor: &mut dyn WeightSensor,
+    calibration: &WeightSensorCalibration,
+    expected: f32,
+) -> Option<String> {
+    match sensor.get_weight_reading(calibration) {
+        Ok(reading) if !close(reading.grams, expected) => {
+            Some(format!("{} g instead of {} g", reading.grams, expected))
+        }
+        Ok(reading) if !reading.settled => Some("reading isn't settled".to_string()),
+        Ok(_) => None,
+        Err(e) => Some(format!("failed: {}", e)),
+    }
+}
+
+/// Reads the next power reading and describes what's wrong with it.
+fn check_power(sensor: &mut dyn PowerSensor, raw: &PowerRawReading) -> Option<String> {
+    let bus_voltage_volts = raw.bus_voltage_4mv as f32 * 0.004;
+    let current_amps = (raw.current_register as i16 as f32 / 1000.0).clamp(0.0, CURRENT_MAX_AMPS);
+    match sensor.get_power_reading() {
+        Ok(reading) => {
+            let matches = close(reading.bus_voltage_volts, bus_voltage_volts)
+                && close(reading.current_amps, current_amps)
+                && close(reading.power_watts, bus_voltage_volts * current_amps);
+            (!matches).then(|| {
+                format!(
+                    "{:?} instead of {} V, {} A, {} W",
+                    reading,
+                    bus_voltage_volts,
+                    current_amps,
+                    bus_voltage_volts * current_amps
+                )
+            })
+        }
+        Err(e) => Some(format!("failed: {}", e)),
+    }
+}
+
+/// Equal up to float rounding, which grows with the size of the value.
+fn close(actual: f32, expected: f32) -> bool {
+    (actual - expected).abs() <= f32::max(0.001, expected.abs() * 1e-6)
+}
+
+fn panic_on_failures(name: &str, contract: &str, failures: Vec<ConformanceFailure>) {
+    if !failures.is_empty() {
+        let failures: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+        panic!(
+            "{} breaks the {} contract in {} way(s):\n  {}",
+            name,
+            contract,
+            failures.len(),
+            failures.join("\n  ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensors::sensor_fused::{FusedCell, SensorFused};
+    use crate::sensors::{LoadCellSettings, WeightReading};
+
+    #[test]
+    fn test_replay_conforms() {
+        WeightSensorConformance::new()
+            .assert_conforms(|replay| Ok(Box::new(replay) as Box<dyn WeightSensor>));
+        PowerSensorConformance::new()
+            .assert_conforms(|replay| Ok(Box::new(replay) as Box<dyn PowerSensor>));
+    }
+
+    #[test]
+    fn test_fused_sensor_conforms() {
+        let cell = |name: &str, replay: SensorReplay| FusedCell {
+            name: name.to_string(),
+            sensor: Box::new(replay),
+            gain: 1.0,
+            expected_share: 0.5,
+        };
+        let cell_settings = |name: &str| LoadCellSettings {
+            name: name.to_string(),
+            gain: 1.0,
+            expected_share: 0.5,
+        };
+        WeightSensorConformance::new()
+            .settings(WeightSensorSettings {
+                geometry_factor: 1.0,
+                cells: vec![cell_settings("left"), cell_settings("right")],
+                mismatch_tolerance: 0.25,
+                mismatch_min_grams: 50.0,
+            })
+            .assert_conforms(|replay| {
+                // the weight is all on the left cell
+                let empty = SensorReplay::new().with_weight_samples(vec![Ok(0)]);
+                let sensor = SensorFused::new(
+                    vec![cell("left", replay), cell("right", empty)],
+                    1.0,
+                    0.25,
+                    50.0,
+                )?;
+                Ok(Box::new(sensor) as Box<dyn WeightSensor>)
+            });
+    }
+
+    /// Converts without the deadband and reads errors as an empty bowl.
+    struct CarelessSensor(SensorReplay);
+
+    impl WeightSensor for CarelessSensor {
+        fn get_name(&self) -> String {
+            "CarelessSensor".to_string()
+        }
+
+        fn get_weight_reading(
+            &mut self,
+            calibration: &WeightSensorCalibration,
+        ) -> Result<WeightReading, String> {
+            let grams = match self.get_raw() {
+                Ok(raw) => (raw - calibration.tare_raw) as f32 / calibration.scale,
+                Err(_) => 0.0,
+            };
+            Ok(WeightReading {
+                grams,
+                settled: true,
+            })
+        }
+
+        fn get_raw(&mut self) -> Result<i32, String> {
+            WeightSensor::get_raw(&mut self.0)
+        }
+    }
+
+    #[test]
+    fn test_nonconforming_sensor_is_reported() {
+        let failures = WeightSensorConformance::new()
+            .check(|replay| Ok(Box::new(CarelessSensor(replay)) as Box<dyn WeightSensor>));
+        let checks: Vec<&str> = failures.iter().map(|f| f.check).collect();
+        for check in ["calibration", "deadband", "read_error", "settings"] {
+            assert!(
+                checks.contains(&check),
+                "{} not reported: {:?}",
+                check,
+                failures
+            );
+        }
+        assert!(!checks.contains(&"raw"), "{:?}", failures);
+    }
+}
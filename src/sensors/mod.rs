@@ -1,10 +1,19 @@
 use serde::{Deserialize, Serialize};
 
+use crate::config::{self, WeightMonitorConfig};
+
+pub mod conformance;
+pub mod sensor_bme280;
+pub mod sensor_dht22;
+pub mod sensor_ds18b20;
+pub mod sensor_fused;
 pub mod sensor_hx711;
 pub mod sensor_ina219;
 pub mod sensor_mock;
+pub mod sensor_replay;
+pub mod temperature;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct WeightSensorCalibration {
     /// Scale factor for converting raw readings to grams
     pub scale: f32,
@@ -14,6 +23,10 @@ pub struct WeightSensorCalibration {
 
     /// Raw tare value to subtract from readings
     pub tare_raw: i32,
+
+    /// Raw tare value of each load cell of a fused sensor, in configured order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cell_tare_raw: Vec<i32>,
 }
 
 impl Default for WeightSensorCalibration {
@@ -22,11 +35,109 @@ impl Default for WeightSensorCalibration {
             scale: 1.0,
             offset: 0.0,
             tare_raw: 0,
+            cell_tare_raw: Vec::new(),
+        }
+    }
+}
+
+/// Weights closer to zero than this read as zero, so an empty bowl doesn't flicker.
+pub const WEIGHT_DEADBAND_GRAMS: f32 = 1.0;
+
+/// Converts a raw load cell reading to grams. The calibration describes the load cell
+/// itself, the geometry factor the installation, so a calibration can be copied between
+/// identical units.
+pub fn grams_from_raw(
+    raw: i32,
+    calibration: &WeightSensorCalibration,
+    geometry_factor: f32,
+) -> f32 {
+    let grams = ((raw as f32 - calibration.tare_raw as f32) - calibration.offset)
+        / calibration.scale
+        * geometry_factor;
+    if grams.abs() < WEIGHT_DEADBAND_GRAMS {
+        0.0
+    } else {
+        grams
+    }
+}
+
+/// Weight sensor parameters that can change while the sensor runs, see
+/// `WeightSensor::apply_settings`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeightSensorSettings {
+    pub geometry_factor: f32,
+    /// Load cells of a fused sensor in configured order, empty for a single cell
+    pub cells: Vec<LoadCellSettings>,
+    pub mismatch_tolerance: f32,
+    pub mismatch_min_grams: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoadCellSettings {
+    pub name: String,
+    pub gain: f32,
+    pub expected_share: f32,
+}
+
+impl WeightSensorSettings {
+    pub fn from_config(weight_monitor_config: &WeightMonitorConfig) -> Self {
+        let fusion_config = weight_monitor_config.fusion.as_ref();
+        let cells = fusion_config.map(|f| f.cells.as_slice()).unwrap_or_default();
+        let default_share = 1.0 / cells.len().max(1) as f32;
+        WeightSensorSettings {
+            geometry_factor: weight_monitor_config
+                .geometry_factor
+                .unwrap_or(config::WEIGHT_GEOMETRY_FACTOR_DEFAULT),
+            cells: cells
+                .iter()
+                .map(|cell| LoadCellSettings {
+                    name: cell.name.clone(),
+                    gain: cell.gain.unwrap_or(config::LOAD_CELL_GAIN_DEFAULT),
+                    expected_share: cell.expected_share.unwrap_or(default_share),
+                })
+                .collect(),
+            mismatch_tolerance: fusion_config
+                .and_then(|f| f.mismatch_tolerance)
+                .unwrap_or(config::LOAD_CELL_MISMATCH_TOLERANCE_DEFAULT),
+            mismatch_min_grams: fusion_config
+                .and_then(|f| f.mismatch_min_grams)
+                .unwrap_or(config::LOAD_CELL_MISMATCH_MIN_GRAMS_DEFAULT),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.geometry_factor.is_finite() || self.geometry_factor <= 0.0 {
+            return Err(format!(
+                "Invalid weight_monitor.geometry_factor {}, must be greater than 0",
+                self.geometry_factor
+            ));
+        }
+        for cell in &self.cells {
+            if !cell.gain.is_finite() || cell.gain <= 0.0 {
+                return Err(format!(
+                    "Invalid gain {} of load cell '{}', must be greater than 0",
+                    cell.gain, cell.name
+                ));
+            }
+            if !(cell.expected_share > 0.0 && cell.expected_share <= 1.0) {
+                return Err(format!(
+                    "Invalid expected_share {} of load cell '{}', must be greater than 0 and at most 1",
+                    cell.expected_share, cell.name
+                ));
+            }
+        }
+        let total_share: f32 = self.cells.iter().map(|cell| cell.expected_share).sum();
+        if !self.cells.is_empty() && (total_share - 1.0).abs() > 0.01 {
+            return Err(format!(
+                "Expected shares of the load cells add up to {}, must be 1",
+                total_share
+            ));
         }
+        Ok(())
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PowerReading {
     pub bus_voltage_volts: f32,
     pub current_amps: f32,
@@ -53,26 +164,60 @@ impl Default for PowerReading {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Unconverted INA219 register values, used for diagnosing wiring and noise.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PowerRawReading {
+    /// Shunt voltage register, LSB 10 µV
+    pub shunt_voltage_10uv: i16,
+    /// Bus voltage register, LSB 4 mV
+    pub bus_voltage_4mv: u16,
+    /// Current register, scaled by the calibration
+    pub current_register: u16,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WeightReading {
     pub grams: f32,
+    /// False while the motor runs and until the weight is stable again afterwards
+    pub settled: bool,
 }
 
 impl WeightReading {
     pub fn dummy() -> Self {
-        WeightReading { grams: -1.0 }
+        WeightReading {
+            grams: -1.0,
+            settled: true,
+        }
     }
 }
 
+/// One load cell of a fused weight sensor, reported in `/status`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, utoipa::ToSchema)]
+pub struct LoadCellStatus {
+    pub name: String,
+    /// Weight on this cell, unset until the sensor is tared
+    pub grams: Option<f32>,
+    /// Share of the total weight on this cell, unset below the mismatch check weight
+    pub share: Option<f32>,
+    pub expected_share: f32,
+    /// Read errors, or a share too far from the expected one
+    pub failing: bool,
+    pub error: Option<String>,
+}
+
 impl Default for WeightReading {
     fn default() -> Self {
-        WeightReading { grams: 0.0 }
+        WeightReading {
+            grams: 0.0,
+            settled: true,
+        }
     }
 }
 
 pub trait PowerSensor: Send + Sync {
     fn get_name(&self) -> String;
     fn get_power_reading(&mut self) -> Result<PowerReading, String>;
+    fn get_raw(&mut self) -> Result<PowerRawReading, String>;
 }
 
 pub trait WeightSensor: Send {
@@ -82,4 +227,21 @@ pub trait WeightSensor: Send {
         calibration: &WeightSensorCalibration,
     ) -> Result<WeightReading, String>;
     fn get_raw(&mut self) -> Result<i32, String>;
+
+    /// Raw reading with the raw readings of the load cells it is fused from, none for a
+    /// single cell.
+    fn get_raw_with_cells(&mut self) -> Result<(i32, Vec<i32>), String> {
+        Ok((self.get_raw()?, Vec::new()))
+    }
+
+    /// Load cells as of the last reading, empty for a single cell.
+    fn get_cell_statuses(&self) -> Vec<LoadCellStatus> {
+        Vec::new()
+    }
+
+    /// Takes over changed settings. Called by the weight monitor between readings, never
+    /// during one. Sensors without such parameters ignore them.
+    fn apply_settings(&mut self, _settings: &WeightSensorSettings) -> Result<(), String> {
+        Ok(())
+    }
 }
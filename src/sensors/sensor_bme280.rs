@@ -0,0 +1,188 @@
+use crate::config;
+use crate::sensors::temperature::{TemperatureReading, TemperatureSensor};
+use rppal::i2c::I2c;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+const BME280_ADDRESS_DEFAULT: u16 = 0x76;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_CALIB_TP: u8 = 0x88;
+const REG_CALIB_H: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+const CHIP_ID_BME280: u8 = 0x60;
+const CHIP_ID_BMP280: u8 = 0x58;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Bme280Config {
+    /// I2C bus, `/dev/i2c-<bus>` (default 1)
+    pub i2c_bus: Option<u8>,
+    /// I2C address, 0x76 or 0x77 depending on the SDO pin (default 0x76)
+    pub address: Option<u16>,
+}
+
+/// Factory trimming parameters, needed to turn the raw ADC values into units.
+#[derive(Debug, Clone, Default)]
+struct Calibration {
+    t1: u16,
+    t2: i16,
+    t3: i16,
+    h1: u8,
+    h2: i16,
+    h3: u8,
+    h4: i16,
+    h5: i16,
+    h6: i8,
+}
+
+impl Calibration {
+    fn from_registers(tp: &[u8; 26], h: &[u8; 7]) -> Self {
+        Calibration {
+            t1: u16::from_le_bytes([tp[0], tp[1]]),
+            t2: i16::from_le_bytes([tp[2], tp[3]]),
+            t3: i16::from_le_bytes([tp[4], tp[5]]),
+            h1: tp[25],
+            h2: i16::from_le_bytes([h[0], h[1]]),
+            h3: h[2],
+            // H4 and H5 are 12 bit values sharing the nibbles of 0xE5
+            h4: ((h[3] as i8 as i16) << 4) | (h[4] & 0x0F) as i16,
+            h5: ((h[5] as i8 as i16) << 4) | (h[4] >> 4) as i16,
+            h6: h[6] as i8,
+        }
+    }
+
+    /// Returns the temperature and the `t_fine` value humidity compensation depends on,
+    /// using the floating point formulas from the datasheet.
+    fn compensate_temperature(&self, adc_t: i32) -> (f64, f64) {
+        let adc_t = adc_t as f64;
+        let t1 = self.t1 as f64;
+        let var1 = (adc_t / 16384.0 - t1 / 1024.0) * self.t2 as f64;
+        let var2 = (adc_t / 131072.0 - t1 / 8192.0).powi(2) * self.t3 as f64;
+        let t_fine = var1 + var2;
+        (t_fine / 5120.0, t_fine)
+    }
+
+    fn compensate_humidity(&self, adc_h: i32, t_fine: f64) -> f64 {
+        let var_h = t_fine - 76800.0;
+        let var_h = (adc_h as f64 - (self.h4 as f64 * 64.0 + self.h5 as f64 / 16384.0 * var_h))
+            * (self.h2 as f64 / 65536.0
+                * (1.0
+                    + self.h6 as f64 / 67108864.0
+                        * var_h
+                        * (1.0 + self.h3 as f64 / 67108864.0 * var_h)));
+        let var_h = var_h * (1.0 - self.h1 as f64 * var_h / 524288.0);
+        var_h.clamp(0.0, 100.0)
+    }
+}
+
+/// BME280 (or BMP280, without humidity) on I2C, running in normal mode so every
+/// read returns the latest conversion without waiting.
+pub struct SensorBme280 {
+    i2c: I2c,
+    calibration: Calibration,
+    has_humidity: bool,
+}
+
+impl SensorBme280 {
+    pub fn new(config: Option<&Bme280Config>) -> Result<Self, String> {
+        let bus = config
+            .and_then(|c| c.i2c_bus)
+            .unwrap_or(config::I2C_BUS_DEFAULT);
+        let address = config
+            .and_then(|c| c.address)
+            .unwrap_or(BME280_ADDRESS_DEFAULT);
+
+        let mut i2c =
+            I2c::with_bus(bus).map_err(|e| format!("Failed to open I2C bus {}: {}", bus, e))?;
+        i2c.set_slave_address(address)
+            .map_err(|e| format!("Failed to set BME280 address {:#04x}: {}", address, e))?;
+
+        let chip_id = i2c
+            .smbus_read_byte(REG_CHIP_ID)
+            .map_err(|e| format!("No BME280 responding at {:#04x}: {}", address, e))?;
+        let has_humidity = match chip_id {
+            CHIP_ID_BME280 => true,
+            CHIP_ID_BMP280 => false,
+            other => return Err(format!("Unexpected BME280 chip id {:#04x}", other)),
+        };
+
+        let mut tp = [0u8; 26];
+        let mut h = [0u8; 7];
+        i2c.write_read(&[REG_CALIB_TP], &mut tp)
+            .map_err(|e| format!("Failed to read BME280 calibration: {}", e))?;
+        if has_humidity {
+            i2c.write_read(&[REG_CALIB_H], &mut h)
+                .map_err(|e| format!("Failed to read BME280 calibration: {}", e))?;
+        }
+
+        // humidity oversampling x1 (only applied after the ctrl_meas write), 1 s standby,
+        // then temperature and pressure oversampling x1 in normal mode
+        let setup = [
+            (REG_CTRL_HUM, 0x01),
+            (REG_CONFIG, 0xA0),
+            (REG_CTRL_MEAS, 0x27),
+        ];
+        for (register, value) in setup {
+            i2c.smbus_write_byte(register, value)
+                .map_err(|e| format!("Failed to configure BME280: {}", e))?;
+        }
+
+        info!(
+            "Initialized {} on I2C bus {} at {:#04x}",
+            if has_humidity { "BME280" } else { "BMP280" },
+            bus,
+            address
+        );
+        Ok(SensorBme280 {
+            i2c,
+            calibration: Calibration::from_registers(&tp, &h),
+            has_humidity,
+        })
+    }
+}
+
+impl TemperatureSensor for SensorBme280 {
+    fn get_name(&self) -> String {
+        "SensorBME280".to_string()
+    }
+
+    fn get_temperature_reading(&mut self) -> Result<TemperatureReading, String> {
+        // pressure (3), temperature (3) and humidity (2) in one burst so they belong together
+        let mut data = [0u8; 8];
+        self.i2c
+            .write_read(&[REG_DATA], &mut data)
+            .map_err(|e| format!("Failed to read BME280: {}", e))?;
+
+        let adc_t = ((data[3] as i32) << 12) | ((data[4] as i32) << 4) | ((data[5] as i32) >> 4);
+        let (celsius, t_fine) = self.calibration.compensate_temperature(adc_t);
+        let humidity_percent = self.has_humidity.then(|| {
+            let adc_h = ((data[6] as i32) << 8) | data[7] as i32;
+            self.calibration.compensate_humidity(adc_h, t_fine) as f32
+        });
+
+        Ok(TemperatureReading {
+            celsius: celsius as f32,
+            humidity_percent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compensate_temperature() {
+        // worked example from the Bosch BMP280 datasheet, the formula is shared with the BME280
+        let calibration = Calibration {
+            t1: 27504,
+            t2: 26435,
+            t3: -1000,
+            ..Default::default()
+        };
+        let (celsius, _) = calibration.compensate_temperature(519888);
+        assert!((celsius - 25.08).abs() < 0.01, "got {}", celsius);
+    }
+}
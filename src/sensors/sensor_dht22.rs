@@ -0,0 +1,60 @@
+use crate::sensors::temperature::{TemperatureReading, TemperatureSensor};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const IIO_DEVICES_DIR: &str = "/sys/bus/iio/devices";
+const IIO_DEVICE_DEFAULT: &str = "iio:device0";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Dht22Config {
+    /// IIO device created by the dht11 overlay (default `iio:device0`)
+    pub iio_device: Option<String>,
+}
+
+/// DHT22 read through the kernel IIO driver (`dtoverlay=dht11,gpiopin=<pin>`, which also
+/// handles the DHT22). The protocol's microsecond timing isn't reliable from userspace.
+pub struct SensorDht22 {
+    device_path: PathBuf,
+}
+
+impl SensorDht22 {
+    pub fn new(config: Option<&Dht22Config>) -> Result<Self, String> {
+        let device = config
+            .and_then(|c| c.iio_device.clone())
+            .unwrap_or_else(|| IIO_DEVICE_DEFAULT.to_string());
+        let device_path = Path::new(IIO_DEVICES_DIR).join(&device);
+        if !device_path.join("in_temp_input").exists() {
+            return Err(format!(
+                "DHT22 IIO device {} not found, is the dht11 overlay enabled?",
+                device
+            ));
+        }
+        info!("Initialized DHT22 at {}", device);
+        Ok(SensorDht22 { device_path })
+    }
+
+    /// IIO reports both channels in thousandths.
+    fn read_milli(&self, channel: &str) -> Result<f32, String> {
+        let value = std::fs::read_to_string(self.device_path.join(channel))
+            .map_err(|e| format!("Failed to read DHT22 {}: {}", channel, e))?;
+        value
+            .trim()
+            .parse::<i32>()
+            .map(|milli| milli as f32 / 1000.0)
+            .map_err(|e| format!("Invalid DHT22 {} value '{}': {}", channel, value.trim(), e))
+    }
+}
+
+impl TemperatureSensor for SensorDht22 {
+    fn get_name(&self) -> String {
+        "SensorDHT22".to_string()
+    }
+
+    fn get_temperature_reading(&mut self) -> Result<TemperatureReading, String> {
+        Ok(TemperatureReading {
+            celsius: self.read_milli("in_temp_input")?,
+            humidity_percent: Some(self.read_milli("in_humidityrelative_input")?),
+        })
+    }
+}
@@ -0,0 +1,110 @@
+use crate::sensors::temperature::{TemperatureReading, TemperatureSensor};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const W1_DEVICES_DIR: &str = "/sys/bus/w1/devices";
+/// Family code prefix of DS18B20 device ids on the 1-Wire bus
+const DS18B20_FAMILY_PREFIX: &str = "28-";
+/// Value of the temperature register after power-on, read when a conversion didn't run
+const POWER_ON_RESET_MILLI_CELSIUS: i32 = 85_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Ds18b20Config {
+    /// 1-Wire device id, e.g. `28-00000abcdef0` (default: the first DS18B20 found)
+    pub device_id: Option<String>,
+}
+
+/// DS18B20 read through the kernel 1-Wire driver (`dtoverlay=w1-gpio`).
+pub struct SensorDs18b20 {
+    slave_path: PathBuf,
+}
+
+impl SensorDs18b20 {
+    pub fn new(config: Option<&Ds18b20Config>) -> Result<Self, String> {
+        let device_id = match config.and_then(|c| c.device_id.clone()) {
+            Some(device_id) => device_id,
+            None => find_device(Path::new(W1_DEVICES_DIR))?,
+        };
+        let slave_path = Path::new(W1_DEVICES_DIR).join(&device_id).join("w1_slave");
+        if !slave_path.exists() {
+            return Err(format!(
+                "DS18B20 device {} not found on the 1-Wire bus",
+                device_id
+            ));
+        }
+        info!("Initialized DS18B20 {}", device_id);
+        Ok(SensorDs18b20 { slave_path })
+    }
+}
+
+impl TemperatureSensor for SensorDs18b20 {
+    fn get_name(&self) -> String {
+        "SensorDS18B20".to_string()
+    }
+
+    fn get_temperature_reading(&mut self) -> Result<TemperatureReading, String> {
+        let contents = std::fs::read_to_string(&self.slave_path)
+            .map_err(|e| format!("Failed to read DS18B20: {}", e))?;
+        Ok(TemperatureReading {
+            celsius: parse_w1_slave(&contents)?,
+            humidity_percent: None,
+        })
+    }
+}
+
+fn find_device(devices_dir: &Path) -> Result<String, String> {
+    let entries = std::fs::read_dir(devices_dir)
+        .map_err(|e| format!("Failed to list 1-Wire devices, is w1-gpio enabled? {}", e))?;
+    let mut device_ids: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(DS18B20_FAMILY_PREFIX))
+        .collect();
+    device_ids.sort();
+    device_ids
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No DS18B20 found on the 1-Wire bus".to_string())
+}
+
+/// Parses the `w1_slave` file: the first line ends in `YES` if the CRC matched, the
+/// second carries the temperature in millidegrees as `t=23125`.
+fn parse_w1_slave(contents: &str) -> Result<f32, String> {
+    let mut lines = contents.lines();
+    let crc_line = lines.next().unwrap_or_default();
+    if !crc_line.trim_end().ends_with("YES") {
+        return Err("DS18B20 CRC check failed".to_string());
+    }
+    let milli_celsius: i32 = lines
+        .next()
+        .and_then(|line| line.split("t=").nth(1))
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| "DS18B20 reading has no temperature".to_string())?;
+    if milli_celsius == POWER_ON_RESET_MILLI_CELSIUS {
+        return Err("DS18B20 returned its power-on value, check the sensor supply".to_string());
+    }
+    Ok(milli_celsius as f32 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_w1_slave() {
+        let ok = "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n72 01 4b 46 7f ff 0e 10 57 t=23125\n";
+        assert_eq!(parse_w1_slave(ok), Ok(23.125));
+
+        let negative =
+            "5e ff 4b 46 7f ff 02 10 e4 : crc=e4 YES\n5e ff 4b 46 7f ff 02 10 e4 t=-10125\n";
+        assert_eq!(parse_w1_slave(negative), Ok(-10.125));
+
+        let bad_crc =
+            "72 01 4b 46 7f ff 0e 10 57 : crc=00 NO\n72 01 4b 46 7f ff 0e 10 57 t=23125\n";
+        assert!(parse_w1_slave(bad_crc).is_err());
+
+        let reset = "50 05 4b 46 7f ff 0c 10 1c : crc=1c YES\n50 05 4b 46 7f ff 0c 10 1c t=85000\n";
+        assert!(parse_w1_slave(reset).is_err());
+    }
+}
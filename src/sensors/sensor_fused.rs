@@ -0,0 +1,422 @@
+use crate::sensors::LoadCellStatus;
+use crate::sensors::WeightReading;
+use crate::sensors::WeightSensor;
+use crate::sensors::WeightSensorCalibration;
+use crate::sensors::grams_from_raw;
+use crate::sensors::{LoadCellSettings, WeightSensorSettings};
+
+/// Consecutive readings a cell must be off (or back in line) before its flag changes, so
+/// noise and single read errors don't toggle it.
+const FAILING_READINGS: u32 = 30;
+
+/// A load cell of a fused sensor.
+pub struct FusedCell {
+    pub name: String,
+    pub sensor: Box<dyn WeightSensor>,
+    /// Multiplies the raw readings, see `LoadCellConfig::gain`
+    pub gain: f32,
+    pub expected_share: f32,
+}
+
+#[derive(Default)]
+struct CellHealth {
+    error_streak: u32,
+    mismatch_streak: u32,
+    match_streak: u32,
+    mismatched: bool,
+}
+
+/// Sums several load cells under one platform into a single weight sensor. The global
+/// calibration applies to the sum of the gain-weighted raw readings, so tare and scale
+/// calibration work as with one cell. Once tared, each cell's share of the weight is
+/// compared with its expected share to find a failing cell.
+pub struct SensorFused {
+    cells: Vec<FusedCell>,
+    statuses: Vec<LoadCellStatus>,
+    health: Vec<CellHealth>,
+    geometry_factor: f32,
+    mismatch_tolerance: f32,
+    mismatch_min_grams: f32,
+}
+
+impl SensorFused {
+    pub fn new(
+        cells: Vec<FusedCell>,
+        geometry_factor: f32,
+        mismatch_tolerance: f32,
+        mismatch_min_grams: f32,
+    ) -> Result<Self, String> {
+        if cells.len() < 2 {
+            return Err("weight_monitor.fusion needs at least two cells".to_string());
+        }
+        WeightSensorSettings {
+            geometry_factor,
+            cells: cells
+                .iter()
+                .map(|cell| LoadCellSettings {
+                    name: cell.name.clone(),
+                    gain: cell.gain,
+                    expected_share: cell.expected_share,
+                })
+                .collect(),
+            mismatch_tolerance,
+            mismatch_min_grams,
+        }
+        .validate()?;
+
+        let statuses = cells
+            .iter()
+            .map(|cell| LoadCellStatus {
+                name: cell.name.clone(),
+                grams: None,
+                share: None,
+                expected_share: cell.expected_share,
+                failing: false,
+                error: None,
+            })
+            .collect();
+        let health = cells.iter().map(|_| CellHealth::default()).collect();
+        Ok(SensorFused {
+            cells,
+            statuses,
+            health,
+            geometry_factor,
+            mismatch_tolerance,
+            mismatch_min_grams,
+        })
+    }
+
+    /// Reads every cell, so each one's read errors are tracked even if another fails.
+    fn read_cells(&mut self) -> Result<Vec<i32>, String> {
+        let mut raws = Vec::with_capacity(self.cells.len());
+        let mut first_error = None;
+        for ((cell, status), health) in self
+            .cells
+            .iter_mut()
+            .zip(self.statuses.iter_mut())
+            .zip(self.health.iter_mut())
+        {
+            match cell.sensor.get_raw() {
+                Ok(raw) => {
+                    health.error_streak = 0;
+                    status.error = None;
+                    raws.push(raw);
+                }
+                Err(e) => {
+                    health.error_streak += 1;
+                    if first_error.is_none() {
+                        first_error = Some(format!("Load cell '{}': {}", cell.name, e));
+                    }
+                    status.error = Some(e);
+                }
+            }
+            status.failing = health.mismatched || health.error_streak >= FAILING_READINGS;
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(raws),
+        }
+    }
+
+    fn fuse(&self, raws: &[i32]) -> i32 {
+        self.cells
+            .iter()
+            .zip(raws)
+            .map(|(cell, raw)| cell.gain * *raw as f32)
+            .sum::<f32>()
+            .round() as i32
+    }
+
+    /// Updates the per-cell weights and shares. Of the cells further from their expected
+    /// share than the tolerance, the one furthest off counts as mismatched; between two
+    /// equally far off, the one carrying less, as a broken cell usually reads low.
+    fn check_cells(&mut self, raws: &[i32], calibration: &WeightSensorCalibration) {
+        if calibration.cell_tare_raw.len() != self.cells.len() {
+            return;
+        }
+        let cell_grams: Vec<f32> = self
+            .cells
+            .iter()
+            .zip(raws.iter().zip(&calibration.cell_tare_raw))
+            .map(|(cell, (raw, tare))| {
+                cell.gain * (*raw as f32 - *tare as f32) / calibration.scale
+                    * self.geometry_factor
+            })
+            .collect();
+        let total: f32 = cell_grams.iter().sum();
+        let checked = total.abs() >= self.mismatch_min_grams;
+
+        let mut worst: Option<(usize, f32)> = None;
+        for (i, grams) in cell_grams.iter().enumerate() {
+            let status = &mut self.statuses[i];
+            status.grams = Some(*grams);
+            status.share = checked.then(|| grams / total);
+            if let Some(share) = status.share {
+                let deviation = (share - status.expected_share).abs();
+                let further = worst.is_none_or(|(worst_i, worst_deviation)| {
+                    deviation > worst_deviation
+                        || (deviation == worst_deviation && *grams < cell_grams[worst_i])
+                });
+                if deviation > self.mismatch_tolerance && further {
+                    worst = Some((i, deviation));
+                }
+            }
+        }
+        if !checked {
+            return;
+        }
+
+        for (i, (health, status)) in self
+            .health
+            .iter_mut()
+            .zip(self.statuses.iter_mut())
+            .enumerate()
+        {
+            if worst.is_some_and(|(worst_i, _)| worst_i == i) {
+                health.mismatch_streak += 1;
+                health.match_streak = 0;
+            } else {
+                health.match_streak += 1;
+                health.mismatch_streak = 0;
+            }
+            if health.mismatch_streak >= FAILING_READINGS {
+                health.mismatched = true;
+            } else if health.match_streak >= FAILING_READINGS {
+                health.mismatched = false;
+            }
+            status.failing = health.mismatched || health.error_streak >= FAILING_READINGS;
+        }
+    }
+}
+
+impl WeightSensor for SensorFused {
+    fn get_name(&self) -> String {
+        "SensorFused".to_string()
+    }
+
+    fn get_weight_reading(
+        &mut self,
+        calibration: &WeightSensorCalibration,
+    ) -> Result<WeightReading, String> {
+        let raws = self.read_cells()?;
+        self.check_cells(&raws, calibration);
+
+        let raw = self.fuse(&raws);
+        Ok(WeightReading {
+            grams: grams_from_raw(raw, calibration, self.geometry_factor),
+            settled: true,
+        })
+    }
+
+    fn get_raw(&mut self) -> Result<i32, String> {
+        let raws = self.read_cells()?;
+        Ok(self.fuse(&raws))
+    }
+
+    fn get_raw_with_cells(&mut self) -> Result<(i32, Vec<i32>), String> {
+        let raws = self.read_cells()?;
+        Ok((self.fuse(&raws), raws))
+    }
+
+    fn get_cell_statuses(&self) -> Vec<LoadCellStatus> {
+        self.statuses.clone()
+    }
+
+    /// Cells can't be added or removed at runtime, only their gains and shares change.
+    fn apply_settings(&mut self, settings: &WeightSensorSettings) -> Result<(), String> {
+        settings.validate()?;
+        let names_match = settings.cells.len() == self.cells.len()
+            && settings
+                .cells
+                .iter()
+                .zip(&self.cells)
+                .all(|(new, cell)| new.name == cell.name);
+        if !names_match {
+            return Err("Load cells differ from the running sensor, restart to change them".to_string());
+        }
+        for ((cell, status), new) in self
+            .cells
+            .iter_mut()
+            .zip(self.statuses.iter_mut())
+            .zip(&settings.cells)
+        {
+            cell.gain = new.gain;
+            cell.expected_share = new.expected_share;
+            status.expected_share = new.expected_share;
+        }
+        self.geometry_factor = settings.geometry_factor;
+        self.mismatch_tolerance = settings.mismatch_tolerance;
+        self.mismatch_min_grams = settings.mismatch_min_grams;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    /// Reads the shared raw value, fails on `i32::MIN`.
+    struct StubCell(Arc<AtomicI32>);
+
+    impl WeightSensor for StubCell {
+        fn get_name(&self) -> String {
+            "StubCell".to_string()
+        }
+
+        fn get_weight_reading(
+            &mut self,
+            _calibration: &WeightSensorCalibration,
+        ) -> Result<WeightReading, String> {
+            Err("not used".to_string())
+        }
+
+        fn get_raw(&mut self) -> Result<i32, String> {
+            match self.0.load(Ordering::Relaxed) {
+                i32::MIN => Err("read error".to_string()),
+                raw => Ok(raw),
+            }
+        }
+    }
+
+    fn fused_sensor() -> (SensorFused, Arc<AtomicI32>, Arc<AtomicI32>) {
+        let left = Arc::new(AtomicI32::new(0));
+        let right = Arc::new(AtomicI32::new(0));
+        let cell = |name: &str, raw: &Arc<AtomicI32>, gain: f32| FusedCell {
+            name: name.to_string(),
+            sensor: Box::new(StubCell(Arc::clone(raw))),
+            gain,
+            expected_share: 0.5,
+        };
+        let sensor = SensorFused::new(
+            vec![cell("left", &left, 1.0), cell("right", &right, 2.0)],
+            1.0,
+            0.25,
+            50.0,
+        )
+        .unwrap();
+        (sensor, left, right)
+    }
+
+    fn read(sensor: &mut SensorFused, calibration: &WeightSensorCalibration, times: u32) -> f32 {
+        let mut grams = 0.0;
+        for _ in 0..times {
+            grams = sensor.get_weight_reading(calibration).unwrap().grams;
+        }
+        grams
+    }
+
+    fn failing(sensor: &SensorFused) -> Vec<bool> {
+        sensor
+            .get_cell_statuses()
+            .iter()
+            .map(|status| status.failing)
+            .collect()
+    }
+
+    #[test]
+    fn test_fused_reading_and_mismatch() {
+        let (mut sensor, left, right) = fused_sensor();
+        let calibration = WeightSensorCalibration {
+            scale: 2.0,
+            offset: 0.0,
+            tare_raw: 1000 + 2 * 2000,
+            cell_tare_raw: vec![1000, 2000],
+        };
+
+        // 400 g split evenly, the right cell is half as sensitive
+        left.store(1400, Ordering::Relaxed);
+        right.store(2200, Ordering::Relaxed);
+        assert_eq!(read(&mut sensor, &calibration, FAILING_READINGS), 400.0);
+        let statuses = sensor.get_cell_statuses();
+        assert_eq!(statuses[0].grams, Some(200.0));
+        assert_eq!(statuses[1].share, Some(0.5));
+        assert_eq!(failing(&sensor), vec![false, false]);
+
+        // the right cell stops picking up load
+        left.store(1700, Ordering::Relaxed);
+        right.store(2050, Ordering::Relaxed);
+        read(&mut sensor, &calibration, FAILING_READINGS - 1);
+        assert_eq!(failing(&sensor), vec![false, false]);
+        read(&mut sensor, &calibration, 1);
+        assert_eq!(failing(&sensor), vec![false, true]);
+
+        // below the check weight the flag stays
+        left.store(1010, Ordering::Relaxed);
+        right.store(2000, Ordering::Relaxed);
+        read(&mut sensor, &calibration, FAILING_READINGS);
+        assert_eq!(failing(&sensor), vec![false, true]);
+        assert_eq!(sensor.get_cell_statuses()[1].share, None);
+
+        left.store(1400, Ordering::Relaxed);
+        right.store(2200, Ordering::Relaxed);
+        read(&mut sensor, &calibration, FAILING_READINGS);
+        assert_eq!(failing(&sensor), vec![false, false]);
+    }
+
+    #[test]
+    fn test_fused_read_errors() {
+        let (mut sensor, left, right) = fused_sensor();
+        let calibration = WeightSensorCalibration::default();
+        left.store(100, Ordering::Relaxed);
+        right.store(i32::MIN, Ordering::Relaxed);
+
+        for _ in 0..FAILING_READINGS {
+            let error = sensor.get_weight_reading(&calibration).unwrap_err();
+            assert!(error.contains("'right'"), "{}", error);
+        }
+        assert_eq!(failing(&sensor), vec![false, true]);
+        assert_eq!(
+            sensor.get_cell_statuses()[1].error.as_deref(),
+            Some("read error")
+        );
+
+        right.store(100, Ordering::Relaxed);
+        assert_eq!(sensor.get_raw_with_cells().unwrap(), (300, vec![100, 100]));
+        assert_eq!(failing(&sensor), vec![false, false]);
+    }
+
+    #[test]
+    fn test_fused_apply_settings() {
+        let (mut sensor, left, right) = fused_sensor();
+        left.store(100, Ordering::Relaxed);
+        right.store(100, Ordering::Relaxed);
+        assert_eq!(sensor.get_raw().unwrap(), 300);
+
+        let cell = |name: &str, gain: f32, expected_share: f32| LoadCellSettings {
+            name: name.to_string(),
+            gain,
+            expected_share,
+        };
+        let mut settings = WeightSensorSettings {
+            geometry_factor: 1.0,
+            cells: vec![cell("left", 1.0, 0.6), cell("right", 1.0, 0.4)],
+            mismatch_tolerance: 0.25,
+            mismatch_min_grams: 50.0,
+        };
+        sensor.apply_settings(&settings).unwrap();
+        assert_eq!(sensor.get_raw().unwrap(), 200);
+        assert_eq!(sensor.get_cell_statuses()[0].expected_share, 0.6);
+
+        // the cells themselves can't change, and invalid settings leave the sensor as it is
+        settings.cells[1].name = "back".to_string();
+        assert!(sensor.apply_settings(&settings).is_err());
+        settings.cells[1] = cell("right", 0.0, 0.4);
+        assert!(sensor.apply_settings(&settings).is_err());
+        assert_eq!(sensor.get_raw().unwrap(), 200);
+    }
+
+    #[test]
+    fn test_fused_config_errors() {
+        let cell = |expected_share: f32| FusedCell {
+            name: "cell".to_string(),
+            sensor: Box::new(StubCell(Arc::new(AtomicI32::new(0)))),
+            gain: 1.0,
+            expected_share,
+        };
+        assert!(SensorFused::new(vec![cell(1.0)], 1.0, 0.25, 50.0).is_err());
+        assert!(SensorFused::new(vec![cell(0.5), cell(0.4)], 1.0, 0.25, 50.0).is_err());
+        assert!(SensorFused::new(vec![cell(0.8), cell(0.2)], 1.0, 0.25, 50.0).is_ok());
+    }
+}
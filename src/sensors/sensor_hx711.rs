@@ -1,17 +1,31 @@
 use crate::sensors::WeightReading;
 use crate::sensors::WeightSensor;
 use crate::sensors::WeightSensorCalibration;
+use crate::sensors::WeightSensorSettings;
+use crate::sensors::grams_from_raw;
 use hx711_spi::{Hx711, Hx711Error, Mode as HxMode};
 use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 use tracing::{info};
 
 pub struct SensorHx711 {
     hx711: Hx711<Spi>,
+    /// Applied on top of the calibration, see `weight_monitor.geometry_factor`
+    geometry_factor: f32,
 }
 
 impl SensorHx711 {
-    pub fn new(_spi_bus: Bus, _slave_select: SlaveSelect) -> Result<Self, String> {
-        let spi_result = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 1_000_000, Mode::Mode1);
+    pub fn new(
+        spi_bus: Bus,
+        slave_select: SlaveSelect,
+        geometry_factor: f32,
+    ) -> Result<Self, String> {
+        if !geometry_factor.is_finite() || geometry_factor <= 0.0 {
+            return Err(format!(
+                "Invalid weight_monitor.geometry_factor {}, must be greater than 0",
+                geometry_factor
+            ));
+        }
+        let spi_result = Spi::new(spi_bus, slave_select, 1_000_000, Mode::Mode1);
 
         let spi = match spi_result {
             Ok(s) => s,
@@ -36,8 +50,11 @@ impl SensorHx711 {
             }
         }
 
-        info!("Initialized HX711 on SPI bus {:?} with slave select {:?}", _spi_bus, _slave_select);
-        Ok(SensorHx711 { hx711 })
+        info!("Initialized HX711 on SPI bus {:?} with slave select {:?}", spi_bus, slave_select);
+        Ok(SensorHx711 {
+            hx711,
+            geometry_factor,
+        })
     }
 }
 
@@ -58,14 +75,12 @@ impl WeightSensor for SensorHx711 {
             }
         };
 
-        let mut grams = SensorHx711::grams_from_raw(raw, &calibration);
-
-        //trace!("grams={grams}");
-        if grams.abs() < 1.0 { 
-            grams = 0.0; 
-        } // 1 g deadband
+        let grams = grams_from_raw(raw, calibration, self.geometry_factor);
 
-        let reading = WeightReading { grams };
+        let reading = WeightReading {
+            grams,
+            settled: true,
+        };
         Ok(reading)
     }
 
@@ -81,10 +96,28 @@ impl WeightSensor for SensorHx711 {
         //trace!("raw={raw}");
         Ok(raw)
     }
+
+    fn apply_settings(&mut self, settings: &WeightSensorSettings) -> Result<(), String> {
+        settings.validate()?;
+        self.geometry_factor = settings.geometry_factor;
+        Ok(())
+    }
 }
 
-impl SensorHx711 {
-    fn grams_from_raw(raw: i32, cal: &WeightSensorCalibration) -> f32 {
-        ((raw as f32 - cal.tare_raw as f32) - cal.offset) / cal.scale
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grams_from_raw_geometry_factor() {
+        let cal = WeightSensorCalibration {
+            scale: 2.0,
+            offset: 0.0,
+            tare_raw: 1000,
+            cell_tare_raw: Vec::new(),
+        };
+        assert_eq!(grams_from_raw(1200, &cal, 1.0), 100.0);
+        // the load cell only carries 80% of the hopper weight
+        assert_eq!(grams_from_raw(1160, &cal, 1.25), 100.0);
     }
 }
@@ -1,23 +1,125 @@
+use crate::config;
+use crate::sensors::PowerRawReading;
 use crate::sensors::PowerReading;
 use crate::sensors::PowerSensor;
 use ina219::SyncIna219;
 use ina219::address::Address;
 use ina219::calibration::IntCalibration;
 use ina219::calibration::MicroAmpere;
+use ina219::configuration::{BusVoltageRange, Configuration, Resolution, ShuntVoltageRange};
 use linux_embedded_hal::I2cdev;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
 
+/// Current readings are clamped to this, higher readings are noise.
+pub const CURRENT_MAX_AMPS: f32 = 2.0;
+
+/// INA219 configuration register settings. Higher resolution and more averaging
+/// reduce noise at the cost of a longer conversion time, which delays overcurrent detection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Ina219Config {
+    /// I2C bus the sensor is wired to, `/dev/i2c-<bus>` (default 1)
+    pub i2c_bus: Option<u8>,
+    /// Bus voltage full scale range in volts: 16 or 32 (default 32)
+    pub bus_voltage_range_v: Option<u8>,
+    /// Shunt voltage full scale range in millivolts: 40, 80, 160 or 320 (default 320).
+    /// With the 0.1 ohm shunt this limits the measurable current to range / 100 amps.
+    pub shunt_voltage_range_mv: Option<u16>,
+    /// Bus ADC mode: 9bit, 10bit, 11bit, 12bit or avg2 .. avg128 (default 12bit)
+    pub bus_adc: Option<String>,
+    /// Shunt ADC mode, same values as `bus_adc` (default 12bit)
+    pub shunt_adc: Option<String>,
+}
+
+impl Ina219Config {
+    /// Builds the configuration register value, unset options keep the chip's power-on defaults.
+    pub fn to_configuration(&self) -> Result<Configuration, String> {
+        let mut configuration = Configuration::default();
+
+        if let Some(range) = self.bus_voltage_range_v {
+            configuration.bus_voltage_range = match range {
+                16 => BusVoltageRange::Fsr16v,
+                32 => BusVoltageRange::Fsr32v,
+                other => return Err(format!("Unsupported INA219 bus voltage range {} V", other)),
+            };
+        }
+        if let Some(range) = self.shunt_voltage_range_mv {
+            configuration.shunt_voltage_range = match range {
+                40 => ShuntVoltageRange::Fsr40mv,
+                80 => ShuntVoltageRange::Fsr80mv,
+                160 => ShuntVoltageRange::Fsr160mv,
+                320 => ShuntVoltageRange::Fsr320mv,
+                other => return Err(format!("Unsupported INA219 shunt voltage range {} mV", other)),
+            };
+        }
+        if let Some(mode) = &self.bus_adc {
+            configuration.bus_resolution = parse_adc_mode(mode)?;
+        }
+        if let Some(mode) = &self.shunt_adc {
+            configuration.shunt_resolution = parse_adc_mode(mode)?;
+        }
+        Ok(configuration)
+    }
+}
+
+fn parse_adc_mode(mode: &str) -> Result<Resolution, String> {
+    match mode.to_lowercase().as_str() {
+        "9bit" => Ok(Resolution::Res9Bit),
+        "10bit" => Ok(Resolution::Res10Bit),
+        "11bit" => Ok(Resolution::Res11Bit),
+        "12bit" => Ok(Resolution::Res12Bit),
+        "avg2" => Ok(Resolution::Avg2),
+        "avg4" => Ok(Resolution::Avg4),
+        "avg8" => Ok(Resolution::Avg8),
+        "avg16" => Ok(Resolution::Avg16),
+        "avg32" => Ok(Resolution::Avg32),
+        "avg64" => Ok(Resolution::Avg64),
+        "avg128" => Ok(Resolution::Avg128),
+        other => Err(format!("Unsupported INA219 ADC mode '{}'", other)),
+    }
+}
+
+/// Bus voltage register in volts, LSB 4 mV.
+pub fn bus_voltage_from_register(voltage_4mv: u16) -> f32 {
+    (voltage_4mv as u32 * 4) as f32 / 1000.0
+}
+
+/// Current register in amps, LSB 1 mA with the calibration the sensor is initialized with.
+/// The register is two's complement, so noise around zero reads as a small negative current
+/// instead of wrapping around. Currents below zero or above `CURRENT_MAX_AMPS` are noise
+/// and clamped.
+pub fn current_amps_from_register(current_register: u16) -> f32 {
+    let current_amps = current_register as i16 as f32 / 1000.0;
+    if current_amps > CURRENT_MAX_AMPS {
+        debug!("Current reading is unrealistic: {} A", current_amps);
+    }
+    current_amps.clamp(0.0, CURRENT_MAX_AMPS)
+}
+
+/// Converts the raw registers the way `get_power_reading` does, for replaying captures.
+pub fn power_reading_from_raw(raw: &PowerRawReading) -> PowerReading {
+    power_reading(
+        bus_voltage_from_register(raw.bus_voltage_4mv),
+        current_amps_from_register(raw.current_register),
+    )
+}
+
+fn power_reading(bus_voltage_volts: f32, current_amps: f32) -> PowerReading {
+    PowerReading {
+        bus_voltage_volts,
+        current_amps,
+        power_watts: bus_voltage_volts * current_amps,
+    }
+}
+
 pub struct SensorIna219 {
     ina219: SyncIna219<I2cdev, Option<IntCalibration>>,
 }
 
 impl SensorIna219 {
-    pub fn new() -> Self {
-        let ina219 = Self::init_ina219_sensor().unwrap_or_else(|e| {
-            error!("Failed to initialize INA219 sensor: {}", e);
-            panic!("INA219 sensor initialization failed");
-        });
-        SensorIna219 { ina219 }
+    pub fn new(config: Option<&Ina219Config>) -> Result<Self, String> {
+        let ina219 = Self::init_ina219_sensor(config)?;
+        Ok(SensorIna219 { ina219 })
     }
 
     pub fn get_bus_voltage(&mut self) -> Result<f32, String> {
@@ -25,7 +127,7 @@ impl SensorIna219 {
             .ina219
             .bus_voltage()
             .map_err(|e| format!("Failed to read bus voltage: {}", e))?;
-        Ok(bus_voltage.voltage_mv() as f32 / 1000.0) // Convert mV to V
+        Ok(bus_voltage_from_register(bus_voltage.voltage_4mv()))
     }
 
     pub fn get_current_amps(&mut self) -> Result<f32, String> {
@@ -33,21 +135,25 @@ impl SensorIna219 {
             .ina219
             .current_raw()
             .map_err(|e| format!("Failed to read current: {}", e))?;
-
-        let current_amps = current.0 as f32 / 1000.0; // Convert mA to A
-        if current_amps > 2.0 {
-            debug!("Current reading is unrealistic: {} A", current_amps);
-        }
-        Ok(current_amps.clamp(0.0, 2.0)) // clamped to realistic range
+        Ok(current_amps_from_register(current.0))
     }
 
-    fn init_ina219_sensor() -> Result<SyncIna219<I2cdev, Option<IntCalibration>>, String> {
+    fn init_ina219_sensor(
+        config: Option<&Ina219Config>,
+    ) -> Result<SyncIna219<I2cdev, Option<IntCalibration>>, String> {
         info!("Initializing INA219 sensor");
+        // validate the settings before touching the bus
+        let configuration = config
+            .map(|c| c.to_configuration())
+            .transpose()?;
 
         // Initialize the I2C device
-        let i2c = I2cdev::new("/dev/i2c-1")
+        let bus = config
+            .and_then(|c| c.i2c_bus)
+            .unwrap_or(config::I2C_BUS_DEFAULT);
+        let i2c = I2cdev::new(format!("/dev/i2c-{}", bus))
             .map_err(|e| format!("Failed to initialize I2C device: {}", e))?;
-        debug!("I2C device initialized");
+        debug!("I2C device initialized on bus {}", bus);
 
         let address_byte = 0x40; // Default I2C address for INA219, todo: make configurable
         let address = Address::from_byte(0x40).unwrap();
@@ -70,7 +176,21 @@ impl SensorIna219 {
             }
         }
 
-        let ina219 = ina219_init_result.unwrap();
+        let mut ina219 = ina219_init_result.unwrap();
+
+        if let Some(configuration) = configuration {
+            ina219
+                .set_configuration(configuration)
+                .map_err(|e| format!("Failed to write INA219 configuration: {}", e))?;
+            info!(
+                "INA219 configured: bus range {:?}, shunt range {:?}, bus ADC {:?}, shunt ADC {:?}, conversion time {} us",
+                configuration.bus_voltage_range,
+                configuration.shunt_voltage_range,
+                configuration.bus_resolution,
+                configuration.shunt_resolution,
+                configuration.conversion_time_us().unwrap_or_default()
+            );
+        }
 
         info!(
             "INA219 sensor initialized successfully at address {}",
@@ -88,12 +208,74 @@ impl PowerSensor for SensorIna219 {
     fn get_power_reading(&mut self) -> Result<PowerReading, String> {
         let bus_voltage = self.get_bus_voltage()?;
         let current = self.get_current_amps()?;
-        let power = bus_voltage * current;
+        Ok(power_reading(bus_voltage, current))
+    }
+
+    fn get_raw(&mut self) -> Result<PowerRawReading, String> {
+        let shunt_voltage = self
+            .ina219
+            .shunt_voltage()
+            .map_err(|e| format!("Failed to read shunt voltage: {}", e))?;
+        let bus_voltage = self
+            .ina219
+            .bus_voltage()
+            .map_err(|e| format!("Failed to read bus voltage: {}", e))?;
+        let current = self
+            .ina219
+            .current_raw()
+            .map_err(|e| format!("Failed to read current: {}", e))?;
 
-        Ok(PowerReading {
-            bus_voltage_volts: bus_voltage,
-            current_amps: current,
-            power_watts: power,
+        Ok(PowerRawReading {
+            shunt_voltage_10uv: shunt_voltage.shunt_voltage_10uv(),
+            bus_voltage_4mv: bus_voltage.voltage_4mv(),
+            current_register: current.0,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ina219_config_to_configuration() {
+        let config = Ina219Config {
+            i2c_bus: None,
+            bus_voltage_range_v: Some(16),
+            shunt_voltage_range_mv: Some(80),
+            bus_adc: None,
+            shunt_adc: Some("avg16".to_string()),
+        };
+        let configuration = config.to_configuration().unwrap();
+        assert_eq!(configuration.bus_voltage_range, BusVoltageRange::Fsr16v);
+        assert_eq!(configuration.shunt_voltage_range, ShuntVoltageRange::Fsr80mv);
+        assert_eq!(configuration.bus_resolution, Resolution::Res12Bit);
+        assert_eq!(configuration.shunt_resolution, Resolution::Avg16);
+
+        let invalid = Ina219Config {
+            shunt_adc: Some("13bit".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.to_configuration().is_err());
+    }
+
+    #[test]
+    fn test_power_reading_from_raw() {
+        let raw = |bus_voltage_4mv: u16, current_register: u16| PowerRawReading {
+            shunt_voltage_10uv: 0,
+            bus_voltage_4mv,
+            current_register,
+        };
+        let reading = power_reading_from_raw(&raw(3000, 500));
+        assert_eq!(reading.bus_voltage_volts, 12.0);
+        assert_eq!(reading.current_amps, 0.5);
+        assert_eq!(reading.power_watts, 6.0);
+
+        // -2 mA of noise at idle, not 65.534 A
+        assert_eq!(power_reading_from_raw(&raw(3000, 0xFFFE)).current_amps, 0.0);
+        assert_eq!(
+            power_reading_from_raw(&raw(3000, 2500)).current_amps,
+            CURRENT_MAX_AMPS
+        );
+    }
+}
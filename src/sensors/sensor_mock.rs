@@ -1,7 +1,9 @@
+use crate::sensors::PowerRawReading;
 use crate::sensors::PowerReading;
 use crate::sensors::PowerSensor;
 use crate::sensors::WeightSensor;
 use crate::sensors::WeightSensorCalibration;
+use crate::sensors::temperature::{TemperatureReading, TemperatureSensor};
 
 pub struct SensorMock {}
 
@@ -15,7 +17,10 @@ impl WeightSensor for SensorMock {
         _calibration: &WeightSensorCalibration,
     ) -> Result<crate::sensors::WeightReading, String> {
         // Return a dummy weight reading for testing purposes
-        Ok(crate::sensors::WeightReading { grams: 12345.0 })
+        Ok(crate::sensors::WeightReading {
+            grams: 12345.0,
+            settled: true,
+        })
     }
 
     fn get_raw(&mut self) -> Result<i32, String> {
@@ -37,6 +42,29 @@ impl PowerSensor for SensorMock {
             power_watts: 0.5,
         })
     }
+
+    fn get_raw(&mut self) -> Result<PowerRawReading, String> {
+        // Return dummy register values for testing purposes
+        Ok(PowerRawReading {
+            shunt_voltage_10uv: 6000,
+            bus_voltage_4mv: 3000,
+            current_register: 600,
+        })
+    }
+}
+
+impl TemperatureSensor for SensorMock {
+    fn get_name(&self) -> String {
+        "SensorMock".to_string()
+    }
+
+    fn get_temperature_reading(&mut self) -> Result<TemperatureReading, String> {
+        // Return a dummy climate reading for testing purposes
+        Ok(TemperatureReading {
+            celsius: 22.5,
+            humidity_percent: Some(45.0),
+        })
+    }
 }
 
 impl SensorMock {
@@ -44,3 +72,9 @@ impl SensorMock {
         SensorMock {}
     }
 }
+
+impl Default for SensorMock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
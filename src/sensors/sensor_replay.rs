@@ -0,0 +1,249 @@
+use crate::sensors::PowerRawReading;
+use crate::sensors::PowerReading;
+use crate::sensors::PowerSensor;
+use crate::sensors::WeightReading;
+use crate::sensors::WeightSensor;
+use crate::sensors::WeightSensorCalibration;
+use crate::sensors::WeightSensorSettings;
+use crate::sensors::grams_from_raw;
+use crate::sensors::sensor_ina219::power_reading_from_raw;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+/// A recorded raw read, the sample or the error the sensor returned.
+pub type RecordedSample<T> = Result<T, String>;
+
+/// One line of a capture from `/debug/weight/raw` or `/debug/power/raw`.
+#[derive(Deserialize)]
+struct CaptureLine<T> {
+    raw: Option<T>,
+    error: Option<String>,
+}
+
+/// Replays recorded raw samples and converts them the way the HX711 and INA219 drivers do,
+/// so the conversion can be tested against captures from real hardware. Each read takes the
+/// next sample, recorded errors included, and the replay starts over after the last one.
+pub struct SensorReplay {
+    weight_samples: Vec<RecordedSample<i32>>,
+    power_samples: Vec<RecordedSample<PowerRawReading>>,
+    next_weight_sample: usize,
+    next_power_sample: usize,
+    /// Applied on top of the calibration, see `weight_monitor.geometry_factor`
+    geometry_factor: f32,
+}
+
+impl SensorReplay {
+    pub fn new() -> Self {
+        SensorReplay {
+            weight_samples: Vec::new(),
+            power_samples: Vec::new(),
+            next_weight_sample: 0,
+            next_power_sample: 0,
+            geometry_factor: 1.0,
+        }
+    }
+
+    pub fn with_weight_samples(mut self, samples: Vec<RecordedSample<i32>>) -> Self {
+        self.weight_samples = samples;
+        self.next_weight_sample = 0;
+        self
+    }
+
+    pub fn with_power_samples(mut self, samples: Vec<RecordedSample<PowerRawReading>>) -> Self {
+        self.power_samples = samples;
+        self.next_power_sample = 0;
+        self
+    }
+
+    /// Reads a capture saved from one of the raw debug streams, one JSON sample per line.
+    pub fn load_capture<T: DeserializeOwned>(path: &str) -> Result<Vec<RecordedSample<T>>, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read capture {}: {}", path, e))?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                let capture_line: CaptureLine<T> = serde_json::from_str(line)
+                    .map_err(|e| format!("{} line {}: {}", path, index + 1, e))?;
+                match (capture_line.raw, capture_line.error) {
+                    (Some(raw), _) => Ok(Ok(raw)),
+                    (None, Some(error)) => Ok(Err(error)),
+                    (None, None) => Err(format!(
+                        "{} line {}: neither raw nor error",
+                        path,
+                        index + 1
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    fn next_sample<T: Clone>(
+        samples: &[RecordedSample<T>],
+        next: &mut usize,
+        kind: &str,
+    ) -> Result<T, String> {
+        if samples.is_empty() {
+            return Err(format!("No recorded {} samples", kind));
+        }
+        let sample = samples[*next].clone();
+        *next = (*next + 1) % samples.len();
+        sample
+    }
+}
+
+impl Default for SensorReplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WeightSensor for SensorReplay {
+    fn get_name(&self) -> String {
+        "SensorReplay".to_string()
+    }
+
+    fn get_weight_reading(
+        &mut self,
+        calibration: &WeightSensorCalibration,
+    ) -> Result<WeightReading, String> {
+        let raw = WeightSensor::get_raw(self)
+            .map_err(|e| format!("Could not get weight reading in grams: {}", e))?;
+        Ok(WeightReading {
+            grams: grams_from_raw(raw, calibration, self.geometry_factor),
+            settled: true,
+        })
+    }
+
+    fn get_raw(&mut self) -> Result<i32, String> {
+        Self::next_sample(&self.weight_samples, &mut self.next_weight_sample, "weight")
+    }
+
+    fn apply_settings(&mut self, settings: &WeightSensorSettings) -> Result<(), String> {
+        settings.validate()?;
+        self.geometry_factor = settings.geometry_factor;
+        Ok(())
+    }
+}
+
+impl PowerSensor for SensorReplay {
+    fn get_name(&self) -> String {
+        "SensorReplay".to_string()
+    }
+
+    fn get_power_reading(&mut self) -> Result<PowerReading, String> {
+        let raw = PowerSensor::get_raw(self)?;
+        Ok(power_reading_from_raw(&raw))
+    }
+
+    fn get_raw(&mut self) -> Result<PowerRawReading, String> {
+        Self::next_sample(&self.power_samples, &mut self.next_power_sample, "power")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sensors");
+
+    /// Readings a capture must convert to, `None` where the sensor returned an error.
+    #[derive(Deserialize)]
+    struct WeightGolden {
+        calibration: WeightSensorCalibration,
+        geometry_factor: f32,
+        grams: Vec<Option<f32>>,
+    }
+
+    #[derive(Deserialize)]
+    struct PowerGolden {
+        readings: Vec<Option<PowerReading>>,
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/{}", FIXTURES_DIR, name)
+    }
+
+    fn assert_close(actual: f32, expected: f32, what: &str) {
+        assert!(
+            (actual - expected).abs() <= 0.01,
+            "{}: {} instead of {}",
+            what,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_hx711_golden_values() {
+        let golden: WeightGolden = serde_json::from_str(
+            &std::fs::read_to_string(fixture("hx711_treat_drop.golden.json")).unwrap(),
+        )
+        .unwrap();
+        let samples = SensorReplay::load_capture(&fixture("hx711_treat_drop.ndjson")).unwrap();
+        assert_eq!(samples.len(), golden.grams.len());
+
+        let mut sensor = SensorReplay::new().with_weight_samples(samples);
+        sensor
+            .apply_settings(&WeightSensorSettings {
+                geometry_factor: golden.geometry_factor,
+                cells: Vec::new(),
+                mismatch_tolerance: config::LOAD_CELL_MISMATCH_TOLERANCE_DEFAULT,
+                mismatch_min_grams: config::LOAD_CELL_MISMATCH_MIN_GRAMS_DEFAULT,
+            })
+            .unwrap();
+        for (index, expected) in golden.grams.iter().enumerate() {
+            let reading = sensor.get_weight_reading(&golden.calibration);
+            match expected {
+                Some(grams) => {
+                    assert_close(reading.unwrap().grams, *grams, &format!("sample {}", index))
+                }
+                None => assert!(reading.is_err(), "sample {} should fail", index),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ina219_golden_values() {
+        let golden: PowerGolden = serde_json::from_str(
+            &std::fs::read_to_string(fixture("ina219_dispense.golden.json")).unwrap(),
+        )
+        .unwrap();
+        let samples = SensorReplay::load_capture(&fixture("ina219_dispense.ndjson")).unwrap();
+        assert_eq!(samples.len(), golden.readings.len());
+
+        let mut sensor = SensorReplay::new().with_power_samples(samples);
+        for (index, expected) in golden.readings.iter().enumerate() {
+            let reading = sensor.get_power_reading();
+            match expected {
+                Some(expected) => {
+                    let reading = reading.unwrap();
+                    let what = format!("sample {}", index);
+                    assert_close(reading.bus_voltage_volts, expected.bus_voltage_volts, &what);
+                    assert_close(reading.current_amps, expected.current_amps, &what);
+                    assert_close(reading.power_watts, expected.power_watts, &what);
+                }
+                None => assert!(reading.is_err(), "sample {} should fail", index),
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_capture_errors() {
+        let path = std::env::temp_dir().join(format!("capture-{}.ndjson", rand::random::<u32>()));
+        std::fs::write(
+            &path,
+            "{\"elapsed_ms\":0,\"raw\":12}\n{\"elapsed_ms\":100}\n",
+        )
+        .unwrap();
+        let error = SensorReplay::load_capture::<i32>(path.to_str().unwrap()).unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut sensor = SensorReplay::new();
+        assert!(WeightSensor::get_raw(&mut sensor).is_err());
+        assert!(sensor.get_power_reading().is_err());
+    }
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Enclosure climate reading. Humidity is only set by sensors that measure it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureReading {
+    pub celsius: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity_percent: Option<f32>,
+}
+
+/// Temperature sensors may block for up to a second per reading (the DS18B20 converts
+/// on every read), callers should read them on a blocking thread.
+pub trait TemperatureSensor: Send {
+    fn get_name(&self) -> String;
+    fn get_temperature_reading(&mut self) -> Result<TemperatureReading, String>;
+}
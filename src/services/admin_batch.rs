@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::application_state::{AppStateMutex, DispenserStatus};
+use crate::config::{self, AppConfig};
+use crate::error::{ApiError, FieldError};
+use crate::sensors::WeightSensorCalibration;
+use crate::services::config_reload;
+use crate::services::config_validation;
+use crate::services::supervisor;
+use crate::services::weight_monitor;
+
+/// One step of `POST /admin/batch`. Running a step twice has the same effect as once.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    /// Sets a value that `POST /config/reload` can apply, e.g. `motor.cooldown_ms`, in
+    /// memory until the next reload or restart
+    SetConfig {
+        path: String,
+        #[schema(value_type = Object)]
+        value: Value,
+    },
+    /// Re-reads `config.yaml` like `POST /config/reload`
+    ReloadConfig,
+    /// Re-reads the weight sensor calibration file, e.g. after copying one from another
+    /// dispenser
+    ReloadCalibration,
+    /// Stops a supervised background task and starts it again, e.g. `mqtt_publisher`
+    RestartTask { name: String },
+}
+
+impl BatchOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            BatchOperation::SetConfig { .. } => "set_config",
+            BatchOperation::ReloadConfig => "reload_config",
+            BatchOperation::ReloadCalibration => "reload_calibration",
+            BatchOperation::RestartTask { .. } => "restart_task",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, utoipa::ToSchema)]
+pub struct BatchRequest {
+    /// Run in order, all or none of them
+    pub operations: Vec<BatchOperation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStepStatus {
+    Applied,
+    /// Already in effect, nothing to do
+    Unchanged,
+    /// Also a restart that failed after the rest of the batch was applied
+    Failed,
+    /// Would have worked, but another step failed or the config would be invalid, so
+    /// nothing was applied
+    NotApplied,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct BatchStepResult {
+    pub op: String,
+    pub status: BatchStepStatus,
+    /// What the step changed, or why it failed
+    pub message: Option<String>,
+}
+
+/// Returned by `POST /admin/batch`, one result per operation in request order.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct BatchResponse {
+    /// False if any step failed or the resulting config is invalid, then nothing was
+    /// applied
+    pub applied: bool,
+    pub steps: Vec<BatchStepResult>,
+    /// Startup checks the config would fail after all steps. They concern the batch as a
+    /// whole, a value may only be invalid together with another step's.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_errors: Vec<FieldError>,
+}
+
+/// Changes the batch makes once every step has been checked.
+struct PendingChanges {
+    app_config: AppConfig,
+    /// Descriptions of the config changes, for the `config_reloaded` event
+    config_changes: Vec<String>,
+    calibration: Option<WeightSensorCalibration>,
+    restarts: Vec<String>,
+}
+
+fn step_result(op: &BatchOperation, result: Result<Option<String>, String>) -> BatchStepResult {
+    let (status, message) = match result {
+        Ok(Some(message)) => (BatchStepStatus::Applied, Some(message)),
+        Ok(None) => (BatchStepStatus::Unchanged, None),
+        Err(e) => (BatchStepStatus::Failed, Some(e)),
+    };
+    BatchStepResult {
+        op: op.name().to_string(),
+        status,
+        message,
+    }
+}
+
+/// Sets `path` in the pending config. Only settings a reload may change are accepted.
+fn set_config(
+    pending: &mut PendingChanges,
+    path: &str,
+    value: &Value,
+) -> Result<Option<String>, String> {
+    if !config_reload::is_config_field(path) {
+        return Err(format!("{} is not a setting of config.yaml", path));
+    }
+    if config_reload::reloadable_setting(path).is_none() {
+        return Err(format!(
+            "{} can't be changed without a restart, edit config.yaml instead",
+            path
+        ));
+    }
+    let mut config_json = serde_json::to_value(&pending.app_config).map_err(|e| e.to_string())?;
+    let old_value = path
+        .split('.')
+        .try_fold(&config_json, |node, key| node.get(key))
+        .cloned()
+        .unwrap_or(Value::Null);
+    if old_value == *value {
+        return Ok(None);
+    }
+    config_reload::set_path(&mut config_json, path, value.clone())?;
+    pending.app_config = serde_json::from_value(config_json)
+        .map_err(|e| format!("Invalid value for {}: {}", path, e))?;
+    pending.config_changes.push(path.to_string());
+    Ok(Some(format!(
+        "{} changed from {} to {}",
+        path, old_value, value
+    )))
+}
+
+fn reload_config(pending: &mut PendingChanges) -> Result<Option<String>, String> {
+    let new_config = config_reload::read_config_file().map_err(|e| e.to_string())?;
+    let (merged, response) = config_reload::merge(&pending.app_config, &new_config)?;
+    pending.app_config = merged;
+    if !response.restart_required.is_empty() {
+        info!(
+            "Config changes that need a restart: {}",
+            response.restart_required.join(", ")
+        );
+    }
+    if response.applied.is_empty() {
+        return Ok(None);
+    }
+    pending
+        .config_changes
+        .extend(response.applied.iter().cloned());
+    let mut message = format!("Applied {}", response.applied.join(", "));
+    if !response.restart_required.is_empty() {
+        message.push_str(&format!(
+            ", after a restart {}",
+            response.restart_required.join(", ")
+        ));
+    }
+    Ok(Some(message))
+}
+
+/// Runs the operations as one transaction. Every step is checked against the state the
+/// steps before it would leave, and only if all of them pass and the resulting config is
+/// valid are the changes applied, config first, then the calibration, then the task
+/// restarts. The state stays locked throughout, so nothing else changes the config in
+/// between. A restart that still fails is reported as a failed step of an applied batch.
+pub async fn run_batch(
+    app_state: &AppStateMutex,
+    operations: &[BatchOperation],
+    user: &str,
+) -> Result<BatchResponse, ApiError> {
+    if operations.is_empty() || operations.len() > config::ADMIN_BATCH_OPERATIONS_MAX {
+        return Err(ApiError::BadRequest(format!(
+            "A batch needs 1 to {} operations",
+            config::ADMIN_BATCH_OPERATIONS_MAX
+        )));
+    }
+
+    let mut state_guard = app_state.lock().await;
+    // the rollback would undo the batch's changes along with the staged ones
+    let changes_config = operations.iter().any(|op| {
+        matches!(
+            op,
+            BatchOperation::SetConfig { .. } | BatchOperation::ReloadConfig
+        )
+    });
+    if changes_config && state_guard.staged_config.is_some() {
+        return Err(ApiError::BadRequest(
+            "A staged config is waiting for confirmation".to_string(),
+        ));
+    }
+    let mut pending = PendingChanges {
+        app_config: state_guard.app_config.clone(),
+        config_changes: Vec::new(),
+        calibration: None,
+        restarts: Vec::new(),
+    };
+
+    let mut steps: Vec<BatchStepResult> = operations
+        .iter()
+        .map(|op| {
+            let result = match op {
+                BatchOperation::SetConfig { path, value } => set_config(&mut pending, path, value),
+                BatchOperation::ReloadConfig => reload_config(&mut pending),
+                BatchOperation::ReloadCalibration => {
+                    if state_guard.status == DispenserStatus::Calibrating {
+                        Err("The weight sensor is being calibrated".to_string())
+                    } else {
+                        weight_monitor::load_calibration_from_file().map(|calibration| {
+                            pending.calibration = Some(calibration);
+                            Some("Calibration reloaded".to_string())
+                        })
+                    }
+                }
+                BatchOperation::RestartTask { name } => {
+                    let running_tasks = supervisor::running_tasks(&state_guard);
+                    if running_tasks.contains(&name.as_str()) {
+                        pending.restarts.push(name.clone());
+                        Ok(Some(format!("Task '{}' restarted", name)))
+                    } else {
+                        Err(format!(
+                            "No running task '{}', running are: {}",
+                            name,
+                            running_tasks.join(", ")
+                        ))
+                    }
+                }
+            };
+            step_result(op, result)
+        })
+        .collect();
+
+    // checked as a whole, a value may only be valid together with a later one
+    let config_errors = config_validation::validate(&pending.app_config);
+    if !config_errors.is_empty()
+        || steps
+            .iter()
+            .any(|step| step.status == BatchStepStatus::Failed)
+    {
+        for step in steps
+            .iter_mut()
+            .filter(|s| s.status != BatchStepStatus::Failed)
+        {
+            step.status = BatchStepStatus::NotApplied;
+        }
+        return Ok(BatchResponse {
+            applied: false,
+            steps,
+            config_errors,
+        });
+    }
+
+    if !pending.config_changes.is_empty() {
+        let message = format!(
+            "Config changed by {} in a batch: {}",
+            user,
+            pending.config_changes.join(", ")
+        );
+        config_reload::apply(&mut state_guard, pending.app_config, message);
+    }
+    if let Some(calibration) = pending.calibration {
+        let _ = state_guard.calibration_tx.send(calibration);
+    }
+    // the tasks were checked above with the state locked, so this only fails if a task
+    // finished in between, after the config was already applied
+    let restart_steps = operations
+        .iter()
+        .zip(steps.iter_mut())
+        .filter(|(op, _)| matches!(op, BatchOperation::RestartTask { .. }));
+    for (op, step) in restart_steps {
+        if let BatchOperation::RestartTask { name } = op
+            && let Err(e) = supervisor::request_restart(&state_guard, name)
+        {
+            warn!("Batch restart of task '{}' failed: {}", name, e);
+            step.status = BatchStepStatus::Failed;
+            step.message = Some(e);
+        }
+    }
+    info!("Admin batch of {} operations run by {}", steps.len(), user);
+    Ok(BatchResponse {
+        applied: true,
+        steps,
+        config_errors: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+          cooldown_ms: 1000
+        "#;
+
+    fn pending() -> PendingChanges {
+        PendingChanges {
+            app_config: config::load_app_config_from_str(CONFIG),
+            config_changes: Vec::new(),
+            calibration: None,
+            restarts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_set_config() {
+        let mut pending = pending();
+        assert_eq!(
+            set_config(&mut pending, "motor.cooldown_ms", &serde_json::json!(2000)),
+            Ok(Some(
+                "motor.cooldown_ms changed from 1000 to 2000".to_string()
+            ))
+        );
+        assert_eq!(pending.app_config.motor.cooldown_ms, Some(2000));
+        // setting it again is a no-op
+        assert_eq!(
+            set_config(&mut pending, "motor.cooldown_ms", &serde_json::json!(2000)),
+            Ok(None)
+        );
+
+        // sections that aren't configured yet are created
+        set_config(&mut pending, "triggers.daily_limit", &serde_json::json!(6)).unwrap();
+        assert_eq!(
+            pending.app_config.triggers.as_ref().unwrap().daily_limit,
+            Some(6)
+        );
+        assert_eq!(
+            pending.config_changes,
+            vec!["motor.cooldown_ms", "triggers.daily_limit"]
+        );
+
+        assert!(
+            set_config(
+                &mut pending,
+                "motor.cooldown_ms",
+                &serde_json::json!("soon")
+            )
+            .is_err()
+        );
+        let error = set_config(
+            &mut pending,
+            "motor.motor_type",
+            &serde_json::json!("StepperNema14"),
+        )
+        .unwrap_err();
+        assert!(error.contains("restart"), "{}", error);
+
+        // paths through a value or a list are rejected, not followed
+        for path in [
+            "motor.cooldown_ms.x",
+            "triggers.quiet_hours.0",
+            "triggers.daily_limt",
+        ] {
+            let error = set_config(&mut pending, path, &serde_json::json!(1)).unwrap_err();
+            assert!(error.contains("not a setting"), "{}", error);
+        }
+        assert_eq!(pending.app_config.motor.cooldown_ms, Some(2000));
+    }
+}
@@ -0,0 +1,244 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use crate::application_state::AppStateMutex;
+use crate::config::{self, AssistantConfig};
+use crate::error::ApiError;
+use crate::services::triggers::{self, AssistantTrigger};
+
+/// The dispenser is exposed to assistants as a single, non-reversible scene.
+const DEVICE_ID: &str = "treat-dispenser";
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GoogleRequest {
+    pub request_id: String,
+    pub inputs: Vec<GoogleInput>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleInput {
+    pub intent: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AlexaRequest {
+    pub directive: AlexaDirective,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AlexaDirective {
+    pub header: AlexaHeader,
+    pub endpoint: Option<AlexaEndpoint>,
+    #[serde(default)]
+    pub payload: Value,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AlexaHeader {
+    pub namespace: String,
+    pub name: String,
+    pub correlation_token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AlexaEndpoint {
+    pub endpoint_id: Option<String>,
+    pub scope: Option<AlexaScope>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AlexaScope {
+    pub token: String,
+}
+
+pub fn is_valid_token(assistant_config: &AssistantConfig, token: &str) -> bool {
+    !token.is_empty() && assistant_config.access_tokens.iter().any(|t| t == token)
+}
+
+/// Handles a Google smart home intent. The bearer token must already be validated.
+pub async fn handle_google(
+    app_state: AppStateMutex,
+    assistant_config: &AssistantConfig,
+    request: GoogleRequest,
+) -> Result<Value, ApiError> {
+    let intent = request
+        .inputs
+        .first()
+        .map(|input| input.intent.as_str())
+        .ok_or_else(|| ApiError::BadRequest("Request has no inputs".to_string()))?;
+
+    let payload = match intent {
+        "action.devices.SYNC" => {
+            let agent_user_id = app_state.lock().await.app_config.api.admin_user.clone();
+            json!({
+                "agentUserId": agent_user_id,
+                "devices": [{
+                    "id": DEVICE_ID,
+                    "type": "action.devices.types.SCENE",
+                    "traits": ["action.devices.traits.Scene"],
+                    "name": { "name": device_name(assistant_config) },
+                    "willReportState": false,
+                    "attributes": { "sceneReversible": false },
+                    "deviceInfo": {
+                        "manufacturer": "crungo-net",
+                        "model": "treat-dispenser-api",
+                        "swVersion": env!("CARGO_PKG_VERSION"),
+                    },
+                }],
+            })
+        }
+        "action.devices.QUERY" => json!({
+            "devices": { (DEVICE_ID): { "online": true, "status": "SUCCESS" } },
+        }),
+        "action.devices.EXECUTE" => {
+            let command = match dispense(app_state).await {
+                Ok(()) => json!({ "ids": [DEVICE_ID], "status": "SUCCESS" }),
+                Err(e) => {
+                    json!({ "ids": [DEVICE_ID], "status": "ERROR", "errorCode": google_error_code(&e) })
+                }
+            };
+            json!({ "commands": [command] })
+        }
+        "action.devices.DISCONNECT" => {
+            info!("Assistant account unlinked");
+            return Ok(json!({}));
+        }
+        other => {
+            return Err(ApiError::BadRequest(format!(
+                "Unsupported intent '{}'",
+                other
+            )));
+        }
+    };
+
+    Ok(json!({ "requestId": request.request_id, "payload": payload }))
+}
+
+/// Handles an Alexa smart home directive. Alexa carries the linked-account token in the
+/// directive itself and expects errors as a normal response, so this validates the token.
+pub async fn handle_alexa(
+    app_state: AppStateMutex,
+    assistant_config: &AssistantConfig,
+    request: AlexaRequest,
+) -> Value {
+    let directive = request.directive;
+    let correlation_token = directive.header.correlation_token.clone();
+
+    let token = directive
+        .endpoint
+        .as_ref()
+        .and_then(|e| e.scope.as_ref())
+        .map(|s| s.token.clone())
+        .or_else(|| {
+            directive.payload["scope"]["token"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .unwrap_or_default();
+    if !is_valid_token(assistant_config, &token) {
+        warn!("Rejected Alexa directive with an invalid token");
+        return alexa_error(
+            correlation_token,
+            "INVALID_AUTHORIZATION_CREDENTIAL",
+            "Unknown access token",
+        );
+    }
+
+    match (
+        directive.header.namespace.as_str(),
+        directive.header.name.as_str(),
+    ) {
+        ("Alexa.Discovery", "Discover") => json!({
+            "event": {
+                "header": alexa_header("Alexa.Discovery", "Discover.Response", None),
+                "payload": {
+                    "endpoints": [{
+                        "endpointId": DEVICE_ID,
+                        "manufacturerName": "crungo-net",
+                        "friendlyName": device_name(assistant_config),
+                        "description": "Treat dispenser",
+                        "displayCategories": ["SCENE_TRIGGER"],
+                        "capabilities": [
+                            { "type": "AlexaInterface", "interface": "Alexa.SceneController", "version": "3", "supportsDeactivation": false },
+                            { "type": "AlexaInterface", "interface": "Alexa", "version": "3" },
+                        ],
+                    }],
+                },
+            },
+        }),
+        ("Alexa.SceneController", "Activate") => match dispense(app_state).await {
+            Ok(()) => json!({
+                "event": {
+                    "header": alexa_header("Alexa.SceneController", "ActivationStarted", correlation_token),
+                    "endpoint": { "endpointId": DEVICE_ID },
+                    "payload": {
+                        "cause": { "type": "VOICE_INTERACTION" },
+                        "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                    },
+                },
+                "context": {},
+            }),
+            Err(e) => {
+                let error_type = match e {
+                    ApiError::Busy(_) | ApiError::RateLimited(_) => "ENDPOINT_BUSY",
+                    _ => "ENDPOINT_UNREACHABLE",
+                };
+                alexa_error(correlation_token, error_type, &e.to_string())
+            }
+        },
+        (namespace, name) => alexa_error(
+            correlation_token,
+            "INVALID_DIRECTIVE",
+            &format!("Unsupported directive {}.{}", namespace, name),
+        ),
+    }
+}
+
+async fn dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
+    let result = triggers::request_dispense(&app_state, &AssistantTrigger).await;
+    if let Err(e) = &result {
+        warn!("Assistant dispense request failed: {}", e);
+    }
+    result
+}
+
+fn device_name(assistant_config: &AssistantConfig) -> String {
+    assistant_config
+        .device_name
+        .clone()
+        .unwrap_or_else(|| config::ASSISTANT_DEVICE_NAME_DEFAULT.to_string())
+}
+
+fn google_error_code(error: &ApiError) -> &'static str {
+    match error {
+        ApiError::Busy(_) | ApiError::RateLimited(_) => "deviceBusy",
+        _ => "hardwareFailure",
+    }
+}
+
+fn alexa_header(namespace: &str, name: &str, correlation_token: Option<String>) -> Value {
+    let mut header = json!({
+        "namespace": namespace,
+        "name": name,
+        "payloadVersion": "3",
+        "messageId": format!("{:032x}", rand::random::<u128>()),
+    });
+    if let Some(token) = correlation_token {
+        header["correlationToken"] = Value::String(token);
+    }
+    header
+}
+
+fn alexa_error(correlation_token: Option<String>, error_type: &str, message: &str) -> Value {
+    json!({
+        "event": {
+            "header": alexa_header("Alexa", "ErrorResponse", correlation_token),
+            "endpoint": { "endpointId": DEVICE_ID },
+            "payload": { "type": error_type, "message": message },
+        },
+    })
+}
@@ -0,0 +1,49 @@
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use tracing::error;
+
+use crate::services::events::EventKind;
+use crate::utils::{datetime, filesystem};
+
+/// User recorded for actions the dispenser takes on its own, e.g. when a timeout ends.
+pub const SYSTEM_USER: &str = "system";
+
+/// One line of `audit.log`.
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub time: String,
+    pub user: String,
+    pub action: EventKind,
+    pub message: String,
+}
+
+/// Appends an action to `audit.log` in the data directory, one JSON line each. Unlike the
+/// event log, the file keeps every entry and survives restarts. A failed write is only
+/// logged.
+pub async fn record(user: &str, action: EventKind, message: &str) {
+    let entry = AuditEntry {
+        time: datetime::get_formatted_current_timestamp(),
+        user: user.to_string(),
+        action,
+        message: message.to_string(),
+    };
+    let result =
+        tokio::task::spawn_blocking(move || append(&filesystem::get_audit_log_path(), &entry))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result);
+    if let Err(e) = result {
+        error!("Failed to write audit log entry: {}", e);
+    }
+}
+
+fn append(path: &str, entry: &AuditEntry) -> Result<(), String> {
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())
+}
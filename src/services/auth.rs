@@ -1,44 +1,157 @@
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
 use jsonwebtoken::{EncodingKey, Header, encode};
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, warn};
 
+use crate::config::{ApiConfig, SessionCookieConfig};
 use crate::{application_state::AppStateMutex, error::ApiError};
 
-#[derive(Serialize, Deserialize, Clone)]
+/// HttpOnly cookie holding the JWT of a cookie login.
+pub const SESSION_COOKIE: &str = "dispenser_session";
+/// Script-readable cookie holding the CSRF token of a cookie login.
+pub const CSRF_COOKIE: &str = "dispenser_csrf";
+/// Requests authenticated by the session cookie must echo the CSRF token in this header,
+/// except for `GET` and `HEAD`.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+const TOKEN_LIFETIME_SECS: i64 = 7 * 24 * 3600;
+
+#[derive(Serialize, Deserialize, Clone, utoipa::ToSchema)]
 pub struct LoginRequest {
     pub username: String,
     password: String,
+    /// Set the JWT as an HttpOnly cookie instead of returning it, requires
+    /// `api.session_cookies`
+    #[serde(default)]
+    pub session_cookie: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
 pub struct LoginResponse {
     pub token: String,
     pub expires_at: u64,
 }
 
+/// Response to a cookie login. The JWT is only in the session cookie, scripts get the
+/// CSRF token to send back in the `X-CSRF-Token` header.
+#[derive(Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub csrf_token: String,
+    pub expires_at: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Claims {
-    sub: String,
-    exp: u64,
+    pub sub: String,
+    pub exp: u64,
+    /// Only set for cookie logins, the middleware accepts the session cookie only for
+    /// tokens carrying one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf: Option<String>,
+}
+
+pub fn new_csrf_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// `Set-Cookie` values for a cookie login. Both cookies are `SameSite=Strict`, so browsers
+/// don't attach them to requests started by other sites.
+pub fn session_cookies(token: &str, csrf_token: &str, cookie_config: &SessionCookieConfig) -> [String; 2] {
+    let attributes = cookie_attributes(TOKEN_LIFETIME_SECS, cookie_config);
+    [
+        format!("{}={}; HttpOnly; {}", SESSION_COOKIE, token, attributes),
+        format!("{}={}; {}", CSRF_COOKIE, csrf_token, attributes),
+    ]
+}
+
+/// `Set-Cookie` values that make the browser drop the session cookies.
+pub fn expired_session_cookies(cookie_config: &SessionCookieConfig) -> [String; 2] {
+    let attributes = cookie_attributes(0, cookie_config);
+    [
+        format!("{}=; HttpOnly; {}", SESSION_COOKIE, attributes),
+        format!("{}=; {}", CSRF_COOKIE, attributes),
+    ]
+}
+
+fn cookie_attributes(max_age_secs: i64, cookie_config: &SessionCookieConfig) -> String {
+    let secure = if cookie_config.secure.unwrap_or(true) {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!("Path=/; Max-Age={}; SameSite=Strict{}", max_age_secs, secure)
+}
+
+/// Hashes a password for `api.admin_password`, printed by `--hash-password`.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+        .map_err(|e| format!("Failed to encode salt: {}", e))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+fn is_password_hash(admin_password: &str) -> bool {
+    admin_password.starts_with("$argon2")
+}
+
+/// Checks a password against `api.admin_password`, an Argon2 hash or, from configs
+/// written before hashing was supported, the plaintext password.
+fn verify_password(password: &str, admin_password: &str) -> bool {
+    if !is_password_hash(admin_password) {
+        return password == admin_password;
+    }
+    match PasswordHash::new(admin_password) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(e) => {
+            error!("api.admin_password is not a valid Argon2 hash: {}", e);
+            false
+        }
+    }
+}
+
+/// Warns at startup if `api.admin_password` is still in plaintext.
+pub fn check_admin_password(api_config: &ApiConfig) {
+    if !is_password_hash(&api_config.admin_password) {
+        warn!(
+            "api.admin_password is stored in plaintext, replace it with the hash printed by --hash-password"
+        );
+    }
 }
 
 /// Validates user credentials and generates a JWT token if successful.
 /// The token is valid for one week.
+///
+/// * `csrf` - CSRF token of a cookie login, embedded in the JWT.
 pub async fn handle_login(
     app_state: AppStateMutex,
     payload: LoginRequest,
+    csrf: Option<String>,
 ) -> Result<LoginResponse, ApiError> {
-    let config = &app_state.lock().await.app_config;
-    if payload.username == config.api.admin_user && payload.password == config.api.admin_password {
-        // Create JWT token that expires in one year
+    let (admin_user, admin_password) = {
+        let api_config = &app_state.lock().await.app_config.api;
+        (api_config.admin_user.clone(), api_config.admin_password.clone())
+    };
+    // hashing takes a while by design, keep it off the async workers
+    let password = payload.password.clone();
+    let password_valid =
+        tokio::task::spawn_blocking(move || verify_password(&password, &admin_password))
+            .await
+            .map_err(|e| ApiError::Internal(format!("Password check failed: {}", e)))?;
+    if payload.username == admin_user && password_valid {
         let expiration = chrono::Utc::now()
-            .checked_add_signed(chrono::Duration::days(7))
+            .checked_add_signed(chrono::Duration::seconds(TOKEN_LIFETIME_SECS))
             .expect("invalid timestamp")
             .timestamp() as u64;
 
         let claims = Claims {
             sub: payload.username,
             exp: expiration,
+            csrf,
         };
 
         let jwt_secret_env_result = std::env::var("DISPENSER_JWT_SECRET");
@@ -72,3 +185,22 @@ pub async fn handle_login(
         Err(ApiError::Unauthorized)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_password() {
+        let hash = hash_password("hunter2").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+        // every hash gets its own salt
+        assert_ne!(hash, hash_password("hunter2").unwrap());
+
+        // configs from before hashing keep working
+        assert!(verify_password("hunter2", "hunter2"));
+        assert!(!verify_password("hunter2", "$argon2id$not-a-hash"));
+    }
+}
@@ -0,0 +1,284 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::config::AppConfig;
+use crate::utils::filesystem;
+
+/// Name of the manifest entry written into every backup archive. It identifies the
+/// archive as a dispenser backup and is never restored into the data directory.
+const MANIFEST_FILE_NAME: &str = "backup_manifest.json";
+
+/// Subdirectory of the data dir where pre-restore snapshots are kept.
+const SNAPSHOT_DIR_NAME: &str = "backups";
+
+/// Upper bound for a single file inside an uploaded archive, protects against gzip bombs.
+const MAX_RESTORED_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupManifest {
+    pub version: String,
+    pub created_at: String,
+    pub files: Vec<String>,
+}
+
+/// A file extracted from a validated backup archive.
+pub struct BackupFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, utoipa::ToSchema)]
+pub struct RestoreResponse {
+    pub message: String,
+    pub restored_files: Vec<String>,
+    pub snapshot: String,
+}
+
+/// Builds a tar.gz of every file in the data directory: config, weight sensor
+/// calibration and any other persisted dispenser data. Subdirectories (including
+/// earlier snapshots) are not included.
+pub fn create_backup() -> Result<Vec<u8>, String> {
+    let data_dir = filesystem::get_data_dir();
+    let file_names = list_data_files(&data_dir)?;
+
+    let manifest = BackupManifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        files: file_names.clone(),
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    append_bytes(&mut builder, MANIFEST_FILE_NAME, &manifest_json)?;
+    for name in &file_names {
+        let contents = std::fs::read(Path::new(&data_dir).join(name))
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        append_bytes(&mut builder, name, &contents)?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to write backup archive: {}", e))
+}
+
+/// Unpacks an uploaded archive in memory and checks that it is a dispenser backup whose
+/// files can be restored safely. Nothing is written to disk.
+pub fn validate_backup(archive: &[u8]) -> Result<Vec<BackupFile>, String> {
+    let mut tar_archive = tar::Archive::new(GzDecoder::new(archive));
+    let entries = tar_archive
+        .entries()
+        .map_err(|e| format!("Not a valid tar.gz archive: {}", e))?;
+
+    let mut manifest: Option<BackupManifest> = None;
+    let mut files = Vec::new();
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Not a valid tar.gz archive: {}", e))?;
+        let name = entry
+            .path()
+            .map_err(|e| format!("Invalid entry path: {}", e))?
+            .to_string_lossy()
+            .to_string();
+
+        if !entry.header().entry_type().is_file() {
+            return Err(format!("Unexpected non-file entry '{}'", name));
+        }
+        if !is_plain_file_name(&name) {
+            return Err(format!("Unexpected entry path '{}'", name));
+        }
+        if entry.size() > MAX_RESTORED_FILE_BYTES {
+            return Err(format!("Entry '{}' is too large", name));
+        }
+
+        let mut contents = Vec::new();
+        entry
+            .take(MAX_RESTORED_FILE_BYTES)
+            .read_to_end(&mut contents)
+            .map_err(|e| format!("Failed to read entry '{}': {}", name, e))?;
+
+        if name == MANIFEST_FILE_NAME {
+            manifest = Some(
+                serde_json::from_slice(&contents)
+                    .map_err(|e| format!("Invalid backup manifest: {}", e))?,
+            );
+            continue;
+        }
+
+        validate_file_contents(&name, &contents)?;
+        files.push(BackupFile { name, contents });
+    }
+
+    let manifest = manifest
+        .ok_or_else(|| "Archive is not a dispenser backup, manifest is missing".to_string())?;
+    for expected in &manifest.files {
+        if !files.iter().any(|f| &f.name == expected) {
+            return Err(format!("Backup is incomplete, '{}' is missing", expected));
+        }
+    }
+
+    info!(
+        "Validated backup created at {} by version {} ({} files)",
+        manifest.created_at,
+        manifest.version,
+        files.len()
+    );
+    Ok(files)
+}
+
+/// Writes validated backup files into the data directory. A snapshot of the current
+/// data directory is saved first so a bad restore can be undone.
+///
+/// Returns the names of the restored files and the path of the snapshot.
+pub fn apply_backup(files: &[BackupFile]) -> Result<(Vec<String>, String), String> {
+    let data_dir = filesystem::get_data_dir();
+    let snapshot_path = save_snapshot(&data_dir, "pre-restore")?;
+    info!("Saved pre-restore snapshot to {}", snapshot_path);
+
+    let mut restored = Vec::with_capacity(files.len());
+    for file in files {
+        // write to a temporary file first so a failed write never leaves a truncated file behind
+        let target = Path::new(&data_dir).join(&file.name);
+        let temp = Path::new(&data_dir).join(format!(".{}.restore", file.name));
+        std::fs::write(&temp, &file.contents)
+            .and_then(|_| std::fs::rename(&temp, &target))
+            .map_err(|e| format!("Failed to restore {}: {}", file.name, e))?;
+        restored.push(file.name.clone());
+    }
+
+    Ok((restored, snapshot_path))
+}
+
+/// Saves a backup of the current data directory to `<data_dir>/backups/<label>-<timestamp>.tar.gz`.
+pub fn save_snapshot(data_dir: &str, label: &str) -> Result<String, String> {
+    let snapshot_dir = Path::new(data_dir).join(SNAPSHOT_DIR_NAME);
+    std::fs::create_dir_all(&snapshot_dir)
+        .map_err(|e| format!("Failed to create snapshot directory: {}", e))?;
+
+    let archive = create_backup()?;
+    let snapshot_path = snapshot_dir.join(format!("{}-{}.tar.gz", label, backup_timestamp()));
+    std::fs::write(&snapshot_path, archive)
+        .map_err(|e| format!("Failed to write snapshot: {}", e))?;
+    Ok(snapshot_path.to_string_lossy().to_string())
+}
+
+/// Timestamp used in backup file names, e.g. `20250101-120000`.
+pub fn backup_timestamp() -> String {
+    chrono::Local::now().format("%Y%m%d-%H%M%S").to_string()
+}
+
+fn list_data_files(data_dir: &str) -> Result<Vec<String>, String> {
+    let read_dir = match std::fs::read_dir(data_dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!(
+                "Data directory {} does not exist, backup will be empty",
+                data_dir
+            );
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(format!("Failed to read data directory {}: {}", data_dir, e)),
+    };
+
+    let mut names: Vec<String> = read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| is_plain_file_name(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Only flat, non-hidden file names are accepted, which rules out path traversal
+/// (`../`, absolute paths) as well as our own temporary files.
+fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains('/') && !name.contains('\\')
+}
+
+fn validate_file_contents(name: &str, contents: &[u8]) -> Result<(), String> {
+    if name.ends_with(".yaml") || name.ends_with(".yml") {
+        let text =
+            std::str::from_utf8(contents).map_err(|_| format!("'{}' is not valid UTF-8", name))?;
+        if name == "config.yaml" {
+            serde_yaml::from_str::<AppConfig>(text)
+                .map_err(|e| format!("'{}' is not a valid config: {}", name, e))?;
+        } else {
+            serde_yaml::from_str::<serde_yaml::Value>(text)
+                .map_err(|e| format!("'{}' is not valid YAML: {}", name, e))?;
+        }
+    } else if name.ends_with(".json") {
+        serde_json::from_slice::<serde_json::Value>(contents)
+            .map_err(|e| format!("'{}' is not valid JSON: {}", name, e))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn append_bytes<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+) -> Result<(), String> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, name, contents)
+        .map_err(|e| format!("Failed to add {} to backup: {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (name, contents) in entries {
+            append_bytes(&mut builder, name, contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_validate_backup() {
+        let manifest = br#"{"version":"1.0.0","created_at":"2025-01-01T00:00:00Z","files":["weight_sensor_calibration.json"]}"#;
+        let calibration = br#"{"tare_raw":100,"scale":0.5}"#;
+
+        let valid = build_archive(&[
+            (MANIFEST_FILE_NAME, manifest),
+            ("weight_sensor_calibration.json", calibration),
+        ]);
+        let files = validate_backup(&valid).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "weight_sensor_calibration.json");
+
+        let missing_manifest = build_archive(&[("weight_sensor_calibration.json", calibration)]);
+        assert!(validate_backup(&missing_manifest).is_err());
+
+        let missing_file = build_archive(&[(MANIFEST_FILE_NAME, manifest)]);
+        assert!(validate_backup(&missing_file).is_err());
+
+        let corrupt_json = build_archive(&[
+            (MANIFEST_FILE_NAME, manifest),
+            ("weight_sensor_calibration.json", b"{not json"),
+        ]);
+        assert!(validate_backup(&corrupt_json).is_err());
+
+        assert!(validate_backup(b"definitely not a tarball").is_err());
+    }
+
+    #[test]
+    fn test_is_plain_file_name() {
+        assert!(is_plain_file_name("config.yaml"));
+        assert!(!is_plain_file_name("../config.yaml"));
+        assert!(!is_plain_file_name("/etc/passwd"));
+        assert!(!is_plain_file_name(".config.yaml.restore"));
+    }
+}
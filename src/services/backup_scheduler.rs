@@ -0,0 +1,438 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::application_state::ApplicationState;
+use crate::config::{self, BackupConfig, LocalBackupConfig, S3BackupConfig, SftpBackupConfig};
+use crate::services::backup;
+use crate::services::energy;
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::utils::{datetime, state_helpers};
+
+/// Delay before the first scheduled backup after startup, so a boot loop doesn't
+/// hammer the destination but a device that reboots daily still gets backed up.
+const FIRST_BACKUP_DELAY: Duration = Duration::from_secs(60);
+
+/// How often a backup deferred by the `energy` policy is retried.
+const DEFERRED_BACKUP_RETRY: Duration = Duration::from_secs(15 * 60);
+
+const BACKUP_FILE_PREFIX: &str = "treat-dispenser-backup-";
+
+/// Outcome of the most recent scheduled backup, reported in `/status`.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct BackupStatus {
+    pub time: String,
+    pub success: bool,
+    /// Where the backup was stored, or the error if it failed
+    pub detail: String,
+}
+
+enum BackupDestination {
+    Local(LocalBackupConfig),
+    S3(S3BackupConfig),
+    Sftp(SftpBackupConfig),
+}
+
+impl BackupDestination {
+    fn from_config(backup_config: &BackupConfig) -> Result<Self, String> {
+        let missing = |section: &str| {
+            format!(
+                "Backup destination is '{}' but the '{}' section is missing",
+                backup_config.destination, section
+            )
+        };
+        match backup_config.destination.as_str() {
+            "local" => backup_config
+                .local
+                .clone()
+                .map(BackupDestination::Local)
+                .ok_or_else(|| missing("local")),
+            "s3" => backup_config
+                .s3
+                .clone()
+                .map(BackupDestination::S3)
+                .ok_or_else(|| missing("s3")),
+            "sftp" => backup_config
+                .sftp
+                .clone()
+                .map(BackupDestination::Sftp)
+                .ok_or_else(|| missing("sftp")),
+            other => Err(format!("Unsupported backup destination '{}'", other)),
+        }
+    }
+
+    /// Stores the archive and returns a description of where it went.
+    async fn store(&self, file_name: &str, archive: Vec<u8>) -> Result<String, String> {
+        match self {
+            BackupDestination::Local(local) => store_local(local, file_name, &archive),
+            BackupDestination::S3(s3) => upload_s3(s3, file_name, archive).await,
+            BackupDestination::Sftp(sftp) => upload_sftp(sftp, file_name, &archive).await,
+        }
+    }
+}
+
+/// Starts periodic backups if a `backup` section is configured. Each run builds the same
+/// archive as `GET /admin/backup` and stores it at the configured destination. Runs
+/// deferred by the `energy` policy are retried every 15 minutes.
+pub async fn start_backup_scheduler(app_state: &Arc<Mutex<ApplicationState>>) {
+    let backup_config = match app_state.lock().await.app_config.backup.clone() {
+        Some(c) if c.enabled.unwrap_or(true) => c,
+        _ => return,
+    };
+
+    let destination = match BackupDestination::from_config(&backup_config) {
+        Ok(destination) => Arc::new(destination),
+        Err(e) => {
+            error!("Scheduled backups disabled: {}", e);
+            return;
+        }
+    };
+
+    let interval = Duration::from_secs(
+        backup_config
+            .interval_hours
+            .unwrap_or(config::BACKUP_INTERVAL_HOURS_DEFAULT)
+            .max(1)
+            * 3600,
+    );
+    info!(
+        "Scheduled backups to '{}' every {} hours",
+        backup_config.destination,
+        interval.as_secs() / 3600
+    );
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "backup_scheduler", move || {
+        let app_state = Arc::clone(&app_state_clone);
+        let destination = Arc::clone(&destination);
+        async move {
+            tokio::time::sleep(FIRST_BACKUP_DELAY).await;
+            loop {
+                while energy::defer_task(&app_state, "scheduled backup").await {
+                    tokio::time::sleep(DEFERRED_BACKUP_RETRY).await;
+                }
+                run_backup(&app_state, &destination).await;
+                tokio::time::sleep(interval).await;
+            }
+        }
+    });
+}
+
+async fn run_backup(app_state: &Arc<Mutex<ApplicationState>>, destination: &BackupDestination) {
+    let file_name = format!(
+        "{}{}.tar.gz",
+        BACKUP_FILE_PREFIX,
+        backup::backup_timestamp()
+    );
+
+    let result = match tokio::task::spawn_blocking(backup::create_backup).await {
+        Ok(Ok(archive)) => destination.store(&file_name, archive).await,
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(format!("Backup task failed: {}", e)),
+    };
+
+    let status = BackupStatus {
+        time: datetime::get_formatted_current_timestamp(),
+        success: result.is_ok(),
+        detail: match &result {
+            Ok(location) => location.clone(),
+            Err(e) => e.clone(),
+        },
+    };
+
+    let event_bus = app_state.lock().await.event_bus.clone();
+    match result {
+        Ok(location) => {
+            info!("Scheduled backup stored at {}", location);
+            event_bus.publish(
+                EventKind::BackupCompleted,
+                format!("Backup stored at {}", location),
+            );
+        }
+        Err(e) => {
+            let message = format!("Scheduled backup failed: {}", e);
+            warn!("{}", message);
+            state_helpers::record_error(app_state, &message).await;
+            event_bus.publish(EventKind::BackupFailed, message);
+        }
+    }
+
+    app_state.lock().await.last_backup = Some(status);
+}
+
+fn store_local(
+    local: &LocalBackupConfig,
+    file_name: &str,
+    archive: &[u8],
+) -> Result<String, String> {
+    let directory = Path::new(&local.directory);
+    std::fs::create_dir_all(directory)
+        .map_err(|e| format!("Failed to create {}: {}", local.directory, e))?;
+
+    let path = directory.join(file_name);
+    std::fs::write(&path, archive)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    // timestamps in the names sort chronologically, so the oldest come first
+    let keep = local
+        .keep
+        .unwrap_or(config::BACKUP_LOCAL_KEEP_DEFAULT)
+        .max(1);
+    let mut existing: Vec<_> = std::fs::read_dir(directory)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| {
+            p.file_name()
+                .map(|n| n.to_string_lossy().starts_with(BACKUP_FILE_PREFIX))
+                .unwrap_or(false)
+        })
+        .collect();
+    existing.sort();
+    if existing.len() > keep {
+        for old in &existing[..existing.len() - keep] {
+            if let Err(e) = std::fs::remove_file(old) {
+                warn!("Failed to remove old backup {}: {}", old.display(), e);
+            }
+        }
+    }
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+async fn upload_s3(
+    s3: &S3BackupConfig,
+    file_name: &str,
+    archive: Vec<u8>,
+) -> Result<String, String> {
+    let key = format!("{}{}", s3.prefix.as_deref().unwrap_or(""), file_name);
+    let url = format!(
+        "{}/{}/{}",
+        s3.endpoint.trim_end_matches('/'),
+        s3.bucket,
+        uri_encode(&key, false)
+    );
+    let parsed_url =
+        reqwest::Url::parse(&url).map_err(|e| format!("Invalid S3 endpoint: {}", e))?;
+    let host = match (parsed_url.host_str(), parsed_url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err("Invalid S3 endpoint: missing host".to_string()),
+    };
+
+    let now = chrono::Utc::now();
+    let signed = sign_s3_put(
+        &SigningParams {
+            access_key_id: &s3.access_key_id,
+            secret_access_key: &s3.secret_access_key,
+            region: s3
+                .region
+                .as_deref()
+                .unwrap_or(config::BACKUP_S3_REGION_DEFAULT),
+            amz_date: &now.format("%Y%m%dT%H%M%SZ").to_string(),
+        },
+        &host,
+        parsed_url.path(),
+        &archive,
+    );
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(300))
+        .build()
+        .map_err(|e| e.to_string())?;
+    client
+        .put(parsed_url)
+        .header("x-amz-date", signed.amz_date)
+        .header("x-amz-content-sha256", signed.payload_hash)
+        .header("authorization", signed.authorization)
+        .header("content-type", "application/gzip")
+        .body(archive)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("S3 upload failed: {}", e))?;
+
+    Ok(format!("s3://{}/{}", s3.bucket, key))
+}
+
+async fn upload_sftp(
+    sftp: &SftpBackupConfig,
+    file_name: &str,
+    archive: &[u8],
+) -> Result<String, String> {
+    let local_path = std::env::temp_dir().join(file_name);
+    std::fs::write(&local_path, archive)
+        .map_err(|e| format!("Failed to write temporary backup file: {}", e))?;
+
+    let remote_path = format!(
+        "{}/{}",
+        sftp.remote_directory.trim_end_matches('/'),
+        file_name
+    );
+    let batch = format!("put \"{}\" \"{}\"\n", local_path.display(), remote_path);
+
+    let mut command = tokio::process::Command::new("sftp");
+    command
+        .arg("-b")
+        .arg("-")
+        .arg("-P")
+        .arg(
+            sftp.port
+                .unwrap_or(config::BACKUP_SFTP_PORT_DEFAULT)
+                .to_string(),
+        )
+        .arg("-o")
+        .arg("BatchMode=yes");
+    if let Some(identity_file) = &sftp.identity_file {
+        command.arg("-i").arg(identity_file);
+    }
+    command
+        .arg(format!("{}@{}", sftp.user, sftp.host))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped());
+
+    let result = async {
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to run sftp: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            use tokio::io::AsyncWriteExt;
+            stdin
+                .write_all(batch.as_bytes())
+                .await
+                .map_err(|e| format!("Failed to write sftp batch: {}", e))?;
+        }
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| format!("sftp did not complete: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "sftp exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+    .await;
+
+    let _ = std::fs::remove_file(&local_path);
+    result.map(|_| format!("sftp://{}@{}{}", sftp.user, sftp.host, remote_path))
+}
+
+struct SigningParams<'a> {
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    region: &'a str,
+    /// Request time as `YYYYMMDDTHHMMSSZ`
+    amz_date: &'a str,
+}
+
+struct SignedHeaders {
+    amz_date: String,
+    payload_hash: String,
+    authorization: String,
+}
+
+/// Signs a single-part S3 PUT with AWS Signature Version 4. Only `host`,
+/// `x-amz-content-sha256` and `x-amz-date` are signed, which every S3-compatible
+/// store accepts.
+fn sign_s3_put(params: &SigningParams, host: &str, path: &str, payload: &[u8]) -> SignedHeaders {
+    let payload_hash = hex::encode(Sha256::digest(payload));
+    let date = &params.amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, params.region);
+    let signed_header_names = "host;x-amz-content-sha256;x-amz-date";
+
+    let canonical_request = format!(
+        "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        path, host, payload_hash, params.amz_date, signed_header_names, payload_hash
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        params.amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let signing_key = signing_key(params.secret_access_key, date, params.region, "s3");
+    let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    SignedHeaders {
+        amz_date: params.amz_date.to_string(),
+        payload_hash,
+        authorization: format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            params.access_key_id, scope, signed_header_names, signature
+        ),
+    }
+}
+
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters, and `/` unless
+/// `encode_slash` is set, as SigV4 requires for object keys.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_key_matches_aws_example() {
+        // example from the AWS Signature Version 4 documentation
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(
+            uri_encode("pets/kitchen dispenser+1.tar.gz", false),
+            "pets/kitchen%20dispenser%2B1.tar.gz"
+        );
+        assert_eq!(uri_encode("a/b", true), "a%2Fb");
+    }
+}
@@ -0,0 +1,457 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{MissedTickBehavior, interval};
+use tracing::{error, info, trace, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{self, BowlConfig};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::services::weight_monitor;
+use crate::utils::{datetime, filesystem};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+/// Samples averaged into one bowl reading, about one per second.
+const SAMPLES_PER_READING: usize = 5;
+/// Treats are still landing and bouncing this long after a dispense.
+const DISPENSE_QUIET: Duration = Duration::from_secs(30);
+/// Share of `min_drop_grams` the weight must fall by to count as still eating.
+const STILL_EATING_FRACTION: f32 = 0.2;
+
+/// How the pet reacted to a dispense.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedingResponse {
+    AteImmediately,
+    AteLater,
+    Ignored,
+}
+
+/// A meal from the bowl, published with `bowl_emptied` events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct BowlMeal {
+    /// Estimated from the weight decrease
+    pub grams: f32,
+    pub duration_secs: u64,
+    /// Unset if there was no dispense the meal could be attributed to
+    pub minutes_after_dispense: Option<u64>,
+    pub response: Option<FeedingResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct MealRecord {
+    pub time: String,
+    pub grams: f32,
+}
+
+/// Meal totals and reactions to dispenses, persisted so they survive restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, utoipa::ToSchema)]
+pub struct BowlStats {
+    pub meal_count: u64,
+    pub eaten_grams: f32,
+    pub last_meal: Option<MealRecord>,
+    pub ate_immediately: u64,
+    pub ate_later: u64,
+    pub ignored: u64,
+}
+
+impl BowlStats {
+    fn record_meal(&mut self, meal: &BowlMeal) {
+        self.meal_count += 1;
+        self.eaten_grams += meal.grams;
+        self.last_meal = Some(MealRecord {
+            time: datetime::get_formatted_current_timestamp(),
+            grams: meal.grams,
+        });
+        if let Some(response) = meal.response {
+            self.record_response(response);
+        }
+    }
+
+    fn record_response(&mut self, response: FeedingResponse) {
+        match response {
+            FeedingResponse::AteImmediately => self.ate_immediately += 1,
+            FeedingResponse::AteLater => self.ate_later += 1,
+            FeedingResponse::Ignored => self.ignored += 1,
+        }
+    }
+}
+
+struct MealInProgress {
+    start_grams: f32,
+    started: Instant,
+    /// Weight at the last decrease of at least the still-eating step
+    checkpoint_grams: f32,
+    last_drop: Instant,
+}
+
+/// Finds meals in the bowl readings: a decrease of at least `min_drop_grams` within
+/// `drop_window`, over once the weight hasn't decreased further for `settle`. Changes
+/// while dispensing and for `DISPENSE_QUIET` afterwards are treats landing in the bowl.
+/// Also tracks how the pet reacted to the last completed dispense.
+struct BowlTracker {
+    min_drop_grams: f32,
+    drop_window: Duration,
+    settle: Duration,
+    immediate: Duration,
+    ignored: Duration,
+    recent: VecDeque<(Instant, f32)>,
+    meal: Option<MealInProgress>,
+    dispensing: bool,
+    quiet_until: Option<Instant>,
+    last_dispense: Option<Instant>,
+}
+
+impl BowlTracker {
+    fn new(bowl_config: &BowlConfig) -> Self {
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        BowlTracker {
+            min_drop_grams: bowl_config
+                .min_drop_grams
+                .unwrap_or(config::BOWL_MIN_DROP_GRAMS_DEFAULT),
+            drop_window: Duration::from_secs(
+                bowl_config
+                    .drop_window_secs
+                    .unwrap_or(config::BOWL_DROP_WINDOW_SECS_DEFAULT),
+            ),
+            settle: Duration::from_secs(
+                bowl_config
+                    .settle_secs
+                    .unwrap_or(config::BOWL_SETTLE_SECS_DEFAULT),
+            ),
+            immediate: minutes(
+                bowl_config
+                    .immediate_minutes
+                    .unwrap_or(config::BOWL_IMMEDIATE_MINUTES_DEFAULT),
+            ),
+            ignored: minutes(
+                bowl_config
+                    .ignored_minutes
+                    .unwrap_or(config::BOWL_IGNORED_MINUTES_DEFAULT),
+            ),
+            recent: VecDeque::new(),
+            meal: None,
+            dispensing: false,
+            quiet_until: None,
+            last_dispense: None,
+        }
+    }
+
+    fn dispense_started(&mut self) {
+        self.dispensing = true;
+        self.meal = None;
+        self.recent.clear();
+    }
+
+    fn dispense_ended(&mut self, now: Instant) {
+        self.dispensing = false;
+        self.quiet_until = Some(now + DISPENSE_QUIET);
+    }
+
+    /// A completed dispense starts waiting for the pet. Returns `Ignored` if the previous
+    /// one is still waiting.
+    fn dispensed(&mut self, now: Instant) -> Option<FeedingResponse> {
+        self.last_dispense
+            .replace(now)
+            .map(|_| FeedingResponse::Ignored)
+    }
+
+    /// Returns `Ignored` once a dispense has waited for longer than `ignored`.
+    fn expire(&mut self, now: Instant) -> Option<FeedingResponse> {
+        let dispensed = self.last_dispense?;
+        if now.duration_since(dispensed) > self.ignored {
+            self.last_dispense = None;
+            Some(FeedingResponse::Ignored)
+        } else {
+            None
+        }
+    }
+
+    fn observe(&mut self, grams: f32, now: Instant) -> Option<BowlMeal> {
+        if self.dispensing || self.quiet_until.is_some_and(|until| now < until) {
+            self.recent.clear();
+            return None;
+        }
+
+        if let Some(meal) = self.meal.as_mut() {
+            if meal.checkpoint_grams - grams >= self.min_drop_grams * STILL_EATING_FRACTION {
+                meal.checkpoint_grams = grams;
+                meal.last_drop = now;
+            } else if now.duration_since(meal.last_drop) >= self.settle {
+                return self.finish_meal(grams, now);
+            }
+            return None;
+        }
+
+        while self
+            .recent
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > self.drop_window)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((now, grams));
+        let (peak_time, peak_grams) = self
+            .recent
+            .iter()
+            .copied()
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        if peak_grams - grams >= self.min_drop_grams {
+            self.meal = Some(MealInProgress {
+                start_grams: peak_grams,
+                started: peak_time,
+                checkpoint_grams: grams,
+                last_drop: now,
+            });
+        }
+        None
+    }
+
+    fn finish_meal(&mut self, grams: f32, now: Instant) -> Option<BowlMeal> {
+        let meal = self.meal.take()?;
+        self.recent.clear();
+        self.recent.push_back((now, grams));
+        let eaten = meal.start_grams - grams;
+        // the weight came back up, e.g. the pet only leaned on the bowl
+        if eaten < self.min_drop_grams {
+            return None;
+        }
+
+        let since_dispense = self
+            .last_dispense
+            .take()
+            .map(|dispensed| meal.started.saturating_duration_since(dispensed));
+        Some(BowlMeal {
+            grams: eaten,
+            duration_secs: now.duration_since(meal.started).as_secs(),
+            minutes_after_dispense: since_dispense.map(|d| d.as_secs() / 60),
+            response: since_dispense.map(|d| {
+                if d <= self.immediate {
+                    FeedingResponse::AteImmediately
+                } else {
+                    FeedingResponse::AteLater
+                }
+            }),
+        })
+    }
+}
+
+/// Starts watching the bowl load cell if a `bowl` section is configured. Meals are
+/// published as `bowl_emptied` events and counted in the bowl stats with the pet's
+/// reaction to the preceding dispense.
+pub async fn start_bowl_monitor(app_state: &Arc<Mutex<ApplicationState>>) {
+    let (bowl_config, has_sensor) = {
+        let state_guard = app_state.lock().await;
+        match state_guard.app_config.bowl.clone() {
+            Some(bowl_config) => (bowl_config, state_guard.bowl_sensor_mutex.is_some()),
+            None => return,
+        }
+    };
+    if !has_sensor {
+        warn!("Bowl sensor unavailable, not detecting meals");
+        return;
+    }
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "bowl_monitor", move || {
+        run_bowl_monitor(Arc::clone(&app_state_clone), bowl_config.clone())
+    });
+}
+
+async fn run_bowl_monitor(app_state: Arc<Mutex<ApplicationState>>, bowl_config: BowlConfig) {
+    let (sensor_mutex, mut events_rx) = {
+        let state_guard = app_state.lock().await;
+        match state_guard.bowl_sensor_mutex.clone() {
+            Some(sensor_mutex) => (sensor_mutex, state_guard.event_bus.subscribe()),
+            None => return,
+        }
+    };
+    let counts_per_gram = bowl_config
+        .counts_per_gram
+        .unwrap_or(1.0)
+        .abs()
+        .max(f32::EPSILON);
+    let mut tracker = BowlTracker::new(&bowl_config);
+    let mut samples = Vec::with_capacity(SAMPLES_PER_READING);
+    let mut tick = interval(SAMPLE_INTERVAL);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    info!("Starting bowl monitor");
+
+    loop {
+        tokio::select! {
+            event = events_rx.recv() => match event {
+                Ok(event) => match (event.kind, event.status) {
+                    (EventKind::StatusChanged, Some(DispenserStatus::Dispensing)) => {
+                        tracker.dispense_started();
+                    }
+                    (EventKind::StatusChanged, _) if tracker.dispensing => {
+                        tracker.dispense_ended(Instant::now());
+                    }
+                    (EventKind::Dispensed, _) => {
+                        if let Some(response) = tracker.dispensed(Instant::now()) {
+                            record_ignored(&app_state, response).await;
+                        }
+                    }
+                    _ => {}
+                },
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = tick.tick() => {
+                let read_result = sensor_mutex.lock().await.get_raw();
+                match read_result {
+                    Ok(raw) => samples.push(raw as f32 / counts_per_gram),
+                    Err(e) => trace!("Failed to read bowl weight: {}", e),
+                }
+                if samples.len() < SAMPLES_PER_READING {
+                    continue;
+                }
+                let grams = weight_monitor::calculate_trimmed_mean(&mut samples);
+                samples.clear();
+
+                let now = Instant::now();
+                if let Some(response) = tracker.expire(now) {
+                    record_ignored(&app_state, response).await;
+                }
+                if let Some(meal) = tracker.observe(grams, now) {
+                    record_meal(&app_state, meal).await;
+                }
+            }
+        }
+    }
+}
+
+async fn record_meal(app_state: &Arc<Mutex<ApplicationState>>, meal: BowlMeal) {
+    info!("Pet ate {:.1} g from the bowl", meal.grams);
+    let mut state_guard = app_state.lock().await;
+    state_guard.bowl_stats.record_meal(&meal);
+    if let Err(e) = save_stats_to_file(&state_guard.bowl_stats) {
+        error!("Failed to save bowl stats: {}", e);
+    }
+    state_guard.event_bus.publish_bowl_emptied(meal);
+}
+
+async fn record_ignored(app_state: &Arc<Mutex<ApplicationState>>, response: FeedingResponse) {
+    info!("Dispense ignored, the pet didn't eat after it");
+    let mut state_guard = app_state.lock().await;
+    state_guard.bowl_stats.record_response(response);
+    if let Err(e) = save_stats_to_file(&state_guard.bowl_stats) {
+        error!("Failed to save bowl stats: {}", e);
+    }
+}
+
+pub fn load_stats_from_file() -> BowlStats {
+    filesystem::read_json_from_file(&filesystem::get_bowl_stats_file_path()).unwrap_or_else(
+        |e| {
+            warn!("No bowl stats loaded, starting from zero: {}", e);
+            BowlStats::default()
+        },
+    )
+}
+
+fn save_stats_to_file(stats: &BowlStats) -> Result<(), String> {
+    filesystem::save_json_to_file(&filesystem::get_bowl_stats_file_path(), stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> BowlTracker {
+        BowlTracker::new(&BowlConfig {
+            sensor: "SensorMock".to_string(),
+            slave_select: None,
+            counts_per_gram: None,
+            min_drop_grams: Some(5.0),
+            drop_window_secs: Some(60),
+            settle_secs: Some(20),
+            immediate_minutes: Some(5),
+            ignored_minutes: Some(60),
+        })
+    }
+
+    /// Feeds one reading per second, returns the meals found.
+    fn feed(
+        tracker: &mut BowlTracker,
+        start: Instant,
+        from_sec: u64,
+        readings: &[f32],
+    ) -> Vec<BowlMeal> {
+        readings
+            .iter()
+            .enumerate()
+            .filter_map(|(i, grams)| {
+                tracker.observe(*grams, start + Duration::from_secs(from_sec + i as u64))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_meal_after_dispense() {
+        let mut tracker = tracker();
+        let start = Instant::now();
+        assert_eq!(tracker.dispensed(start), None);
+        tracker.dispense_started();
+        // treats landing don't count
+        assert!(feed(&mut tracker, start, 0, &[20.0, 40.0, 35.0]).is_empty());
+        tracker.dispense_ended(start + Duration::from_secs(3));
+        assert!(feed(&mut tracker, start, 3, &[50.0; 30]).is_empty());
+
+        // eating two minutes later, then the bowl stays put
+        let mut readings = vec![50.0, 46.0, 41.0, 37.0, 33.0, 30.0];
+        readings.extend([30.0; 25]);
+        let meals = feed(&mut tracker, start, 120, &readings);
+        assert_eq!(meals.len(), 1);
+        assert_eq!(meals[0].grams, 20.0);
+        assert_eq!(meals[0].minutes_after_dispense, Some(2));
+        assert_eq!(meals[0].response, Some(FeedingResponse::AteImmediately));
+
+        // no dispense to attribute the next meal to
+        let meals = feed(&mut tracker, start, 200, &[30.0, 20.0, 20.0, 20.0, 20.0]);
+        assert!(meals.is_empty());
+        let meals = feed(&mut tracker, start, 205, &[20.0; 20]);
+        assert_eq!(meals.len(), 1);
+        assert_eq!(meals[0].response, None);
+    }
+
+    #[test]
+    fn test_slow_drift_and_ignored_dispense() {
+        let mut tracker = tracker();
+        let start = Instant::now();
+        // 10 g over five minutes is drift, not a meal
+        let drift: Vec<f32> = (0..300).map(|s| 100.0 - s as f32 / 30.0).collect();
+        assert!(feed(&mut tracker, start, 0, &drift).is_empty());
+
+        tracker.dispensed(start);
+        assert_eq!(tracker.expire(start + Duration::from_secs(59 * 60)), None);
+        assert_eq!(
+            tracker.expire(start + Duration::from_secs(61 * 60)),
+            Some(FeedingResponse::Ignored)
+        );
+        assert_eq!(tracker.expire(start + Duration::from_secs(62 * 60)), None);
+
+        tracker.dispensed(start);
+        assert_eq!(tracker.dispensed(start), Some(FeedingResponse::Ignored));
+    }
+
+    #[test]
+    fn test_bowl_stats() {
+        let mut stats = BowlStats::default();
+        stats.record_meal(&BowlMeal {
+            grams: 12.5,
+            duration_secs: 40,
+            minutes_after_dispense: Some(20),
+            response: Some(FeedingResponse::AteLater),
+        });
+        stats.record_response(FeedingResponse::Ignored);
+        assert_eq!(stats.meal_count, 1);
+        assert_eq!(stats.eaten_grams, 12.5);
+        assert_eq!(stats.ate_later, 1);
+        assert_eq!(stats.ignored, 1);
+        assert_eq!(stats.last_meal.unwrap().grams, 12.5);
+    }
+}
@@ -0,0 +1,67 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::config::{self, CameraConfig};
+use crate::utils::datetime;
+
+/// Picture of the bowl, attached to the `dispense_completed` webhooks.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub taken_at: String,
+    /// As answered by the camera, e.g. `image/jpeg`
+    pub content_type: String,
+    /// The picture, base64 encoded
+    pub data: String,
+}
+
+/// Waits `delay_ms` for the treats to land and fetches a picture from the camera.
+pub async fn take_snapshot(
+    client: &reqwest::Client,
+    camera: &CameraConfig,
+) -> Result<Snapshot, String> {
+    let delay_ms = camera
+        .delay_ms
+        .unwrap_or(config::CAMERA_SNAPSHOT_DELAY_MS_DEFAULT);
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+
+    let taken_at = datetime::get_formatted_current_timestamp();
+    let response = client
+        .get(&camera.snapshot_url)
+        .timeout(Duration::from_secs(config::CAMERA_SNAPSHOT_TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(|e| format!("Camera unreachable: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Camera returned {}", response.status()));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("image/jpeg")
+        .to_string();
+    if !content_type.starts_with("image/") {
+        return Err(format!(
+            "Camera returned {} instead of an image",
+            content_type
+        ));
+    }
+    let image = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read the snapshot: {}", e))?;
+    if image.len() > config::CAMERA_SNAPSHOT_MAX_BYTES {
+        return Err(format!(
+            "Snapshot of {} bytes is larger than {} bytes",
+            image.len(),
+            config::CAMERA_SNAPSHOT_MAX_BYTES
+        ));
+    }
+    Ok(Snapshot {
+        taken_at,
+        content_type,
+        data: STANDARD.encode(&image),
+    })
+}
@@ -0,0 +1,474 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::info;
+
+use crate::application_state::{AppStateMutex, ApplicationState};
+use crate::config::{self, AppConfig};
+use crate::error::{ApiError, FieldError};
+use crate::sensors::WeightSensorSettings;
+use crate::services::config_validation;
+use crate::services::events::EventKind;
+use crate::services::schedule_export;
+use crate::utils::{datetime, filesystem};
+
+/// Settings that are read from `ApplicationState.app_config` each time they are used, or
+/// handed to the running weight sensor, so a reload takes effect right away. A change
+/// anywhere else needs a restart.
+const RELOADABLE_SETTINGS: [&str; 14] = [
+    "motor.cooldown_ms",
+    "motor.dispense_degrees",
+    "motor.max_dispense_degrees",
+    "motor.piece_chunk_degrees",
+    "motor.weight_dispense_timeout_secs",
+    "power_monitor.motor_current_limit_amps",
+    "weight_monitor.piece_weight_grams",
+    "weight_monitor.piece_tolerance_grams",
+    "weight_monitor.grams_tolerance_grams",
+    "weight_monitor.jam_detection",
+    "weight_monitor.geometry_factor",
+    "weight_monitor.fusion.mismatch_tolerance",
+    "weight_monitor.fusion.mismatch_min_grams",
+    "triggers",
+];
+
+/// Reloadable as long as only the gains and expected shares of the cells change.
+const LOAD_CELLS: &str = "weight_monitor.fusion.cells";
+
+/// Returned by `POST /config/reload`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, utoipa::ToSchema)]
+pub struct ConfigReloadResponse {
+    /// Changed settings that are in effect now
+    pub applied: Vec<String>,
+    /// Changed settings that only take effect after a restart
+    pub restart_required: Vec<String>,
+    /// Set for a dry run, `applied` then lists what a reload would apply
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// Set for a staged reload, the previous config is restored unless
+    /// `POST /config/confirm` is called before then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_before: Option<String>,
+}
+
+/// How `POST /config/reload` applies the file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReloadMode {
+    /// Only check the file and report what would change
+    pub dry_run: bool,
+    /// Restore the previous config unless confirmed within this many seconds
+    pub confirm_within_secs: Option<u64>,
+}
+
+/// Config that a staged reload replaced, restored unless confirmed by the deadline.
+pub struct StagedConfig {
+    pub previous: AppConfig,
+    pub deadline: Instant,
+}
+
+impl ReloadMode {
+    fn check(&self) -> Result<(), ApiError> {
+        let Some(secs) = self.confirm_within_secs else {
+            return Ok(());
+        };
+        let mut errors = Vec::new();
+        if !(1..=config::CONFIG_CONFIRM_WITHIN_SECS_MAX).contains(&secs) {
+            errors.push(FieldError::new(
+                "confirm_within_secs",
+                format!(
+                    "must be between 1 and {}",
+                    config::CONFIG_CONFIRM_WITHIN_SECS_MAX
+                ),
+            ));
+        }
+        if self.dry_run {
+            errors.push(FieldError::new(
+                "confirm_within_secs",
+                "can't be combined with dry_run",
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
+
+/// Rejects a config file that wouldn't start either, see `config_validation::validate`.
+fn validate(app_config: &AppConfig) -> Result<(), ApiError> {
+    let errors = config_validation::validate(app_config);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::Validation(errors))
+    }
+}
+
+/// Collects the dotted paths of the values that differ between two configs. Lists are
+/// compared as a whole.
+fn changed_paths(old: &Value, new: &Value, path: &str, changed: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let keys: BTreeSet<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+            for key in keys {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                changed_paths(
+                    old_fields.get(key).unwrap_or(&Value::Null),
+                    new_fields.get(key).unwrap_or(&Value::Null),
+                    &child_path,
+                    changed,
+                );
+            }
+        }
+        _ if old != new => changed.push(path.to_string()),
+        _ => {}
+    }
+}
+
+pub(crate) fn reloadable_setting(path: &str) -> Option<&'static str> {
+    RELOADABLE_SETTINGS.iter().copied().find(|setting| {
+        path == *setting
+            || path
+                .strip_prefix(setting)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// True if both configs have the same load cells on the same sensors.
+fn same_load_cells(current: &AppConfig, new: &AppConfig) -> bool {
+    let cells = |app_config: &AppConfig| {
+        app_config.weight_monitor.fusion.as_ref().map(|fusion| {
+            fusion
+                .cells
+                .iter()
+                .map(|cell| (cell.name.clone(), cell.sensor.clone(), cell.slave_select))
+                .collect::<Vec<_>>()
+        })
+    };
+    cells(current) == cells(new)
+}
+
+/// True if `path` names a field of the config file, a section or a value inside one, but
+/// nothing inside a value or list.
+pub(crate) fn is_config_field(path: &str) -> bool {
+    let schema = config::app_config_schema();
+    path.split('.')
+        .try_fold(&schema, |node, key| schema_field(&schema, node, key))
+        .is_some()
+}
+
+/// Looks up the schema of field `key` of the object `node` describes, following `$ref`s
+/// and the variants of optional sections.
+fn schema_field<'a>(root: &'a Value, node: &'a Value, key: &str) -> Option<&'a Value> {
+    if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+        let definition = reference
+            .strip_prefix("#/$defs/")
+            .and_then(|name| root.get("$defs")?.get(name))?;
+        return schema_field(root, definition, key);
+    }
+    if let Some(field) = node.get("properties").and_then(|fields| fields.get(key)) {
+        return Some(field);
+    }
+    ["anyOf", "oneOf", "allOf"]
+        .iter()
+        .filter_map(|combinator| node.get(*combinator)?.as_array())
+        .flatten()
+        .find_map(|variant| schema_field(root, variant, key))
+}
+
+/// Sets the value at `path`, creating sections that are `null`. Fails if the path leads
+/// through a value that isn't a section.
+pub(crate) fn set_path(config: &mut Value, path: &str, value: Value) -> Result<(), String> {
+    let mut node = config;
+    for key in path.split('.') {
+        if node.is_null() {
+            *node = Value::Object(Default::default());
+        }
+        node = node
+            .as_object_mut()
+            .ok_or_else(|| format!("{} is not a setting", path))?
+            .entry(key)
+            .or_insert(Value::Null);
+    }
+    *node = value;
+    Ok(())
+}
+
+/// Applies the reloadable settings of `new` to `current` and reports which settings changed.
+pub(crate) fn merge(
+    current: &AppConfig,
+    new: &AppConfig,
+) -> Result<(AppConfig, ConfigReloadResponse), String> {
+    let mut current_json = serde_json::to_value(current).map_err(|e| e.to_string())?;
+    let new_json = serde_json::to_value(new).map_err(|e| e.to_string())?;
+    let mut changed = Vec::new();
+    changed_paths(&current_json, &new_json, "", &mut changed);
+
+    let mut response = ConfigReloadResponse::default();
+    for path in changed {
+        let setting = reloadable_setting(&path).or_else(|| {
+            (path == LOAD_CELLS && same_load_cells(current, new)).then_some(LOAD_CELLS)
+        });
+        match setting {
+            Some(setting) => {
+                if !response.applied.iter().any(|applied| applied == setting) {
+                    response.applied.push(setting.to_string());
+                }
+            }
+            None => response.restart_required.push(path),
+        }
+    }
+    for setting in &response.applied {
+        let value = setting
+            .split('.')
+            .fold(&new_json, |node, key| &node[key])
+            .clone();
+        set_path(&mut current_json, setting, value)?;
+    }
+    let merged = serde_json::from_value(current_json).map_err(|e| e.to_string())?;
+    Ok((merged, response))
+}
+
+/// Re-reads `config.yaml` and applies the settings in `RELOADABLE_SETTINGS` without a
+/// restart. Other changes are reported in `restart_required` and left alone until then.
+/// Nothing is applied if the file doesn't parse or a reloadable value is invalid, or for a
+/// dry run. A staged reload keeps the previous config and restores it unless `confirm` is
+/// called within `confirm_within_secs`.
+pub async fn reload(
+    app_state: &AppStateMutex,
+    user: &str,
+    mode: ReloadMode,
+) -> Result<ConfigReloadResponse, ApiError> {
+    mode.check()?;
+    let new_config = read_config_file()?;
+
+    let mut state_guard = app_state.lock().await;
+    if !mode.dry_run && state_guard.staged_config.is_some() {
+        return Err(ApiError::BadRequest(
+            "A staged config is waiting for confirmation".to_string(),
+        ));
+    }
+    let (merged, mut response) = merge(&state_guard.app_config, &new_config)
+        .map_err(|e| ApiError::Internal(format!("Failed to apply config: {}", e)))?;
+    if mode.dry_run {
+        response.dry_run = true;
+        return Ok(response);
+    }
+    if !response.applied.is_empty() {
+        let mut message = format!(
+            "Config reloaded by {}, applied {}",
+            user,
+            response.applied.join(", ")
+        );
+        if let Some(secs) = mode.confirm_within_secs {
+            let confirm_before = SystemTime::now() + Duration::from_secs(secs);
+            response.confirm_before = Some(datetime::format_system_time(confirm_before));
+            message.push_str(&format!(", to be confirmed within {} seconds", secs));
+            let deadline = Instant::now() + Duration::from_secs(secs);
+            state_guard.staged_config = Some(StagedConfig {
+                previous: state_guard.app_config.clone(),
+                deadline,
+            });
+            tokio::spawn(roll_back_unless_confirmed(app_state.clone(), deadline));
+        }
+        apply(&mut state_guard, merged, message);
+    }
+    if !response.restart_required.is_empty() {
+        info!(
+            "Config changes that need a restart: {}",
+            response.restart_required.join(", ")
+        );
+    }
+    Ok(response)
+}
+
+/// Keeps the config of a staged reload. Fails if there is nothing to confirm, e.g.
+/// because it was already rolled back.
+pub async fn confirm(app_state: &AppStateMutex, user: &str) -> Result<(), ApiError> {
+    let mut state_guard = app_state.lock().await;
+    if state_guard.staged_config.take().is_none() {
+        return Err(ApiError::NotFound(
+            "No staged config is waiting for confirmation".to_string(),
+        ));
+    }
+    let message = format!("Staged config confirmed by {}", user);
+    info!("{}", message);
+    state_guard
+        .event_bus
+        .publish(EventKind::ConfigReloaded, message);
+    Ok(())
+}
+
+/// Restores the config that a staged reload replaced once `deadline` passes, unless it
+/// was confirmed in the meantime.
+async fn roll_back_unless_confirmed(app_state: AppStateMutex, deadline: Instant) {
+    tokio::time::sleep_until(deadline.into()).await;
+    let mut state_guard = app_state.lock().await;
+    let Some(staged) = state_guard
+        .staged_config
+        .take_if(|staged| staged.deadline == deadline)
+    else {
+        return;
+    };
+    apply(
+        &mut state_guard,
+        staged.previous,
+        "Staged config not confirmed in time, restored the previous config".to_string(),
+    );
+}
+
+/// Reads and checks `config.yaml` from the data directory. Saved schedules replace the
+/// ones in the file, as at startup.
+pub(crate) fn read_config_file() -> Result<AppConfig, ApiError> {
+    let config_path = filesystem::get_config_path();
+    let config_str = std::fs::read_to_string(&config_path)
+        .map_err(|e| ApiError::Internal(format!("Failed to read {}: {}", config_path, e)))?;
+    let mut new_config = config::parse_app_config(&config_str)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid config file: {}", e)))?;
+    schedule_export::apply_saved_schedules(&mut new_config);
+    validate(&new_config)?;
+    Ok(new_config)
+}
+
+/// Makes `app_config` the running config and publishes a `config_reloaded` event with
+/// `message`.
+pub(crate) fn apply(state_guard: &mut ApplicationState, app_config: AppConfig, message: String) {
+    // the weight monitor hands them to the sensor between two readings
+    let sensor_settings = WeightSensorSettings::from_config(&app_config.weight_monitor);
+    state_guard
+        .weight_sensor_settings_tx
+        .send_if_modified(|settings| {
+            let modified = *settings != sensor_settings;
+            *settings = sensor_settings;
+            modified
+        });
+    state_guard.app_config = app_config;
+    info!("{}", message);
+    state_guard
+        .event_bus
+        .publish(EventKind::ConfigReloaded, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        api:
+          listen_address: "0.0.0.0:3500"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+          motor_current_limit_amps: 0.7
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+          cooldown_ms: 5000
+        "#;
+
+    #[test]
+    fn test_merge_applies_reloadable_settings() {
+        let current = config::load_app_config_from_str(CONFIG);
+        let new = config::load_app_config_from_str(
+            &CONFIG
+                .replace("0.0.0.0:3500", "0.0.0.0:3600")
+                .replace("StepperMock", "Stepper28BYJ48")
+                .replace("cooldown_ms: 5000", "cooldown_ms: 1000")
+                .replace(
+                    "sensor: \"SensorMock\"\n        motor:",
+                    "sensor: \"SensorMock\"\n          jam_detection:\n            min_drop_grams: 2.0\n        motor:",
+                ),
+        );
+
+        let (merged, response) = merge(&current, &new).unwrap();
+        assert_eq!(
+            response.applied,
+            vec!["motor.cooldown_ms", "weight_monitor.jam_detection"]
+        );
+        assert_eq!(
+            response.restart_required,
+            vec!["api.listen_address", "motor.motor_type"]
+        );
+        assert_eq!(merged.motor.cooldown_ms, Some(1000));
+        assert!(merged.weight_monitor.jam_detection.is_some());
+        assert_eq!(merged.motor.motor_type, "StepperMock");
+        assert_eq!(merged.api.listen_address, "0.0.0.0:3500");
+    }
+
+    #[test]
+    fn test_merge_load_cells() {
+        let fused = |left_gain: &str, right_slave_select: &str| {
+            config::load_app_config_from_str(&CONFIG.replace(
+                "sensor: \"SensorMock\"\n        motor:",
+                &format!(
+                    "sensor: \"SensorFused\"\n          fusion:\n            cells:\n              - name: \"left\"\n                sensor: \"SensorHX711\"\n                gain: {}\n              - name: \"right\"\n                sensor: \"SensorHX711\"\n                slave_select: {}\n        motor:",
+                    left_gain, right_slave_select
+                ),
+            ))
+        };
+        let current = fused("1.0", "1");
+
+        let (merged, response) = merge(&current, &fused("1.2", "1")).unwrap();
+        assert_eq!(response.applied, vec![LOAD_CELLS]);
+        assert!(response.restart_required.is_empty());
+        assert_eq!(
+            merged.weight_monitor.fusion.unwrap().cells[0].gain,
+            Some(1.2)
+        );
+
+        let (merged, response) = merge(&current, &fused("1.2", "2")).unwrap();
+        assert!(response.applied.is_empty());
+        assert_eq!(response.restart_required, vec![LOAD_CELLS]);
+        assert_eq!(
+            merged.weight_monitor.fusion.unwrap().cells[0].gain,
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_set_path() {
+        let mut config_json =
+            serde_json::json!({ "motor": { "cooldown_ms": 5000 }, "triggers": null });
+        set_path(&mut config_json, "motor.cooldown_ms", 1000.into()).unwrap();
+        set_path(&mut config_json, "triggers.daily_limit", 6.into()).unwrap();
+        assert_eq!(
+            config_json,
+            serde_json::json!({ "motor": { "cooldown_ms": 1000 }, "triggers": { "daily_limit": 6 } })
+        );
+        assert!(set_path(&mut config_json, "motor.cooldown_ms.x", 1.into()).is_err());
+        assert_eq!(config_json["motor"]["cooldown_ms"], 1000);
+
+        assert!(is_config_field("motor.cooldown_ms"));
+        assert!(is_config_field("triggers.quiet_hours"));
+        assert!(is_config_field(
+            "weight_monitor.jam_detection.min_drop_grams"
+        ));
+        assert!(!is_config_field("motor.cooldown_ms.x"));
+        assert!(!is_config_field("triggers.quiet_hours.0"));
+        assert!(!is_config_field("triggers.daily_limt"));
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_values() {
+        let new = config::load_app_config_from_str(&CONFIG.replace(
+            "motor_current_limit_amps: 0.7",
+            "motor_current_limit_amps: 0",
+        ));
+        match validate(&new) {
+            Err(ApiError::Validation(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].field, "power_monitor.motor_current_limit_amps");
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        assert!(validate(&config::load_app_config_from_str(CONFIG)).is_ok());
+    }
+}
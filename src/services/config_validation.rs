@@ -0,0 +1,574 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::config::{self, AppConfig};
+use crate::error::FieldError;
+use crate::motor::stepper_28byj48::STEPPER_PINS;
+use crate::motor::stepper_nema14::{self, StepGeneration};
+use crate::sensors::WeightSensorSettings;
+use crate::sensors::sensor_ina219;
+
+const MOTOR_TYPES: [&str; 3] = ["Stepper28BYJ48", "StepperNema14", "StepperMock"];
+const POWER_SENSORS: [&str; 2] = ["SensorINA219", "SensorMock"];
+const WEIGHT_SENSORS: [&str; 3] = ["SensorHX711", "SensorFused", "SensorMock"];
+const LOAD_CELL_SENSORS: [&str; 2] = ["SensorHX711", "SensorMock"];
+const TEMPERATURE_SENSORS: [&str; 4] =
+    ["SensorDS18B20", "SensorDHT22", "SensorBME280", "SensorMock"];
+
+/// GPIOs of SPI0 used by the HX711s, MISO, MOSI and SCLK.
+const SPI0_PINS: [u8; 3] = [9, 10, 11];
+/// Chip enable GPIOs of SPI0 by slave select.
+const SPI0_CE_PINS: [(u8, u8); 2] = [(0, 8), (1, 7)];
+/// SDA and SCL of I2C bus 1.
+const I2C1_PINS: [u8; 2] = [2, 3];
+
+/// A GPIO claimed by a config entry.
+struct PinUse {
+    pin: u8,
+    field: String,
+    /// What uses the pin, for the conflict message
+    user: String,
+}
+
+impl PinUse {
+    fn new(pin: u8, field: &str, user: &str) -> Self {
+        PinUse {
+            pin,
+            field: field.to_string(),
+            user: user.to_string(),
+        }
+    }
+}
+
+/// Checks the config for problems the parser can't catch: unknown sensor and motor types,
+/// GPIO pins claimed twice and values out of range. Every problem is returned, so a config
+/// can be fixed in one go.
+pub fn validate(app_config: &AppConfig) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    check_types(app_config, &mut errors);
+    check_ranges(app_config, &mut errors);
+    check_pins(&used_pins(app_config), &mut errors);
+    check_slave_selects(app_config, &mut errors);
+    check_labels(app_config, &mut errors);
+    check_webhooks(app_config, &mut errors);
+    errors
+}
+
+fn check_type(field: &str, value: &str, known: &[&str], errors: &mut Vec<FieldError>) {
+    if known.contains(&value) {
+        return;
+    }
+    let message = match known.iter().find(|name| name.eq_ignore_ascii_case(value)) {
+        Some(name) => format!("unknown type '{}', did you mean '{}'?", value, name),
+        None => format!(
+            "unknown type '{}', expected one of {}",
+            value,
+            known.join(", ")
+        ),
+    };
+    errors.push(FieldError::new(field, message));
+}
+
+fn check_types(app_config: &AppConfig, errors: &mut Vec<FieldError>) {
+    let motor = &app_config.motor;
+    check_type("motor.motor_type", &motor.motor_type, &MOTOR_TYPES, errors);
+    if motor.motor_type == "StepperNema14" && motor.nema14.is_none() {
+        errors.push(FieldError::new(
+            "motor.nema14",
+            "required with motor_type StepperNema14",
+        ));
+    }
+    check_type(
+        "power_monitor.sensor",
+        &app_config.power_monitor.sensor,
+        &POWER_SENSORS,
+        errors,
+    );
+
+    let weight_monitor = &app_config.weight_monitor;
+    check_type(
+        "weight_monitor.sensor",
+        &weight_monitor.sensor,
+        &WEIGHT_SENSORS,
+        errors,
+    );
+    if weight_monitor.sensor == "SensorFused" && weight_monitor.fusion.is_none() {
+        errors.push(FieldError::new(
+            "weight_monitor.fusion",
+            "required with sensor SensorFused",
+        ));
+    }
+    if let Some(fusion) = &weight_monitor.fusion {
+        for (i, cell) in fusion.cells.iter().enumerate() {
+            check_type(
+                &format!("weight_monitor.fusion.cells[{}].sensor", i),
+                &cell.sensor,
+                &LOAD_CELL_SENSORS,
+                errors,
+            );
+        }
+    }
+    if let Some(bowl) = &app_config.bowl {
+        check_type("bowl.sensor", &bowl.sensor, &LOAD_CELL_SENSORS, errors);
+    }
+    if let Some(temperature) = &app_config.temperature {
+        check_type(
+            "temperature.sensor",
+            &temperature.sensor,
+            &TEMPERATURE_SENSORS,
+            errors,
+        );
+    }
+}
+
+fn check_ranges(app_config: &AppConfig, errors: &mut Vec<FieldError>) {
+    let motor = &app_config.motor;
+    let power_monitor = &app_config.power_monitor;
+    let positive = [
+        ("motor.dispense_degrees", motor.dispense_degrees),
+        ("motor.max_dispense_degrees", motor.max_dispense_degrees),
+        ("motor.piece_chunk_degrees", motor.piece_chunk_degrees),
+        (
+            "power_monitor.motor_current_limit_amps",
+            power_monitor.motor_current_limit_amps,
+        ),
+        (
+            "weight_monitor.piece_weight_grams",
+            app_config.weight_monitor.piece_weight_grams,
+        ),
+    ];
+    let not_negative = [
+        (
+            "weight_monitor.piece_tolerance_grams",
+            app_config.weight_monitor.piece_tolerance_grams,
+        ),
+        (
+            "weight_monitor.grams_tolerance_grams",
+            app_config.weight_monitor.grams_tolerance_grams,
+        ),
+    ];
+    errors.extend(
+        positive
+            .iter()
+            .filter(|(_, value)| value.is_some_and(|value| !(value.is_finite() && value > 0.0)))
+            .map(|(field, _)| FieldError::new(field, "must be greater than 0")),
+    );
+    errors.extend(
+        not_negative
+            .iter()
+            .filter(|(_, value)| value.is_some_and(|value| !(value.is_finite() && value >= 0.0)))
+            .map(|(field, _)| FieldError::new(field, "must be at least 0")),
+    );
+
+    if motor
+        .cooldown_ms
+        .is_some_and(|cooldown_ms| cooldown_ms > config::MOTOR_COOLDOWN_MS_MAX)
+    {
+        errors.push(FieldError::new(
+            "motor.cooldown_ms",
+            format!("must be at most {}", config::MOTOR_COOLDOWN_MS_MAX),
+        ));
+    }
+    let dispense_degrees = motor
+        .dispense_degrees
+        .unwrap_or(config::DISPENSE_DEGREES_DEFAULT);
+    let max_dispense_degrees = motor
+        .max_dispense_degrees
+        .unwrap_or(config::MAX_DISPENSE_DEGREES_DEFAULT);
+    if dispense_degrees > max_dispense_degrees {
+        errors.push(FieldError::new(
+            "motor.dispense_degrees",
+            format!(
+                "must not exceed max_dispense_degrees ({}), dispenses without degrees would be rejected",
+                max_dispense_degrees
+            ),
+        ));
+    }
+    if let Some(nema14) = &motor.nema14
+        && nema14.step_generation == Some(StepGeneration::Pwm)
+        && let Err(e) = stepper_nema14::pwm_channel(nema14.step_pin)
+    {
+        errors.push(FieldError::new("motor.nema14.step_pin", e));
+    }
+
+    if power_monitor.sensor == "SensorINA219" {
+        if let Some(Err(e)) = power_monitor.ina219.as_ref().map(|c| c.to_configuration()) {
+            errors.push(FieldError::new("power_monitor.ina219", e));
+        }
+        let current_limit = power_monitor
+            .motor_current_limit_amps
+            .unwrap_or(config::MOTOR_CURRENT_LIMIT_AMPS_DEFAULT);
+        if current_limit >= sensor_ina219::CURRENT_MAX_AMPS {
+            errors.push(FieldError::new(
+                "power_monitor.motor_current_limit_amps",
+                format!(
+                    "must be below {}, the INA219 readings never exceed it so the limit would never trip",
+                    sensor_ina219::CURRENT_MAX_AMPS
+                ),
+            ));
+        }
+    }
+
+    if let Err(e) = WeightSensorSettings::from_config(&app_config.weight_monitor).validate() {
+        errors.push(FieldError::new("weight_monitor", e));
+    }
+    if let Some(hopper_level) = &app_config.weight_monitor.hopper_level
+        && hopper_level
+            .low_threshold_grams
+            .is_some_and(|low| low <= hopper_level.empty_threshold_grams)
+    {
+        errors.push(FieldError::new(
+            "weight_monitor.hopper_level.low_threshold_grams",
+            "must be above empty_threshold_grams",
+        ));
+    }
+}
+
+/// True if any configured load cell is an HX711 on SPI0.
+fn uses_spi0(app_config: &AppConfig) -> bool {
+    let weight_monitor = &app_config.weight_monitor;
+    weight_monitor.sensor == "SensorHX711"
+        || (weight_monitor.sensor == "SensorFused"
+            && weight_monitor
+                .fusion
+                .as_ref()
+                .is_some_and(|fusion| fusion.cells.iter().any(|cell| cell.sensor == "SensorHX711")))
+        || app_config
+            .bowl
+            .as_ref()
+            .is_some_and(|bowl| bowl.sensor == "SensorHX711")
+}
+
+/// The slave selects of the HX711s on SPI0, with the field each comes from.
+fn hx711_slave_selects(app_config: &AppConfig) -> Vec<(u8, String)> {
+    let mut slave_selects = Vec::new();
+    let weight_monitor = &app_config.weight_monitor;
+    if weight_monitor.sensor == "SensorHX711" {
+        slave_selects.push((0, "weight_monitor.sensor".to_string()));
+    }
+    if weight_monitor.sensor == "SensorFused"
+        && let Some(fusion) = &weight_monitor.fusion
+    {
+        for (i, cell) in fusion.cells.iter().enumerate() {
+            if cell.sensor == "SensorHX711" {
+                slave_selects.push((
+                    cell.slave_select.unwrap_or(0),
+                    format!("weight_monitor.fusion.cells[{}].slave_select", i),
+                ));
+            }
+        }
+    }
+    if let Some(bowl) = &app_config.bowl
+        && bowl.sensor == "SensorHX711"
+    {
+        slave_selects.push((
+            bowl.slave_select
+                .unwrap_or(config::BOWL_SLAVE_SELECT_DEFAULT),
+            "bowl.slave_select".to_string(),
+        ));
+    }
+    slave_selects
+}
+
+fn used_pins(app_config: &AppConfig) -> Vec<PinUse> {
+    let mut pins = Vec::new();
+    let motor = &app_config.motor;
+    match motor.motor_type.as_str() {
+        "Stepper28BYJ48" => pins.extend(
+            STEPPER_PINS
+                .iter()
+                .map(|&pin| PinUse::new(pin, "motor.motor_type", "the Stepper28BYJ48 driver")),
+        ),
+        "StepperNema14" => {
+            if let Some(nema14) = &motor.nema14 {
+                for (name, pin) in [
+                    ("dir_pin", nema14.dir_pin),
+                    ("step_pin", nema14.step_pin),
+                    ("sleep_pin", nema14.sleep_pin),
+                    ("reset_pin", nema14.reset_pin),
+                    ("enable_pin", nema14.enable_pin),
+                ] {
+                    let field = format!("motor.nema14.{}", name);
+                    pins.push(PinUse::new(pin, &field, &field));
+                }
+                if let Some(index_sensor) = &nema14.index_sensor {
+                    let field = "motor.nema14.index_sensor.pin";
+                    pins.push(PinUse::new(index_sensor.pin, field, field));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if uses_spi0(app_config) {
+        pins.extend(
+            SPI0_PINS
+                .iter()
+                .map(|&pin| PinUse::new(pin, "weight_monitor.sensor", "SPI0 of the HX711")),
+        );
+        for (slave_select, field) in hx711_slave_selects(app_config) {
+            if let Some((_, pin)) = SPI0_CE_PINS.iter().find(|(ss, _)| *ss == slave_select) {
+                pins.push(PinUse::new(*pin, &field, "SPI0 chip enable of an HX711"));
+            }
+        }
+    }
+    let ina219_on_i2c1 = app_config.power_monitor.sensor == "SensorINA219"
+        && app_config
+            .power_monitor
+            .ina219
+            .as_ref()
+            .and_then(|ina219| ina219.i2c_bus)
+            .unwrap_or(config::I2C_BUS_DEFAULT)
+            == 1;
+    if ina219_on_i2c1 {
+        pins.extend(
+            I2C1_PINS
+                .iter()
+                .map(|&pin| PinUse::new(pin, "power_monitor.sensor", "I2C bus 1 of the INA219")),
+        );
+    }
+    if let Some(temperature) = &app_config.temperature {
+        let bme280_on_i2c1 = temperature.sensor == "SensorBME280"
+            && temperature
+                .bme280
+                .as_ref()
+                .and_then(|bme280| bme280.i2c_bus)
+                .unwrap_or(config::I2C_BUS_DEFAULT)
+                == 1;
+        // the INA219 and BME280 share the bus
+        if bme280_on_i2c1 && !ina219_on_i2c1 {
+            pins.extend(
+                I2C1_PINS
+                    .iter()
+                    .map(|&pin| PinUse::new(pin, "temperature.sensor", "I2C bus 1 of the BME280")),
+            );
+        }
+    }
+
+    if let Some(fan) = &app_config.fan {
+        pins.push(PinUse::new(fan.pin, "fan.pin", "fan.pin"));
+    }
+    for (i, input) in app_config.digital_inputs.iter().flatten().enumerate() {
+        let field = format!("digital_inputs[{}].pin", i);
+        pins.push(PinUse::new(input.pin, &field, &field));
+    }
+    for (i, output) in app_config.digital_outputs.iter().flatten().enumerate() {
+        let field = format!("digital_outputs[{}].pin", i);
+        pins.push(PinUse::new(output.pin, &field, &field));
+    }
+    pins
+}
+
+/// Reports pins outside the BCM range and every pin claimed by more than one entry.
+fn check_pins(pins: &[PinUse], errors: &mut Vec<FieldError>) {
+    let mut users: BTreeMap<u8, &PinUse> = BTreeMap::new();
+    for pin_use in pins {
+        if pin_use.pin > config::GPIO_PIN_MAX {
+            errors.push(FieldError::new(
+                &pin_use.field,
+                format!(
+                    "GPIO {} doesn't exist, use a BCM GPIO number from 0 to {}",
+                    pin_use.pin,
+                    config::GPIO_PIN_MAX
+                ),
+            ));
+            continue;
+        }
+        match users.get(&pin_use.pin) {
+            Some(first) => errors.push(FieldError::new(
+                &pin_use.field,
+                format!("GPIO {} is already used by {}", pin_use.pin, first.user),
+            )),
+            None => {
+                users.insert(pin_use.pin, pin_use);
+            }
+        }
+    }
+}
+
+fn check_slave_selects(app_config: &AppConfig, errors: &mut Vec<FieldError>) {
+    let mut used: BTreeMap<u8, String> = BTreeMap::new();
+    for (slave_select, field) in hx711_slave_selects(app_config) {
+        if slave_select > 2 {
+            errors.push(FieldError::new(&field, "must be 0 to 2"));
+            continue;
+        }
+        match used.get(&slave_select) {
+            Some(first) => errors.push(FieldError::new(
+                &field,
+                format!(
+                    "slave select {} is already used by the HX711 of {}",
+                    slave_select, first
+                ),
+            )),
+            None => {
+                used.insert(slave_select, field);
+            }
+        }
+    }
+}
+
+fn check_labels(app_config: &AppConfig, errors: &mut Vec<FieldError>) {
+    let inputs = app_config
+        .digital_inputs
+        .iter()
+        .flatten()
+        .map(|input| input.label.as_str());
+    let outputs = app_config
+        .digital_outputs
+        .iter()
+        .flatten()
+        .map(|output| output.label.as_str());
+    for (section, labels) in [
+        ("digital_inputs", inputs.collect::<Vec<_>>()),
+        ("digital_outputs", outputs.collect::<Vec<_>>()),
+    ] {
+        let mut seen = HashSet::new();
+        for (i, label) in labels.into_iter().enumerate() {
+            if !seen.insert(label) {
+                errors.push(FieldError::new(
+                    &format!("{}[{}].label", section, i),
+                    format!("label '{}' is used twice", label),
+                ));
+            }
+        }
+    }
+}
+
+fn check_http_url(field: &str, url: &str, errors: &mut Vec<FieldError>) {
+    match reqwest::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => errors.push(FieldError::new(
+            field,
+            format!("must be an http or https URL, not {}", url.scheme()),
+        )),
+        Err(e) => errors.push(FieldError::new(field, format!("invalid URL: {}", e))),
+    }
+}
+
+fn check_webhooks(app_config: &AppConfig, errors: &mut Vec<FieldError>) {
+    for (i, webhook) in app_config.webhooks.iter().flatten().enumerate() {
+        check_http_url(&format!("webhooks[{}].url", i), &webhook.url, errors);
+        if webhook
+            .max_attempts
+            .is_some_and(|attempts| attempts == 0 || attempts > config::WEBHOOK_MAX_ATTEMPTS_MAX)
+        {
+            errors.push(FieldError::new(
+                &format!("webhooks[{}].max_attempts", i),
+                format!("must be between 1 and {}", config::WEBHOOK_MAX_ATTEMPTS_MAX),
+            ));
+        }
+        if webhook.attach_snapshot == Some(true) && app_config.camera.is_none() {
+            errors.push(FieldError::new(
+                &format!("webhooks[{}].attach_snapshot", i),
+                "needs a camera section",
+            ));
+        }
+    }
+    if let Some(camera) = &app_config.camera {
+        check_http_url("camera.snapshot_url", &camera.snapshot_url, errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_example_config_is_valid() {
+        let app_config = config::load_app_config_from_str(include_str!("../../config/config.yaml"));
+        assert_eq!(validate(&app_config), vec![]);
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let app_config = config::load_app_config_from_str(
+            r#"
+            api:
+              listen_address: "127.0.0.1:0"
+              admin_user: "admin"
+              admin_password: "password"
+            motor:
+              motor_type: "StepperNema14"
+              cooldown_ms: 86400000
+              nema14:
+                dir_pin: 26
+                step_pin: 19
+                sleep_pin: 13
+                reset_pin: 6
+                enable_pin: 17
+            power_monitor:
+              sensor: "SensorIna219"
+              motor_current_limit_amps: 0.7
+            weight_monitor:
+              sensor: "SensorHX711"
+              hopper_level:
+                empty_threshold_grams: 50
+                low_threshold_grams: 40
+            bowl:
+              sensor: "SensorHX711"
+              slave_select: 0
+            fan:
+              pin: 17
+              target_celsius: 35.0
+            digital_inputs:
+              - label: "lid"
+                pin: 40
+            webhooks:
+              - url: "https://example.com/hooks/dispenser"
+              - url: "ftp://example.com/hooks"
+                max_attempts: 0
+                attach_snapshot: true
+            "#,
+        );
+        let errors = validate(&app_config);
+        assert_eq!(
+            fields(&errors),
+            vec![
+                "power_monitor.sensor",
+                "motor.cooldown_ms",
+                "weight_monitor.hopper_level.low_threshold_grams",
+                "bowl.slave_select",
+                "fan.pin",
+                "digital_inputs[0].pin",
+                "bowl.slave_select",
+                "webhooks[1].url",
+                "webhooks[1].max_attempts",
+                "webhooks[1].attach_snapshot",
+            ]
+        );
+        assert!(errors[0].message.contains("did you mean 'SensorINA219'?"));
+        assert!(errors[3].message.contains("GPIO 8 is already used by"));
+        assert!(errors[4].message.contains("motor.nema14.enable_pin"));
+    }
+
+    #[test]
+    fn test_current_limit_above_ina219_range() {
+        let app_config = config::load_app_config_from_str(
+            r#"
+            api:
+              listen_address: "127.0.0.1:0"
+              admin_user: "admin"
+              admin_password: "password"
+            motor:
+              motor_type: "StepperMock"
+              dispense_degrees: 9000
+            power_monitor:
+              sensor: "SensorINA219"
+              motor_current_limit_amps: 2.5
+            weight_monitor:
+              sensor: "SensorMock"
+            "#,
+        );
+        assert_eq!(
+            fields(&validate(&app_config)),
+            vec![
+                "motor.dispense_degrees",
+                "power_monitor.motor_current_limit_amps",
+            ]
+        );
+    }
+}
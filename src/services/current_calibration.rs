@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, trace, warn};
+
+use crate::application_state::{AppStateMutex, DispenserStatus};
+use crate::config::{self, AppConfig};
+use crate::error::{ApiError, FieldError};
+use crate::motor::{self, Direction, StepMode};
+use crate::sensors::PowerSensor;
+use crate::services::error_reporting::{self, ErrorKind};
+use crate::services::events::EventKind;
+use crate::utils::state_helpers;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+/// Readings taken with the motor stopped before the run starts.
+const IDLE_SAMPLES: usize = 10;
+const MIN_RUNNING_SAMPLES: usize = 10;
+/// Standard deviations above the mean running current the recommendation starts from.
+const STDDEV_FACTOR: f32 = 3.0;
+/// Recommendations are rounded up to a multiple of this.
+const LIMIT_STEP_MILLIAMPS: f32 = 50.0;
+
+/// Optional body of `POST /power/current-calibration`.
+#[derive(Deserialize, Debug, Default, utoipa::ToSchema)]
+pub struct CurrentCalibrationRequest {
+    /// Motor rotation to measure, defaults to `CURRENT_CALIBRATION_DEGREES_DEFAULT`
+    pub degrees: Option<f32>,
+    /// Headroom on top of the unloaded current for the load of treats
+    pub margin_percent: Option<u32>,
+    /// Sets `power_monitor.motor_current_limit_amps` to the recommendation until restart
+    pub apply: Option<bool>,
+}
+
+impl CurrentCalibrationRequest {
+    pub fn validate(&self, app_config: &AppConfig) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        let max_degrees = app_config
+            .motor
+            .max_dispense_degrees
+            .unwrap_or(config::MAX_DISPENSE_DEGREES_DEFAULT);
+        if let Some(degrees) = self.degrees
+            && !(degrees > 0.0 && degrees <= max_degrees)
+        {
+            errors.push(FieldError::new(
+                "degrees",
+                format!("must be greater than 0 and at most {}", max_degrees),
+            ));
+        }
+        if let Some(margin_percent) = self.margin_percent
+            && margin_percent > config::CURRENT_CALIBRATION_MARGIN_PERCENT_MAX
+        {
+            errors.push(FieldError::new(
+                "margin_percent",
+                format!(
+                    "must be at most {}",
+                    config::CURRENT_CALIBRATION_MARGIN_PERCENT_MAX
+                ),
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
+
+/// Current readings taken while the motor ran.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct CurrentStats {
+    pub samples: usize,
+    pub mean_amps: f32,
+    pub stddev_amps: f32,
+    pub min_amps: f32,
+    pub peak_amps: f32,
+}
+
+impl CurrentStats {
+    fn from_samples(samples: &[f32]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let count = samples.len() as f32;
+        let mean_amps = samples.iter().sum::<f32>() / count;
+        let variance = samples
+            .iter()
+            .map(|amps| (amps - mean_amps).powi(2))
+            .sum::<f32>()
+            / count;
+        Some(CurrentStats {
+            samples: samples.len(),
+            mean_amps,
+            stddev_amps: variance.sqrt(),
+            min_amps: samples.iter().copied().fold(f32::INFINITY, f32::min),
+            peak_amps: samples.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct CurrentCalibrationResponse {
+    /// Mean current with the motor stopped, unset if the sensor couldn't be read
+    pub idle_amps: Option<f32>,
+    pub running: CurrentStats,
+    /// Limit in effect before the calibration
+    pub current_limit_amps: f32,
+    pub recommended_limit_amps: f32,
+    pub applied: bool,
+}
+
+/// The overcurrent check compares the average over about 7 seconds with the limit, which
+/// is at most the mean while the motor runs. The limit starts from the mean plus three
+/// standard deviations and adds `margin_percent` for the load of treats, rounded up to
+/// 0.05 A.
+fn recommend_limit(running: &CurrentStats, margin_percent: u32) -> f32 {
+    let baseline = running.mean_amps + STDDEV_FACTOR * running.stddev_amps;
+    let limit = baseline * (1.0 + margin_percent as f32 / 100.0);
+    // whole milliamps first, so float noise doesn't round e.g. 0.9 A up to 0.95 A
+    let milliamps = (limit * 1000.0).round();
+    (milliamps / LIMIT_STEP_MILLIAMPS).ceil() * LIMIT_STEP_MILLIAMPS / 1000.0
+}
+
+async fn sample_current(sensor_mutex: &Arc<Mutex<Box<dyn PowerSensor>>>, samples: &mut Vec<f32>) {
+    loop {
+        let read_result = sensor_mutex.lock().await.get_power_reading();
+        match read_result {
+            Ok(reading) => samples.push(reading.current_amps),
+            Err(e) => trace!("Failed to read current during calibration: {}", e),
+        }
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+/// Runs the motor by `degrees` with the hopper empty and measures its current, then
+/// recommends a `power_monitor.motor_current_limit_amps`. The dispenser is
+/// `MeasuringCurrent` meanwhile, high current doesn't cancel the run and `POST /cancel`
+/// stops it. With `apply`, the recommendation replaces the limit until the next restart
+/// and a `current_limit_changed` event is published with the user.
+pub async fn calibrate_current_limit(
+    app_state: &AppStateMutex,
+    request: &CurrentCalibrationRequest,
+    user: &str,
+) -> Result<CurrentCalibrationResponse, ApiError> {
+    let (motor, sensor_mutex, cancel_token, current_limit) = {
+        let mut state_guard = app_state.lock().await;
+        let sensor_mutex = state_guard
+            .power_sensor_mutex
+            .clone()
+            .ok_or_else(|| ApiError::Hardware("No power sensor available".to_string()))?;
+        match state_guard.status {
+            DispenserStatus::Operational | DispenserStatus::Cancelled => {}
+            DispenserStatus::Dispensing
+            | DispenserStatus::Cooldown
+            | DispenserStatus::Stirring
+            | DispenserStatus::Calibrating
+            | DispenserStatus::MeasuringCurrent => {
+                return Err(ApiError::Busy(format!(
+                    "Cannot measure the motor current while the dispenser is {}",
+                    state_guard.status
+                )));
+            }
+            _ => {
+                return Err(ApiError::Hardware(format!(
+                    "Dispenser is not operational, cannot measure the motor current (current status: {:?})",
+                    state_guard.status
+                )));
+            }
+        }
+        state_guard.set_status(DispenserStatus::MeasuringCurrent);
+        let token = CancellationToken::new();
+        state_guard.motor_cancel_token = Some(token.clone());
+        let current_limit = state_guard
+            .app_config
+            .power_monitor
+            .motor_current_limit_amps
+            .unwrap_or(config::MOTOR_CURRENT_LIMIT_AMPS_DEFAULT);
+        (
+            Arc::clone(&state_guard.motor),
+            sensor_mutex,
+            token,
+            current_limit,
+        )
+    };
+
+    let mut idle_samples = Vec::with_capacity(IDLE_SAMPLES);
+    for _ in 0..IDLE_SAMPLES {
+        if let Ok(reading) = sensor_mutex.lock().await.get_power_reading() {
+            idle_samples.push(reading.current_amps);
+        }
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+
+    let degrees = request
+        .degrees
+        .unwrap_or(config::CURRENT_CALIBRATION_DEGREES_DEFAULT);
+    info!("Measuring the unloaded motor current over {}°", degrees);
+    let mut samples = Vec::new();
+    let result = tokio::select! {
+        result = motor.run_motor_degrees_async(
+            degrees,
+            &Direction::CounterClockwise,
+            &StepMode::Full,
+            app_state,
+            &cancel_token,
+        ) => result,
+        () = sample_current(&sensor_mutex, &mut samples) => unreachable!(),
+    };
+
+    app_state.lock().await.motor_cancel_token = None;
+    if let Err(e) = result {
+        warn!("Current measurement ended: {}", e);
+        if cancel_token.is_cancelled() {
+            // the cancel request already set the status
+            return Err(ApiError::Hardware(
+                "Current measurement was cancelled".to_string(),
+            ));
+        } else if e.starts_with(motor::STALL_ERROR_PREFIX) {
+            state_helpers::record_error(app_state, &e).await;
+            state_helpers::set_dispenser_status_async(app_state, DispenserStatus::Jammed).await;
+        } else {
+            error_reporting::report(
+                ErrorKind::BackgroundTask,
+                format!("Current measurement motor run failed: {}", e),
+            );
+            state_helpers::set_dispenser_status_async(app_state, DispenserStatus::Unknown).await;
+        }
+        return Err(ApiError::Hardware(format!("Motor run failed: {}", e)));
+    }
+    state_helpers::set_dispenser_status_async(app_state, DispenserStatus::Operational).await;
+
+    let running = CurrentStats::from_samples(&samples)
+        .filter(|stats| stats.samples >= MIN_RUNNING_SAMPLES)
+        .ok_or_else(|| {
+            ApiError::Hardware(format!(
+                "Only {} current readings during the motor run, measure over more degrees",
+                samples.len()
+            ))
+        })?;
+    let margin_percent = request
+        .margin_percent
+        .unwrap_or(config::CURRENT_CALIBRATION_MARGIN_PERCENT_DEFAULT);
+    let recommended = recommend_limit(&running, margin_percent);
+    info!(
+        "Unloaded motor current {:.3} A (σ {:.3} A, peak {:.3} A), recommended limit {:.2} A",
+        running.mean_amps, running.stddev_amps, running.peak_amps, recommended
+    );
+
+    let applied = request.apply.unwrap_or(false);
+    if applied {
+        let mut state_guard = app_state.lock().await;
+        state_guard
+            .app_config
+            .power_monitor
+            .motor_current_limit_amps = Some(recommended);
+        let message = format!(
+            "Motor current limit changed from {:.2} A to {:.2} A by {}",
+            current_limit, recommended, user
+        );
+        info!("{}", message);
+        state_guard
+            .event_bus
+            .publish(EventKind::CurrentLimitChanged, message);
+    }
+
+    Ok(CurrentCalibrationResponse {
+        idle_amps: CurrentStats::from_samples(&idle_samples).map(|stats| stats.mean_amps),
+        running,
+        current_limit_amps: current_limit,
+        recommended_limit_amps: recommended,
+        applied,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recommend_limit() {
+        let steady = CurrentStats::from_samples(&[0.6; 50]).unwrap();
+        assert!(steady.stddev_amps < 1e-6);
+        assert_eq!(recommend_limit(&steady, 50), 0.9);
+        assert_eq!(recommend_limit(&steady, 0), 0.6);
+
+        let noisy = CurrentStats::from_samples(&[0.4, 0.6, 0.4, 0.6]).unwrap();
+        assert!((noisy.mean_amps - 0.5).abs() < 1e-6);
+        assert!((noisy.stddev_amps - 0.1).abs() < 1e-6);
+        assert_eq!(noisy.peak_amps, 0.6);
+        // (0.5 + 3 * 0.1) * 1.25 = 1.0
+        assert_eq!(recommend_limit(&noisy, 25), 1.0);
+        // 0.8 * 1.1 = 0.88, rounded up
+        assert_eq!(recommend_limit(&noisy, 10), 0.9);
+
+        assert!(CurrentStats::from_samples(&[]).is_none());
+    }
+}
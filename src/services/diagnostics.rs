@@ -0,0 +1,174 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+use crate::application_state::{ApplicationState, HardwareStatus};
+use crate::config;
+use crate::logging::recent;
+use crate::services::backup;
+use crate::services::i2c_scan::{self, I2cScanResponse};
+
+/// Config fields whose name contains one of these are replaced before the config is
+/// added to the bundle, it is meant to be attached to public bug reports.
+const SECRET_FIELD_MARKERS: [&str; 6] = [
+    "password",
+    "secret",
+    "token",
+    "access_key",
+    "dsn",
+    "webhook_url",
+];
+const REDACTED: &str = "<redacted>";
+
+#[derive(Serialize)]
+struct VersionInfo {
+    version: String,
+    os: String,
+    arch: String,
+    hostname: Option<String>,
+    uptime_seconds: u64,
+    created_at: String,
+}
+
+/// Hardware probe results: what initialized at startup and what answers on the I2C bus now.
+#[derive(Serialize)]
+struct ProbeReport {
+    status: String,
+    hardware: HardwareStatus,
+    init_errors: Vec<String>,
+    stale_channels: Vec<String>,
+    last_error_msg: Option<String>,
+    last_error_time: Option<String>,
+    i2c_scan: Option<I2cScanResponse>,
+    i2c_scan_error: Option<String>,
+}
+
+/// Builds a tar.gz with everything usually asked for in a bug report: version info,
+/// the config with secrets redacted, recent logs and events, a hardware probe report,
+/// the weight sensor calibration and the last minute of power readings.
+pub async fn create_diagnostics(
+    app_state: &Arc<Mutex<ApplicationState>>,
+) -> Result<Vec<u8>, String> {
+    let (mut probe, version_info, config_json, calibration, power_trace, events, i2c_bus) = {
+        let state_guard = app_state.lock().await;
+        let probe = ProbeReport {
+            status: state_guard.status.to_string(),
+            hardware: state_guard.hardware.clone(),
+            init_errors: state_guard.init_errors.clone(),
+            stale_channels: state_guard.stale_channels.clone(),
+            last_error_msg: state_guard.last_error_msg.clone(),
+            last_error_time: state_guard.last_error_time.clone(),
+            i2c_scan: None,
+            i2c_scan_error: None,
+        };
+        let version_info = VersionInfo {
+            version: state_guard.version.clone(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: sysinfo::System::host_name(),
+            uptime_seconds: SystemTime::now()
+                .duration_since(state_guard.startup_time)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        (
+            probe,
+            version_info,
+            serde_json::to_value(&state_guard.app_config).map_err(|e| e.to_string())?,
+            state_guard.calibration_rx.borrow().clone(),
+            state_guard.power_trace.clone(),
+            state_guard.event_bus.recent(),
+            state_guard
+                .app_config
+                .power_monitor
+                .ina219
+                .as_ref()
+                .and_then(|c| c.i2c_bus)
+                .unwrap_or(config::I2C_BUS_DEFAULT),
+        )
+    };
+
+    match tokio::task::spawn_blocking(move || i2c_scan::scan_bus(i2c_bus)).await {
+        Ok(Ok(response)) => probe.i2c_scan = Some(response),
+        Ok(Err(e)) => probe.i2c_scan_error = Some(e),
+        Err(e) => probe.i2c_scan_error = Some(format!("I2C scan task failed: {}", e)),
+    }
+
+    let config_yaml = serde_yaml::to_string(&redact_secrets(config_json))
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    let mut logs = recent::recent_logs().join("\n");
+    logs.push('\n');
+
+    let files: [(&str, Vec<u8>); 7] = [
+        ("version.json", to_json(&version_info)?),
+        ("config.yaml", config_yaml.into_bytes()),
+        ("logs.txt", logs.into_bytes()),
+        ("events.json", to_json(&events)?),
+        ("probe.json", to_json(&probe)?),
+        ("calibration.json", to_json(&calibration)?),
+        ("power_trace.json", to_json(&power_trace)?),
+    ];
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (name, contents) in &files {
+        backup::append_bytes(&mut builder, name, contents)?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to write diagnostics archive: {}", e))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| e.to_string())
+}
+
+/// Replaces the values of secret looking fields anywhere in the config.
+fn redact_secrets(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let is_secret = SECRET_FIELD_MARKERS
+                    .iter()
+                    .any(|marker| key.contains(marker));
+                if is_secret && !value.is_null() {
+                    (key, serde_json::Value::String(REDACTED.to_string()))
+                } else {
+                    (key, redact_secrets(value))
+                }
+            })
+            .collect(),
+        serde_json::Value::Array(items) => items.into_iter().map(redact_secrets).collect(),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        let config = serde_json::json!({
+            "api": { "admin_user": "admin", "admin_password": "hunter2" },
+            "hooks": [{ "name": "ifttt", "token": "abc", "min_interval_secs": 60 }],
+            "backup": { "s3": { "access_key_id": "AKIA", "secret_access_key": "xyz", "bucket": "b" } },
+            "error_reporting": { "sentry_dsn": null }
+        });
+        let redacted = redact_secrets(config);
+
+        assert_eq!(redacted["api"]["admin_user"], "admin");
+        assert_eq!(redacted["api"]["admin_password"], REDACTED);
+        assert_eq!(redacted["hooks"][0]["token"], REDACTED);
+        assert_eq!(redacted["hooks"][0]["min_interval_secs"], 60);
+        assert_eq!(redacted["backup"]["s3"]["access_key_id"], REDACTED);
+        assert_eq!(redacted["backup"]["s3"]["secret_access_key"], REDACTED);
+        assert_eq!(redacted["backup"]["s3"]["bucket"], "b");
+        assert!(redacted["error_reporting"]["sentry_dsn"].is_null());
+    }
+}
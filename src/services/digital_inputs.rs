@@ -0,0 +1,214 @@
+use rppal::gpio::{InputPin, Level};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::{MissedTickBehavior, interval};
+use tracing::{error, info};
+
+use crate::application_state::ApplicationState;
+use crate::config::{self, DigitalInputConfig, InputPull};
+use crate::services::error_reporting::{self, ErrorKind};
+use crate::services::supervisor;
+use crate::utils::datetime;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Current level of a configured input, reported in `/status`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct DigitalInputState {
+    pub pin: u8,
+    /// Debounced level, unset until the input has been read
+    pub high: Option<bool>,
+    pub last_changed: Option<String>,
+}
+
+/// Initial state of every configured input, keyed by label.
+pub fn initial_states(
+    inputs: Option<&Vec<DigitalInputConfig>>,
+) -> BTreeMap<String, DigitalInputState> {
+    inputs
+        .into_iter()
+        .flatten()
+        .map(|input| {
+            (
+                input.label.clone(),
+                DigitalInputState {
+                    pin: input.pin,
+                    high: None,
+                    last_changed: None,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Accepts a new level only after it held for the debounce time, so contact bounce
+/// on a switch doesn't produce a burst of events.
+struct Debouncer {
+    debounce: Duration,
+    stable: bool,
+    pending_since: Option<Instant>,
+}
+
+impl Debouncer {
+    fn new(level: bool, debounce: Duration) -> Self {
+        Debouncer {
+            debounce,
+            stable: level,
+            pending_since: None,
+        }
+    }
+
+    /// Returns the new level once a change has been stable for the debounce time.
+    fn update(&mut self, level: bool, now: Instant) -> Option<bool> {
+        if level == self.stable {
+            self.pending_since = None;
+            return None;
+        }
+        let since = *self.pending_since.get_or_insert(now);
+        if now.duration_since(since) < self.debounce {
+            return None;
+        }
+        self.stable = level;
+        self.pending_since = None;
+        Some(level)
+    }
+}
+
+struct WatchedInput {
+    label: String,
+    pin: InputPin,
+    debouncer: Debouncer,
+}
+
+/// Polls the pins configured under `digital_inputs` and publishes an `input_changed`
+/// event for every debounced level change. Does nothing if no inputs are configured.
+pub async fn start_digital_inputs_monitor(app_state: &Arc<Mutex<ApplicationState>>) {
+    if app_state.lock().await.app_config.digital_inputs.is_none() {
+        return;
+    }
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "digital_inputs", move || {
+        run_digital_inputs_monitor(Arc::clone(&app_state_clone))
+    });
+}
+
+async fn run_digital_inputs_monitor(app_state: Arc<Mutex<ApplicationState>>) {
+    let (gpio, inputs) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.gpio.clone(),
+            state_guard
+                .app_config
+                .digital_inputs
+                .clone()
+                .unwrap_or_default(),
+        )
+    };
+
+    let Some(gpio) = gpio else {
+        error!("No GPIO available, digital inputs are not monitored");
+        error_reporting::report(
+            ErrorKind::BackgroundTask,
+            "Digital inputs could not be monitored, no GPIO available",
+        );
+        return;
+    };
+
+    let mut watched = Vec::new();
+    for input in &inputs {
+        let pin = match gpio.get(input.pin) {
+            Ok(pin) => match input.pull.unwrap_or_default() {
+                InputPull::Up => pin.into_input_pullup(),
+                InputPull::Down => pin.into_input_pulldown(),
+                InputPull::None => pin.into_input(),
+            },
+            Err(e) => {
+                error!(
+                    "Failed to get pin {} for input '{}': {}",
+                    input.pin, input.label, e
+                );
+                continue;
+            }
+        };
+        let level = pin.read() == Level::High;
+        let debounce = Duration::from_millis(
+            input
+                .debounce_ms
+                .unwrap_or(config::DIGITAL_INPUT_DEBOUNCE_MS_DEFAULT),
+        );
+        set_input_level(&app_state, &input.label, level, false).await;
+        info!(
+            "Monitoring input '{}' on pin {}, currently {}",
+            input.label,
+            input.pin,
+            if level { "high" } else { "low" }
+        );
+        watched.push(WatchedInput {
+            label: input.label.clone(),
+            pin,
+            debouncer: Debouncer::new(level, debounce),
+        });
+    }
+
+    let mut tick = interval(POLL_INTERVAL);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tick.tick().await;
+        let now = Instant::now();
+        for input in &mut watched {
+            let level = input.pin.read() == Level::High;
+            if let Some(level) = input.debouncer.update(level, now) {
+                set_input_level(&app_state, &input.label, level, true).await;
+            }
+        }
+    }
+}
+
+async fn set_input_level(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    label: &str,
+    high: bool,
+    changed: bool,
+) {
+    let mut state_guard = app_state.lock().await;
+    if let Some(input) = state_guard.digital_inputs.get_mut(label) {
+        input.high = Some(high);
+        if changed {
+            input.last_changed = Some(datetime::get_formatted_current_timestamp());
+        }
+    }
+    if changed {
+        info!(
+            "Input '{}' changed to {}",
+            label,
+            if high { "high" } else { "low" }
+        );
+        state_guard.event_bus.publish_input_change(label, high);
+        state_guard.notify_status_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debouncer() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(false, Duration::from_millis(50));
+
+        assert_eq!(debouncer.update(false, at(0)), None);
+        // a bounce shorter than the debounce time is ignored
+        assert_eq!(debouncer.update(true, at(10)), None);
+        assert_eq!(debouncer.update(false, at(20)), None);
+        assert_eq!(debouncer.update(true, at(30)), None);
+        assert_eq!(debouncer.update(true, at(70)), None);
+        assert_eq!(debouncer.update(true, at(80)), Some(true));
+        assert_eq!(debouncer.update(true, at(200)), None);
+    }
+}
@@ -0,0 +1,101 @@
+use rppal::gpio::{Gpio, OutputPin};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::application_state::ApplicationState;
+use crate::config::DigitalOutputConfig;
+
+/// A configured output and the pin driving it. The pin is unset if it couldn't be claimed.
+pub struct DigitalOutput {
+    pub pin_number: u8,
+    pin: Option<OutputPin>,
+    pub on: bool,
+}
+
+impl DigitalOutput {
+    pub fn state(&self) -> DigitalOutputState {
+        DigitalOutputState {
+            pin: self.pin_number,
+            on: self.on,
+            available: self.pin.is_some(),
+        }
+    }
+}
+
+/// Current level of a configured output, reported in `/status` and by `POST /outputs/{label}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct DigitalOutputState {
+    pub pin: u8,
+    pub on: bool,
+    /// False if GPIO is unavailable or the pin is used by something else
+    pub available: bool,
+}
+
+/// Claims the pins of the outputs configured under `digital_outputs`, all switched off.
+pub fn init_outputs(
+    gpio: Option<&Gpio>,
+    outputs: Option<&Vec<DigitalOutputConfig>>,
+) -> BTreeMap<String, DigitalOutput> {
+    outputs
+        .into_iter()
+        .flatten()
+        .map(|output| {
+            let pin = gpio.and_then(|gpio| match gpio.get(output.pin) {
+                Ok(pin) => Some(pin.into_output_low()),
+                Err(e) => {
+                    error!(
+                        "Failed to get pin {} for output '{}': {}",
+                        output.pin, output.label, e
+                    );
+                    None
+                }
+            });
+            (
+                output.label.clone(),
+                DigitalOutput {
+                    pin_number: output.pin,
+                    pin,
+                    on: false,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Switches a configured output on or off and publishes an `output_changed` event.
+/// Returns `None` if no output with that label is configured.
+pub async fn set_output(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    label: &str,
+    on: bool,
+) -> Result<Option<DigitalOutputState>, String> {
+    let mut state_guard = app_state.lock().await;
+    let Some(output) = state_guard.digital_outputs.get_mut(label) else {
+        return Ok(None);
+    };
+    let Some(pin) = output.pin.as_mut() else {
+        return Err(format!(
+            "Output '{}' is unavailable, pin {} could not be claimed",
+            label, output.pin_number
+        ));
+    };
+
+    if on {
+        pin.set_high();
+    } else {
+        pin.set_low();
+    }
+    output.on = on;
+    let state = output.state();
+
+    info!(
+        "Output '{}' switched {}",
+        label,
+        if on { "on" } else { "off" }
+    );
+    state_guard.event_bus.publish_output_change(label, on);
+    Ok(Some(state))
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::application_state::{AppStateMutex, DispenserStatus};
+use crate::config::RecoveryStatus;
+use crate::motor::{Direction, StepMode};
+use crate::services::dispenser::TriggerSource;
+use crate::services::events::EventKind;
+use crate::utils::{filesystem, state_helpers};
+
+/// Written when a dispense starts and removed once its motor run has ended. Finding it at
+/// startup means the process died mid-dispense, e.g. from a power cut or crash.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DispenseJournal {
+    pub job_id: String,
+    pub trigger: TriggerSource,
+    pub started_at: String,
+}
+
+pub fn begin(journal: &DispenseJournal) {
+    if let Err(e) =
+        filesystem::save_json_to_file(&filesystem::get_dispense_journal_file_path(), journal)
+    {
+        warn!("Failed to write dispense journal: {}", e);
+    }
+}
+
+pub fn finish() {
+    match std::fs::remove_file(filesystem::get_dispense_journal_file_path()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove dispense journal: {}", e),
+    }
+}
+
+/// Checks for a dispense interrupted by the previous shutdown and recovers from it in the
+/// background, see [`recover`]. Call once at startup, after the push notifier started.
+pub async fn recover_interrupted_dispense(app_state: &AppStateMutex) {
+    let path = filesystem::get_dispense_journal_file_path();
+    if !std::path::Path::new(&path).exists() {
+        return;
+    }
+    let journal = filesystem::read_json_from_file::<DispenseJournal>(&path);
+    finish();
+    match journal {
+        Ok(journal) => {
+            let app_state = Arc::clone(app_state);
+            tokio::spawn(async move { recover(&app_state, &journal).await });
+        }
+        Err(e) => error!("Found an unreadable dispense journal, ignoring it: {}", e),
+    }
+}
+
+/// Records the interrupted dispense as a `recovered_interrupted` event and as the last
+/// error, then applies `dispense_recovery`: the dispenser starts `Unknown` (or `Jammed`)
+/// instead of `Operational` and the motor optionally reverses to free stuck treats.
+/// A dispenser that already failed to initialize its hardware keeps that status.
+pub async fn recover(app_state: &AppStateMutex, journal: &DispenseJournal) {
+    let message = format!(
+        "Dispense {} (trigger: {}) started at {} was interrupted by a restart",
+        journal.job_id, journal.trigger, journal.started_at
+    );
+    warn!("{}", message);
+
+    let (motor, event_bus, reverse) = {
+        let mut state_guard = app_state.lock().await;
+        let recovery_config = state_guard.app_config.dispense_recovery.clone();
+        let operational = state_guard.status == DispenserStatus::Operational;
+        if operational {
+            let status = match recovery_config.as_ref().and_then(|c| c.status) {
+                Some(RecoveryStatus::Jammed) => DispenserStatus::Jammed,
+                Some(RecoveryStatus::Unknown) | None => DispenserStatus::Unknown,
+            };
+            state_guard.set_status(status);
+        }
+        let reverse = recovery_config
+            .and_then(|c| c.reverse_degrees)
+            .filter(|degrees| operational && *degrees > 0.0)
+            .map(|degrees| {
+                let token = CancellationToken::new();
+                state_guard.motor_cancel_token = Some(token.clone());
+                (degrees, token)
+            });
+        (
+            Arc::clone(&state_guard.motor),
+            state_guard.event_bus.clone(),
+            reverse,
+        )
+    };
+    event_bus.publish(EventKind::RecoveredInterrupted, message.clone());
+    state_helpers::record_error(app_state, &message).await;
+
+    if let Some((degrees, cancel_token)) = reverse {
+        info!("Reversing motor by {}° after the interrupted dispense", degrees);
+        let result = motor
+            .run_motor_degrees_async(
+                degrees,
+                &Direction::Clockwise,
+                &StepMode::Full,
+                app_state,
+                &cancel_token,
+            )
+            .await;
+        app_state.lock().await.motor_cancel_token = None;
+        if let Err(e) = result {
+            warn!("Recovery reverse run ended: {}", e);
+        }
+    }
+}
+
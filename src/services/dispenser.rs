@@ -1,30 +1,483 @@
 use crate::application_state::AppStateMutex;
 use crate::application_state::DispenserStatus;
-use crate::error::ApiError;
-use crate::motor::{AsyncStepperMotor, Direction, StepMode};
+use crate::error::{ApiError, FieldError};
+use crate::motor::{self, AsyncStepperMotor, Direction, StepMode};
+use crate::services::dispense_recovery::{self, DispenseJournal};
+use crate::services::error_reporting::{self, ErrorKind};
+use crate::services::events::EventKind;
+use crate::services::history::{self, DispenseResult, HistoryEntry};
+use crate::services::jam_detector::JamDetector;
+use crate::services::motor_vibration;
+use crate::services::stats;
+use crate::services::temperature_monitor;
+use crate::services::trickle::{self, TrickleJob, TricklePlan};
 use crate::utils::datetime;
-use crate::utils::state_helpers::set_dispenser_status_async;
+use crate::utils::state_helpers::{self, set_dispenser_status_async};
 use crate::config;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info, warn};
+use tracing::{Instrument, debug, info, info_span, warn};
+
+/// What caused a dispense, recorded on the dispense span.
+#[derive(
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TriggerSource {
+    ApiUser,
+    Assistant,
+    Hook,
+    Schedule,
+    Training,
+}
+
+impl fmt::Display for TriggerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerSource::ApiUser => write!(f, "api-user"),
+            TriggerSource::Assistant => write!(f, "assistant"),
+            TriggerSource::Hook => write!(f, "hook"),
+            TriggerSource::Schedule => write!(f, "schedule"),
+            TriggerSource::Training => write!(f, "training"),
+        }
+    }
+}
+
+/// Time for treats to land and the scale to publish a fresh mean between the motor runs
+/// of a dispense by weight.
+const WEIGHT_SETTLE_DELAY: Duration = Duration::from_millis(1000);
+
+/// Optional body of `POST /dispense`.
+#[derive(Deserialize, Debug, Default, utoipa::ToSchema)]
+pub struct DispenseRequest {
+    /// Motor rotation for this dispense, defaults to `motor.dispense_degrees`
+    pub degrees: Option<f32>,
+    /// Number of treats to dispense, measured by weight instead of a fixed rotation
+    pub pieces: Option<u32>,
+    /// Why the dispense was made, recorded in the history and shown in notifications
+    pub note: Option<String>,
+}
+
+/// How much a dispense should deliver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DispenseAmount {
+    /// `motor.dispense_degrees`
+    Default,
+    Degrees(f32),
+    Pieces(u32),
+    Grams(f32),
+    /// `grams` spread over `duration_minutes` in small bursts
+    Trickle { grams: f32, duration_minutes: u64 },
+}
+
+impl DispenseAmount {
+    /// How the amount is measured, the `profile` of the dispense span.
+    pub fn profile(&self) -> &'static str {
+        match self {
+            DispenseAmount::Default => "default",
+            DispenseAmount::Degrees(_) => "degrees",
+            DispenseAmount::Pieces(_) => "pieces",
+            DispenseAmount::Grams(_) => "grams",
+            DispenseAmount::Trickle { .. } => "trickle",
+        }
+    }
+
+    /// The degrees, pieces or grams, as `profile` says. `None` for the default amount.
+    pub fn quantity(&self) -> Option<f32> {
+        match *self {
+            DispenseAmount::Default => None,
+            DispenseAmount::Degrees(degrees) => Some(degrees),
+            DispenseAmount::Pieces(pieces) => Some(pieces as f32),
+            DispenseAmount::Grams(grams) | DispenseAmount::Trickle { grams, .. } => Some(grams),
+        }
+    }
+}
+
+impl fmt::Display for DispenseAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispenseAmount::Default => write!(f, "the default amount"),
+            DispenseAmount::Degrees(degrees) => write!(f, "{} degrees", degrees),
+            DispenseAmount::Pieces(pieces) => write!(f, "{} pieces", pieces),
+            DispenseAmount::Grams(grams) => write!(f, "{:.1} g", grams),
+            DispenseAmount::Trickle {
+                grams,
+                duration_minutes,
+            } => write!(f, "{:.1} g over {} minutes", grams, duration_minutes),
+        }
+    }
+}
+
+impl DispenseRequest {
+    pub fn validate(&self, app_config: &config::AppConfig) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        let max_degrees = app_config
+            .motor
+            .max_dispense_degrees
+            .unwrap_or(config::MAX_DISPENSE_DEGREES_DEFAULT);
+        if let Some(degrees) = self.degrees
+            && !(degrees > 0.0 && degrees <= max_degrees)
+        {
+            errors.push(FieldError::new(
+                "degrees",
+                format!("must be greater than 0 and at most {}", max_degrees),
+            ));
+        }
+        if let Some(pieces) = self.pieces {
+            if self.degrees.is_some() {
+                errors.push(FieldError::new("pieces", "cannot be combined with degrees"));
+            } else if app_config.weight_monitor.piece_weight_grams.is_none() {
+                errors.push(FieldError::new(
+                    "pieces",
+                    "requires weight_monitor.piece_weight_grams to be configured",
+                ));
+            } else if !(1..=config::DISPENSE_PIECES_MAX).contains(&pieces) {
+                errors.push(FieldError::new(
+                    "pieces",
+                    format!("must be between 1 and {}", config::DISPENSE_PIECES_MAX),
+                ));
+            }
+        }
+        if let Some(note) = &self.note
+            && note.chars().count() > config::DISPENSE_NOTE_MAX_CHARS
+        {
+            errors.push(FieldError::new(
+                "note",
+                format!("must be at most {} characters", config::DISPENSE_NOTE_MAX_CHARS),
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+
+    /// The note, unset if blank
+    pub fn note(&self) -> Option<String> {
+        self.note
+            .as_deref()
+            .map(str::trim)
+            .filter(|note| !note.is_empty())
+            .map(str::to_string)
+    }
+
+    pub fn amount(&self) -> DispenseAmount {
+        match (self.pieces, self.degrees) {
+            (Some(pieces), _) => DispenseAmount::Pieces(pieces),
+            (None, Some(degrees)) => DispenseAmount::Degrees(degrees),
+            (None, None) => DispenseAmount::Default,
+        }
+    }
+}
+
+/// Body of `POST /dispense/grams`.
+#[derive(Deserialize, Debug, utoipa::ToSchema)]
+pub struct DispenseGramsRequest {
+    /// Weight the hopper should lose
+    pub grams: f32,
+}
+
+impl DispenseGramsRequest {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.grams > 0.0 && self.grams <= config::DISPENSE_GRAMS_MAX {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(vec![FieldError::new(
+                "grams",
+                format!(
+                    "must be greater than 0 and at most {}",
+                    config::DISPENSE_GRAMS_MAX
+                ),
+            )]))
+        }
+    }
+
+    pub fn amount(&self) -> DispenseAmount {
+        DispenseAmount::Grams(self.grams)
+    }
+}
+
+/// Settings for dispensing by pieces or grams, resolved from the config.
+#[derive(Clone)]
+struct WeightTarget {
+    target_grams: f32,
+    tolerance_grams: f32,
+    chunk_degrees: f32,
+    max_degrees: f32,
+    timeout: Duration,
+}
+
+impl WeightTarget {
+    /// `None` for a fixed rotation, or pieces without `piece_weight_grams`.
+    fn from_config(amount: DispenseAmount, app_config: &config::AppConfig) -> Option<Self> {
+        let (target_grams, tolerance_grams) = match amount {
+            DispenseAmount::Pieces(pieces) => {
+                let piece_weight_grams = app_config.weight_monitor.piece_weight_grams?;
+                (
+                    pieces as f32 * piece_weight_grams,
+                    app_config
+                        .weight_monitor
+                        .piece_tolerance_grams
+                        .unwrap_or(piece_weight_grams / 2.0),
+                )
+            }
+            DispenseAmount::Grams(grams) | DispenseAmount::Trickle { grams, .. } => (
+                grams,
+                app_config
+                    .weight_monitor
+                    .grams_tolerance_grams
+                    .unwrap_or(config::GRAMS_TOLERANCE_DEFAULT),
+            ),
+            DispenseAmount::Default | DispenseAmount::Degrees(_) => return None,
+        };
+        Some(WeightTarget {
+            target_grams,
+            tolerance_grams,
+            chunk_degrees: app_config
+                .motor
+                .piece_chunk_degrees
+                .unwrap_or(config::PIECE_CHUNK_DEGREES_DEFAULT),
+            max_degrees: app_config
+                .motor
+                .max_dispense_degrees
+                .unwrap_or(config::MAX_DISPENSE_DEGREES_DEFAULT),
+            timeout: Duration::from_secs(
+                app_config
+                    .motor
+                    .weight_dispense_timeout_secs
+                    .unwrap_or(config::WEIGHT_DISPENSE_TIMEOUT_SECS_DEFAULT),
+            ),
+        })
+    }
+
+    /// True once `dropped_grams` is within the tolerance of the target.
+    fn reached(&self, dropped_grams: f32) -> bool {
+        dropped_grams >= self.target_grams - self.tolerance_grams
+    }
+}
+
+/// Closed loop dispense: runs the motor in `chunk_degrees` increments and weighs the
+/// hopper in between until the weight dropped by the target amount. Stops with a warning
+/// once `max_degrees` were turned without reaching it, e.g. when the hopper runs empty.
+/// Cancels the dispense, stopping the motor mid-run, once `timeout` has passed, e.g.
+/// when the scale stopped updating.
+async fn dispense_by_weight(
+    motor: &Arc<Box<dyn AsyncStepperMotor + Send + Sync>>,
+    app_state: &AppStateMutex,
+    cancel_token: &CancellationToken,
+    target: &WeightTarget,
+    grams_before: f32,
+) -> Result<u32, String> {
+    let timeout = target.timeout;
+    let target_grams = target.target_grams;
+    let timeout_state = Arc::clone(app_state);
+    let timeout_token = cancel_token.clone();
+    let timeout_task = tokio::spawn(
+        async move {
+            tokio::time::sleep(timeout).await;
+            let reason = format!(
+                "Timed out after {} s before {:.1} g were dispensed",
+                timeout.as_secs(),
+                target_grams
+            );
+            warn!("{}", reason);
+            timeout_state.lock().await.motor_cancel_reason = Some(reason);
+            timeout_token.cancel();
+        }
+        .in_current_span(),
+    );
+    let result = run_weight_chunks(motor, app_state, cancel_token, target, grams_before).await;
+    timeout_task.abort();
+    result
+}
+
+async fn run_weight_chunks(
+    motor: &Arc<Box<dyn AsyncStepperMotor + Send + Sync>>,
+    app_state: &AppStateMutex,
+    cancel_token: &CancellationToken,
+    target: &WeightTarget,
+    grams_before: f32,
+) -> Result<u32, String> {
+    let mut total_degrees = 0.0;
+    let mut total_steps = 0;
+    loop {
+        total_steps += motor
+            .run_motor_degrees_async(
+                target.chunk_degrees,
+                &Direction::CounterClockwise,
+                &StepMode::Full,
+                app_state,
+                cancel_token,
+            )
+            .await?;
+        total_degrees += target.chunk_degrees;
+
+        tokio::select! {
+            _ = tokio::time::sleep(WEIGHT_SETTLE_DELAY) => {}
+            _ = cancel_token.cancelled() => return Err("Motor operation cancelled".to_string()),
+        }
+        let dropped_grams = grams_before - motor_vibration::settled_grams(app_state).await;
+        debug!(
+            "Dispensed {:.1} of {:.1} g after {}°",
+            dropped_grams, target.target_grams, total_degrees
+        );
+        if target.reached(dropped_grams) {
+            return Ok(total_steps);
+        }
+        if total_degrees + target.chunk_degrees > target.max_degrees {
+            warn!(
+                "Stopping after {}°, only {:.1} of {:.1} g dispensed",
+                total_degrees, dropped_grams, target.target_grams
+            );
+            return Ok(total_steps);
+        }
+    }
+}
+
+/// Trickle dispense: bursts of `plan.burst_grams` dispensed by weight, `plan.interval`
+/// apart, until the hopper weight dropped by the target. Waits while paused, and stops
+/// early with a warning if a burst falls short, e.g. when the hopper runs empty.
+async fn dispense_trickle(
+    motor: &Arc<Box<dyn AsyncStepperMotor + Send + Sync>>,
+    app_state: &AppStateMutex,
+    cancel_token: &CancellationToken,
+    target: &WeightTarget,
+    plan: TricklePlan,
+    mut pause_rx: watch::Receiver<bool>,
+    grams_before: f32,
+) -> Result<u32, String> {
+    let mut total_steps = 0;
+    loop {
+        let burst_before = motor_vibration::settled_grams(app_state).await;
+        let burst_grams = plan
+            .burst_grams
+            .min(target.target_grams - (grams_before - burst_before));
+        // a burst has to deliver at least half its weight, so every burst makes progress
+        let burst = WeightTarget {
+            target_grams: burst_grams,
+            tolerance_grams: target.tolerance_grams.min(burst_grams / 2.0),
+            ..target.clone()
+        };
+        total_steps += dispense_by_weight(motor, app_state, cancel_token, &burst, burst_before).await?;
+
+        let grams_after = motor_vibration::settled_grams(app_state).await;
+        let dispensed_grams = grams_before - grams_after;
+        trickle::burst_done(app_state, dispensed_grams).await;
+        if target.reached(dispensed_grams) {
+            return Ok(total_steps);
+        }
+        if !burst.reached(burst_before - grams_after) {
+            warn!(
+                "Stopping trickle, burst dispensed only {:.1} of {:.1} g ({:.1} of {:.1} g in total)",
+                burst_before - grams_after,
+                burst.target_grams,
+                dispensed_grams,
+                target.target_grams
+            );
+            return Ok(total_steps);
+        }
+        trickle::wait_for_next_burst(app_state, &mut pause_rx, plan.interval, cancel_token)
+            .await?;
+    }
+}
+
+/// The span everything logged during a dispense job is attached to.
+fn dispense_span(job_id: &str, amount: DispenseAmount, trigger: TriggerSource) -> tracing::Span {
+    info_span!(
+        "dispense",
+        job_id = %job_id,
+        profile = amount.profile(),
+        trigger = %trigger
+    )
+}
 
 /// Dispenses treats by controlling GPIO pins for a stepper motor.
 /// This function updates the dispenser state to "Dispensing" before starting the dispensing process.
 /// It uses a background task to perform the dispensing steps without blocking the main thread and thus
 /// does not affect API responsiveness.
 /// After dispensing, it updates the state to "Operational" and records the last dispense time.
-pub async fn dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
+/// A journal file marks the motor run, so a dispense cut short by a restart is recovered at
+/// startup, see `dispense_recovery`.
+///
+/// Everything logged while the job runs, including by the motor driver and power monitor,
+/// is attached to a `dispense` span carrying the job ID, profile and trigger source.
+///
+/// * `amount` - Fixed rotation, number of pieces or grams, or grams trickled over time.
+/// * `note` - Free text recorded in the history and on the `dispensed` event.
+pub async fn dispense(
+    app_state: AppStateMutex,
+    trigger: TriggerSource,
+    amount: DispenseAmount,
+    note: Option<String>,
+) -> Result<(), ApiError> {
     let motor: Arc<Box<dyn AsyncStepperMotor + Send + Sync>>;
+    let motor_degrees: f32;
+    let weight_target: Option<WeightTarget>;
+    let mut trickle: Option<(TricklePlan, watch::Receiver<bool>)> = None;
+    let jam_config: Option<config::JamDetectionConfig>;
+    let grams_before: f32;
+    let attempt: HistoryEntry;
 
     // query status before starting the process, done atomically to avoid race conditions
     {
         let mut state_guard = app_state.lock().await;
         match state_guard.status {
             DispenserStatus::Operational | DispenserStatus::Cancelled => {
-                state_guard.status = DispenserStatus::Dispensing;
+                if let Some(reason) = temperature_monitor::dispense_blocked_reason(
+                    state_guard.app_config.temperature.as_ref(),
+                    state_guard.temperature_readings_rx.borrow().as_ref(),
+                ) {
+                    return Err(ApiError::Busy(reason));
+                }
+                state_guard.set_status(DispenserStatus::Dispensing);
                 motor = Arc::clone(&state_guard.motor);
+                motor_degrees = match amount {
+                    DispenseAmount::Degrees(degrees) => degrees,
+                    _ => state_guard
+                        .app_config
+                        .motor
+                        .dispense_degrees
+                        .unwrap_or(config::DISPENSE_DEGREES_DEFAULT),
+                };
+                weight_target = WeightTarget::from_config(amount, &state_guard.app_config);
+                if let DispenseAmount::Trickle { grams, duration_minutes } = amount {
+                    let plan = TricklePlan::new(grams, duration_minutes, &state_guard.app_config);
+                    let (job, pause_rx) = TrickleJob::new(grams, &plan);
+                    state_guard.trickle_job = Some(job);
+                    trickle = Some((plan, pause_rx));
+                }
+                // dispensing by weight already weighs the hopper between motor runs
+                jam_config = match amount {
+                    DispenseAmount::Pieces(_)
+                    | DispenseAmount::Grams(_)
+                    | DispenseAmount::Trickle { .. } => None,
+                    _ => state_guard
+                        .app_config
+                        .weight_monitor
+                        .jam_detection
+                        .clone()
+                        .filter(|c| c.enabled.unwrap_or(true)),
+                };
+                grams_before = state_guard.weight_readings_rx.borrow().grams;
+                attempt = HistoryEntry::new(
+                    trigger,
+                    amount,
+                    &state_guard.app_config,
+                    DispenseResult::Completed,
+                )
+                .with_note(note.clone());
             }
             DispenserStatus::Dispensing => {
                 return Err(ApiError::Busy(
@@ -34,6 +487,17 @@ pub async fn dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
             DispenserStatus::Cooldown => {
                 return Err(ApiError::Busy("Waiting for cooldown".to_string()));
             }
+            DispenserStatus::Stirring => {
+                return Err(ApiError::Busy("Hopper is being stirred".to_string()));
+            }
+            DispenserStatus::MeasuringCurrent => {
+                return Err(ApiError::Busy("Motor current is being measured".to_string()));
+            }
+            DispenserStatus::Calibrating => {
+                return Err(ApiError::Busy(
+                    "Weight sensor is being calibrated".to_string(),
+                ));
+            }
             DispenserStatus::Empty => {
                 return Err(ApiError::Hardware("Dispenser is empty".to_string()));
             }
@@ -46,43 +510,131 @@ pub async fn dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
         }
     }; // Lock is released here, we want to avoid holding the lock for long periods so other tasks can access the state
 
-    info!("Dispensing treatos...");
+    let job_id = format!("{:08x}", rand::random::<u32>());
+    let span = dispense_span(&job_id, amount, trigger);
+    span.in_scope(|| info!("Dispensing treatos ({:?})...", amount));
+    dispense_recovery::begin(&DispenseJournal {
+        job_id: job_id.clone(),
+        trigger,
+        started_at: datetime::get_formatted_current_timestamp(),
+    });
     let app_state_clone = Arc::clone(&app_state);
 
     tokio::spawn(async move {
         let cancel_token = {
             let token = CancellationToken::new();
-            // short lock to set the cancellation token
-            app_state_clone.lock().await.motor_cancel_token = Some(token.clone());
+            // short lock to set the cancellation token and publish the span to other tasks
+            let mut state_guard = app_state_clone.lock().await;
+            state_guard.motor_cancel_token = Some(token.clone());
+            state_guard.motor_cancel_reason = None;
+            state_guard.motor_jam_reason = None;
+            state_guard.dispense_span = Some(tracing::Span::current());
             token
         };
 
+        let jam_detector = match &jam_config {
+            Some(jam_config) => {
+                let (events_rx, weight_readings_rx) = {
+                    let state_guard = app_state_clone.lock().await;
+                    (state_guard.event_bus.subscribe(), state_guard.weight_readings_rx.clone())
+                };
+                let detector = JamDetector::new(jam_config, grams_before);
+                Some(tokio::spawn(
+                    detector
+                        .watch(events_rx, weight_readings_rx, cancel_token.clone())
+                        .in_current_span(),
+                ))
+            }
+            None => None,
+        };
+
         let step_mode = StepMode::Full;
         let dir = Direction::CounterClockwise;
-        let async_motor_run_result = motor
-            .run_motor_degrees_async(2160.0, &dir, &step_mode, &app_state_clone, &cancel_token)
-            .await;
+        let async_motor_run_result = match (&weight_target, trickle) {
+            (Some(target), Some((plan, pause_rx))) => {
+                dispense_trickle(&motor, &app_state_clone, &cancel_token, target, plan, pause_rx, grams_before).await
+            }
+            (Some(target), None) => {
+                dispense_by_weight(&motor, &app_state_clone, &cancel_token, target, grams_before).await
+            }
+            (None, _) => {
+                motor
+                    .run_motor_degrees_async(motor_degrees, &dir, &step_mode, &app_state_clone, &cancel_token)
+                    .await
+            }
+        };
+
+        let jam = match jam_detector {
+            Some(handle) => {
+                handle.abort();
+                handle.await.ok().flatten()
+            }
+            None => None,
+        };
+        let jam = match jam {
+            Some(jam) => Some(jam),
+            None => app_state_clone.lock().await.motor_jam_reason.take(),
+        };
+        // the motor stopped, a restart from here on doesn't interrupt the dispense
+        dispense_recovery::finish();
 
         match async_motor_run_result {
             Ok(steps) => {
                 info!("Motor run completed successfully, steps: {}", steps);
+                let event_bus = app_state_clone.lock().await.event_bus.clone();
+                event_bus.publish_dispensed(trigger, note.as_deref());
+                let stats_state = Arc::clone(&app_state_clone);
+                let attempt = attempt.with_steps(steps);
+                tokio::spawn(
+                    async move {
+                        stats::record_dispense(&stats_state, trigger, grams_before, attempt).await;
+                    }
+                    .in_current_span(),
+                );
                 // enforce a cooldown period after operation
+                let cooldown = {
+                    let mut state_guard = app_state_clone.lock().await;
+                    let cooldown = Duration::from_millis(state_guard.app_config.motor.cooldown_ms.unwrap_or(config::MOTOR_COOLDOWN_MS_DEFAULT));
+                    state_guard.cooldown_until = Some(Instant::now() + cooldown);
+                    cooldown
+                };
                 set_dispenser_status_async(&app_state_clone, DispenserStatus::Cooldown).await;
-                let cooldown_ms = app_state_clone.lock().await.app_config.motor.cooldown_ms.unwrap_or(config::MOTOR_COOLDOWN_MS_DEFAULT);
-                tokio::time::sleep(Duration::from_millis(cooldown_ms)).await;
+                tokio::time::sleep(cooldown).await;
 
                 let mut state_guard = app_state_clone.lock().await;
                 state_guard.last_dispense_time = Some(datetime::get_formatted_current_timestamp());
-                state_guard.status = DispenserStatus::Operational;
+                state_guard.cooldown_until = None;
+                state_guard.set_status(DispenserStatus::Operational);
                 state_guard.last_step_index = Some(async_motor_run_result.unwrap());
                 info!("Treatos dispensed successfully!");
             }
             Err(e) => {
                 warn!("Motor operation ended: {:?}", e);
-                if cancel_token.is_cancelled() {
+                let failed = |result| HistoryEntry { result, ..attempt.clone() };
+                let event_bus = app_state_clone.lock().await.event_bus.clone();
+                if let Some(jam) = jam {
+                    history::record(failed(DispenseResult::Jammed).with_reason(jam.clone()));
+                    event_bus.publish_dispense_ended(EventKind::DispenseFailed, trigger, &jam);
+                    state_helpers::record_error(&app_state_clone, &jam).await;
+                    set_dispenser_status_async(&app_state_clone, DispenserStatus::Jammed).await;
+                } else if cancel_token.is_cancelled() {
                     warn!("Motor operation was cancelled.");
+                    let reason = app_state_clone.lock().await.motor_cancel_reason.take().unwrap_or(e);
+                    event_bus.publish_dispense_ended(EventKind::DispenseCancelled, trigger, &reason);
+                    history::record(failed(DispenseResult::Cancelled).with_reason(reason));
                     set_dispenser_status_async(&app_state_clone, DispenserStatus::Cancelled).await;
+                } else if e.starts_with(motor::STALL_ERROR_PREFIX) {
+                    history::record(failed(DispenseResult::Jammed).with_reason(e.clone()));
+                    event_bus.publish_dispense_ended(EventKind::DispenseFailed, trigger, &e);
+                    state_helpers::record_error(&app_state_clone, &e).await;
+                    set_dispenser_status_async(&app_state_clone, DispenserStatus::Jammed).await;
                 } else {
+                    history::record(failed(DispenseResult::Failed).with_reason(e.clone()));
+                    event_bus.publish_dispense_ended(EventKind::DispenseFailed, trigger, &e);
+                    error_reporting::report(
+                        ErrorKind::BackgroundTask,
+                        format!("Dispense motor run failed: {}", e),
+                    );
                     set_dispenser_status_async(&app_state_clone, DispenserStatus::Unknown).await;
                 }
             }
@@ -92,11 +644,13 @@ pub async fn dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
         {
             let mut state_guard = app_state_clone.lock().await;
             state_guard.motor_cancel_token = None;
+            state_guard.dispense_span = None;
+            state_guard.trickle_job = None;
             debug!("Motor cancellation token cleared after dispensing.");
         }
-    });
+    }.instrument(span.clone()));
 
-    info!("Dispensing process started in the background.");
+    span.in_scope(|| info!("Dispensing process started in the background."));
     Ok(())
 }
 
@@ -106,7 +660,8 @@ pub async fn cancel_dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
     if let Some(cancel_token) = &state_guard.motor_cancel_token {
         cancel_token.cancel();
         info!("Motor operation cancelled successfully.");
-        state_guard.status = DispenserStatus::Cancelled;
+        state_guard.motor_cancel_reason = Some("Cancelled through the API".to_string());
+        state_guard.set_status(DispenserStatus::Cancelled);
         state_guard.motor_cancel_token = None;
     } else {
         return Err(ApiError::Hardware(
@@ -116,3 +671,72 @@ pub async fn cancel_dispense(app_state: AppStateMutex) -> Result<(), ApiError> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+    use tracing::field::{Field, Visit};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::{Layer, registry};
+
+    /// Keeps the fields of every new span.
+    #[derive(Clone, Default)]
+    struct SpanFields(Arc<Mutex<Vec<BTreeMap<String, String>>>>);
+
+    struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for SpanFields {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: Context<'_, S>,
+        ) {
+            let mut fields = BTreeMap::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+    }
+
+    #[test]
+    fn test_dispense_span_fields() {
+        let spans = SpanFields::default();
+        let subscriber = registry().with(spans.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            dispense_span(
+                "3f9a01c2",
+                DispenseAmount::Pieces(3),
+                TriggerSource::Schedule,
+            );
+            dispense_span(
+                "3f9a01c3",
+                DispenseAmount::Trickle {
+                    grams: 20.0,
+                    duration_minutes: 30,
+                },
+                TriggerSource::ApiUser,
+            );
+        });
+
+        let spans = spans.0.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["job_id"], "3f9a01c2");
+        assert_eq!(spans[0]["profile"], "pieces");
+        assert_eq!(spans[0]["trigger"], "schedule");
+        assert_eq!(spans[1]["profile"], "trickle");
+        assert_eq!(spans[1]["trigger"], "api-user");
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::{Local, NaiveTime};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::application_state::ApplicationState;
+use crate::config::{ConserveWindow, EnergyConfig};
+use crate::services::events::EventKind;
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|e| format!("Invalid window time '{}': {}", time, e))
+}
+
+/// Whether `now` falls into `window`, an error if its times aren't `HH:MM`.
+pub fn in_window(window: &ConserveWindow, now: NaiveTime) -> Result<bool, String> {
+    let (start, end) = (parse_time(&window.start)?, parse_time(&window.end)?);
+    Ok(if start <= end {
+        start <= now && now < end
+    } else {
+        // spans midnight
+        now >= start || now < end
+    })
+}
+
+/// Why energy should be conserved right now, if it should. `bus_voltage_volts` of zero
+/// means there is no power reading, a missing sensor shouldn't hold back tasks forever.
+pub fn conserve_reason(
+    energy_config: Option<&EnergyConfig>,
+    bus_voltage_volts: f32,
+    now: NaiveTime,
+) -> Option<String> {
+    let energy_config = energy_config?;
+    if let Some(low) = energy_config.low_battery_volts
+        && bus_voltage_volts > 0.0
+        && bus_voltage_volts < low
+    {
+        return Some(format!(
+            "battery low ({:.2} V, threshold {:.2} V)",
+            bus_voltage_volts, low
+        ));
+    }
+    for window in energy_config.conserve_windows.iter().flatten() {
+        match in_window(window, now) {
+            Ok(true) => {
+                return Some(format!("conserve window {}-{}", window.start, window.end));
+            }
+            Ok(false) => {}
+            Err(e) => warn!("{}", e),
+        }
+    }
+    None
+}
+
+/// Checks the `energy` policy before a non-urgent background task runs. Returns true
+/// and publishes a `task_deferred` event if the task should wait.
+pub async fn defer_task(app_state: &Arc<Mutex<ApplicationState>>, task: &str) -> bool {
+    let state_guard = app_state.lock().await;
+    let Some(reason) = conserve_reason(
+        state_guard.app_config.energy.as_ref(),
+        state_guard.power_readings_rx.borrow().bus_voltage_volts,
+        Local::now().time(),
+    ) else {
+        return false;
+    };
+
+    let message = format!("Deferred {}: {}", task, reason);
+    info!("{}", message);
+    state_guard
+        .event_bus
+        .publish(EventKind::TaskDeferred, message);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conserve_reason() {
+        let energy_config = EnergyConfig {
+            low_battery_volts: Some(11.8),
+            conserve_windows: Some(vec![ConserveWindow {
+                start: "20:00".to_string(),
+                end: "07:00".to_string(),
+            }]),
+        };
+        let at = |time: &str| parse_time(time).unwrap();
+
+        assert!(conserve_reason(Some(&energy_config), 12.4, at("12:00")).is_none());
+        assert!(conserve_reason(Some(&energy_config), 11.5, at("12:00")).is_some());
+        // no power reading
+        assert!(conserve_reason(Some(&energy_config), 0.0, at("12:00")).is_none());
+        // the window spans midnight
+        assert!(conserve_reason(Some(&energy_config), 12.4, at("23:30")).is_some());
+        assert!(conserve_reason(Some(&energy_config), 12.4, at("06:59")).is_some());
+        assert!(conserve_reason(Some(&energy_config), 12.4, at("07:00")).is_none());
+        assert!(conserve_reason(None, 11.5, at("23:30")).is_none());
+    }
+}
@@ -0,0 +1,384 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::config::{self, AppConfig};
+
+static REPORTER: OnceLock<mpsc::UnboundedSender<ErrorReport>> = OnceLock::new();
+static DEVICE: OnceLock<DeviceMetadata> = OnceLock::new();
+/// When each message was last sent, shared by the reporter task and the panic hook
+static RECENTLY_REPORTED: Mutex<Option<HashMap<String, Instant>>> = Mutex::new(None);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Panic,
+    Internal,
+    BackgroundTask,
+}
+
+/// Payload POSTed to the generic error report webhook.
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorReport {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub timestamp: String,
+    pub device: DeviceMetadata,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DeviceMetadata {
+    /// From the `device` section, so reports from several dispensers can be told apart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fleet_id: Option<String>,
+    pub hostname: String,
+    pub version: String,
+    pub os: String,
+    pub motor: String,
+    pub environment: String,
+}
+
+/// Sentry project endpoint derived from a DSN (`https://<key>@<host>/<project_id>`).
+#[derive(Debug, Clone, PartialEq)]
+struct SentryTarget {
+    store_url: String,
+    public_key: String,
+}
+
+impl SentryTarget {
+    fn from_dsn(dsn: &str) -> Result<Self, String> {
+        let url = reqwest::Url::parse(dsn).map_err(|e| format!("Invalid Sentry DSN: {}", e))?;
+        let public_key = url.username();
+        if public_key.is_empty() {
+            return Err("Invalid Sentry DSN: missing public key".to_string());
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| "Invalid Sentry DSN: missing host".to_string())?;
+        let project_id = url.path().trim_matches('/');
+        if project_id.is_empty() {
+            return Err("Invalid Sentry DSN: missing project id".to_string());
+        }
+        let port = url.port().map(|p| format!(":{}", p)).unwrap_or_default();
+
+        Ok(SentryTarget {
+            store_url: format!(
+                "{}://{}{}/api/{}/store/",
+                url.scheme(),
+                host,
+                port,
+                project_id
+            ),
+            public_key: public_key.to_string(),
+        })
+    }
+}
+
+/// Starts the error reporting task if it is enabled in config and installs a panic hook
+/// that forwards panics to it. Safe to call more than once, only the first call has effect.
+pub fn init(app_config: &AppConfig) {
+    let reporting_config = match &app_config.error_reporting {
+        Some(c) if c.enabled.unwrap_or(true) => c.clone(),
+        _ => return,
+    };
+
+    if REPORTER.get().is_some() {
+        return;
+    }
+
+    let sentry = match reporting_config.sentry_dsn.as_deref().map(SentryTarget::from_dsn) {
+        Some(Ok(target)) => Some(target),
+        Some(Err(e)) => {
+            error!("Sentry reporting disabled: {}", e);
+            None
+        }
+        None => None,
+    };
+    let targets = Targets {
+        sentry,
+        webhook_url: reporting_config.webhook_url.clone(),
+    };
+
+    if targets.sentry.is_none() && targets.webhook_url.is_none() {
+        warn!("Error reporting is enabled but neither sentry_dsn nor webhook_url is configured");
+        return;
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    if REPORTER.set(tx).is_err() {
+        return;
+    }
+
+    let device_config = app_config.device.as_ref();
+    let device = DeviceMetadata {
+        name: device_config.map(|d| d.name.clone()),
+        location: device_config.and_then(|d| d.location.clone()),
+        fleet_id: device_config.and_then(|d| d.fleet_id.clone()),
+        hostname: sysinfo::System::host_name().unwrap_or_else(|| "unknown".to_string()),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        os: sysinfo::System::long_os_version().unwrap_or_else(|| "unknown".to_string()),
+        motor: app_config.motor.motor_type.clone(),
+        environment: reporting_config
+            .environment
+            .clone()
+            .unwrap_or_else(|| config::ERROR_REPORTING_ENVIRONMENT_DEFAULT.to_string()),
+    };
+    let _ = DEVICE.set(device);
+
+    tokio::spawn(run_reporter(rx, targets.clone()));
+    install_panic_hook(targets);
+    info!("Error reporting enabled");
+}
+
+/// Queues an error for delivery. Does nothing when error reporting is not configured.
+pub fn report(kind: ErrorKind, message: impl Into<String>) {
+    let (Some(tx), Some(report)) = (REPORTER.get(), new_report(kind, message.into())) else {
+        return;
+    };
+    let _ = tx.send(report);
+}
+
+fn new_report(kind: ErrorKind, message: String) -> Option<ErrorReport> {
+    Some(ErrorReport {
+        kind,
+        message,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        device: DEVICE.get()?.clone(),
+    })
+}
+
+/// Panics are sent from the hook itself instead of being queued, the process may exit
+/// as soon as the hook returns and take the reporter task with it.
+fn install_panic_hook(targets: Targets) {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        previous_hook(panic_info);
+        let location = panic_info
+            .location()
+            .map(|l| format!(" at {}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        let message = format!("{}{}", panic_message(panic_info.payload()), location);
+        if let Some(report) = new_report(ErrorKind::Panic, message) {
+            send_now(&targets, report);
+        }
+    }));
+}
+
+/// Sends `report` and waits for it, at most `ERROR_REPORTING_PANIC_TIMEOUT_SECS`. The
+/// panicking thread may be a runtime worker, which can't block on a future, so the
+/// request runs on its own thread and runtime.
+fn send_now(targets: &Targets, report: ErrorReport) {
+    let timeout = Duration::from_secs(config::ERROR_REPORTING_PANIC_TIMEOUT_SECS);
+    let targets = targets.clone();
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    let sender = std::thread::Builder::new()
+        .name("panic-report".to_string())
+        .spawn(move || {
+            if let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                runtime.block_on(targets.send(&http_client(timeout), &report));
+            }
+            let _ = done_tx.send(());
+        });
+    if sender.is_ok() {
+        let _ = done_rx.recv_timeout(timeout);
+    }
+}
+
+/// Extracts the message from a panic payload, which is a `&str` or `String` for
+/// `panic!` invocations with a message.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+fn http_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .unwrap_or_default()
+}
+
+/// Where reports go, from the `error_reporting` section.
+#[derive(Debug, Clone)]
+struct Targets {
+    sentry: Option<SentryTarget>,
+    webhook_url: Option<String>,
+}
+
+impl Targets {
+    async fn send(&self, client: &reqwest::Client, report: &ErrorReport) {
+        // identical messages are only reported once per window so a failing sensor loop can't flood the endpoint
+        if !first_in_window(&report.message) {
+            return;
+        }
+
+        if let Some(url) = &self.webhook_url
+            && let Err(e) = client.post(url).json(report).send().await.and_then(|r| r.error_for_status())
+        {
+            warn!("Failed to deliver error report to webhook: {}", e);
+        }
+
+        if let Some(target) = &self.sentry
+            && let Err(e) = send_to_sentry(client, target, report).await
+        {
+            warn!("Failed to deliver error report to Sentry: {}", e);
+        }
+    }
+}
+
+/// Whether `message` wasn't reported within the dedup window, and records it as reported.
+fn first_in_window(message: &str) -> bool {
+    let now = Instant::now();
+    let dedup_window = Duration::from_secs(config::ERROR_REPORTING_DEDUP_WINDOW_SECS);
+    let mut guard = RECENTLY_REPORTED.lock().unwrap_or_else(|e| e.into_inner());
+    let recently_reported = guard.get_or_insert_with(HashMap::new);
+    recently_reported.retain(|_, reported_at| now.duration_since(*reported_at) < dedup_window);
+    if recently_reported.contains_key(message) {
+        return false;
+    }
+    recently_reported.insert(message.to_string(), now);
+    true
+}
+
+async fn run_reporter(mut rx: mpsc::UnboundedReceiver<ErrorReport>, targets: Targets) {
+    let client = http_client(Duration::from_secs(10));
+    while let Some(report) = rx.recv().await {
+        targets.send(&client, &report).await;
+    }
+}
+
+async fn send_to_sentry(
+    client: &reqwest::Client,
+    target: &SentryTarget,
+    report: &ErrorReport,
+) -> Result<(), reqwest::Error> {
+    let level = match report.kind {
+        ErrorKind::Panic => "fatal",
+        ErrorKind::Internal | ErrorKind::BackgroundTask => "error",
+    };
+    let mut tags = serde_json::json!({
+        "kind": report.kind,
+        "motor": report.device.motor,
+        "os": report.device.os,
+    });
+    // Sentry only accepts string tag values
+    for (key, value) in [
+        ("device", &report.device.name),
+        ("location", &report.device.location),
+        ("fleet_id", &report.device.fleet_id),
+    ] {
+        if let Some(value) = value {
+            tags[key] = value.as_str().into();
+        }
+    }
+    let event = serde_json::json!({
+        "event_id": format!("{:032x}", rand::random::<u128>()),
+        "timestamp": report.timestamp,
+        "level": level,
+        "platform": "other",
+        "logger": "treat-dispenser-api",
+        "server_name": report.device.hostname,
+        "release": report.device.version,
+        "environment": report.device.environment,
+        "message": { "formatted": report.message },
+        "tags": tags,
+    });
+
+    client
+        .post(&target.store_url)
+        .header(
+            "X-Sentry-Auth",
+            format!(
+                "Sentry sentry_version=7, sentry_client=treat-dispenser-api/{}, sentry_key={}",
+                report.device.version, target.public_key
+            ),
+        )
+        .json(&event)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentry_target_from_dsn() {
+        let target = SentryTarget::from_dsn("https://abc123@o42.ingest.sentry.io/4501").unwrap();
+        assert_eq!(
+            target,
+            SentryTarget {
+                store_url: "https://o42.ingest.sentry.io/api/4501/store/".to_string(),
+                public_key: "abc123".to_string(),
+            }
+        );
+
+        let self_hosted = SentryTarget::from_dsn("http://key@sentry.lan:9000/7").unwrap();
+        assert_eq!(self_hosted.store_url, "http://sentry.lan:9000/api/7/store/");
+
+        assert!(SentryTarget::from_dsn("https://sentry.io/1").is_err());
+        assert!(SentryTarget::from_dsn("https://key@sentry.io/").is_err());
+    }
+
+    #[test]
+    fn test_send_now_waits_for_delivery() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/errors", listener.local_addr().unwrap());
+        let (received_tx, received_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("boom at src/lib.rs") {
+                let n = stream.read(&mut buf).unwrap();
+                assert!(n > 0, "connection closed before the report was sent");
+                request.extend_from_slice(&buf[..n]);
+            }
+            received_tx.send(()).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let targets = Targets {
+            sentry: None,
+            webhook_url: Some(url),
+        };
+        let report = ErrorReport {
+            kind: ErrorKind::Panic,
+            message: format!("boom at src/lib.rs:{}", rand::random::<u32>()),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            device: DeviceMetadata {
+                name: None,
+                location: None,
+                fleet_id: None,
+                hostname: "test".to_string(),
+                version: "0.0.0".to_string(),
+                os: "test".to_string(),
+                motor: "noop".to_string(),
+                environment: "test".to_string(),
+            },
+        };
+        send_now(&targets, report);
+        assert!(received_rx.try_recv().is_ok());
+    }
+}
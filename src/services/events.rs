@@ -0,0 +1,349 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+use crate::application_state::DispenserStatus;
+use crate::services::bowl::BowlMeal;
+use crate::services::dispenser::TriggerSource;
+use crate::services::trickle::TrickleProgress;
+use crate::utils::datetime;
+
+/// Number of events kept in memory for `GET /events`.
+const RECENT_EVENTS_CAPACITY: usize = 200;
+
+#[derive(Serialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    TaskPanicked,
+    TaskRestarted,
+    BackupCompleted,
+    BackupFailed,
+    StatusChanged,
+    Dispensed,
+    DispenseFailed,
+    DispenseCancelled,
+    ChannelStale,
+    ChannelRecovered,
+    StepLoss,
+    DispenseProgress,
+    HopperStirred,
+    Refilled,
+    InputChanged,
+    OutputChanged,
+    FanSwitched,
+    HumidityHigh,
+    HumidityNormal,
+    TaskDeferred,
+    RecoveredInterrupted,
+    FileQuarantined,
+    MigrationFailed,
+    OvercurrentProtectionDisabled,
+    OvercurrentProtectionEnabled,
+    CurrentLimitChanged,
+    OvercurrentDetected,
+    CurrentJamDetected,
+    ConfigReloaded,
+    WeightSensorSettingsApplied,
+    WeightSensorSettingsRejected,
+    WeightSensorTared,
+    WeightSensorCalibrated,
+    LoadCellFailing,
+    LoadCellRecovered,
+    BowlEmptied,
+    TrickleProgress,
+    TrainingRewarded,
+    FeedingUpcoming,
+    HopperLow,
+}
+
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct DispenserEvent {
+    pub kind: EventKind,
+    pub message: String,
+    pub timestamp: String,
+    /// New dispenser status, set for `StatusChanged` events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<DispenserStatus>,
+    /// Motor run progress, set for `DispenseProgress` events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<DispenseProgress>,
+    /// What caused the dispense, set for `Dispensed`, `DispenseFailed` and `DispenseCancelled`
+    /// events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<TriggerSource>,
+    /// Input and its new level, set for `InputChanged` events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<InputChange>,
+    /// Output and its new state, set for `OutputChanged` events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputChange>,
+    /// What the pet ate, set for `BowlEmptied` events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meal: Option<BowlMeal>,
+    /// Trickle dispense progress, set for `TrickleProgress` events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trickle: Option<TrickleProgress>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct DispenseProgress {
+    pub percent: u8,
+    pub steps_done: u32,
+    pub total_steps: u32,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct InputChange {
+    pub label: String,
+    pub high: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct OutputChange {
+    pub label: String,
+    pub on: bool,
+}
+
+/// In-process event log. Events are kept in a bounded ring buffer for later inspection
+/// and broadcast to any live subscribers.
+pub struct EventBus {
+    sender: broadcast::Sender<DispenserEvent>,
+    recent: Mutex<VecDeque<DispenserEvent>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(RECENT_EVENTS_CAPACITY);
+        EventBus {
+            sender,
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_EVENTS_CAPACITY)),
+        }
+    }
+
+    pub fn publish(&self, kind: EventKind, message: impl Into<String>) {
+        self.publish_event(DispenserEvent {
+            kind,
+            message: message.into(),
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: None,
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    pub fn publish_status_change(&self, status: &DispenserStatus) {
+        self.publish_event(DispenserEvent {
+            kind: EventKind::StatusChanged,
+            message: format!("Dispenser status changed to {}", status),
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: Some(status.clone()),
+            progress: None,
+            trigger: None,
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    pub fn publish_progress(&self, progress: DispenseProgress) {
+        self.publish_event(DispenserEvent {
+            kind: EventKind::DispenseProgress,
+            message: format!(
+                "Dispensing {}% ({}/{} steps)",
+                progress.percent, progress.steps_done, progress.total_steps
+            ),
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: Some(progress),
+            trigger: None,
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    pub fn publish_dispensed(&self, trigger: TriggerSource, note: Option<&str>) {
+        let message = match note {
+            Some(note) => format!("Treats dispensed (trigger: {}): {}", trigger, note),
+            None => format!("Treats dispensed (trigger: {})", trigger),
+        };
+        self.publish_event(DispenserEvent {
+            kind: EventKind::Dispensed,
+            message,
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: Some(trigger),
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    /// Publishes a `DispenseFailed` or `DispenseCancelled` event with the reason the motor
+    /// run ended.
+    pub fn publish_dispense_ended(&self, kind: EventKind, trigger: TriggerSource, reason: &str) {
+        let message = match kind {
+            EventKind::DispenseCancelled => {
+                format!("Dispense cancelled (trigger: {}): {}", trigger, reason)
+            }
+            _ => format!("Dispense failed (trigger: {}): {}", trigger, reason),
+        };
+        self.publish_event(DispenserEvent {
+            kind,
+            message,
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: Some(trigger),
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    pub fn publish_input_change(&self, label: &str, high: bool) {
+        self.publish_event(DispenserEvent {
+            kind: EventKind::InputChanged,
+            message: format!(
+                "Input '{}' changed to {}",
+                label,
+                if high { "high" } else { "low" }
+            ),
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: None,
+            input: Some(InputChange {
+                label: label.to_string(),
+                high,
+            }),
+            output: None,
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    pub fn publish_output_change(&self, label: &str, on: bool) {
+        self.publish_event(DispenserEvent {
+            kind: EventKind::OutputChanged,
+            message: format!(
+                "Output '{}' switched {}",
+                label,
+                if on { "on" } else { "off" }
+            ),
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: None,
+            input: None,
+            output: Some(OutputChange {
+                label: label.to_string(),
+                on,
+            }),
+            meal: None,
+            trickle: None,
+        });
+    }
+
+    pub fn publish_bowl_emptied(&self, meal: BowlMeal) {
+        let message = match meal.minutes_after_dispense {
+            Some(minutes) => format!(
+                "Pet ate {:.1} g from the bowl, {} min after the dispense",
+                meal.grams, minutes
+            ),
+            None => format!("Pet ate {:.1} g from the bowl", meal.grams),
+        };
+        self.publish_event(DispenserEvent {
+            kind: EventKind::BowlEmptied,
+            message,
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: None,
+            input: None,
+            output: None,
+            meal: Some(meal),
+            trickle: None,
+        });
+    }
+
+    pub fn publish_trickle_progress(&self, message: String, progress: TrickleProgress) {
+        self.publish_event(DispenserEvent {
+            kind: EventKind::TrickleProgress,
+            message,
+            timestamp: datetime::get_formatted_current_timestamp(),
+            status: None,
+            progress: None,
+            trigger: None,
+            input: None,
+            output: None,
+            meal: None,
+            trickle: Some(progress),
+        });
+    }
+
+    fn publish_event(&self, event: DispenserEvent) {
+        {
+            let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+            if recent.len() == RECENT_EVENTS_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+
+        // no subscribers is not an error, the event is still in the ring buffer
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DispenserEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Returns recorded events, oldest first.
+    pub fn recent(&self) -> Vec<DispenserEvent> {
+        self.recent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_events_are_bounded() {
+        let bus = EventBus::new();
+        for i in 0..RECENT_EVENTS_CAPACITY + 5 {
+            bus.publish(EventKind::TaskRestarted, format!("event {}", i));
+        }
+
+        let recent = bus.recent();
+        assert_eq!(recent.len(), RECENT_EVENTS_CAPACITY);
+        assert_eq!(recent[0].message, "event 5");
+        assert_eq!(
+            recent.last().unwrap().message,
+            format!("event {}", RECENT_EVENTS_CAPACITY + 4)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use rppal::gpio::{Gpio, OutputPin};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::application_state::ApplicationState;
+use crate::config::{self, FanConfig};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FanMode {
+    /// Switched by the temperature controller
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+/// Fan state reported in `/status` and by `POST /fan`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct FanStatus {
+    pub mode: FanMode,
+    pub on: bool,
+    /// False if GPIO is unavailable or the pin is used by something else
+    pub available: bool,
+}
+
+/// Two point controller: the fan starts above the target temperature and only stops
+/// once it is `hysteresis` below it, so it doesn't cycle around the target.
+struct Hysteresis {
+    target_celsius: f32,
+    hysteresis_celsius: f32,
+}
+
+impl Hysteresis {
+    fn fan_on(&self, currently_on: bool, celsius: f32) -> bool {
+        if currently_on {
+            celsius > self.target_celsius - self.hysteresis_celsius
+        } else {
+            celsius > self.target_celsius
+        }
+    }
+}
+
+/// The enclosure fan and the pin driving it. The pin is unset if it couldn't be claimed.
+pub struct Fan {
+    pin: Option<OutputPin>,
+    speed_percent: u8,
+    controller: Hysteresis,
+    pub mode: FanMode,
+    pub on: bool,
+}
+
+impl Fan {
+    pub fn new(gpio: Option<&Gpio>, fan_config: &FanConfig) -> Self {
+        let pin = gpio.and_then(|gpio| match gpio.get(fan_config.pin) {
+            Ok(pin) => Some(pin.into_output_low()),
+            Err(e) => {
+                error!("Failed to get fan pin {}: {}", fan_config.pin, e);
+                None
+            }
+        });
+        Fan {
+            pin,
+            speed_percent: fan_config.speed_percent.unwrap_or(100).clamp(1, 100),
+            controller: Hysteresis {
+                target_celsius: fan_config.target_celsius,
+                hysteresis_celsius: fan_config
+                    .hysteresis_celsius
+                    .unwrap_or(config::FAN_HYSTERESIS_CELSIUS_DEFAULT),
+            },
+            mode: FanMode::Auto,
+            on: false,
+        }
+    }
+
+    pub fn status(&self) -> FanStatus {
+        FanStatus {
+            mode: self.mode,
+            on: self.on,
+            available: self.pin.is_some(),
+        }
+    }
+
+    /// Works out whether the fan should run in the current mode, `celsius` is only
+    /// used in auto mode. Returns true if the fan was switched.
+    fn update(&mut self, celsius: Option<f32>) -> Result<bool, String> {
+        let on = match self.mode {
+            FanMode::On => true,
+            FanMode::Off => false,
+            FanMode::Auto => match celsius {
+                Some(celsius) => self.controller.fan_on(self.on, celsius),
+                None => self.on,
+            },
+        };
+        if on == self.on {
+            return Ok(false);
+        }
+
+        let Some(pin) = self.pin.as_mut() else {
+            return Ok(false);
+        };
+        match (on, self.speed_percent) {
+            (false, _) => {
+                let _ = pin.clear_pwm();
+                pin.set_low();
+            }
+            (true, 100) => pin.set_high(),
+            (true, speed) => pin
+                .set_pwm_frequency(config::FAN_PWM_FREQUENCY_HZ, speed as f64 / 100.0)
+                .map_err(|e| format!("Failed to start fan PWM: {}", e))?,
+        }
+        self.on = on;
+        Ok(true)
+    }
+}
+
+/// Switches the fan with the temperature readings while it is in auto mode.
+/// Does nothing without a `fan` section.
+pub async fn start_fan_controller(app_state: &Arc<Mutex<ApplicationState>>) {
+    if app_state.lock().await.fan.is_none() {
+        return;
+    }
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "fan_controller", move || {
+        run_fan_controller(Arc::clone(&app_state_clone))
+    });
+}
+
+async fn run_fan_controller(app_state: Arc<Mutex<ApplicationState>>) {
+    let mut readings_rx = app_state.lock().await.temperature_readings_rx.clone();
+    info!("Starting fan controller");
+
+    while readings_rx.changed().await.is_ok() {
+        let celsius = readings_rx.borrow_and_update().as_ref().map(|r| r.celsius);
+        let mut state_guard = app_state.lock().await;
+        if let Err(e) = update_fan(&mut state_guard, celsius) {
+            warn!("{}", e);
+        }
+    }
+}
+
+/// Sets the fan mode, `Auto` hands control back to the temperature controller.
+pub async fn set_fan_mode(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    mode: FanMode,
+) -> Result<Option<FanStatus>, String> {
+    let mut state_guard = app_state.lock().await;
+    let celsius = state_guard
+        .temperature_readings_rx
+        .borrow()
+        .as_ref()
+        .map(|r| r.celsius);
+    let Some(fan) = state_guard.fan.as_mut() else {
+        return Ok(None);
+    };
+    if fan.pin.is_none() {
+        return Err("Fan is unavailable, its pin could not be claimed".to_string());
+    }
+    fan.mode = mode;
+    info!("Fan mode set to {:?}", mode);
+    update_fan(&mut state_guard, celsius)?;
+    Ok(state_guard.fan.as_ref().map(|fan| fan.status()))
+}
+
+fn update_fan(state: &mut ApplicationState, celsius: Option<f32>) -> Result<(), String> {
+    let Some(fan) = state.fan.as_mut() else {
+        return Ok(());
+    };
+    if fan.update(celsius)? {
+        let message = format!(
+            "Fan switched {} ({:?} mode)",
+            if fan.on { "on" } else { "off" },
+            fan.mode
+        );
+        info!("{}", message);
+        state.event_bus.publish(EventKind::FanSwitched, message);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hysteresis() {
+        let controller = Hysteresis {
+            target_celsius: 35.0,
+            hysteresis_celsius: 2.0,
+        };
+
+        assert!(!controller.fan_on(false, 35.0));
+        assert!(controller.fan_on(false, 35.5));
+        // keeps running until 2 °C below the target
+        assert!(controller.fan_on(true, 34.0));
+        assert!(controller.fan_on(true, 33.5));
+        assert!(!controller.fan_on(true, 33.0));
+    }
+}
@@ -0,0 +1,180 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::application_state::ApplicationState;
+use crate::config::{self, DeviceConfig, FleetConfig};
+use crate::services::status;
+use crate::services::supervisor;
+
+/// Header carrying `sha256=<hex HMAC of the body>` when `fleet.secret` is set.
+pub const SIGNATURE_HEADER: &str = "X-Dispenser-Signature";
+
+/// Body of the heartbeat POSTed to `fleet.url`.
+#[derive(Serialize, Debug)]
+pub struct Heartbeat {
+    pub device: Option<DeviceConfig>,
+    pub hostname: Option<String>,
+    pub version: String,
+    pub dispenser_status: String,
+    pub uptime_seconds: u64,
+    pub last_dispensed: Option<String>,
+    pub last_error_msg: Option<String>,
+    pub remaining_treats_grams: f32,
+    /// RFC 3339, covered by the signature so receivers can reject replayed heartbeats
+    pub sent_at: String,
+}
+
+/// Delay before the next heartbeat: the interval while the fleet endpoint is reachable,
+/// doubling with every failed heartbeat up to `FLEET_BACKOFF_MAX_SECS`.
+struct Backoff {
+    interval: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    fn next_delay(&self) -> Duration {
+        let max = Duration::from_secs(config::FLEET_BACKOFF_MAX_SECS).max(self.interval);
+        self.interval
+            .saturating_mul(2u32.saturating_pow(self.failures.min(16)))
+            .min(max)
+    }
+}
+
+/// Starts reporting status and version to `fleet.url` every `interval_secs` if a `fleet`
+/// section is configured, for a central dashboard of several dispensers.
+pub async fn start_fleet_heartbeat(app_state: &Arc<Mutex<ApplicationState>>) {
+    let fleet_config = match app_state.lock().await.app_config.fleet.clone() {
+        Some(c) if c.enabled.unwrap_or(true) => c,
+        _ => return,
+    };
+
+    let interval = Duration::from_secs(
+        fleet_config
+            .interval_secs
+            .unwrap_or(config::FLEET_INTERVAL_SECS_DEFAULT)
+            .max(10),
+    );
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Fleet heartbeat disabled: {}", e);
+            return;
+        }
+    };
+    info!(
+        "Sending fleet heartbeats to {} every {} seconds",
+        fleet_config.url,
+        interval.as_secs()
+    );
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "fleet_heartbeat", move || {
+        let app_state = Arc::clone(&app_state_clone);
+        let fleet_config = fleet_config.clone();
+        let client = client.clone();
+        async move {
+            let mut backoff = Backoff {
+                interval,
+                failures: 0,
+            };
+            loop {
+                match send_heartbeat(&app_state, &client, &fleet_config).await {
+                    Ok(()) => {
+                        if backoff.failures > 0 {
+                            info!("Fleet heartbeat delivered again");
+                        }
+                        backoff.failures = 0;
+                    }
+                    Err(e) => {
+                        backoff.failures += 1;
+                        warn!(
+                            "Fleet heartbeat failed ({} in a row): {}",
+                            backoff.failures, e
+                        );
+                    }
+                }
+                tokio::time::sleep(backoff.next_delay()).await;
+            }
+        }
+    });
+}
+
+async fn send_heartbeat(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    client: &reqwest::Client,
+    fleet_config: &FleetConfig,
+) -> Result<(), String> {
+    let status = status::get_status(app_state).await;
+    let heartbeat = Heartbeat {
+        device: status.device,
+        hostname: sysinfo::System::host_name(),
+        version: status.version,
+        dispenser_status: status.dispenser_status,
+        uptime_seconds: status.uptime_seconds,
+        last_dispensed: status.last_dispensed,
+        last_error_msg: status.last_error_msg,
+        remaining_treats_grams: status.remaining_treats_grams,
+        sent_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let body = serde_json::to_vec(&heartbeat).map_err(|e| e.to_string())?;
+
+    let mut request = client
+        .post(&fleet_config.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(secret) = &fleet_config.secret {
+        request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+    }
+    request
+        .body(body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    debug!("Fleet heartbeat sent");
+    Ok(())
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `body`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_heartbeat() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = Backoff {
+            interval: Duration::from_secs(300),
+            failures: 0,
+        };
+        assert_eq!(backoff.next_delay(), Duration::from_secs(300));
+        backoff.failures = 2;
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1200));
+        backoff.failures = 40;
+        assert_eq!(
+            backoff.next_delay(),
+            Duration::from_secs(config::FLEET_BACKOFF_MAX_SECS)
+        );
+    }
+}
@@ -0,0 +1,807 @@
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, error};
+
+use crate::config::{self, AppConfig};
+use crate::error::{ApiError, FieldError};
+use crate::services::dispenser::{DispenseAmount, TriggerSource};
+use crate::services::history_import::IMPORT_TRIGGER;
+use crate::services::migrations::Migration;
+use crate::utils::{datetime, filesystem};
+
+/// How long a write waits for another connection to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a dispense attempt ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DispenseResult {
+    Completed,
+    Cancelled,
+    Jammed,
+    Failed,
+    /// Not started, e.g. because the dispenser was busy or a trigger limit was reached
+    Rejected,
+}
+
+impl DispenseResult {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DispenseResult::Completed => "completed",
+            DispenseResult::Cancelled => "cancelled",
+            DispenseResult::Jammed => "jammed",
+            DispenseResult::Failed => "failed",
+            DispenseResult::Rejected => "rejected",
+        }
+    }
+
+    fn parse(result: &str) -> Option<Self> {
+        [
+            DispenseResult::Completed,
+            DispenseResult::Cancelled,
+            DispenseResult::Jammed,
+            DispenseResult::Failed,
+            DispenseResult::Rejected,
+        ]
+        .into_iter()
+        .find(|r| r.as_str() == result)
+    }
+}
+
+/// One dispense attempt as listed by `GET /history`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct HistoryEntry {
+    /// Row ID, unset until the entry has been recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub time: String,
+    /// Trigger source, e.g. `api-user`
+    pub trigger: String,
+    /// Requested motor rotation, unset for a dispense by pieces or grams
+    pub degrees: Option<f32>,
+    pub pieces: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grams: Option<f32>,
+    pub result: DispenseResult,
+    /// Motor steps run, only known for completed dispenses
+    pub steps: Option<u32>,
+    /// Why the dispense didn't complete
+    pub reason: Option<String>,
+    /// Note given with the request or schedule, or added later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Weight drop measured after a completed dispense, as counted in the stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dispensed_grams: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dispensed_pieces: Option<u32>,
+    /// Archived entries are left out of the stats and of `GET /history` by default
+    #[serde(default)]
+    pub archived: bool,
+    /// Part of the stats totals unless archived, set once a completed dispense has been
+    /// weighed. Imported entries never are.
+    #[serde(skip)]
+    pub counted: bool,
+}
+
+impl HistoryEntry {
+    /// An attempt starting now, with the default rotation resolved from the config.
+    pub fn new(
+        trigger: TriggerSource,
+        amount: DispenseAmount,
+        app_config: &AppConfig,
+        result: DispenseResult,
+    ) -> Self {
+        let (degrees, pieces, grams) = match amount {
+            DispenseAmount::Default => (
+                Some(
+                    app_config
+                        .motor
+                        .dispense_degrees
+                        .unwrap_or(config::DISPENSE_DEGREES_DEFAULT),
+                ),
+                None,
+                None,
+            ),
+            DispenseAmount::Degrees(degrees) => (Some(degrees), None, None),
+            DispenseAmount::Pieces(pieces) => (None, Some(pieces), None),
+            DispenseAmount::Grams(grams) | DispenseAmount::Trickle { grams, .. } => {
+                (None, None, Some(grams))
+            }
+        };
+        HistoryEntry {
+            id: None,
+            time: datetime::get_formatted_current_timestamp(),
+            trigger: trigger.to_string(),
+            degrees,
+            pieces,
+            grams,
+            result,
+            steps: None,
+            reason: None,
+            note: None,
+            dispensed_grams: None,
+            dispensed_pieces: None,
+            archived: false,
+            counted: false,
+        }
+    }
+
+    pub fn with_steps(mut self, steps: u32) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: Option<String>) -> Self {
+        self.note = note;
+        self
+    }
+
+    pub fn with_dispensed(mut self, grams: f32, pieces: Option<u32>) -> Self {
+        self.dispensed_grams = Some(grams);
+        self.dispensed_pieces = pieces;
+        self
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let result: String = row.get(5)?;
+        Ok(HistoryEntry {
+            id: Some(row.get(0)?),
+            time: row.get(1)?,
+            trigger: row.get(2)?,
+            degrees: row.get(3)?,
+            pieces: row.get(4)?,
+            result: DispenseResult::parse(&result).unwrap_or(DispenseResult::Failed),
+            steps: row.get(6)?,
+            reason: row.get(7)?,
+            grams: row.get(8)?,
+            note: row.get(9)?,
+            dispensed_grams: row.get(10)?,
+            dispensed_pieces: row.get(11)?,
+            archived: row.get(12)?,
+            counted: row.get(13)?,
+        })
+    }
+}
+
+/// Columns read by `HistoryEntry::from_row`, in order.
+const ENTRY_COLUMNS: &str = "id, time, trigger, degrees, pieces, result, steps, reason, grams, note, \
+    dispensed_grams, dispensed_pieces, archived, counted";
+
+/// Body of `PATCH /history/{id}`.
+#[derive(Deserialize, Debug, utoipa::ToSchema)]
+pub struct HistoryUpdate {
+    /// Replaces the note, an empty one removes it
+    pub note: Option<String>,
+    pub archived: Option<bool>,
+}
+
+impl HistoryUpdate {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        if self.note.is_none() && self.archived.is_none() {
+            errors.push(FieldError::new("note", "set note or archived"));
+        }
+        if let Some(note) = &self.note
+            && note.chars().count() > config::DISPENSE_NOTE_MAX_CHARS
+        {
+            errors.push(FieldError::new(
+                "note",
+                format!("must be at most {} characters", config::DISPENSE_NOTE_MAX_CHARS),
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
+
+/// A page of `GET /history`, newest attempt first.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: u64,
+}
+
+/// Validated `page` and `per_page` query parameters, pages start at 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl Pagination {
+    pub fn from_query(page: Option<u32>, per_page: Option<u32>) -> Result<Self, ApiError> {
+        let page = page.unwrap_or(1);
+        let per_page = per_page.unwrap_or(config::HISTORY_PER_PAGE_DEFAULT);
+
+        let mut errors = Vec::new();
+        if page == 0 {
+            errors.push(FieldError::new("page", "must be at least 1"));
+        }
+        if !(1..=config::HISTORY_PER_PAGE_MAX).contains(&per_page) {
+            errors.push(FieldError::new(
+                "per_page",
+                format!("must be between 1 and {}", config::HISTORY_PER_PAGE_MAX),
+            ));
+        }
+        if errors.is_empty() {
+            Ok(Pagination { page, per_page })
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
+
+fn open(path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS dispense_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            time TEXT NOT NULL,
+            trigger TEXT NOT NULL,
+            degrees REAL,
+            pieces INTEGER,
+            result TEXT NOT NULL,
+            steps INTEGER,
+            reason TEXT,
+            grams REAL,
+            note TEXT,
+            dispensed_grams REAL,
+            dispensed_pieces INTEGER,
+            archived INTEGER NOT NULL DEFAULT 0,
+            counted INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    Ok(conn)
+}
+
+/// Schema changes of `dispense_history.db`, applied at startup by `migrations`. Columns
+/// already there are skipped, databases from before versions were recorded may have some.
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 2,
+            description: "dispensing by grams",
+            apply: |path| add_columns(path, &[("grams", "REAL")]),
+        },
+        Migration {
+            version: 3,
+            description: "dispense notes",
+            apply: |path| add_columns(path, &[("note", "TEXT")]),
+        },
+        Migration {
+            version: 4,
+            description: "measured amounts and archiving",
+            apply: |path| {
+                add_columns(
+                    path,
+                    &[
+                        ("dispensed_grams", "REAL"),
+                        ("dispensed_pieces", "INTEGER"),
+                        ("archived", "INTEGER NOT NULL DEFAULT 0"),
+                    ],
+                )
+            },
+        },
+        Migration {
+            version: 5,
+            description: "dispenses counted in the stats",
+            apply: add_counted_column,
+        },
+    ]
+}
+
+/// Marks the completed dispenses recorded so far as counted in the stats, all but the
+/// imported ones.
+fn add_counted_column(path: &str) -> Result<(), String> {
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    if conn
+        .prepare("SELECT counted FROM dispense_history LIMIT 0")
+        .is_ok()
+    {
+        return Ok(());
+    }
+    conn.execute(
+        "ALTER TABLE dispense_history ADD COLUMN counted INTEGER NOT NULL DEFAULT 0",
+        [],
+    )
+    .and_then(|_| {
+        conn.execute(
+            "UPDATE dispense_history SET counted = 1 WHERE result = ?1 AND trigger != ?2",
+            params![DispenseResult::Completed.as_str(), IMPORT_TRIGGER],
+        )
+    })
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+fn add_columns(path: &str, columns: &[(&str, &str)]) -> Result<(), String> {
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    for (column, column_type) in columns {
+        if conn
+            .prepare(&format!("SELECT {} FROM dispense_history LIMIT 0", column))
+            .is_ok()
+        {
+            continue;
+        }
+        conn.execute(
+            &format!(
+                "ALTER TABLE dispense_history ADD COLUMN {} {}",
+                column, column_type
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Returns the row ID of the new entry.
+fn insert(conn: &Connection, entry: &HistoryEntry) -> rusqlite::Result<i64> {
+    conn.execute(
+        "INSERT INTO dispense_history (time, trigger, degrees, pieces, result, steps, reason, grams,
+         note, dispensed_grams, dispensed_pieces, archived, counted)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            entry.time,
+            entry.trigger,
+            entry.degrees,
+            entry.pieces,
+            entry.result.as_str(),
+            entry.steps,
+            entry.reason,
+            entry.grams,
+            entry.note,
+            entry.dispensed_grams,
+            entry.dispensed_pieces,
+            entry.archived,
+            entry.counted,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn query_page(
+    conn: &Connection,
+    pagination: Pagination,
+    include_archived: bool,
+) -> rusqlite::Result<HistoryPage> {
+    let total: u64 = conn.query_row(
+        "SELECT COUNT(*) FROM dispense_history WHERE archived = 0 OR ?1",
+        params![include_archived],
+        |row| row.get(0),
+    )?;
+    let mut statement = conn.prepare(&format!(
+        "SELECT {} FROM dispense_history WHERE archived = 0 OR ?1
+         ORDER BY time DESC, id DESC LIMIT ?2 OFFSET ?3",
+        ENTRY_COLUMNS
+    ))?;
+    let offset = (pagination.page as u64 - 1) * pagination.per_page as u64;
+    let entries = statement
+        .query_map(
+            params![include_archived, pagination.per_page, offset],
+            HistoryEntry::from_row,
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(HistoryPage {
+        entries,
+        page: pagination.page,
+        per_page: pagination.per_page,
+        total,
+    })
+}
+
+fn query_entry(conn: &Connection, id: i64) -> rusqlite::Result<Option<HistoryEntry>> {
+    conn.query_row(
+        &format!("SELECT {} FROM dispense_history WHERE id = ?1", ENTRY_COLUMNS),
+        params![id],
+        HistoryEntry::from_row,
+    )
+    .optional()
+}
+
+/// Inserts the entries that aren't recorded yet, matched by time, trigger and amount, so
+/// importing the same file twice doesn't duplicate them. Returns the inserted count.
+fn insert_new(conn: &mut Connection, entries: &[HistoryEntry]) -> rusqlite::Result<u32> {
+    let transaction = conn.transaction()?;
+    let mut inserted = 0;
+    for entry in entries {
+        let exists = transaction
+            .query_row(
+                "SELECT 1 FROM dispense_history
+                 WHERE time = ?1 AND trigger = ?2 AND pieces IS ?3 AND grams IS ?4",
+                params![entry.time, entry.trigger, entry.pieces, entry.grams],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !exists {
+            insert(&transaction, entry)?;
+            inserted += 1;
+        }
+    }
+    transaction.commit()?;
+    Ok(inserted)
+}
+
+fn mark_dispensed(
+    conn: &Connection,
+    id: i64,
+    grams: f32,
+    pieces: Option<u32>,
+) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "UPDATE dispense_history SET dispensed_grams = ?1, dispensed_pieces = ?2, counted = 1
+         WHERE id = ?3 RETURNING archived",
+        params![grams, pieces, id],
+        |row| row.get(0),
+    )
+}
+
+/// Applies `update` to an entry, returns it as it was before and after. Locks the
+/// database in between, so the entry isn't counted in the stats meanwhile.
+fn update_entry(
+    conn: &mut Connection,
+    id: i64,
+    update: &HistoryUpdate,
+) -> rusqlite::Result<Option<(HistoryEntry, HistoryEntry)>> {
+    let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let Some(before) = query_entry(&transaction, id)? else {
+        return Ok(None);
+    };
+    let note = match &update.note {
+        Some(note) => Some(note.trim())
+            .filter(|note| !note.is_empty())
+            .map(str::to_string),
+        None => before.note.clone(),
+    };
+    let archived = update.archived.unwrap_or(before.archived);
+    transaction.execute(
+        "UPDATE dispense_history SET note = ?1, archived = ?2 WHERE id = ?3",
+        params![note, archived, id],
+    )?;
+    transaction.commit()?;
+    let after = HistoryEntry {
+        note,
+        archived,
+        ..before.clone()
+    };
+    Ok(Some((before, after)))
+}
+
+/// Appends a dispense attempt to `dispense_history.db` in the data directory. Written in
+/// the background, a failed write is logged and doesn't affect the dispense.
+pub fn record(entry: HistoryEntry) {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_dispense_history_db_path();
+        match open(&path).and_then(|conn| insert(&conn, &entry)) {
+            Ok(_) => debug!("Recorded {:?} dispense in the history", entry.result),
+            Err(e) => error!("Failed to record dispense in {}: {}", path, e),
+        }
+    });
+}
+
+/// Records a completed dispense as soon as it ends and returns its row ID, so the amount
+/// measured once the hopper has settled can be added with `set_dispensed`.
+pub async fn record_completed(entry: HistoryEntry) -> Result<i64, String> {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_dispense_history_db_path();
+        open(&path)
+            .and_then(|conn| insert(&conn, &entry))
+            .map_err(|e| format!("Failed to record dispense in {}: {}", path, e))
+    })
+    .await
+    .map_err(|e| format!("Dispense history task failed: {}", e))?
+}
+
+/// Adds the amount measured after a completed dispense to its entry and marks it counted
+/// in the stats. Returns whether the entry was archived meanwhile, it is then left out of
+/// the stats until restored.
+pub async fn set_dispensed(id: i64, grams: f32, pieces: Option<u32>) -> Result<bool, String> {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_dispense_history_db_path();
+        open(&path)
+            .and_then(|conn| mark_dispensed(&conn, id, grams, pieces))
+            .map_err(|e| format!("Failed to update dispense {} in {}: {}", id, path, e))
+    })
+    .await
+    .map_err(|e| format!("Dispense history task failed: {}", e))?
+}
+
+/// Lists recorded dispense attempts, newest first.
+pub async fn list(pagination: Pagination, include_archived: bool) -> Result<HistoryPage, ApiError> {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_dispense_history_db_path();
+        open(&path)
+            .and_then(|conn| query_page(&conn, pagination, include_archived))
+            .map_err(|e| ApiError::Internal(format!("Failed to read dispense history: {}", e)))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Dispense history task failed: {}", e)))?
+}
+
+/// Records entries imported from another feeder, see `history_import`. Returns how many
+/// were new.
+pub async fn import(entries: Vec<HistoryEntry>) -> Result<u32, ApiError> {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_dispense_history_db_path();
+        open(&path)
+            .and_then(|mut conn| insert_new(&mut conn, &entries))
+            .map_err(|e| ApiError::Internal(format!("Failed to import dispense history: {}", e)))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Dispense history task failed: {}", e)))?
+}
+
+/// Changes the note or archived flag of an entry, returns it as it was before and after.
+pub async fn update(
+    id: i64,
+    update: HistoryUpdate,
+) -> Result<(HistoryEntry, HistoryEntry), ApiError> {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_dispense_history_db_path();
+        open(&path)
+            .and_then(|mut conn| update_entry(&mut conn, id, &update))
+            .map_err(|e| ApiError::Internal(format!("Failed to update dispense history: {}", e)))?
+            .ok_or_else(|| ApiError::NotFound(format!("No history entry {}", id)))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Dispense history task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(result: DispenseResult) -> HistoryEntry {
+        HistoryEntry {
+            id: None,
+            time: "2025-01-01 12:00:00".to_string(),
+            trigger: TriggerSource::ApiUser.to_string(),
+            degrees: Some(90.0),
+            pieces: None,
+            grams: None,
+            result,
+            steps: None,
+            reason: None,
+            note: None,
+            dispensed_grams: None,
+            dispensed_pieces: None,
+            archived: false,
+            counted: false,
+        }
+    }
+
+    #[test]
+    fn test_history_pages() {
+        let conn = open(":memory:").unwrap();
+        insert(&conn, &entry(DispenseResult::Completed).with_steps(512)).unwrap();
+        insert(
+            &conn,
+            &entry(DispenseResult::Rejected).with_reason("Waiting for cooldown"),
+        )
+        .unwrap();
+        insert(
+            &conn,
+            &entry(DispenseResult::Cancelled).with_reason("Cancelled through the API"),
+        )
+        .unwrap();
+
+        let first = query_page(
+            &conn,
+            Pagination {
+                page: 1,
+                per_page: 2,
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(first.total, 3);
+        assert_eq!(first.entries.len(), 2);
+        assert_eq!(first.entries[0].result, DispenseResult::Cancelled);
+        assert_eq!(
+            first.entries[1].reason.as_deref(),
+            Some("Waiting for cooldown")
+        );
+
+        let second = query_page(
+            &conn,
+            Pagination {
+                page: 2,
+                per_page: 2,
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            second.entries,
+            vec![HistoryEntry {
+                id: Some(1),
+                ..entry(DispenseResult::Completed).with_steps(512)
+            }]
+        );
+
+        let past_end = query_page(
+            &conn,
+            Pagination {
+                page: 3,
+                per_page: 2,
+            },
+            false,
+        )
+        .unwrap();
+        assert!(past_end.entries.is_empty());
+        assert_eq!(past_end.total, 3);
+    }
+
+    #[test]
+    fn test_old_database_migrated() {
+        let path = std::env::temp_dir().join(format!("history-{}.db", rand::random::<u32>()));
+        let path = path.to_str().unwrap();
+        Connection::open(path)
+            .unwrap()
+            .execute(
+                "CREATE TABLE dispense_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    time TEXT NOT NULL,
+                    trigger TEXT NOT NULL,
+                    degrees REAL,
+                    pieces INTEGER,
+                    result TEXT NOT NULL,
+                    steps INTEGER,
+                    reason TEXT
+                )",
+                [],
+            )
+            .unwrap();
+        Connection::open(path)
+            .unwrap()
+            .execute(
+                "INSERT INTO dispense_history (time, trigger, result) VALUES
+                 ('2024-01-01 12:00:00', 'api-user', 'completed'),
+                 ('2024-01-01 13:00:00', 'import', 'completed'),
+                 ('2024-01-01 14:00:00', 'api-user', 'jammed')",
+                [],
+            )
+            .unwrap();
+
+        for migration in migrations() {
+            (migration.apply)(path).unwrap();
+        }
+        let conn = open(path).unwrap();
+        // only dispenses the stats counted before
+        let counted: Vec<bool> = (1..=3)
+            .map(|id| query_entry(&conn, id).unwrap().unwrap().counted)
+            .collect();
+        assert_eq!(counted, vec![true, false, false]);
+        conn.execute("DELETE FROM dispense_history", []).unwrap();
+
+        let by_grams = HistoryEntry {
+            degrees: None,
+            grams: Some(25.0),
+            note: Some("vet said extra portion".to_string()),
+            dispensed_grams: Some(24.2),
+            ..entry(DispenseResult::Completed)
+        };
+        insert(&conn, &by_grams).unwrap();
+        let page = query_page(&conn, Pagination { page: 1, per_page: 1 }, false).unwrap();
+        assert_eq!(
+            page.entries,
+            vec![HistoryEntry {
+                id: Some(4),
+                ..by_grams
+            }]
+        );
+        // columns already there are skipped
+        assert!((migrations()[0].apply)(path).is_ok());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_archive_entries() {
+        let mut conn = open(":memory:").unwrap();
+        let completed = entry(DispenseResult::Completed).with_dispensed(7.4, Some(3));
+        insert(&conn, &completed).unwrap();
+        insert(&conn, &entry(DispenseResult::Completed)).unwrap();
+        let pagination = Pagination {
+            page: 1,
+            per_page: 10,
+        };
+
+        let archive = HistoryUpdate {
+            note: Some(" test dispense into a cup ".to_string()),
+            archived: Some(true),
+        };
+        let (before, after) = update_entry(&mut conn, 1, &archive).unwrap().unwrap();
+        assert!(!before.archived);
+        assert_eq!(
+            after,
+            HistoryEntry {
+                id: Some(1),
+                note: Some("test dispense into a cup".to_string()),
+                archived: true,
+                ..completed
+            }
+        );
+
+        let page = query_page(&conn, pagination, false).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].id, Some(2));
+        let page = query_page(&conn, pagination, true).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries[1], after);
+
+        // an empty note removes it, the archived flag is kept
+        let clear_note = HistoryUpdate {
+            note: Some(String::new()),
+            archived: None,
+        };
+        let (_, after) = update_entry(&mut conn, 1, &clear_note).unwrap().unwrap();
+        assert!(after.note.is_none() && after.archived);
+        assert!(update_entry(&mut conn, 3, &clear_note).unwrap().is_none());
+
+        // weighed after it was archived, it's counted once restored
+        assert!(mark_dispensed(&conn, 1, 7.4, Some(3)).unwrap());
+        assert!(!mark_dispensed(&conn, 2, 5.0, None).unwrap());
+        let restore = HistoryUpdate {
+            note: None,
+            archived: Some(false),
+        };
+        let (_, after) = update_entry(&mut conn, 1, &restore).unwrap().unwrap();
+        assert!(after.counted && !after.archived);
+    }
+
+    #[test]
+    fn test_imported_entries_sorted_by_time() {
+        let mut conn = open(":memory:").unwrap();
+        insert(&conn, &entry(DispenseResult::Completed)).unwrap();
+        let imported = |time: &str| HistoryEntry {
+            time: time.to_string(),
+            trigger: "import".to_string(),
+            degrees: None,
+            pieces: Some(2),
+            ..entry(DispenseResult::Completed)
+        };
+        let entries = vec![
+            imported("2024-06-01 07:30:00"),
+            imported("2024-06-01 18:00:00"),
+        ];
+        assert_eq!(insert_new(&mut conn, &entries).unwrap(), 2);
+        // importing the same entries again adds nothing
+        assert_eq!(insert_new(&mut conn, &entries).unwrap(), 0);
+
+        let page = query_page(&conn, Pagination { page: 1, per_page: 10 }, false).unwrap();
+        let times: Vec<&str> = page.entries.iter().map(|e| e.time.as_str()).collect();
+        assert_eq!(
+            times,
+            vec!["2025-01-01 12:00:00", "2024-06-01 18:00:00", "2024-06-01 07:30:00"]
+        );
+    }
+
+    #[test]
+    fn test_pagination_from_query() {
+        assert_eq!(
+            Pagination::from_query(None, None).unwrap(),
+            Pagination {
+                page: 1,
+                per_page: config::HISTORY_PER_PAGE_DEFAULT
+            }
+        );
+        assert!(Pagination::from_query(Some(0), None).is_err());
+        assert!(Pagination::from_query(None, Some(0)).is_err());
+        assert!(Pagination::from_query(None, Some(config::HISTORY_PER_PAGE_MAX + 1)).is_err());
+    }
+}
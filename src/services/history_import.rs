@@ -0,0 +1,356 @@
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::ApiError;
+use crate::services::history::{self, DispenseResult, HistoryEntry};
+
+/// Trigger recorded for imported entries.
+pub const IMPORT_TRIGGER: &str = "import";
+
+/// Rejected rows listed in the import summary, the rest are only counted.
+const REJECTED_ROWS_LISTED: usize = 20;
+
+/// Timestamp formats tried in order, after RFC 3339. Dates with slashes are read month
+/// first unless `day_first` is set.
+const DATE_TIME_FORMATS: [&str; 8] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d %H:%M",
+    "%d.%m.%Y %H:%M:%S",
+    "%d.%m.%Y %H:%M",
+];
+const MONTH_FIRST_FORMATS: [&str; 4] = [
+    "%m/%d/%Y %H:%M:%S",
+    "%m/%d/%Y %H:%M",
+    "%m/%d/%Y %I:%M:%S %p",
+    "%m/%d/%Y %I:%M %p",
+];
+const DAY_FIRST_FORMATS: [&str; 4] = [
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+    "%d/%m/%Y %I:%M:%S %p",
+    "%d/%m/%Y %I:%M %p",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    DateTime,
+    Date,
+    Time,
+    Pieces,
+    Grams,
+    Result,
+    Note,
+}
+
+/// Header names used by feeder apps for each column, compared case insensitively with
+/// underscores read as spaces.
+const COLUMN_NAMES: [(&str, Column); 26] = [
+    ("timestamp", Column::DateTime),
+    ("datetime", Column::DateTime),
+    ("date time", Column::DateTime),
+    ("date/time", Column::DateTime),
+    ("fed at", Column::DateTime),
+    ("feeding time", Column::DateTime),
+    ("date", Column::Date),
+    ("time", Column::Time),
+    ("pieces", Column::Pieces),
+    ("portions", Column::Pieces),
+    ("portion", Column::Pieces),
+    ("servings", Column::Pieces),
+    ("amount", Column::Pieces),
+    ("grams", Column::Grams),
+    ("weight", Column::Grams),
+    ("weight (g)", Column::Grams),
+    ("amount (g)", Column::Grams),
+    ("status", Column::Result),
+    ("result", Column::Result),
+    ("state", Column::Result),
+    ("note", Column::Note),
+    ("notes", Column::Note),
+    ("comment", Column::Note),
+    ("meal", Column::Note),
+    ("name", Column::Note),
+    ("label", Column::Note),
+];
+
+/// A row of the file that wasn't imported.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct RejectedRow {
+    /// Line in the file, the header is line 1
+    pub line: u64,
+    pub error: String,
+}
+
+/// Returned by `POST /history/import` and `--import-history`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct ImportSummary {
+    pub imported: u32,
+    /// Rows already in the history, e.g. from an earlier import of the same file
+    pub duplicates: u32,
+    pub rejected: u32,
+    /// The first rejected rows
+    pub rejected_rows: Vec<RejectedRow>,
+}
+
+/// Where each column sits in a row.
+#[derive(Debug, Default)]
+struct Layout {
+    date_time: Option<usize>,
+    date: Option<usize>,
+    time: Option<usize>,
+    pieces: Option<usize>,
+    grams: Option<usize>,
+    result: Option<usize>,
+    note: Option<usize>,
+}
+
+impl Layout {
+    fn from_header(header: &csv::StringRecord) -> Result<Self, String> {
+        let mut layout = Layout::default();
+        for (index, name) in header.iter().enumerate() {
+            let name = name
+                .trim_start_matches('\u{feff}')
+                .trim()
+                .to_lowercase()
+                .replace('_', " ");
+            let Some((_, column)) = COLUMN_NAMES.iter().find(|(known, _)| *known == name) else {
+                continue;
+            };
+            let slot = match column {
+                Column::DateTime => &mut layout.date_time,
+                Column::Date => &mut layout.date,
+                Column::Time => &mut layout.time,
+                Column::Pieces => &mut layout.pieces,
+                Column::Grams => &mut layout.grams,
+                Column::Result => &mut layout.result,
+                Column::Note => &mut layout.note,
+            };
+            slot.get_or_insert(index);
+        }
+        if layout.date_time.is_none() && layout.date.is_none() && layout.time.is_none() {
+            return Err(format!(
+                "no timestamp column found, expected one of: {}",
+                COLUMN_NAMES
+                    .iter()
+                    .filter(|(_, column)| {
+                        matches!(column, Column::DateTime | Column::Date | Column::Time)
+                    })
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        Ok(layout)
+    }
+
+    fn entry(&self, row: &csv::StringRecord, day_first: bool) -> Result<HistoryEntry, String> {
+        let cell = |index: Option<usize>| {
+            index
+                .and_then(|index| row.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let time = match (cell(self.date_time), cell(self.date), cell(self.time)) {
+            (Some(date_time), _, _) => date_time.to_string(),
+            (None, Some(date), Some(time)) => format!("{} {}", date, time),
+            (None, Some(date_time), None) | (None, None, Some(date_time)) => date_time.to_string(),
+            (None, None, None) => return Err("missing timestamp".to_string()),
+        };
+
+        let pieces = match cell(self.pieces).map(parse_number).transpose()? {
+            Some(pieces) if pieces < 0.0 || pieces.fract() != 0.0 => {
+                return Err(format!("portions must be a whole number, got {}", pieces));
+            }
+            pieces => pieces.map(|pieces| pieces as u32),
+        };
+        let grams = match cell(self.grams).map(parse_number).transpose()? {
+            Some(grams) if grams < 0.0 => {
+                return Err(format!("grams must not be negative, got {}", grams));
+            }
+            grams => grams,
+        };
+
+        Ok(HistoryEntry {
+            id: None,
+            time: parse_time(&time, day_first)?,
+            trigger: IMPORT_TRIGGER.to_string(),
+            degrees: None,
+            pieces,
+            grams,
+            result: cell(self.result).map_or(Ok(DispenseResult::Completed), parse_result)?,
+            steps: None,
+            reason: None,
+            note: cell(self.note).map(str::to_string),
+            dispensed_grams: None,
+            dispensed_pieces: None,
+            archived: false,
+            counted: false,
+        })
+    }
+}
+
+/// Reads a number with an optional unit, e.g. `2`, `12.5 g` or `12,5`.
+fn parse_number(value: &str) -> Result<f32, String> {
+    let number: String = value
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',' || *c == '-')
+        .collect();
+    number
+        .replace(',', ".")
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))
+}
+
+/// Formats a timestamp like the recorded entries. Timestamps without a UTC offset are
+/// taken as local time.
+fn parse_time(value: &str, day_first: bool) -> Result<String, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string());
+    }
+    let slash_formats = if day_first {
+        DAY_FIRST_FORMATS
+    } else {
+        MONTH_FIRST_FORMATS
+    };
+    DATE_TIME_FORMATS
+        .iter()
+        .chain(slash_formats.iter())
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .ok_or_else(|| format!("unrecognized timestamp '{}'", value))
+}
+
+fn parse_result(value: &str) -> Result<DispenseResult, String> {
+    match value.to_lowercase().as_str() {
+        "completed" | "complete" | "success" | "successful" | "succeeded" | "ok" | "done"
+        | "fed" | "dispensed" => Ok(DispenseResult::Completed),
+        "jammed" | "jam" | "blocked" | "stuck" => Ok(DispenseResult::Jammed),
+        "cancelled" | "canceled" | "skipped" => Ok(DispenseResult::Cancelled),
+        "failed" | "failure" | "fail" | "error" => Ok(DispenseResult::Failed),
+        "rejected" => Ok(DispenseResult::Rejected),
+        _ => Err(format!("unknown status '{}'", value)),
+    }
+}
+
+/// Separator of the file, whichever of comma, semicolon and tab the header has most of.
+fn detect_delimiter(csv: &str) -> u8 {
+    let header = csv.lines().next().unwrap_or_default();
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|delimiter| {
+            let count = header.bytes().filter(|c| c == delimiter).count();
+            // the first one wins a tie
+            (count, *delimiter == b',')
+        })
+        .unwrap_or(b',')
+}
+
+/// Reads the entries of an exported feeding history, oldest first, and the rows that
+/// couldn't be read.
+fn parse_csv(csv: &str, day_first: bool) -> Result<(Vec<HistoryEntry>, Vec<RejectedRow>), String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(detect_delimiter(csv))
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let header = reader
+        .headers()
+        .map_err(|e| format!("unreadable header: {}", e))?
+        .clone();
+    let layout = Layout::from_header(&header)?;
+
+    let mut entries = Vec::new();
+    let mut rejected = Vec::new();
+    for row in reader.records() {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                rejected.push(RejectedRow {
+                    line,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = row.position().map_or(0, |position| position.line());
+        match layout.entry(&row, day_first) {
+            Ok(entry) => entries.push(entry),
+            Err(error) => rejected.push(RejectedRow { line, error }),
+        }
+    }
+    entries.sort_by(|a, b| a.time.cmp(&b.time));
+    Ok((entries, rejected))
+}
+
+/// Imports feeding history exported as CSV from another feeder into the dispense history.
+/// Rows that can't be read are skipped and listed in the summary, rows already recorded
+/// are skipped too. Imported entries don't count in the stats.
+pub async fn import(csv: String, day_first: bool) -> Result<ImportSummary, ApiError> {
+    let (entries, rejected) = parse_csv(&csv, day_first).map_err(ApiError::BadRequest)?;
+    let read = entries.len() as u32;
+    let imported = history::import(entries).await?;
+    info!(
+        "Imported {} history entries, {} duplicates and {} rejected rows skipped",
+        imported,
+        read - imported,
+        rejected.len()
+    );
+    Ok(ImportSummary {
+        imported,
+        duplicates: read - imported,
+        rejected: rejected.len() as u32,
+        rejected_rows: rejected.into_iter().take(REJECTED_ROWS_LISTED).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exports() {
+        let csv = "\u{feff}Date,Time,Portions,Status,Meal\n\
+            06/02/2024,7:30 PM,2,Success,Dinner\n\
+            06/01/2024,07:30,1,Failed,\n\
+            06/03/2024,08:00,1.5,Success,\n\
+            yesterday,08:00,1,Success,\n";
+        let (entries, rejected) = parse_csv(csv, false).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].time, "2024-06-01 07:30:00");
+        assert_eq!(entries[0].result, DispenseResult::Failed);
+        assert_eq!(entries[1].time, "2024-06-02 19:30:00");
+        assert_eq!(entries[1].pieces, Some(2));
+        assert_eq!(entries[1].note.as_deref(), Some("Dinner"));
+        assert_eq!(entries[1].trigger, IMPORT_TRIGGER);
+        assert_eq!(
+            rejected,
+            vec![
+                RejectedRow {
+                    line: 4,
+                    error: "portions must be a whole number, got 1.5".to_string()
+                },
+                RejectedRow {
+                    line: 5,
+                    error: "unrecognized timestamp 'yesterday 08:00'".to_string()
+                },
+            ]
+        );
+
+        let csv = "feeding_time;Weight (g)\n01/02/2024 08:00;12,5 g\n";
+        let (entries, rejected) = parse_csv(csv, true).unwrap();
+        assert!(rejected.is_empty());
+        assert_eq!(entries[0].time, "2024-02-01 08:00:00");
+        assert_eq!(entries[0].grams, Some(12.5));
+        assert_eq!(entries[0].pieces, None);
+
+        assert!(parse_csv("portions,grams\n1,2\n", false).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{self, HopperLevelConfig};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+
+/// What the hopper level check decided for a new weight reading.
+#[derive(Debug, PartialEq)]
+enum LevelChange {
+    BecameLow,
+    BecameEmpty,
+    Refilled,
+}
+
+/// Marks the hopper empty below a weight threshold and detects refills as a weight
+/// increase that holds for a while, so a hand resting on the hopper doesn't count.
+/// Running low is reported once, until the weight rises by a refill's worth above the low
+/// threshold again.
+struct HopperLevel {
+    empty_threshold_grams: f32,
+    low_threshold_grams: Option<f32>,
+    low_reported: bool,
+    refill_min_increase_grams: f32,
+    refill_sustain: Duration,
+    /// Lowest weight seen while empty, refills are measured against it
+    empty_min_grams: f32,
+    increase_since: Option<Instant>,
+}
+
+impl HopperLevel {
+    fn new(level_config: &HopperLevelConfig) -> Self {
+        HopperLevel {
+            empty_threshold_grams: level_config.empty_threshold_grams,
+            low_threshold_grams: level_config.low_threshold_grams,
+            low_reported: false,
+            refill_min_increase_grams: level_config
+                .refill_min_increase_grams
+                .unwrap_or(config::REFILL_MIN_INCREASE_GRAMS_DEFAULT),
+            refill_sustain: Duration::from_secs(
+                level_config
+                    .refill_sustain_secs
+                    .unwrap_or(config::REFILL_SUSTAIN_SECS_DEFAULT),
+            ),
+            empty_min_grams: f32::MAX,
+            increase_since: None,
+        }
+    }
+
+    fn update(
+        &mut self,
+        grams: f32,
+        status: &DispenserStatus,
+        now: Instant,
+    ) -> Option<LevelChange> {
+        match status {
+            DispenserStatus::Operational if grams < self.empty_threshold_grams => {
+                self.empty_min_grams = grams;
+                self.increase_since = None;
+                self.low_reported = true;
+                Some(LevelChange::BecameEmpty)
+            }
+            DispenserStatus::Operational => {
+                let low_threshold_grams = self.low_threshold_grams?;
+                if grams >= low_threshold_grams + self.refill_min_increase_grams {
+                    self.low_reported = false;
+                }
+                if grams >= low_threshold_grams || self.low_reported {
+                    return None;
+                }
+                self.low_reported = true;
+                Some(LevelChange::BecameLow)
+            }
+            DispenserStatus::Empty => {
+                self.empty_min_grams = self.empty_min_grams.min(grams);
+                let refilled = grams >= self.empty_threshold_grams
+                    && grams - self.empty_min_grams >= self.refill_min_increase_grams;
+                if !refilled {
+                    self.increase_since = None;
+                    return None;
+                }
+                let since = *self.increase_since.get_or_insert(now);
+                if now.duration_since(since) < self.refill_sustain {
+                    return None;
+                }
+                // still low after a small refill is reported again
+                self.low_reported = false;
+                Some(LevelChange::Refilled)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether the hopper holds treats, shown as `treats_available` in `/status`. Not while
+/// the status is `Empty`, nor with `hopper_level` while the weight is below
+/// `empty_threshold_grams`, e.g. during a dispense. Without `hopper_level` only the
+/// status counts, the weight alone can't tell an empty hopper.
+pub fn treats_available(
+    status: &DispenserStatus,
+    grams: f32,
+    level_config: Option<&HopperLevelConfig>,
+) -> bool {
+    *status != DispenserStatus::Empty
+        && level_config.is_none_or(|c| grams >= c.empty_threshold_grams)
+}
+
+/// Watches the weight readings if `weight_monitor.hopper_level` is configured: publishes a
+/// `hopper_low` event when the hopper weight falls below `low_threshold_grams`, sets the
+/// status to `Empty` when it falls below `empty_threshold_grams`, and back to
+/// `Operational` with a `refilled` event once a refill is detected.
+pub async fn start_hopper_level_monitor(app_state: &Arc<Mutex<ApplicationState>>) {
+    let level_config = match app_state
+        .lock()
+        .await
+        .app_config
+        .weight_monitor
+        .hopper_level
+        .clone()
+    {
+        Some(c) => c,
+        None => return,
+    };
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "hopper_level", move || {
+        run_hopper_level_monitor(Arc::clone(&app_state_clone), level_config.clone())
+    });
+}
+
+async fn run_hopper_level_monitor(
+    app_state: Arc<Mutex<ApplicationState>>,
+    level_config: HopperLevelConfig,
+) {
+    let mut weight_readings_rx = app_state.lock().await.weight_readings_tx.subscribe();
+    let mut level = HopperLevel::new(&level_config);
+    info!(
+        "Monitoring hopper level, empty below {} g, low below {}",
+        level_config.empty_threshold_grams,
+        level_config
+            .low_threshold_grams
+            .map_or("unset".to_string(), |grams| format!("{} g", grams))
+    );
+
+    while weight_readings_rx.changed().await.is_ok() {
+        let grams = weight_readings_rx.borrow_and_update().grams;
+
+        let mut state_guard = app_state.lock().await;
+        let status = state_guard.status.clone();
+        match level.update(grams, &status, Instant::now()) {
+            Some(LevelChange::BecameLow) => {
+                warn!("Hopper is running low ({:.1} g)", grams);
+                state_guard.event_bus.publish(
+                    EventKind::HopperLow,
+                    format!("Hopper is running low ({:.0} g left)", grams),
+                );
+            }
+            Some(LevelChange::BecameEmpty) => {
+                warn!("Hopper is empty ({:.1} g)", grams);
+                state_guard.set_status(DispenserStatus::Empty);
+            }
+            Some(LevelChange::Refilled) => {
+                info!("Hopper refilled ({:.1} g)", grams);
+                state_guard.set_status(DispenserStatus::Operational);
+                state_guard.event_bus.publish(
+                    EventKind::Refilled,
+                    format!("Hopper refilled ({:.0} g)", grams),
+                );
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_and_refill() {
+        let level_config = HopperLevelConfig {
+            empty_threshold_grams: 50.0,
+            low_threshold_grams: None,
+            refill_min_increase_grams: Some(100.0),
+            refill_sustain_secs: Some(5),
+        };
+        let mut level = HopperLevel::new(&level_config);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // never marked empty while dispensing
+        assert_eq!(
+            level.update(40.0, &DispenserStatus::Dispensing, at(0)),
+            None
+        );
+        assert_eq!(
+            level.update(40.0, &DispenserStatus::Operational, at(0)),
+            Some(LevelChange::BecameEmpty)
+        );
+
+        let empty = DispenserStatus::Empty;
+        assert_eq!(level.update(30.0, &empty, at(1)), None);
+        // a short push on the hopper isn't a refill
+        assert_eq!(level.update(200.0, &empty, at(2)), None);
+        assert_eq!(level.update(35.0, &empty, at(3)), None);
+        // the refill has to hold for 5 s
+        assert_eq!(level.update(400.0, &empty, at(4)), None);
+        assert_eq!(level.update(400.0, &empty, at(8)), None);
+        assert_eq!(
+            level.update(400.0, &empty, at(9)),
+            Some(LevelChange::Refilled)
+        );
+    }
+
+    #[test]
+    fn test_running_low() {
+        let level_config = HopperLevelConfig {
+            empty_threshold_grams: 50.0,
+            low_threshold_grams: Some(150.0),
+            refill_min_increase_grams: Some(100.0),
+            refill_sustain_secs: Some(0),
+        };
+        let mut level = HopperLevel::new(&level_config);
+        let now = Instant::now();
+        let operational = DispenserStatus::Operational;
+
+        assert_eq!(level.update(300.0, &operational, now), None);
+        assert_eq!(
+            level.update(140.0, &DispenserStatus::Dispensing, now),
+            None
+        );
+        assert_eq!(
+            level.update(140.0, &operational, now),
+            Some(LevelChange::BecameLow)
+        );
+        // reported once, also when the reading wobbles around the threshold
+        assert_eq!(level.update(130.0, &operational, now), None);
+        assert_eq!(level.update(155.0, &operational, now), None);
+        assert_eq!(level.update(145.0, &operational, now), None);
+        // until the hopper was topped up
+        assert_eq!(level.update(260.0, &operational, now), None);
+        assert_eq!(
+            level.update(140.0, &operational, now),
+            Some(LevelChange::BecameLow)
+        );
+        assert_eq!(
+            level.update(40.0, &operational, now),
+            Some(LevelChange::BecameEmpty)
+        );
+        assert_eq!(
+            level.update(145.0, &DispenserStatus::Empty, now),
+            Some(LevelChange::Refilled)
+        );
+        assert_eq!(
+            level.update(145.0, &operational, now),
+            Some(LevelChange::BecameLow)
+        );
+    }
+
+    #[test]
+    fn test_treats_available() {
+        let level_config = HopperLevelConfig {
+            empty_threshold_grams: 50.0,
+            low_threshold_grams: None,
+            refill_min_increase_grams: None,
+            refill_sustain_secs: None,
+        };
+        let operational = DispenserStatus::Operational;
+        assert!(treats_available(&operational, 120.0, Some(&level_config)));
+        assert!(!treats_available(&operational, 40.0, Some(&level_config)));
+        assert!(!treats_available(&DispenserStatus::Empty, 120.0, Some(&level_config)));
+        assert!(treats_available(&operational, 0.0, None));
+    }
+}
@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::application_state::ApplicationState;
+use crate::config::{self, HumidityWarningConfig};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::utils::datetime;
+
+const HOUR: Duration = Duration::from_secs(3600);
+/// Hourly averages kept for `/stats`
+const HISTORY_HOURS: usize = 24;
+/// The trend compares the latest hourly average with the one this many hours earlier
+const TREND_HOURS: usize = 3;
+/// Change in percentage points over `TREND_HOURS` that counts as rising or falling
+const TREND_DELTA_PERCENT: f32 = 3.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HumidityTrend {
+    Rising,
+    Steady,
+    Falling,
+}
+
+/// Hopper humidity summary included in `/stats`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct HumidityStats {
+    pub current_percent: f32,
+    pub average_24h_percent: f32,
+    /// Unset until there are readings from at least two hours
+    pub trend: Option<HumidityTrend>,
+    /// Hourly averages, oldest first
+    pub hourly_percent: Vec<f32>,
+    /// When the humidity warning started, unset while humidity is normal
+    pub high_since: Option<String>,
+}
+
+struct HourAverage {
+    started: Instant,
+    sum: f32,
+    count: u32,
+}
+
+impl HourAverage {
+    fn average(&self) -> f32 {
+        self.sum / self.count as f32
+    }
+}
+
+/// Hourly humidity averages over the last day.
+#[derive(Default)]
+pub struct HumidityHistory {
+    hours: VecDeque<HourAverage>,
+    current_percent: Option<f32>,
+    pub high_since: Option<String>,
+}
+
+impl HumidityHistory {
+    pub fn record(&mut self, percent: f32, now: Instant) {
+        self.current_percent = Some(percent);
+        match self.hours.back_mut() {
+            Some(hour) if now.duration_since(hour.started) < HOUR => {
+                hour.sum += percent;
+                hour.count += 1;
+            }
+            _ => {
+                if self.hours.len() == HISTORY_HOURS {
+                    self.hours.pop_front();
+                }
+                self.hours.push_back(HourAverage {
+                    started: now,
+                    sum: percent,
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    fn trend(&self) -> Option<HumidityTrend> {
+        if self.hours.len() < 2 {
+            return None;
+        }
+        let latest = self.hours.back()?.average();
+        let earlier_index = self.hours.len().saturating_sub(TREND_HOURS + 1);
+        let delta = latest - self.hours[earlier_index].average();
+        Some(if delta > TREND_DELTA_PERCENT {
+            HumidityTrend::Rising
+        } else if delta < -TREND_DELTA_PERCENT {
+            HumidityTrend::Falling
+        } else {
+            HumidityTrend::Steady
+        })
+    }
+
+    /// Returns `None` until a humidity reading was recorded.
+    pub fn stats(&self) -> Option<HumidityStats> {
+        let current_percent = self.current_percent?;
+        let (sum, count) = self.hours.iter().fold((0.0, 0), |(sum, count), hour| {
+            (sum + hour.sum, count + hour.count)
+        });
+        Some(HumidityStats {
+            current_percent,
+            average_24h_percent: sum / count as f32,
+            trend: self.trend(),
+            hourly_percent: self.hours.iter().map(HourAverage::average).collect(),
+            high_since: self.high_since.clone(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AlarmChange {
+    High,
+    Normal,
+}
+
+/// Raises the alarm once humidity stayed above the threshold for the sustain time,
+/// a short spike from opening the lid doesn't count.
+struct HumidityAlarm {
+    max_percent: f32,
+    sustain: Duration,
+    above_since: Option<Instant>,
+    active: bool,
+}
+
+impl HumidityAlarm {
+    fn new(warning_config: &HumidityWarningConfig) -> Self {
+        HumidityAlarm {
+            max_percent: warning_config.max_percent,
+            sustain: Duration::from_secs(
+                warning_config
+                    .sustain_minutes
+                    .unwrap_or(config::HUMIDITY_SUSTAIN_MINUTES_DEFAULT)
+                    * 60,
+            ),
+            above_since: None,
+            active: false,
+        }
+    }
+
+    fn update(&mut self, percent: f32, now: Instant) -> Option<AlarmChange> {
+        if percent <= self.max_percent {
+            self.above_since = None;
+            if self.active {
+                self.active = false;
+                return Some(AlarmChange::Normal);
+            }
+            return None;
+        }
+        let since = *self.above_since.get_or_insert(now);
+        if !self.active && now.duration_since(since) >= self.sustain {
+            self.active = true;
+            return Some(AlarmChange::High);
+        }
+        None
+    }
+}
+
+/// Records humidity readings for `/stats` and, with `temperature.humidity_warning`
+/// configured, publishes `humidity_high` and `humidity_normal` events. Does nothing
+/// without a temperature sensor.
+pub async fn start_humidity_tracker(app_state: &Arc<Mutex<ApplicationState>>) {
+    if app_state.lock().await.temperature_sensor_mutex.is_none() {
+        return;
+    }
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "humidity_tracker", move || {
+        run_humidity_tracker(Arc::clone(&app_state_clone))
+    });
+}
+
+async fn run_humidity_tracker(app_state: Arc<Mutex<ApplicationState>>) {
+    let (mut readings_rx, warning_config) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.temperature_readings_rx.clone(),
+            state_guard
+                .app_config
+                .temperature
+                .as_ref()
+                .and_then(|c| c.humidity_warning.clone()),
+        )
+    };
+    let mut alarm = warning_config.as_ref().map(HumidityAlarm::new);
+
+    while readings_rx.changed().await.is_ok() {
+        let Some(percent) = readings_rx
+            .borrow_and_update()
+            .as_ref()
+            .and_then(|r| r.humidity_percent)
+        else {
+            continue;
+        };
+        let now = Instant::now();
+        let change = alarm.as_mut().and_then(|alarm| alarm.update(percent, now));
+
+        let mut state_guard = app_state.lock().await;
+        state_guard.humidity.record(percent, now);
+        match change {
+            Some(AlarmChange::High) => {
+                let message = format!(
+                    "Hopper humidity has been above {:.0}% for a while ({:.0}%), treats may go soggy",
+                    alarm.as_ref().map(|a| a.max_percent).unwrap_or_default(),
+                    percent
+                );
+                warn!("{}", message);
+                state_guard.humidity.high_since = Some(datetime::get_formatted_current_timestamp());
+                state_guard
+                    .event_bus
+                    .publish(EventKind::HumidityHigh, message);
+            }
+            Some(AlarmChange::Normal) => {
+                info!("Hopper humidity back to normal ({:.0}%)", percent);
+                state_guard.humidity.high_since = None;
+                state_guard.event_bus.publish(
+                    EventKind::HumidityNormal,
+                    format!("Hopper humidity back to normal ({:.0}%)", percent),
+                );
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_humidity_alarm() {
+        let mut alarm = HumidityAlarm::new(&HumidityWarningConfig {
+            max_percent: 70.0,
+            sustain_minutes: Some(30),
+        });
+        let start = Instant::now();
+        let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+
+        assert_eq!(alarm.update(75.0, at(0)), None);
+        // a dip resets the sustain time
+        assert_eq!(alarm.update(65.0, at(20)), None);
+        assert_eq!(alarm.update(75.0, at(25)), None);
+        assert_eq!(alarm.update(75.0, at(50)), None);
+        assert_eq!(alarm.update(80.0, at(55)), Some(AlarmChange::High));
+        assert_eq!(alarm.update(80.0, at(60)), None);
+        assert_eq!(alarm.update(60.0, at(65)), Some(AlarmChange::Normal));
+    }
+
+    #[test]
+    fn test_humidity_trend() {
+        let mut history = HumidityHistory::default();
+        let start = Instant::now();
+        assert_eq!(history.stats(), None);
+
+        history.record(50.0, start);
+        history.record(52.0, start + Duration::from_secs(60));
+        assert_eq!(history.stats().unwrap().trend, None);
+
+        for (hour, percent) in [(1, 53.0), (2, 56.0), (3, 58.0)] {
+            history.record(percent, start + HOUR * hour);
+        }
+        let stats = history.stats().unwrap();
+        assert_eq!(stats.hourly_percent, vec![51.0, 53.0, 56.0, 58.0]);
+        assert_eq!(stats.trend, Some(HumidityTrend::Rising));
+        assert_eq!(stats.current_percent, 58.0);
+    }
+}
@@ -0,0 +1,82 @@
+use rppal::i2c::I2c;
+use serde::Serialize;
+use tracing::{debug, info};
+
+/// First and last 7-bit addresses probed, the rest are reserved by the I2C spec.
+const FIRST_ADDRESS: u16 = 0x03;
+const LAST_ADDRESS: u16 = 0x77;
+
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct I2cDevice {
+    /// Address formatted as hex, e.g. `0x40`
+    pub address: String,
+    /// Chips commonly found at this address
+    pub likely_devices: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug, Clone, utoipa::ToSchema)]
+pub struct I2cScanResponse {
+    pub bus: u8,
+    pub devices: Vec<I2cDevice>,
+}
+
+/// Probes every address on `/dev/i2c-<bus>` and returns the ones that acknowledge.
+/// Blocks for the duration of the scan, run it on a blocking thread.
+pub fn scan_bus(bus: u8) -> Result<I2cScanResponse, String> {
+    info!("Scanning I2C bus {}", bus);
+    let mut i2c =
+        I2c::with_bus(bus).map_err(|e| format!("Failed to open I2C bus {}: {}", bus, e))?;
+
+    let mut devices = Vec::new();
+    for address in FIRST_ADDRESS..=LAST_ADDRESS {
+        if i2c.set_slave_address(address).is_err() {
+            continue;
+        }
+        if probe(&i2c, address) {
+            debug!("I2C device responded at {:#04x}", address);
+            devices.push(I2cDevice {
+                address: format!("{:#04x}", address),
+                likely_devices: likely_devices(address),
+            });
+        }
+    }
+
+    info!("I2C scan of bus {} found {} device(s)", bus, devices.len());
+    Ok(I2cScanResponse { bus, devices })
+}
+
+/// Same probing strategy as `i2cdetect`: a read for EEPROM ranges, where a quick
+/// write could corrupt data on some chips, and a quick write everywhere else.
+fn probe(i2c: &I2c, address: u16) -> bool {
+    if (0x30..=0x37).contains(&address) || (0x50..=0x5f).contains(&address) {
+        i2c.smbus_receive_byte().is_ok()
+    } else {
+        i2c.smbus_quick_command(false).is_ok()
+    }
+}
+
+/// Chips commonly found at an address, most relevant to this project first.
+fn likely_devices(address: u16) -> Vec<&'static str> {
+    match address {
+        0x48..=0x4b => vec!["INA219", "INA226", "ADS1115"],
+        0x40..=0x4f => vec!["INA219", "INA226", "PCA9685"],
+        0x20..=0x27 => vec!["MCP23017", "PCF8574"],
+        0x3c | 0x3d => vec!["SSD1306"],
+        0x50..=0x57 => vec!["AT24 EEPROM"],
+        0x68 => vec!["DS3231", "MPU6050"],
+        0x76 | 0x77 => vec!["BME280", "BMP280"],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_likely_devices() {
+        assert_eq!(likely_devices(0x40)[0], "INA219");
+        assert!(likely_devices(0x3c).contains(&"SSD1306"));
+        assert!(likely_devices(0x10).is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::config::{self, JamDetectionConfig};
+use crate::sensors::WeightReading;
+use crate::services::events::DispenserEvent;
+
+/// Checks the hopper weight once a dispense reaches a set fraction of its run.
+pub struct JamDetector {
+    check_at_percent: u8,
+    min_drop_grams: f32,
+    grams_before: f32,
+}
+
+impl JamDetector {
+    pub fn new(jam_config: &JamDetectionConfig, grams_before: f32) -> Self {
+        let check_at_fraction = jam_config
+            .check_at_fraction
+            .unwrap_or(config::JAM_CHECK_AT_FRACTION_DEFAULT)
+            .clamp(0.0, 1.0);
+        JamDetector {
+            check_at_percent: (check_at_fraction * 100.0).round() as u8,
+            min_drop_grams: jam_config
+                .min_drop_grams
+                .unwrap_or(config::JAM_MIN_DROP_GRAMS_DEFAULT),
+            grams_before,
+        }
+    }
+
+    /// Returns the jam message if, at `percent` of the run, the hopper weight hasn't
+    /// dropped by `min_drop_grams`. `None` before the check is due or if treats dropped.
+    fn check(&self, percent: u8, grams_now: f32) -> Option<String> {
+        if percent < self.check_at_percent {
+            return None;
+        }
+        let dropped = self.grams_before - grams_now;
+        debug!("Jam check at {}%: {:.1} g dropped", percent, dropped);
+        (dropped < self.min_drop_grams).then(|| {
+            format!(
+                "Jam detected: only {:.1} g dropped after {}% of the dispense",
+                dropped.max(0.0),
+                percent
+            )
+        })
+    }
+
+    /// Follows the dispense progress events and cancels the motor run if the check fails.
+    /// Returns the jam message, or `None` once the check passed or the run ended first.
+    pub async fn watch(
+        self,
+        mut events_rx: broadcast::Receiver<DispenserEvent>,
+        weight_readings_rx: watch::Receiver<WeightReading>,
+        cancel_token: CancellationToken,
+    ) -> Option<String> {
+        loop {
+            let event = match events_rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            let Some(progress) = event.progress else {
+                continue;
+            };
+            if progress.percent < self.check_at_percent {
+                continue;
+            }
+            let jam = self.check(progress.percent, weight_readings_rx.borrow().grams);
+            if let Some(message) = &jam {
+                warn!("{}, stopping the motor", message);
+                cancel_token.cancel();
+            }
+            return jam;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jam_check() {
+        let jam_config = JamDetectionConfig {
+            enabled: None,
+            check_at_fraction: Some(0.5),
+            min_drop_grams: Some(2.0),
+        };
+        let detector = JamDetector::new(&jam_config, 500.0);
+        assert!(detector.check(40, 500.0).is_none());
+        assert!(detector.check(50, 495.0).is_none());
+        let jam = detector.check(50, 499.5).expect("should detect a jam");
+        assert!(jam.contains("0.5 g"), "{}", jam);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+/// Finnish texts of the error codes (see `ApiError::code`) and event kinds.
+const FINNISH: [(&str, &str); 49] = [
+    ("unauthorized", "Pyyntöä ei ole valtuutettu"),
+    ("forbidden", "Kielletty"),
+    ("busy", "Annostelija on varattu"),
+    ("hardware", "Laitteistovirhe"),
+    ("bad_request", "Virheellinen pyyntö"),
+    ("not_found", "Ei löytynyt"),
+    ("rate_limited", "Liian monta pyyntöä"),
+    ("validation_failed", "Virheellisiä kenttiä"),
+    ("internal", "Sisäinen virhe"),
+    ("task_panicked", "Taustatehtävä kaatui"),
+    ("task_restarted", "Taustatehtävä käynnistettiin uudelleen"),
+    ("backup_completed", "Varmuuskopio valmis"),
+    ("backup_failed", "Varmuuskopiointi epäonnistui"),
+    ("status_changed", "Annostelijan tila muuttui"),
+    ("dispensed", "Herkut annosteltu"),
+    ("dispense_failed", "Annostelu epäonnistui"),
+    ("dispense_cancelled", "Annostelu peruttiin"),
+    ("channel_stale", "Anturin lukemat eivät päivity"),
+    ("channel_recovered", "Anturin lukemat päivittyvät taas"),
+    ("step_loss", "Moottori hukkasi askelia"),
+    ("dispense_progress", "Annostelu käynnissä"),
+    ("hopper_stirred", "Säiliö sekoitettiin"),
+    ("refilled", "Säiliö täytettiin"),
+    ("input_changed", "Tulon tila muuttui"),
+    ("output_changed", "Lähdön tila muuttui"),
+    ("fan_switched", "Tuuletin kytkettiin"),
+    ("humidity_high", "Säiliön kosteus on korkea"),
+    ("humidity_normal", "Säiliön kosteus on normaali"),
+    ("task_deferred", "Tehtävää lykättiin"),
+    ("recovered_interrupted", "Keskeytynyt annostelu kirjattiin"),
+    (
+        "file_quarantined",
+        "Lukukelvoton tiedosto siirrettiin sivuun",
+    ),
+    ("migration_failed", "Tiedoston päivitys epäonnistui"),
+    (
+        "overcurrent_protection_disabled",
+        "Ylivirtasuojaus poistettiin käytöstä",
+    ),
+    (
+        "overcurrent_protection_enabled",
+        "Ylivirtasuojaus on käytössä",
+    ),
+    ("current_limit_changed", "Virtaraja muuttui"),
+    ("overcurrent_detected", "Ylivirta havaittiin"),
+    (
+        "current_jam_detected",
+        "Moottorin virrasta havaittiin tukos",
+    ),
+    ("config_reloaded", "Asetukset ladattiin uudelleen"),
+    (
+        "weight_sensor_settings_applied",
+        "Painoanturin asetukset otettiin käyttöön",
+    ),
+    (
+        "weight_sensor_settings_rejected",
+        "Painoanturin asetuksia ei otettu käyttöön",
+    ),
+    ("weight_sensor_tared", "Painoanturi nollattiin"),
+    ("weight_sensor_calibrated", "Painoanturi kalibroitiin"),
+    ("load_cell_failing", "Punnituskenno ei toimi"),
+    ("load_cell_recovered", "Punnituskenno toimii taas"),
+    ("bowl_emptied", "Lemmikki söi kupista"),
+    ("trickle_progress", "Hidas annostelu etenee"),
+    ("training_rewarded", "Koulutuspalkkio annettiin"),
+    ("feeding_upcoming", "Ruokinta on tulossa"),
+    ("hopper_low", "Säiliö on vähissä"),
+];
+
+/// Texts of the error codes and event kinds by language. English is what the API answers
+/// anyway, so it has no entries.
+pub struct MessageCatalogue {
+    languages: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl MessageCatalogue {
+    /// The built-in Finnish texts, with `custom` from `api.messages` added or replacing them.
+    pub fn new(custom: Option<&BTreeMap<String, BTreeMap<String, String>>>) -> Self {
+        let mut languages: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        languages.insert(
+            "fi".to_string(),
+            FINNISH
+                .iter()
+                .map(|(code, text)| (code.to_string(), text.to_string()))
+                .collect(),
+        );
+        for (language, texts) in custom.into_iter().flatten() {
+            languages
+                .entry(language.to_lowercase())
+                .or_default()
+                .extend(texts.clone());
+        }
+        languages.remove("en");
+        MessageCatalogue { languages }
+    }
+
+    /// The language of the catalogue that `accept_language` prefers, `None` for English or
+    /// if it asks for none of them. Languages are matched by their primary tag, so `fi-FI`
+    /// gets `fi`.
+    pub fn select_language(&self, accept_language: Option<&str>) -> Option<&str> {
+        let mut ranges: Vec<(&str, f32)> = accept_language?
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // stable, so equally preferred languages keep their order
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (tag, _) in ranges {
+            let tag = tag.to_lowercase();
+            let primary = tag.split('-').next().unwrap_or_default();
+            if primary == "en" {
+                return None;
+            }
+            if let Some((language, _)) = self
+                .languages
+                .get_key_value(&tag)
+                .or_else(|| self.languages.get_key_value(primary))
+            {
+                return Some(language);
+            }
+        }
+        None
+    }
+
+    pub fn text(&self, language: &str, code: &str) -> Option<&str> {
+        self.languages.get(language)?.get(code).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_language() {
+        let catalogue = MessageCatalogue::new(None);
+        assert_eq!(catalogue.select_language(None), None);
+        assert_eq!(catalogue.select_language(Some("fi")), Some("fi"));
+        assert_eq!(
+            catalogue.select_language(Some("fi-FI,fi;q=0.9")),
+            Some("fi")
+        );
+        assert_eq!(
+            catalogue.select_language(Some("sv;q=0.9, fi;q=0.5, en;q=0.8")),
+            None
+        );
+        assert_eq!(
+            catalogue.select_language(Some("sv, en;q=0.5, fi;q=0.8")),
+            Some("fi")
+        );
+        assert_eq!(catalogue.select_language(Some("fi;q=0")), None);
+        assert_eq!(catalogue.select_language(Some("de, *")), None);
+    }
+
+    #[test]
+    fn test_custom_messages() {
+        let custom: BTreeMap<String, BTreeMap<String, String>> = serde_yaml::from_str(
+            r#"
+            fi:
+              dispensed: "Namit annosteltu"
+            SV:
+              dispensed: "Godis utdelat"
+            en:
+              dispensed: "Yum"
+            "#,
+        )
+        .unwrap();
+        let catalogue = MessageCatalogue::new(Some(&custom));
+        assert_eq!(catalogue.text("fi", "dispensed"), Some("Namit annosteltu"));
+        assert_eq!(catalogue.text("fi", "not_found"), Some("Ei löytynyt"));
+        assert_eq!(catalogue.select_language(Some("sv-SE")), Some("sv"));
+        assert_eq!(catalogue.text("sv", "dispensed"), Some("Godis utdelat"));
+        assert_eq!(catalogue.text("sv", "not_found"), None);
+        assert_eq!(catalogue.select_language(Some("en")), None);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{error, info, warn};
+
+use crate::application_state::AppStateMutex;
+use crate::services::events::EventKind;
+use crate::services::history;
+use crate::utils::{filesystem, state_helpers};
+
+/// Recorded format version of each persisted file, by file name.
+type DataVersions = BTreeMap<String, u32>;
+
+/// One format change of a persisted file. `apply` upgrades the file in place from the
+/// previous version to `version`.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&str) -> Result<(), String>,
+}
+
+/// A persisted file and the migrations of its format. Version 1 is the format from before
+/// versions were recorded.
+struct Artifact {
+    path: String,
+    migrations: Vec<Migration>,
+}
+
+impl Artifact {
+    fn latest_version(&self) -> u32 {
+        self.migrations
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+fn artifacts() -> Vec<Artifact> {
+    let unchanged = |path: String| Artifact {
+        path,
+        migrations: Vec::new(),
+    };
+    vec![
+        Artifact {
+            path: filesystem::get_dispense_history_db_path(),
+            migrations: history::migrations(),
+        },
+        unchanged(filesystem::get_calibration_file_path()),
+        unchanged(filesystem::get_dispense_stats_file_path()),
+        unchanged(filesystem::get_notification_devices_file_path()),
+        unchanged(filesystem::get_bowl_stats_file_path()),
+        unchanged(filesystem::get_weight_history_db_path()),
+        unchanged(filesystem::get_status_shares_file_path()),
+        unchanged(filesystem::get_schedules_file_path()),
+        unchanged(filesystem::get_audit_log_path()),
+    ]
+}
+
+/// Upgrades every persisted file in the data directory to the format this build expects.
+/// Must run before the application state or the persisted file check opens them. Each file
+/// is copied to `<file>.v<version>.bak` before its first migration and restored from that
+/// copy if one fails. Returns a message for each file that couldn't be migrated, those
+/// stay at their old version and are retried on the next start.
+pub fn run_migrations() -> Vec<String> {
+    let versions_path = filesystem::get_data_versions_file_path();
+    let mut versions: DataVersions = if Path::new(&versions_path).exists() {
+        filesystem::read_json_from_file(&versions_path).unwrap_or_else(|e| {
+            warn!(
+                "Failed to read {}, assuming old formats: {}",
+                versions_path, e
+            );
+            DataVersions::new()
+        })
+    } else {
+        DataVersions::new()
+    };
+
+    let mut failures = Vec::new();
+    for artifact in artifacts() {
+        let name = file_name(&artifact.path);
+        let recorded = versions.get(&name).copied();
+        match migrate(&artifact, recorded) {
+            Ok(version) => {
+                versions.insert(name, version);
+            }
+            Err(e) => failures.push(format!("{} could not be migrated: {}", name, e)),
+        }
+    }
+
+    if let Err(e) = filesystem::save_json_to_file(&versions_path, &versions) {
+        warn!("Failed to save {}: {}", versions_path, e);
+    }
+    failures
+}
+
+/// Publishes a `migration_failed` event and records the last error for each failure.
+pub async fn report_failures(app_state: &AppStateMutex, failures: Vec<String>) {
+    let event_bus = app_state.lock().await.event_bus.clone();
+    for message in failures {
+        event_bus.publish(EventKind::MigrationFailed, message.clone());
+        state_helpers::record_error(app_state, &message).await;
+    }
+}
+
+/// Applies the migrations newer than the recorded version and returns the version the file
+/// is at afterwards. A file that doesn't exist yet is created in the latest format, so it
+/// counts as migrated.
+fn migrate(artifact: &Artifact, recorded: Option<u32>) -> Result<u32, String> {
+    let latest = artifact.latest_version();
+    if !Path::new(&artifact.path).exists() {
+        return Ok(latest);
+    }
+    let from = recorded.unwrap_or(1);
+    if from >= latest {
+        return Ok(from);
+    }
+
+    let backup_path = format!("{}.v{}.bak", artifact.path, from);
+    std::fs::copy(&artifact.path, &backup_path)
+        .map_err(|e| format!("backup to {} failed: {}", backup_path, e))?;
+    info!(
+        "Migrating {} from version {} to {}, backup at {}",
+        artifact.path, from, latest, backup_path
+    );
+
+    for migration in artifact.migrations.iter().filter(|m| m.version > from) {
+        if let Err(e) = (migration.apply)(&artifact.path) {
+            error!(
+                "Migration of {} to version {} ({}) failed: {}",
+                artifact.path, migration.version, migration.description, e
+            );
+            if let Err(restore_error) = std::fs::copy(&backup_path, &artifact.path) {
+                error!(
+                    "Failed to restore {} from {}: {}",
+                    artifact.path, backup_path, restore_error
+                );
+            }
+            return Err(format!(
+                "version {} ({}) failed: {}",
+                migration.version, migration.description, e
+            ));
+        }
+        info!(
+            "Migrated {} to version {} ({})",
+            artifact.path, migration.version, migration.description
+        );
+    }
+    Ok(latest)
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn append(path: &str, text: &str) -> Result<(), String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        std::fs::write(path, content + text).map_err(|e| e.to_string())
+    }
+
+    fn test_artifact(path: &str) -> Artifact {
+        Artifact {
+            path: path.to_string(),
+            migrations: vec![
+                Migration {
+                    version: 2,
+                    description: "add b",
+                    apply: |path| append(path, "b"),
+                },
+                Migration {
+                    version: 3,
+                    description: "add c",
+                    apply: |path| append(path, "c"),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_migrations_apply_and_back_up() {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("data.txt");
+        let path = file.to_str().unwrap();
+
+        // missing files are created in the latest format
+        assert_eq!(migrate(&test_artifact(path), None), Ok(3));
+
+        // unrecorded files are at version 1
+        std::fs::write(&file, "a").unwrap();
+        assert_eq!(migrate(&test_artifact(path), None), Ok(3));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "abc");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("data.txt.v1.bak")).unwrap(),
+            "a"
+        );
+
+        // only newer migrations run
+        std::fs::write(&file, "ab").unwrap();
+        assert_eq!(migrate(&test_artifact(path), Some(2)), Ok(3));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "abc");
+        assert_eq!(migrate(&test_artifact(path), Some(3)), Ok(3));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "abc");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_migration_restores_backup() {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("data.txt");
+        let path = file.to_str().unwrap();
+        std::fs::write(&file, "a").unwrap();
+
+        let mut artifact = test_artifact(path);
+        artifact.migrations.push(Migration {
+            version: 4,
+            description: "broken",
+            apply: |_| Err("disk on fire".to_string()),
+        });
+        let error = migrate(&artifact, None).unwrap_err();
+        assert_eq!(error, "version 4 (broken) failed: disk on fire");
+        // the migrations before the failing one are rolled back too
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "a");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,50 @@
+pub mod admin_batch;
+pub mod assistant;
+pub mod audit_log;
 pub mod auth;
+pub mod backup;
+pub mod backup_scheduler;
+pub mod bowl;
+pub mod camera;
+pub mod config_reload;
+pub mod config_validation;
+pub mod current_calibration;
+pub mod diagnostics;
+pub mod digital_inputs;
+pub mod digital_outputs;
+pub mod dispense_recovery;
 pub mod dispenser;
+pub mod energy;
+pub mod error_reporting;
+pub mod events;
+pub mod fan;
+pub mod fleet;
+pub mod hopper_level;
+pub mod history;
+pub mod history_import;
+pub mod humidity;
+pub mod i2c_scan;
+pub mod jam_detector;
+pub mod localization;
+pub mod migrations;
+pub mod motor_vibration;
+pub mod mqtt;
+pub mod persisted_files;
 pub mod power_monitor;
+pub mod push_notifications;
+pub mod schedule_export;
+pub mod scheduler;
+pub mod sensor_debug;
+pub mod share;
+pub mod stats;
 pub mod status;
+pub mod stir;
+pub mod supervisor;
+pub mod temperature_monitor;
+pub mod training;
+pub mod trickle;
+pub mod triggers;
+pub mod watchdog;
+pub mod weight_history;
+pub mod webhooks;
 pub mod weight_monitor;
@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+use crate::application_state::{AppStateMutex, DispenserStatus};
+use crate::config::{self, MotorVibrationConfig, VibrationMode};
+use crate::sensors::WeightReading;
+use crate::services::events::{DispenserEvent, EventKind};
+
+/// Follows the motor through dispense progress and status events, and decides how weight
+/// averages taken meanwhile are published. Readings are marked unsettled from the start of
+/// a motor run until the weight is stable again after it.
+pub struct VibrationFilter {
+    mode: VibrationMode,
+    down_weight_factor: f32,
+    settle: Duration,
+    stable_tolerance_grams: f32,
+    motor_active: bool,
+    /// Set from the end of a motor run until readings are settled again
+    stopped_at: Option<Instant>,
+    last_grams: Option<f32>,
+}
+
+impl VibrationFilter {
+    pub fn new(vibration_config: &MotorVibrationConfig) -> Self {
+        VibrationFilter {
+            mode: vibration_config.mode.unwrap_or_default(),
+            down_weight_factor: vibration_config
+                .down_weight_factor
+                .unwrap_or(config::VIBRATION_DOWN_WEIGHT_FACTOR_DEFAULT)
+                .clamp(0.0, 1.0),
+            settle: Duration::from_millis(
+                vibration_config
+                    .settle_ms
+                    .unwrap_or(config::VIBRATION_SETTLE_MS_DEFAULT),
+            ),
+            stable_tolerance_grams: vibration_config
+                .stable_tolerance_grams
+                .unwrap_or(config::VIBRATION_STABLE_TOLERANCE_GRAMS_DEFAULT),
+            motor_active: false,
+            stopped_at: None,
+            last_grams: None,
+        }
+    }
+
+    /// Dispense runs report progress from 0 to 100%, stirring only has its status. A run
+    /// that is cancelled or fails ends with a status change.
+    pub fn observe(&mut self, event: &DispenserEvent, now: Instant) {
+        let active = match (&event.kind, &event.progress, &event.status) {
+            (EventKind::DispenseProgress, Some(progress), _) => progress.percent < 100,
+            (EventKind::StatusChanged, _, Some(DispenserStatus::Stirring)) => true,
+            (EventKind::StatusChanged, _, Some(DispenserStatus::MeasuringCurrent)) => true,
+            // between the runs of a dispense by weight, progress decides
+            (EventKind::StatusChanged, _, Some(DispenserStatus::Dispensing)) => return,
+            (EventKind::StatusChanged, _, Some(_)) => false,
+            _ => return,
+        };
+        self.set_motor_active(active, now);
+    }
+
+    fn set_motor_active(&mut self, active: bool, now: Instant) {
+        if self.motor_active && !active {
+            self.stopped_at = Some(now);
+        }
+        self.motor_active = active;
+    }
+
+    /// Whether samples are currently dropped instead of averaged.
+    pub fn suppressing(&self) -> bool {
+        self.motor_active && self.mode == VibrationMode::Suppress
+    }
+
+    /// Turns an average into the reading to publish. Without an average (all samples
+    /// suppressed) the last reading is repeated, unsettled, so the channel stays alive.
+    pub fn reading(&mut self, mean_grams: Option<f32>, now: Instant) -> Option<WeightReading> {
+        let grams = match (mean_grams, self.last_grams) {
+            (Some(mean), Some(last))
+                if self.motor_active && self.mode == VibrationMode::DownWeight =>
+            {
+                last + self.down_weight_factor * (mean - last)
+            }
+            (Some(mean), _) => mean,
+            (None, Some(last)) => last,
+            (None, None) => return None,
+        };
+
+        let settled = match self.stopped_at {
+            _ if self.motor_active => false,
+            Some(stopped_at) => {
+                let stable = mean_grams.is_some()
+                    && self
+                        .last_grams
+                        .is_some_and(|last| (grams - last).abs() <= self.stable_tolerance_grams);
+                let settled = stable && now.duration_since(stopped_at) >= self.settle;
+                if settled {
+                    self.stopped_at = None;
+                }
+                settled
+            }
+            None => true,
+        };
+
+        self.last_grams = Some(grams);
+        Some(WeightReading { grams, settled })
+    }
+}
+
+/// The hopper weight once readings have settled after a motor run, waiting at most
+/// `VIBRATION_SETTLE_WAIT_MAX_MS`. Without `motor_vibration` every reading counts as
+/// settled, so the latest one is returned right away.
+pub async fn settled_grams(app_state: &AppStateMutex) -> f32 {
+    let (mut weight_readings_rx, enabled) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.weight_readings_rx.clone(),
+            state_guard
+                .app_config
+                .weight_monitor
+                .motor_vibration
+                .is_some(),
+        )
+    };
+    if enabled {
+        // the current reading may predate a short motor run, so wait for a new one
+        weight_readings_rx.mark_unchanged();
+        let max_wait = Duration::from_millis(config::VIBRATION_SETTLE_WAIT_MAX_MS);
+        let _ = tokio::time::timeout(max_wait, async {
+            if weight_readings_rx.changed().await.is_ok() {
+                let _ = weight_readings_rx.wait_for(|r| r.settled).await;
+            }
+        })
+        .await;
+    }
+    weight_readings_rx.borrow().grams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(mode: VibrationMode) -> VibrationFilter {
+        VibrationFilter::new(&MotorVibrationConfig {
+            mode: Some(mode),
+            down_weight_factor: Some(0.5),
+            settle_ms: Some(500),
+            stable_tolerance_grams: Some(0.5),
+        })
+    }
+
+    #[test]
+    fn test_readings_settle_after_motor_run() {
+        let mut filter = filter(VibrationMode::Suppress);
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        assert!(filter.reading(Some(100.0), at(0)).unwrap().settled);
+
+        filter.set_motor_active(true, at(100));
+        assert!(filter.suppressing());
+        let held = filter.reading(None, at(500)).unwrap();
+        assert_eq!(held.grams, 100.0);
+        assert!(!held.settled);
+
+        filter.set_motor_active(false, at(1000));
+        // too early, then still moving, then stable
+        assert!(!filter.reading(Some(95.0), at(1200)).unwrap().settled);
+        assert!(!filter.reading(Some(92.0), at(1600)).unwrap().settled);
+        assert!(filter.reading(Some(91.8), at(2000)).unwrap().settled);
+        assert!(filter.reading(Some(95.0), at(2400)).unwrap().settled);
+    }
+
+    #[test]
+    fn test_down_weight_blends_readings() {
+        let mut filter = filter(VibrationMode::DownWeight);
+        let now = Instant::now();
+        filter.reading(Some(100.0), now);
+
+        filter.set_motor_active(true, now);
+        assert!(!filter.suppressing());
+        let reading = filter.reading(Some(120.0), now).unwrap();
+        assert_eq!(reading.grams, 110.0);
+        assert!(!reading.settled);
+    }
+}
@@ -0,0 +1,260 @@
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{self, DeviceConfig, MqttConfig};
+use crate::services::supervisor;
+use crate::utils::datetime;
+
+/// Messages queued for the broker before new ones are dropped, e.g. while it is down.
+const REQUEST_CAPACITY: usize = 64;
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Payload of the status topic.
+#[derive(Serialize, Debug)]
+struct StatusMessage {
+    status: DispenserStatus,
+    timestamp: String,
+}
+
+/// Topics resolved from the config.
+#[derive(Debug, Clone, PartialEq)]
+struct MqttTopics {
+    status: String,
+    weight: String,
+    power: String,
+    /// `online` while connected, `offline` as the last will
+    availability: String,
+}
+
+impl MqttTopics {
+    fn from_config(mqtt_config: &MqttConfig, device: Option<&DeviceConfig>) -> Self {
+        let prefix = mqtt_config
+            .topic_prefix
+            .clone()
+            .unwrap_or_else(|| match device {
+                Some(device) => format!("treat-dispenser/{}", device.name),
+                None => "treat-dispenser".to_string(),
+            });
+        let topic = |configured: &Option<String>, name: &str| {
+            configured
+                .clone()
+                .unwrap_or_else(|| format!("{}/{}", prefix, name))
+        };
+        MqttTopics {
+            status: topic(&mqtt_config.status_topic, "status"),
+            weight: topic(&mqtt_config.weight_topic, "weight"),
+            power: topic(&mqtt_config.power_topic, "power"),
+            availability: format!("{}/availability", prefix),
+        }
+    }
+}
+
+fn mqtt_options(
+    mqtt_config: &MqttConfig,
+    device: Option<&DeviceConfig>,
+    topics: &MqttTopics,
+) -> MqttOptions {
+    let client_id = mqtt_config
+        .client_id
+        .clone()
+        .unwrap_or_else(|| match device {
+            Some(device) => format!("treat-dispenser-{}", device.name),
+            None => "treat-dispenser".to_string(),
+        });
+    let mut options = MqttOptions::new(
+        client_id,
+        &mqtt_config.host,
+        mqtt_config.port.unwrap_or(config::MQTT_PORT_DEFAULT),
+    );
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        &topics.availability,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &mqtt_config.username {
+        options.set_credentials(username, mqtt_config.password.clone().unwrap_or_default());
+    }
+    options
+}
+
+/// Starts publishing dispenser status changes, weight readings and power readings to an
+/// MQTT broker if an `mqtt` section is configured. Status messages are retained, so
+/// subscribers get the current status right away. Weight and power readings are sent at
+/// most every `telemetry_interval_secs`, and only when there is a new reading. While the
+/// broker is unreachable, messages are dropped and the connection is retried.
+pub async fn start_mqtt_publisher(app_state: &Arc<Mutex<ApplicationState>>) {
+    let (mqtt_config, device) = {
+        let state_guard = app_state.lock().await;
+        match state_guard.app_config.mqtt.clone() {
+            Some(c) if c.enabled.unwrap_or(true) => (c, state_guard.app_config.device.clone()),
+            _ => return,
+        }
+    };
+    let topics = MqttTopics::from_config(&mqtt_config, device.as_ref());
+    let options = mqtt_options(&mqtt_config, device.as_ref(), &topics);
+    let interval = Duration::from_secs(
+        mqtt_config
+            .telemetry_interval_secs
+            .unwrap_or(config::MQTT_TELEMETRY_INTERVAL_SECS_DEFAULT)
+            .max(1),
+    );
+    info!(
+        "Publishing telemetry to MQTT broker {}:{} under {}",
+        options.broker_address().0,
+        options.broker_address().1,
+        topics.status
+    );
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "mqtt_publisher", move || {
+        run_publisher(
+            Arc::clone(&app_state_clone),
+            options.clone(),
+            topics.clone(),
+            interval,
+        )
+    });
+}
+
+async fn run_publisher(
+    app_state: Arc<Mutex<ApplicationState>>,
+    options: MqttOptions,
+    topics: MqttTopics,
+    interval: Duration,
+) {
+    let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+    let (mut transitions_rx, mut weight_readings_rx, mut power_readings_rx) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.status_transitions_tx.subscribe(),
+            state_guard.weight_readings_rx.clone(),
+            state_guard.power_readings_rx.clone(),
+        )
+    };
+    let publish = |topic: &str, retain: bool, payload: String| {
+        if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, retain, payload) {
+            debug!("Dropped MQTT message to {}: {}", topic, e);
+        }
+    };
+    let publish_status = |status: DispenserStatus, timestamp: String| {
+        let message = StatusMessage { status, timestamp };
+        if let Ok(payload) = serde_json::to_string(&message) {
+            publish(&topics.status, true, payload);
+        }
+    };
+
+    let mut connected = false;
+    let mut failing = false;
+    let mut telemetry = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            event = eventloop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker");
+                    connected = true;
+                    failing = false;
+                    publish(&topics.availability, true, "online".to_string());
+                    let status = app_state.lock().await.status.clone();
+                    publish_status(status, datetime::get_formatted_current_timestamp());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    // the event loop reconnects on the next poll, only the first failure
+                    // in a row is worth a warning
+                    if failing {
+                        debug!("MQTT connection failed: {}", e);
+                    } else {
+                        warn!("MQTT connection failed: {}", e);
+                    }
+                    connected = false;
+                    failing = true;
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+            transition = transitions_rx.recv() => match transition {
+                Ok(transition) => publish_status(transition.to, transition.timestamp),
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("MQTT publisher skipped {} status transitions", skipped);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = telemetry.tick() => {
+                if !connected {
+                    continue;
+                }
+                if weight_readings_rx.has_changed().unwrap_or(false) {
+                    let reading = weight_readings_rx.borrow_and_update().clone();
+                    if let Ok(payload) = serde_json::to_string(&reading) {
+                        publish(&topics.weight, false, payload);
+                    }
+                }
+                if power_readings_rx.has_changed().unwrap_or(false) {
+                    let reading = power_readings_rx.borrow_and_update().clone();
+                    if let Ok(payload) = serde_json::to_string(&reading) {
+                        publish(&topics.power, false, payload);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mqtt_config() -> MqttConfig {
+        MqttConfig {
+            enabled: None,
+            host: "broker.local".to_string(),
+            port: None,
+            client_id: None,
+            username: None,
+            password: None,
+            topic_prefix: None,
+            status_topic: None,
+            weight_topic: None,
+            power_topic: None,
+            telemetry_interval_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_topics_from_config() {
+        let device = DeviceConfig {
+            name: "barn-feeder".to_string(),
+            location: None,
+            fleet_id: None,
+        };
+        let topics = MqttTopics::from_config(&mqtt_config(), Some(&device));
+        assert_eq!(topics.status, "treat-dispenser/barn-feeder/status");
+        assert_eq!(
+            topics.availability,
+            "treat-dispenser/barn-feeder/availability"
+        );
+
+        let topics = MqttTopics::from_config(
+            &MqttConfig {
+                topic_prefix: Some("home/feeder".to_string()),
+                weight_topic: Some("sensors/hopper".to_string()),
+                ..mqtt_config()
+            },
+            Some(&device),
+        );
+        assert_eq!(topics.status, "home/feeder/status");
+        assert_eq!(topics.weight, "sensors/hopper");
+        assert_eq!(topics.power, "home/feeder/power");
+
+        let topics = MqttTopics::from_config(&mqtt_config(), None);
+        assert_eq!(topics.power, "treat-dispenser/power");
+    }
+}
@@ -0,0 +1,167 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{error, info};
+
+use crate::application_state::AppStateMutex;
+use crate::sensors::WeightSensorCalibration;
+use crate::services::bowl::BowlStats;
+use crate::services::dispense_recovery::DispenseJournal;
+use crate::services::events::EventKind;
+use crate::services::push_notifications::DeviceRegistration;
+use crate::services::schedule_export::ScheduleSet;
+use crate::services::share::StatusShare;
+use crate::services::stats::DispenseStats;
+use crate::utils::{filesystem, state_helpers};
+
+/// Suffix appended to persisted files that failed the startup check.
+const QUARANTINE_SUFFIX: &str = ".bad";
+
+/// A persisted file that couldn't be read at startup and was moved aside, so the service
+/// started with defaults instead.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct QuarantinedFile {
+    pub file: String,
+    pub error: String,
+    /// Where the file was moved to, unset if it couldn't be moved
+    pub quarantined_as: Option<String>,
+}
+
+type FileCheck = fn(&str) -> Result<(), String>;
+
+fn parses<T: DeserializeOwned>(path: &str) -> Result<(), String> {
+    filesystem::read_json_from_file::<T>(path).map(|_| ())
+}
+
+/// Checks that every persisted file in the data directory can be parsed. Must run before
+/// the application state loads them. Files that can't are renamed with a `.bad` suffix, so
+/// they are kept for inspection while loading falls back to defaults. Missing files are not
+/// an error.
+pub fn check_persisted_files() -> Vec<QuarantinedFile> {
+    let checks: [(String, FileCheck); 7] = [
+        (
+            filesystem::get_calibration_file_path(),
+            parses::<WeightSensorCalibration>,
+        ),
+        (
+            filesystem::get_dispense_stats_file_path(),
+            parses::<DispenseStats>,
+        ),
+        (
+            filesystem::get_notification_devices_file_path(),
+            parses::<Vec<DeviceRegistration>>,
+        ),
+        (
+            filesystem::get_dispense_journal_file_path(),
+            parses::<DispenseJournal>,
+        ),
+        (
+            filesystem::get_bowl_stats_file_path(),
+            parses::<BowlStats>,
+        ),
+        (
+            filesystem::get_status_shares_file_path(),
+            parses::<Vec<StatusShare>>,
+        ),
+        (
+            filesystem::get_schedules_file_path(),
+            parses::<ScheduleSet>,
+        ),
+    ];
+
+    let checked = checks
+        .iter()
+        .filter(|(path, _)| Path::new(path).exists())
+        .count();
+    let quarantined: Vec<QuarantinedFile> = checks
+        .iter()
+        .filter_map(|(path, check)| check_file(path, *check))
+        .collect();
+    info!(
+        "Persisted files checked: {} ok, {} quarantined",
+        checked - quarantined.len(),
+        quarantined.len()
+    );
+    quarantined
+}
+
+/// Lists the quarantined files in `/status`, and publishes a `file_quarantined` event and
+/// records the last error for each of them.
+pub async fn report_quarantined_files(
+    app_state: &AppStateMutex,
+    quarantined: Vec<QuarantinedFile>,
+) {
+    let event_bus = {
+        let mut state_guard = app_state.lock().await;
+        state_guard.quarantined_files = quarantined.clone();
+        state_guard.event_bus.clone()
+    };
+    for file in quarantined {
+        let message = format!(
+            "{} was unreadable and replaced by defaults: {}",
+            file.file, file.error
+        );
+        event_bus.publish(EventKind::FileQuarantined, message.clone());
+        state_helpers::record_error(app_state, &message).await;
+    }
+}
+
+fn check_file(path: &str, check: FileCheck) -> Option<QuarantinedFile> {
+    if !Path::new(path).exists() {
+        return None;
+    }
+    let error = check(path).err()?;
+
+    let bad_path = format!("{}{}", path, QUARANTINE_SUFFIX);
+    let quarantined_as = match std::fs::rename(path, &bad_path) {
+        Ok(()) => {
+            error!("Quarantined {} as {}: {}", path, bad_path, error);
+            Some(bad_path)
+        }
+        Err(e) => {
+            error!("Failed to quarantine {}: {} ({})", path, e, error);
+            None
+        }
+    };
+    Some(QuarantinedFile {
+        file: Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string()),
+        error,
+        quarantined_as,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrupt_file_is_quarantined() {
+        let dir = std::env::temp_dir().join(format!("persisted-files-{}", rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.json");
+        let corrupt = dir.join("corrupt.json");
+        std::fs::write(&good, r#"{"scale":2.0,"offset":0.0,"tare_raw":10}"#).unwrap();
+        std::fs::write(&corrupt, r#"{"scale":2.0,"off"#).unwrap();
+
+        let check = parses::<WeightSensorCalibration>;
+        assert_eq!(check_file(good.to_str().unwrap(), check), None);
+        assert_eq!(
+            check_file(dir.join("missing.json").to_str().unwrap(), check),
+            None
+        );
+
+        let incident = check_file(corrupt.to_str().unwrap(), check).unwrap();
+        assert_eq!(incident.file, "corrupt.json");
+        assert!(!corrupt.exists());
+        assert!(dir.join("corrupt.json.bad").exists());
+        assert_eq!(
+            incident.quarantined_as.as_deref(),
+            dir.join("corrupt.json.bad").to_str()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::Mutex;
-use tracing::{debug, error, info, warn};
+use tracing::{Span, debug, error, info, warn};
 
 use crate::application_state;
+use crate::services::audit_log;
+use crate::services::error_reporting::{self, ErrorKind};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
 use crate::sensors::PowerReading;
-use crate::config;
+use crate::config::{self, CurrentJamConfig};
+use crate::utils::datetime;
+
+const POWER_READING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Overcurrent cancellation switched off through the API, e.g. to jog a deliberately
+/// loaded mechanism. Switches itself back on at `until`.
+#[derive(Debug, Clone)]
+pub struct OvercurrentOverride {
+    pub until: Instant,
+    pub until_time: String,
+    pub disabled_by: String,
+}
+
+impl OvercurrentOverride {
+    fn is_active(&self) -> bool {
+        Instant::now() < self.until
+    }
+}
+
+/// Whether high current cancels motor runs, reported in `/status`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct OvercurrentProtectionStatus {
+    pub enabled: bool,
+    pub disabled_until: Option<String>,
+    pub disabled_by: Option<String>,
+}
+
+impl OvercurrentProtectionStatus {
+    pub fn from_override(overcurrent_override: Option<&OvercurrentOverride>) -> Self {
+        match overcurrent_override.filter(|o| o.is_active()) {
+            Some(o) => OvercurrentProtectionStatus {
+                enabled: false,
+                disabled_until: Some(o.until_time.clone()),
+                disabled_by: Some(o.disabled_by.clone()),
+            },
+            None => OvercurrentProtectionStatus {
+                enabled: true,
+                disabled_until: None,
+                disabled_by: None,
+            },
+        }
+    }
+}
+
+/// Stops high current from cancelling motor runs for `duration`, after which protection
+/// is enabled again on its own. Both are published to the event log and recorded in the
+/// audit log with the user.
+pub async fn disable_overcurrent_protection(
+    app_state: &Arc<Mutex<application_state::ApplicationState>>,
+    duration: Duration,
+    user: &str,
+) -> OvercurrentProtectionStatus {
+    let until = Instant::now() + duration;
+    let overcurrent_override = OvercurrentOverride {
+        until,
+        until_time: datetime::format_system_time(SystemTime::now() + duration),
+        disabled_by: user.to_string(),
+    };
+    let message = format!(
+        "Overcurrent protection disabled by {} until {}",
+        user, overcurrent_override.until_time
+    );
+    warn!("{}", message);
+    let status = {
+        let mut state_guard = app_state.lock().await;
+        state_guard.overcurrent_override = Some(overcurrent_override);
+        state_guard
+            .event_bus
+            .publish(EventKind::OvercurrentProtectionDisabled, message.clone());
+        OvercurrentProtectionStatus::from_override(state_guard.overcurrent_override.as_ref())
+    };
+    audit_log::record(user, EventKind::OvercurrentProtectionDisabled, &message).await;
+
+    let app_state = Arc::clone(app_state);
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+        let message = "Overcurrent protection enabled again after timeout";
+        {
+            let mut state_guard = app_state.lock().await;
+            // unless it was enabled or disabled again in the meantime
+            if state_guard
+                .overcurrent_override
+                .as_ref()
+                .is_none_or(|o| o.until != until)
+            {
+                return;
+            }
+            state_guard.overcurrent_override = None;
+            info!("{}", message);
+            state_guard
+                .event_bus
+                .publish(EventKind::OvercurrentProtectionEnabled, message.to_string());
+        }
+        audit_log::record(
+            audit_log::SYSTEM_USER,
+            EventKind::OvercurrentProtectionEnabled,
+            message,
+        )
+        .await;
+    });
+    status
+}
+
+/// Ends a `disable_overcurrent_protection` before its timeout.
+pub async fn enable_overcurrent_protection(
+    app_state: &Arc<Mutex<application_state::ApplicationState>>,
+    user: &str,
+) -> OvercurrentProtectionStatus {
+    let message = format!("Overcurrent protection enabled by {}", user);
+    let enabled = {
+        let mut state_guard = app_state.lock().await;
+        let enabled = state_guard.overcurrent_override.take().is_some();
+        if enabled {
+            info!("{}", message);
+            state_guard
+                .event_bus
+                .publish(EventKind::OvercurrentProtectionEnabled, message.clone());
+        }
+        enabled
+    };
+    if enabled {
+        audit_log::record(user, EventKind::OvercurrentProtectionEnabled, &message).await;
+    }
+    OvercurrentProtectionStatus::from_override(None)
+}
 
 struct PowerMonitor {
     readings_vec: Vec<PowerReading>,
@@ -47,72 +178,268 @@ impl PowerMonitor {
     }
 }
 
+/// Detects a jam from the motor current while dispensing. A blocked auger draws high
+/// current for as long as the motor pushes against it, unlike the short spike when the
+/// motor starts, so a jam is reported once `min_spike_share` of the readings within a
+/// full window are above `spike_amps`.
+struct CurrentJamDetector {
+    spike_amps: f32,
+    window_len: usize,
+    min_spikes: usize,
+    window: VecDeque<bool>,
+}
+
+impl CurrentJamDetector {
+    fn new(jam_config: &CurrentJamConfig, current_limit: f32) -> Self {
+        let window_ms = jam_config
+            .window_ms
+            .unwrap_or(config::CURRENT_JAM_WINDOW_MS_DEFAULT);
+        let window_len = (window_ms / POWER_READING_INTERVAL.as_millis() as u64).max(1) as usize;
+        let min_spike_share = jam_config
+            .min_spike_share
+            .unwrap_or(config::CURRENT_JAM_MIN_SPIKE_SHARE_DEFAULT)
+            .clamp(0.0, 1.0);
+        CurrentJamDetector {
+            spike_amps: jam_config
+                .spike_amps
+                .unwrap_or(current_limit * config::CURRENT_JAM_SPIKE_FRACTION_DEFAULT),
+            window_len,
+            min_spikes: ((min_spike_share * window_len as f32).ceil() as usize).max(1),
+            window: VecDeque::with_capacity(window_len),
+        }
+    }
+
+    /// Returns the jam message once the window holds enough spikes.
+    fn observe(&mut self, current_amps: f32) -> Option<String> {
+        if self.window.len() == self.window_len {
+            self.window.pop_front();
+        }
+        self.window.push_back(current_amps > self.spike_amps);
+        if self.window.len() < self.window_len {
+            return None;
+        }
+        let spikes = self.window.iter().filter(|spike| **spike).count();
+        (spikes >= self.min_spikes).then(|| {
+            format!(
+                "Jam detected: motor current above {:.2} A in {} of {} readings",
+                self.spike_amps, spikes, self.window_len
+            )
+        })
+    }
+}
+
+/// Feeds a reading to the current jam detector while a dispense runs, and stops the
+/// motor as jammed once it reports one. The detector starts over with every dispense.
+async fn check_current_jam(
+    app_state: &Arc<Mutex<application_state::ApplicationState>>,
+    jam_config: &CurrentJamConfig,
+    jam_detector: &mut Option<CurrentJamDetector>,
+    current_amps: f32,
+) {
+    let mut state_guard = app_state.lock().await;
+    let cancel_token = match &state_guard.motor_cancel_token {
+        Some(token) if state_guard.status == application_state::DispenserStatus::Dispensing => {
+            token.clone()
+        }
+        _ => {
+            *jam_detector = None;
+            return;
+        }
+    };
+    let current_limit = state_guard
+        .app_config
+        .power_monitor
+        .motor_current_limit_amps
+        .unwrap_or(config::MOTOR_CURRENT_LIMIT_AMPS_DEFAULT);
+    let Some(jam) = jam_detector
+        .get_or_insert_with(|| CurrentJamDetector::new(jam_config, current_limit))
+        .observe(current_amps)
+    else {
+        return;
+    };
+
+    let dispense_span = state_guard.dispense_span.clone().unwrap_or_else(Span::none);
+    if let Some(o) = state_guard
+        .overcurrent_override
+        .as_ref()
+        .filter(|o| o.is_active())
+    {
+        let message = format!(
+            "{}, not stopping, overcurrent protection disabled by {} until {}",
+            jam, o.disabled_by, o.until_time
+        );
+        dispense_span.in_scope(|| warn!("{}", message));
+        state_guard
+            .event_bus
+            .publish(EventKind::CurrentJamDetected, message);
+        return;
+    }
+    dispense_span.in_scope(|| warn!("{}, stopping the motor", jam));
+    state_guard.event_bus.publish(
+        EventKind::CurrentJamDetected,
+        format!("{}, stopping the motor", jam),
+    );
+    state_guard.motor_jam_reason = Some(jam.clone());
+    state_guard.motor_cancel_reason = Some(jam);
+    cancel_token.cancel();
+    *jam_detector = None;
+}
+
+/// The last `POWER_TRACE_SAMPLES` power readings (about a minute), included in the
+/// diagnostics bundle. Updated in batches whenever the monitor averages its readings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PowerTrace {
+    /// Time of the newest sample
+    pub updated: Option<String>,
+    pub interval_ms: u64,
+    /// Oldest first
+    pub samples: VecDeque<PowerReading>,
+}
+
+impl PowerTrace {
+    pub fn record(&mut self, readings: &[PowerReading]) {
+        for reading in readings {
+            if self.samples.len() == config::POWER_TRACE_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(reading.clone());
+        }
+        self.interval_ms = POWER_READING_INTERVAL.as_millis() as u64;
+        self.updated = Some(datetime::get_formatted_current_timestamp());
+    }
+}
+
 pub async fn start_power_monitoring_thread(
     app_state: &Arc<Mutex<application_state::ApplicationState>>,
 ) {
-    tokio::spawn({
-        let current_sensor = app_state.lock().await.power_sensor_mutex.clone();
-        let power_readings_tx = app_state.lock().await.power_readings_tx.clone();
-        let app_state_clone = Arc::clone(&app_state);
-
-        async move {
-            info!("Starting power monitoring thread");
-            let mut power_monitor = PowerMonitor::new();
-            let mut i = 0;
-
-            let config = app_state_clone.lock().await.app_config.clone();
-            let current_limit = config.power_monitor.motor_current_limit_amps.unwrap_or(config::MOTOR_CURRENT_LIMIT_AMPS_DEFAULT);
-
-            loop {
-                match &current_sensor {
-                    Some(sensor_mutex) => {
-                        let power_reading_result = sensor_mutex.lock().await.get_power_reading();
-                        match power_reading_result {
-                            Ok(power_reading) => {
-                                // publish the power reading to the channel
-                                power_monitor.add_reading(power_reading.clone());
-                                let _ = power_readings_tx.send(power_reading);
-                            }
-                            Err(e) => {
-                                error!("Failed to get power reading: {}", e);
-                            }
-                        }
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "power_monitor", move || {
+        run_power_monitor(Arc::clone(&app_state_clone))
+    });
+}
 
-                        // log and clear power readings after every 70 readings (approx every 7 seconds)
-                        if i == 70 {
-                            let avg_current = power_monitor.get_average_current();
-                            debug!(
-                                "Average current over last {} readings: {} A",
-                                power_monitor.get_readings().len(),
-                                avg_current
-                            );
-
-                            if avg_current > current_limit {
-                                warn!("High average current detected: {} A", avg_current);
-                                warn!("Readings: {:?}", power_monitor.get_readings());
-                                let state_guard = app_state_clone.lock().await;
-
-                                if let Some(cancel_token) = &state_guard.motor_cancel_token {
-                                    info!(
-                                        "Cancelling ongoing motor operations due to high current."
-                                    );
-                                    cancel_token.cancel();
-                                }
-                            }
-                            power_monitor.clear_readings();
-                            i = 0;
+async fn run_power_monitor(app_state: Arc<Mutex<application_state::ApplicationState>>) {
+    let current_sensor = app_state.lock().await.power_sensor_mutex.clone();
+    let power_readings_tx = app_state.lock().await.power_readings_tx.clone();
+    let jam_config = app_state
+        .lock()
+        .await
+        .app_config
+        .power_monitor
+        .jam_detection
+        .clone()
+        .filter(|c| c.enabled.unwrap_or(true));
+    let mut jam_detector = None;
+
+    info!("Starting power monitoring thread");
+    let mut power_monitor = PowerMonitor::new();
+    let mut i = 0;
+
+    loop {
+        match &current_sensor {
+            Some(sensor_mutex) => {
+                let power_reading_result = sensor_mutex.lock().await.get_power_reading();
+                match power_reading_result {
+                    Ok(power_reading) => {
+                        // publish the power reading to the channel
+                        power_monitor.add_reading(power_reading.clone());
+                        if let Some(jam_config) = &jam_config {
+                            check_current_jam(
+                                &app_state,
+                                jam_config,
+                                &mut jam_detector,
+                                power_reading.current_amps,
+                            )
+                            .await;
                         }
+                        let _ = power_readings_tx.send(power_reading);
                     }
-                    None => {
-                        error!("Power monitor is not initialized");
-                        break;
+                    Err(e) => {
+                        error!("Failed to get power reading: {}", e);
                     }
                 }
-                tokio::time::sleep(Duration::from_millis(100)).await;
-                i += 1;
+
+                // log and clear power readings after every 70 readings (approx every 7 seconds)
+                if i == 70 {
+                    let avg_current = power_monitor.get_average_current();
+
+                    // attach to the running dispense job (if any) so these events show up in its timeline
+                    // read on every check, the current calibration can change the limit
+                    let (dispense_span, current_limit) = {
+                        let mut state_guard = app_state.lock().await;
+                        state_guard.power_trace.record(power_monitor.get_readings());
+                        (
+                            state_guard.dispense_span.clone().unwrap_or_else(Span::none),
+                            state_guard
+                                .app_config
+                                .power_monitor
+                                .motor_current_limit_amps
+                                .unwrap_or(config::MOTOR_CURRENT_LIMIT_AMPS_DEFAULT),
+                        )
+                    };
+                    dispense_span.in_scope(|| {
+                        debug!(
+                            "Average current over last {} readings: {} A ({} W)",
+                            power_monitor.get_readings().len(),
+                            avg_current,
+                            power_monitor.get_average_power()
+                        );
+                    });
+
+                    if avg_current > current_limit {
+                        dispense_span.in_scope(|| {
+                            warn!("High average current detected: {} A", avg_current);
+                            warn!("Readings: {:?}", power_monitor.get_readings());
+                        });
+                        let mut state_guard = app_state.lock().await;
+                        state_guard.event_bus.publish(
+                            EventKind::OvercurrentDetected,
+                            format!(
+                                "Average current {:.2} A above the {:.2} A limit",
+                                avg_current, current_limit
+                            ),
+                        );
+
+                        if let Some(o) = state_guard
+                            .overcurrent_override
+                            .as_ref()
+                            .filter(|o| o.is_active())
+                        {
+                            dispense_span.in_scope(|| {
+                                warn!(
+                                    "Not cancelling, overcurrent protection disabled by {} until {}",
+                                    o.disabled_by, o.until_time
+                                )
+                            });
+                        } else if state_guard.status == application_state::DispenserStatus::MeasuringCurrent {
+                            debug!("Not cancelling, the motor current is being measured");
+                        } else if let Some(cancel_token) = state_guard.motor_cancel_token.clone() {
+                            dispense_span.in_scope(|| {
+                                info!("Cancelling ongoing motor operations due to high current.")
+                            });
+                            state_guard.motor_cancel_reason = Some(format!(
+                                "Average current {:.2} A above the {:.2} A limit",
+                                avg_current, current_limit
+                            ));
+                            cancel_token.cancel();
+                        }
+                    }
+                    power_monitor.clear_readings();
+                    i = 0;
+                }
+            }
+            None => {
+                error!("Power monitor is not initialized");
+                error_reporting::report(
+                    ErrorKind::BackgroundTask,
+                    "Power monitor is not initialized, overcurrent protection is inactive",
+                );
+                break;
             }
         }
-    });
+        tokio::time::sleep(POWER_READING_INTERVAL).await;
+        i += 1;
+    }
 }
 
 #[cfg(test)]
@@ -148,4 +475,45 @@ mod tests {
         monitor.clear_readings();
         assert!(monitor.get_readings().is_empty());
     }
+
+    #[test]
+    fn test_current_jam_detector() {
+        let jam_config = CurrentJamConfig {
+            enabled: None,
+            spike_amps: None,
+            window_ms: Some(1000),
+            min_spike_share: Some(0.8),
+        };
+        let mut detector = CurrentJamDetector::new(&jam_config, 1.0);
+        assert_eq!(detector.spike_amps, 0.8);
+        assert_eq!(detector.window_len, 10);
+
+        // a start-up spike followed by normal current
+        for current in [1.5, 1.4, 0.5, 0.4, 0.4, 0.5, 0.4, 0.4, 0.5, 0.4] {
+            assert!(detector.observe(current).is_none());
+        }
+        // current stays high, reported once 8 of the last 10 readings are spikes
+        for _ in 0..7 {
+            assert!(detector.observe(1.2).is_none());
+        }
+        let jam = detector.observe(1.2).expect("should detect a jam");
+        assert!(jam.contains("8 of 10"), "{}", jam);
+    }
+
+    #[test]
+    fn test_power_trace_keeps_latest_samples() {
+        let mut trace = PowerTrace::default();
+        let readings: Vec<PowerReading> = (0..config::POWER_TRACE_SAMPLES + 10)
+            .map(|i| PowerReading {
+                bus_voltage_volts: 12.0,
+                current_amps: i as f32,
+                power_watts: 0.0,
+            })
+            .collect();
+        trace.record(&readings);
+
+        assert_eq!(trace.samples.len(), config::POWER_TRACE_SAMPLES);
+        assert_eq!(trace.samples.front().unwrap().current_amps, 10.0);
+        assert!(trace.updated.is_some());
+    }
 }
@@ -0,0 +1,356 @@
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{DeviceConfig, FcmConfig};
+use crate::services::events::{DispenserEvent, EventKind};
+use crate::services::supervisor;
+use crate::utils::{datetime, filesystem};
+
+const FCM_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+
+/// Access tokens are valid for an hour, refresh a bit early.
+const ACCESS_TOKEN_LIFETIME: Duration = Duration::from_secs(3300);
+
+/// A companion app installation that receives push notifications.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct DeviceRegistration {
+    /// FCM registration token of the app installation
+    pub token: String,
+    /// e.g. android | ios, informational only
+    pub platform: Option<String>,
+    pub registered_at: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ServiceAccount {
+    project_id: String,
+    client_email: String,
+    private_key: String,
+    token_uri: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AssertionClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+pub fn load_devices() -> Vec<DeviceRegistration> {
+    filesystem::read_json_from_file(&filesystem::get_notification_devices_file_path())
+        .unwrap_or_default()
+}
+
+fn save_devices(devices: &[DeviceRegistration]) -> Result<(), String> {
+    filesystem::save_json_to_file(&filesystem::get_notification_devices_file_path(), &devices)
+        .map_err(|e| format!("Failed to save notification devices: {}", e))
+}
+
+/// Adds a device, or updates its platform if the token is already registered.
+pub fn register_device(
+    token: &str,
+    platform: Option<String>,
+) -> Result<DeviceRegistration, String> {
+    if token.trim().is_empty() {
+        return Err("Device token must not be empty".to_string());
+    }
+
+    let mut devices = load_devices();
+    devices.retain(|d| d.token != token);
+    let registration = DeviceRegistration {
+        token: token.to_string(),
+        platform,
+        registered_at: Some(datetime::get_formatted_current_timestamp()),
+    };
+    devices.push(registration.clone());
+    save_devices(&devices)?;
+    Ok(registration)
+}
+
+/// Removes a device, returns false if the token wasn't registered.
+pub fn unregister_device(token: &str) -> Result<bool, String> {
+    let mut devices = load_devices();
+    let count = devices.len();
+    devices.retain(|d| d.token != token);
+    if devices.len() == count {
+        return Ok(false);
+    }
+    save_devices(&devices)?;
+    Ok(true)
+}
+
+/// Title and body of the push notification for an event, `None` for events the app
+/// doesn't need to be woken up for.
+fn notification_for(event: &DispenserEvent) -> Option<(&'static str, String)> {
+    match (&event.kind, &event.status) {
+        (EventKind::Dispensed, _) => Some(("Treats dispensed", event.message.clone())),
+        (EventKind::StatusChanged, Some(DispenserStatus::Jammed)) => Some((
+            "Dispenser jammed",
+            "The dispenser is jammed and needs attention".to_string(),
+        )),
+        (EventKind::RecoveredInterrupted, _) => {
+            Some(("Dispense interrupted", event.message.clone()))
+        }
+        (EventKind::StepLoss | EventKind::LoadCellFailing, _) => Some(("Maintenance needed", event.message.clone())),
+        (EventKind::StatusChanged, Some(DispenserStatus::Empty)) => Some((
+            "Dispenser empty",
+            "The treat hopper is empty, time for a refill".to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/// Prefixes the title with the device name, so alerts from several dispensers can be
+/// told apart, e.g. `barn-feeder: Dispenser empty`.
+fn device_title(device: Option<&DeviceConfig>, title: &str) -> String {
+    match device {
+        Some(device) => format!("{}: {}", device.name, title),
+        None => title.to_string(),
+    }
+}
+
+struct FcmSender {
+    client: reqwest::Client,
+    service_account: ServiceAccount,
+    access_token: Option<(String, Instant)>,
+}
+
+impl FcmSender {
+    fn from_config(fcm_config: &FcmConfig) -> Result<Self, String> {
+        let service_account: ServiceAccount =
+            filesystem::read_json_from_file(&fcm_config.service_account_file)
+                .map_err(|e| format!("Failed to read FCM service account file: {}", e))?;
+        // fail at startup rather than on the first notification if the key is unusable
+        EncodingKey::from_rsa_pem(service_account.private_key.as_bytes())
+            .map_err(|e| format!("Invalid FCM service account private key: {}", e))?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(FcmSender {
+            client,
+            service_account,
+            access_token: None,
+        })
+    }
+
+    /// Exchanges a signed service account assertion for an OAuth access token.
+    async fn get_access_token(&mut self) -> Result<String, String> {
+        if let Some((token, fetched_at)) = &self.access_token
+            && fetched_at.elapsed() < ACCESS_TOKEN_LIFETIME
+        {
+            return Ok(token.clone());
+        }
+
+        let token_uri = self
+            .service_account
+            .token_uri
+            .clone()
+            .unwrap_or_else(|| "https://oauth2.googleapis.com/token".to_string());
+        let now = chrono::Utc::now().timestamp();
+        let claims = AssertionClaims {
+            iss: &self.service_account.client_email,
+            scope: FCM_SCOPE,
+            aud: &token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let key = EncodingKey::from_rsa_pem(self.service_account.private_key.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let assertion = encode(&Header::new(Algorithm::RS256), &claims, &key)
+            .map_err(|e| format!("Failed to sign token request: {}", e))?;
+
+        let response: serde_json::Value = self
+            .client
+            .post(&token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to fetch FCM access token: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid token response: {}", e))?;
+
+        let token = response["access_token"]
+            .as_str()
+            .ok_or_else(|| "Token response has no access_token".to_string())?
+            .to_string();
+        self.access_token = Some((token.clone(), Instant::now()));
+        Ok(token)
+    }
+
+    /// Sends one notification. Returns `Ok(false)` if FCM reports the device token
+    /// as no longer registered.
+    async fn send(&mut self, device_token: &str, title: &str, body: &str) -> Result<bool, String> {
+        let access_token = self.get_access_token().await?;
+        let url = format!(
+            "https://fcm.googleapis.com/v1/projects/{}/messages:send",
+            self.service_account.project_id
+        );
+        let message = serde_json::json!({
+            "message": {
+                "token": device_token,
+                "notification": { "title": title, "body": body },
+            }
+        });
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(access_token)
+            .json(&message)
+            .send()
+            .await
+            .map_err(|e| format!("FCM request failed: {}", e))?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            reqwest::StatusCode::UNAUTHORIZED => {
+                self.access_token = None;
+                Err("FCM rejected the access token".to_string())
+            }
+            status => Err(format!(
+                "FCM returned {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// Forwards dispensed, jammed, empty and step loss events to every registered device if FCM is configured.
+pub async fn start_push_notifier(app_state: &Arc<Mutex<ApplicationState>>) {
+    let (fcm_config, event_bus, device_config) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard
+                .app_config
+                .notifications
+                .as_ref()
+                .and_then(|n| n.fcm.clone()),
+            state_guard.event_bus.clone(),
+            state_guard.app_config.device.clone(),
+        )
+    };
+    let Some(fcm_config) = fcm_config else {
+        return;
+    };
+
+    let sender = match FcmSender::from_config(&fcm_config) {
+        Ok(sender) => Arc::new(Mutex::new(sender)),
+        Err(e) => {
+            error!("Push notifications disabled: {}", e);
+            return;
+        }
+    };
+    info!("FCM push notifications enabled");
+
+    supervisor::spawn_supervised(app_state, "push_notifier", move || {
+        let sender = Arc::clone(&sender);
+        let device_config = device_config.clone();
+        let mut events = event_bus.subscribe();
+        async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Push notifier skipped {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let Some((title, body)) = notification_for(&event) else {
+                    continue;
+                };
+                let title = device_title(device_config.as_ref(), title);
+
+                let mut sender = sender.lock().await;
+                for device in load_devices() {
+                    match sender.send(&device.token, &title, &body).await {
+                        Ok(true) => debug!("Push notification sent: {}", title),
+                        Ok(false) => {
+                            info!("Removing unregistered push notification device");
+                            if let Err(e) = unregister_device(&device.token) {
+                                warn!("{}", e);
+                            }
+                        }
+                        Err(e) => warn!("Failed to send push notification: {}", e),
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind, status: Option<DispenserStatus>) -> DispenserEvent {
+        DispenserEvent {
+            kind,
+            message: "Treats dispensed (trigger: api-user)".to_string(),
+            timestamp: "2025-01-01 12:00:00".to_string(),
+            status,
+            progress: None,
+            trigger: None,
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        }
+    }
+
+    #[test]
+    fn test_notification_for_event() {
+        assert_eq!(
+            notification_for(&event(EventKind::Dispensed, None)).map(|n| n.0),
+            Some("Treats dispensed")
+        );
+        assert_eq!(
+            notification_for(&event(
+                EventKind::StatusChanged,
+                Some(DispenserStatus::Jammed)
+            ))
+            .map(|n| n.0),
+            Some("Dispenser jammed")
+        );
+        assert!(
+            notification_for(&event(
+                EventKind::StatusChanged,
+                Some(DispenserStatus::Cooldown)
+            ))
+            .is_none()
+        );
+        assert!(notification_for(&event(EventKind::BackupCompleted, None)).is_none());
+    }
+
+    #[test]
+    fn test_device_title() {
+        let device = DeviceConfig {
+            name: "barn-feeder".to_string(),
+            location: None,
+            fleet_id: Some("farm".to_string()),
+        };
+        assert_eq!(
+            device_title(Some(&device), "Dispenser empty"),
+            "barn-feeder: Dispenser empty"
+        );
+        assert_eq!(device_title(None, "Dispenser empty"), "Dispenser empty");
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::application_state::AppStateMutex;
+use crate::config::{self, AppConfig, FeedingScheduleConfig};
+use crate::error::{ApiError, FieldError};
+use crate::services::{scheduler, supervisor};
+use crate::utils::filesystem;
+
+/// Every feeding schedule as one document, served and replaced by `/schedules/export`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleSet {
+    pub schedules: Vec<FeedingScheduleConfig>,
+}
+
+/// Document formats of `/schedules/export`, the same content either way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleFormat {
+    Json,
+    Yaml,
+}
+
+impl ScheduleFormat {
+    /// YAML for media types like `application/yaml` or `text/yaml`, JSON otherwise.
+    pub fn from_media_type(media_type: Option<&str>) -> Self {
+        if media_type.is_some_and(|media_type| media_type.contains("yaml")) {
+            ScheduleFormat::Yaml
+        } else {
+            ScheduleFormat::Json
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ScheduleFormat::Json => "application/json",
+            ScheduleFormat::Yaml => "application/yaml",
+        }
+    }
+
+    pub fn serialize(&self, set: &ScheduleSet) -> Result<String, String> {
+        match self {
+            ScheduleFormat::Json => serde_json::to_string_pretty(set).map_err(|e| e.to_string()),
+            ScheduleFormat::Yaml => serde_yaml::to_string(set).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn deserialize(&self, document: &[u8]) -> Result<ScheduleSet, String> {
+        match self {
+            ScheduleFormat::Json => serde_json::from_slice(document).map_err(|e| e.to_string()),
+            ScheduleFormat::Yaml => serde_yaml::from_slice(document).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Summary of `PUT /schedules/export`.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct ScheduleImportSummary {
+    pub schedules: usize,
+    /// Conflicts with the dispense limits over the next days, as `--check-schedules`
+    /// reports them
+    pub conflicts: Vec<String>,
+}
+
+/// Replaces the `schedules` section with the saved schedules, if any were saved. Runs at
+/// startup, after the persisted file check.
+pub fn apply_saved_schedules(app_config: &mut AppConfig) {
+    let path = filesystem::get_schedules_file_path();
+    if !Path::new(&path).exists() {
+        return;
+    }
+    match filesystem::read_json_from_file::<ScheduleSet>(&path) {
+        Ok(set) => {
+            info!(
+                "Using {} feeding schedules from {} instead of the config",
+                set.schedules.len(),
+                path
+            );
+            app_config.schedules = Some(set.schedules);
+        }
+        Err(e) => warn!("Ignoring saved feeding schedules in {}: {}", path, e),
+    }
+}
+
+pub async fn export(app_state: &AppStateMutex) -> ScheduleSet {
+    ScheduleSet {
+        schedules: app_state
+            .lock()
+            .await
+            .app_config
+            .schedules
+            .clone()
+            .unwrap_or_default(),
+    }
+}
+
+/// Checks every schedule, also the disabled ones, and names must be unique.
+fn validate(set: &ScheduleSet, app_config: &AppConfig) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut names = HashSet::new();
+    for (i, schedule) in set.schedules.iter().enumerate() {
+        let field = format!("schedules.{}", i);
+        if !names.insert(schedule.name.as_str()) {
+            errors.push(FieldError::new(
+                &format!("{}.name", field),
+                format!("'{}' is used by another schedule", schedule.name),
+            ));
+        }
+        if let Err(e) = scheduler::validate(schedule, app_config) {
+            errors.push(FieldError::new(&field, e));
+        }
+    }
+    errors
+}
+
+/// Saves `set` in place of the configured schedules and restarts the scheduler with it.
+/// Nothing is saved if any schedule is invalid.
+pub async fn import(
+    app_state: &AppStateMutex,
+    set: ScheduleSet,
+    user: &str,
+) -> Result<ScheduleImportSummary, ApiError> {
+    let restart = {
+        let mut state_guard = app_state.lock().await;
+        let errors = validate(&set, &state_guard.app_config);
+        if !errors.is_empty() {
+            return Err(ApiError::Validation(errors));
+        }
+        filesystem::save_json_to_file(&filesystem::get_schedules_file_path(), &set)
+            .map_err(|e| ApiError::Internal(format!("Failed to save schedules: {}", e)))?;
+        state_guard.app_config.schedules = Some(set.schedules.clone());
+        supervisor::running_tasks(&state_guard)
+            .contains(&"scheduler")
+            .then(|| supervisor::request_restart(&state_guard, "scheduler"))
+    };
+    info!(
+        "{} feeding schedules replaced by {}",
+        set.schedules.len(),
+        user
+    );
+    match restart {
+        Some(result) => result.map_err(ApiError::Internal)?,
+        None => scheduler::start_scheduler(app_state).await,
+    }
+
+    let app_config = app_state.lock().await.app_config.clone();
+    let report = scheduler::dry_run(
+        &app_config,
+        chrono::Local::now(),
+        config::SCHEDULE_DRY_RUN_DAYS,
+    );
+    Ok(ScheduleImportSummary {
+        schedules: set.schedules.len(),
+        conflicts: report.conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let yaml = r#"
+schedules:
+  - name: breakfast
+    at: ["07:30"]
+    pieces: 3
+  - name: weekday lunch
+    cron: "0 12 * * 1-5"
+    enabled: false
+"#;
+        let set = ScheduleFormat::Yaml.deserialize(yaml.as_bytes()).unwrap();
+        assert_eq!(set.schedules.len(), 2);
+        assert_eq!(set.schedules[1].enabled, Some(false));
+
+        for format in [ScheduleFormat::Json, ScheduleFormat::Yaml] {
+            let document = format.serialize(&set).unwrap();
+            let parsed = format.deserialize(document.as_bytes()).unwrap();
+            assert_eq!(
+                serde_json::to_value(&parsed).unwrap(),
+                serde_json::to_value(&set).unwrap()
+            );
+        }
+
+        assert_eq!(
+            ScheduleFormat::from_media_type(Some("text/yaml; charset=utf-8")),
+            ScheduleFormat::Yaml
+        );
+        assert_eq!(ScheduleFormat::from_media_type(None), ScheduleFormat::Json);
+        assert!(
+            ScheduleFormat::Json
+                .deserialize(br#"{"schedule": []}"#)
+                .is_err()
+        );
+    }
+}
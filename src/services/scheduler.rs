@@ -0,0 +1,740 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeDelta, Timelike};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{self, AppConfig, FeedingScheduleConfig};
+use crate::services::dispenser::{DispenseAmount, DispenseRequest, TriggerSource};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::services::triggers::{self, Trigger, TriggerLog};
+use crate::utils::datetime;
+
+/// Longest the scheduler sleeps before looking at the clock again, so it catches up
+/// with clock changes, e.g. once NTP synced after boot.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// The next feeding, reported in `/status`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct NextScheduledDispense {
+    pub schedule: String,
+    pub time: String,
+    /// How the amount is measured: `default`, `degrees`, `pieces`, `grams` or `trickle`.
+    /// Of the first schedule if several feed at the same time.
+    pub profile: String,
+    /// Degrees, pieces or grams as `profile` says, the portion drawn with `randomize`.
+    /// Not set for `default`, which turns `motor.dispense_degrees`.
+    pub amount: Option<f32>,
+}
+
+/// A configured schedule with its times parsed.
+struct FeedingSchedule {
+    name: String,
+    crons: Vec<Cron>,
+    amount: DispenseAmount,
+    note: Option<String>,
+    /// Portion range drawn from for every feeding, replaces `amount`
+    grams_range: Option<(f32, f32)>,
+    jitter_secs: i64,
+    /// Lead time of the `feeding_upcoming` event
+    warning: Option<TimeDelta>,
+}
+
+/// The next feeding of a schedule, with the time and portion drawn for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PlannedFeeding {
+    /// Time from the schedule, before the jitter
+    nominal: DateTime<Local>,
+    time: DateTime<Local>,
+    amount: DispenseAmount,
+}
+
+impl FeedingSchedule {
+    fn parse(schedule: &FeedingScheduleConfig, app_config: &AppConfig) -> Result<Self, String> {
+        let mut patterns = Vec::new();
+        for time in schedule.at.iter().flatten() {
+            let time = NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|e| format!("Invalid time '{}': {}", time, e))?;
+            patterns.push(format!("{} {} * * *", time.minute(), time.hour()));
+        }
+        patterns.extend(schedule.cron.clone());
+        if patterns.is_empty() {
+            return Err("Set `at` or `cron`".to_string());
+        }
+        let crons = patterns
+            .iter()
+            .map(|pattern| {
+                Cron::new(pattern)
+                    .parse()
+                    .map_err(|e| format!("Invalid cron expression '{}': {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let request = DispenseRequest {
+            degrees: schedule.degrees,
+            pieces: schedule.pieces,
+            note: schedule.note.clone(),
+        };
+        request.validate(app_config).map_err(|e| e.to_string())?;
+
+        let randomize = schedule.randomize.as_ref();
+        let grams_range = match randomize.map(|r| (r.min_grams, r.max_grams)) {
+            None | Some((None, None)) => None,
+            Some((Some(min), Some(max))) => {
+                if schedule.pieces.is_some() || schedule.degrees.is_some() {
+                    return Err("A portion range can't be combined with pieces or degrees".to_string());
+                }
+                if !(min > 0.0 && min <= max && max <= config::DISPENSE_GRAMS_MAX) {
+                    return Err(format!(
+                        "Portion range must be greater than 0 and at most {} g, with min_grams at most max_grams",
+                        config::DISPENSE_GRAMS_MAX
+                    ));
+                }
+                Some((min, max))
+            }
+            Some(_) => return Err("Set both min_grams and max_grams".to_string()),
+        };
+        let jitter_minutes = randomize.and_then(|r| r.jitter_minutes).unwrap_or(0);
+        if jitter_minutes > config::SCHEDULE_JITTER_MINUTES_MAX {
+            return Err(format!(
+                "jitter_minutes must be at most {}",
+                config::SCHEDULE_JITTER_MINUTES_MAX
+            ));
+        }
+        if schedule
+            .warn_minutes_before
+            .is_some_and(|minutes| minutes > config::SCHEDULE_WARNING_MINUTES_MAX)
+        {
+            return Err(format!(
+                "warn_minutes_before must be at most {}",
+                config::SCHEDULE_WARNING_MINUTES_MAX
+            ));
+        }
+
+        Ok(FeedingSchedule {
+            name: schedule.name.clone(),
+            crons,
+            amount: request.amount(),
+            note: request.note(),
+            grams_range,
+            jitter_secs: jitter_minutes as i64 * 60,
+            warning: schedule
+                .warn_minutes_before
+                .map(|minutes| TimeDelta::minutes(minutes as i64)),
+        })
+    }
+
+    fn next_after(&self, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+        self.crons
+            .iter()
+            .filter_map(|cron| cron.find_next_occurrence(after, false).ok())
+            .min()
+    }
+
+    fn is_randomized(&self) -> bool {
+        self.grams_range.is_some() || self.jitter_secs > 0
+    }
+
+    /// Plans the first feeding after `after`, drawing its time and portion. The time is
+    /// moved to `not_before` if the jitter would put it earlier, so feedings keep their
+    /// order and never lie in the past.
+    fn plan_after(
+        &self,
+        after: &DateTime<Local>,
+        not_before: DateTime<Local>,
+    ) -> Option<PlannedFeeding> {
+        let nominal = self.next_after(after)?;
+        let offset = match self.jitter_secs {
+            0 => 0,
+            jitter => rand::random_range(-jitter..=jitter),
+        };
+        let amount = match self.grams_range {
+            // to 0.1 g, for readable logs
+            Some((min, max)) => {
+                let grams = (rand::random_range(min..=max) * 10.0).round() / 10.0;
+                DispenseAmount::Grams(grams.clamp(min, max))
+            }
+            None => self.amount,
+        };
+        Some(PlannedFeeding {
+            nominal,
+            time: (nominal + TimeDelta::seconds(offset)).max(not_before),
+            amount,
+        })
+    }
+}
+
+struct ScheduleTrigger {
+    name: String,
+    amount: DispenseAmount,
+    note: Option<String>,
+}
+
+impl Trigger for ScheduleTrigger {
+    fn source(&self) -> TriggerSource {
+        TriggerSource::Schedule
+    }
+
+    fn name(&self) -> String {
+        format!("schedule '{}'", self.name)
+    }
+
+    fn amount(&self) -> DispenseAmount {
+        self.amount
+    }
+
+    fn note(&self) -> Option<String> {
+        self.note.clone()
+    }
+}
+
+/// Parses the enabled schedules, logging and leaving out invalid ones.
+/// Checks a schedule like the scheduler does when it starts.
+pub(crate) fn validate(schedule: &FeedingScheduleConfig, app_config: &AppConfig) -> Result<(), String> {
+    FeedingSchedule::parse(schedule, app_config).map(|_| ())
+}
+
+fn parse_schedules(app_config: &AppConfig) -> Vec<FeedingSchedule> {
+    app_config
+        .schedules
+        .iter()
+        .flatten()
+        .filter(|schedule| schedule.enabled.unwrap_or(true))
+        .filter_map(
+            |schedule| match FeedingSchedule::parse(schedule, app_config) {
+                Ok(parsed) => Some(parsed),
+                Err(e) => {
+                    error!("Ignoring feeding schedule '{}': {}", schedule.name, e);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// The earliest planned time and the indices of the schedules due then.
+fn next_due(plans: &[Option<PlannedFeeding>]) -> Option<(DateTime<Local>, Vec<usize>)> {
+    let time = plans.iter().flatten().map(|plan| plan.time).min()?;
+    let due = plans
+        .iter()
+        .enumerate()
+        .filter(|(_, plan)| plan.is_some_and(|plan| plan.time == time))
+        .map(|(i, _)| i)
+        .collect();
+    Some((time, due))
+}
+
+/// A feeding of a dry run and whether the trigger limits would let it through.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DryRunFeeding {
+    pub time: String,
+    pub schedule: String,
+    /// Why the feeding would be rejected
+    pub rejected: Option<String>,
+}
+
+/// The feedings the schedules would start over the next days, checked against the
+/// `triggers` limits.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DryRunReport {
+    pub from: String,
+    pub until: String,
+    pub feedings: Vec<DryRunFeeding>,
+    /// Rejected feedings, and days on which the schedules use up the daily limit
+    pub conflicts: Vec<String>,
+    /// Schedules left out because they are invalid
+    pub ignored: Vec<String>,
+}
+
+/// Plays the enabled schedules from `from` for `days` days against the quiet hours,
+/// daily limit, minimum interval and disabled triggers, as if nothing else dispensed.
+/// Randomized feedings are checked at their time before the jitter.
+pub fn dry_run(app_config: &AppConfig, from: DateTime<Local>, days: i64) -> DryRunReport {
+    let until = from + TimeDelta::days(days);
+    let mut ignored = Vec::new();
+    let schedules: Vec<FeedingSchedule> = app_config
+        .schedules
+        .iter()
+        .flatten()
+        .filter(|schedule| schedule.enabled.unwrap_or(true))
+        .filter_map(
+            |schedule| match FeedingSchedule::parse(schedule, app_config) {
+                Ok(parsed) => Some(parsed),
+                Err(e) => {
+                    ignored.push(format!("Schedule '{}': {}", schedule.name, e));
+                    None
+                }
+            },
+        )
+        .collect();
+
+    let mut due: Vec<(DateTime<Local>, usize)> = Vec::new();
+    for (i, schedule) in schedules.iter().enumerate() {
+        let mut after = from;
+        while let Some(time) = schedule.next_after(&after).filter(|time| *time < until) {
+            due.push((time, i));
+            after = time;
+        }
+    }
+    due.sort();
+
+    let triggers_config = app_config.triggers.as_ref();
+    let coalesce = triggers_config
+        .and_then(|c| c.arbitration.as_ref())
+        .and_then(|a| a.coalesce_window_ms)
+        .is_some_and(|ms| ms > 0);
+    let mut trigger_log = TriggerLog::default();
+    let mut accepted_per_day: HashMap<NaiveDate, u32> = HashMap::new();
+    let start = Instant::now();
+    let mut feedings = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for group in due.chunk_by(|a, b| a.0 == b.0) {
+        let time = group[0].0;
+        let instant = start + (time - from).to_std().unwrap_or_default();
+        let triggers: Vec<ScheduleTrigger> = group
+            .iter()
+            .map(|(_, i)| ScheduleTrigger {
+                name: schedules[*i].name.clone(),
+                amount: schedules[*i].amount,
+                note: None,
+            })
+            .collect();
+        // merged into one dispense by arbitration, otherwise one after the other
+        let dispenses: Vec<&[ScheduleTrigger]> = if coalesce {
+            vec![&triggers[..]]
+        } else {
+            triggers.chunks(1).collect()
+        };
+
+        for dispense in dispenses {
+            let rejected = match trigger_log.check(
+                &dispense[0],
+                triggers_config,
+                time.naive_local(),
+                instant,
+            ) {
+                Ok(()) => {
+                    trigger_log.record(
+                        dispense.iter().map(|t| t.name.as_str()),
+                        time.naive_local(),
+                        instant,
+                    );
+                    let accepted = accepted_per_day.entry(time.date_naive()).or_default();
+                    *accepted += 1;
+                    if let Some(limit) = triggers_config.and_then(|c| c.daily_limit)
+                        && *accepted == limit
+                    {
+                        conflicts.push(format!(
+                            "{}: the daily limit of {} dispenses is used up by schedules at {}, other triggers are rejected for the rest of the day",
+                            time.format("%Y-%m-%d"),
+                            limit,
+                            time.format("%H:%M")
+                        ));
+                    }
+                    None
+                }
+                Err(e) => Some(e.to_string()),
+            };
+            for trigger in dispense {
+                if let Some(reason) = &rejected {
+                    conflicts.push(format!(
+                        "{}: {} rejected, {}",
+                        datetime::format_system_time(time.into()),
+                        trigger.name(),
+                        reason
+                    ));
+                }
+                feedings.push(DryRunFeeding {
+                    time: datetime::format_system_time(time.into()),
+                    schedule: trigger.name.clone(),
+                    rejected: rejected.clone(),
+                });
+            }
+        }
+    }
+
+    DryRunReport {
+        from: datetime::format_system_time(from.into()),
+        until: datetime::format_system_time(until.into()),
+        feedings,
+        conflicts,
+        ignored,
+    }
+}
+
+/// Starts dispensing at the times of the `schedules` section. A feeding that finds the
+/// dispenser busy, e.g. in its cooldown, waits up to `SCHEDULE_BUSY_WAIT_SECS` for it.
+/// Feedings go through the trigger limits like any other trigger, and feedings missed
+/// while the service wasn't running are not caught up.
+/// Conflicts with the trigger limits over the next `SCHEDULE_DRY_RUN_DAYS` are logged.
+pub async fn start_scheduler(app_state: &Arc<Mutex<ApplicationState>>) {
+    let app_config = app_state.lock().await.app_config.clone();
+    let count = parse_schedules(&app_config).len();
+    if count == 0 {
+        return;
+    }
+    info!("Starting feeding scheduler with {} schedules", count);
+    let report = dry_run(&app_config, Local::now(), config::SCHEDULE_DRY_RUN_DAYS);
+    for conflict in &report.conflicts {
+        warn!("Schedule conflict {}", conflict);
+    }
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "scheduler", move || {
+        let app_state = Arc::clone(&app_state_clone);
+        // read on every start, so a restart picks up replaced schedules
+        async move {
+            let schedules = parse_schedules(&app_state.lock().await.app_config);
+            run_scheduler(app_state, schedules).await
+        }
+    });
+}
+
+/// Indices of the planned feedings whose warning is due at `now` and hasn't been published
+/// yet, and the time of the next warning after that.
+fn due_warnings(
+    schedules: &[FeedingSchedule],
+    plans: &[Option<PlannedFeeding>],
+    warned: &[bool],
+    now: DateTime<Local>,
+) -> (Vec<usize>, Option<DateTime<Local>>) {
+    let mut due = Vec::new();
+    let mut next = None;
+    for (i, plan) in plans.iter().enumerate() {
+        let (Some(plan), Some(warning), false) = (plan, schedules[i].warning, warned[i]) else {
+            continue;
+        };
+        let warn_at = plan.time - warning;
+        if warn_at <= now {
+            due.push(i);
+        } else if next.is_none_or(|next| warn_at < next) {
+            next = Some(warn_at);
+        }
+    }
+    (due, next)
+}
+
+async fn run_scheduler(app_state: Arc<Mutex<ApplicationState>>, schedules: Vec<FeedingSchedule>) {
+    let now = Local::now();
+    let mut plans: Vec<Option<PlannedFeeding>> = schedules
+        .iter()
+        .map(|schedule| schedule.plan_after(&now, now))
+        .collect();
+    // set once the upcoming feeding of a schedule was announced, until it's planned anew
+    let mut warned = vec![false; schedules.len()];
+    while let Some((time, due)) = next_due(&plans) {
+        let amount = plans[due[0]].map_or(DispenseAmount::Default, |plan| plan.amount);
+        let next = NextScheduledDispense {
+            schedule: due
+                .iter()
+                .map(|i| schedules[*i].name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            time: datetime::format_system_time(time.into()),
+            profile: amount.profile().to_string(),
+            amount: amount.quantity(),
+        };
+        app_state.lock().await.next_scheduled_dispense = Some(next);
+
+        let now = Local::now();
+        let (warnings, next_warning) = due_warnings(&schedules, &plans, &warned, now);
+        for i in warnings {
+            if let Some(plan) = &plans[i] {
+                let message = format!(
+                    "Feeding by schedule '{}' at {}, {}",
+                    schedules[i].name,
+                    datetime::format_system_time(plan.time.into()),
+                    plan.amount
+                );
+                info!("{}", message);
+                app_state
+                    .lock()
+                    .await
+                    .event_bus
+                    .publish(EventKind::FeedingUpcoming, message);
+            }
+            warned[i] = true;
+        }
+
+        let wait = (time - now).to_std().unwrap_or_default();
+        if !wait.is_zero() {
+            let warning_wait = next_warning
+                .and_then(|warn_at| (warn_at - now).to_std().ok())
+                .unwrap_or(MAX_SLEEP);
+            tokio::time::sleep(wait.min(warning_wait).min(MAX_SLEEP)).await;
+            continue;
+        }
+
+        for i in due {
+            let schedule = &schedules[i];
+            let Some(plan) = plans[i].take() else {
+                continue;
+            };
+            if schedule.is_randomized() {
+                info!(
+                    "Randomized feeding by schedule '{}': {:?} at {} ({:+} min from {})",
+                    schedule.name,
+                    plan.amount,
+                    datetime::format_system_time(plan.time.into()),
+                    (plan.time - plan.nominal).num_minutes(),
+                    datetime::format_system_time(plan.nominal.into())
+                );
+            }
+            let trigger = ScheduleTrigger {
+                name: schedule.name.clone(),
+                amount: plan.amount,
+                note: schedule.note.clone(),
+            };
+            // in a task of its own, so waiting for a busy dispenser doesn't hold up the
+            // other schedules
+            tokio::spawn(feed(Arc::clone(&app_state), trigger));
+            plans[i] = schedule.plan_after(&plan.nominal, time);
+            warned[i] = false;
+        }
+    }
+    app_state.lock().await.next_scheduled_dispense = None;
+}
+
+async fn feed(app_state: Arc<Mutex<ApplicationState>>, trigger: ScheduleTrigger) {
+    let mut status_rx = app_state.lock().await.dispenser_status_tx.subscribe();
+    let not_busy = status_rx.wait_for(|status| {
+        !matches!(
+            status,
+            DispenserStatus::Dispensing
+                | DispenserStatus::Cooldown
+                | DispenserStatus::Stirring
+                | DispenserStatus::Calibrating
+                | DispenserStatus::MeasuringCurrent
+        )
+    });
+    let busy_wait = Duration::from_secs(config::SCHEDULE_BUSY_WAIT_SECS);
+    if tokio::time::timeout(busy_wait, not_busy).await.is_err() {
+        warn!(
+            "Dispenser still busy after {} s, trying {} anyway",
+            busy_wait.as_secs(),
+            trigger.name()
+        );
+    }
+
+    info!("Scheduled feeding by {}", trigger.name());
+    if let Err(e) = triggers::request_dispense(&app_state, &trigger).await {
+        warn!("Scheduled feeding by {} failed: {}", trigger.name(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(name: &str, at: Option<&str>, cron: Option<&str>) -> FeedingScheduleConfig {
+        FeedingScheduleConfig {
+            name: name.to_string(),
+            at: at.map(|time| vec![time.to_string()]),
+            cron: cron.map(str::to_string),
+            pieces: None,
+            degrees: None,
+            note: None,
+            enabled: None,
+            randomize: None,
+            warn_minutes_before: None,
+        }
+    }
+
+    fn app_config() -> AppConfig {
+        config::load_app_config_from_str(
+            r#"
+            api:
+              listen_address: "127.0.0.1:0"
+              admin_user: "admin"
+              admin_password: "password"
+            motor:
+              motor_type: "StepperMock"
+            power_monitor:
+              sensor: "SensorMock"
+            weight_monitor:
+              sensor: "SensorMock"
+            "#,
+        )
+    }
+
+    #[test]
+    fn test_next_due() {
+        let app_config = app_config();
+        let schedules: Vec<FeedingSchedule> = [
+            schedule("breakfast", Some("07:30"), None),
+            schedule("weekdays", None, Some("30 7 * * 1-5")),
+            schedule("dinner", Some("18:00"), None),
+        ]
+        .iter()
+        .map(|s| FeedingSchedule::parse(s, &app_config).unwrap())
+        .collect();
+        let plans = |after: DateTime<Local>| -> Vec<Option<PlannedFeeding>> {
+            schedules
+                .iter()
+                .map(|schedule| schedule.plan_after(&after, after))
+                .collect()
+        };
+
+        // 2025-01-06 is a Monday
+        let monday_noon = Local.with_ymd_and_hms(2025, 1, 6, 12, 0, 0).unwrap();
+        let (time, due) = next_due(&plans(monday_noon)).unwrap();
+        assert_eq!(time, Local.with_ymd_and_hms(2025, 1, 6, 18, 0, 0).unwrap());
+        assert_eq!(due.len(), 1);
+        assert_eq!(schedules[due[0]].name, "dinner");
+
+        let (time, due) = next_due(&plans(time)).unwrap();
+        assert_eq!(time, Local.with_ymd_and_hms(2025, 1, 7, 7, 30, 0).unwrap());
+        assert_eq!(due.len(), 2);
+
+        let saturday = Local.with_ymd_and_hms(2025, 1, 11, 0, 0, 0).unwrap();
+        let (_, due) = next_due(&plans(saturday)).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(schedules[due[0]].name, "breakfast");
+
+        assert!(FeedingSchedule::parse(&schedule("bad", Some("7:3x"), None), &app_config).is_err());
+        assert!(FeedingSchedule::parse(&schedule("bad", None, Some("* *")), &app_config).is_err());
+        assert!(FeedingSchedule::parse(&schedule("empty", None, None), &app_config).is_err());
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let app_config = config::load_app_config_from_str(
+            r#"
+            api:
+              listen_address: "127.0.0.1:0"
+              admin_user: "admin"
+              admin_password: "password"
+            motor:
+              motor_type: "StepperMock"
+            power_monitor:
+              sensor: "SensorMock"
+            weight_monitor:
+              sensor: "SensorMock"
+            triggers:
+              quiet_hours:
+                - start: "22:00"
+                  end: "06:00"
+              daily_limit: 2
+            schedules:
+              - name: "breakfast"
+                at: ["07:00"]
+              - name: "lunch"
+                at: ["12:00"]
+              - name: "snack"
+                at: ["15:00"]
+              - name: "midnight snack"
+                at: ["23:00"]
+              - name: "broken"
+                at: ["25:00"]
+            "#,
+        );
+        let from = Local.with_ymd_and_hms(2025, 1, 6, 0, 0, 0).unwrap();
+        let report = dry_run(&app_config, from, 2);
+
+        assert_eq!(report.feedings.len(), 8);
+        assert_eq!(report.ignored.len(), 1);
+        assert!(report.ignored[0].contains("broken"));
+        let rejected: Vec<(&str, &str)> = report
+            .feedings
+            .iter()
+            .filter_map(|f| f.rejected.as_deref().map(|r| (f.time.as_str(), r)))
+            .collect();
+        assert_eq!(rejected.len(), 4);
+        assert_eq!(rejected[0].0, "2025-01-06 15:00:00");
+        assert!(rejected[0].1.contains("Daily limit of 2"));
+        assert_eq!(rejected[1].0, "2025-01-06 23:00:00");
+        assert!(rejected[1].1.contains("Quiet hours 22:00-06:00"));
+        // 4 rejected feedings, and the daily limit used up at noon on both days
+        assert_eq!(report.conflicts.len(), 6);
+        assert!(report.conflicts[0].starts_with("2025-01-06: the daily limit of 2"));
+        assert!(report.conflicts[0].contains("at 12:00"));
+    }
+
+    #[test]
+    fn test_randomized_feedings() {
+        let app_config = app_config();
+        let mut config = schedule("breakfast", Some("07:30"), None);
+        config.randomize = Some(config::ScheduleRandomizationConfig {
+            min_grams: Some(3.0),
+            max_grams: Some(7.0),
+            jitter_minutes: Some(20),
+        });
+        let schedule_parsed = FeedingSchedule::parse(&config, &app_config).unwrap();
+
+        let evening = Local.with_ymd_and_hms(2025, 1, 6, 20, 0, 0).unwrap();
+        let nominal = Local.with_ymd_and_hms(2025, 1, 7, 7, 30, 0).unwrap();
+        for _ in 0..100 {
+            let plan = schedule_parsed.plan_after(&evening, evening).unwrap();
+            assert_eq!(plan.nominal, nominal);
+            assert!((plan.time - nominal).num_seconds().abs() <= 20 * 60);
+            match plan.amount {
+                DispenseAmount::Grams(grams) => assert!((3.0..=7.0).contains(&grams)),
+                other => panic!("expected grams, got {:?}", other),
+            }
+        }
+        // never before the previous feeding or now
+        let not_before = Local.with_ymd_and_hms(2025, 1, 7, 7, 25, 0).unwrap();
+        for _ in 0..100 {
+            let plan = schedule_parsed.plan_after(&evening, not_before).unwrap();
+            assert!(plan.time >= not_before);
+        }
+
+        let invalid = |min_grams, max_grams, degrees| {
+            let mut config = schedule("bad", Some("07:30"), None);
+            config.degrees = degrees;
+            config.randomize = Some(config::ScheduleRandomizationConfig {
+                min_grams,
+                max_grams,
+                jitter_minutes: None,
+            });
+            FeedingSchedule::parse(&config, &app_config).is_err()
+        };
+        assert!(invalid(Some(7.0), Some(3.0), None));
+        assert!(invalid(Some(3.0), None, None));
+        assert!(invalid(Some(3.0), Some(7.0), Some(90.0)));
+        assert!(!invalid(None, None, None));
+    }
+
+    #[test]
+    fn test_due_warnings() {
+        let app_config = app_config();
+        let mut warned_schedule = schedule("breakfast", Some("07:30"), None);
+        warned_schedule.warn_minutes_before = Some(10);
+        let schedules: Vec<FeedingSchedule> =
+            [warned_schedule, schedule("dinner", Some("18:00"), None)]
+                .iter()
+                .map(|s| FeedingSchedule::parse(s, &app_config).unwrap())
+                .collect();
+        let midnight = Local.with_ymd_and_hms(2025, 1, 6, 0, 0, 0).unwrap();
+        let plans: Vec<Option<PlannedFeeding>> = schedules
+            .iter()
+            .map(|schedule| schedule.plan_after(&midnight, midnight))
+            .collect();
+
+        let warn_at = Local.with_ymd_and_hms(2025, 1, 6, 7, 20, 0).unwrap();
+        assert_eq!(
+            due_warnings(&schedules, &plans, &[false, false], midnight),
+            (vec![], Some(warn_at))
+        );
+        assert_eq!(
+            due_warnings(&schedules, &plans, &[false, false], warn_at),
+            (vec![0], None)
+        );
+        // published once per feeding
+        assert_eq!(
+            due_warnings(&schedules, &plans, &[true, false], warn_at),
+            (vec![], None)
+        );
+
+        let mut too_early = schedule("bad", Some("07:30"), None);
+        too_early.warn_minutes_before = Some(config::SCHEDULE_WARNING_MINUTES_MAX + 1);
+        assert!(FeedingSchedule::parse(&too_early, &app_config).is_err());
+    }
+}
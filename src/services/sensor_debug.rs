@@ -0,0 +1,139 @@
+use futures::Stream;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config;
+use crate::error::{ApiError, FieldError};
+
+/// A single unfiltered sensor sample, serialized as one NDJSON line.
+#[derive(Serialize, Debug)]
+pub struct RawSample<T: Serialize> {
+    /// Milliseconds since the start of the capture
+    pub elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Capture window and sample interval of a raw sensor stream.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureSettings {
+    pub duration: Duration,
+    pub interval: Duration,
+}
+
+impl CaptureSettings {
+    /// Applies defaults and checks the bounds, capture requests are capped so a
+    /// forgotten client can't keep the sensor busy.
+    pub fn from_query(
+        duration_secs: Option<u64>,
+        interval_ms: Option<u64>,
+    ) -> Result<Self, ApiError> {
+        let duration_secs = duration_secs.unwrap_or(config::RAW_DEBUG_DURATION_SECS_DEFAULT);
+        let interval_ms = interval_ms.unwrap_or(config::RAW_DEBUG_INTERVAL_MS_DEFAULT);
+
+        let mut errors = Vec::new();
+        if !(1..=config::RAW_DEBUG_DURATION_SECS_MAX).contains(&duration_secs) {
+            errors.push(FieldError::new(
+                "duration_secs",
+                format!(
+                    "must be between 1 and {}",
+                    config::RAW_DEBUG_DURATION_SECS_MAX
+                ),
+            ));
+        }
+        if !(config::RAW_DEBUG_INTERVAL_MS_MIN..=config::RAW_DEBUG_INTERVAL_MS_MAX)
+            .contains(&interval_ms)
+        {
+            errors.push(FieldError::new(
+                "interval_ms",
+                format!(
+                    "must be between {} and {}",
+                    config::RAW_DEBUG_INTERVAL_MS_MIN,
+                    config::RAW_DEBUG_INTERVAL_MS_MAX
+                ),
+            ));
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::Validation(errors));
+        }
+
+        Ok(CaptureSettings {
+            duration: Duration::from_secs(duration_secs),
+            interval: Duration::from_millis(interval_ms),
+        })
+    }
+}
+
+/// Samples `read` every `interval` until `duration` has elapsed, yielding one JSON
+/// line per sample. Read errors are included in the stream rather than ending it,
+/// intermittent failures are often exactly what is being diagnosed.
+pub fn stream_raw_samples<T, F, Fut>(
+    settings: CaptureSettings,
+    read: F,
+) -> impl Stream<Item = Result<String, Infallible>>
+where
+    T: Serialize,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    let started_at = Instant::now();
+    let deadline = started_at + settings.duration;
+
+    futures::stream::unfold(
+        (read, started_at),
+        move |(read, next_sample_at)| async move {
+            if next_sample_at >= deadline {
+                return None;
+            }
+            tokio::time::sleep_until(next_sample_at).await;
+
+            let result = read().await;
+            let sample = RawSample {
+                elapsed_ms: started_at.elapsed().as_millis() as u64,
+                raw: result.as_ref().ok(),
+                error: result.as_ref().err().cloned(),
+            };
+            let mut line = serde_json::to_string(&sample).unwrap_or_default();
+            line.push('\n');
+
+            // skip ahead instead of bursting if a read took longer than the interval
+            let next = (next_sample_at + settings.interval).max(Instant::now());
+            Some((Ok(line), (read, next)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_stream_raw_samples() {
+        let settings = CaptureSettings {
+            duration: Duration::from_millis(50),
+            interval: Duration::from_millis(10),
+        };
+        let lines: Vec<String> = stream_raw_samples(settings, || async { Ok::<i32, String>(42) })
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert!(!lines.is_empty() && lines.len() <= 5);
+        let first: serde_json::Value = serde_json::from_str(lines[0].trim()).unwrap();
+        assert_eq!(first["raw"], 42);
+        assert!(first.get("error").is_none());
+    }
+
+    #[test]
+    fn test_capture_settings_bounds() {
+        assert!(CaptureSettings::from_query(None, None).is_ok());
+        assert!(CaptureSettings::from_query(Some(0), None).is_err());
+        assert!(CaptureSettings::from_query(Some(10_000), Some(0)).is_err());
+    }
+}
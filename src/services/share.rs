@@ -0,0 +1,302 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::application_state::ApplicationState;
+use crate::config;
+use crate::error::{ApiError, FieldError};
+use crate::services::status;
+use crate::utils::{datetime, filesystem};
+
+/// Body of `POST /share`.
+#[derive(Deserialize, Debug, Default, utoipa::ToSchema)]
+pub struct CreateShareRequest {
+    /// Who the link is for, e.g. `pet-sitter`
+    pub label: Option<String>,
+    /// The link stops working after this, never when unset
+    pub expires_in_hours: Option<u64>,
+}
+
+impl CreateShareRequest {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        if let Some(label) = &self.label
+            && label.chars().count() > config::SHARE_LABEL_MAX_CHARS
+        {
+            errors.push(FieldError::new(
+                "label",
+                format!(
+                    "must be at most {} characters",
+                    config::SHARE_LABEL_MAX_CHARS
+                ),
+            ));
+        }
+        if let Some(hours) = self.expires_in_hours
+            && !(1..=config::SHARE_EXPIRES_IN_HOURS_MAX).contains(&hours)
+        {
+            errors.push(FieldError::new(
+                "expires_in_hours",
+                format!(
+                    "must be between 1 and {}",
+                    config::SHARE_EXPIRES_IN_HOURS_MAX
+                ),
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
+
+/// A status link as listed by `GET /share`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct ShareInfo {
+    pub id: String,
+    pub label: Option<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+}
+
+/// A status link as kept in `status_shares.json`. Only the hash of its token is stored,
+/// the token itself is shown once when the link is created.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusShare {
+    #[serde(flatten)]
+    pub info: ShareInfo,
+    pub token_sha256: String,
+}
+
+/// Returned by `POST /share`.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct CreatedShare {
+    #[serde(flatten)]
+    pub info: ShareInfo,
+    pub token: String,
+    /// Path of the status page, append it to the dispenser's address
+    pub path: String,
+}
+
+/// What a status link shows. Nothing in it allows controlling the dispenser.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct SharedStatus {
+    pub device_name: Option<String>,
+    pub status: String,
+    pub emoji: String,
+    pub text: String,
+    pub last_feeding: Option<String>,
+    pub next_feeding: Option<String>,
+    pub remaining_grams: f32,
+    /// `remaining_grams` in the display unit, e.g. `412 g`
+    pub remaining: String,
+    pub treats_available: bool,
+}
+
+fn token_sha256(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn load_shares() -> Vec<StatusShare> {
+    filesystem::read_json_from_file(&filesystem::get_status_shares_file_path()).unwrap_or_default()
+}
+
+fn save_shares(shares: &[StatusShare]) -> Result<(), ApiError> {
+    filesystem::save_json_to_file(&filesystem::get_status_shares_file_path(), &shares)
+        .map_err(|e| ApiError::Internal(format!("Failed to save status links: {}", e)))
+}
+
+fn is_expired(share: &StatusShare) -> bool {
+    share
+        .info
+        .expires_at
+        .as_ref()
+        .is_some_and(|expires_at| *expires_at <= datetime::get_formatted_current_timestamp())
+}
+
+/// Mints a status link with a new random token. Expired links are dropped on the way.
+pub fn create(request: CreateShareRequest) -> Result<CreatedShare, ApiError> {
+    let token = format!("{:032x}", rand::random::<u128>());
+    let info = ShareInfo {
+        id: format!("{:08x}", rand::random::<u32>()),
+        label: request
+            .label
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty()),
+        created_at: datetime::get_formatted_current_timestamp(),
+        expires_at: request.expires_in_hours.map(|hours| {
+            (Local::now() + chrono::Duration::hours(hours as i64))
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        }),
+    };
+
+    let mut shares = load_shares();
+    shares.retain(|share| !is_expired(share));
+    shares.push(StatusShare {
+        info: info.clone(),
+        token_sha256: token_sha256(&token),
+    });
+    save_shares(&shares)?;
+    Ok(CreatedShare {
+        info,
+        path: format!("/shared/{}", token),
+        token,
+    })
+}
+
+/// Lists the links that haven't expired.
+pub fn list() -> Vec<ShareInfo> {
+    load_shares()
+        .into_iter()
+        .filter(|share| !is_expired(share))
+        .map(|share| share.info)
+        .collect()
+}
+
+/// Revokes a link, returns false if there is none with that ID.
+pub fn revoke(id: &str) -> Result<bool, ApiError> {
+    let mut shares = load_shares();
+    let count = shares.len();
+    shares.retain(|share| share.info.id != id);
+    if shares.len() == count {
+        return Ok(false);
+    }
+    save_shares(&shares)?;
+    Ok(true)
+}
+
+/// Finds the link a token belongs to, unless it expired.
+pub fn find(token: &str) -> Option<ShareInfo> {
+    let token_sha256 = token_sha256(token);
+    load_shares()
+        .into_iter()
+        .find(|share| share.token_sha256 == token_sha256 && !is_expired(share))
+        .map(|share| share.info)
+}
+
+pub async fn get_shared_status(app_state: &Arc<Mutex<ApplicationState>>) -> SharedStatus {
+    let summary = status::get_summary(app_state).await;
+    let full_status = status::get_status(app_state).await;
+    SharedStatus {
+        device_name: full_status.device.map(|device| device.name),
+        status: summary.status,
+        emoji: summary.emoji,
+        text: summary.text,
+        last_feeding: summary.last_dispense,
+        next_feeding: full_status
+            .next_scheduled_dispense
+            .map(|next| format!("{} ({})", next.time, next.schedule)),
+        remaining_grams: summary.remaining_grams,
+        remaining: summary.remaining,
+        treats_available: full_status.treats_available,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders the status as a small page that reloads itself every minute.
+pub fn render_html(shared: &SharedStatus) -> String {
+    let title = escape_html(shared.device_name.as_deref().unwrap_or("Treat Dispenser"));
+    let hopper = if shared.treats_available {
+        escape_html(&shared.remaining)
+    } else {
+        format!("{}, needs a refill", escape_html(&shared.remaining))
+    };
+    let rows = [
+        (
+            "Last feeding",
+            shared.last_feeding.as_deref().unwrap_or("none yet"),
+        ),
+        (
+            "Next feeding",
+            shared.next_feeding.as_deref().unwrap_or("none scheduled"),
+        ),
+    ]
+    .iter()
+    .map(|(name, value)| format!("<tr><th>{}</th><td>{}</td></tr>", name, escape_html(value)))
+    .collect::<String>();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="60">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 28em; margin: 2em auto; padding: 0 1em; }}
+.status {{ font-size: 1.5em; }}
+th {{ text-align: left; padding-right: 1em; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class="status">{emoji} {text}</p>
+<table>
+<tr><th>Hopper</th><td>{hopper}</td></tr>
+{rows}
+</table>
+</body>
+</html>
+"#,
+        title = title,
+        emoji = shared.emoji,
+        text = escape_html(&shared.text),
+        hopper = hopper,
+        rows = rows,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html_escapes() {
+        let shared = SharedStatus {
+            device_name: Some("<script>alert(1)</script>".to_string()),
+            status: "Empty".to_string(),
+            emoji: "🫙".to_string(),
+            text: "Empty".to_string(),
+            last_feeding: Some("2025-01-01 12:00:00".to_string()),
+            next_feeding: None,
+            remaining_grams: 0.0,
+            remaining: "0 g".to_string(),
+            treats_available: false,
+        };
+        let html = render_html(&shared);
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.contains("0 g, needs a refill"));
+        assert!(html.contains("none scheduled"));
+        // the page has no controls
+        assert!(!html.contains("<form") && !html.contains("<button"));
+    }
+
+    #[test]
+    fn test_expired_share() {
+        let share = |expires_at: Option<&str>| StatusShare {
+            info: ShareInfo {
+                id: "0a1b2c3d".to_string(),
+                label: None,
+                created_at: "2025-01-01 12:00:00".to_string(),
+                expires_at: expires_at.map(str::to_string),
+            },
+            token_sha256: token_sha256("secret"),
+        };
+        assert!(is_expired(&share(Some("2025-01-02 12:00:00"))));
+        assert!(!is_expired(&share(Some("2999-01-01 00:00:00"))));
+        assert!(!is_expired(&share(None)));
+    }
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::application_state::ApplicationState;
+use crate::services::dispenser::TriggerSource;
+use crate::services::history::{self, HistoryEntry};
+use crate::services::motor_vibration;
+use crate::utils::{datetime, filesystem};
+
+/// Time for the treats to land and the motor vibration to stop before the hopper is
+/// weighed again. The weight monitor publishes a trimmed mean roughly every 450 ms.
+const DISPENSE_SETTLE_DELAY: Duration = Duration::from_millis(2000);
+
+/// One completed dispense, measured by the weight change of the hopper.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct DispenseRecord {
+    pub time: String,
+    pub trigger: TriggerSource,
+    pub grams: f32,
+    /// Estimated from `weight_monitor.piece_weight_grams`, if configured
+    pub pieces: Option<u32>,
+}
+
+/// Dispense totals, persisted so they survive restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, utoipa::ToSchema)]
+pub struct DispenseStats {
+    pub dispense_count: u64,
+    pub total_grams: f32,
+    pub total_pieces: u64,
+    pub last_dispense: Option<DispenseRecord>,
+    /// Totals per trigger source, keyed like `api-user`
+    #[serde(default)]
+    pub by_trigger: BTreeMap<String, TriggerStats>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, utoipa::ToSchema)]
+pub struct TriggerStats {
+    pub dispense_count: u64,
+    pub total_grams: f32,
+    pub total_pieces: u64,
+}
+
+impl TriggerStats {
+    fn add(&mut self, record: &DispenseRecord) {
+        self.dispense_count += 1;
+        self.total_grams += record.grams;
+        self.total_pieces += record.pieces.unwrap_or(0) as u64;
+    }
+
+    /// Adds `count` dispenses to the totals, negative values take them out.
+    fn adjust(&mut self, count: i64, grams: f32, pieces: i64) {
+        self.dispense_count = self.dispense_count.saturating_add_signed(count);
+        self.total_grams = (self.total_grams + grams).max(0.0);
+        self.total_pieces = self.total_pieces.saturating_add_signed(pieces);
+    }
+}
+
+impl DispenseStats {
+    fn record(&mut self, record: DispenseRecord) {
+        self.dispense_count += 1;
+        self.total_grams += record.grams;
+        self.total_pieces += record.pieces.unwrap_or(0) as u64;
+        self.by_trigger
+            .entry(record.trigger.to_string())
+            .or_default()
+            .add(&record);
+        self.last_dispense = Some(record);
+    }
+
+    /// Takes an archived dispense out of the totals, or puts it back once restored.
+    fn exclude(&mut self, entry: &HistoryEntry) {
+        let sign = if entry.archived { -1 } else { 1 };
+        let grams = sign as f32 * entry.dispensed_grams.unwrap_or(0.0);
+        let pieces = sign * entry.dispensed_pieces.unwrap_or(0) as i64;
+        self.dispense_count = self.dispense_count.saturating_add_signed(sign);
+        self.total_grams = (self.total_grams + grams).max(0.0);
+        self.total_pieces = self.total_pieces.saturating_add_signed(pieces);
+        self.by_trigger
+            .entry(entry.trigger.clone())
+            .or_default()
+            .adjust(sign, grams, pieces);
+    }
+}
+
+/// Estimated number of treats in `grams`.
+fn pieces_for(grams: f32, piece_weight_grams: f32) -> Option<u32> {
+    (piece_weight_grams > 0.0).then(|| (grams / piece_weight_grams).round() as u32)
+}
+
+/// Records `attempt` in the history right away, and the finished dispense in the stats
+/// and the history entry once the hopper weight has settled. The dispensed amount is the
+/// drop from `grams_before`, the hopper weight when the dispense started.
+pub async fn record_dispense(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    trigger: TriggerSource,
+    grams_before: f32,
+    attempt: HistoryEntry,
+) {
+    let id = history::record_completed(attempt)
+        .await
+        .inspect_err(|e| error!("{}", e))
+        .ok();
+    tokio::time::sleep(DISPENSE_SETTLE_DELAY).await;
+    let grams_after = motor_vibration::settled_grams(app_state).await;
+
+    let grams = (grams_before - grams_after).max(0.0);
+    let pieces = app_state
+        .lock()
+        .await
+        .app_config
+        .weight_monitor
+        .piece_weight_grams
+        .and_then(|piece_weight_grams| pieces_for(grams, piece_weight_grams));
+    info!(
+        "Dispensed {:.1} g ({} pieces)",
+        grams,
+        pieces.map_or("unknown".to_string(), |p| p.to_string())
+    );
+    let archived = match id {
+        Some(id) => history::set_dispensed(id, grams, pieces)
+            .await
+            .unwrap_or_else(|e| {
+                error!("{}", e);
+                false
+            }),
+        None => false,
+    };
+    if archived {
+        info!("Dispense was archived before it was weighed, leaving it out of the stats");
+        return;
+    }
+
+    let mut state_guard = app_state.lock().await;
+    state_guard.dispense_stats.record(DispenseRecord {
+        time: datetime::get_formatted_current_timestamp(),
+        trigger,
+        grams,
+        pieces,
+    });
+    if let Err(e) = save_stats_to_file(&state_guard.dispense_stats) {
+        error!("Failed to save dispense stats: {}", e);
+    }
+}
+
+/// Updates the totals after a history entry was archived or restored. Entries that were
+/// never counted, e.g. failed or imported dispenses, are skipped.
+pub async fn set_excluded(app_state: &Arc<Mutex<ApplicationState>>, entry: &HistoryEntry) {
+    if !entry.counted {
+        return;
+    }
+    let mut state_guard = app_state.lock().await;
+    state_guard.dispense_stats.exclude(entry);
+    if let Err(e) = save_stats_to_file(&state_guard.dispense_stats) {
+        error!("Failed to save dispense stats: {}", e);
+    }
+}
+
+pub fn load_stats_from_file() -> DispenseStats {
+    filesystem::read_json_from_file(&filesystem::get_dispense_stats_file_path()).unwrap_or_else(
+        |e| {
+            warn!("No dispense stats loaded, starting from zero: {}", e);
+            DispenseStats::default()
+        },
+    )
+}
+
+fn save_stats_to_file(stats: &DispenseStats) -> Result<(), String> {
+    filesystem::save_json_to_file(&filesystem::get_dispense_stats_file_path(), stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::history::DispenseResult;
+
+    #[test]
+    fn test_piece_counting() {
+        assert_eq!(pieces_for(7.4, 2.5), Some(3));
+        assert_eq!(pieces_for(0.9, 2.5), Some(0));
+        assert_eq!(pieces_for(7.4, 0.0), None);
+
+        let mut stats = DispenseStats::default();
+        for (grams, trigger) in [(7.4, TriggerSource::ApiUser), (5.0, TriggerSource::Hook)] {
+            stats.record(DispenseRecord {
+                time: "2025-01-01 12:00:00".to_string(),
+                trigger,
+                grams,
+                pieces: pieces_for(grams, 2.5),
+            });
+        }
+        assert_eq!(stats.dispense_count, 2);
+        assert_eq!(stats.total_pieces, 5);
+        assert!((stats.total_grams - 12.4).abs() < 0.001);
+        assert_eq!(
+            stats.by_trigger["hook"],
+            TriggerStats {
+                dispense_count: 1,
+                total_grams: 5.0,
+                total_pieces: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_archived_dispense_excluded() {
+        let mut stats = DispenseStats::default();
+        stats.record(DispenseRecord {
+            time: "2025-01-01 12:00:00".to_string(),
+            trigger: TriggerSource::ApiUser,
+            grams: 7.5,
+            pieces: Some(3),
+        });
+        let mut entry = HistoryEntry::new(
+            TriggerSource::ApiUser,
+            crate::services::dispenser::DispenseAmount::Degrees(90.0),
+            &crate::config::load_app_config_from_str(
+                r#"
+                api:
+                  listen_address: "127.0.0.1:0"
+                  admin_user: "admin"
+                  admin_password: "password"
+                motor:
+                  motor_type: "StepperMock"
+                power_monitor:
+                  sensor: "SensorMock"
+                weight_monitor:
+                  sensor: "SensorMock"
+                "#,
+            ),
+            DispenseResult::Completed,
+        )
+        .with_dispensed(7.5, Some(3));
+
+        entry.archived = true;
+        stats.exclude(&entry);
+        assert_eq!(stats.dispense_count, 0);
+        assert_eq!(stats.total_grams, 0.0);
+        assert_eq!(stats.total_pieces, 0);
+        assert_eq!(stats.by_trigger["api-user"], TriggerStats::default());
+
+        entry.archived = false;
+        stats.exclude(&entry);
+        assert_eq!(stats.dispense_count, 1);
+        assert_eq!(stats.total_grams, 7.5);
+        assert_eq!(stats.by_trigger["api-user"].total_pieces, 3);
+    }
+}
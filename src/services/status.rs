@@ -1,42 +1,102 @@
-use crate::application_state::ApplicationState;
+use crate::application_state::{ApplicationState, DispenserStatus, HardwareStatus};
+use crate::config::DeviceConfig;
+use crate::sensors::LoadCellStatus;
+use crate::services::backup_scheduler::BackupStatus;
+use crate::services::digital_inputs::DigitalInputState;
+use crate::services::digital_outputs::DigitalOutputState;
+use crate::services::fan::FanStatus;
+use crate::services::hopper_level;
+use crate::services::persisted_files::QuarantinedFile;
+use crate::services::power_monitor::OvercurrentProtectionStatus;
+use crate::services::scheduler::NextScheduledDispense;
+use crate::utils::units::DisplayWeight;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use std::time::{SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::Mutex;
-use tracing::{error, warn};
 
 pub async fn get_status(state: &Arc<Mutex<ApplicationState>>) -> StatusResponse {
     let now = SystemTime::now();
 
     // Lock once and extract only what we need
     let (
+        device,
         gpio_available,
         startup_time,
         last_dispensed,
         last_error_msg,
         last_error_time,
         dispenser_status,
+        treats_available,
         version,
         motor_name,
         power_readings_rx,
         motor_power_sensor_mutex,
         weight_readings_rx,
+        weight_sensor_mutex,
+        last_backup,
+        next_scheduled_dispense,
+        overcurrent_protection,
+        display_unit,
+        stale_channels,
+        init_errors,
+        quarantined_files,
+        hardware,
+        digital_inputs,
+        digital_outputs,
+        temperature_readings_rx,
+        fan,
+        cooldown_remaining_ms,
     ) = {
         let state_guard = state.lock().await;
 
         (
+            state_guard.app_config.device.clone(),
             state_guard.gpio.is_some(),
             state_guard.startup_time,
             state_guard.last_dispense_time.clone(),
             state_guard.last_error_msg.clone(),
             state_guard.last_error_time.clone(),
             state_guard.status.clone().to_string(),
+            hopper_level::treats_available(
+                &state_guard.status,
+                state_guard.weight_readings_rx.borrow().grams,
+                state_guard.app_config.weight_monitor.hopper_level.as_ref(),
+            ),
             state_guard.version.clone(),
             state_guard.motor.get_name().clone(),
             state_guard.power_readings_rx.clone(),
             state_guard.power_sensor_mutex.clone(),
             state_guard.weight_readings_rx.clone(),
+            state_guard.weight_sensor_mutex.clone(),
+            state_guard.last_backup.clone(),
+            state_guard.next_scheduled_dispense.clone(),
+            OvercurrentProtectionStatus::from_override(state_guard.overcurrent_override.as_ref()),
+            state_guard
+                .app_config
+                .weight_monitor
+                .display_unit
+                .unwrap_or_default(),
+            state_guard.stale_channels.clone(),
+            state_guard.init_errors.clone(),
+            state_guard.quarantined_files.clone(),
+            state_guard.hardware.clone(),
+            state_guard.digital_inputs.clone(),
+            state_guard
+                .digital_outputs
+                .iter()
+                .map(|(label, output)| (label.clone(), output.state()))
+                .collect(),
+            state_guard.temperature_readings_rx.clone(),
+            state_guard.fan.as_ref().map(|fan| fan.status()),
+            state_guard
+                .cooldown_until
+                .filter(|_| state_guard.status == DispenserStatus::Cooldown)
+                .map(|until| until.saturating_duration_since(Instant::now()).as_millis() as u64),
         )
     }; // lock is dropped here
 
@@ -52,12 +112,20 @@ pub async fn get_status(state: &Arc<Mutex<ApplicationState>>) -> StatusResponse
         None => "No Power Sensor".to_string(),
     };
 
-    let remaining_treats_grams = weight_readings_rx.borrow().grams;
+    let load_cells = match weight_sensor_mutex {
+        Some(sensor) => sensor.lock().await.get_cell_statuses(),
+        None => Vec::new(),
+    };
+
+    let weight_reading = weight_readings_rx.borrow().clone();
+    let remaining_treats_grams = weight_reading.grams;
+    let temperature_reading = temperature_readings_rx.borrow().clone();
 
     StatusResponse {
+        device,
         gpio_available,
         motor_operational: gpio_available, // temporary placeholder
-        treats_available: gpio_available,  // temporary placeholder
+        treats_available,
         last_dispensed,
         uptime_seconds,
         last_error_msg,
@@ -70,11 +138,154 @@ pub async fn get_status(state: &Arc<Mutex<ApplicationState>>) -> StatusResponse
         motor_current_amps: Some(power_reading.current_amps),
         motor_power_watts: Some(power_reading.power_watts),
         remaining_treats_grams,
+        remaining_treats: DisplayWeight::from_grams(remaining_treats_grams, display_unit),
+        weight_settled: weight_reading.settled,
+        load_cells,
+        temperature_celsius: temperature_reading.as_ref().map(|r| r.celsius),
+        humidity_percent: temperature_reading.and_then(|r| r.humidity_percent),
+        last_backup,
+        next_scheduled_dispense,
+        overcurrent_protection,
+        stale_channels,
+        init_errors,
+        quarantined_files,
+        hardware,
+        digital_inputs,
+        digital_outputs,
+        fan,
+        cooldown_remaining_ms,
+    }
+}
+
+/// ETag identifying the parts of the status that change on events: dispenser status,
+/// last dispense, last error, last backup, stale channels, failing load cells and digital input and output levels. Live sensor readings and uptime are left
+/// out, otherwise the tag would change on every request.
+pub fn status_etag(status: &StatusResponse) -> String {
+    let mut hasher = DefaultHasher::new();
+    status.dispenser_status.hash(&mut hasher);
+    status.last_dispensed.hash(&mut hasher);
+    status.last_error_msg.hash(&mut hasher);
+    status.last_error_time.hash(&mut hasher);
+    status.last_backup.as_ref().map(|b| &b.time).hash(&mut hasher);
+    status.stale_channels.hash(&mut hasher);
+    for cell in &status.load_cells {
+        cell.name.hash(&mut hasher);
+        cell.failing.hash(&mut hasher);
+    }
+    for (label, input) in &status.digital_inputs {
+        label.hash(&mut hasher);
+        input.high.hash(&mut hasher);
+    }
+    for (label, output) in &status.digital_outputs {
+        label.hash(&mut hasher);
+        output.on.hash(&mut hasher);
+    }
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Waits until the status ETag differs from `since` or the timeout elapses.
+/// Returns the current status and its ETag, and whether it changed.
+pub async fn wait_for_status_change(
+    state: &Arc<Mutex<ApplicationState>>,
+    since: &str,
+    timeout: Duration,
+) -> (StatusResponse, String, bool) {
+    // subscribe before reading the status so a change in between isn't missed
+    let mut status_rx = state.lock().await.status_tx.subscribe();
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        let status = get_status(state).await;
+        let etag = status_etag(&status);
+        if etag.trim_matches('"') != since.trim_matches('"') {
+            return (status, etag, true);
+        }
+
+        match tokio::time::timeout_at(deadline, status_rx.changed()).await {
+            Ok(Ok(())) => continue,
+            // timed out, or the sender is gone which only happens on shutdown
+            _ => return (status, etag, false),
+        }
+    }
+}
+
+/// Media type prefix of the versioned status responses, followed by the version and `+json`.
+const STATUS_MEDIA_TYPE_PREFIX: &str = "application/vnd.treat-dispenser.status.v";
+
+/// Versions of the `/status` response. A client asks for one with the `Accept` header, e.g.
+/// `application/vnd.treat-dispenser.status.v2+json`, and gets `V1` without one. The fields
+/// of a version never change, new fields go into a new version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusVersion {
+    V1,
+    V2,
+    V3,
+}
+
+impl StatusVersion {
+    /// The version asked for by the first versioned status media type in `accept`, `V1` if
+    /// there is none.
+    pub fn from_accept(accept: Option<&str>) -> Result<Self, String> {
+        let Some(media_type) = accept
+            .into_iter()
+            .flat_map(|a| a.split(','))
+            .find_map(|range| {
+                range
+                    .split(';')
+                    .next()
+                    .map(str::trim)
+                    .filter(|media_type| media_type.starts_with(STATUS_MEDIA_TYPE_PREFIX))
+            })
+        else {
+            return Ok(StatusVersion::V1);
+        };
+        match &media_type[STATUS_MEDIA_TYPE_PREFIX.len()..] {
+            "1+json" => Ok(StatusVersion::V1),
+            "2+json" => Ok(StatusVersion::V2),
+            "3+json" => Ok(StatusVersion::V3),
+            _ => Err(format!(
+                "Unsupported status version {}, supported are {}1+json to {}3+json",
+                media_type, STATUS_MEDIA_TYPE_PREFIX, STATUS_MEDIA_TYPE_PREFIX
+            )),
+        }
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            StatusVersion::V1 => "application/vnd.treat-dispenser.status.v1+json",
+            StatusVersion::V2 => "application/vnd.treat-dispenser.status.v2+json",
+            StatusVersion::V3 => "application/vnd.treat-dispenser.status.v3+json",
+        }
+    }
+}
+
+/// The latest status, what `get_status` returns.
+pub type StatusResponse = StatusResponseV3;
+
+/// Next feeding as reported by versions 1 and 2, without the amount.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledDispenseV1 {
+    pub schedule: String,
+    pub time: String,
+}
+
+impl From<NextScheduledDispense> for ScheduledDispenseV1 {
+    fn from(next: NextScheduledDispense) -> Self {
+        ScheduledDispenseV1 {
+            schedule: next.schedule,
+            time: next.time,
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct StatusResponse {
+/// The status as served before versioning, the default of `/status`. Frozen: a field added
+/// here would break clients that reject unknown fields, add it to a new version instead.
+#[derive(Serialize, Deserialize, Debug, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StatusResponseV1 {
+    /// Name, location and fleet of this dispenser, if a `device` section is configured
+    pub device: Option<DeviceConfig>,
     pub gpio_available: bool,
     pub motor_operational: bool,
     pub treats_available: bool,
@@ -90,4 +301,354 @@ pub struct StatusResponse {
     pub motor_current_amps: Option<f32>,
     pub motor_power_watts: Option<f32>,
     pub remaining_treats_grams: f32,
+    /// `remaining_treats_grams` in the configured display unit
+    pub remaining_treats: DisplayWeight,
+    /// False while motor vibration makes the weight unreliable, see `motor_vibration`
+    pub weight_settled: bool,
+    /// Load cells of a `SensorFused` weight sensor, empty for a single cell
+    pub load_cells: Vec<LoadCellStatus>,
+    /// Latest enclosure temperature, if a temperature sensor is configured and has been read
+    pub temperature_celsius: Option<f32>,
+    pub humidity_percent: Option<f32>,
+    pub last_backup: Option<BackupStatus>,
+    /// Next feeding of the `schedules` section
+    pub next_scheduled_dispense: Option<ScheduledDispenseV1>,
+    /// Off while disabled through `POST /power/overcurrent/disable`
+    pub overcurrent_protection: OvercurrentProtectionStatus,
+    /// Sensor reading channels that stopped updating, see the watchdog
+    pub stale_channels: Vec<String>,
+    /// Hardware that failed to initialize at startup
+    pub init_errors: Vec<String>,
+    /// Persisted files that were unreadable at startup and moved aside with a `.bad` suffix
+    pub quarantined_files: Vec<QuarantinedFile>,
+    pub hardware: HardwareStatus,
+    /// Levels of the inputs configured under `digital_inputs`, by label
+    pub digital_inputs: BTreeMap<String, DigitalInputState>,
+    /// States of the outputs configured under `digital_outputs`, by label
+    pub digital_outputs: BTreeMap<String, DigitalOutputState>,
+    /// Enclosure fan, if one is configured
+    pub fan: Option<FanStatus>,
+}
+
+/// `StatusResponseV1` with the time left of the cooldown.
+#[derive(Serialize, Deserialize, Debug, utoipa::ToSchema)]
+pub struct StatusResponseV2 {
+    /// Name, location and fleet of this dispenser, if a `device` section is configured
+    pub device: Option<DeviceConfig>,
+    pub gpio_available: bool,
+    pub motor_operational: bool,
+    pub treats_available: bool,
+    pub last_dispensed: Option<String>,
+    pub uptime_seconds: u64,
+    pub dispenser_status: String,
+    pub last_error_msg: Option<String>,
+    pub last_error_time: Option<String>,
+    pub version: String,
+    pub motor: String,
+    pub motor_power_sensor: String,
+    pub motor_voltage_volts: Option<f32>,
+    pub motor_current_amps: Option<f32>,
+    pub motor_power_watts: Option<f32>,
+    pub remaining_treats_grams: f32,
+    /// `remaining_treats_grams` in the configured display unit
+    pub remaining_treats: DisplayWeight,
+    /// False while motor vibration makes the weight unreliable, see `motor_vibration`
+    pub weight_settled: bool,
+    /// Load cells of a `SensorFused` weight sensor, empty for a single cell
+    pub load_cells: Vec<LoadCellStatus>,
+    /// Latest enclosure temperature, if a temperature sensor is configured and has been read
+    pub temperature_celsius: Option<f32>,
+    pub humidity_percent: Option<f32>,
+    pub last_backup: Option<BackupStatus>,
+    /// Next feeding of the `schedules` section
+    pub next_scheduled_dispense: Option<ScheduledDispenseV1>,
+    /// Off while disabled through `POST /power/overcurrent/disable`
+    pub overcurrent_protection: OvercurrentProtectionStatus,
+    /// Sensor reading channels that stopped updating, see the watchdog
+    pub stale_channels: Vec<String>,
+    /// Hardware that failed to initialize at startup
+    pub init_errors: Vec<String>,
+    /// Persisted files that were unreadable at startup and moved aside with a `.bad` suffix
+    pub quarantined_files: Vec<QuarantinedFile>,
+    pub hardware: HardwareStatus,
+    /// Levels of the inputs configured under `digital_inputs`, by label
+    pub digital_inputs: BTreeMap<String, DigitalInputState>,
+    /// States of the outputs configured under `digital_outputs`, by label
+    pub digital_outputs: BTreeMap<String, DigitalOutputState>,
+    /// Enclosure fan, if one is configured
+    pub fan: Option<FanStatus>,
+    /// Time until dispenses are accepted again, set while the status is `Cooldown`
+    #[serde(default)]
+    pub cooldown_remaining_ms: Option<u64>,
+}
+
+/// `StatusResponseV3` with the profile and amount of the next feeding.
+#[derive(Serialize, Deserialize, Debug, utoipa::ToSchema)]
+pub struct StatusResponseV3 {
+    /// Name, location and fleet of this dispenser, if a `device` section is configured
+    pub device: Option<DeviceConfig>,
+    pub gpio_available: bool,
+    pub motor_operational: bool,
+    pub treats_available: bool,
+    pub last_dispensed: Option<String>,
+    pub uptime_seconds: u64,
+    pub dispenser_status: String,
+    pub last_error_msg: Option<String>,
+    pub last_error_time: Option<String>,
+    pub version: String,
+    pub motor: String,
+    pub motor_power_sensor: String,
+    pub motor_voltage_volts: Option<f32>,
+    pub motor_current_amps: Option<f32>,
+    pub motor_power_watts: Option<f32>,
+    pub remaining_treats_grams: f32,
+    /// `remaining_treats_grams` in the configured display unit
+    pub remaining_treats: DisplayWeight,
+    /// False while motor vibration makes the weight unreliable, see `motor_vibration`
+    pub weight_settled: bool,
+    /// Load cells of a `SensorFused` weight sensor, empty for a single cell
+    pub load_cells: Vec<LoadCellStatus>,
+    /// Latest enclosure temperature, if a temperature sensor is configured and has been read
+    pub temperature_celsius: Option<f32>,
+    pub humidity_percent: Option<f32>,
+    pub last_backup: Option<BackupStatus>,
+    /// Next feeding of the `schedules` section
+    pub next_scheduled_dispense: Option<NextScheduledDispense>,
+    /// Off while disabled through `POST /power/overcurrent/disable`
+    pub overcurrent_protection: OvercurrentProtectionStatus,
+    /// Sensor reading channels that stopped updating, see the watchdog
+    pub stale_channels: Vec<String>,
+    /// Hardware that failed to initialize at startup
+    pub init_errors: Vec<String>,
+    /// Persisted files that were unreadable at startup and moved aside with a `.bad` suffix
+    pub quarantined_files: Vec<QuarantinedFile>,
+    pub hardware: HardwareStatus,
+    /// Levels of the inputs configured under `digital_inputs`, by label
+    pub digital_inputs: BTreeMap<String, DigitalInputState>,
+    /// States of the outputs configured under `digital_outputs`, by label
+    pub digital_outputs: BTreeMap<String, DigitalOutputState>,
+    /// Enclosure fan, if one is configured
+    pub fan: Option<FanStatus>,
+    /// Time until dispenses are accepted again, set while the status is `Cooldown`
+    #[serde(default)]
+    pub cooldown_remaining_ms: Option<u64>,
+}
+
+impl From<StatusResponseV2> for StatusResponseV1 {
+    fn from(status: StatusResponseV2) -> Self {
+        StatusResponseV1 {
+            device: status.device,
+            gpio_available: status.gpio_available,
+            motor_operational: status.motor_operational,
+            treats_available: status.treats_available,
+            last_dispensed: status.last_dispensed,
+            uptime_seconds: status.uptime_seconds,
+            dispenser_status: status.dispenser_status,
+            last_error_msg: status.last_error_msg,
+            last_error_time: status.last_error_time,
+            version: status.version,
+            motor: status.motor,
+            motor_power_sensor: status.motor_power_sensor,
+            motor_voltage_volts: status.motor_voltage_volts,
+            motor_current_amps: status.motor_current_amps,
+            motor_power_watts: status.motor_power_watts,
+            remaining_treats_grams: status.remaining_treats_grams,
+            remaining_treats: status.remaining_treats,
+            weight_settled: status.weight_settled,
+            load_cells: status.load_cells,
+            temperature_celsius: status.temperature_celsius,
+            humidity_percent: status.humidity_percent,
+            last_backup: status.last_backup,
+            next_scheduled_dispense: status.next_scheduled_dispense,
+            overcurrent_protection: status.overcurrent_protection,
+            stale_channels: status.stale_channels,
+            init_errors: status.init_errors,
+            quarantined_files: status.quarantined_files,
+            hardware: status.hardware,
+            digital_inputs: status.digital_inputs,
+            digital_outputs: status.digital_outputs,
+            fan: status.fan,
+        }
+    }
+}
+
+impl From<StatusResponseV3> for StatusResponseV2 {
+    fn from(status: StatusResponseV3) -> Self {
+        StatusResponseV2 {
+            device: status.device,
+            gpio_available: status.gpio_available,
+            motor_operational: status.motor_operational,
+            treats_available: status.treats_available,
+            last_dispensed: status.last_dispensed,
+            uptime_seconds: status.uptime_seconds,
+            dispenser_status: status.dispenser_status,
+            last_error_msg: status.last_error_msg,
+            last_error_time: status.last_error_time,
+            version: status.version,
+            motor: status.motor,
+            motor_power_sensor: status.motor_power_sensor,
+            motor_voltage_volts: status.motor_voltage_volts,
+            motor_current_amps: status.motor_current_amps,
+            motor_power_watts: status.motor_power_watts,
+            remaining_treats_grams: status.remaining_treats_grams,
+            remaining_treats: status.remaining_treats,
+            weight_settled: status.weight_settled,
+            load_cells: status.load_cells,
+            temperature_celsius: status.temperature_celsius,
+            humidity_percent: status.humidity_percent,
+            last_backup: status.last_backup,
+            next_scheduled_dispense: status.next_scheduled_dispense.map(Into::into),
+            overcurrent_protection: status.overcurrent_protection,
+            stale_channels: status.stale_channels,
+            init_errors: status.init_errors,
+            quarantined_files: status.quarantined_files,
+            hardware: status.hardware,
+            digital_inputs: status.digital_inputs,
+            digital_outputs: status.digital_outputs,
+            fan: status.fan,
+            cooldown_remaining_ms: status.cooldown_remaining_ms,
+        }
+    }
+}
+
+/// A status response in the version the client asked for.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum VersionedStatusResponse {
+    V1(StatusResponseV1),
+    V2(StatusResponseV2),
+    V3(StatusResponseV3),
+}
+
+impl VersionedStatusResponse {
+    pub fn new(status: StatusResponse, version: StatusVersion) -> Self {
+        match version {
+            StatusVersion::V1 => VersionedStatusResponse::V1(StatusResponseV2::from(status).into()),
+            StatusVersion::V2 => VersionedStatusResponse::V2(status.into()),
+            StatusVersion::V3 => VersionedStatusResponse::V3(status),
+        }
+    }
+}
+
+/// Compact status for small displays and widgets, one request instead of several.
+#[derive(Serialize, Deserialize, Debug, utoipa::ToSchema)]
+pub struct SummaryResponse {
+    pub status: String,
+    pub emoji: String,
+    /// Short human readable status, e.g. `Ready`
+    pub text: String,
+    pub remaining_grams: f32,
+    /// Remaining treats formatted in the display unit, e.g. `412 g`
+    pub remaining: String,
+    pub last_dispense: Option<String>,
+}
+
+pub async fn get_summary(state: &Arc<Mutex<ApplicationState>>) -> SummaryResponse {
+    let (status, remaining_grams, display_unit, last_dispense) = {
+        let state_guard = state.lock().await;
+        (
+            state_guard.status.clone(),
+            state_guard.weight_readings_rx.borrow().grams,
+            state_guard
+                .app_config
+                .weight_monitor
+                .display_unit
+                .unwrap_or_default(),
+            state_guard.last_dispense_time.clone(),
+        )
+    };
+
+    let (emoji, text) = status_face(&status);
+    let remaining = DisplayWeight::from_grams(remaining_grams, display_unit);
+    SummaryResponse {
+        status: status.to_string(),
+        emoji: emoji.to_string(),
+        text: text.to_string(),
+        remaining_grams,
+        remaining: format!("{:.0} {}", remaining.value, remaining.symbol),
+        last_dispense,
+    }
+}
+
+fn status_face(status: &DispenserStatus) -> (&'static str, &'static str) {
+    match status {
+        DispenserStatus::Operational => ("✅", "Ready"),
+        DispenserStatus::Dispensing => ("🥕", "Dispensing"),
+        DispenserStatus::Cooldown => ("⏳", "Cooling down"),
+        DispenserStatus::Stirring => ("🔄", "Stirring"),
+        DispenserStatus::Calibrating => ("⚖️", "Calibrating"),
+        DispenserStatus::MeasuringCurrent => ("🔌", "Measuring current"),
+        DispenserStatus::Cancelled => ("⏹️", "Cancelled"),
+        DispenserStatus::Empty => ("🫙", "Empty"),
+        DispenserStatus::Jammed => ("⚠️", "Jammed"),
+        DispenserStatus::CalibrationFailed => ("⚠️", "Calibration failed"),
+        DispenserStatus::MotorControlError | DispenserStatus::NoGpio => ("❌", "Hardware error"),
+        DispenserStatus::Unknown => ("❓", "Unknown"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/status");
+
+    fn fixture(name: &str) -> serde_json::Value {
+        let path = format!("{}/{}", FIXTURES_DIR, name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    /// Serializes through a string, so f32 fields compare equal to the fixture values.
+    fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    /// Fails when a field of a released version is added, removed, renamed or retyped. Add
+    /// the field to a new version and a new fixture instead of changing these.
+    #[test]
+    fn test_status_versions_match_fixtures() {
+        let v1_fixture = fixture("v1.json");
+        let v2_fixture = fixture("v2.json");
+        let v3_fixture = fixture("v3.json");
+
+        let v1: StatusResponseV1 = serde_json::from_value(v1_fixture.clone()).unwrap();
+        assert_eq!(to_json(&v1), v1_fixture);
+        let v2: StatusResponseV2 = serde_json::from_value(v2_fixture.clone()).unwrap();
+        assert_eq!(to_json(&v2), v2_fixture);
+        let v3: StatusResponseV3 = serde_json::from_value(v3_fixture.clone()).unwrap();
+        assert_eq!(to_json(&v3), v3_fixture);
+
+        // V3 only adds to the next feeding of V2
+        assert_eq!(to_json(&StatusResponseV2::from(v3)), v2_fixture);
+        assert!(serde_json::from_value::<StatusResponseV2>(v3_fixture).is_err());
+
+        // V2 adds to V1 without changing it
+        assert_eq!(to_json(&StatusResponseV1::from(v2)), v1_fixture);
+        assert!(serde_json::from_value::<StatusResponseV1>(v2_fixture).is_err());
+        let v2_from_v1: StatusResponseV2 = serde_json::from_value(v1_fixture).unwrap();
+        assert_eq!(v2_from_v1.cooldown_remaining_ms, None);
+    }
+
+    #[test]
+    fn test_status_version_from_accept() {
+        assert_eq!(StatusVersion::from_accept(None), Ok(StatusVersion::V1));
+        assert_eq!(
+            StatusVersion::from_accept(Some("application/json")),
+            Ok(StatusVersion::V1)
+        );
+        assert_eq!(
+            StatusVersion::from_accept(Some(
+                "text/html, application/vnd.treat-dispenser.status.v2+json; q=0.9, */*"
+            )),
+            Ok(StatusVersion::V2)
+        );
+        assert_eq!(
+            StatusVersion::from_accept(Some(StatusVersion::V1.media_type())),
+            Ok(StatusVersion::V1)
+        );
+        assert!(
+            StatusVersion::from_accept(Some("application/vnd.treat-dispenser.status.v9+json"))
+                .is_err()
+        );
+    }
 }
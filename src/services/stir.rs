@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{self, StirConfig};
+use crate::motor::{self, Direction, StepMode};
+use crate::services::energy;
+use crate::services::error_reporting::{self, ErrorKind};
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::utils::state_helpers;
+
+const DUTY_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Motor runtime used for stirring within the last hour. Kept apart from dispensing so
+/// stirring neither triggers a cooldown nor counts against it.
+struct StirDuty {
+    runs: VecDeque<(Instant, Duration)>,
+    budget: Duration,
+}
+
+impl StirDuty {
+    fn new(budget: Duration) -> Self {
+        StirDuty {
+            runs: VecDeque::new(),
+            budget,
+        }
+    }
+
+    fn used(&mut self, now: Instant) -> Duration {
+        while let Some((started, _)) = self.runs.front()
+            && now.duration_since(*started) > DUTY_WINDOW
+        {
+            self.runs.pop_front();
+        }
+        self.runs.iter().map(|(_, runtime)| *runtime).sum()
+    }
+
+    fn has_budget(&mut self, now: Instant) -> bool {
+        self.used(now) < self.budget
+    }
+
+    fn record(&mut self, started: Instant, runtime: Duration) {
+        self.runs.push_back((started, runtime));
+    }
+}
+
+/// Starts stirring the hopper every `stir.interval_minutes` if a `stir` section is
+/// configured. Runs are skipped while the dispenser is busy or not operational, and once
+/// `max_motor_secs_per_hour` of stirring was used within the last hour, and while the
+/// `energy` policy is conserving.
+pub async fn start_stir_scheduler(app_state: &Arc<Mutex<ApplicationState>>) {
+    let stir_config = match app_state.lock().await.app_config.stir.clone() {
+        Some(c) if c.enabled.unwrap_or(true) => c,
+        _ => return,
+    };
+
+    let interval = Duration::from_secs(
+        stir_config
+            .interval_minutes
+            .unwrap_or(config::STIR_INTERVAL_MINUTES_DEFAULT)
+            .max(1)
+            * 60,
+    );
+    let budget = Duration::from_secs(
+        stir_config
+            .max_motor_secs_per_hour
+            .unwrap_or(config::STIR_MAX_MOTOR_SECS_PER_HOUR_DEFAULT),
+    );
+    info!(
+        "Stirring the hopper every {} minutes",
+        interval.as_secs() / 60
+    );
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "stir_scheduler", move || {
+        let app_state = Arc::clone(&app_state_clone);
+        let stir_config = stir_config.clone();
+        async move {
+            let mut duty = StirDuty::new(budget);
+            loop {
+                tokio::time::sleep(interval).await;
+
+                if energy::defer_task(&app_state, "hopper stir").await {
+                    continue;
+                }
+                let now = Instant::now();
+                if !duty.has_budget(now) {
+                    info!("Skipping hopper stir, hourly motor budget used up");
+                    continue;
+                }
+                match stir_hopper(&app_state, &stir_config).await {
+                    Ok(runtime) => duty.record(now, runtime),
+                    Err(e) => debug!("Hopper stir skipped: {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Runs the stir routine once: `cycles` times forward and back by `degrees`. The
+/// dispenser is `Stirring` meanwhile, and the run can be stopped with `POST /cancel`.
+/// Unlike a dispense, there is no cooldown afterwards and the last dispense time is kept.
+///
+/// Returns the motor runtime, or an error if the dispenser wasn't free to stir.
+pub async fn stir_hopper(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    stir_config: &StirConfig,
+) -> Result<Duration, String> {
+    let (motor, cancel_token) = {
+        let mut state_guard = app_state.lock().await;
+        if state_guard.status != DispenserStatus::Operational {
+            return Err(format!(
+                "Dispenser is not operational (current status: {:?})",
+                state_guard.status
+            ));
+        }
+        state_guard.set_status(DispenserStatus::Stirring);
+        let token = CancellationToken::new();
+        state_guard.motor_cancel_token = Some(token.clone());
+        (Arc::clone(&state_guard.motor), token)
+    };
+
+    let degrees = stir_config.degrees.unwrap_or(config::STIR_DEGREES_DEFAULT);
+    let cycles = stir_config.cycles.unwrap_or(config::STIR_CYCLES_DEFAULT);
+    info!("Stirring hopper, {} cycles of {}°", cycles, degrees);
+
+    let started = Instant::now();
+    let mut result = Ok(());
+    'cycles: for _ in 0..cycles {
+        for direction in [Direction::Clockwise, Direction::CounterClockwise] {
+            result = motor
+                .run_motor_degrees_async(
+                    degrees,
+                    &direction,
+                    &StepMode::Full,
+                    app_state,
+                    &cancel_token,
+                )
+                .await
+                .map(|_| ());
+            if result.is_err() {
+                break 'cycles;
+            }
+        }
+    }
+    let runtime = started.elapsed();
+
+    app_state.lock().await.motor_cancel_token = None;
+    match result {
+        Ok(()) => {
+            let event_bus = {
+                let mut state_guard = app_state.lock().await;
+                state_guard.set_status(DispenserStatus::Operational);
+                state_guard.event_bus.clone()
+            };
+            event_bus.publish(
+                EventKind::HopperStirred,
+                format!("Hopper stirred ({} cycles)", cycles),
+            );
+        }
+        Err(e) => {
+            warn!("Hopper stir ended: {}", e);
+            if cancel_token.is_cancelled() {
+                // the cancel request already set the status
+            } else if e.starts_with(motor::STALL_ERROR_PREFIX) {
+                state_helpers::record_error(app_state, &e).await;
+                state_helpers::set_dispenser_status_async(app_state, DispenserStatus::Jammed).await;
+            } else {
+                error_reporting::report(
+                    ErrorKind::BackgroundTask,
+                    format!("Hopper stir motor run failed: {}", e),
+                );
+                state_helpers::set_dispenser_status_async(app_state, DispenserStatus::Unknown)
+                    .await;
+            }
+        }
+    }
+    Ok(runtime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stir_duty_budget() {
+        let start = Instant::now();
+        let mut duty = StirDuty::new(Duration::from_secs(60));
+        assert!(duty.has_budget(start));
+
+        duty.record(start, Duration::from_secs(40));
+        assert!(duty.has_budget(start + Duration::from_secs(60)));
+        duty.record(start + Duration::from_secs(60), Duration::from_secs(20));
+        assert!(!duty.has_budget(start + Duration::from_secs(120)));
+
+        // the first run drops out of the window after an hour
+        assert!(duty.has_budget(start + Duration::from_secs(3601)));
+        assert_eq!(
+            duty.used(start + Duration::from_secs(3601)),
+            Duration::from_secs(20)
+        );
+    }
+}
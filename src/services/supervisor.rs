@@ -0,0 +1,119 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+use tracing::{error, info};
+
+use crate::application_state::ApplicationState;
+use crate::services::error_reporting;
+use crate::services::events::EventKind;
+use crate::utils::state_helpers;
+
+const RESTART_DELAY_INITIAL: Duration = Duration::from_secs(1);
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(60);
+
+/// A task that stayed up at least this long is considered healthy again and
+/// restarts with the initial delay.
+const HEALTHY_RUN_TIME: Duration = Duration::from_secs(60);
+
+/// Spawns a long running background task and restarts it if it panics. Each panic is
+/// recorded in `last_error_msg`/`last_error_time` and the event log before the restart,
+/// so it shows up in `/status` and `/events`. A task that returns normally is not restarted.
+/// While it runs, `request_restart` stops the task and starts it again right away.
+///
+/// * `make_task` - Called for every (re)start to build a fresh instance of the task.
+pub fn spawn_supervised<F, Fut>(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    name: &'static str,
+    make_task: F,
+) where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let app_state = Arc::clone(app_state);
+
+    tokio::spawn(async move {
+        let mut restart_delay = RESTART_DELAY_INITIAL;
+        let restart_requested = Arc::new(Notify::new());
+        app_state
+            .lock()
+            .await
+            .supervised_tasks
+            .insert(name, Arc::clone(&restart_requested));
+
+        loop {
+            let started_at = Instant::now();
+            let mut task = tokio::spawn(make_task());
+            let result = tokio::select! {
+                result = &mut task => result,
+                _ = restart_requested.notified() => {
+                    task.abort();
+                    let _ = task.await;
+                    info!("Restarting task '{}' on request", name);
+                    let event_bus = app_state.lock().await.event_bus.clone();
+                    event_bus.publish(
+                        EventKind::TaskRestarted,
+                        format!("Task '{}' restarted on request", name),
+                    );
+                    restart_delay = RESTART_DELAY_INITIAL;
+                    continue;
+                }
+            };
+            let join_error = match result {
+                Ok(()) => {
+                    info!("Task '{}' finished", name);
+                    app_state.lock().await.supervised_tasks.remove(name);
+                    return;
+                }
+                Err(e) => e,
+            };
+
+            if !join_error.is_panic() {
+                // cancelled, which only happens when the runtime shuts down
+                return;
+            }
+
+            let message = format!(
+                "Task '{}' panicked: {}",
+                name,
+                error_reporting::panic_message(join_error.into_panic().as_ref())
+            );
+            error!("{}", message);
+            state_helpers::record_error(&app_state, &message).await;
+            let event_bus = app_state.lock().await.event_bus.clone();
+            event_bus.publish(EventKind::TaskPanicked, message);
+
+            if started_at.elapsed() >= HEALTHY_RUN_TIME {
+                restart_delay = RESTART_DELAY_INITIAL;
+            }
+            tokio::time::sleep(restart_delay).await;
+            restart_delay = (restart_delay * 2).min(RESTART_DELAY_MAX);
+
+            info!("Restarting task '{}'", name);
+            event_bus.publish(
+                EventKind::TaskRestarted,
+                format!("Task '{}' restarted", name),
+            );
+        }
+    });
+}
+
+/// Asks the supervised task `name` to restart. Fails if no running task has that name.
+pub fn request_restart(app_state: &ApplicationState, name: &str) -> Result<(), String> {
+    match app_state.supervised_tasks.get(name) {
+        Some(restart_requested) => {
+            restart_requested.notify_one();
+            Ok(())
+        }
+        None => Err(format!(
+            "No running task '{}', running are: {}",
+            name,
+            running_tasks(app_state).join(", ")
+        )),
+    }
+}
+
+/// Names of the running supervised tasks, sorted.
+pub fn running_tasks(app_state: &ApplicationState) -> Vec<&'static str> {
+    app_state.supervised_tasks.keys().copied().collect()
+}
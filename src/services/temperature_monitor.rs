@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{MissedTickBehavior, interval};
+use tracing::{info, trace, warn};
+
+use crate::application_state::ApplicationState;
+use crate::config::{self, TemperatureConfig};
+use crate::sensors::temperature::{TemperatureReading, TemperatureSensor};
+use crate::services::supervisor;
+
+/// Spawns a supervised task that reads the temperature sensor every
+/// `temperature.interval_secs` and publishes the readings. Does nothing if no
+/// temperature sensor is configured or it failed to initialize.
+pub async fn start_temperature_monitoring_thread(app_state: &Arc<Mutex<ApplicationState>>) {
+    if app_state.lock().await.temperature_sensor_mutex.is_none() {
+        return;
+    }
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "temperature_monitor", move || {
+        run_temperature_monitor(Arc::clone(&app_state_clone))
+    });
+}
+
+async fn run_temperature_monitor(app_state: Arc<Mutex<ApplicationState>>) {
+    let (sensor_mutex, readings_tx, interval_secs) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.temperature_sensor_mutex.clone(),
+            state_guard.temperature_readings_tx.clone(),
+            state_guard
+                .app_config
+                .temperature
+                .as_ref()
+                .and_then(|c| c.interval_secs)
+                .unwrap_or(config::TEMPERATURE_INTERVAL_SECS_DEFAULT),
+        )
+    };
+    let Some(sensor_mutex) = sensor_mutex else {
+        return;
+    };
+
+    info!("Starting temperature monitoring thread");
+    let mut tick = interval(Duration::from_secs(interval_secs.max(1)));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tick.tick().await;
+        match read_blocking(&sensor_mutex).await {
+            Ok(reading) => {
+                trace!("Temperature reading: {:?}", reading);
+                let _ = readings_tx.send(Some(reading));
+            }
+            // the last reading is kept, single failed reads are common with DHT sensors
+            Err(e) => warn!("Failed to read temperature: {}", e),
+        }
+    }
+}
+
+/// Reads the sensor on a blocking thread, some sensors take up to a second per read.
+pub async fn read_blocking(
+    sensor_mutex: &Arc<Mutex<Box<dyn TemperatureSensor>>>,
+) -> Result<TemperatureReading, String> {
+    let sensor_mutex = Arc::clone(sensor_mutex);
+    tokio::task::spawn_blocking(move || sensor_mutex.blocking_lock().get_temperature_reading())
+        .await
+        .unwrap_or_else(|e| Err(format!("Temperature read task failed: {}", e)))
+}
+
+/// Why a dispense is not allowed at the current temperature, if it isn't. Without a
+/// reading dispensing is allowed, a broken sensor shouldn't stop the treats.
+pub fn dispense_blocked_reason(
+    temperature_config: Option<&TemperatureConfig>,
+    reading: Option<&TemperatureReading>,
+) -> Option<String> {
+    let (temperature_config, reading) = (temperature_config?, reading?);
+    if let Some(max) = temperature_config.max_dispense_celsius
+        && reading.celsius > max
+    {
+        return Some(format!(
+            "Too warm to dispense ({:.1} °C, limit {:.1} °C)",
+            reading.celsius, max
+        ));
+    }
+    if let Some(min) = temperature_config.min_dispense_celsius
+        && reading.celsius < min
+    {
+        return Some(format!(
+            "Too cold to dispense ({:.1} °C, limit {:.1} °C)",
+            reading.celsius, min
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispense_blocked_reason() {
+        let temperature_config = TemperatureConfig {
+            sensor: "SensorMock".to_string(),
+            interval_secs: None,
+            max_dispense_celsius: Some(30.0),
+            min_dispense_celsius: None,
+            ds18b20: None,
+            dht22: None,
+            bme280: None,
+            humidity_warning: None,
+        };
+        let reading = |celsius| TemperatureReading {
+            celsius,
+            humidity_percent: None,
+        };
+
+        assert!(dispense_blocked_reason(Some(&temperature_config), Some(&reading(31.0))).is_some());
+        assert!(dispense_blocked_reason(Some(&temperature_config), Some(&reading(29.5))).is_none());
+        assert!(dispense_blocked_reason(Some(&temperature_config), None).is_none());
+        assert!(dispense_blocked_reason(None, Some(&reading(40.0))).is_none());
+    }
+}
@@ -0,0 +1,321 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::application_state::{AppStateMutex, ApplicationState};
+use crate::config::{self, ReinforcementSchedule, TrainingConfig};
+use crate::error::{ApiError, FieldError};
+use crate::services::dispenser::DispenseAmount;
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::services::triggers::{self, TrainingTrigger};
+use crate::utils::datetime;
+
+/// Optional body of `POST /training/start`, overrides the `training` config for the session.
+#[derive(Deserialize, Debug, Default, utoipa::ToSchema)]
+pub struct StartTrainingRequest {
+    pub schedule: Option<ReinforcementSchedule>,
+    pub ratio: Option<u32>,
+}
+
+impl StartTrainingRequest {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if let Some(ratio) = self.ratio
+            && !(1..=config::TRAINING_RATIO_MAX).contains(&ratio)
+        {
+            return Err(ApiError::Validation(vec![FieldError::new(
+                "ratio",
+                format!("must be between 1 and {}", config::TRAINING_RATIO_MAX),
+            )]));
+        }
+        Ok(())
+    }
+}
+
+/// Counters of one training session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct TrainingSession {
+    pub schedule: ReinforcementSchedule,
+    pub ratio: u32,
+    pub started_at: String,
+    /// Unset while the session runs
+    pub stopped_at: Option<String>,
+    pub presses: u32,
+    pub rewards: u32,
+    /// Rewards earned but not dispensed, e.g. because the dispenser was busy
+    pub missed_rewards: u32,
+    pub presses_since_reward: u32,
+    /// Presses per earned reward, unset until the first one
+    pub presses_per_reward: Option<f32>,
+    pub last_press_at: Option<String>,
+    pub last_reward_at: Option<String>,
+    /// Presses the next reward takes
+    #[serde(skip)]
+    required_presses: u32,
+}
+
+impl TrainingSession {
+    fn new(schedule: ReinforcementSchedule, ratio: u32) -> Self {
+        TrainingSession {
+            schedule,
+            ratio,
+            started_at: datetime::get_formatted_current_timestamp(),
+            stopped_at: None,
+            presses: 0,
+            rewards: 0,
+            missed_rewards: 0,
+            presses_since_reward: 0,
+            presses_per_reward: None,
+            last_press_at: None,
+            last_reward_at: None,
+            required_presses: required_presses(schedule, ratio),
+        }
+    }
+
+    /// Counts a press, returns true if it earned a reward.
+    fn press(&mut self) -> bool {
+        self.presses += 1;
+        self.presses_since_reward += 1;
+        self.last_press_at = Some(datetime::get_formatted_current_timestamp());
+        if self.presses_since_reward < self.required_presses {
+            return false;
+        }
+        self.presses_since_reward = 0;
+        self.required_presses = required_presses(self.schedule, self.ratio);
+        true
+    }
+
+    /// Records the outcome of an earned reward.
+    fn rewarded(&mut self, dispensed: bool) {
+        if dispensed {
+            self.rewards += 1;
+            self.last_reward_at = Some(datetime::get_formatted_current_timestamp());
+        } else {
+            self.missed_rewards += 1;
+        }
+        self.presses_per_reward =
+            Some(self.presses as f32 / (self.rewards + self.missed_rewards) as f32);
+    }
+}
+
+/// Presses until the next reward. A variable schedule draws evenly from 1 to
+/// `2 * ratio - 1`, so rewards come after `ratio` presses on average.
+fn required_presses(schedule: ReinforcementSchedule, ratio: u32) -> u32 {
+    let ratio = ratio.max(1);
+    match schedule {
+        ReinforcementSchedule::Fixed => ratio,
+        ReinforcementSchedule::Variable => rand::random_range(1..=2 * ratio - 1),
+    }
+}
+
+/// Totals over all sessions since startup.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, utoipa::ToSchema)]
+pub struct TrainingTotals {
+    pub sessions: u32,
+    pub presses: u32,
+    pub rewards: u32,
+}
+
+/// Returned by the `/training` endpoints. Kept in memory, so a restart resets it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, utoipa::ToSchema)]
+pub struct TrainingState {
+    pub active: bool,
+    /// The running session, or the last one once stopped
+    pub session: Option<TrainingSession>,
+    pub totals: TrainingTotals,
+}
+
+fn no_training() -> ApiError {
+    ApiError::NotFound("No training input configured".to_string())
+}
+
+pub async fn get_state(app_state: &AppStateMutex) -> TrainingState {
+    app_state.lock().await.training.clone()
+}
+
+/// Starts a training session, ending the running one.
+pub async fn start(
+    app_state: &AppStateMutex,
+    request: StartTrainingRequest,
+) -> Result<TrainingState, ApiError> {
+    let mut state_guard = app_state.lock().await;
+    let training_config = state_guard
+        .app_config
+        .training
+        .as_ref()
+        .ok_or_else(no_training)?;
+    let schedule = request
+        .schedule
+        .unwrap_or(training_config.schedule.unwrap_or_default());
+    let ratio = request
+        .ratio
+        .or(training_config.ratio)
+        .unwrap_or(config::TRAINING_RATIO_DEFAULT)
+        .clamp(1, config::TRAINING_RATIO_MAX);
+
+    let training = &mut state_guard.training;
+    end_session(training);
+    training.active = true;
+    training.session = Some(TrainingSession::new(schedule, ratio));
+    training.totals.sessions += 1;
+    info!(
+        "Training session started, {:?} ratio of {}",
+        schedule, ratio
+    );
+    Ok(training.clone())
+}
+
+pub async fn stop(app_state: &AppStateMutex) -> Result<TrainingState, ApiError> {
+    let mut state_guard = app_state.lock().await;
+    let training = &mut state_guard.training;
+    if !training.active {
+        return Err(ApiError::NotFound(
+            "No training session is running".to_string(),
+        ));
+    }
+    end_session(training);
+    if let Some(session) = &training.session {
+        info!(
+            "Training session stopped after {} presses and {} rewards",
+            session.presses, session.rewards
+        );
+    }
+    Ok(training.clone())
+}
+
+fn end_session(training: &mut TrainingState) {
+    if let Some(session) = training.session.as_mut().filter(|_| training.active) {
+        session.stopped_at = Some(datetime::get_formatted_current_timestamp());
+    }
+    training.active = false;
+}
+
+/// Counts presses of the `training.input` while a session runs and dispenses the rewards
+/// they earn. Does nothing if training isn't configured.
+pub async fn start_training_monitor(app_state: &Arc<Mutex<ApplicationState>>) {
+    let training_config = {
+        let state_guard = app_state.lock().await;
+        let Some(training_config) = state_guard.app_config.training.clone() else {
+            return;
+        };
+        if !state_guard
+            .digital_inputs
+            .contains_key(&training_config.input)
+        {
+            warn!(
+                "Training input '{}' is not configured under digital_inputs, training is unavailable",
+                training_config.input
+            );
+            return;
+        }
+        training_config
+    };
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "training", move || {
+        run_training_monitor(Arc::clone(&app_state_clone), training_config.clone())
+    });
+}
+
+async fn run_training_monitor(
+    app_state: Arc<Mutex<ApplicationState>>,
+    training_config: TrainingConfig,
+) {
+    let mut events_rx = app_state.lock().await.event_bus.subscribe();
+    let pressed_high = training_config.pressed_high.unwrap_or(false);
+    let amount = training_config
+        .reward_degrees
+        .map_or(DispenseAmount::Default, DispenseAmount::Degrees);
+
+    loop {
+        match events_rx.recv().await {
+            Ok(event) => {
+                let pressed = event.kind == EventKind::InputChanged
+                    && event.input.is_some_and(|input| {
+                        input.label == training_config.input && input.high == pressed_high
+                    });
+                if pressed {
+                    handle_press(&app_state, amount).await;
+                }
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn handle_press(app_state: &AppStateMutex, amount: DispenseAmount) {
+    let presses = {
+        let mut state_guard = app_state.lock().await;
+        let training = &mut state_guard.training;
+        if !training.active {
+            return;
+        }
+        let Some(session) = training.session.as_mut() else {
+            return;
+        };
+        let reward_due = session.press();
+        training.totals.presses += 1;
+        if !reward_due {
+            return;
+        }
+        session.presses
+    };
+
+    let result = triggers::request_dispense(app_state, &TrainingTrigger(amount)).await;
+    let mut state_guard = app_state.lock().await;
+    if let Err(e) = &result {
+        warn!(
+            "Training reward after press {} not dispensed: {}",
+            presses, e
+        );
+    } else {
+        info!("Training reward after press {}", presses);
+        state_guard.training.totals.rewards += 1;
+        state_guard.event_bus.publish(
+            EventKind::TrainingRewarded,
+            format!("Training reward after press {}", presses),
+        );
+    }
+    if let Some(session) = state_guard.training.session.as_mut() {
+        session.rewarded(result.is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_ratio() {
+        let mut session = TrainingSession::new(ReinforcementSchedule::Fixed, 3);
+        let rewarded: Vec<bool> = (0..7).map(|_| session.press()).collect();
+        assert_eq!(
+            rewarded,
+            vec![false, false, true, false, false, true, false]
+        );
+        assert_eq!(session.presses, 7);
+        assert_eq!(session.presses_since_reward, 1);
+
+        session.rewarded(true);
+        session.rewarded(false);
+        assert_eq!(session.rewards, 1);
+        assert_eq!(session.missed_rewards, 1);
+        assert_eq!(session.presses_per_reward, Some(3.5));
+    }
+
+    #[test]
+    fn test_variable_ratio() {
+        let draws: Vec<u32> = (0..2000)
+            .map(|_| required_presses(ReinforcementSchedule::Variable, 5))
+            .collect();
+        assert!(draws.iter().all(|n| (1..=9).contains(n)));
+        let mean = draws.iter().sum::<u32>() as f32 / draws.len() as f32;
+        assert!((4.5..5.5).contains(&mean), "mean {}", mean);
+
+        // a ratio of one rewards every press
+        assert_eq!(required_presses(ReinforcementSchedule::Variable, 1), 1);
+    }
+}
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::application_state::AppStateMutex;
+use crate::config::{self, AppConfig};
+use crate::error::{ApiError, FieldError};
+use crate::services::dispenser::DispenseAmount;
+use crate::utils::datetime;
+
+/// Body of `POST /dispense/trickle`.
+#[derive(Deserialize, Debug, utoipa::ToSchema)]
+pub struct TrickleRequest {
+    /// Weight dispensed over the whole duration
+    pub grams: f32,
+    pub duration_minutes: u64,
+}
+
+impl TrickleRequest {
+    pub fn validate(&self, app_config: &AppConfig) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        if !(self.grams > 0.0 && self.grams <= config::DISPENSE_GRAMS_MAX) {
+            errors.push(FieldError::new(
+                "grams",
+                format!(
+                    "must be greater than 0 and at most {}",
+                    config::DISPENSE_GRAMS_MAX
+                ),
+            ));
+        }
+        let max_minutes = app_config
+            .trickle
+            .as_ref()
+            .and_then(|t| t.max_duration_minutes)
+            .unwrap_or(config::TRICKLE_MAX_DURATION_MINUTES_DEFAULT);
+        if !(1..=max_minutes).contains(&self.duration_minutes) {
+            errors.push(FieldError::new(
+                "duration_minutes",
+                format!("must be between 1 and {}", max_minutes),
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+
+    pub fn amount(&self) -> DispenseAmount {
+        DispenseAmount::Trickle {
+            grams: self.grams,
+            duration_minutes: self.duration_minutes,
+        }
+    }
+}
+
+/// How a trickle dispense is split into bursts, resolved from the config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TricklePlan {
+    pub burst_grams: f32,
+    pub bursts: u32,
+    /// Time from the end of one burst to the start of the next
+    pub interval: Duration,
+}
+
+impl TricklePlan {
+    pub fn new(grams: f32, duration_minutes: u64, app_config: &AppConfig) -> Self {
+        let burst_grams = app_config
+            .trickle
+            .as_ref()
+            .and_then(|t| t.burst_grams)
+            .unwrap_or(config::TRICKLE_BURST_GRAMS_DEFAULT)
+            .min(grams);
+        let bursts = ((grams / burst_grams).ceil() as u32).max(1);
+        TricklePlan {
+            burst_grams,
+            bursts,
+            interval: Duration::from_secs(duration_minutes * 60) / bursts,
+        }
+    }
+}
+
+/// State of the running trickle dispense, returned by `GET /dispense/trickle`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct TrickleProgress {
+    pub target_grams: f32,
+    pub dispensed_grams: f32,
+    pub bursts_done: u32,
+    /// Bursts of `burst_grams` the target was split into, short bursts add more
+    pub bursts_planned: u32,
+    pub burst_grams: f32,
+    pub paused: bool,
+    pub started_at: String,
+    /// Unset during a burst and while paused
+    pub next_burst_at: Option<String>,
+}
+
+/// The running trickle dispense, kept in the application state so it can be paused.
+#[derive(Debug)]
+pub struct TrickleJob {
+    pub progress: TrickleProgress,
+    pause_tx: watch::Sender<bool>,
+}
+
+impl TrickleJob {
+    /// Returns the job and the receiver the dispense waits on while paused.
+    pub fn new(target_grams: f32, plan: &TricklePlan) -> (Self, watch::Receiver<bool>) {
+        let (pause_tx, pause_rx) = watch::channel(false);
+        let job = TrickleJob {
+            progress: TrickleProgress {
+                target_grams,
+                dispensed_grams: 0.0,
+                bursts_done: 0,
+                bursts_planned: plan.bursts,
+                burst_grams: plan.burst_grams,
+                paused: false,
+                started_at: datetime::get_formatted_current_timestamp(),
+                next_burst_at: None,
+            },
+            pause_tx,
+        };
+        (job, pause_rx)
+    }
+}
+
+fn no_trickle() -> ApiError {
+    ApiError::NotFound("No trickle dispense is running".to_string())
+}
+
+pub async fn progress(app_state: &AppStateMutex) -> Result<TrickleProgress, ApiError> {
+    let state_guard = app_state.lock().await;
+    state_guard
+        .trickle_job
+        .as_ref()
+        .map(|job| job.progress.clone())
+        .ok_or_else(no_trickle)
+}
+
+/// Pauses or resumes the running trickle dispense. A burst in progress is finished first,
+/// and after resuming the next burst follows a full interval later.
+pub async fn set_paused(
+    app_state: &AppStateMutex,
+    paused: bool,
+) -> Result<TrickleProgress, ApiError> {
+    let mut state_guard = app_state.lock().await;
+    let event_bus = state_guard.event_bus.clone();
+    let job = state_guard.trickle_job.as_mut().ok_or_else(no_trickle)?;
+    let changed = job.pause_tx.send_if_modified(|current| {
+        let changed = *current != paused;
+        *current = paused;
+        changed
+    });
+    if changed {
+        job.progress.paused = paused;
+        if paused {
+            job.progress.next_burst_at = None;
+        }
+        let message = if paused {
+            format!(
+                "Trickle dispense paused after {:.1} of {:.1} g",
+                job.progress.dispensed_grams, job.progress.target_grams
+            )
+        } else {
+            "Trickle dispense resumed".to_string()
+        };
+        info!("{}", message);
+        event_bus.publish_trickle_progress(message, job.progress.clone());
+    }
+    Ok(job.progress.clone())
+}
+
+/// Records a finished burst and publishes the progress.
+pub async fn burst_done(app_state: &AppStateMutex, dispensed_grams: f32) {
+    let mut state_guard = app_state.lock().await;
+    let event_bus = state_guard.event_bus.clone();
+    if let Some(job) = state_guard.trickle_job.as_mut() {
+        job.progress.bursts_done += 1;
+        job.progress.dispensed_grams = dispensed_grams;
+        let message = format!(
+            "Trickled {:.1} of {:.1} g ({}/{} bursts)",
+            dispensed_grams,
+            job.progress.target_grams,
+            job.progress.bursts_done,
+            job.progress.bursts_planned
+        );
+        event_bus.publish_trickle_progress(message, job.progress.clone());
+    }
+}
+
+async fn set_next_burst_at(app_state: &AppStateMutex, next_burst_at: Option<SystemTime>) {
+    if let Some(job) = app_state.lock().await.trickle_job.as_mut() {
+        job.progress.next_burst_at = next_burst_at.map(datetime::format_system_time);
+    }
+}
+
+/// Waits `interval` for the next burst. Pausing interrupts the wait until resumed, then
+/// the full interval is waited again.
+pub async fn wait_for_next_burst(
+    app_state: &AppStateMutex,
+    pause_rx: &mut watch::Receiver<bool>,
+    interval: Duration,
+    cancel_token: &CancellationToken,
+) -> Result<(), String> {
+    let cancelled = || "Motor operation cancelled".to_string();
+    loop {
+        if *pause_rx.borrow_and_update() {
+            tokio::select! {
+                _ = pause_rx.wait_for(|paused| !paused) => {}
+                _ = cancel_token.cancelled() => return Err(cancelled()),
+            }
+        }
+        set_next_burst_at(app_state, Some(SystemTime::now() + interval)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => break,
+            _ = pause_rx.changed() => {}
+            _ = cancel_token.cancelled() => return Err(cancelled()),
+        }
+    }
+    set_next_burst_at(app_state, None).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_config(trickle: &str) -> AppConfig {
+        config::load_app_config_from_str(&format!(
+            r#"
+            api:
+              listen_address: "127.0.0.1:0"
+              admin_user: "admin"
+              admin_password: "password"
+            motor:
+              motor_type: "StepperMock"
+            power_monitor:
+              sensor: "SensorMock"
+            weight_monitor:
+              sensor: "SensorMock"
+            {}
+            "#,
+            trickle
+        ))
+    }
+
+    #[test]
+    fn test_trickle_plan() {
+        let plan = TricklePlan::new(20.0, 10, &app_config(""));
+        assert_eq!(plan.burst_grams, 2.0);
+        assert_eq!(plan.bursts, 10);
+        assert_eq!(plan.interval, Duration::from_secs(60));
+
+        // a remainder gets its own burst
+        let plan = TricklePlan::new(
+            5.0,
+            3,
+            &app_config("trickle:\n              burst_grams: 2.0"),
+        );
+        assert_eq!(plan.bursts, 3);
+        assert_eq!(plan.interval, Duration::from_secs(60));
+
+        // less than one burst
+        let plan = TricklePlan::new(1.5, 1, &app_config(""));
+        assert_eq!(plan.burst_grams, 1.5);
+        assert_eq!(plan.bursts, 1);
+    }
+
+    #[test]
+    fn test_trickle_request_validation() {
+        let config = app_config("trickle:\n              max_duration_minutes: 30");
+        let request = |grams, duration_minutes| TrickleRequest {
+            grams,
+            duration_minutes,
+        };
+        assert!(request(20.0, 10).validate(&config).is_ok());
+        assert!(request(20.0, 30).validate(&config).is_ok());
+        match request(0.0, 31).validate(&config) {
+            Err(ApiError::Validation(errors)) => {
+                let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+                assert_eq!(fields, vec!["grams", "duration_minutes"]);
+            }
+            other => panic!("expected validation errors, got {:?}", other),
+        }
+        assert!(request(20.0, 0).validate(&config).is_err());
+    }
+}
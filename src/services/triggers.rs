@@ -0,0 +1,496 @@
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::application_state::AppStateMutex;
+use crate::config::{self, ArbitrationConfig, HookConfig, TriggersConfig};
+use crate::error::ApiError;
+use crate::services::dispenser::{self, DispenseAmount, TriggerSource};
+use crate::services::energy;
+use crate::services::history::{self, DispenseResult, HistoryEntry};
+use crate::utils::state_helpers;
+
+/// Something that can start a dispense. Every trigger goes through `request_dispense`, so
+/// the `triggers` limits apply to new triggers without each reimplementing them.
+pub trait Trigger: Send + Sync {
+    /// Recorded on the dispense span and in the stats
+    fn source(&self) -> TriggerSource;
+
+    /// Shown in logs and errors, rate limits are kept per name
+    fn name(&self) -> String {
+        self.source().to_string()
+    }
+
+    fn amount(&self) -> DispenseAmount {
+        DispenseAmount::Default
+    }
+
+    /// Minimum time between two dispenses of this trigger, on top of `triggers.min_interval_secs`
+    fn min_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Recorded in the dispense history and on the `dispensed` event
+    fn note(&self) -> Option<String> {
+        None
+    }
+}
+
+/// `POST /dispense`
+pub struct ApiTrigger {
+    pub amount: DispenseAmount,
+    pub note: Option<String>,
+}
+
+impl ApiTrigger {
+    pub fn new(amount: DispenseAmount) -> Self {
+        ApiTrigger { amount, note: None }
+    }
+}
+
+impl Trigger for ApiTrigger {
+    fn source(&self) -> TriggerSource {
+        TriggerSource::ApiUser
+    }
+
+    fn amount(&self) -> DispenseAmount {
+        self.amount
+    }
+
+    fn note(&self) -> Option<String> {
+        self.note.clone()
+    }
+}
+
+/// Google Assistant and Alexa
+pub struct AssistantTrigger;
+
+impl Trigger for AssistantTrigger {
+    fn source(&self) -> TriggerSource {
+        TriggerSource::Assistant
+    }
+}
+
+/// Rewards in training mode, see `training`
+pub struct TrainingTrigger(pub DispenseAmount);
+
+impl Trigger for TrainingTrigger {
+    fn source(&self) -> TriggerSource {
+        TriggerSource::Training
+    }
+
+    fn amount(&self) -> DispenseAmount {
+        self.0
+    }
+}
+
+impl Trigger for HookConfig {
+    fn source(&self) -> TriggerSource {
+        TriggerSource::Hook
+    }
+
+    fn name(&self) -> String {
+        format!("hook '{}'", self.name)
+    }
+
+    fn min_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(
+            self.min_interval_secs
+                .unwrap_or(config::HOOK_MIN_INTERVAL_SECS_DEFAULT),
+        ))
+    }
+}
+
+/// Accepted dispenses, for the rate limits and the daily limit, and the open arbitration
+/// round. Kept in memory, so a restart resets them.
+#[derive(Debug, Default)]
+pub struct TriggerLog {
+    last_accepted: HashMap<String, Instant>,
+    last_any: Option<Instant>,
+    day: Option<NaiveDate>,
+    accepted_today: u32,
+    round: Option<ArbitrationRound>,
+}
+
+/// A trigger taking part in an arbitration round.
+#[derive(Debug, Clone)]
+struct Candidate {
+    name: String,
+    source: TriggerSource,
+    amount: DispenseAmount,
+    note: Option<String>,
+}
+
+/// Triggers collected during `coalesce_window_ms`. They all get the outcome of the one
+/// dispense started for the highest priority candidate.
+#[derive(Debug)]
+struct ArbitrationRound {
+    candidates: Vec<Candidate>,
+    outcome_tx: watch::Sender<Option<Result<(), ApiError>>>,
+}
+
+impl TriggerLog {
+    /// Checks whether a dispense by `trigger` is allowed right now.
+    pub(crate) fn check(
+        &self,
+        trigger: &dyn Trigger,
+        triggers_config: Option<&TriggersConfig>,
+        now: NaiveDateTime,
+        instant: Instant,
+    ) -> Result<(), ApiError> {
+        if let Some(triggers_config) = triggers_config {
+            let disabled = triggers_config
+                .arbitration
+                .as_ref()
+                .and_then(|a| a.disabled.as_ref());
+            if disabled.is_some_and(|d| d.contains(&trigger.source())) {
+                return Err(ApiError::Forbidden(format!(
+                    "Dispenses by {} are disabled",
+                    trigger.source()
+                )));
+            }
+            for window in triggers_config.quiet_hours.iter().flatten() {
+                match energy::in_window(window, now.time()) {
+                    Ok(true) => {
+                        return Err(ApiError::Busy(format!(
+                            "Quiet hours {}-{}",
+                            window.start, window.end
+                        )));
+                    }
+                    Ok(false) => {}
+                    Err(e) => warn!("{}", e),
+                }
+            }
+            let accepted_today = if self.day == Some(now.date()) {
+                self.accepted_today
+            } else {
+                0
+            };
+            if let Some(limit) = triggers_config.daily_limit
+                && accepted_today >= limit
+            {
+                return Err(ApiError::RateLimited(format!(
+                    "Daily limit of {} dispenses reached",
+                    limit
+                )));
+            }
+            if let Some(secs) = triggers_config.min_interval_secs {
+                check_interval(
+                    self.last_any,
+                    Duration::from_secs(secs),
+                    instant,
+                    "Dispenses",
+                )?;
+            }
+        }
+        if let Some(min_interval) = trigger.min_interval() {
+            let name = trigger.name();
+            check_interval(
+                self.last_accepted.get(&name).copied(),
+                min_interval,
+                instant,
+                &format!("Dispenses by {}", name),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Counts one dispense, started for all of `names`.
+    pub(crate) fn record<'a>(
+        &mut self,
+        names: impl IntoIterator<Item = &'a str>,
+        now: NaiveDateTime,
+        instant: Instant,
+    ) {
+        if self.day != Some(now.date()) {
+            self.day = Some(now.date());
+            self.accepted_today = 0;
+        }
+        self.accepted_today += 1;
+        self.last_any = Some(instant);
+        for name in names {
+            self.last_accepted.insert(name.to_string(), instant);
+        }
+    }
+}
+
+fn check_interval(
+    last: Option<Instant>,
+    min_interval: Duration,
+    instant: Instant,
+    what: &str,
+) -> Result<(), ApiError> {
+    let Some(last) = last else {
+        return Ok(());
+    };
+    let elapsed = instant.saturating_duration_since(last);
+    if elapsed >= min_interval {
+        return Ok(());
+    }
+    Err(ApiError::RateLimited(format!(
+        "{} are limited, next one possible in {} s",
+        what,
+        min_interval.saturating_sub(elapsed).as_secs() + 1
+    )))
+}
+
+/// The candidate whose source comes first in `priority`, the earliest one among equals.
+/// Sources missing from `priority` come last.
+fn winner(candidates: &[Candidate], priority: &[TriggerSource]) -> Option<Candidate> {
+    candidates
+        .iter()
+        .min_by_key(|c| {
+            priority
+                .iter()
+                .position(|source| *source == c.source)
+                .unwrap_or(priority.len())
+        })
+        .cloned()
+}
+
+/// Starts a dispense for `trigger` if the `triggers` limits and the trigger's own interval
+/// allow it. Only dispenses that started count towards the limits, a busy dispenser
+/// shouldn't lock a trigger out. Failed dispenses are recorded as the last error, and
+/// rejected ones in the dispense history.
+///
+/// With `triggers.arbitration.coalesce_window_ms`, the first trigger opens a round and
+/// triggers arriving within the window join it. Once the window has passed, a single
+/// dispense is started for the highest priority trigger and all of them get its outcome.
+pub async fn request_dispense(
+    app_state: &AppStateMutex,
+    trigger: &dyn Trigger,
+) -> Result<(), ApiError> {
+    let candidate = Candidate {
+        name: trigger.name(),
+        source: trigger.source(),
+        amount: trigger.amount(),
+        note: trigger.note(),
+    };
+    let outcome_rx = {
+        let mut state_guard = app_state.lock().await;
+        if let Err(e) = state_guard.trigger_log.check(
+            trigger,
+            state_guard.app_config.triggers.as_ref(),
+            Local::now().naive_local(),
+            Instant::now(),
+        ) {
+            info!("Dispense by {} rejected: {}", candidate.name, e);
+            history::record(
+                HistoryEntry::new(
+                    candidate.source,
+                    candidate.amount,
+                    &state_guard.app_config,
+                    DispenseResult::Rejected,
+                )
+                .with_reason(e.to_string())
+                .with_note(candidate.note),
+            );
+            return Err(e);
+        }
+
+        let arbitration = state_guard
+            .app_config
+            .triggers
+            .as_ref()
+            .and_then(|c| c.arbitration.clone());
+        let Some(window) = arbitration
+            .as_ref()
+            .and_then(|a| a.coalesce_window_ms)
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis)
+        else {
+            drop(state_guard);
+            info!("Dispense triggered by {}", candidate.name);
+            return start_dispense(app_state, vec![candidate.clone()], candidate).await;
+        };
+
+        match &mut state_guard.trigger_log.round {
+            Some(round) => {
+                info!("Dispense by {} joined the open round", candidate.name);
+                round.candidates.push(candidate);
+                round.outcome_tx.subscribe()
+            }
+            None => {
+                info!(
+                    "Dispense triggered by {}, waiting {} ms for other triggers",
+                    candidate.name,
+                    window.as_millis()
+                );
+                let (outcome_tx, outcome_rx) = watch::channel(None);
+                state_guard.trigger_log.round = Some(ArbitrationRound {
+                    candidates: vec![candidate],
+                    outcome_tx,
+                });
+                // in a task of its own, so a client disconnecting doesn't abandon the round
+                tokio::spawn(run_round(
+                    Arc::clone(app_state),
+                    window,
+                    arbitration.unwrap_or_default(),
+                ));
+                outcome_rx
+            }
+        }
+    };
+    wait_for_outcome(outcome_rx).await
+}
+
+async fn run_round(app_state: AppStateMutex, window: Duration, arbitration: ArbitrationConfig) {
+    tokio::time::sleep(window).await;
+    let Some(round) = app_state.lock().await.trigger_log.round.take() else {
+        return;
+    };
+    let priority = arbitration.priority.unwrap_or_default();
+    let Some(winner) = winner(&round.candidates, &priority) else {
+        return;
+    };
+    if round.candidates.len() > 1 {
+        info!(
+            "Merged {} triggers into one dispense by {}",
+            round.candidates.len(),
+            winner.name
+        );
+    }
+    let outcome = start_dispense(&app_state, round.candidates, winner).await;
+    round.outcome_tx.send_replace(Some(outcome));
+}
+
+async fn wait_for_outcome(
+    mut outcome_rx: watch::Receiver<Option<Result<(), ApiError>>>,
+) -> Result<(), ApiError> {
+    match outcome_rx.wait_for(Option::is_some).await {
+        Ok(outcome) => outcome.clone().unwrap_or(Ok(())),
+        Err(_) => Err(ApiError::Internal(
+            "Trigger arbitration ended without a dispense".to_string(),
+        )),
+    }
+}
+
+async fn start_dispense(
+    app_state: &AppStateMutex,
+    candidates: Vec<Candidate>,
+    winner: Candidate,
+) -> Result<(), ApiError> {
+    let result = dispenser::dispense(
+        Arc::clone(app_state),
+        winner.source,
+        winner.amount,
+        winner.note.clone(),
+    )
+    .await;
+    if let Err(e) = result {
+        let entry = HistoryEntry::new(
+            winner.source,
+            winner.amount,
+            &app_state.lock().await.app_config,
+            DispenseResult::Rejected,
+        )
+        .with_note(winner.note);
+        history::record(entry.with_reason(e.to_string()));
+        state_helpers::record_error(app_state, &e).await;
+        return Err(e);
+    }
+    app_state.lock().await.trigger_log.record(
+        candidates.iter().map(|c| c.name.as_str()),
+        Local::now().naive_local(),
+        Instant::now(),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConserveWindow;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_trigger_limits() {
+        let triggers_config = TriggersConfig {
+            quiet_hours: Some(vec![ConserveWindow {
+                start: "22:00".to_string(),
+                end: "06:00".to_string(),
+            }]),
+            daily_limit: Some(2),
+            min_interval_secs: Some(60),
+            arbitration: Some(ArbitrationConfig {
+                coalesce_window_ms: None,
+                priority: None,
+                disabled: Some(vec![TriggerSource::Assistant]),
+            }),
+        };
+        let api = ApiTrigger::new(DispenseAmount::Default);
+        let hook = HookConfig {
+            name: "a".to_string(),
+            token: "secret".to_string(),
+            min_interval_secs: Some(600),
+        };
+        let start = Instant::now();
+        let mut log = TriggerLog::default();
+        let check = |log: &TriggerLog, trigger: &dyn Trigger, now, secs| {
+            log.check(
+                trigger,
+                Some(&triggers_config),
+                now,
+                start + Duration::from_secs(secs),
+            )
+        };
+
+        assert!(matches!(
+            check(&log, &AssistantTrigger, at("2025-01-01", "12:00"), 0),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(matches!(
+            check(&log, &api, at("2025-01-01", "23:00"), 0),
+            Err(ApiError::Busy(_))
+        ));
+        assert!(check(&log, &hook, at("2025-01-01", "12:00"), 0).is_ok());
+        log.record(["hook 'a'"], at("2025-01-01", "12:00"), start);
+
+        // the global interval applies to other triggers, the hook interval only to the hook
+        assert!(check(&log, &api, at("2025-01-01", "12:00"), 30).is_err());
+        assert!(check(&log, &api, at("2025-01-01", "12:01"), 61).is_ok());
+        assert!(check(&log, &hook, at("2025-01-01", "12:01"), 61).is_err());
+
+        log.record(
+            ["api-user"],
+            at("2025-01-01", "12:01"),
+            start + Duration::from_secs(61),
+        );
+        assert!(matches!(
+            check(&log, &api, at("2025-01-01", "18:00"), 3600),
+            Err(ApiError::RateLimited(_))
+        ));
+        // the daily limit resets at midnight
+        assert!(check(&log, &api, at("2025-01-02", "08:00"), 36000).is_ok());
+    }
+
+    #[test]
+    fn test_arbitration_winner() {
+        let candidate = |name: &str, source| Candidate {
+            name: name.to_string(),
+            source,
+            amount: DispenseAmount::Default,
+            note: None,
+        };
+        let candidates = [
+            candidate("hook 'a'", TriggerSource::Hook),
+            candidate("assistant", TriggerSource::Assistant),
+            candidate("hook 'b'", TriggerSource::Hook),
+        ];
+
+        let winner_name = |priority: &[TriggerSource]| winner(&candidates, priority).unwrap().name;
+        assert_eq!(winner_name(&[]), "hook 'a'");
+        assert_eq!(winner_name(&[TriggerSource::Assistant]), "assistant");
+        assert_eq!(
+            winner_name(&[TriggerSource::ApiUser, TriggerSource::Hook]),
+            "hook 'a'"
+        );
+        assert!(winner(&[], &[]).is_none());
+    }
+}
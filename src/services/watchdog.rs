@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, watch};
+use tracing::{error, info};
+
+use crate::application_state::ApplicationState;
+use crate::config;
+use crate::services::events::EventKind;
+use crate::services::supervisor;
+use crate::utils::state_helpers;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks when a watch channel last received a value.
+struct ChannelWatch {
+    name: &'static str,
+    last_update: Instant,
+    stale: bool,
+}
+
+/// Outcome of a staleness check that needs to be reported.
+#[derive(Debug, PartialEq)]
+enum Transition {
+    BecameStale,
+    Recovered,
+}
+
+impl ChannelWatch {
+    fn new(name: &'static str) -> Self {
+        ChannelWatch {
+            name,
+            last_update: Instant::now(),
+            stale: false,
+        }
+    }
+
+    fn check(&mut self, updated: bool, now: Instant, stale_after: Duration) -> Option<Transition> {
+        if updated {
+            self.last_update = now;
+        }
+        let stale = now.duration_since(self.last_update) > stale_after;
+        if stale == self.stale {
+            return None;
+        }
+        self.stale = stale;
+        Some(if stale {
+            Transition::BecameStale
+        } else {
+            Transition::Recovered
+        })
+    }
+}
+
+/// Returns true if the sender published a value since the last call.
+fn take_update<T>(rx: &mut watch::Receiver<T>) -> bool {
+    let updated = rx.has_changed().unwrap_or(false);
+    if updated {
+        rx.mark_unchanged();
+    }
+    updated
+}
+
+/// Alarms when the weight or power readings channel hasn't been updated within
+/// `watchdog.stale_after_secs`. This catches monitor loops that are still alive but
+/// stuck, e.g. on a blocking lock, which the supervisor can't see. Only channels whose
+/// sensor is present are watched.
+pub async fn start_watchdog(app_state: &Arc<Mutex<ApplicationState>>) {
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "watchdog", move || {
+        run_watchdog(Arc::clone(&app_state_clone))
+    });
+}
+
+async fn run_watchdog(app_state: Arc<Mutex<ApplicationState>>) {
+    let (stale_after, mut power_rx, mut weight_rx, event_bus) = {
+        let state_guard = app_state.lock().await;
+        let stale_after_secs = state_guard
+            .app_config
+            .watchdog
+            .as_ref()
+            .and_then(|w| w.stale_after_secs)
+            .unwrap_or(config::WATCHDOG_STALE_AFTER_SECS_DEFAULT);
+        (
+            Duration::from_secs(stale_after_secs.max(1)),
+            state_guard
+                .power_sensor_mutex
+                .as_ref()
+                .map(|_| state_guard.power_readings_tx.subscribe()),
+            state_guard
+                .weight_sensor_mutex
+                .as_ref()
+                .map(|_| state_guard.weight_readings_tx.subscribe()),
+            state_guard.event_bus.clone(),
+        )
+    };
+    info!(
+        "Starting watchdog, readings are stale after {} s",
+        stale_after.as_secs()
+    );
+
+    let mut power_watch = ChannelWatch::new("power_readings");
+    let mut weight_watch = ChannelWatch::new("weight_readings");
+
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let now = Instant::now();
+
+        let mut transitions = Vec::new();
+        if let Some(rx) = power_rx.as_mut()
+            && let Some(transition) = power_watch.check(take_update(rx), now, stale_after)
+        {
+            transitions.push((power_watch.name, power_watch.last_update, transition));
+        }
+        if let Some(rx) = weight_rx.as_mut()
+            && let Some(transition) = weight_watch.check(take_update(rx), now, stale_after)
+        {
+            transitions.push((weight_watch.name, weight_watch.last_update, transition));
+        }
+
+        for (name, last_update, transition) in transitions {
+            match transition {
+                Transition::BecameStale => {
+                    let message = format!(
+                        "Channel '{}' has not been updated for {} s",
+                        name,
+                        now.duration_since(last_update).as_secs()
+                    );
+                    error!("{}", message);
+                    state_helpers::record_error(&app_state, &message).await;
+                    {
+                        let mut state_guard = app_state.lock().await;
+                        state_guard.stale_channels.push(name.to_string());
+                        state_guard.notify_status_changed();
+                    }
+                    event_bus.publish(EventKind::ChannelStale, message);
+                }
+                Transition::Recovered => {
+                    let message = format!("Channel '{}' is being updated again", name);
+                    info!("{}", message);
+                    {
+                        let mut state_guard = app_state.lock().await;
+                        state_guard.stale_channels.retain(|c| c != name);
+                        state_guard.notify_status_changed();
+                    }
+                    event_bus.publish(EventKind::ChannelRecovered, message);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_watch_transitions() {
+        let stale_after = Duration::from_secs(5);
+        let start = Instant::now();
+        let mut watch = ChannelWatch::new("power_readings");
+        watch.last_update = start;
+
+        assert_eq!(
+            watch.check(false, start + Duration::from_secs(3), stale_after),
+            None
+        );
+        assert_eq!(
+            watch.check(false, start + Duration::from_secs(6), stale_after),
+            Some(Transition::BecameStale)
+        );
+        // only reported once while it stays stale
+        assert_eq!(
+            watch.check(false, start + Duration::from_secs(7), stale_after),
+            None
+        );
+        assert_eq!(
+            watch.check(true, start + Duration::from_secs(8), stale_after),
+            Some(Transition::Recovered)
+        );
+    }
+}
@@ -0,0 +1,506 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{Instrument, debug, info, warn};
+
+use crate::application_state::{ApplicationState, DispenserStatus};
+use crate::config::{self, CameraConfig, DeviceConfig, WebhookConfig, WebhookEvent};
+use crate::services::camera::{self, Snapshot};
+use crate::services::events::{DispenserEvent, EventKind};
+use crate::services::fleet;
+use crate::services::supervisor;
+use crate::utils::datetime;
+
+/// Number of deliveries kept in memory for `GET /webhooks/deliveries`.
+const DELIVERY_LOG_CAPACITY: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Being sent or waiting for a retry
+    Pending,
+    Delivered,
+    /// Gave up after the last attempt or a response that isn't worth retrying
+    Failed,
+}
+
+/// One event sent to one webhook, with the outcome of its latest attempt.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct WebhookDelivery {
+    /// Also sent in the payload, so receivers can drop retried duplicates
+    pub id: u64,
+    pub url: String,
+    /// e.g. dispense_completed | dispense_failed | dispense_cancelled | hopper_low |
+    /// hopper_empty
+    pub event: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, unset if it got no response
+    pub last_status_code: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Recent webhook deliveries, kept in a bounded ring buffer like the event log.
+pub struct DeliveryLog {
+    next_id: AtomicU64,
+    deliveries: std::sync::Mutex<VecDeque<WebhookDelivery>>,
+}
+
+impl Default for DeliveryLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeliveryLog {
+    pub fn new() -> Self {
+        DeliveryLog {
+            next_id: AtomicU64::new(1),
+            deliveries: std::sync::Mutex::new(VecDeque::with_capacity(DELIVERY_LOG_CAPACITY)),
+        }
+    }
+
+    /// Records a new pending delivery and returns its id.
+    fn start(&self, url: &str, event: WebhookEvent) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = datetime::get_formatted_current_timestamp();
+        let mut deliveries = self.deliveries.lock().unwrap_or_else(|e| e.into_inner());
+        if deliveries.len() == DELIVERY_LOG_CAPACITY {
+            deliveries.pop_front();
+        }
+        deliveries.push_back(WebhookDelivery {
+            id,
+            url: url.to_string(),
+            event: webhook_event_name(event),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            last_status_code: None,
+            last_error: None,
+            created_at: now.clone(),
+            updated_at: now,
+        });
+        id
+    }
+
+    /// Records an attempt. Deliveries already pushed out of the buffer are left alone.
+    fn record_attempt(
+        &self,
+        id: u64,
+        status: DeliveryStatus,
+        status_code: Option<u16>,
+        error: Option<String>,
+    ) {
+        let mut deliveries = self.deliveries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(delivery) = deliveries.iter_mut().find(|d| d.id == id) {
+            delivery.status = status;
+            delivery.attempts += 1;
+            delivery.last_status_code = status_code;
+            delivery.last_error = error;
+            delivery.updated_at = datetime::get_formatted_current_timestamp();
+        }
+    }
+
+    /// Returns recorded deliveries, oldest first.
+    pub fn recent(&self) -> Vec<WebhookDelivery> {
+        self.deliveries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Body POSTed to the webhook URLs.
+#[derive(Serialize, Debug)]
+struct WebhookPayload<'a> {
+    event: WebhookEvent,
+    delivery_id: u64,
+    device: Option<&'a DeviceConfig>,
+    /// The event as published on the event bus, as in `GET /events`
+    data: &'a DispenserEvent,
+    /// Camera picture after the dispense, for webhooks with `attach_snapshot`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<&'a Snapshot>,
+}
+
+/// A failed attempt, and whether another one could succeed.
+struct AttemptFailure {
+    status_code: Option<u16>,
+    error: String,
+    retry: bool,
+}
+
+fn webhook_event_name(event: WebhookEvent) -> String {
+    serde_json::to_value(event)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// The webhook event for a dispenser event, `None` for events webhooks aren't sent for.
+fn webhook_event_for(event: &DispenserEvent) -> Option<WebhookEvent> {
+    match (&event.kind, &event.status) {
+        (EventKind::Dispensed, _) => Some(WebhookEvent::DispenseCompleted),
+        (EventKind::DispenseFailed, _) => Some(WebhookEvent::DispenseFailed),
+        (EventKind::DispenseCancelled, _) => Some(WebhookEvent::DispenseCancelled),
+        (EventKind::HopperLow, _) => Some(WebhookEvent::HopperLow),
+        (EventKind::StatusChanged, Some(DispenserStatus::Empty)) => Some(WebhookEvent::HopperEmpty),
+        _ => None,
+    }
+}
+
+/// Whether the webhook gets a snapshot with the event, which needs a `camera` section.
+fn wants_snapshot(
+    webhook: &WebhookConfig,
+    event: WebhookEvent,
+    camera: Option<&CameraConfig>,
+) -> bool {
+    event == WebhookEvent::DispenseCompleted
+        && camera.is_some()
+        && webhook.attach_snapshot.unwrap_or(false)
+}
+
+fn subscribes_to(webhook: &WebhookConfig, event: WebhookEvent) -> bool {
+    webhook
+        .events
+        .as_ref()
+        .is_none_or(|events| events.contains(&event))
+}
+
+/// Delay before the retry following `failed_attempts` failures: `retry_delay_ms`, doubling
+/// with every further failure up to `WEBHOOK_BACKOFF_MAX_MS`.
+fn retry_delay(webhook: &WebhookConfig, failed_attempts: u32) -> Duration {
+    let first = Duration::from_millis(
+        webhook
+            .retry_delay_ms
+            .unwrap_or(config::WEBHOOK_RETRY_DELAY_MS_DEFAULT),
+    );
+    let max = Duration::from_millis(config::WEBHOOK_BACKOFF_MAX_MS).max(first);
+    first
+        .saturating_mul(2u32.saturating_pow(failed_attempts.saturating_sub(1).min(16)))
+        .min(max)
+}
+
+/// Starts POSTing dispense and hopper events to the `webhooks` if any are configured.
+pub async fn start_webhook_sender(app_state: &Arc<Mutex<ApplicationState>>) {
+    let (webhooks, event_bus, delivery_log, device_config, camera) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.app_config.webhooks.clone().unwrap_or_default(),
+            state_guard.event_bus.clone(),
+            state_guard.webhook_deliveries.clone(),
+            state_guard.app_config.device.clone(),
+            state_guard.app_config.camera.clone(),
+        )
+    };
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Webhooks disabled: {}", e);
+            return;
+        }
+    };
+    info!("Sending events to {} webhooks", webhooks.len());
+
+    let webhooks = Arc::new(webhooks);
+    supervisor::spawn_supervised(app_state, "webhook_sender", move || {
+        let webhooks = Arc::clone(&webhooks);
+        let delivery_log = Arc::clone(&delivery_log);
+        let device_config = device_config.clone();
+        let camera = camera.clone();
+        let client = client.clone();
+        let mut events = event_bus.subscribe();
+        async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Webhook sender skipped {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let Some(webhook_event) = webhook_event_for(&event) else {
+                    continue;
+                };
+
+                let (with_snapshot, without_snapshot): (Vec<_>, Vec<_>) = webhooks
+                    .iter()
+                    .filter(|w| subscribes_to(w, webhook_event))
+                    .map(|w| (w.clone(), delivery_log.start(&w.url, webhook_event)))
+                    .partition(|(w, _)| wants_snapshot(w, webhook_event, camera.as_ref()));
+                let deliveries = Deliveries {
+                    client: client.clone(),
+                    delivery_log: Arc::clone(&delivery_log),
+                    device_config: device_config.clone(),
+                    event,
+                    webhook_event,
+                };
+                deliveries.start(without_snapshot, None);
+                if let Some(camera) = camera.clone()
+                    && !with_snapshot.is_empty()
+                {
+                    // the camera waits for the treats to land, so the other events
+                    // aren't held up
+                    tokio::spawn(
+                        async move {
+                            let snapshot =
+                                match camera::take_snapshot(&deliveries.client, &camera).await {
+                                    Ok(snapshot) => Some(snapshot),
+                                    Err(e) => {
+                                        warn!("Sending the webhooks without a snapshot: {}", e);
+                                        None
+                                    }
+                                };
+                            deliveries.start(with_snapshot, snapshot.as_ref());
+                        }
+                        .in_current_span(),
+                    );
+                }
+            }
+        }
+    });
+}
+
+/// The deliveries of one event.
+struct Deliveries {
+    client: reqwest::Client,
+    delivery_log: Arc<DeliveryLog>,
+    device_config: Option<DeviceConfig>,
+    event: DispenserEvent,
+    webhook_event: WebhookEvent,
+}
+
+impl Deliveries {
+    /// Sends the event to the webhooks, under the delivery ids recorded for them.
+    fn start(&self, webhooks: Vec<(WebhookConfig, u64)>, snapshot: Option<&Snapshot>) {
+        for (webhook, id) in webhooks {
+            let payload = WebhookPayload {
+                event: self.webhook_event,
+                delivery_id: id,
+                device: self.device_config.as_ref(),
+                data: &self.event,
+                snapshot,
+            };
+            let body = match serde_json::to_vec(&payload) {
+                Ok(body) => body,
+                Err(e) => {
+                    self.delivery_log.record_attempt(
+                        id,
+                        DeliveryStatus::Failed,
+                        None,
+                        Some(e.to_string()),
+                    );
+                    continue;
+                }
+            };
+            // retries wait, so every delivery gets its own task and a slow endpoint
+            // doesn't hold up the others
+            tokio::spawn(
+                deliver(
+                    self.client.clone(),
+                    webhook,
+                    Arc::clone(&self.delivery_log),
+                    id,
+                    body,
+                )
+                .in_current_span(),
+            );
+        }
+    }
+}
+
+/// Sends one delivery, retrying network errors, `429` and `5xx` responses with backoff
+/// until `max_attempts` is reached.
+async fn deliver(
+    client: reqwest::Client,
+    webhook: WebhookConfig,
+    delivery_log: Arc<DeliveryLog>,
+    id: u64,
+    body: Vec<u8>,
+) {
+    let max_attempts = webhook
+        .max_attempts
+        .unwrap_or(config::WEBHOOK_MAX_ATTEMPTS_DEFAULT)
+        .max(1);
+    for attempt in 1..=max_attempts {
+        match send(&client, &webhook, &body).await {
+            Ok(status_code) => {
+                delivery_log.record_attempt(id, DeliveryStatus::Delivered, Some(status_code), None);
+                debug!("Webhook delivery {} sent to {}", id, webhook.url);
+                return;
+            }
+            Err(failure) => {
+                let gave_up = !failure.retry || attempt == max_attempts;
+                let status = if gave_up {
+                    DeliveryStatus::Failed
+                } else {
+                    DeliveryStatus::Pending
+                };
+                warn!(
+                    "Webhook delivery {} to {} failed (attempt {} of {}): {}",
+                    id, webhook.url, attempt, max_attempts, failure.error
+                );
+                delivery_log.record_attempt(id, status, failure.status_code, Some(failure.error));
+                if gave_up {
+                    return;
+                }
+                tokio::time::sleep(retry_delay(&webhook, attempt)).await;
+            }
+        }
+    }
+}
+
+async fn send(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    body: &[u8],
+) -> Result<u16, AttemptFailure> {
+    let mut request = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(secret) = &webhook.secret {
+        request = request.header(fleet::SIGNATURE_HEADER, fleet::sign(secret, body));
+    }
+    let response = request
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|e| AttemptFailure {
+            status_code: None,
+            error: e.to_string(),
+            retry: true,
+        })?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16());
+    }
+    Err(AttemptFailure {
+        status_code: Some(status.as_u16()),
+        error: format!("Webhook returned {}", status),
+        retry: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind, status: Option<DispenserStatus>) -> DispenserEvent {
+        DispenserEvent {
+            kind,
+            message: "Dispense failed (trigger: api-user): stalled".to_string(),
+            timestamp: "2025-01-01 12:00:00".to_string(),
+            status,
+            progress: None,
+            trigger: None,
+            input: None,
+            output: None,
+            meal: None,
+            trickle: None,
+        }
+    }
+
+    fn webhook(events: Option<Vec<WebhookEvent>>) -> WebhookConfig {
+        WebhookConfig {
+            url: "http://127.0.0.1:9/hook".to_string(),
+            events,
+            secret: None,
+            max_attempts: None,
+            retry_delay_ms: Some(500),
+            attach_snapshot: None,
+        }
+    }
+
+    #[test]
+    fn test_webhook_event_for() {
+        assert_eq!(
+            webhook_event_for(&event(EventKind::DispenseFailed, None)),
+            Some(WebhookEvent::DispenseFailed)
+        );
+        assert_eq!(
+            webhook_event_for(&event(
+                EventKind::StatusChanged,
+                Some(DispenserStatus::Empty)
+            )),
+            Some(WebhookEvent::HopperEmpty)
+        );
+        assert_eq!(
+            webhook_event_for(&event(
+                EventKind::StatusChanged,
+                Some(DispenserStatus::Jammed)
+            )),
+            None
+        );
+        assert_eq!(
+            webhook_event_for(&event(EventKind::HopperLow, None)),
+            Some(WebhookEvent::HopperLow)
+        );
+        assert_eq!(
+            webhook_event_for(&event(EventKind::DispenseProgress, None)),
+            None
+        );
+
+        assert!(subscribes_to(&webhook(None), WebhookEvent::HopperEmpty));
+        let failures_only = webhook(Some(vec![WebhookEvent::DispenseFailed]));
+        assert!(subscribes_to(&failures_only, WebhookEvent::DispenseFailed));
+        assert!(!subscribes_to(
+            &failures_only,
+            WebhookEvent::DispenseCompleted
+        ));
+        assert_eq!(
+            webhook_event_name(WebhookEvent::HopperEmpty),
+            "hopper_empty"
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_max() {
+        let webhook = webhook(None);
+        assert_eq!(retry_delay(&webhook, 1), Duration::from_millis(500));
+        assert_eq!(retry_delay(&webhook, 2), Duration::from_millis(1000));
+        assert_eq!(retry_delay(&webhook, 4), Duration::from_millis(4000));
+        assert_eq!(
+            retry_delay(&webhook, 40),
+            Duration::from_millis(config::WEBHOOK_BACKOFF_MAX_MS)
+        );
+    }
+
+    #[test]
+    fn test_delivery_log_is_bounded() {
+        let log = DeliveryLog::new();
+        for _ in 0..DELIVERY_LOG_CAPACITY + 5 {
+            log.start("http://127.0.0.1:9/hook", WebhookEvent::DispenseCompleted);
+        }
+        let recent = log.recent();
+        assert_eq!(recent.len(), DELIVERY_LOG_CAPACITY);
+        assert_eq!(recent[0].id, 6);
+
+        let id = recent.last().unwrap().id;
+        log.record_attempt(
+            id,
+            DeliveryStatus::Failed,
+            Some(404),
+            Some("Not found".to_string()),
+        );
+        let delivery = log.recent().pop().unwrap();
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_status_code, Some(404));
+    }
+}
@@ -0,0 +1,394 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+use crate::application_state::ApplicationState;
+use crate::config;
+use crate::error::{ApiError, FieldError};
+use crate::services::supervisor;
+use crate::utils::{datetime, filesystem};
+
+/// How long a write waits for another connection to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Averages older than the retention are deleted once per this many minutes.
+const PRUNE_INTERVAL_MINUTES: i64 = 60;
+const TIME_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+
+/// Average hopper weight over `interval_minutes` starting at `time`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct WeightSnapshot {
+    pub time: String,
+    pub grams: f32,
+    pub min_grams: f32,
+    pub max_grams: f32,
+}
+
+/// Response of `GET /weight/history`, oldest snapshot first.
+#[derive(Serialize, Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct WeightTimeline {
+    pub from: String,
+    pub to: String,
+    pub interval_minutes: u32,
+    /// Intervals without settled readings are left out
+    pub snapshots: Vec<WeightSnapshot>,
+}
+
+/// Validated `from`, `to` and `interval_minutes` query parameters, in minutes since the
+/// Unix epoch. `to_minute` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelineRange {
+    pub from_minute: i64,
+    pub to_minute: i64,
+    pub interval_minutes: u32,
+}
+
+impl TimelineRange {
+    /// Times are local, as elsewhere in the API. `to` defaults to `now`, `from` to
+    /// `WEIGHT_HISTORY_RANGE_HOURS_DEFAULT` before `to`.
+    pub fn from_query(
+        from: Option<&str>,
+        to: Option<&str>,
+        interval_minutes: Option<u32>,
+        now: DateTime<Local>,
+    ) -> Result<Self, ApiError> {
+        let mut errors = Vec::new();
+        let mut parse = |field: &str, value: Option<&str>| match value.map(parse_local_time) {
+            None => None,
+            Some(Some(secs)) => Some(secs),
+            Some(None) => {
+                errors.push(FieldError::new(
+                    field,
+                    "must be a local time like 2025-01-01 12:00:00 or a date",
+                ));
+                None
+            }
+        };
+        let to_secs = parse("to", to).unwrap_or(now.timestamp());
+        let from_secs = parse("from", from)
+            .unwrap_or(to_secs - config::WEIGHT_HISTORY_RANGE_HOURS_DEFAULT * 3600);
+        let interval_minutes = interval_minutes.unwrap_or(1);
+
+        if !(1..=config::WEIGHT_HISTORY_INTERVAL_MINUTES_MAX).contains(&interval_minutes) {
+            errors.push(FieldError::new(
+                "interval_minutes",
+                format!(
+                    "must be between 1 and {}",
+                    config::WEIGHT_HISTORY_INTERVAL_MINUTES_MAX
+                ),
+            ));
+        }
+        let (from_minute, to_minute) = (from_secs.div_euclid(60), to_secs.div_euclid(60));
+        if errors.is_empty() {
+            if from_minute >= to_minute {
+                errors.push(FieldError::new(
+                    "from",
+                    "must be at least a minute before to",
+                ));
+            } else if (to_minute - from_minute) / interval_minutes as i64
+                > config::WEIGHT_HISTORY_POINTS_MAX
+            {
+                errors.push(FieldError::new(
+                    "interval_minutes",
+                    format!(
+                        "too small for the time range, at most {} snapshots are returned",
+                        config::WEIGHT_HISTORY_POINTS_MAX
+                    ),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(TimelineRange {
+                from_minute,
+                to_minute,
+                interval_minutes,
+            })
+        } else {
+            Err(ApiError::Validation(errors))
+        }
+    }
+}
+
+/// Seconds since the Unix epoch of a local time or date (midnight).
+fn parse_local_time(value: &str) -> Option<i64> {
+    let naive = TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+fn format_minute(minute: i64) -> String {
+    datetime::format_system_time(UNIX_EPOCH + Duration::from_secs(minute.max(0) as u64 * 60))
+}
+
+fn current_minute() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+        / 60
+}
+
+/// Settled readings of the minute in progress.
+#[derive(Debug, Clone, PartialEq)]
+struct MinuteAverage {
+    minute: i64,
+    sum_grams: f64,
+    samples: u32,
+    min_grams: f32,
+    max_grams: f32,
+}
+
+impl MinuteAverage {
+    fn new(minute: i64) -> Self {
+        MinuteAverage {
+            minute,
+            sum_grams: 0.0,
+            samples: 0,
+            min_grams: f32::INFINITY,
+            max_grams: f32::NEG_INFINITY,
+        }
+    }
+
+    fn add(&mut self, grams: f32) {
+        self.sum_grams += grams as f64;
+        self.samples += 1;
+        self.min_grams = self.min_grams.min(grams);
+        self.max_grams = self.max_grams.max(grams);
+    }
+
+    fn grams(&self) -> f32 {
+        (self.sum_grams / self.samples.max(1) as f64) as f32
+    }
+}
+
+fn open(path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS weight_history (
+            minute INTEGER PRIMARY KEY,
+            grams REAL NOT NULL,
+            min_grams REAL NOT NULL,
+            max_grams REAL NOT NULL,
+            samples INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(conn)
+}
+
+fn insert(conn: &Connection, average: &MinuteAverage) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO weight_history (minute, grams, min_grams, max_grams, samples)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            average.minute,
+            average.grams(),
+            average.min_grams,
+            average.max_grams,
+            average.samples,
+        ],
+    )?;
+    Ok(())
+}
+
+fn prune(conn: &Connection, before_minute: i64) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM weight_history WHERE minute < ?1",
+        params![before_minute],
+    )
+}
+
+fn query_range(conn: &Connection, range: TimelineRange) -> rusqlite::Result<Vec<WeightSnapshot>> {
+    let mut statement = conn.prepare(
+        "SELECT minute / ?3 AS bucket, SUM(grams * samples) / SUM(samples),
+                MIN(min_grams), MAX(max_grams)
+         FROM weight_history WHERE minute >= ?1 AND minute < ?2
+         GROUP BY bucket ORDER BY bucket",
+    )?;
+    let interval = range.interval_minutes as i64;
+    statement
+        .query_map(
+            params![range.from_minute, range.to_minute, interval],
+            |row| {
+                let bucket: i64 = row.get(0)?;
+                let grams: f64 = row.get(1)?;
+                let min_grams: f64 = row.get(2)?;
+                let max_grams: f64 = row.get(3)?;
+                Ok(WeightSnapshot {
+                    time: format_minute(bucket * interval),
+                    grams: grams as f32,
+                    min_grams: min_grams as f32,
+                    max_grams: max_grams as f32,
+                })
+            },
+        )?
+        .collect()
+}
+
+/// Records the average of the settled weight readings of every minute to
+/// `weight_history.db` in the data directory, unless `weight_history.enabled` is false or
+/// there is no weight sensor. Averages older than `retention_days` are deleted.
+pub async fn start_weight_history_recorder(app_state: &Arc<Mutex<ApplicationState>>) {
+    let retention_days = {
+        let state_guard = app_state.lock().await;
+        let history_config = state_guard.app_config.weight_history.as_ref();
+        if history_config.and_then(|c| c.enabled) == Some(false) {
+            return;
+        }
+        if state_guard.weight_sensor_mutex.is_none() {
+            info!("No weight sensor, not recording the weight history");
+            return;
+        }
+        history_config
+            .and_then(|c| c.retention_days)
+            .unwrap_or(config::WEIGHT_HISTORY_RETENTION_DAYS_DEFAULT)
+    };
+
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "weight_history", move || {
+        run_recorder(Arc::clone(&app_state_clone), retention_days)
+    });
+}
+
+async fn run_recorder(app_state: Arc<Mutex<ApplicationState>>, retention_days: u32) {
+    let mut weight_readings_rx = app_state.lock().await.weight_readings_rx.clone();
+    let mut current: Option<MinuteAverage> = None;
+
+    // readings are only published while the weight monitor runs
+    while weight_readings_rx.changed().await.is_ok() {
+        let reading = weight_readings_rx.borrow_and_update().clone();
+        if !reading.settled {
+            continue;
+        }
+        let minute = current_minute();
+        if let Some(average) = current.take_if(|average| average.minute != minute) {
+            record(average, retention_days);
+        }
+        current
+            .get_or_insert_with(|| MinuteAverage::new(minute))
+            .add(reading.grams);
+    }
+}
+
+fn record(average: MinuteAverage, retention_days: u32) {
+    tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_weight_history_db_path();
+        let result = open(&path).and_then(|conn| {
+            insert(&conn, &average)?;
+            if average.minute % PRUNE_INTERVAL_MINUTES == 0 {
+                let deleted = prune(&conn, average.minute - retention_days as i64 * 24 * 60)?;
+                if deleted > 0 {
+                    debug!("Deleted {} weight averages past the retention", deleted);
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            error!("Failed to record weight average in {}: {}", path, e);
+        }
+    });
+}
+
+/// Recorded weight averages in `range`, merged into `interval_minutes` snapshots.
+pub async fn timeline(range: TimelineRange) -> Result<WeightTimeline, ApiError> {
+    let snapshots = tokio::task::spawn_blocking(move || {
+        let path = filesystem::get_weight_history_db_path();
+        open(&path)
+            .and_then(|conn| query_range(&conn, range))
+            .map_err(|e| ApiError::Internal(format!("Failed to read weight history: {}", e)))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Weight history task failed: {}", e)))??;
+
+    Ok(WeightTimeline {
+        from: format_minute(range.from_minute),
+        to: format_minute(range.to_minute),
+        interval_minutes: range.interval_minutes,
+        snapshots,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn average(minute: i64, readings: &[f32]) -> MinuteAverage {
+        let mut average = MinuteAverage::new(minute);
+        for grams in readings {
+            average.add(*grams);
+        }
+        average
+    }
+
+    #[test]
+    fn test_query_range_merges_intervals() {
+        let conn = open(":memory:").unwrap();
+        insert(&conn, &average(100, &[500.0, 510.0])).unwrap();
+        insert(&conn, &average(101, &[490.0])).unwrap();
+        insert(&conn, &average(105, &[480.0])).unwrap();
+
+        let range = |from_minute, to_minute, interval_minutes| TimelineRange {
+            from_minute,
+            to_minute,
+            interval_minutes,
+        };
+        let snapshots = query_range(&conn, range(100, 106, 1)).unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0].grams, 505.0);
+        assert_eq!(snapshots[0].min_grams, 500.0);
+        assert_eq!(snapshots[0].time, format_minute(100));
+
+        // weighted by the number of readings
+        let snapshots = query_range(&conn, range(100, 106, 5)).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].grams, 500.0);
+        assert_eq!(snapshots[0].max_grams, 510.0);
+        assert_eq!(snapshots[1].time, format_minute(105));
+
+        assert!(query_range(&conn, range(101, 105, 1)).unwrap().len() == 1);
+        assert_eq!(prune(&conn, 105).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_timeline_range_from_query() {
+        let now = parse_local_time("2025-01-08 12:00:30")
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .unwrap()
+            .with_timezone(&Local);
+
+        let range = TimelineRange::from_query(None, None, None, now).unwrap();
+        assert_eq!(range.to_minute - range.from_minute, 24 * 60);
+        assert_eq!(range.interval_minutes, 1);
+
+        let range = TimelineRange::from_query(Some("2025-01-01"), None, Some(60), now).unwrap();
+        assert_eq!(format_minute(range.from_minute), "2025-01-01 00:00:00");
+        assert_eq!(format_minute(range.to_minute), "2025-01-08 12:00:00");
+
+        let range =
+            TimelineRange::from_query(Some("2025-01-08T06:30:00"), None, None, now).unwrap();
+        assert_eq!(format_minute(range.from_minute), "2025-01-08 06:30:00");
+
+        for (from, to, interval) in [
+            (Some("yesterday"), None, None),
+            (Some("2025-01-09"), None, None),
+            (None, None, Some(0)),
+            (Some("2024-01-01"), None, Some(1)),
+        ] {
+            assert!(TimelineRange::from_query(from, to, interval, now).is_err());
+        }
+    }
+}
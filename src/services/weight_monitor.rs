@@ -1,87 +1,149 @@
 use crate::application_state::{self, ApplicationState};
-use crate::sensors::{WeightSensorCalibration};
+use crate::config;
+use crate::error::{ApiError, FieldError};
+use crate::sensors::{LoadCellStatus, WeightSensorCalibration};
+use crate::services::error_reporting::{self, ErrorKind};
+use crate::services::events::EventKind;
+use crate::services::motor_vibration::VibrationFilter;
+use crate::services::supervisor;
 use crate::utils::state_helpers;
 use crate::utils::filesystem;
 use crate::application_state::DispenserStatus;
 use crate::sensors::WeightReading;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::sync::{Arc, atomic::Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::TryRecvError;
 use tokio::time::{MissedTickBehavior, interval};
 use tracing::{debug, error, warn, info, trace};
 
 /// Spawns an asynchronous task that periodically reads the weight sensor (if present)
 /// and publishes processed weight readings to subscribers. Skips sampling while a
-/// calibration (tare or scale) operation is in progress.
+/// calibration (tare or scale) operation is in progress. The task is restarted by the
+/// supervisor if it panics.
 ///
 /// * `app_state` - Shared application state containing sensor handles and channels.
 pub async fn start_weight_monitoring_thread(app_state: &Arc<Mutex<ApplicationState>>) {
-    tokio::spawn({
-        let app_state_clone = Arc::clone(app_state);
-        let sensor_mutex_opt = app_state_clone.lock().await.weight_sensor_mutex.clone();
-        let weight_readings_tx = app_state_clone.lock().await.weight_readings_tx.clone();
-        let calibration_in_progress =
-            Arc::clone(&app_state_clone.lock().await.calibration_in_progress);
+    let app_state_clone = Arc::clone(app_state);
+    supervisor::spawn_supervised(app_state, "weight_monitor", move || {
+        run_weight_monitor(Arc::clone(&app_state_clone))
+    });
+}
+
+async fn run_weight_monitor(app_state: Arc<Mutex<ApplicationState>>) {
+    let (sensor_mutex_opt, weight_readings_tx, calibration_in_progress, calibration_rx, mut settings_rx, mut events_rx, vibration_config) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.weight_sensor_mutex.clone(),
+            state_guard.weight_readings_tx.clone(),
+            Arc::clone(&state_guard.calibration_in_progress),
+            state_guard.calibration_rx.clone(),
+            // a clone of the initial receiver, so settings changed before a restart by the
+            // supervisor are applied again
+            state_guard.weight_sensor_settings_rx.clone(),
+            state_guard.event_bus.subscribe(),
+            state_guard.app_config.weight_monitor.motor_vibration.clone(),
+        )
+    };
+    let mut vibration_filter = vibration_config.as_ref().map(VibrationFilter::new);
 
-        let calibration_rx = app_state_clone.lock().await.calibration_rx.clone();
+    match sensor_mutex_opt {
+        Some(sensor_mutex) => {
+            info!("Starting weight monitoring thread");
 
-        async move {
-            match sensor_mutex_opt {
-                Some(sensor_mutex) => {
-                    info!("Starting weight monitoring thread");
+            // If RATE=L (10 SPS): period ~100 ms. If RATE=H (80 SPS): ~12–15 ms.
+            let mut tick = interval(Duration::from_millis(15));
+            tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-                    // If RATE=L (10 SPS): period ~100 ms. If RATE=H (80 SPS): ~12–15 ms.
-                    let mut tick = interval(Duration::from_millis(15));
-                    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut samples: Vec<WeightReading> = Vec::new();
+            let mut suppressed_ticks = 0;
+            let mut failing_cells = BTreeSet::new();
 
-                    let mut samples: Vec<WeightReading> = Vec::new();
+            loop {
+                tick.tick().await;
 
+                if let Some(filter) = vibration_filter.as_mut() {
                     loop {
-                        tick.tick().await;
+                        match events_rx.try_recv() {
+                            Ok(event) => filter.observe(&event, Instant::now()),
+                            Err(TryRecvError::Lagged(_)) => continue,
+                            Err(_) => break,
+                        }
+                    }
+                }
 
-                        if samples.len() >= 30 {
-                            // Every 30 samples (450 ms approx), calculate and publish the trimmed mean (reduces noise and outliers)
-                            let mean_weight = calculate_trimmed_mean(
-                                &mut samples.iter().map(|r| r.grams).collect::<Vec<f32>>()
-                            );
+                if samples.len() >= 30 || suppressed_ticks >= 30 {
+                    // Every 30 samples (450 ms approx), calculate and publish the trimmed mean (reduces noise and outliers)
+                    let mean_weight = (!samples.is_empty()).then(|| calculate_trimmed_mean(
+                        &mut samples.iter().map(|r| r.grams).collect::<Vec<f32>>()
+                    ));
+
+                    let mean_reading = match vibration_filter.as_mut() {
+                        Some(filter) => filter.reading(mean_weight, Instant::now()),
+                        None => mean_weight.map(|grams| WeightReading {
+                            grams,
+                            settled: true,
+                        }),
+                    };
+
+                    if let Some(mean_reading) = mean_reading {
+                        let _ = weight_readings_tx.send(mean_reading);
+                    }
+                    samples.clear();
+                    suppressed_ticks = 0;
+
+                    let cell_statuses = sensor_mutex.lock().await.get_cell_statuses();
+                    report_failing_cells(&app_state, &cell_statuses, &mut failing_cells).await;
+
+                    // between two averages and outside calibrations, so no average or
+                    // calibration mixes readings taken with old and new settings
+                    if settings_rx.has_changed().unwrap_or(false)
+                        && !calibration_in_progress.load(Ordering::Relaxed)
+                    {
+                        let settings = settings_rx.borrow_and_update().clone();
+                        let result = sensor_mutex.lock().await.apply_settings(&settings);
+                        report_settings_applied(&app_state, result).await;
+                    }
+                }
 
-                            let mean_reading = WeightReading {
-                                grams: mean_weight,
-                            };
+                if calibration_in_progress.load(Ordering::Relaxed) {
+                    debug!("Calibration in progress, skipping weight reading");
+                    continue;
+                }
 
-                            let _ = weight_readings_tx.send(mean_reading);
-                            samples.clear();
-                        }
+                if vibration_filter.as_ref().is_some_and(|f| f.suppressing()) {
+                    trace!("Motor running, skipping weight reading");
+                    suppressed_ticks += 1;
+                    continue;
+                }
 
-                        if calibration_in_progress.load(Ordering::Relaxed) {
-                            debug!("Calibration in progress, skipping weight reading");
-                            continue;
-                        }
+                let calibration = calibration_rx.borrow().clone();
+                let reading_result = {
+                    let mut sensor = sensor_mutex.lock().await;
+                    sensor.get_weight_reading(&calibration)
+                };
 
-                        let calibration = calibration_rx.borrow().clone();
-                        let reading_result = {
-                            let mut sensor = sensor_mutex.lock().await;
-                            sensor.get_weight_reading(&calibration)
-                        };
-
-                        match reading_result {
-                            Ok(weight) => {
-                                trace!("Weight reading: {:?}", weight);
-                                samples.push(weight.clone());
-                            }
-                            Err(e) => {
-                                trace!("Failed to read weight: {}", e);
-                            }
-                        }
+                match reading_result {
+                    Ok(weight) => {
+                        trace!("Weight reading: {:?}", weight);
+                        samples.push(weight.clone());
+                    }
+                    Err(e) => {
+                        trace!("Failed to read weight: {}", e);
                     }
-                }
-                None => {
-                    error!("No weight sensor available");
                 }
             }
         }
-    });
+        None => {
+            error!("No weight sensor available");
+            error_reporting::report(
+                ErrorKind::BackgroundTask,
+                "Weight monitor could not start, no weight sensor available",
+            );
+        }
+    }
 }
 
 /// Performs a scale calibration using a known mass placed on the load cell.
@@ -95,25 +157,22 @@ pub async fn start_weight_monitoring_thread(app_state: &Arc<Mutex<ApplicationSta
 pub async fn calibrate_weight_sensor(
     app_state: Arc<Mutex<ApplicationState>>,
     known_mass_grams: f32,
-) -> Result<CalibrationResponse, String> {
+) -> Result<CalibrationResponse, ApiError> {
     let app_state = Arc::clone(&app_state);
 
-    let dispenser_status = app_state.lock().await.status.clone();
-    if dispenser_status != DispenserStatus::Operational && dispenser_status != DispenserStatus::Cancelled {
-        return Err("Dispenser is not operational, cannot calibrate".to_string());
-    }
-
-    let calibration_in_progress = app_state.lock().await.calibration_in_progress.clone();
-    calibration_in_progress.store(true, Ordering::Relaxed);
+    let calibration_in_progress = begin_calibration(&app_state, "calibrate").await?;
 
-    state_helpers::set_dispenser_status_async(
-        &app_state,
-        application_state::DispenserStatus::Calibrating,
-    ).await;
-
-    let (calibration_rx, calibration_tx) = {
+    let (calibration_rx, calibration_tx, geometry_factor) = {
         let app_state_lock = app_state.lock().await;
-        (app_state_lock.calibration_rx.clone(), app_state_lock.calibration_tx.clone())
+        (
+            app_state_lock.calibration_rx.clone(),
+            app_state_lock.calibration_tx.clone(),
+            app_state_lock
+                .app_config
+                .weight_monitor
+                .geometry_factor
+                .unwrap_or(config::WEIGHT_GEOMETRY_FACTOR_DEFAULT),
+        )
     };
 
     // Get the current calibration state
@@ -142,25 +201,35 @@ pub async fn calibrate_weight_sensor(
             tokio::time::sleep(Duration::from_millis(15)).await;
         }
     } else {
+        calibration_in_progress.store(false, Ordering::Relaxed);
         state_helpers::set_dispenser_status_async(
             &app_state,
             application_state::DispenserStatus::CalibrationFailed,
         ).await;
-        return Err("No weight sensor available".to_string());
+        return Err(ApiError::Hardware("No weight sensor available".to_string()));
     }
 
     calibration_in_progress.store(false, Ordering::Relaxed);
 
     let mean_raw = calculate_trimmed_mean(&mut samples);
 
-    // Calculate the scale factor
-    let mut scale = (mean_raw - calibration.tare_raw as f32) / known_mass_grams;
+    // Calculate the scale factor for the load cell alone, the sensor applies the geometry
+    // factor on top
+    let mut scale =
+        (mean_raw - calibration.tare_raw as f32) * geometry_factor / known_mass_grams;
     if scale < 0.0 {
         scale = scale.abs();
     }
 
     calibration.scale = scale;
     let _ = calibration_tx.send(calibration.clone());
+    app_state.lock().await.event_bus.publish(
+        EventKind::WeightSensorCalibrated,
+        format!(
+            "Weight sensor calibrated with {} g, scale factor {:.4}",
+            known_mass_grams, scale
+        ),
+    );
 
     // save the updated calibration to file
     if let Err(e) = save_calibration_to_file(&calibration) {
@@ -186,21 +255,10 @@ pub async fn calibrate_weight_sensor(
 /// Returns updated calibration metadata including the new tare value or an error.
 pub async fn tare_weight_sensor(
     app_state: Arc<Mutex<ApplicationState>>,
-) -> Result<CalibrationResponse, String> {
+) -> Result<CalibrationResponse, ApiError> {
     let app_state = Arc::clone(&app_state);
 
-    let dispenser_status = app_state.lock().await.status.clone();
-    if dispenser_status != DispenserStatus::Operational && dispenser_status != DispenserStatus::Cancelled {
-        return Err("Dispenser is not operational, cannot tare".to_string());
-    }
-
-    let calibration_in_progress = app_state.lock().await.calibration_in_progress.clone();
-    calibration_in_progress.store(true, Ordering::Relaxed);
-
-    state_helpers::set_dispenser_status_async(
-        &app_state,
-        application_state::DispenserStatus::Calibrating,
-    ).await;
+    let calibration_in_progress = begin_calibration(&app_state, "tare").await?;
 
     let (calibration_rx, calibration_tx) = {
         let app_state_lock = app_state.lock().await;
@@ -212,6 +270,8 @@ pub async fn tare_weight_sensor(
 
     let sensor_mutex_opt = app_state.lock().await.weight_sensor_mutex.clone();
     let mut samples: Vec<f32> = Vec::with_capacity(300);
+    // per load cell of a fused sensor
+    let mut cell_samples: Vec<Vec<f32>> = Vec::new();
 
     if let Some(sensor_mutex) = sensor_mutex_opt {
         // get approx 3 seconds of samples from weight sensor
@@ -220,11 +280,15 @@ pub async fn tare_weight_sensor(
         for _ in 0..300 {
             let read_result = {
                 let mut sensor = sensor_mutex.lock().await;
-                sensor.get_raw()
+                sensor.get_raw_with_cells()
             };
             match read_result {
-                Ok(reading) => {
+                Ok((reading, cell_readings)) => {
                     samples.push(reading as f32);
+                    cell_samples.resize_with(cell_readings.len(), Vec::new);
+                    for (cell, cell_reading) in cell_samples.iter_mut().zip(cell_readings) {
+                        cell.push(cell_reading as f32);
+                    }
                 }
                 Err(e) => {
                     trace!("Failed to read weight during tare: {}", e);
@@ -238,21 +302,29 @@ pub async fn tare_weight_sensor(
             &app_state,
             application_state::DispenserStatus::CalibrationFailed,
         ).await;
-        return Err("No weight sensor available".to_string());
+        return Err(ApiError::Hardware("No weight sensor available".to_string()));
     }
 
     calibration_in_progress.store(false, Ordering::Relaxed);
     let tare_raw = calculate_trimmed_mean(&mut samples);
 
     calibration.tare_raw = tare_raw as i32;
+    calibration.cell_tare_raw = cell_samples
+        .iter_mut()
+        .map(|cell| calculate_trimmed_mean(cell) as i32)
+        .collect();
 
     let calibration_publish_result = calibration_tx.send(calibration.clone());
     if calibration_publish_result.is_err() {
         error!("Failed to publish tare calibration");
-        return Err("Failed to publish tare calibration".to_string());
+        return Err(ApiError::Internal("Failed to publish tare calibration".to_string()));
     }
 
     info!("Tare completed, tare_raw: {}", tare_raw);
+    app_state.lock().await.event_bus.publish(
+        EventKind::WeightSensorTared,
+        format!("Weight sensor tared, tare_raw {}", calibration.tare_raw),
+    );
 
     state_helpers::set_dispenser_status_async(
         &app_state,
@@ -265,9 +337,108 @@ pub async fn tare_weight_sensor(
     })
 }
 
+/// Acknowledges changed weight sensor settings with an event, or reports why the sensor
+/// kept its previous ones.
+async fn report_settings_applied(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    result: Result<(), String>,
+) {
+    let state_guard = app_state.lock().await;
+    match result {
+        Ok(()) => {
+            info!("Weight sensor settings applied");
+            state_guard
+                .event_bus
+                .publish(EventKind::WeightSensorSettingsApplied, "Weight sensor settings applied");
+        }
+        Err(e) => {
+            let message = format!("Weight sensor kept its previous settings: {}", e);
+            warn!("{}", message);
+            state_guard
+                .event_bus
+                .publish(EventKind::WeightSensorSettingsRejected, message);
+        }
+    }
+}
+
+/// Logs and publishes load cells of a fused sensor that started or stopped failing since
+/// the last check. `failing_cells` holds the names of the failing ones.
+async fn report_failing_cells(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    cell_statuses: &[LoadCellStatus],
+    failing_cells: &mut BTreeSet<String>,
+) {
+    for cell in cell_statuses {
+        if cell.failing && !failing_cells.contains(&cell.name) {
+            let message = match (&cell.error, cell.share) {
+                (Some(e), _) => format!("Load cell '{}' is failing: {}", cell.name, e),
+                (None, Some(share)) => format!(
+                    "Load cell '{}' is failing, it carries {:.0}% of the weight instead of {:.0}%",
+                    cell.name,
+                    share * 100.0,
+                    cell.expected_share * 100.0
+                ),
+                (None, None) => format!("Load cell '{}' is failing", cell.name),
+            };
+            warn!("{}", message);
+            failing_cells.insert(cell.name.clone());
+            state_helpers::record_error(app_state, &message).await;
+            app_state
+                .lock()
+                .await
+                .event_bus
+                .publish(EventKind::LoadCellFailing, message);
+        } else if !cell.failing && failing_cells.remove(&cell.name) {
+            let message = format!("Load cell '{}' reads as expected again", cell.name);
+            info!("{}", message);
+            app_state
+                .lock()
+                .await
+                .event_bus
+                .publish(EventKind::LoadCellRecovered, message);
+        }
+    }
+}
+
+/// Switches the dispenser to `Calibrating` if it is idle, checked and set under one lock so
+/// a dispense can't start in between. Returns the flag that pauses the weight monitor,
+/// already set.
+async fn begin_calibration(
+    app_state: &Arc<Mutex<ApplicationState>>,
+    action: &str,
+) -> Result<Arc<std::sync::atomic::AtomicBool>, ApiError> {
+    let mut state_guard = app_state.lock().await;
+    match state_guard.status {
+        DispenserStatus::Operational | DispenserStatus::Cancelled => {}
+        DispenserStatus::Dispensing
+        | DispenserStatus::Cooldown
+        | DispenserStatus::Stirring
+        | DispenserStatus::MeasuringCurrent => {
+            return Err(ApiError::Busy(format!(
+                "Cannot {} while the dispenser is {}",
+                action, state_guard.status
+            )));
+        }
+        DispenserStatus::Calibrating => {
+            return Err(ApiError::Busy(
+                "Weight sensor calibration is already in progress".to_string(),
+            ));
+        }
+        _ => {
+            return Err(ApiError::Hardware(format!(
+                "Dispenser is not operational, cannot {} (current status: {:?})",
+                action, state_guard.status
+            )));
+        }
+    }
+    state_guard.set_status(DispenserStatus::Calibrating);
+    state_guard.calibration_in_progress.store(true, Ordering::Relaxed);
+    Ok(Arc::clone(&state_guard.calibration_in_progress))
+}
+
 /// Response returned by calibration/tare endpoints containing a human-friendly
 /// message and the updated calibration state.
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
 pub struct CalibrationResponse {
     pub msg: String,
     pub calibration: WeightSensorCalibration,
@@ -275,15 +446,32 @@ pub struct CalibrationResponse {
 
 /// Request payload for scale calibration; carries the known mass (in grams)
 /// currently placed on the load cell.
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::ToSchema)]
 pub struct CalibrationRequest {
     pub known_mass_grams: f32,
 }
 
+impl CalibrationRequest {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if !(self.known_mass_grams > 0.0
+            && self.known_mass_grams <= config::CALIBRATION_KNOWN_MASS_GRAMS_MAX)
+        {
+            return Err(ApiError::Validation(vec![FieldError::new(
+                "known_mass_grams",
+                format!(
+                    "must be greater than 0 and at most {}",
+                    config::CALIBRATION_KNOWN_MASS_GRAMS_MAX
+                ),
+            )]));
+        }
+        Ok(())
+    }
+}
+
 /// Computes a 20% trimmed mean (removes the lowest and highest 20% of values)
 /// from the supplied sample slice, returning a f32. Helps reject outliers
 /// and reduce noise in raw load cell readings.
-fn calculate_trimmed_mean(samples: &mut [f32]) -> f32 {
+pub fn calculate_trimmed_mean(samples: &mut [f32]) -> f32 {
     samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
     let n = samples.len();
     let k = (n * 20) / 100;
@@ -296,10 +484,8 @@ fn calculate_trimmed_mean(samples: &mut [f32]) -> f32 {
 
     // subslice that excludes lowest k and highest k samples,
     let slice = &samples[k..n-k];
-    let sum: f32 = slice.iter().map(|&v| v).sum();
-    let trimmed_mean = sum as f32 / (slice.len() as f32);
-
-    trimmed_mean
+    let sum: f32 = slice.iter().sum();
+    sum / (slice.len() as f32)
 }
 
 pub fn save_calibration_to_file(
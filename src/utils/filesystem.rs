@@ -1,9 +1,75 @@
+use std::sync::OnceLock;
+
+/// Directory holding the config file and all persisted dispenser data.
+/// Defaults to `/etc/treat-dispenser-api`, override with `DISPENSER_DATA_DIR`.
+pub fn get_data_dir() -> String {
+    std::env::var("DISPENSER_DATA_DIR").unwrap_or_else(|_| "/etc/treat-dispenser-api".to_string())
+}
+
+/// Config file set with `--config`, read instead of `config.yaml` in the data directory.
+static CONFIG_PATH: OnceLock<String> = OnceLock::new();
+
+/// Reads the config from `path` instead of the data directory. Only the first call counts.
+pub fn set_config_path(path: String) {
+    let _ = CONFIG_PATH.set(path);
+}
+
 pub fn get_config_path() -> String {
-    "/etc/treat-dispenser-api/config.yaml".to_string() // todo: make this configurable
+    CONFIG_PATH
+        .get()
+        .cloned()
+        .unwrap_or_else(|| format!("{}/config.yaml", get_data_dir()))
 }
 
 pub fn get_calibration_file_path() -> String {
-    "/etc/treat-dispenser-api/weight_sensor_calibration.json".to_string() // todo: make this configurable
+    format!("{}/weight_sensor_calibration.json", get_data_dir())
+}
+
+pub fn get_notification_devices_file_path() -> String {
+    format!("{}/notification_devices.json", get_data_dir())
+}
+
+pub fn get_dispense_stats_file_path() -> String {
+    format!("{}/dispense_stats.json", get_data_dir())
+}
+
+/// Meals eaten from the bowl, see `bowl`.
+pub fn get_bowl_stats_file_path() -> String {
+    format!("{}/bowl_stats.json", get_data_dir())
+}
+
+/// SQLite database of all dispense attempts, see `history`.
+pub fn get_dispense_history_db_path() -> String {
+    format!("{}/dispense_history.db", get_data_dir())
+}
+
+/// SQLite database of minute averages of the hopper weight, see `weight_history`.
+pub fn get_weight_history_db_path() -> String {
+    format!("{}/weight_history.db", get_data_dir())
+}
+
+pub fn get_status_shares_file_path() -> String {
+    format!("{}/status_shares.json", get_data_dir())
+}
+
+/// Feeding schedules saved with `PUT /schedules/export`, see `schedule_export`.
+pub fn get_schedules_file_path() -> String {
+    format!("{}/schedules.json", get_data_dir())
+}
+
+/// Append-only record of safety-relevant actions and who took them, see `audit_log`.
+pub fn get_audit_log_path() -> String {
+    format!("{}/audit.log", get_data_dir())
+}
+
+/// Format version of each persisted file, see `migrations`.
+pub fn get_data_versions_file_path() -> String {
+    format!("{}/data_versions.json", get_data_dir())
+}
+
+/// Only exists while a dispense is running, see `dispense_recovery`.
+pub fn get_dispense_journal_file_path() -> String {
+    format!("{}/dispense_in_progress.json", get_data_dir())
 }
 
 pub fn save_json_to_file<T: serde::Serialize>(path: &str, data: &T) -> Result<(), String> {
@@ -14,4 +80,4 @@ pub fn save_json_to_file<T: serde::Serialize>(path: &str, data: &T) -> Result<()
 pub fn read_json_from_file<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, String> {
     let json_data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
     serde_json::from_str(&json_data).map_err(|e| e.to_string())
-}
\ No newline at end of file
+}
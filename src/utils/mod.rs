@@ -1,3 +1,5 @@
 pub mod datetime;
 pub mod filesystem;
 pub mod state_helpers;
+pub mod tls;
+pub mod units;
@@ -4,7 +4,7 @@ use crate::application_state::{self, ApplicationState, DispenserStatus};
 use crate::utils::datetime;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, info};
+use tracing::info;
 
 /// Records an error message and timestamp in the dispenser state
 /// This function is asynchronous and locks the state mutex to ensure thread safety.
@@ -13,6 +13,7 @@ pub async fn record_error<E: Display>(hw_state: &Arc<Mutex<ApplicationState>>, e
     let mut state_lock = hw_state.lock().await;
     state_lock.last_error_msg = Some(error.to_string());
     state_lock.last_error_time = Some(datetime::get_formatted_current_timestamp());
+    state_lock.notify_status_changed();
 }
 
 /// Acquires a lock on the DispenserState and sets the dispenser status synchronously.
@@ -22,7 +23,7 @@ pub fn set_dispenser_status(
 ) {
     let mut state_guard = state.blocking_lock();
 
-    state_guard.status = status.clone();
+    state_guard.set_status(status.clone());
     info!("Dispenser status set to {:?}", status);
 }
 
@@ -33,6 +34,6 @@ pub async fn set_dispenser_status_async(
 ) {
     let mut state_guard = state.lock().await;
 
-    state_guard.status = status.clone();
+    state_guard.set_status(status.clone());
     info!("Dispenser status set to {:?}", status);
 }
@@ -0,0 +1,257 @@
+use axum::serve::Listener;
+use rustls::ServerConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
+
+use crate::config::{self, TlsConfig};
+
+/// Connections that finished their handshake but weren't picked up by the server yet.
+const ACCEPTED_QUEUE_SIZE: usize = 64;
+
+/// Loads the certificate chain and key from `tls_config` into a rustls server config. Both
+/// HTTP/2 and HTTP/1.1 are offered over ALPN.
+pub fn load_server_config(tls_config: &TlsConfig) -> Result<ServerConfig, String> {
+    let resolver = ReloadingCertResolver::new(
+        tls_config.clone(),
+        Duration::from_secs(config::TLS_CERT_RELOAD_CHECK_SECS),
+    )?;
+    let mut server_config = ServerConfig::builder_with_provider(Arc::new(crypto_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+fn crypto_provider() -> CryptoProvider {
+    rustls::crypto::ring::default_provider()
+}
+
+fn load_certified_key(tls_config: &TlsConfig) -> Result<CertifiedKey, String> {
+    let cert_chain = CertificateDer::pem_file_iter(&tls_config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificate {}: {}", tls_config.cert_path, e))?;
+    if cert_chain.is_empty() {
+        return Err(format!("No certificate found in {}", tls_config.cert_path));
+    }
+    let key = PrivateKeyDer::from_pem_file(&tls_config.key_path)
+        .map_err(|e| format!("Failed to read private key {}: {}", tls_config.key_path, e))?;
+    CertifiedKey::from_der(cert_chain, key, &crypto_provider())
+        .map_err(|e| format!("Invalid certificate or key: {}", e))
+}
+
+fn modified_times(tls_config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((
+        modified(&tls_config.cert_path)?,
+        modified(&tls_config.key_path)?,
+    ))
+}
+
+/// Serves the certificate from `cert_path` and `key_path` and reads both again once they
+/// change, e.g. after certbot renewed the certificate. The files are looked at during a
+/// handshake, at most once per `check_interval`. A pair that doesn't load, e.g. because
+/// only one of them was replaced yet, is retried at the next check and the current
+/// certificate stays in use until then.
+#[derive(Debug)]
+struct ReloadingCertResolver {
+    tls_config: TlsConfig,
+    check_interval: Duration,
+    loaded: Mutex<LoadedCert>,
+}
+
+#[derive(Debug)]
+struct LoadedCert {
+    key: Arc<CertifiedKey>,
+    modified: Option<(SystemTime, SystemTime)>,
+    checked_at: Instant,
+}
+
+impl ReloadingCertResolver {
+    fn new(tls_config: TlsConfig, check_interval: Duration) -> Result<Self, String> {
+        let modified = modified_times(&tls_config);
+        let key = load_certified_key(&tls_config)?;
+        Ok(ReloadingCertResolver {
+            tls_config,
+            check_interval,
+            loaded: Mutex::new(LoadedCert {
+                key: Arc::new(key),
+                modified,
+                checked_at: Instant::now(),
+            }),
+        })
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if loaded.checked_at.elapsed() >= self.check_interval {
+            loaded.checked_at = Instant::now();
+            let modified = modified_times(&self.tls_config);
+            if modified != loaded.modified {
+                match load_certified_key(&self.tls_config) {
+                    Ok(key) => {
+                        info!("Reloaded TLS certificate {}", self.tls_config.cert_path);
+                        loaded.key = Arc::new(key);
+                        loaded.modified = modified;
+                    }
+                    Err(e) => warn!("Keeping the current TLS certificate: {}", e),
+                }
+            }
+        }
+        Arc::clone(&loaded.key)
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// Accepts TCP connections and hands them to the server once their TLS handshake is done.
+/// Handshakes run in their own tasks, so a slow or silent client doesn't hold up others.
+pub struct TlsListener {
+    accepted: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(mut listener: TcpListener, server_config: ServerConfig) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let (sender, accepted) = mpsc::channel(ACCEPTED_QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = tokio::select! {
+                    connection = Listener::accept(&mut listener) => connection,
+                    // the server stopped, close the socket
+                    _ = sender.closed() => break,
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let handshake = tokio::time::timeout(
+                        Duration::from_secs(config::TLS_HANDSHAKE_TIMEOUT_SECS),
+                        acceptor.accept(stream),
+                    );
+                    match handshake.await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, remote_addr)).await;
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", remote_addr, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", remote_addr),
+                    }
+                });
+            }
+        });
+
+        Ok(TlsListener {
+            accepted,
+            local_addr,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(connection) => connection,
+            // the accept task only stops once this listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_server_config_missing_files() {
+        let error = load_server_config(&TlsConfig {
+            cert_path: "/nonexistent/cert.pem".to_string(),
+            key_path: "/nonexistent/key.pem".to_string(),
+        })
+        .unwrap_err();
+        assert!(error.contains("/nonexistent/cert.pem"));
+    }
+
+    #[test]
+    fn test_certificate_reloaded_when_replaced() {
+        let dir = std::env::temp_dir().join(format!("treat-dispenser-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tls_config = TlsConfig {
+            cert_path: dir.join("cert.pem").display().to_string(),
+            key_path: dir.join("key.pem").display().to_string(),
+        };
+        // copies with a fresh modification time, so a replacement is always noticed
+        let install = |cert: &str, key: &str, modified: SystemTime| {
+            for (from, to) in [(cert, &tls_config.cert_path), (key, &tls_config.key_path)] {
+                std::fs::write(to, std::fs::read(from).unwrap()).unwrap();
+                std::fs::File::options()
+                    .write(true)
+                    .open(to)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+            }
+        };
+        let fixture = |name: &str| format!("tests/fixtures/tls/{}", name);
+        let first_cert = |path: &str| {
+            CertificateDer::pem_file_iter(path)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap()
+        };
+        let now = SystemTime::now();
+
+        install(&fixture("cert.pem"), &fixture("key.pem"), now);
+        let resolver = ReloadingCertResolver::new(tls_config.clone(), Duration::ZERO).unwrap();
+        assert_eq!(resolver.current().cert[0], first_cert(&fixture("cert.pem")));
+
+        install(
+            &fixture("renewed_cert.pem"),
+            &fixture("renewed_key.pem"),
+            now + Duration::from_secs(1),
+        );
+        assert_eq!(
+            resolver.current().cert[0],
+            first_cert(&fixture("renewed_cert.pem"))
+        );
+
+        // a key that doesn't match keeps the renewed certificate in use
+        install(
+            &fixture("cert.pem"),
+            &fixture("renewed_key.pem"),
+            now + Duration::from_secs(2),
+        );
+        assert_eq!(
+            resolver.current().cert[0],
+            first_cert(&fixture("renewed_cert.pem"))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
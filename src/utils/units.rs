@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const GRAMS_PER_OUNCE: f32 = 28.349_523;
+
+/// Unit weights are shown in. Grams are always the canonical unit stored and
+/// reported by the API, other units are derived for display.
+#[derive(
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Default,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum WeightUnit {
+    #[default]
+    Grams,
+    Ounces,
+}
+
+impl WeightUnit {
+    pub fn convert_grams(self, grams: f32) -> f32 {
+        match self {
+            WeightUnit::Grams => grams,
+            WeightUnit::Ounces => grams / GRAMS_PER_OUNCE,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            WeightUnit::Grams => "g",
+            WeightUnit::Ounces => "oz",
+        }
+    }
+}
+
+impl fmt::Display for WeightUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeightUnit::Grams => write!(f, "grams"),
+            WeightUnit::Ounces => write!(f, "ounces"),
+        }
+    }
+}
+
+/// A weight converted to the configured display unit, serialized next to the
+/// canonical `*_grams` field.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, utoipa::ToSchema)]
+pub struct DisplayWeight {
+    pub value: f32,
+    pub unit: WeightUnit,
+    pub symbol: String,
+}
+
+impl DisplayWeight {
+    pub fn from_grams(grams: f32, unit: WeightUnit) -> Self {
+        DisplayWeight {
+            value: unit.convert_grams(grams),
+            unit,
+            symbol: unit.symbol().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weight_unit_conversion() {
+        assert_eq!(WeightUnit::Grams.convert_grams(100.0), 100.0);
+        assert!((WeightUnit::Ounces.convert_grams(100.0) - 3.527_396).abs() < 1e-4);
+
+        let display = DisplayWeight::from_grams(GRAMS_PER_OUNCE, WeightUnit::Ounces);
+        assert!((display.value - 1.0).abs() < 1e-6);
+        assert_eq!(
+            serde_json::to_value(&display).unwrap()["unit"],
+            serde_json::json!("ounces")
+        );
+    }
+}
@@ -6,12 +6,51 @@ use std::sync::Once;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 use tracing::info;
-use treat_dispenser_api::application_state::ApplicationState;
-use treat_dispenser_api::build_app;
+use treat_dispenser_api::application_state::{ApplicationState, ComponentState, DispenserStatus};
+use treat_dispenser_api::{DispenserBuilder, build_app};
+use treat_dispenser_api::routes::route_table::{RouteAuth, RouteInfo};
+use treat_dispenser_api::sensors::{WeightReading, WeightSensor, WeightSensorCalibration};
 use treat_dispenser_api::services::power_monitor::start_power_monitoring_thread;
-use treat_dispenser_api::services::status::StatusResponse;
+use treat_dispenser_api::services::auth::SessionResponse;
+use treat_dispenser_api::services::status::{
+    StatusResponse, StatusResponseV1, StatusResponseV2, SummaryResponse,
+};
+use treat_dispenser_api::config::{
+    AppConfig, AppConfigBuilder, CurrentJamConfig, DeviceConfig, HopperLevelConfig,
+    JamDetectionConfig, RateLimitConfig, RateLimitRule, SessionCookieConfig, StirConfig,
+    TriggersConfig,
+};
+use treat_dispenser_api::services::migrations;
+use treat_dispenser_api::utils::filesystem;
+use treat_dispenser_api::services::scheduler;
+use treat_dispenser_api::services::stir;
+use treat_dispenser_api::services::dispense_recovery::{self, DispenseJournal};
+use treat_dispenser_api::services::current_calibration::CurrentCalibrationResponse;
+use treat_dispenser_api::services::dispenser::TriggerSource;
+use treat_dispenser_api::services::events::EventKind;
+use treat_dispenser_api::services::fan::{self, FanMode};
+use treat_dispenser_api::services::history::{DispenseResult, HistoryPage};
+use treat_dispenser_api::services::weight_history::WeightTimeline;
+use treat_dispenser_api::services::humidity;
+use treat_dispenser_api::services::hopper_level;
+use treat_dispenser_api::services::supervisor;
+use treat_dispenser_api::services::temperature_monitor;
+use treat_dispenser_api::services::training;
+use treat_dispenser_api::services::watchdog;
+use treat_dispenser_api::services::webhooks::{self, DeliveryStatus, WebhookDelivery};
 
-async fn setup(config: Option<Box<&str>>) -> (SocketAddr, Client, Arc<Mutex<ApplicationState>>) {
+async fn setup(config: Option<&str>) -> (SocketAddr, Client, Arc<Mutex<ApplicationState>>) {
+    let config = match config {
+        Some(config_str) => {
+            info!("Using config: {}", config_str);
+            treat_dispenser_api::config::load_app_config_from_str(config_str)
+        }
+        None => test_config().build(),
+    };
+    setup_config(config).await
+}
+
+async fn setup_config(config: AppConfig) -> (SocketAddr, Client, Arc<Mutex<ApplicationState>>) {
     dotenv::from_filename(".env.test").ok();
     init_logging();
     let (addr, app_state) = start_server(config).await;
@@ -21,15 +60,18 @@ async fn setup(config: Option<Box<&str>>) -> (SocketAddr, Client, Arc<Mutex<Appl
 
 static INIT: Once = Once::new();
 
+/// Held by tests that write `config.yaml` or `schedules.json` in the shared data directory,
+/// a config reload in another test would read them.
+static CONFIG_FILES: Mutex<()> = Mutex::const_new(());
+
 pub fn init_logging() {
     INIT.call_once(|| {
-        // Initialize logging
-        tracing_subscriber::fmt()
-            .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-            .with_ansi(true)
-            .with_thread_ids(true)
-            .with_thread_names(true)
-            .init();
+        // Use the application's logging setup so the runtime log level handle is available
+        treat_dispenser_api::configure_logging();
+        // as at startup, the data directory may be left from an older build
+        std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+        let failures = migrations::run_migrations();
+        assert!(failures.is_empty(), "{:?}", failures);
     });
 }
 
@@ -37,28 +79,15 @@ async fn wait_for_server(millis: u64) {
     tokio::time::sleep(tokio::time::Duration::from_millis(millis)).await;
 }
 
-async fn start_server(config: Option<Box<&str>>) -> (SocketAddr, Arc<Mutex<ApplicationState>>) {
-    let config_str = config.unwrap_or_else(|| {
-        Box::new(
-        r#"
-        api:
-          listen_address: "127.0.0.1:0"
-          admin_user: "admin"
-          admin_password: "password"
-        power_monitor:
-          sensor: "SensorMock"
-          motor_current_limit_amps: 0.7
-        weight_monitor:
-          sensor: "SensorMock"
-        motor:
-          motor_type: "StepperMock"
-          cooldown_ms: 5000
-        "#,
-        )
-    });
-    info!("Using config: {}", config_str);
+/// Mock hardware on a free port, the base of the test configs.
+fn test_config() -> AppConfigBuilder {
+    AppConfigBuilder::new("admin", "password")
+        .listen_address("127.0.0.1:0")
+        .cooldown_ms(5000)
+        .motor_current_limit_amps(0.7)
+}
 
-    let config = treat_dispenser_api::config::load_app_config_from_str(config_str.as_ref());
+async fn start_server(config: AppConfig) -> (SocketAddr, Arc<Mutex<ApplicationState>>) {
     let (_app_state, app) = build_app(config.clone());
     let listener = TcpListener::bind(config.api.listen_address).await.unwrap();
     let addr = listener.local_addr().unwrap();
@@ -113,8 +142,30 @@ async fn post_with_auth(client: &Client, addr: SocketAddr, path: &str) -> reqwes
     req.send().await.unwrap()
 }
 
+async fn put_json_with_auth(
+    client: &Client,
+    addr: SocketAddr,
+    path: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    let login_response = login(client, addr, "admin", "password").await;
+    let token = login_response.token;
+    let url = format!("http://{}{}", addr, path);
+    let req = client.put(&url).json(&body);
+    let req = req.header("Authorization", format!("Bearer {}", token));
+    req.send().await.unwrap()
+}
+
+/// The status in its latest version.
 async fn get_hardware_status(client: &Client, addr: SocketAddr) -> StatusResponse {
-    let response = get_with_auth(client, addr, "/status").await;
+    let token = login(client, addr, "admin", "password").await.token;
+    let response = client
+        .get(format!("http://{}/status", addr))
+        .bearer_auth(token)
+        .header("Accept", "application/vnd.treat-dispenser.status.v3+json")
+        .send()
+        .await
+        .unwrap();
     assert!(
         response.status().is_success(),
         "Expected success, got: {}",
@@ -140,8 +191,8 @@ async fn test_status_endpoint() {
 
     let status_json = response.json::<StatusResponse>().await.unwrap();
 
-    assert!(status_json.gpio_available == false);
-    assert!(status_json.treats_available == false);
+    assert!(!status_json.gpio_available);
+    assert!(status_json.treats_available);
     assert!(
         status_json.uptime_seconds > 0,
         "Uptime should be greater than 0"
@@ -159,6 +210,239 @@ async fn test_status_endpoint() {
     assert_eq!(status_json.motor_current_amps, Some(0.0));
     assert_eq!(status_json.motor_power_watts, Some(0.0));
     assert_eq!(status_json.motor_power_sensor, "SensorMock");
+    assert!(status_json.device.is_none());
+    assert!(status_json.quarantined_files.is_empty());
+}
+
+#[tokio::test]
+async fn test_status_reports_device() {
+    let (addr, client, _) = setup_config(
+        test_config()
+            .device(DeviceConfig {
+                name: "barn-feeder".to_string(),
+                location: Some("Barn, left stall".to_string()),
+                fleet_id: Some("farm".to_string()),
+            })
+            .build(),
+    )
+    .await;
+
+    let device = get_hardware_status(&client, addr).await.device.unwrap();
+    assert_eq!(device.name, "barn-feeder");
+    assert_eq!(device.location.as_deref(), Some("Barn, left stall"));
+    assert_eq!(device.fleet_id.as_deref(), Some("farm"));
+}
+
+#[tokio::test]
+async fn test_login_with_hashed_password() {
+    let hash = treat_dispenser_api::services::auth::hash_password("password").unwrap();
+    let (addr, client, _) = setup_config(
+        AppConfigBuilder::new("admin", &hash)
+            .listen_address("127.0.0.1:0")
+            .build(),
+    )
+    .await;
+
+    assert!(!login(&client, addr, "admin", "password").await.token.is_empty());
+    let response = client
+        .post(format!("http://{}/login", addr))
+        .json(&serde_json::json!({ "username": "admin", "password": hash }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_login_rate_limited() {
+    let (addr, client, _) = setup_config(
+        test_config()
+            .rate_limit(RateLimitConfig {
+                login: Some(RateLimitRule {
+                    requests: 2,
+                    per_seconds: 60,
+                }),
+                ..Default::default()
+            })
+            .build(),
+    )
+    .await;
+    let attempt = async || {
+        client
+            .post(format!("http://{}/login", addr))
+            .json(&serde_json::json!({ "username": "admin", "password": "wrong" }))
+            .send()
+            .await
+            .unwrap()
+    };
+
+    // failed logins use up the requests too
+    assert_eq!(attempt().await.status(), reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(attempt().await.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = attempt().await;
+    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "30");
+
+    // other endpoints aren't limited
+    let response = client
+        .get(format!("http://{}/summary", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_status_redacted_without_token() {
+    let (addr, client, app_state) = setup_config(
+        test_config()
+            .status_redaction(&["errors", "power", "last_backup", "last_dispense"])
+            .build(),
+    )
+    .await;
+    {
+        let mut state_guard = app_state.lock().await;
+        state_guard.last_error_msg = Some("I2C bus error on 0x40".to_string());
+        state_guard.last_dispense_time = Some("2025-01-01 12:00:00".to_string());
+    }
+
+    let anonymous: serde_json::Value = client
+        .get(format!("http://{}/status", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    for field in ["last_error_msg", "hardware", "motor_current_amps", "last_backup"] {
+        assert!(anonymous.get(field).is_none(), "{} not redacted", field);
+    }
+    assert_eq!(anonymous["motor"], "StepperMock");
+
+    let admin: serde_json::Value = get_with_auth(&client, addr, "/status").await.json().await.unwrap();
+    assert_eq!(admin["last_error_msg"], "I2C bus error on 0x40");
+    assert!(admin.get("motor_current_amps").is_some());
+
+    // an invalid token gets the redacted view too
+    let response = client
+        .get(format!("http://{}/status", addr))
+        .bearer_auth("not-a-token")
+        .send()
+        .await
+        .unwrap();
+    let invalid: serde_json::Value = response.json().await.unwrap();
+    assert!(invalid.get("last_error_msg").is_none());
+
+    let anonymous: serde_json::Value = client
+        .get(format!("http://{}/summary", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(anonymous.get("last_dispense").is_none());
+    assert_eq!(anonymous["status"], "Operational");
+    let admin: serde_json::Value = get_with_auth(&client, addr, "/summary").await.json().await.unwrap();
+    assert_eq!(admin["last_dispense"], "2025-01-01 12:00:00");
+
+    // the streams need the admin token, they aren't redacted
+    for path in ["/events/stream", "/ws/weight"] {
+        let response = client
+            .get(format!("http://{}{}", addr, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_https_listener() {
+    use axum::serve::ListenerExt;
+    use treat_dispenser_api::utils::tls::{self, TlsListener};
+
+    dotenv::from_filename(".env.test").ok();
+    init_logging();
+    let config = treat_dispenser_api::config::load_app_config_from_str(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+          tls:
+            cert_path: "tests/fixtures/tls/cert.pem"
+            key_path: "tests/fixtures/tls/key.pem"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        "#,
+    );
+    let server_config = tls::load_server_config(config.api.tls.as_ref().unwrap()).unwrap();
+    let (_app_state, app) = build_app(config.clone());
+    let listener = TcpListener::bind(&config.api.listen_address).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = TlsListener::new(listener, server_config).unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener.tap_io(|_| {}),
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    wait_for_server(100).await;
+
+    // the fixture certificate is self-signed
+    let client = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("https://{}/login", addr))
+        .json(&serde_json::json!({"username": "admin", "password": "password"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = client
+        .get(format!("https://{}/summary", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    // no cleartext HTTP on the same port
+    assert!(
+        Client::new()
+            .get(format!("http://{}/summary", addr))
+            .send()
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_summary_endpoint() {
+    let (addr, client, _) = setup(None).await;
+
+    // public like /status/wait, so displays don't need a token
+    let response = client
+        .get(format!("http://{}/summary", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let summary = response.json::<SummaryResponse>().await.unwrap();
+    assert_eq!(summary.status, "Operational");
+    assert_eq!(summary.emoji, "✅");
+    assert_eq!(summary.text, "Ready");
+    assert!(summary.last_dispense.is_none());
 }
 
 #[tokio::test]
@@ -178,6 +462,38 @@ async fn test_power_monitoring_thread() {
     assert_eq!(status_json.motor_power_sensor, "SensorMock");
 }
 
+#[tokio::test]
+async fn test_current_jam_detection() {
+    let (addr, client, app_state) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .current_jam_detection(CurrentJamConfig {
+                spike_amps: Some(0.5),
+                window_ms: Some(500),
+                ..Default::default()
+            })
+            .build(),
+    )
+    .await;
+    start_power_monitoring_thread(&app_state).await;
+
+    // the mock sensor reads 0.6 A, a jam for this config
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(response.status().is_success());
+    wait_for_server(2000).await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Jammed");
+    assert!(
+        status
+            .last_error_msg
+            .as_deref()
+            .is_some_and(|e| e.contains("motor current above 0.50 A")),
+        "{:?}",
+        status.last_error_msg
+    );
+}
+
 #[tokio::test]
 async fn test_weight_monitoring_thread() {
     let (addr, client, app_state) = setup(None).await;
@@ -190,37 +506,40 @@ async fn test_weight_monitoring_thread() {
     let status_json = response.json::<StatusResponse>().await.unwrap();
 
     assert_eq!(status_json.remaining_treats_grams, 12345.0);
+    assert_eq!(status_json.remaining_treats.value, 12345.0);
+    assert_eq!(status_json.remaining_treats.symbol, "g");
 }
 
 #[tokio::test]
-async fn test_dispense_endpoint_unauthorized() {
-    let (addr, client, _) = setup(None).await;
-    let req = client.post(format!("http://{}/dispense", addr));
-    let response = req.send().await.unwrap();
-    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
-}
-
-#[tokio::test]
-async fn test_dispense_endpoint_authorized() {
-    let (addr, client, _) = setup(None).await;
-    let response = post_with_auth(&client, addr, "/dispense").await;
+async fn test_weight_sensor_settings_acknowledged() {
+    let (addr, client, app_state) = setup(None).await;
+    start_weight_monitoring_thread(&app_state).await;
 
-    let health_status = get_hardware_status(&client, addr).await;
+    app_state
+        .lock()
+        .await
+        .weight_sensor_settings_tx
+        .send_modify(|settings| settings.geometry_factor = 1.25);
+    // applied after the next average, about every 450 ms
+    wait_for_server(1500).await;
 
+    let events: Vec<serde_json::Value> = get_with_auth(&client, addr, "/events")
+        .await
+        .json()
+        .await
+        .unwrap();
     assert!(
-        response.status().is_success(),
-        "Expected success, got: {}",
-        response.status()
-    );
-    assert!(
-        health_status.dispenser_status == "Dispensing",
-        "Dispenser should be in 'Dispensing' state"
+        events
+            .iter()
+            .any(|e| e["kind"] == "weight_sensor_settings_applied"),
+        "{:?}",
+        events
     );
 }
 
 #[tokio::test]
-async fn test_dispense_endpoint_overcurrent_protection() {
-    let (addr, client, app_state) = setup(Some(Box::new(
+async fn test_event_stream() {
+    let (addr, client, _) = setup(Some(
         r#"
         api:
           listen_address: "127.0.0.1:0"
@@ -228,85 +547,3154 @@ async fn test_dispense_endpoint_overcurrent_protection() {
           admin_password: "password"
         power_monitor:
           sensor: "SensorMock"
-          motor_current_limit_amps: 0.1
         weight_monitor:
           sensor: "SensorMock"
         motor:
           motor_type: "StepperMock"
-          cooldown_ms: 5000
         "#,
-    )))
+    ))
     .await;
-    start_power_monitoring_thread(&app_state).await;
 
-    let response = post_with_auth(&client, addr, "/dispense").await;
-
-    wait_for_server(7500).await; // wait for mock dispensing to finish
-    let hardware_status = get_hardware_status(&client, addr).await;
+    let response = client
+        .get(format!("http://{}/events/stream", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
 
+    let mut response = get_with_auth(&client, addr, "/events/stream").await;
+    assert!(response.status().is_success());
     assert!(
-        response.status().is_success(),
-        "Expected success, got: {}",
-        response.status()
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/event-stream")
     );
-    assert_eq!(
-        hardware_status.dispenser_status, "Cancelled",
-        "Dispenser should be in 'Cancelled' state"
+
+    let tare = post_with_auth(&client, addr, "/tare").await;
+    assert!(tare.status().is_success());
+
+    // the tare's events are waiting in the stream
+    let mut kinds: Vec<String> = Vec::new();
+    let mut buffer = String::new();
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while !kinds.iter().any(|kind| kind == "weight_sensor_tared") {
+            let chunk = response.chunk().await.unwrap().expect("stream ended");
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buffer.find("\n\n") {
+                let message: String = buffer.drain(..end + 2).collect();
+                for data in message.lines().filter_map(|line| line.strip_prefix("data: ")) {
+                    let event: serde_json::Value = serde_json::from_str(data).unwrap();
+                    kinds.push(event["kind"].as_str().unwrap().to_string());
+                }
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no weight_sensor_tared event, got {:?}", kinds));
+    assert!(kinds.iter().any(|kind| kind == "status_changed"), "{:?}", kinds);
+}
+
+/// Receives webhooks, answering `503` to the first one, and keeps the signatures and bodies.
+async fn start_webhook_receiver() -> (SocketAddr, Arc<std::sync::Mutex<Vec<(String, String)>>>) {
+    let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let received_clone = Arc::clone(&received);
+    let app = axum::Router::new().route(
+        "/hook",
+        axum::routing::post(move |headers: axum::http::HeaderMap, body: String| {
+            let received = Arc::clone(&received_clone);
+            async move {
+                let mut received = received.lock().unwrap();
+                let signature = headers
+                    .get("X-Dispenser-Signature")
+                    .map(|value| value.to_str().unwrap().to_string())
+                    .unwrap_or_default();
+                received.push((signature, body));
+                if received.len() == 1 {
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    axum::http::StatusCode::NO_CONTENT
+                }
+            }
+        }),
     );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (addr, received)
 }
 
 #[tokio::test]
-async fn test_dispense_endpoint_busy_response() {
-    let (addr, client, _) = setup(None).await;
-    let _ = post_with_auth(&client, addr, "/dispense").await;
-    let response = post_with_auth(&client, addr, "/dispense").await;
-
-    let hardware_status = get_hardware_status(&client, addr).await;
-
-    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
-    assert_eq!(
-        hardware_status.dispenser_status, "Dispensing",
-        "Dispenser should be in 'Dispensing' state"
+async fn test_webhook_retried_and_logged() {
+    let (receiver_addr, received) = start_webhook_receiver().await;
+    let config = format!(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        webhooks:
+          - url: "http://{}/hook"
+            secret: "change-me"
+            retry_delay_ms: 50
+          - url: "http://{}/hook"
+            events: ["hopper_empty"]
+        "#,
+        receiver_addr, receiver_addr
     );
+    let (addr, client, app_state) = setup(Some(&config)).await;
+    webhooks::start_webhook_sender(&app_state).await;
+    // let the sender subscribe to the event bus
+    wait_for_server(100).await;
 
-    wait_for_server(12500).await; // wait for mock dispensing to finish
-    let hardware_status = get_hardware_status(&client, addr).await;
-    assert_eq!(
-        hardware_status.dispenser_status, "Cooldown",
-        "Dispenser should be in 'Cooldown' state after dispensing"
+    let event_bus = app_state.lock().await.event_bus.clone();
+    event_bus.publish(EventKind::BackupCompleted, "Backup completed");
+    event_bus.publish_dispense_ended(
+        EventKind::DispenseFailed,
+        TriggerSource::Schedule,
+        "Motor stall during soft start at step 42 (0.91 A)",
     );
 
-    wait_for_server(5500).await; // Wait for cooldown period to finish
-    let hardware_status = get_hardware_status(&client, addr).await;
+    let delivery_log = app_state.lock().await.webhook_deliveries.clone();
+    for _ in 0..50 {
+        let delivered = delivery_log
+            .recent()
+            .iter()
+            .any(|delivery| delivery.status == DeliveryStatus::Delivered);
+        if delivered {
+            break;
+        }
+        wait_for_server(100).await;
+    }
+    let deliveries: Vec<WebhookDelivery> = get_with_auth(&client, addr, "/webhooks/deliveries")
+        .await
+        .json()
+        .await
+        .unwrap();
+    // only the first webhook wants dispense failures
+    assert_eq!(deliveries.len(), 1, "{:?}", deliveries);
+    assert_eq!(deliveries[0].event, "dispense_failed");
+    assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+    assert_eq!(deliveries[0].attempts, 2);
+    assert_eq!(deliveries[0].last_status_code, Some(204));
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 2);
+    // the retry sends the same body
+    assert_eq!(received[0], received[1]);
+    let (signature, body) = &received[1];
     assert_eq!(
-        hardware_status.dispenser_status, "Operational",
-        "Dispenser should be back to 'Operational' state after cooldown"
+        signature,
+        &treat_dispenser_api::services::fleet::sign("change-me", body.as_bytes())
     );
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(payload["event"], "dispense_failed");
+    assert_eq!(payload["delivery_id"], deliveries[0].id);
+    assert_eq!(payload["data"]["trigger"], "schedule");
 }
 
 #[tokio::test]
-async fn test_cancel_dispense_endpoint() {
-    let (addr, client, _) = setup(None).await;
-    let response = post_with_auth(&client, addr, "/dispense").await;
-
-    assert!(
-        response.status().is_success(),
-        "Expected success, got: {}",
-        response.status()
+async fn test_webhook_snapshot() {
+    let (receiver_addr, received) = start_webhook_receiver().await;
+    let camera = axum::Router::new().route(
+        "/snapshot.jpg",
+        axum::routing::get(|| async {
+            ([("Content-Type", "image/jpeg")], vec![0xFFu8, 0xD8, 0xFF, 0xD9])
+        }),
     );
-
-    // Cancel the dispense operation
-    let cancel_response = post_with_auth(&client, addr, "/cancel").await;
-    assert!(
-        cancel_response.status().is_success(),
-        "Expected success, got: {}",
-        cancel_response.status()
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let camera_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, camera).await.unwrap();
+    });
+    let config = format!(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        camera:
+          snapshot_url: "http://{}/snapshot.jpg"
+          delay_ms: 0
+        webhooks:
+          - url: "http://{}/hook"
+            attach_snapshot: true
+            retry_delay_ms: 50
+          - url: "http://{}/hook"
+            retry_delay_ms: 50
+        "#,
+        camera_addr, receiver_addr, receiver_addr
     );
+    let (_, _, app_state) = setup(Some(&config)).await;
+    webhooks::start_webhook_sender(&app_state).await;
+    wait_for_server(100).await;
 
-    // Check the status after cancellation
-    let hardware_status = get_hardware_status(&client, addr).await;
-    assert_eq!(
-        hardware_status.dispenser_status, "Cancelled",
-        "Dispenser should be in 'Cancelled' state"
-    );
+    let event_bus = app_state.lock().await.event_bus.clone();
+    event_bus.publish_dispensed(TriggerSource::Schedule, None);
+
+    let delivery_log = app_state.lock().await.webhook_deliveries.clone();
+    for _ in 0..50 {
+        let delivered = delivery_log
+            .recent()
+            .iter()
+            .filter(|delivery| delivery.status == DeliveryStatus::Delivered)
+            .count();
+        if delivered == 2 {
+            break;
+        }
+        wait_for_server(100).await;
+    }
+    let mut snapshots: Vec<serde_json::Value> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, body)| serde_json::from_str::<serde_json::Value>(body).unwrap())
+        .filter(|payload| payload["event"] == "dispense_completed")
+        .map(|payload| (payload["delivery_id"].as_u64(), payload["snapshot"].clone()))
+        .collect::<std::collections::BTreeMap<_, _>>()
+        .into_values()
+        .collect();
+    snapshots.sort_by_key(|snapshot| snapshot.is_null());
+    assert_eq!(snapshots.len(), 2, "{:?}", snapshots);
+    // only the first webhook asked for the picture
+    assert_eq!(snapshots[0]["content_type"], "image/jpeg");
+    assert_eq!(snapshots[0]["data"], "/9j/2Q==");
+    assert!(snapshots[1].is_null());
+}
+
+#[tokio::test]
+async fn test_hopper_low_webhook() {
+    let (receiver_addr, received) = start_webhook_receiver().await;
+    let config = format!(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+          hopper_level:
+            empty_threshold_grams: 50
+            low_threshold_grams: 150
+        motor:
+          motor_type: "StepperMock"
+        webhooks:
+          - url: "http://{}/hook"
+            events: ["hopper_low"]
+            retry_delay_ms: 50
+        "#,
+        receiver_addr
+    );
+    let (_addr, _client, app_state) = setup(Some(&config)).await;
+    webhooks::start_webhook_sender(&app_state).await;
+    hopper_level::start_hopper_level_monitor(&app_state).await;
+    wait_for_server(100).await;
+
+    let weight_readings_tx = app_state.lock().await.weight_readings_tx.clone();
+    for grams in [300.0, 120.0, 110.0] {
+        weight_readings_tx.send_replace(WeightReading {
+            grams,
+            settled: true,
+        });
+        wait_for_server(100).await;
+    }
+
+    let delivery_log = app_state.lock().await.webhook_deliveries.clone();
+    for _ in 0..50 {
+        let delivered = delivery_log
+            .recent()
+            .iter()
+            .any(|delivery| delivery.status == DeliveryStatus::Delivered);
+        if delivered {
+            break;
+        }
+        wait_for_server(100).await;
+    }
+    let deliveries = delivery_log.recent();
+    // reported once, not for every low reading
+    assert_eq!(deliveries.len(), 1, "{:?}", deliveries);
+    assert_eq!(deliveries[0].event, "hopper_low");
+    assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+
+    let received = received.lock().unwrap().clone();
+    let payload: serde_json::Value = serde_json::from_str(&received.last().unwrap().1).unwrap();
+    assert_eq!(payload["event"], "hopper_low");
+    assert_eq!(payload["data"]["kind"], "hopper_low");
+    assert_eq!(app_state.lock().await.status, DispenserStatus::Operational);
+}
+
+#[tokio::test]
+async fn test_hopper_empty_blocks_dispense() {
+    let (addr, client, app_state) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .hopper_level(HopperLevelConfig {
+                empty_threshold_grams: 50.0,
+                low_threshold_grams: None,
+                refill_min_increase_grams: Some(100.0),
+                refill_sustain_secs: Some(0),
+            })
+            .build(),
+    )
+    .await;
+    hopper_level::start_hopper_level_monitor(&app_state).await;
+    wait_for_server(200).await;
+
+    let weight_readings_tx = app_state.lock().await.weight_readings_tx.clone();
+    let send_grams = |grams| {
+        weight_readings_tx.send_replace(WeightReading {
+            grams,
+            settled: true,
+        });
+    };
+
+    send_grams(30.0);
+    wait_for_server(200).await;
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Empty");
+    assert!(!status.treats_available);
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("Dispenser is empty"), "{}", body);
+
+    // refilled above the threshold
+    send_grams(300.0);
+    wait_for_server(200).await;
+    send_grams(300.0);
+    wait_for_server(200).await;
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Operational");
+    assert!(status.treats_available);
+}
+
+#[tokio::test]
+async fn test_fused_weight_sensor() {
+    let (addr, client, app_state) = setup(Some(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorFused"
+          fusion:
+            cells:
+              - name: "left"
+                sensor: "SensorMock"
+              - name: "right"
+                sensor: "SensorMock"
+                slave_select: 1
+        motor:
+          motor_type: "StepperMock"
+        "#,
+    ))
+    .await;
+    start_weight_monitoring_thread(&app_state).await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.hardware.weight_sensor.state, ComponentState::Available);
+    let names: Vec<&str> = status.load_cells.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["left", "right"]);
+    assert!(status.load_cells.iter().all(|c| c.expected_share == 0.5));
+    assert!(status.load_cells.iter().all(|c| !c.failing && c.grams.is_none()));
+
+    let response = post_with_auth(&client, addr, "/tare").await;
+    assert!(response.status().is_success());
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(
+        body["calibration"]["cell_tare_raw"],
+        serde_json::json!([123456, 123456])
+    );
+
+    // two identical mock cells, shares are only checked above 50 g
+    wait_for_server(1000).await;
+    let status = get_hardware_status(&client, addr).await;
+    assert!(status.load_cells.iter().all(|c| c.grams == Some(0.0) && !c.failing));
+}
+
+#[tokio::test]
+async fn test_bowl_sensor() {
+    let (addr, client, _) = setup(Some(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        bowl:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        "#,
+    ))
+    .await;
+
+    let status: serde_json::Value = get_with_auth(&client, addr, "/status")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["hardware"]["bowl_sensor"]["state"], "available");
+
+    let stats: serde_json::Value = get_with_auth(&client, addr, "/stats")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["bowl"]["meal_count"], 0);
+    assert_eq!(stats["bowl"]["ignored"], 0);
+
+    // not reported without a bowl section
+    let (addr, client, _) = setup(None).await;
+    let stats: serde_json::Value = get_with_auth(&client, addr, "/stats")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(stats.get("bowl").is_none());
+}
+
+#[tokio::test]
+async fn test_degraded_startup_on_init_failure() {
+    let (addr, client, _) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .weight_sensor("SensorBogus")
+            .motor_type("StepperBogus")
+            .build(),
+    )
+    .await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "MotorControlError");
+    assert_eq!(status.motor, "StepperBogus (unavailable)");
+    assert_eq!(status.init_errors.len(), 2);
+    assert!(status.init_errors[0].contains("Unsupported motor type"));
+    assert!(status.init_errors[1].contains("Unsupported weight sensor type"));
+    assert_eq!(status.last_error_msg.as_ref(), status.init_errors.last());
+    assert_eq!(status.hardware.motor.state, ComponentState::Unavailable);
+    assert_eq!(status.hardware.weight_sensor.state, ComponentState::Unavailable);
+    assert_eq!(status.hardware.power_sensor.state, ComponentState::Available);
+    assert!(status.hardware.power_sensor.error.is_none());
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn test_dispense_endpoint_unauthorized() {
+    let (addr, client, _) = setup(None).await;
+    let req = client.post(format!("http://{}/dispense", addr));
+    let response = req.send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_session_cookie_login() {
+    let (addr, client, _) = setup_config(
+        test_config()
+            .session_cookies(SessionCookieConfig {
+                secure: Some(false),
+                ..Default::default()
+            })
+            .build(),
+    )
+    .await;
+
+    let response = client
+        .post(format!("http://{}/login", addr))
+        .json(&serde_json::json!({
+            "username": "admin",
+            "password": "password",
+            "session_cookie": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let set_cookies: Vec<String> = response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .map(|h| h.to_str().unwrap().to_string())
+        .collect();
+    let session = response.json::<SessionResponse>().await.unwrap();
+    assert_eq!(set_cookies.len(), 2);
+    assert!(set_cookies[0].starts_with("dispenser_session="));
+    assert!(set_cookies[0].contains("HttpOnly"));
+    assert!(set_cookies[0].contains("SameSite=Strict"));
+    assert!(!set_cookies[0].contains("Secure"));
+    assert!(set_cookies[1].starts_with(&format!("dispenser_csrf={};", session.csrf_token)));
+    let cookie = set_cookies
+        .iter()
+        .map(|c| c.split(';').next().unwrap())
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    let response = client
+        .get(format!("http://{}/events", addr))
+        .header(reqwest::header::COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    // changes need the CSRF token, auth then passes and the handler answers
+    let response = client
+        .post(format!("http://{}/cancel", addr))
+        .header(reqwest::header::COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    let response = client
+        .post(format!("http://{}/cancel", addr))
+        .header(reqwest::header::COOKIE, &cookie)
+        .header("X-CSRF-Token", &session.csrf_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+
+    // a bearer token can't be replayed as a session cookie
+    let token = login(&client, addr, "admin", "password").await.token;
+    let response = client
+        .get(format!("http://{}/events", addr))
+        .header(reqwest::header::COOKIE, format!("dispenser_session={}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .post(format!("http://{}/logout", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(
+        response
+            .headers()
+            .get_all(reqwest::header::SET_COOKIE)
+            .iter()
+            .all(|h| h.to_str().unwrap().contains("Max-Age=0"))
+    );
+}
+
+#[tokio::test]
+async fn test_session_cookie_login_disabled() {
+    let (addr, client, _) = setup(None).await;
+    let response = client
+        .post(format!("http://{}/login", addr))
+        .json(&serde_json::json!({
+            "username": "admin",
+            "password": "password",
+            "session_cookie": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.headers().get(reqwest::header::SET_COOKIE).is_none());
+}
+
+#[tokio::test]
+async fn test_dispense_endpoint_authorized() {
+    let (addr, client, _) = setup(None).await;
+    let response = post_with_auth(&client, addr, "/dispense").await;
+
+    let health_status = get_hardware_status(&client, addr).await;
+
+    assert!(
+        response.status().is_success(),
+        "Expected success, got: {}",
+        response.status()
+    );
+    assert!(
+        health_status.dispenser_status == "Dispensing",
+        "Dispenser should be in 'Dispensing' state"
+    );
+}
+
+#[tokio::test]
+async fn test_temperature_gates_dispensing() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        temperature:
+          sensor: "SensorMock"
+          interval_secs: 1
+          max_dispense_celsius: 20
+        "#;
+    let (addr, client, app_state) = setup(Some(config)).await;
+    temperature_monitor::start_temperature_monitoring_thread(&app_state).await;
+    humidity::start_humidity_tracker(&app_state).await;
+    wait_for_server(500).await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.temperature_celsius, Some(22.5));
+    assert_eq!(status.humidity_percent, Some(45.0));
+    assert_eq!(
+        status.hardware.temperature_sensor.map(|c| c.state),
+        Some(ComponentState::Available)
+    );
+
+    // the mock reads 22.5 °C, above the limit
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Operational");
+
+    let stats = get_with_auth(&client, addr, "/stats")
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(stats["humidity"]["current_percent"], 45.0);
+    assert_eq!(stats["humidity"]["hourly_percent"], serde_json::json!([45.0]));
+}
+
+#[tokio::test]
+async fn test_fan_controller() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        temperature:
+          sensor: "SensorMock"
+          interval_secs: 1
+        fan:
+          pin: 25
+          target_celsius: 30
+        "#;
+    let (addr, client, app_state) = setup(Some(config)).await;
+    temperature_monitor::start_temperature_monitoring_thread(&app_state).await;
+    fan::start_fan_controller(&app_state).await;
+    wait_for_server(500).await;
+
+    // the mock reads 22.5 °C, below the target
+    let fan_status = get_hardware_status(&client, addr).await.fan.unwrap();
+    assert_eq!(fan_status.mode, FanMode::Auto);
+    assert!(!fan_status.on);
+
+    // without a fan section the override endpoint doesn't exist
+    let (addr, client, _) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+    let response = client
+        .post(format!("http://{}/fan", addr))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&serde_json::json!({ "mode": "on" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_dispense_progress_events() {
+    let (addr, client, app_state) = setup(None).await;
+    let mut events_rx = app_state.lock().await.event_bus.subscribe();
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(response.status().is_success());
+
+    let mut percents = Vec::new();
+    let wait_for_completion = async {
+        while let Ok(event) = events_rx.recv().await {
+            if let Some(progress) = event.progress {
+                assert_eq!(progress.total_steps, 5000);
+                percents.push(progress.percent);
+                if progress.percent == 100 {
+                    assert_eq!(progress.steps_done, 5000);
+                    break;
+                }
+            }
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(30), wait_for_completion)
+        .await
+        .expect("dispense should report 100% progress");
+    assert_eq!(percents, vec![0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+}
+
+#[tokio::test]
+async fn test_status_transitions() {
+    let (addr, client, app_state) = setup(None).await;
+    let (mut status_rx, mut transitions_rx) = {
+        let state_guard = app_state.lock().await;
+        (
+            state_guard.dispenser_status_tx.subscribe(),
+            state_guard.status_transitions_tx.subscribe(),
+        )
+    };
+    assert_eq!(*status_rx.borrow_and_update(), DispenserStatus::Operational);
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(response.status().is_success());
+
+    let mut transitions = Vec::new();
+    let wait_for_completion = async {
+        while let Ok(transition) = transitions_rx.recv().await {
+            transitions.push(transition.clone());
+            if transition.to == DispenserStatus::Operational {
+                break;
+            }
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(30), wait_for_completion)
+        .await
+        .expect("dispenser should return to Operational");
+
+    assert_eq!(transitions[0].from, DispenserStatus::Operational);
+    assert_eq!(transitions[0].to, DispenserStatus::Dispensing);
+    for pair in transitions.windows(2) {
+        assert_eq!(pair[0].to, pair[1].from);
+    }
+    assert!(status_rx.has_changed().unwrap());
+    assert_eq!(*status_rx.borrow_and_update(), DispenserStatus::Operational);
+}
+
+#[tokio::test]
+async fn test_dispense_stats() {
+    let (addr, client, _) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .piece_weight_grams(2.5)
+            .cooldown_ms(0)
+            .build(),
+    )
+    .await;
+
+    let stats: serde_json::Value = get_with_auth(&client, addr, "/stats")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let count_before = stats["dispense_count"].as_u64().unwrap();
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(response.status().is_success());
+    // mock run plus settle delay
+    let mut stats = serde_json::Value::Null;
+    for _ in 0..30 {
+        wait_for_server(1000).await;
+        stats = get_with_auth(&client, addr, "/stats")
+            .await
+            .json()
+            .await
+            .unwrap();
+        if stats["dispense_count"] != count_before {
+            break;
+        }
+    }
+    assert_eq!(stats["dispense_count"], count_before + 1);
+    let last = &stats["last_dispense"];
+    assert_eq!(last["trigger"], "api-user");
+    assert!(stats["by_trigger"]["api-user"]["dispense_count"].as_u64().unwrap() >= 1);
+    // the mock scale never changes
+    assert_eq!(last["grams"], 0.0);
+    assert_eq!(last["pieces"], 0);
+}
+
+#[tokio::test]
+async fn test_weight_jam_detection() {
+    let (addr, client, _) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .weight_jam_detection(JamDetectionConfig {
+                check_at_fraction: Some(0.2),
+                ..Default::default()
+            })
+            .cooldown_ms(0)
+            .build(),
+    )
+    .await;
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(response.status().is_success());
+
+    // the mock scale never changes, so nothing drops
+    let mut status = get_hardware_status(&client, addr).await;
+    for _ in 0..20 {
+        if status.dispenser_status != "Dispensing" {
+            break;
+        }
+        wait_for_server(500).await;
+        status = get_hardware_status(&client, addr).await;
+    }
+    assert_eq!(status.dispenser_status, "Jammed");
+    let last_error = status.last_error_msg.unwrap();
+    assert!(last_error.starts_with("Jam detected"), "{}", last_error);
+}
+
+#[tokio::test]
+async fn test_weight_unsettled_during_motor_run() {
+    let (addr, client, app_state) = setup(Some(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+          motor_vibration:
+            mode: "suppress"
+            settle_ms: 200
+        motor:
+          motor_type: "StepperMock"
+          cooldown_ms: 0
+        "#,
+    ))
+    .await;
+    start_weight_monitoring_thread(&app_state).await;
+    wait_for_server(1000).await;
+    assert!(get_hardware_status(&client, addr).await.weight_settled);
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(response.status().is_success());
+    let mut saw_unsettled = false;
+    let mut status = get_hardware_status(&client, addr).await;
+    for _ in 0..120 {
+        saw_unsettled |= !status.weight_settled;
+        if status.dispenser_status != "Dispensing" && status.weight_settled {
+            break;
+        }
+        wait_for_server(250).await;
+        status = get_hardware_status(&client, addr).await;
+    }
+    assert!(saw_unsettled);
+    assert!(status.weight_settled);
+    assert_eq!(status.remaining_treats_grams, 12345.0);
+}
+
+#[tokio::test]
+async fn test_stir_hopper() {
+    let (addr, client, app_state) = setup(None).await;
+    let stir_config: StirConfig = serde_yaml::from_str("cycles: 1").unwrap();
+
+    let stir_state = Arc::clone(&app_state);
+    let stir_task =
+        tokio::spawn(async move { stir::stir_hopper(&stir_state, &stir_config).await });
+    wait_for_server(500).await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Stirring");
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+    let runtime = stir_task.await.unwrap().expect("stir should run");
+    assert!(runtime.as_secs() >= 5, "{:?}", runtime);
+
+    // no cooldown and no dispense recorded
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Operational");
+    assert!(status.last_dispensed.is_none());
+
+    let events = app_state.lock().await.event_bus.recent();
+    assert!(events.iter().any(|e| e.message == "Hopper stirred (1 cycles)"));
+    assert!(events.iter().all(|e| e.progress.is_none()));
+}
+
+#[tokio::test]
+async fn test_recover_interrupted_dispense() {
+    let (addr, client, app_state) = setup(Some(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        dispense_recovery:
+          status: "jammed"
+          reverse_degrees: 45
+        "#,
+    ))
+    .await;
+    let journal = DispenseJournal {
+        job_id: "1f3a9c2e".to_string(),
+        trigger: TriggerSource::Hook,
+        started_at: "2025-01-01 12:00:00".to_string(),
+    };
+
+    let recover_state = Arc::clone(&app_state);
+    let recovery = tokio::spawn(async move {
+        dispense_recovery::recover(&recover_state, &journal).await;
+    });
+    wait_for_server(500).await;
+
+    // the status is set before the reverse run, which can be cancelled like a dispense
+    assert_eq!(get_hardware_status(&client, addr).await.dispenser_status, "Jammed");
+    assert!(app_state.lock().await.motor_cancel_token.is_some());
+    recovery.await.unwrap();
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Jammed");
+    let last_error = status.last_error_msg.unwrap();
+    assert!(last_error.starts_with("Dispense 1f3a9c2e (trigger: hook)"), "{}", last_error);
+    assert!(app_state.lock().await.motor_cancel_token.is_none());
+
+    let events = app_state.lock().await.event_bus.recent();
+    assert!(
+        events
+            .iter()
+            .any(|e| e.kind == EventKind::RecoveredInterrupted && e.message == last_error)
+    );
+}
+
+#[tokio::test]
+async fn test_dispense_endpoint_overcurrent_protection() {
+    let (addr, client, app_state) =
+        setup_config(test_config().motor_current_limit_amps(0.1).build()).await;
+    start_power_monitoring_thread(&app_state).await;
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+
+    wait_for_server(7500).await; // wait for mock dispensing to finish
+    let hardware_status = get_hardware_status(&client, addr).await;
+
+    assert!(
+        response.status().is_success(),
+        "Expected success, got: {}",
+        response.status()
+    );
+    assert_eq!(
+        hardware_status.dispenser_status, "Cancelled",
+        "Dispenser should be in 'Cancelled' state"
+    );
+}
+
+#[tokio::test]
+async fn test_dispense_daily_limit() {
+    let (addr, client, _) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .triggers(TriggersConfig {
+                daily_limit: Some(0),
+                ..Default::default()
+            })
+            .build(),
+    )
+    .await;
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Operational");
+    assert!(status.last_error_msg.is_none());
+}
+
+#[tokio::test]
+async fn test_dispense_by_grams_timeout() {
+    let (addr, client, _) = setup_config(
+        AppConfigBuilder::new("admin", "password")
+            .listen_address("127.0.0.1:0")
+            .cooldown_ms(0)
+            .with(|config| config.motor.weight_dispense_timeout_secs = Some(1))
+            .build(),
+    )
+    .await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let token = login(&client, addr, "admin", "password").await.token;
+
+    let response = client
+        .post(format!("http://{}/dispense/grams", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "grams": 0.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["fields"][0]["field"], "grams");
+
+    // the mock scale never changes, so the target is never reached
+    let response = client
+        .post(format!("http://{}/dispense/grams", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "grams": 25.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    wait_for_server(2500).await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Cancelled");
+    let history: HistoryPage = get_with_auth(&client, addr, "/history?per_page=100")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(history.entries.iter().any(|entry| {
+        entry.result == DispenseResult::Cancelled
+            && entry.grams == Some(25.0)
+            && entry.degrees.is_none()
+            && entry.reason.as_deref().is_some_and(|r| r.starts_with("Timed out after 1 s"))
+    }));
+}
+
+#[tokio::test]
+async fn test_trickle_dispense() {
+    let (addr, client, _) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+    let trickle = |body: serde_json::Value| {
+        client
+            .post(format!("http://{}/dispense/trickle", addr))
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+    };
+
+    let response = trickle(serde_json::json!({ "grams": 20.0, "duration_minutes": 0 }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let response = get_with_auth(&client, addr, "/dispense/trickle").await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let response = trickle(serde_json::json!({ "grams": 20.0, "duration_minutes": 10 }))
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let progress: serde_json::Value = get_with_auth(&client, addr, "/dispense/trickle")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["target_grams"], 20.0);
+    assert_eq!(progress["bursts_planned"], 10);
+    assert_eq!(progress["bursts_done"], 0);
+    assert_eq!(progress["paused"], false);
+
+    // only one dispense at a time
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+    let response = post_with_auth(&client, addr, "/dispense/trickle/pause").await;
+    assert!(response.status().is_success());
+    let progress: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(progress["paused"], true);
+    assert!(progress["next_burst_at"].is_null());
+    let response = post_with_auth(&client, addr, "/dispense/trickle/resume").await;
+    let progress: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(progress["paused"], false);
+
+    let response = post_with_auth(&client, addr, "/cancel").await;
+    assert!(response.status().is_success());
+    wait_for_server(500).await;
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.dispenser_status, "Cancelled");
+    let response = get_with_auth(&client, addr, "/dispense/trickle").await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let response = post_with_auth(&client, addr, "/dispense/trickle/pause").await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_training_mode() {
+    let (addr, client, app_state) = setup(Some(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        motor:
+          motor_type: "StepperMock"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        digital_inputs:
+          - label: "paw_pad"
+            pin: 17
+        training:
+          input: "paw_pad"
+          ratio: 3
+        "#,
+    ))
+    .await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    training::start_training_monitor(&app_state).await;
+    let event_bus = app_state.lock().await.event_bus.clone();
+    // a press pulls the input low, the release lets it go high again
+    let press = || async {
+        event_bus.publish_input_change("paw_pad", false);
+        event_bus.publish_input_change("paw_pad", true);
+        wait_for_server(50).await;
+    };
+
+    // presses outside a session don't count
+    press().await;
+    let state: serde_json::Value = get_with_auth(&client, addr, "/training")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(state["active"], false);
+    assert_eq!(state["totals"]["presses"], 0);
+
+    let token = login(&client, addr, "admin", "password").await.token;
+    let response = client
+        .post(format!("http://{}/training/start", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "ratio": 0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = post_with_auth(&client, addr, "/training/start").await;
+    assert!(response.status().is_success());
+    let state: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(state["session"]["schedule"], "fixed");
+    assert_eq!(state["session"]["ratio"], 3);
+
+    press().await;
+    press().await;
+    assert_eq!(get_hardware_status(&client, addr).await.dispenser_status, "Operational");
+    press().await;
+    assert_eq!(get_hardware_status(&client, addr).await.dispenser_status, "Dispensing");
+
+    let response = post_with_auth(&client, addr, "/training/stop").await;
+    assert!(response.status().is_success());
+    let state: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(state["active"], false);
+    assert_eq!(state["session"]["presses"], 3);
+    assert_eq!(state["session"]["rewards"], 1);
+    assert_eq!(state["session"]["presses_per_reward"], 3.0);
+    assert!(state["session"]["stopped_at"].is_string());
+    assert_eq!(state["totals"]["sessions"], 1);
+
+    let events = app_state.lock().await.event_bus.recent();
+    assert!(events.iter().any(|e| e.kind == EventKind::TrainingRewarded));
+    let response = post_with_auth(&client, addr, "/training/stop").await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_dispense_history() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        triggers:
+          daily_limit: 0
+        "#;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let (addr, client, _) = setup(Some(config)).await;
+
+    let response = post_with_auth(&client, addr, "/dispense").await;
+    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    // the history is written in the background
+    wait_for_server(500).await;
+
+    // other tests share the data directory, so look for the rejection among the latest
+    let response = get_with_auth(&client, addr, "/history?per_page=100").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let history: HistoryPage = response.json().await.unwrap();
+    assert_eq!(history.page, 1);
+    assert!(history.total >= 1);
+    assert!(history.entries.iter().any(|entry| {
+        entry.result == DispenseResult::Rejected
+            && entry.trigger == "api-user"
+            && entry.reason.as_deref().is_some_and(|r| r.contains("Daily limit"))
+    }));
+
+    let response = get_with_auth(&client, addr, "/history?per_page=0").await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_dispense_note() {
+    let (addr, client, app_state) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let token = login(&client, addr, "admin", "password").await.token;
+    let dispense = |body: serde_json::Value| {
+        client
+            .post(format!("http://{}/dispense", addr))
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+    };
+
+    let response = dispense(serde_json::json!({ "note": "x".repeat(201) }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let mut events = app_state.lock().await.event_bus.subscribe();
+    let response = dispense(serde_json::json!({ "note": " vet said extra portion " }))
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let dispensed = tokio::time::timeout(std::time::Duration::from_secs(15), async {
+        loop {
+            let event = events.recv().await.unwrap();
+            if event.kind == EventKind::Dispensed {
+                return event;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        dispensed.message,
+        "Treats dispensed (trigger: api-user): vet said extra portion"
+    );
+    // the history is written in the background
+    wait_for_server(500).await;
+    let history: HistoryPage = get_with_auth(&client, addr, "/history?per_page=100")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(history.entries.iter().any(|entry| {
+        entry.result == DispenseResult::Completed
+            && entry.note.as_deref() == Some("vet said extra portion")
+    }));
+}
+
+#[tokio::test]
+async fn test_status_link() {
+    let (addr, client, _) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let token = login(&client, addr, "admin", "password").await.token;
+
+    let response = client
+        .post(format!("http://{}/share", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "label": "pet-sitter", "expires_in_hours": 48 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let created: serde_json::Value = response.json().await.unwrap();
+    let id = created["id"].as_str().unwrap().to_string();
+    let path = created["path"].as_str().unwrap().to_string();
+    assert_eq!(created["label"], "pet-sitter");
+    assert!(created["expires_at"].is_string());
+
+    // the link needs no login
+    let response = client
+        .get(format!("http://{}{}", addr, path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    assert!(response.text().await.unwrap().contains("Last feeding"));
+    let shared: serde_json::Value = client
+        .get(format!("http://{}{}?format=json", addr, path))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(shared["remaining_grams"].is_number());
+    assert!(shared.get("last_error_msg").is_none());
+
+    let listed: serde_json::Value = get_with_auth(&client, addr, "/share").await.json().await.unwrap();
+    let listed = listed.as_array().unwrap();
+    assert!(listed.iter().any(|share| share["id"] == id.as_str()));
+    // the token is only shown once
+    assert!(listed.iter().all(|share| share.get("token").is_none()));
+
+    let response = client
+        .delete(format!("http://{}/share/{}", addr, id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    let response = client
+        .get(format!("http://{}{}", addr, path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_import_history() {
+    let (addr, client, _app_state) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let token = login(&client, addr, "admin", "password").await.token;
+    // the history outlives test runs, a random time keeps this import new
+    let csv = format!(
+        "Date,Time,Portions,Status\n2001-{:02}-{:02},{:02}:{:02},2,Success\n2001-01-01,lunch,1,Success\n",
+        rand::random_range(1..=12),
+        rand::random_range(1..=28),
+        rand::random_range(0..24),
+        rand::random_range(0..60)
+    );
+    let import = async |body: String| {
+        client
+            .post(format!("http://{}/history/import", addr))
+            .bearer_auth(&token)
+            .header("Content-Type", "text/csv")
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+    };
+
+    let response = import(csv.clone()).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["imported"], 1);
+    assert_eq!(summary["rejected"], 1);
+    assert_eq!(summary["rejected_rows"][0]["line"], 3);
+
+    // the same file again only finds duplicates
+    let summary: serde_json::Value = import(csv).await.json().await.unwrap();
+    assert_eq!(summary["imported"], 0);
+    assert_eq!(summary["duplicates"], 1);
+
+    let response = import("portions\n2\n".to_string()).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_archive_imported_entry() {
+    let (addr, client, _app_state) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let token = login(&client, addr, "admin", "password").await.token;
+    let note = format!("imported {}", rand::random::<u32>());
+    let response = client
+        .post(format!("http://{}/history/import", addr))
+        .bearer_auth(&token)
+        .header("Content-Type", "text/csv")
+        // recent, so it's on the first history page
+        .body(format!(
+            "Date,Time,Portions,Note\n{},2,{}\n",
+            chrono::Local::now().format("%Y-%m-%d,%H:%M"),
+            note
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let history: HistoryPage = get_with_auth(&client, addr, "/history?per_page=100")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = history
+        .entries
+        .iter()
+        .find(|entry| entry.note.as_deref() == Some(note.as_str()))
+        .and_then(|entry| entry.id)
+        .expect("imported entry not in the history");
+    let get_stats = async || -> serde_json::Value {
+        get_with_auth(&client, addr, "/stats")
+            .await
+            .json()
+            .await
+            .unwrap()
+    };
+    let stats = get_stats().await;
+
+    // imported entries were never counted in the stats, archiving them changes nothing
+    for archived in [true, false] {
+        let response = client
+            .patch(format!("http://{}/history/{}", addr, id))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({ "archived": archived }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(get_stats().await, stats);
+    }
+}
+
+#[tokio::test]
+async fn test_archive_history_entry() {
+    let (addr, client, app_state) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+    let token = login(&client, addr, "admin", "password").await.token;
+    // other tests share the history, the note tells this dispense apart
+    let note = format!("test dispense into a cup {}", rand::random::<u32>());
+
+    let mut events = app_state.lock().await.event_bus.subscribe();
+    let response = client
+        .post(format!("http://{}/dispense", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "note": note }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    tokio::time::timeout(std::time::Duration::from_secs(15), async {
+        while events.recv().await.unwrap().kind != EventKind::Dispensed {}
+    })
+    .await
+    .unwrap();
+    wait_for_server(2500).await;
+
+    let find_entry = async |query: &str| {
+        let path = format!("/history?per_page=100{}", query);
+        let history: HistoryPage = get_with_auth(&client, addr, &path)
+            .await
+            .json()
+            .await
+            .unwrap();
+        history
+            .entries
+            .into_iter()
+            .find(|entry| entry.note.as_deref() == Some(note.as_str()))
+    };
+    let dispense_count = async || {
+        let stats: serde_json::Value = get_with_auth(&client, addr, "/stats")
+            .await
+            .json()
+            .await
+            .unwrap();
+        stats["dispense_count"].as_u64().unwrap()
+    };
+    let entry = find_entry("").await.expect("dispense not in the history");
+    assert!(entry.dispensed_grams.is_some());
+    let id = entry.id.unwrap();
+    let count = dispense_count().await;
+    let update = |body: serde_json::Value| {
+        client
+            .patch(format!("http://{}/history/{}", addr, id))
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+    };
+
+    let response = update(serde_json::json!({})).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = update(serde_json::json!({ "archived": true })).await.unwrap();
+    assert!(response.status().is_success());
+    assert!(find_entry("").await.is_none());
+    assert!(find_entry("&include_archived=true").await.unwrap().archived);
+    assert_eq!(dispense_count().await, count - 1);
+
+    let response = update(serde_json::json!({ "archived": false })).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(dispense_count().await, count);
+
+    let response = client
+        .patch(format!("http://{}/history/{}", addr, i64::MAX))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "archived": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_weight_history_endpoint() {
+    let (addr, client, _) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+
+    let response = get_with_auth(
+        &client,
+        addr,
+        "/weight/history?from=2025-01-01&to=2025-01-08%2012:00:00&interval_minutes=60",
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let timeline: WeightTimeline = response.json().await.unwrap();
+    assert_eq!(timeline.from, "2025-01-01 00:00:00");
+    assert_eq!(timeline.to, "2025-01-08 12:00:00");
+    assert_eq!(timeline.interval_minutes, 60);
+
+    let response = get_with_auth(&client, addr, "/weight/history").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = get_with_auth(&client, addr, "/weight/history?from=last-tuesday").await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let response = get_with_auth(&client, addr, "/weight/history?interval_minutes=0").await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_trigger_arbitration() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        hooks:
+          - name: "ifttt"
+            token: "hook-secret"
+        triggers:
+          arbitration:
+            coalesce_window_ms: 500
+            disabled: ["hook"]
+        "#;
+    let (addr, client, app_state) = setup(Some(config)).await;
+
+    let response = client
+        .post(format!("http://{}/hooks/dispense?token=hook-secret", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    // without coalescing the second request would be rejected as busy
+    let mut transitions_rx = app_state.lock().await.status_transitions_tx.subscribe();
+    let (first, second) = tokio::join!(
+        post_with_auth(&client, addr, "/dispense"),
+        post_with_auth(&client, addr, "/dispense")
+    );
+    assert!(first.status().is_success());
+    assert!(second.status().is_success());
+
+    let transition = transitions_rx.recv().await.unwrap();
+    assert_eq!(transition.to, DispenserStatus::Dispensing);
+    let mut dispenses = 1;
+    while let Ok(Ok(transition)) =
+        tokio::time::timeout(std::time::Duration::from_secs(10), transitions_rx.recv()).await
+    {
+        if transition.to == DispenserStatus::Dispensing {
+            dispenses += 1;
+        }
+        if transition.to == DispenserStatus::Operational {
+            break;
+        }
+    }
+    assert_eq!(dispenses, 1);
+}
+
+#[tokio::test]
+async fn test_next_scheduled_dispense() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        schedules:
+          - name: "breakfast"
+            at: ["{}"]
+            degrees: 45
+            warn_minutes_before: 720
+          - name: "broken"
+            cron: "not a cron"
+        "#;
+    // two hours ahead, so the warning is due right away and nothing is dispensed
+    let at = (chrono::Local::now() + chrono::TimeDelta::hours(2)).format("%H:%M").to_string();
+    let config = config.replace("{}", &at);
+    let (addr, client, app_state) = setup(Some(&config)).await;
+    assert!(get_hardware_status(&client, addr).await.next_scheduled_dispense.is_none());
+
+    scheduler::start_scheduler(&app_state).await;
+    wait_for_server(200).await;
+
+    let next = get_hardware_status(&client, addr)
+        .await
+        .next_scheduled_dispense
+        .expect("the valid schedule should be reported");
+    assert_eq!(next.schedule, "breakfast");
+    assert!(next.time.ends_with(&format!("{}:00", at)), "unexpected time {}", next.time);
+    assert_eq!(next.profile, "degrees");
+    assert_eq!(next.amount, Some(45.0));
+
+    let events: Vec<serde_json::Value> = get_with_auth(&client, addr, "/events")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let upcoming = events
+        .iter()
+        .find(|e| e["kind"] == "feeding_upcoming")
+        .expect("the feeding should be announced");
+    assert!(
+        upcoming["message"].as_str().unwrap().ends_with(", 45 degrees"),
+        "{}",
+        upcoming
+    );
+}
+
+#[tokio::test]
+async fn test_schedule_export() {
+    let _config_files = CONFIG_FILES.lock().await;
+    let (addr, client, app_state) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+    let url = format!("http://{}/schedules/export", addr);
+
+    let export: serde_json::Value = get_with_auth(&client, addr, "/schedules/export")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export, serde_json::json!({ "schedules": [] }));
+
+    // leap day feedings, so nothing is dispensed while the tests run
+    let yaml = r#"
+schedules:
+  - name: "leap day"
+    cron: "0 3 29 2 *"
+    degrees: 90
+  - name: "off"
+    at: ["07:30"]
+    enabled: false
+"#;
+    let response = client
+        .put(&url)
+        .bearer_auth(&token)
+        .header("Content-Type", "application/yaml")
+        .body(yaml)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let summary: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(summary["schedules"], 2);
+    wait_for_server(200).await;
+    let next = get_hardware_status(&client, addr)
+        .await
+        .next_scheduled_dispense
+        .expect("the saved schedule should be running");
+    assert_eq!(next.schedule, "leap day");
+
+    let response = client
+        .get(format!("{}?format=yaml", url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/yaml");
+    let exported_yaml = response.text().await.unwrap();
+    assert!(exported_yaml.contains("name: leap day"), "{}", exported_yaml);
+    let export: serde_json::Value = get_with_auth(&client, addr, "/schedules/export")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export["schedules"][0]["cron"], "0 3 29 2 *");
+    assert_eq!(export["schedules"][1]["enabled"], false);
+
+    // the export goes back in unchanged
+    let response = client
+        .put(&url)
+        .bearer_auth(&token)
+        .header("Content-Type", "application/yaml")
+        .body(exported_yaml)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let invalid = serde_json::json!({ "schedules": [
+        { "name": "twice", "at": ["07:30"] },
+        { "name": "twice", "cron": "not a cron" },
+    ] });
+    let response = put_json_with_auth(&client, addr, "/schedules/export", invalid).await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("schedules.1.name") && body.contains("schedules.1"), "{}", body);
+    let response = put_json_with_auth(&client, addr, "/schedules/export", serde_json::json!([])).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let schedules = app_state.lock().await.app_config.schedules.clone().unwrap();
+    assert_eq!(schedules.len(), 2);
+
+    // a reload keeps the saved schedules, whatever config.yaml says
+    let mut file_config = app_state.lock().await.app_config.clone();
+    file_config.schedules = None;
+    std::fs::write(
+        filesystem::get_config_path(),
+        serde_yaml::to_string(&file_config).unwrap(),
+    )
+    .unwrap();
+    let response = post_with_auth(&client, addr, "/config/reload").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let reload: serde_json::Value = response.json().await.unwrap();
+    assert!(
+        !reload["restart_required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|path| path.as_str().unwrap().starts_with("schedules")),
+        "{}",
+        reload
+    );
+    let schedules = app_state.lock().await.app_config.schedules.clone().unwrap();
+    assert_eq!(schedules[0].name, "leap day");
+
+    let saved = filesystem::get_schedules_file_path();
+    assert!(std::path::Path::new(&saved).exists());
+    std::fs::remove_file(saved).unwrap();
+}
+
+#[tokio::test]
+async fn test_overcurrent_protection_override() {
+    let (addr, client, app_state) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+    assert!(get_hardware_status(&client, addr).await.overcurrent_protection.enabled);
+    // the audit log is kept between test runs, only what this test appends is checked
+    let audit_log_path = filesystem::get_audit_log_path();
+    let audit_log_start = std::fs::read(&audit_log_path).map_or(0, |log| log.len());
+
+    let response = client
+        .post(format!("http://{}/power/overcurrent/disable", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "duration_secs": 0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = client
+        .post(format!("http://{}/power/overcurrent/disable", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "duration_secs": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let protection = get_hardware_status(&client, addr).await.overcurrent_protection;
+    assert!(!protection.enabled);
+    assert_eq!(protection.disabled_by.as_deref(), Some("admin"));
+
+    // enabled again on its own after the timeout
+    wait_for_server(1500).await;
+    assert!(get_hardware_status(&client, addr).await.overcurrent_protection.enabled);
+    let kinds: Vec<EventKind> = app_state
+        .lock()
+        .await
+        .event_bus
+        .recent()
+        .into_iter()
+        .map(|event| event.kind)
+        .collect();
+    assert!(kinds.contains(&EventKind::OvercurrentProtectionDisabled));
+    assert!(kinds.contains(&EventKind::OvercurrentProtectionEnabled));
+
+    let response = post_with_auth(&client, addr, "/power/overcurrent/disable").await;
+    assert!(response.status().is_success());
+    let response = post_with_auth(&client, addr, "/power/overcurrent/enable").await;
+    assert!(response.status().is_success());
+    assert!(get_hardware_status(&client, addr).await.overcurrent_protection.enabled);
+
+    let audit_log = std::fs::read(&audit_log_path).unwrap();
+    let entries: Vec<(String, String)> = String::from_utf8_lossy(&audit_log[audit_log_start..])
+        .lines()
+        .map(|line| {
+            let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                entry["user"].as_str().unwrap().to_string(),
+                entry["action"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    let expected = [
+        ("admin", "overcurrent_protection_disabled"),
+        ("system", "overcurrent_protection_enabled"),
+        ("admin", "overcurrent_protection_disabled"),
+        ("admin", "overcurrent_protection_enabled"),
+    ];
+    assert_eq!(
+        entries,
+        expected.map(|(user, action)| (user.to_string(), action.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_current_calibration() {
+    let (addr, client, app_state) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+
+    let response = client
+        .post(format!("http://{}/power/current-calibration", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "margin_percent": 1000 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = client
+        .post(format!("http://{}/power/current-calibration", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "apply": true }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let calibration: CurrentCalibrationResponse = response.json().await.unwrap();
+    // the mock sensor always reads 0.6 A, plus the default 50% margin
+    assert!((calibration.running.mean_amps - 0.6).abs() < 1e-4);
+    assert_eq!(calibration.current_limit_amps, 0.7);
+    assert_eq!(calibration.recommended_limit_amps, 0.9);
+    assert!(calibration.applied);
+
+    let state_guard = app_state.lock().await;
+    assert_eq!(state_guard.status, DispenserStatus::Operational);
+    assert_eq!(
+        state_guard.app_config.power_monitor.motor_current_limit_amps,
+        Some(0.9)
+    );
+    assert!(
+        state_guard
+            .event_bus
+            .recent()
+            .iter()
+            .any(|event| event.kind == EventKind::CurrentLimitChanged)
+    );
+}
+
+#[tokio::test]
+async fn test_weight_websocket_stream() {
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let (addr, client, app_state) = setup(None).await;
+    let url = format!("ws://{}/ws/weight?interval_ms=100", addr);
+    assert!(tokio_tungstenite::connect_async(&url).await.is_err());
+
+    let token = login(&client, addr, "admin", "password").await.token;
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+    let weight_readings_tx = app_state.lock().await.weight_readings_tx.clone();
+    let mut next_reading = async || -> WeightReading {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(2), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    };
+    // the current reading is sent right away
+    next_reading().await;
+
+    weight_readings_tx.send_replace(WeightReading {
+        grams: 123.5,
+        settled: false,
+    });
+    let reading = next_reading().await;
+    assert_eq!(reading.grams, 123.5);
+    assert!(!reading.settled);
+}
+
+#[tokio::test]
+async fn test_dispense_endpoint_busy_response() {
+    let (addr, client, _) = setup(None).await;
+    let _ = post_with_auth(&client, addr, "/dispense").await;
+    let response = post_with_auth(&client, addr, "/dispense").await;
+
+    let hardware_status = get_hardware_status(&client, addr).await;
+
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        hardware_status.dispenser_status, "Dispensing",
+        "Dispenser should be in 'Dispensing' state"
+    );
+
+    wait_for_server(12500).await; // wait for mock dispensing to finish
+    let hardware_status = get_hardware_status(&client, addr).await;
+    assert_eq!(
+        hardware_status.dispenser_status, "Cooldown",
+        "Dispenser should be in 'Cooldown' state after dispensing"
+    );
+
+    wait_for_server(5500).await; // Wait for cooldown period to finish
+    let hardware_status = get_hardware_status(&client, addr).await;
+    assert_eq!(
+        hardware_status.dispenser_status, "Operational",
+        "Dispenser should be back to 'Operational' state after cooldown"
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_dispense_endpoint() {
+    let (addr, client, _) = setup(None).await;
+    let response = post_with_auth(&client, addr, "/dispense").await;
+
+    assert!(
+        response.status().is_success(),
+        "Expected success, got: {}",
+        response.status()
+    );
+
+    // Cancel the dispense operation
+    let cancel_response = post_with_auth(&client, addr, "/cancel").await;
+    assert!(
+        cancel_response.status().is_success(),
+        "Expected success, got: {}",
+        cancel_response.status()
+    );
+
+    // Check the status after cancellation
+    let hardware_status = get_hardware_status(&client, addr).await;
+    assert_eq!(
+        hardware_status.dispenser_status, "Cancelled",
+        "Dispenser should be in 'Cancelled' state"
+    );
+}
+
+#[tokio::test]
+async fn test_calibration_and_dispense_exclude_each_other() {
+    let (addr, client, app_state) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+    let post = |path: &str| {
+        client
+            .post(format!("http://{}{}", addr, path))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({ "known_mass_grams": 100.0 }))
+            .send()
+    };
+
+    // dispense and calibrate are refused while a tare runs
+    let tare = tokio::spawn(post("/tare"));
+    while app_state.lock().await.status != DispenserStatus::Calibrating {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let response = post("/dispense").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    let response = post("/calibrate").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert!(tare.await.unwrap().unwrap().status().is_success());
+
+    // and a tare is refused while dispensing or cooling down
+    assert!(post("/dispense").await.unwrap().status().is_success());
+    let response = post("/tare").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+    // started at the same time, exactly one of them wins
+    let (addr, client, _) = setup(None).await;
+    let post = |path: &str| {
+        client
+            .post(format!("http://{}{}", addr, path))
+            .bearer_auth(&token)
+            .send()
+    };
+    let (tare, dispense) = tokio::join!(post("/tare"), post("/dispense"));
+    let mut statuses = [tare.unwrap().status(), dispense.unwrap().status()];
+    statuses.sort();
+    assert_eq!(
+        statuses,
+        [
+            reqwest::StatusCode::OK,
+            reqwest::StatusCode::SERVICE_UNAVAILABLE
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_access_log_records_requests() {
+    let access_log_dir = std::env::temp_dir().join(format!(
+        "treat-dispenser-access-log-{}",
+        std::process::id()
+    ));
+    let config = format!(
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        logging:
+          access_log:
+            directory: "{}"
+            rotation: "never"
+        "#,
+        access_log_dir.display()
+    );
+    let (addr, client, _) = setup(Some(&config)).await;
+
+    let response = get_with_auth(&client, addr, "/status").await;
+    assert!(response.status().is_success());
+
+    // the lines are written in the background
+    let mut entries: Vec<serde_json::Value> = Vec::new();
+    for _ in 0..50 {
+        let access_log =
+            std::fs::read_to_string(access_log_dir.join("access.log")).unwrap_or_default();
+        entries = access_log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        if entries.iter().any(|e| e["route"] == "/status") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    assert!(entries.iter().any(|e| e["route"] == "/login" && e["status"] == 200));
+    assert!(entries.iter().any(|e| e["route"] == "/status" && e["client_ip"] == "127.0.0.1"));
+
+    let _ = std::fs::remove_dir_all(access_log_dir);
+}
+
+#[tokio::test]
+async fn test_log_level_endpoint() {
+    let (addr, client, _) = setup(None).await;
+
+    let response = get_with_auth(&client, addr, "/admin/log-level").await;
+    assert!(response.status().is_success());
+    let original: serde_json::Value = response.json().await.unwrap();
+
+    let response = put_json_with_auth(
+        &client,
+        addr,
+        "/admin/log-level",
+        serde_json::json!({ "filter": "info,treat_dispenser_api::services::weight_monitor=trace" }),
+    )
+    .await;
+    assert!(response.status().is_success());
+    let updated: serde_json::Value = response.json().await.unwrap();
+    assert!(
+        updated["filter"]
+            .as_str()
+            .unwrap()
+            .contains("treat_dispenser_api::services::weight_monitor=trace")
+    );
+
+    let response = put_json_with_auth(
+        &client,
+        addr,
+        "/admin/log-level",
+        serde_json::json!({ "filter": "weight_monitor=[invalid" }),
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // restore the original filter for the remaining tests
+    let _ = put_json_with_auth(&client, addr, "/admin/log-level", original).await;
+}
+
+#[tokio::test]
+async fn test_supervised_task_panic_is_recorded() {
+    let (addr, client, app_state) = setup(None).await;
+
+    let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let runs_clone = Arc::clone(&runs);
+    supervisor::spawn_supervised(&app_state, "flaky_task", move || {
+        let runs = Arc::clone(&runs_clone);
+        async move {
+            if runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                panic!("sensor bus exploded");
+            }
+        }
+    });
+
+    // first restart happens after one second
+    wait_for_server(1500).await;
+    assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    let status = get_hardware_status(&client, addr).await;
+    let last_error = status.last_error_msg.expect("panic should be recorded");
+    assert!(last_error.contains("flaky_task"), "{}", last_error);
+    assert!(last_error.contains("sensor bus exploded"), "{}", last_error);
+    assert!(status.last_error_time.is_some());
+
+    let response = get_with_auth(&client, addr, "/events").await;
+    assert!(response.status().is_success());
+    let events: Vec<serde_json::Value> = response.json().await.unwrap();
+    let kinds: Vec<&str> = events.iter().filter_map(|e| e["kind"].as_str()).collect();
+    assert_eq!(kinds, vec!["task_panicked", "task_restarted"]);
+}
+
+#[tokio::test]
+async fn test_backup_and_restore() {
+    let (addr, client, _) = setup(None).await;
+
+    let data_dir = std::env::var("DISPENSER_DATA_DIR").unwrap();
+    std::fs::create_dir_all(&data_dir).unwrap();
+    let calibration_path = format!("{}/weight_sensor_calibration.json", data_dir);
+    let original_calibration = r#"{"scale":0.42,"offset":0.0,"tare_raw":1234}"#;
+    std::fs::write(&calibration_path, original_calibration).unwrap();
+
+    let response = get_with_auth(&client, addr, "/admin/backup").await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "application/gzip"
+    );
+    let archive = response.bytes().await.unwrap();
+
+    std::fs::write(&calibration_path, r#"{"scale":1.0,"offset":0.0,"tare_raw":0}"#).unwrap();
+
+    let token = login(&client, addr, "admin", "password").await.token;
+    let response = client
+        .post(format!("http://{}/admin/restore", addr))
+        .header("Authorization", format!("Bearer {}", token))
+        .body(archive)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let restore: serde_json::Value = response.json().await.unwrap();
+    assert!(
+        restore["restored_files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|f| f == "weight_sensor_calibration.json")
+    );
+    assert!(std::path::Path::new(restore["snapshot"].as_str().unwrap()).exists());
+    assert_eq!(
+        std::fs::read_to_string(&calibration_path).unwrap(),
+        original_calibration
+    );
+
+    let response = client
+        .post(format!("http://{}/admin/restore", addr))
+        .header("Authorization", format!("Bearer {}", token))
+        .body("not a backup")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_reload_config() {
+    let _config_files = CONFIG_FILES.lock().await;
+    let (addr, client, app_state) = setup(None).await;
+    let data_dir = std::env::var("DISPENSER_DATA_DIR").unwrap();
+    std::fs::create_dir_all(&data_dir).unwrap();
+    std::fs::write(
+        format!("{}/config.yaml", data_dir),
+        r#"
+        api:
+          listen_address: "0.0.0.0:3500"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+          motor_current_limit_amps: 0.9
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "Stepper28BYJ48"
+          cooldown_ms: 1000
+        "#,
+    )
+    .unwrap();
+
+    let response = post_with_auth(&client, addr, "/config/reload").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let reload: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        reload["applied"],
+        serde_json::json!(["motor.cooldown_ms", "power_monitor.motor_current_limit_amps"])
+    );
+    assert_eq!(
+        reload["restart_required"],
+        serde_json::json!(["api.listen_address", "motor.motor_type"])
+    );
+
+    let state_guard = app_state.lock().await;
+    assert_eq!(state_guard.app_config.motor.cooldown_ms, Some(1000));
+    assert_eq!(
+        state_guard.app_config.power_monitor.motor_current_limit_amps,
+        Some(0.9)
+    );
+    assert_eq!(state_guard.app_config.motor.motor_type, "StepperMock");
+    assert_eq!(state_guard.app_config.api.listen_address, "127.0.0.1:0");
+}
+
+#[tokio::test]
+async fn test_staged_config_reload() {
+    let _config_files = CONFIG_FILES.lock().await;
+    let (addr, client, app_state) = setup(None).await;
+    let data_dir = std::env::var("DISPENSER_DATA_DIR").unwrap();
+    std::fs::create_dir_all(&data_dir).unwrap();
+    std::fs::write(
+        format!("{}/config.yaml", data_dir),
+        r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+          cooldown_ms: 4321
+        "#,
+    )
+    .unwrap();
+    let original_cooldown = app_state.lock().await.app_config.motor.cooldown_ms;
+    let token = login(&client, addr, "admin", "password").await.token;
+    let post = |path: &str| {
+        client
+            .post(format!("http://{}{}", addr, path))
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+    };
+
+    // a dry run reports the change without applying it
+    let response = post("/config/reload?dry_run=true").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let reload: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reload["dry_run"], true);
+    assert!(
+        reload["applied"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("motor.cooldown_ms"))
+    );
+    assert_eq!(
+        app_state.lock().await.app_config.motor.cooldown_ms,
+        original_cooldown
+    );
+
+    // an unconfirmed staged reload is rolled back
+    let response = post("/config/reload?confirm_within_secs=1").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let reload: serde_json::Value = response.json().await.unwrap();
+    assert!(reload["confirm_before"].is_string());
+    assert_eq!(
+        app_state.lock().await.app_config.motor.cooldown_ms,
+        Some(4321)
+    );
+    let response = post("/config/reload").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(
+        app_state.lock().await.app_config.motor.cooldown_ms,
+        original_cooldown
+    );
+    let response = post("/config/confirm").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // a confirmed one is kept
+    let response = post("/config/reload?confirm_within_secs=1").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let response = post("/config/confirm").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(
+        app_state.lock().await.app_config.motor.cooldown_ms,
+        Some(4321)
+    );
+
+    let response = post("/config/reload?dry_run=true&confirm_within_secs=5")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_admin_batch() {
+    let (addr, client, app_state) = setup(None).await;
+    let starts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let starts_clone = Arc::clone(&starts);
+    supervisor::spawn_supervised(&app_state, "idle_task", move || {
+        starts_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        std::future::pending::<()>()
+    });
+    wait_for_server(100).await;
+
+    let token = login(&client, addr, "admin", "password").await.token;
+    let run_batch = |operations: serde_json::Value| {
+        client
+            .post(format!("http://{}/admin/batch", addr))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({ "operations": operations }))
+            .send()
+    };
+    let operations = serde_json::json!([
+        { "op": "set_config", "path": "motor.cooldown_ms", "value": 2500 },
+        { "op": "set_config", "path": "triggers.daily_limit", "value": 8 },
+        { "op": "restart_task", "name": "idle_task" },
+    ]);
+
+    let response = run_batch(operations.clone()).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let batch: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(batch["applied"], true);
+    let statuses: Vec<&str> = batch["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|step| step["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, vec!["applied", "applied", "applied"]);
+    {
+        let state_guard = app_state.lock().await;
+        assert_eq!(state_guard.app_config.motor.cooldown_ms, Some(2500));
+        assert_eq!(
+            state_guard.app_config.triggers.as_ref().unwrap().daily_limit,
+            Some(8)
+        );
+    }
+    wait_for_server(100).await;
+    assert_eq!(starts.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    // running it again only restarts the task
+    let batch: serde_json::Value = run_batch(operations).await.unwrap().json().await.unwrap();
+    assert_eq!(batch["steps"][0]["status"], "unchanged");
+    assert_eq!(batch["steps"][1]["status"], "unchanged");
+    assert_eq!(batch["steps"][2]["status"], "applied");
+
+    // one failing step and nothing is applied
+    let response = run_batch(serde_json::json!([
+        { "op": "set_config", "path": "motor.cooldown_ms", "value": 500 },
+        { "op": "restart_task", "name": "no_such_task" },
+    ]))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let batch: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(batch["applied"], false);
+    assert_eq!(batch["steps"][0]["status"], "not_applied");
+    assert_eq!(batch["steps"][1]["status"], "failed");
+    assert_eq!(app_state.lock().await.app_config.motor.cooldown_ms, Some(2500));
+
+    // a value the startup checks reject fails the batch too, no step is blamed for it
+    let response = run_batch(serde_json::json!([
+        { "op": "set_config", "path": "power_monitor.motor_current_limit_amps", "value": 0.0 },
+        { "op": "set_config", "path": "motor.cooldown_ms", "value": 3000 },
+    ]))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let batch: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(batch["steps"][0]["status"], "not_applied");
+    assert_eq!(batch["steps"][1]["status"], "not_applied");
+    assert_eq!(
+        batch["config_errors"][0]["field"],
+        "power_monitor.motor_current_limit_amps"
+    );
+    assert_eq!(app_state.lock().await.app_config.motor.cooldown_ms, Some(2500));
+
+    let response = run_batch(serde_json::json!([])).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    wait_for_server(100).await;
+    assert_eq!(starts.load(std::sync::atomic::Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_diagnostics_bundle() {
+    let (addr, client, _) = setup(None).await;
+
+    let response = get_with_auth(&client, addr, "/admin/diagnostics").await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "application/gzip"
+    );
+    let archive = response.bytes().await.unwrap();
+
+    let mut files = std::collections::HashMap::new();
+    let mut tar_archive = tar::Archive::new(flate2::read::GzDecoder::new(&archive[..]));
+    for entry in tar_archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().to_string();
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+        files.insert(name, contents);
+    }
+
+    for name in [
+        "version.json",
+        "config.yaml",
+        "logs.txt",
+        "events.json",
+        "probe.json",
+        "calibration.json",
+        "power_trace.json",
+    ] {
+        assert!(files.contains_key(name), "{} missing", name);
+    }
+    assert!(files["config.yaml"].contains("admin_user: admin"));
+    assert!(files["config.yaml"].contains("admin_password: <redacted>"));
+    assert!(!files["config.yaml"].contains("admin_password: password"));
+
+    let version: serde_json::Value = serde_json::from_str(&files["version.json"]).unwrap();
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    let probe: serde_json::Value = serde_json::from_str(&files["probe.json"]).unwrap();
+    assert_eq!(probe["status"], "Operational");
+}
+
+#[tokio::test]
+async fn test_status_wait_endpoint() {
+    let (addr, client, _) = setup(None).await;
+
+    let response = client.get(format!("http://{}/status", addr)).send().await.unwrap();
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+    // nothing changes, so the wait times out
+    let response = client
+        .get(format!("http://{}/status/wait?since={}&timeout_secs=1", addr, etag))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["etag"].to_str().unwrap(), etag);
+
+    let wait = client
+        .get(format!("http://{}/status/wait?since={}&timeout_secs=10", addr, etag))
+        .send();
+    let trigger = async {
+        wait_for_server(300).await;
+        post_with_auth(&client, addr, "/dispense").await
+    };
+    let (response, dispense_response) = tokio::join!(wait, trigger);
+    assert!(dispense_response.status().is_success());
+
+    let response = response.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_ne!(response.headers()["etag"].to_str().unwrap(), etag);
+    let status = response.json::<StatusResponse>().await.unwrap();
+    assert_eq!(status.dispenser_status, "Dispensing");
+}
+
+#[tokio::test]
+async fn test_localized_messages() {
+    let (addr, client, app_state) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+    let get = |path: &str, language: Option<&str>| {
+        let mut request = client
+            .get(format!("http://{}{}", addr, path))
+            .bearer_auth(&token);
+        if let Some(language) = language {
+            request = request.header("Accept-Language", language);
+        }
+        request.send()
+    };
+
+    let response = get("/dispense/trickle", Some("fi-FI, en;q=0.5"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["content-language"], "fi");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Ei löytynyt: No trickle dispense is running"
+    );
+    let response = get("/dispense/trickle", Some("en, fi;q=0.5"))
+        .await
+        .unwrap();
+    assert!(!response.headers().contains_key("content-language"));
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Not found: No trickle dispense is running"
+    );
+    let response = client
+        .get(format!("http://{}/events", addr))
+        .header("Accept-Language", "fi")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(response.text().await.unwrap(), "Pyyntöä ei ole valtuutettu");
+
+    let event_bus = app_state.lock().await.event_bus.clone();
+    event_bus.publish_dispensed(TriggerSource::Schedule, None);
+    let events: Vec<serde_json::Value> = get("/events", Some("fi"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let dispensed = events.iter().find(|e| e["kind"] == "dispensed").unwrap();
+    assert_eq!(dispensed["text"], "Herkut annosteltu");
+    // the English message is kept, logs and other clients see the same
+    assert_eq!(dispensed["message"], "Treats dispensed (trigger: schedule)");
+    let events: Vec<serde_json::Value> = get("/events", None).await.unwrap().json().await.unwrap();
+    assert!(events.iter().all(|e| e.get("text").is_none()));
+}
+
+#[tokio::test]
+async fn test_status_versions() {
+    let (addr, client, app_state) = setup(None).await;
+    let status_url = format!("http://{}/status", addr);
+    let v2 = "application/vnd.treat-dispenser.status.v2+json";
+
+    // without a version, strict clients keep getting the first one
+    let response = client.get(&status_url).send().await.unwrap();
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.headers()["vary"], "accept");
+    response.json::<StatusResponseV1>().await.unwrap();
+
+    let response = client
+        .get(&status_url)
+        .header("Accept", "application/vnd.treat-dispenser.status.v9+json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_ACCEPTABLE);
+
+    let mut transitions_rx = app_state.lock().await.status_transitions_tx.subscribe();
+    let dispense_response = post_with_auth(&client, addr, "/dispense").await;
+    assert!(dispense_response.status().is_success());
+    let wait_for_cooldown = async {
+        while let Ok(transition) = transitions_rx.recv().await {
+            if transition.to == DispenserStatus::Cooldown {
+                break;
+            }
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(30), wait_for_cooldown)
+        .await
+        .expect("the dispense should reach the cooldown");
+
+    let response = client
+        .get(&status_url)
+        .header("Accept", v2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], v2);
+    let status = response.json::<StatusResponseV2>().await.unwrap();
+    assert_eq!(status.dispenser_status, "Cooldown");
+    let remaining = status.cooldown_remaining_ms.unwrap();
+    // test_config() has a cooldown of 5 seconds
+    assert!(remaining > 0 && remaining <= 5000, "{}", remaining);
+
+    let v3 = "application/vnd.treat-dispenser.status.v3+json";
+    let response = client
+        .get(&status_url)
+        .header("Accept", v3)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], v3);
+    let status = response.json::<StatusResponse>().await.unwrap();
+    assert!(status.cooldown_remaining_ms.is_some());
+}
+
+#[tokio::test]
+async fn test_assistant_fulfillment() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        integrations:
+          assistant:
+            access_tokens: ["linked-account-token"]
+            device_name: "Rabbit treat"
+        "#;
+    let (addr, client, _) = setup(Some(config)).await;
+    let url = format!("http://{}/integrations/assistant", addr);
+
+    let sync_request = serde_json::json!({
+        "requestId": "req-1",
+        "inputs": [{ "intent": "action.devices.SYNC" }]
+    });
+    let response = client.post(&url).json(&sync_request).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .post(&url)
+        .bearer_auth("linked-account-token")
+        .json(&sync_request)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["requestId"], "req-1");
+    assert_eq!(body["payload"]["devices"][0]["name"]["name"], "Rabbit treat");
+
+    let response = client
+        .post(&url)
+        .bearer_auth("linked-account-token")
+        .json(&serde_json::json!({
+            "requestId": "req-2",
+            "inputs": [{ "intent": "action.devices.EXECUTE" }]
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["payload"]["commands"][0]["status"], "SUCCESS");
+
+    // a second activation while dispensing is reported as busy, Alexa style
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({
+            "directive": {
+                "header": {
+                    "namespace": "Alexa.SceneController",
+                    "name": "Activate",
+                    "payloadVersion": "3",
+                    "messageId": "abc",
+                    "correlationToken": "corr"
+                },
+                "endpoint": {
+                    "scope": { "type": "BearerToken", "token": "linked-account-token" },
+                    "endpointId": "treat-dispenser"
+                },
+                "payload": {}
+            }
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["event"]["header"]["name"], "ErrorResponse");
+    assert_eq!(body["event"]["payload"]["type"], "ENDPOINT_BUSY");
+    assert_eq!(body["event"]["header"]["correlationToken"], "corr");
+}
+
+#[tokio::test]
+async fn test_dispense_hook() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        hooks:
+          - name: "ifttt"
+            token: "hook-secret"
+            min_interval_secs: 3600
+        "#;
+    let (addr, client, _) = setup(Some(config)).await;
+
+    let response = client
+        .post(format!("http://{}/hooks/dispense?token=wrong", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // hook tokens are not accepted by the JWT protected endpoints
+    let response = client
+        .post(format!("http://{}/dispense", addr))
+        .bearer_auth("hook-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .post(format!("http://{}/hooks/dispense?token=hook-secret", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let response = client
+        .post(format!("http://{}/hooks/dispense", addr))
+        .header("X-Hook-Token", "hook-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_notification_device_registration() {
+    let (addr, client, _) = setup(None).await;
+    std::fs::create_dir_all(std::env::var("DISPENSER_DATA_DIR").unwrap()).unwrap();
+
+    let token = login(&client, addr, "admin", "password").await.token;
+    let device_token = format!("test-device-{}", rand_suffix());
+
+    let response = client
+        .post(format!("http://{}/notifications/devices", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "token": device_token, "platform": "android" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let registration: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(registration["token"], device_token.as_str());
+
+    let url = format!("http://{}/notifications/devices/{}", addr, device_token);
+    let response = client.delete(&url).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let response = client.delete(&url).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_request_validation_errors() {
+    let (addr, client, _) = setup(None).await;
+    let token = login(&client, addr, "admin", "password").await.token;
+
+    let response = client
+        .post(format!("http://{}/calibrate", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "known_mass_grams": 0.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["fields"][0]["field"], "known_mass_grams");
+
+    let response = client
+        .post(format!("http://{}/dispense", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "degrees": 100000.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["fields"][0]["field"], "degrees");
+
+    // no piece weight configured
+    let response = client
+        .post(format!("http://{}/dispense", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "pieces": 3 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["fields"][0]["field"], "pieces");
+
+    let response = client
+        .post(format!("http://{}/dispense", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "degrees": 360.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn test_config_schema_endpoint() {
+    let (addr, client, _) = setup(None).await;
+    let response = client
+        .get(format!("http://{}/config/schema", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let schema: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(schema["title"], "AppConfig");
+    assert!(schema["properties"]["motor"].is_object());
+}
+
+#[tokio::test]
+async fn test_routes_endpoint() {
+    let (addr, client, _) = setup(None).await;
+    let response = client
+        .get(format!("http://{}/routes", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let routes: Vec<RouteInfo> = response.json().await.unwrap();
+
+    let route = |path: &str| routes.iter().find(|r| r.path == path).unwrap();
+    assert_eq!(route("/routes").auth, RouteAuth::None);
+    assert_eq!(route("/dispense").auth, RouteAuth::BearerToken);
+    assert_eq!(route("/hooks/dispense").auth, RouteAuth::HookToken);
+    assert_eq!(route("/admin/log-level").methods, vec!["GET", "PUT"]);
+
+    // the listing is the contract: every listed route is served and token routes reject
+    // requests without one
+    for route in &routes {
+        let path = route.path.replace("{token}", "x").replace("{label}", "x");
+        for method in &route.methods {
+            let method = reqwest::Method::from_bytes(method.as_bytes()).unwrap();
+            let response = client
+                .request(method.clone(), format!("http://{}{}", addr, path))
+                .send()
+                .await
+                .unwrap();
+            let status = response.status();
+            assert_ne!(status, reqwest::StatusCode::METHOD_NOT_ALLOWED, "{} {}", method, path);
+            match route.auth {
+                RouteAuth::BearerToken => {
+                    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED, "{} {}", method, path)
+                }
+                RouteAuth::None if method == reqwest::Method::GET => {
+                    assert!(status.is_success(), "{} {}: {}", method, path, status)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_openapi_document() {
+    let (addr, client, _) = setup(None).await;
+
+    let routes: Vec<RouteInfo> = client
+        .get(format!("http://{}/routes", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let response = client
+        .get(format!("http://{}/openapi.json", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+
+    // every API route is documented, with the auth the route table enforces
+    for route in routes
+        .iter()
+        .filter(|r| !["/favicon.ico", "/openapi.json", "/docs"].contains(&r.path.as_str()))
+    {
+        for method in &route.methods {
+            let operation = &document["paths"][&route.path][method.to_lowercase()];
+            assert!(operation.is_object(), "{} {} is not documented", method, route.path);
+            let secured = operation["security"][0]["bearer_token"].is_array();
+            assert_eq!(
+                secured,
+                route.auth == RouteAuth::BearerToken,
+                "{} {}",
+                method,
+                route.path
+            );
+        }
+    }
+    let login = &document["paths"]["/login"]["post"];
+    assert_eq!(
+        login["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/LoginRequest"
+    );
+    let status_schema = &document["components"]["schemas"]["StatusResponseV1"];
+    assert!(status_schema["properties"]["remaining_treats_grams"].is_object());
+
+    let response = client
+        .get(format!("http://{}/docs/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert!(response.text().await.unwrap().contains("swagger"));
+}
+
+#[tokio::test]
+async fn test_head_options_and_http2() {
+    let (addr, client, _) = setup(None).await;
+
+    let response = client
+        .head(format!("http://{}/status", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert!(response.headers().contains_key("etag"));
+
+    // CORS preflight for a protected route must not require a token
+    let response = client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("http://{}/dispense", addr),
+        )
+        .header("Origin", "https://dashboard.example")
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "authorization")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["access-control-allow-origin"], "*");
+
+    let response = client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("http://{}/history/1", addr),
+        )
+        .header("Origin", "https://dashboard.example")
+        .header("Access-Control-Request-Method", "PATCH")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let allowed = response.headers()["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(allowed.contains("PATCH"), "{}", allowed);
+
+    let h2_client = Client::builder().http2_prior_knowledge().build().unwrap();
+    let response = h2_client
+        .get(format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.version(), reqwest::Version::HTTP_2);
+}
+
+#[tokio::test]
+async fn test_raw_sensor_debug_streams() {
+    let (addr, client, _) = setup(None).await;
+
+    let response = get_with_auth(&client, addr, "/debug/power/raw?duration_secs=1&interval_ms=100").await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let samples: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(samples.len() >= 5 && samples.len() <= 10);
+    assert_eq!(samples[0]["raw"]["current_register"], 600);
+
+    let response = get_with_auth(&client, addr, "/debug/weight/raw?duration_secs=1&interval_ms=250").await;
+    let body = response.text().await.unwrap();
+    let first: serde_json::Value = serde_json::from_str(body.lines().next().unwrap()).unwrap();
+    assert_eq!(first["raw"], 123456);
+
+    let response = get_with_auth(&client, addr, "/debug/weight/raw?duration_secs=3600").await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_watchdog_flags_stale_channels() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        watchdog:
+          stale_after_secs: 1
+        "#;
+    let (addr, client, app_state) = setup(Some(config)).await;
+    // only the weight monitor runs, so power readings go stale
+    start_weight_monitoring_thread(&app_state).await;
+    watchdog::start_watchdog(&app_state).await;
+    wait_for_server(3500).await;
+
+    let status = get_hardware_status(&client, addr).await;
+    assert_eq!(status.stale_channels, vec!["power_readings".to_string()]);
+
+    start_power_monitoring_thread(&app_state).await;
+    wait_for_server(2500).await;
+    let status = get_hardware_status(&client, addr).await;
+    assert!(status.stale_channels.is_empty());
+}
+
+#[tokio::test]
+async fn test_digital_outputs() {
+    let config = r#"
+        api:
+          listen_address: "127.0.0.1:0"
+          admin_user: "admin"
+          admin_password: "password"
+        power_monitor:
+          sensor: "SensorMock"
+        weight_monitor:
+          sensor: "SensorMock"
+        motor:
+          motor_type: "StepperMock"
+        digital_outputs:
+          - label: "chute_light"
+            pin: 24
+        "#;
+    let (addr, client, _app_state) = setup(Some(config)).await;
+
+    let url = format!("http://{}/outputs/chute_light", addr);
+    let response = client
+        .post(&url)
+        .json(&serde_json::json!({ "on": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let token = login(&client, addr, "admin", "password").await.token;
+    let response = client
+        .post(format!("http://{}/outputs/fan", addr))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&serde_json::json!({ "on": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let status = get_hardware_status(&client, addr).await;
+    let output = &status.digital_outputs["chute_light"];
+    assert_eq!(output.pin, 24);
+    assert!(!output.on);
+}
+
+/// Weighs a fixed amount, stands in for hardware of an embedding program.
+struct FixedWeightSensor {
+    grams: f32,
+}
+
+impl WeightSensor for FixedWeightSensor {
+    fn get_name(&self) -> String {
+        "FixedWeightSensor".to_string()
+    }
+
+    fn get_weight_reading(
+        &mut self,
+        _calibration: &WeightSensorCalibration,
+    ) -> Result<WeightReading, String> {
+        Ok(WeightReading {
+            grams: self.grams,
+            settled: true,
+        })
+    }
+
+    fn get_raw(&mut self) -> Result<i32, String> {
+        Ok(self.grams as i32)
+    }
+}
+
+#[tokio::test]
+async fn test_embedded_dispenser_with_custom_driver() {
+    dotenv::from_filename(".env.test").ok();
+    init_logging();
+    // no HX711 here, the custom driver takes its place
+    let config = test_config().weight_sensor("SensorHX711").build();
+    let dispenser = DispenserBuilder::new(config)
+        .weight_sensor(FixedWeightSensor { grams: 250.0 })
+        .build()
+        .await;
+    let app = axum::Router::new()
+        .route("/", axum::routing::get(|| async { "home hub" }))
+        .nest("/dispenser", dispenser.router);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    wait_for_server(3000).await;
+
+    let client = Client::new();
+    let response = client
+        .post(format!("http://{}/dispenser/login", addr))
+        .json(&serde_json::json!({ "username": "admin", "password": "password" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let token = response
+        .json::<treat_dispenser_api::services::auth::LoginResponse>()
+        .await
+        .unwrap()
+        .token;
+    let status = client
+        .get(format!("http://{}/dispenser/status", addr))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .unwrap()
+        .json::<StatusResponse>()
+        .await
+        .unwrap();
+    assert_eq!(status.hardware.weight_sensor.state, ComponentState::Available);
+    assert_eq!(status.remaining_treats_grams, 250.0);
+
+    let home = client
+        .get(format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(home.text().await.unwrap(), "home hub");
+}
+
+fn rand_suffix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
 }